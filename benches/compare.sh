@@ -0,0 +1,23 @@
+#!/bin/sh
+#
+# Compares the output of two benchmark runs:
+#
+#     cargo bench --features "unstable test-util" > before.txt
+#     # apply changes
+#     cargo bench --features "unstable test-util" > after.txt
+#     benches/compare.sh before.txt after.txt
+#
+# A negative change means the benchmark got faster.
+
+if [ $# -ne 2 ]; then
+    echo "usage: $0 BEFORE AFTER" >&2
+    exit 1
+fi
+
+awk '
+    $3 != "ns/iter" { next }
+    FNR == NR { before[$1] = $2; next }
+    $1 in before {
+        printf "%-32s %14.0f %14.0f %+8.1f%%\n", $1, before[$1], $2, ($2 - before[$1]) * 100 / before[$1]
+    }
+' "$1" "$2"
@@ -0,0 +1,498 @@
+//! Benchmarks of the client and server talking over an in-memory transport,
+//! and of the frame and HPACK codecs.
+//!
+//! ```text
+//! cargo bench --features "unstable test-util" [-- FILTER]
+//! ```
+//!
+//! Each benchmark prints its name, the time per iteration and, if relevant,
+//! its throughput. Save the output of two runs and compare them with
+//! `benches/compare.sh`.
+
+extern crate bytes;
+extern crate futures;
+extern crate h2;
+extern crate http;
+extern crate tokio;
+extern crate tokio_io;
+
+use bytes::{Bytes, BytesMut};
+use futures::{future, Async, Future, Poll, Stream};
+use h2::{client, server, test, Codec};
+use h2::frame::{self, Frame, Headers, Pseudo, Settings, StreamId};
+use h2::hpack::Decoder;
+use h2::hpack::raw::Encoder;
+use http::{HeaderMap, Method, Request, Response};
+use tokio::runtime::current_thread;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::{cmp, env, io, thread};
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+/// Time spent running a benchmark before it is measured.
+const WARM_UP: Duration = Duration::from_millis(100);
+
+/// Number of measurements, the median one is reported.
+const SAMPLES: usize = 5;
+
+/// Size of the bodies sent by the bulk throughput benchmark.
+const BULK_LEN: usize = 1 << 20;
+
+/// Size of the body streamed by the streamed body benchmark.
+const STREAMED_LEN: usize = 100 << 20;
+
+/// Number of requests in flight in the request rate benchmark.
+const CONCURRENT_REQUESTS: usize = 64;
+
+/// Number of threads sending requests on the same connection.
+const SENDER_THREADS: usize = 16;
+
+enum Throughput {
+    None,
+    Bytes(u64),
+    Elements(u64),
+}
+
+struct Runner {
+    filter: Option<String>,
+}
+
+impl Runner {
+    /// Runs the benchmark `name` if it matches the filter.
+    ///
+    /// `run(n)` runs `n` iterations and returns the time spent in the part
+    /// that is measured.
+    fn bench<F>(&self, name: &str, throughput: Throughput, mut run: F)
+    where
+        F: FnMut(u64) -> Duration,
+    {
+        if let Some(ref filter) = self.filter {
+            if !name.contains(&filter[..]) {
+                return;
+            }
+        }
+
+        // Find a number of iterations taking long enough to be measured
+        let mut iters = 1;
+
+        while run(iters) < WARM_UP {
+            iters *= 2;
+        }
+
+        let mut samples: Vec<_> = (0..SAMPLES)
+            .map(|_| nanos(run(iters)) / iters as f64)
+            .collect();
+
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let ns = samples[SAMPLES / 2];
+
+        let throughput = match throughput {
+            Throughput::None => String::new(),
+            Throughput::Bytes(n) => {
+                format!("{:.1} MB/s", n as f64 / ns * 1e9 / (1 << 20) as f64)
+            },
+            Throughput::Elements(n) => format!("{:.0} /s", n as f64 / ns * 1e9),
+        };
+
+        println!("{:<32} {:>14.0} ns/iter {:>16}", name, ns, throughput);
+    }
+}
+
+fn nanos(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e9 + duration.subsec_nanos() as f64
+}
+
+/// Times `routine` over inputs created by `setup` beforehand.
+fn time_with_setup<I, S, R>(iters: u64, mut setup: S, mut routine: R) -> Duration
+where
+    S: FnMut() -> I,
+    R: FnMut(I),
+{
+    let inputs: Vec<_> = (0..iters).map(|_| setup()).collect();
+    let start = Instant::now();
+
+    for input in inputs {
+        routine(input);
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let runner = Runner {
+        filter: env::args().skip(1).find(|arg| !arg.starts_with("-")),
+    };
+
+    bulk_throughput(&runner);
+    streamed_body(&runner);
+    request_rate(&runner);
+    request_rate_threads(&runner);
+
+    // The codecs are polled directly, which requires a task.
+    future::lazy(|| {
+        hpack(&runner);
+        frames(&runner);
+        Ok::<_, ()>(())
+    }).wait().unwrap();
+}
+
+// ===== client and server =====
+
+/// Starts a server answering every request with a 200 response once the
+/// request body was received, and returns a client connected to it.
+fn connect(builder: server::Builder) -> client::SendRequest<Bytes> {
+    let (client_io, server_io) = test::duplex();
+
+    // The requests are handled on their own tasks, so that the connection
+    // keeps receiving their bodies.
+    thread::spawn(move || {
+        let server = builder.handshake::<_, Bytes>(server_io)
+            .and_then(|conn| {
+                conn.for_each(|(request, mut respond)| {
+                    let mut body = request.into_body();
+                    let mut release = body.release_capacity().clone();
+
+                    let response = body
+                        .for_each(move |chunk| release.release_capacity(chunk.len()))
+                        .map(move |_| {
+                            let response = Response::new(());
+                            let _ = respond.send_response(response, true);
+                        })
+                        .then(|_| Ok(()));
+
+                    current_thread::spawn(response);
+                    Ok(())
+                })
+            })
+            .map_err(|e| panic!("server; err={:?}", e));
+
+        current_thread::run(server);
+    });
+
+    let (client, conn) = client::Builder::new()
+        .initial_window_size(BULK_LEN as u32)
+        .handshake(client_io)
+        .wait()
+        .expect("handshake");
+
+    thread::spawn(move || conn.wait().expect("connection"));
+
+    client
+}
+
+fn send(client: &mut client::SendRequest<Bytes>, body: Option<Bytes>) -> client::ResponseFuture {
+    let request = Request::post("https://example.com/").body(()).unwrap();
+
+    match body {
+        Some(body) => {
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            stream.send_data(body, true).unwrap();
+            response
+        },
+        None => client.send_request(request, true).unwrap().0,
+    }
+}
+
+/// A single stream sending a large request body.
+fn bulk_throughput(runner: &Runner) {
+    let mut server = server::Builder::new();
+    server
+        .initial_window_size(BULK_LEN as u32)
+        .initial_connection_window_size(BULK_LEN as u32);
+
+    let mut client = connect(server);
+    let body = Bytes::from(vec![0; BULK_LEN]);
+
+    runner.bench("bulk_throughput", Throughput::Bytes(BULK_LEN as u64), |iters| {
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            client = client.clone().ready().wait().unwrap();
+            send(&mut client, Some(body.clone())).wait().unwrap();
+        }
+
+        start.elapsed()
+    });
+}
+
+/// A single stream sending a 100 MB request body, a chunk at a time as
+/// capacity is assigned to it.
+fn streamed_body(runner: &Runner) {
+    let mut server = server::Builder::new();
+    server
+        .initial_window_size(BULK_LEN as u32)
+        .initial_connection_window_size(BULK_LEN as u32);
+
+    let mut client = connect(server);
+    let chunk = Bytes::from(vec![0; BULK_LEN]);
+
+    runner.bench("streamed_body_100mb", Throughput::Bytes(STREAMED_LEN as u64), |iters| {
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            client = client.clone().ready().wait().unwrap();
+
+            let request = Request::post("https://example.com/").body(()).unwrap();
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            let mut rem = STREAMED_LEN;
+
+            while rem > 0 {
+                stream.reserve_capacity(cmp::min(rem, BULK_LEN));
+
+                let capacity = future::poll_fn(|| stream.poll_capacity())
+                    .wait()
+                    .unwrap()
+                    .expect("capacity");
+                let n = cmp::min(capacity, rem);
+
+                rem -= n;
+                stream.send_data(chunk.slice_to(n), rem == 0).unwrap();
+            }
+
+            response.wait().unwrap();
+        }
+
+        start.elapsed()
+    });
+}
+
+/// Many small requests in flight at once.
+fn request_rate(runner: &Runner) {
+    let mut client = connect(server::Builder::new());
+    let throughput = Throughput::Elements(CONCURRENT_REQUESTS as u64);
+
+    runner.bench("request_rate_64_concurrent", throughput, |iters| {
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            let responses: Vec<_> = (0..CONCURRENT_REQUESTS)
+                .map(|_| {
+                    client = client.clone().ready().wait().unwrap();
+                    send(&mut client, None)
+                })
+                .collect();
+
+            future::join_all(responses).wait().unwrap();
+        }
+
+        start.elapsed()
+    });
+}
+
+/// Requests sent from many threads sharing the connection, which contend for
+/// the lock on its streams.
+fn request_rate_threads(runner: &Runner) {
+    let client = connect(server::Builder::new());
+    let throughput = Throughput::Elements(SENDER_THREADS as u64);
+
+    runner.bench("request_rate_16_threads", throughput, |iters| {
+        let start = Instant::now();
+
+        let threads: Vec<_> = (0..SENDER_THREADS)
+            .map(|_| {
+                let mut client = client.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..iters {
+                        client = client.ready().wait().unwrap();
+                        send(&mut client, None).wait().unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        start.elapsed()
+    });
+}
+
+// ===== hpack =====
+
+fn request_headers() -> Headers {
+    let uri = "https://example.com/api/v1/items/1234?page=3".parse().unwrap();
+
+    let mut fields = HeaderMap::new();
+    fields.insert("user-agent", "h2-bench/0.1".parse().unwrap());
+    fields.insert("accept", "application/json".parse().unwrap());
+    fields.insert("accept-encoding", "gzip, deflate, br".parse().unwrap());
+    fields.insert("accept-language", "en-US,en;q=0.9".parse().unwrap());
+    fields.append("cookie", "session=2f9c1e7a4b".parse().unwrap());
+    fields.append("cookie", "theme=dark".parse().unwrap());
+
+    Headers::new(StreamId::from(1), Pseudo::request(Method::GET, uri), fields)
+}
+
+/// Encodes `frame`, returning the header block without the frame head.
+fn encode_block(frame: Headers, encoder: &mut Encoder) -> BytesMut {
+    let mut dst = BytesMut::with_capacity(4096);
+    assert!(frame.encode(encoder, &mut dst, |_| 0).is_none());
+    dst.split_off(frame::HEADER_LEN)
+}
+
+fn hpack(runner: &Runner) {
+    let mut encoder = Encoder::default();
+    let mut dst = BytesMut::with_capacity(4096);
+
+    runner.bench("hpack_encode_request", Throughput::None, |iters| {
+        time_with_setup(iters, request_headers, |frame| {
+            dst.clear();
+            assert!(frame.encode(&mut encoder, &mut dst, |_| 0).is_none());
+        })
+    });
+
+    // The first block of a connection adds the fields to the dynamic table,
+    // the next ones refer to them.
+    let mut encoder = Encoder::default();
+    let literals = encode_block(request_headers(), &mut encoder);
+    let indexed = encode_block(request_headers(), &mut encoder);
+
+    let mut decoder = Decoder::new(4096);
+
+    runner.bench("hpack_decode_literals", Throughput::None, |iters| {
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            decoder.decode(&literals).unwrap();
+        }
+
+        start.elapsed()
+    });
+
+    runner.bench("hpack_decode_indexed", Throughput::None, |iters| {
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            decoder.decode(&indexed).unwrap();
+        }
+
+        start.elapsed()
+    });
+}
+
+// ===== frames =====
+
+/// A transport reading `input` over and over, and discarding anything
+/// written.
+struct Wire {
+    input: Bytes,
+    pos: usize,
+}
+
+impl io::Read for Wire {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.input.is_empty() {
+            return Ok(0);
+        }
+
+        let n = cmp::min(buf.len(), self.input.len() - self.pos);
+        buf[..n].copy_from_slice(&self.input[self.pos..self.pos + n]);
+        self.pos = (self.pos + n) % self.input.len();
+        Ok(n)
+    }
+}
+
+impl io::Write for Wire {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for Wire {}
+
+impl AsyncWrite for Wire {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+fn codec(input: Bytes) -> Codec<Wire, Cursor<Bytes>> {
+    Codec::new(Wire { input, pos: 0 })
+}
+
+fn data_frame(payload: &Bytes) -> Frame<Cursor<Bytes>> {
+    frame::Data::new(StreamId::from(1), Cursor::new(payload.clone())).into()
+}
+
+fn settings_frame() -> Settings {
+    let mut settings = Settings::default();
+    settings.set_initial_window_size(Some(1 << 20));
+    settings.set_max_concurrent_streams(Some(100));
+    settings.set_max_frame_size(Some(1 << 16));
+    settings
+}
+
+/// Returns the bytes of `frame`, as written by a codec.
+fn encoded<F>(frame: F) -> Bytes
+where
+    F: Fn() -> Frame<Cursor<Bytes>>,
+{
+    let (io, mut other) = test::duplex();
+    let mut codec: Codec<_, Cursor<Bytes>> = Codec::new(io);
+
+    codec.buffer(frame()).unwrap();
+    assert!(codec.flush().unwrap().is_ready());
+    drop(codec);
+
+    let mut dst = Vec::new();
+    io::Read::read_to_end(&mut other, &mut dst).unwrap();
+    dst.into()
+}
+
+fn bench_encode<F>(runner: &Runner, name: &str, throughput: Throughput, frame: F)
+where
+    F: Fn() -> Frame<Cursor<Bytes>>,
+{
+    let mut codec = codec(Bytes::new());
+
+    runner.bench(name, throughput, |iters| {
+        time_with_setup(iters, &frame, |frame| {
+            codec.buffer(frame).unwrap();
+            assert!(codec.flush().unwrap().is_ready());
+        })
+    });
+}
+
+fn bench_parse(runner: &Runner, name: &str, throughput: Throughput, encoded: Bytes) {
+    let mut codec = codec(encoded);
+
+    runner.bench(name, throughput, |iters| {
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            match codec.poll().unwrap() {
+                Async::Ready(Some(_)) => {},
+                res => panic!("unexpected poll result; {:?}", res.map(|_| ())),
+            }
+        }
+
+        start.elapsed()
+    });
+}
+
+fn frames(runner: &Runner) {
+    let payload = Bytes::from(vec![0; 16_384]);
+    let data = encoded(|| data_frame(&payload));
+    let data_len = data.len() as u64;
+
+    // The payload is handed to the transport as is, so this only measures
+    // the frame head.
+    bench_encode(runner, "frame_encode_data", Throughput::None, || data_frame(&payload));
+    bench_encode(runner, "frame_encode_headers", Throughput::None, || request_headers().into());
+    bench_encode(runner, "frame_encode_settings", Throughput::None, || settings_frame().into());
+
+    // Every HEADERS frame is parsed from the same block, made of literals.
+    let headers = encoded(|| request_headers().into());
+    let settings = encoded(|| settings_frame().into());
+
+    bench_parse(runner, "frame_parse_data", Throughput::Bytes(data_len), data);
+    bench_parse(runner, "frame_parse_headers", Throughput::None, headers);
+    bench_parse(runner, "frame_parse_settings", Throughput::None, settings);
+}
@@ -42,9 +42,14 @@
 //! the caller.  The caller should use [`SendRequest::poll_ready`] to check for
 //! capacity before sending a request to the server.
 //!
-//! [`SendRequest`] enforces the [`MAX_CONCURRENT_STREAMS`] setting. The user
-//! must not send a request if `poll_ready` does not return `Ready`. Attempting
-//! to do so will result in an [`Error`] being returned.
+//! [`SendRequest`] enforces the [`MAX_CONCURRENT_STREAMS`] setting. Requests
+//! sent while `poll_ready` does not return `Ready` are queued until a stream
+//! closes. Once too many requests are queued, an [`Error`] is returned
+//! instead.
+//!
+//! [`SendRequest::current_streams`] and [`SendRequest::max_streams`] return
+//! how many streams are active and allowed, which helps picking the least
+//! loaded connection out of a pool.
 //!
 //! # Managing the connection
 //!
@@ -152,26 +157,34 @@
 //! [`SendRequest`]: struct.SendRequest.html
 //! [`ResponseFuture`]: struct.ResponseFuture.html
 //! [`SendRequest::poll_ready`]: struct.SendRequest.html#method.poll_ready
+//! [`SendRequest::current_streams`]: struct.SendRequest.html#method.current_streams
+//! [`SendRequest::max_streams`]: struct.SendRequest.html#method.max_streams
 //! [HTTP/2.0 handshake]: http://httpwg.org/specs/rfc7540.html#ConnectionHeader
 //! [`Builder`]: struct.Builder.html
 //! [`Error`]: ../struct.Error.html
 
-use {SendStream, RecvStream, ReleaseCapacity};
-use codec::{Codec, RecvError, SendError, UserError};
-use frame::{Headers, Pseudo, Reason, Settings, StreamId};
-use proto;
+use {FlowControlMode, PeerSettings, Priority, SendStream, RecvStream, ReleaseCapacity};
+use {AbortHandle, ConnectionStats, Direction, ExtensionFrame, FlushStrategy, FrameView, PingPong, Protocol, SettingsAcked, SettingsChanges, SettingsUpdate};
+use codec::{self, Codec, Padding, SendError, UserError};
+use frame::{self, SettingsBuilder, Headers, Pseudo, Reason, Settings, StreamId};
+use {h2c, proto};
 
 use bytes::{Bytes, IntoBuf};
-use futures::{Async, Future, Poll};
-use http::{uri, Request, Response, Method, Version};
+use futures::{Async, Future, Poll, Stream};
+use http::{header, uri, Request, Response, Method, StatusCode, Version};
+use http::header::HeaderName;
 use tokio_io::{AsyncRead, AsyncWrite};
 use tokio_io::io::WriteAll;
 
-use std::fmt;
+use std::{cmp, fmt};
+use std::io;
 use std::marker::PhantomData;
 use std::time::Duration;
 use std::usize;
 
+/// The HTTP/2.0 connection preface sent by clients.
+pub(crate) const PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
 /// Performs the HTTP/2.0 connection handshake.
 ///
 /// This type implements `Future`, yielding a `(SendRequest, Connection)`
@@ -193,6 +206,43 @@ pub struct Handshake<T, B: IntoBuf = Bytes> {
     _marker: PhantomData<B>,
 }
 
+/// Performs the HTTP/2.0 connection handshake on an HTTP/1.1 connection
+/// upgraded with the "h2c" protocol.
+///
+/// This type implements `Future`, yielding a `(SendRequest, ResponseFuture,
+/// Connection)` instance once the server accepted the upgrade and the
+/// connection preface was sent. The [`ResponseFuture`] completes with the
+/// response to the upgrade request.
+///
+/// See [`Builder::handshake_h2c_upgrade`] for more details.
+///
+/// [`ResponseFuture`]: struct.ResponseFuture.html
+/// [`Builder::handshake_h2c_upgrade`]: struct.Builder.html#method.handshake_h2c_upgrade
+#[must_use = "futures do nothing unless polled"]
+pub struct UpgradeHandshake<T, B: IntoBuf = Bytes> {
+    builder: Builder,
+    method: Method,
+    state: Upgrade<T>,
+    _marker: PhantomData<B>,
+}
+
+/// The steps of an `UpgradeHandshake`.
+enum Upgrade<T> {
+    /// Writing the HTTP/1.1 upgrade request.
+    SendRequest(WriteAll<T, Vec<u8>>),
+
+    /// Reading the head of the HTTP/1.1 response. The head is read one byte
+    /// at a time, so that none of the HTTP/2.0 frames following it are
+    /// consumed.
+    RecvResponse(Option<T>, Vec<u8>),
+
+    /// Writing the connection preface.
+    SendPreface(WriteAll<T, &'static [u8]>),
+
+    /// The settings are invalid, or the upgrade request could not be encoded.
+    Failed(Option<UserError>),
+}
+
 /// Initializes new HTTP/2.0 streams on a connection by sending a request.
 ///
 /// This type does no work itself. Instead, it is a handle to the inner
@@ -206,17 +256,22 @@ pub struct Handshake<T, B: IntoBuf = Bytes> {
 /// connection in FIFO order as HTTP/2.0 prioritization logic can play a role.
 ///
 /// [`SendRequest`] implements [`Clone`], enabling the creation of many
-/// instances that are backed by a single connection.
+/// instances that are backed by a single connection. Stream identifiers are
+/// allocated under the connection's lock, so requests sent from different
+/// clones never share an identifier. Clones waiting in [`poll_ready`] are
+/// notified in the order they started waiting. Once every clone is dropped,
+/// the [`Connection`] sends `GOAWAY` and closes after its active streams
+/// complete.
 ///
 /// See [module] level documentation for more details.
 ///
 /// [module]: index.html
 /// [`Connection`]: struct.Connection.html
 /// [`Clone`]: https://doc.rust-lang.org/std/clone/trait.Clone.html
+/// [`poll_ready`]: #method.poll_ready
 /// [`Error`]: ../struct.Error.html
 pub struct SendRequest<B: IntoBuf> {
     inner: proto::Streams<B::Buf, Peer>,
-    pending: Option<proto::OpaqueStreamRef>,
 }
 
 /// Returns a `SendRequest` instance once it is ready to send at least one
@@ -290,10 +345,67 @@ pub struct Connection<T, B: IntoBuf = Bytes> {
 }
 
 /// A future of an HTTP response.
+///
+/// Dropping the future before the response is received, along with the
+/// request's [`SendStream`] if any, resets the stream with `CANCEL`.
+///
+/// [`SendStream`]: ../struct.SendStream.html
 #[derive(Debug)]
 #[must_use = "futures do nothing unless polled"]
 pub struct ResponseFuture {
     inner: proto::OpaqueStreamRef,
+    push_promise_consumed: bool,
+}
+
+/// A stream of the responses pushed by the server for a request.
+///
+/// Returned by [`ResponseFuture::push_promises`]. Each promise is yielded as
+/// the PUSH_PROMISE frame is received, and the stream ends once the server
+/// sent the end of the response, after which no more pushes can be received.
+///
+/// [`ResponseFuture::push_promises`]: struct.ResponseFuture.html#method.push_promises
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct PushPromises {
+    inner: proto::OpaqueStreamRef,
+}
+
+/// A response the server promised to push.
+///
+/// The promise is made of the request the server is responding to, and of a
+/// future of the pushed response. Dropping the promise, or its response
+/// future, before the response is complete rejects the push, which resets the
+/// pushed stream with `CANCEL`.
+#[derive(Debug)]
+pub struct PushPromise {
+    request: Request<()>,
+    response: PushedResponseFuture,
+}
+
+/// A future of a pushed HTTP response.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct PushedResponseFuture {
+    inner: ResponseFuture,
+}
+
+/// Alternative services advertised by the server in an ALTSVC frame.
+///
+/// [RFC 7838] lets a server advertise other network locations from which an
+/// origin can be reached. This is the equivalent of the `Alt-Svc` response
+/// header field, sent either for a given origin or for the origin of a
+/// request.
+///
+/// Alternative services are passed to the callback set with
+/// [`Builder::on_alt_svc`].
+///
+/// [RFC 7838]: https://tools.ietf.org/html/rfc7838
+/// [`Builder::on_alt_svc`]: struct.Builder.html#method.on_alt_svc
+#[derive(Debug, Clone)]
+pub struct AltSvc {
+    stream_id: Option<::StreamId>,
+    origin: Option<Bytes>,
+    field_value: Bytes,
 }
 
 /// Builds client connections with custom configuration values.
@@ -344,14 +456,116 @@ pub struct Builder {
     /// MAX_CONCURRENT_STREAMS specified in the frame.
     initial_max_send_streams: usize,
 
+    /// Maximum number of requests waiting for a stream to be opened.
+    max_pending_open_streams: usize,
+
     /// Initial target window size for new connections.
     initial_target_connection_window_size: Option<u32>,
 
+    /// Percentage of the target window released before a WINDOW_UPDATE
+    /// frame is sent.
+    window_update_threshold: u8,
+
+    /// How the connection level receive window is released.
+    flow_control_mode: FlowControlMode,
+
+    /// Duration after which exhausted receive windows are reported.
+    window_stall_warning: Option<Duration>,
+
+    /// Whether receive windows are sized from the bandwidth-delay product.
+    adaptive_window: bool,
+
+    /// Maximum size adaptive receive windows grow to.
+    max_adaptive_window_size: u32,
+
+    /// Maximum time to wait for the peer to acknowledge our SETTINGS frame.
+    settings_ack_timeout: Option<Duration>,
+
+    /// Whether SETTINGS updates queued before the previous one was written
+    /// are merged into it.
+    coalesce_settings_updates: bool,
+
+    /// Idle duration after which a keep-alive PING is sent.
+    keep_alive_interval: Option<Duration>,
+
+    /// Maximum time to wait for a keep-alive PING to be acknowledged.
+    keep_alive_timeout: Duration,
+
+    /// Maximum number of PINGs accepted from the peer per `ping_interval`.
+    max_pings_per_interval: Option<usize>,
+
+    /// Interval over which received PINGs are counted.
+    ping_interval: Duration,
+
     /// Maximum number of locally reset streams to keep at a time.
     reset_stream_max: usize,
 
+    /// Maximum number of streams reserved by the server at a time.
+    max_reserved_remote_streams: usize,
+
+    /// Maximum number of bytes of received data buffered per stream before
+    /// window updates are held back.
+    max_buffered_recv_bytes_per_stream: Option<usize>,
+
+    /// Maximum time a stream may buffer too much received data.
+    buffered_recv_timeout: Option<Duration>,
+
     /// Initial `Settings` frame to send as part of the handshake.
-    settings: Settings,
+    settings: SettingsBuilder,
+
+    /// Local cap on the header table size used to encode the header blocks
+    /// sent to the peer.
+    max_send_header_table_size: Option<usize>,
+
+    /// Maximum size of a received header block, across CONTINUATION frames.
+    max_header_block_size: Option<usize>,
+
+    /// Maximum size of a sent header block, across CONTINUATION frames.
+    max_send_header_block_size: Option<usize>,
+
+    /// Maximum number of CONTINUATION frames in a received header block.
+    max_continuation_frames: Option<usize>,
+
+    /// Whether header fields forbidden in HTTP/2 make a received header block
+    /// malformed.
+    strict_header_validation: bool,
+
+    /// Maximum number of parameters accepted in a received SETTINGS frame.
+    max_settings_entries: usize,
+
+    /// Maximum number of bytes of debug data kept from a received GOAWAY
+    /// frame.
+    max_go_away_debug_data: usize,
+
+    /// Called with extension settings received from the peer.
+    on_unknown_setting: Option<proto::OnUnknownSetting>,
+
+    /// Called with the alternative services advertised by the server.
+    on_alt_svc: Option<proto::OnAltSvc>,
+
+    /// Types of the extension frames passed to `on_extension_frame`.
+    extension_types: Vec<u8>,
+
+    /// Maximum payload size of a received extension frame.
+    max_extension_frame_size: usize,
+
+    /// Called with the extension frames received from the peer.
+    on_extension_frame: Option<proto::OnExtensionFrame>,
+
+    /// Chooses the padding of the sent DATA and HEADERS frames.
+    padding: Option<Padding>,
+
+    /// Called with the frames sent and received.
+    frame_tap: Option<codec::FrameTap>,
+
+    /// Size of the buffer the frames to send are written to.
+    write_buffer_size: usize,
+
+    /// When the buffered frames are written to the socket.
+    flush_strategy: FlushStrategy,
+
+    /// Header fields whose values are sent as never indexed literals.
+    sensitive_headers: Vec<HeaderName>,
 
     /// The stream ID of the first (lowest) stream. Subsequent streams will use
     /// monotonically increasing stream IDs.
@@ -371,17 +585,62 @@ where
     /// Returns `Ready` when the connection can initialize a new HTTP/2.0
     /// stream.
     ///
-    /// This function must return `Ready` before `send_request` is called. When
-    /// `NotReady` is returned, the task will be notified once the readiness
-    /// state changes.
+    /// This is the case while fewer streams than the server's
+    /// `MAX_CONCURRENT_STREAMS` setting are active, and no request is queued.
+    /// When `NotReady` is returned, the task will be notified once a stream
+    /// closes, freeing capacity.
+    ///
+    /// This function should return `Ready` before `send_request` is called.
+    /// Requests sent regardless are queued, up to the limit set with
+    /// [`max_pending_open_streams`].
+    ///
+    /// Once the connection has used up all of its stream IDs, an error is
+    /// returned for which [`is_stream_id_overflow`] is true, and the
+    /// connection closes after the remaining streams complete.
     ///
     /// See [module] level docs for more details.
     ///
     /// [module]: index.html
+    /// [`max_pending_open_streams`]: struct.Builder.html#method.max_pending_open_streams
+    /// [`is_stream_id_overflow`]: ../struct.Error.html#method.is_stream_id_overflow
     pub fn poll_ready(&mut self) -> Poll<(), ::Error> {
-        try_ready!(self.inner.poll_pending_open(self.pending.as_ref()));
-        self.pending = None;
-        Ok(().into())
+        self.inner.poll_open_ready()
+    }
+
+    /// Returns the number of streams opened by the client that are still
+    /// active.
+    ///
+    /// Streams are active until they are closed, and count towards the
+    /// server's `MAX_CONCURRENT_STREAMS` setting. Requests waiting for a
+    /// stream to close are not included.
+    pub fn current_streams(&self) -> usize {
+        self.inner.num_send_streams()
+    }
+
+    /// Returns the maximum number of streams the client may have active at a
+    /// time.
+    ///
+    /// This is the `MAX_CONCURRENT_STREAMS` setting of the server, or the
+    /// value set with [`initial_max_send_streams`] until the server's
+    /// SETTINGS frame is received.
+    ///
+    /// [`initial_max_send_streams`]: struct.Builder.html#method.initial_max_send_streams
+    pub fn max_streams(&self) -> usize {
+        self.inner.max_send_streams()
+    }
+
+    /// Returns `true` if the connection is closing and no new requests can
+    /// be sent on it.
+    ///
+    /// This happens once the server sends a GOAWAY frame, for instance as
+    /// part of a graceful shutdown, or when the connection fails. Requests
+    /// sent after a GOAWAY frame fail with an error for which
+    /// [`Error::is_remote_refused`] returns `true`, so they should be
+    /// dispatched to another connection instead.
+    ///
+    /// [`Error::is_remote_refused`]: ../struct.Error.html#method.is_remote_refused
+    pub fn is_closing(&self) -> bool {
+        self.inner.is_closing()
     }
 
     /// Consumes `self`, returning a future that returns `self` back once it is
@@ -424,6 +683,31 @@ where
         ReadySendRequest { inner: Some(self) }
     }
 
+    /// Returns true if the server supports the extended CONNECT method.
+    ///
+    /// This is the case once the server has sent a SETTINGS frame with
+    /// `SETTINGS_ENABLE_CONNECT_PROTOCOL` set to 1, as described in [RFC
+    /// 8441]. Until the server's SETTINGS frame has been received, this
+    /// returns `false`.
+    ///
+    /// [RFC 8441]: https://tools.ietf.org/html/rfc8441#section-3
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.inner.is_extended_connect_protocol_enabled()
+    }
+
+    /// Returns a snapshot of the settings received from the server.
+    ///
+    /// The snapshot reflects every SETTINGS frame received so far. Before the
+    /// server's first SETTINGS frame has been processed, all values are
+    /// reported with their protocol defaults.
+    ///
+    /// See [`PeerSettings`] for more details.
+    ///
+    /// [`PeerSettings`]: ../struct.PeerSettings.html
+    pub fn peer_settings(&self) -> PeerSettings {
+        PeerSettings::new(self.inner.remote_settings())
+    }
+
     /// Sends a HTTP/2.0 request to the server.
     ///
     /// `send_request` initializes a new HTTP/2.0 stream on the associated
@@ -458,6 +742,22 @@ where
     /// The caller should always set the request's version field to 2.0 unless
     /// specifically transmitting an HTTP 1.1 request over 2.0.
     ///
+    /// # CONNECT requests
+    ///
+    /// A `CONNECT` request to an authority-form URI, such as
+    /// `example.com:443`, opens a tunnel as described in section 8.3 of the
+    /// HTTP/2.0 specification. The request is sent with `end_of_stream` set
+    /// to `false`, and once a 2xx response is received, the [`SendStream`] and
+    /// the response body carry the tunneled bytes. Ending either side of the
+    /// stream maps to closing one direction of the tunneled TCP connection.
+    /// Data must not be sent before the response is received.
+    ///
+    /// Once the server enabled the extended CONNECT method (see
+    /// [`is_extended_connect_protocol_enabled`]), a `CONNECT` request to an
+    /// absolute URI may carry a [`Protocol`] in its extensions, which is sent
+    /// as the `:protocol` pseudo header. Sending it before that returns an
+    /// error.
+    ///
     /// # Examples
     ///
     /// Sending a request with no body
@@ -552,21 +852,89 @@ where
     /// [`SendStream`]: ../struct.SendStream.html
     /// [`SendStream::send_data`]: ../struct.SendStream.html#method.send_data
     /// [`SendStream::send_trailers`]: ../struct.SendStream.html#method.send_trailers
+    /// [`is_extended_connect_protocol_enabled`]: #method.is_extended_connect_protocol_enabled
+    /// [`Protocol`]: ../struct.Protocol.html
     pub fn send_request(
         &mut self,
         request: Request<()>,
         end_of_stream: bool,
+    ) -> Result<(ResponseFuture, SendStream<B>), ::Error> {
+        self.send(request, end_of_stream, None)
+    }
+
+    /// Sends a HTTP/2.0 request to the server, with the given priority.
+    ///
+    /// This behaves like [`send_request`], except that the priority is set
+    /// on the HEADERS frame opening the stream, as described in [Section
+    /// 5.3] of RFC 7540. The priority can be changed later on using
+    /// [`SendStream::send_priority`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the weight of `priority` is not between 1 and 256,
+    /// or if `priority` makes the new stream depend on itself.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate futures;
+    /// # extern crate h2;
+    /// # extern crate http;
+    /// # use futures::*;
+    /// # use h2::Priority;
+    /// # use h2::client::*;
+    /// # use http::*;
+    /// # fn doc(send_request: SendRequest<&'static [u8]>)
+    /// # {
+    /// send_request.ready()
+    ///     .and_then(|mut send_request| {
+    ///         let request = Request::get("https://www.example.com/style.css")
+    ///             .body(())
+    ///             .unwrap();
+    ///
+    ///         // Get the stylesheet before other resources.
+    ///         let priority = Priority::new().weight(256);
+    ///
+    ///         let (response, _) = send_request
+    ///             .send_request_with_priority(request, true, priority).unwrap();
+    ///
+    ///         response
+    ///     })
+    ///     .and_then(|response| {
+    ///         // Process the response
+    ///         # Ok(())
+    ///     })
+    ///     # .wait().unwrap();
+    /// # }
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`send_request`]: #method.send_request
+    /// [Section 5.3]: https://tools.ietf.org/html/rfc7540#section-5.3
+    /// [`SendStream::send_priority`]: ../struct.SendStream.html#method.send_priority
+    pub fn send_request_with_priority(
+        &mut self,
+        request: Request<()>,
+        end_of_stream: bool,
+        priority: Priority,
+    ) -> Result<(ResponseFuture, SendStream<B>), ::Error> {
+        let priority = priority.into_internal()?;
+        self.send(request, end_of_stream, Some(priority))
+    }
+
+    fn send(
+        &mut self,
+        request: Request<()>,
+        end_of_stream: bool,
+        priority: Option<frame::StreamDependency>,
     ) -> Result<(ResponseFuture, SendStream<B>), ::Error> {
         self.inner
-            .send_request(request, end_of_stream, self.pending.as_ref())
+            .send_request(request, end_of_stream, priority)
             .map_err(Into::into)
             .map(|stream| {
-                if stream.is_pending_open() {
-                    self.pending = Some(stream.clone_to_opaque());
-                }
-
                 let response = ResponseFuture {
                     inner: stream.clone_to_opaque(),
+                    push_promise_consumed: false,
                 };
 
                 let stream = SendStream::new(stream);
@@ -592,12 +960,10 @@ where
     fn clone(&self) -> Self {
         SendRequest {
             inner: self.inner.clone(),
-            pending: None,
         }
     }
 }
 
-#[cfg(feature = "unstable")]
 impl<B> SendRequest<B>
 where
     B: IntoBuf,
@@ -605,11 +971,20 @@ where
     /// Returns the number of active streams.
     ///
     /// An active stream is a stream that has not yet transitioned to a closed
-    /// state.
+    /// state. Unlike [`current_streams`], this includes the streams pushed by
+    /// the server.
+    ///
+    /// [`current_streams`]: #method.current_streams
     pub fn num_active_streams(&self) -> usize {
         self.inner.num_active_streams()
     }
+}
 
+#[cfg(feature = "unstable")]
+impl<B> SendRequest<B>
+where
+    B: IntoBuf,
+{
     /// Returns the number of streams that are held in memory.
     ///
     /// A wired stream is a stream that is either active or is closed but must
@@ -675,10 +1050,46 @@ impl Builder {
         Builder {
             reset_stream_duration: Duration::from_secs(proto::DEFAULT_RESET_STREAM_SECS),
             reset_stream_max: proto::DEFAULT_RESET_STREAM_MAX,
+            max_reserved_remote_streams: proto::DEFAULT_MAX_RESERVED_REMOTE_STREAMS,
+            max_buffered_recv_bytes_per_stream: None,
+            buffered_recv_timeout: None,
             initial_target_connection_window_size: None,
+            window_update_threshold: proto::DEFAULT_WINDOW_UPDATE_THRESHOLD,
+            flow_control_mode: FlowControlMode::Auto,
+            window_stall_warning: None,
+            adaptive_window: false,
+            max_adaptive_window_size: proto::DEFAULT_MAX_ADAPTIVE_WINDOW_SIZE,
             initial_max_send_streams: usize::MAX,
-            settings: Default::default(),
+            max_pending_open_streams: proto::DEFAULT_MAX_PENDING_OPEN_STREAMS,
+            settings: Settings::builder(),
+            max_send_header_table_size: None,
+            max_header_block_size: None,
+            max_send_header_block_size: None,
+            max_continuation_frames: None,
+            strict_header_validation: true,
+            max_settings_entries: frame::DEFAULT_MAX_SETTINGS_ENTRIES,
+            max_go_away_debug_data: frame::DEFAULT_MAX_GO_AWAY_DEBUG_DATA,
             stream_id: 1.into(),
+            settings_ack_timeout: None,
+            coalesce_settings_updates: true,
+            keep_alive_interval: None,
+            keep_alive_timeout: Duration::from_secs(proto::DEFAULT_KEEP_ALIVE_TIMEOUT_SECS),
+            max_pings_per_interval: None,
+            ping_interval: Duration::from_secs(proto::DEFAULT_PING_INTERVAL_SECS),
+            on_unknown_setting: None,
+            on_alt_svc: None,
+            extension_types: Vec::new(),
+            max_extension_frame_size: frame::DEFAULT_MAX_FRAME_SIZE as usize,
+            on_extension_frame: None,
+            padding: None,
+            frame_tap: None,
+            write_buffer_size: codec::DEFAULT_BUFFER_CAPACITY,
+            flush_strategy: FlushStrategy::Adaptive,
+            sensitive_headers: vec![
+                header::AUTHORIZATION,
+                header::COOKIE,
+                header::PROXY_AUTHORIZATION,
+            ],
         }
     }
 
@@ -713,8 +1124,13 @@ impl Builder {
     /// #
     /// # pub fn main() {}
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// The handshake fails if `size` is greater than 2^31-1, the largest
+    /// window size allowed by HTTP/2.0.
     pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
-        self.settings.set_initial_window_size(Some(size));
+        self.settings.initial_window_size(size);
         self
     }
 
@@ -724,8 +1140,17 @@ impl Builder {
     /// The initial window of a connection is used as part of flow control. For more details,
     /// see [`ReleaseCapacity`].
     ///
+    /// Connections always start with a window of 65,535 octets, so a larger
+    /// value is announced with a `WINDOW_UPDATE` frame as soon as the
+    /// handshake completes.
+    ///
     /// The default value is 65,535.
     ///
+    /// # Panics
+    ///
+    /// This function panics if `size` is greater than 2^31-1, the largest
+    /// window size allowed by HTTP/2.0.
+    ///
     /// [`ReleaseCapacity`]: ../struct.ReleaseCapacity.html
     ///
     /// # Examples
@@ -750,18 +1175,28 @@ impl Builder {
     /// # pub fn main() {}
     /// ```
     pub fn initial_connection_window_size(&mut self, size: u32) -> &mut Self {
+        assert!(size <= proto::MAX_WINDOW_SIZE);
         self.initial_target_connection_window_size = Some(size);
         self
     }
 
-    /// Indicates the size (in octets) of the largest HTTP/2.0 frame payload that the
-    /// configured client is able to accept.
+    /// Sets how much of the receive window must be released before it is
+    /// announced to the peer, as a percentage of the target window.
     ///
-    /// The sender may send data frames that are **smaller** than this value,
-    /// but any data larger than `max` will be broken up into multiple `DATA`
-    /// frames.
+    /// Capacity released with [`ReleaseCapacity`] is not announced right
+    /// away. WINDOW_UPDATE frames are only sent once the released and not yet
+    /// announced capacity of the connection, or of a stream, reaches this
+    /// percentage of its target window, which avoids sending one for nearly
+    /// every DATA frame received. A lower value keeps the peer's window
+    /// fuller at the cost of more frames. With 0, every release is announced.
     ///
-    /// The value **must** be between 16,384 and 16,777,215. The default value is 16,384.
+    /// The default value is 50.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `percent` is greater than 100.
+    ///
+    /// [`ReleaseCapacity`]: ../struct.ReleaseCapacity.html
     ///
     /// # Examples
     ///
@@ -777,32 +1212,29 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_frame_size(1_000_000)
+    ///     .window_update_threshold(25)
     ///     .handshake(my_io);
     /// # client_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    ///
-    /// # Panics
-    ///
-    /// This function panics if `max` is not within the legal range specified
-    /// above.
-    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_frame_size(Some(max));
+    pub fn window_update_threshold(&mut self, percent: u8) -> &mut Self {
+        assert!(percent <= 100, "window update threshold is a percentage");
+        self.window_update_threshold = percent;
         self
     }
 
-    /// Sets the max size of received header frames.
+    /// Sets how the connection level receive window is released.
     ///
-    /// This advisory setting informs a peer of the maximum size of header list
-    /// that the sender is prepared to accept, in octets. The value is based on
-    /// the uncompressed size of header fields, including the length of the name
-    /// and value in octets plus an overhead of 32 octets for each header field.
+    /// With [`FlowControlMode::Manual`], releasing capacity with
+    /// [`ReleaseCapacity::release_capacity`] only increments the window of
+    /// the stream, and the connection window is incremented with
+    /// [`ReleaseCapacity::release_connection_capacity`]. No WINDOW_UPDATE
+    /// frame is then sent for received data the application did not
+    /// release, on the stream or on the connection.
     ///
-    /// This setting is also used to limit the maximum amount of data that is
-    /// buffered to decode HEADERS frames.
+    /// The default value is [`FlowControlMode::Auto`].
     ///
     /// # Examples
     ///
@@ -811,6 +1243,7 @@ impl Builder {
     /// # extern crate tokio_io;
     /// # use tokio_io::*;
     /// # use h2::client::*;
+    /// use h2::FlowControlMode;
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
     /// # -> Handshake<T>
@@ -818,41 +1251,32 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_header_list_size(16 * 1024)
+    ///     .flow_control_mode(FlowControlMode::Manual)
     ///     .handshake(my_io);
     /// # client_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_header_list_size(Some(max));
+    ///
+    /// [`FlowControlMode::Manual`]: ../enum.FlowControlMode.html#variant.Manual
+    /// [`FlowControlMode::Auto`]: ../enum.FlowControlMode.html#variant.Auto
+    /// [`ReleaseCapacity::release_capacity`]: ../struct.ReleaseCapacity.html#method.release_capacity
+    /// [`ReleaseCapacity::release_connection_capacity`]: ../struct.ReleaseCapacity.html#method.release_connection_capacity
+    pub fn flow_control_mode(&mut self, mode: FlowControlMode) -> &mut Self {
+        self.flow_control_mode = mode;
         self
     }
 
-    /// Sets the maximum number of concurrent streams.
-    ///
-    /// The maximum concurrent streams setting only controls the maximum number
-    /// of streams that can be initiated by the remote peer. In other words,
-    /// when this setting is set to 100, this does not limit the number of
-    /// concurrent streams that can be created by the caller.
+    /// Logs a warning when the receive window of a stream, or of the
+    /// connection, stays exhausted for longer than `dur`.
     ///
-    /// It is recommended that this value be no smaller than 100, so as to not
-    /// unnecessarily limit parallelism. However, any value is legal, including
-    /// 0. If `max` is set to 0, then the remote will not be permitted to
-    /// initiate streams.
-    ///
-    /// Note that streams in the reserved state, i.e., push promises that have
-    /// been reserved but the stream has not started, do not count against this
-    /// setting.
+    /// The peer cannot send any more data until the received data is
+    /// released, which an application using [manual flow control] may forget
+    /// to do, stalling the stream or the whole connection. Each stall is
+    /// reported once, when the connection is polled.
     ///
-    /// Also note that if the remote *does* exceed the value set here, it is not
-    /// a protocol level error. Instead, the `h2` library will immediately reset
-    /// the stream.
-    ///
-    /// See [Section 5.1.2] in the HTTP/2.0 spec for more details.
-    ///
-    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    /// By default, stalls are not reported.
     ///
     /// # Examples
     ///
@@ -861,6 +1285,7 @@ impl Builder {
     /// # extern crate tokio_io;
     /// # use tokio_io::*;
     /// # use h2::client::*;
+    /// # use std::time::Duration;
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
     /// # -> Handshake<T>
@@ -868,33 +1293,36 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_concurrent_streams(1000)
+    ///     .window_stall_warning(Duration::from_secs(30))
     ///     .handshake(my_io);
     /// # client_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_concurrent_streams(Some(max));
+    ///
+    /// [manual flow control]: #method.flow_control_mode
+    pub fn window_stall_warning(&mut self, dur: Duration) -> &mut Self {
+        self.window_stall_warning = Some(dur);
         self
     }
 
-    /// Sets the initial maximum of locally initiated (send) streams.
-    ///
-    /// The initial settings will be overwritten by the remote peer when
-    /// the Settings frame is received. The new value will be set to the
-    /// `max_concurrent_streams()` from the frame.
+    /// Enables or disables sizing receive windows from the measured
+    /// bandwidth-delay product of the connection.
     ///
-    /// This setting prevents the caller from exceeding this number of
-    /// streams that are counted towards the concurrency limit.
-    ///
-    /// Sending streams past the limit returned by the peer will be treated
-    /// as a stream error of type PROTOCOL_ERROR or REFUSED_STREAM.
+    /// When enabled, a PING is sent as DATA starts arriving and the amount of
+    /// data received until it is acknowledged is measured. When the peer used
+    /// most of a window within that round trip, the window is what limits
+    /// the throughput: the connection window or the initial window of
+    /// streams then grows to twice the measured amount, the latter with a
+    /// SETTINGS frame. Windows never shrink, and never grow past
+    /// [`max_adaptive_window_size`].
     ///
-    /// See [Section 5.1.2] in the HTTP/2.0 spec for more details.
+    /// Each window grows from the size configured with
+    /// [`initial_window_size`] or [`initial_connection_window_size`], or
+    /// later set with `set_target_window_size` for the connection window.
     ///
-    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    /// The default value is `false`.
     ///
     /// # Examples
     ///
@@ -910,37 +1338,28 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .initial_max_send_streams(1000)
+    ///     .adaptive_window(true)
     ///     .handshake(my_io);
     /// # client_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn initial_max_send_streams(&mut self, initial: usize) -> &mut Self {
-        self.initial_max_send_streams = initial;
+    ///
+    /// [`max_adaptive_window_size`]: #method.max_adaptive_window_size
+    /// [`initial_window_size`]: #method.initial_window_size
+    /// [`initial_connection_window_size`]: #method.initial_connection_window_size
+    pub fn adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        self.adaptive_window = enabled;
         self
     }
 
-    /// Sets the maximum number of concurrent locally reset streams.
-    ///
-    /// When a stream is explicitly reset, the HTTP/2.0 specification requires
-    /// that any further frames received for that stream must be ignored for
-    /// "some time".
-    ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
-    ///
-    /// The `max_concurrent_reset_streams` setting configures sets an upper
-    /// bound on the amount of state that is maintained. When this max value is
-    /// reached, the oldest reset stream is purged from memory.
+    /// Sets the maximum size (in octets) adaptive receive windows grow to.
     ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// This has no effect unless [`adaptive_window`] is enabled.
     ///
-    /// The default value is 10.
+    /// The default value is 16 MB. Values greater than 2^31-1, the largest
+    /// window size allowed by HTTP/2.0, are clamped to it.
     ///
     /// # Examples
     ///
@@ -956,46 +1375,1407 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_concurrent_reset_streams(1000)
+    ///     .adaptive_window(true)
+    ///     .max_adaptive_window_size(4 << 20)
     ///     .handshake(my_io);
     /// # client_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
-        self.reset_stream_max = max;
+    ///
+    /// [`adaptive_window`]: #method.adaptive_window
+    pub fn max_adaptive_window_size(&mut self, size: u32) -> &mut Self {
+        self.max_adaptive_window_size = cmp::min(size, proto::MAX_WINDOW_SIZE);
         self
     }
 
-    /// Sets the duration to remember locally reset streams.
+    /// Indicates the size (in octets) of the largest HTTP/2.0 frame payload that the
+    /// configured client is able to accept.
     ///
-    /// When a stream is explicitly reset, the HTTP/2.0 specification requires
-    /// that any further frames received for that stream must be ignored for
-    /// "some time".
+    /// The sender may send data frames that are **smaller** than this value,
+    /// but any data larger than `max` will be broken up into multiple `DATA`
+    /// frames.
     ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
+    /// The value **must** be between 16,384 and 16,777,215. The default value is 16,384.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_frame_size(1_000_000)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// The handshake fails if `max` is not within the legal range specified
+    /// above.
+    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
+        self.settings.max_frame_size(max);
+        self
+    }
+
+    /// Sets the max size of received header frames.
+    ///
+    /// This advisory setting informs a peer of the maximum size of header list
+    /// that the sender is prepared to accept, in octets. The value is based on
+    /// the uncompressed size of header fields, including the length of the name
+    /// and value in octets plus an overhead of 32 octets for each header field.
+    ///
+    /// This setting is also used to limit the maximum amount of data that is
+    /// buffered to decode HEADERS frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_header_list_size(16 * 1024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.settings.max_header_list_size(max);
+        self
+    }
+
+    /// Sets the max size of the header table the peer may use to compress
+    /// the header blocks it sends.
+    ///
+    /// This is `SETTINGS_HEADER_TABLE_SIZE`, the max size of the HPACK dynamic
+    /// table, in octets. A smaller table uses less memory, at the cost of a
+    /// lower compression ratio. The header blocks received before the peer
+    /// acknowledges the setting may use the default size of 4,096 octets.
+    ///
+    /// A header block resizing the table over this limit is a connection
+    /// error of type `COMPRESSION_ERROR`.
+    ///
+    /// The default value is 4,096.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .header_table_size(1024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn header_table_size(&mut self, size: u32) -> &mut Self {
+        self.settings.header_table_size(size);
+        self
+    }
+
+    /// Sets the max size of the header table used to compress the header
+    /// blocks sent to the peer.
+    ///
+    /// The peer's `SETTINGS_HEADER_TABLE_SIZE` is the max size of the HPACK
+    /// dynamic table it allows. The table used is the smaller of that and this
+    /// value, which bounds the memory used by each connection, at the cost of
+    /// a lower compression ratio.
+    ///
+    /// By default, the table uses the size allowed by the peer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_send_header_table_size(1024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_send_header_table_size(&mut self, size: u32) -> &mut Self {
+        self.max_send_header_table_size = Some(size as usize);
+        self
+    }
+
+    /// Sets the maximum size of a header block received from the peer.
+    ///
+    /// A header block is sent in a HEADERS or PUSH_PROMISE frame, followed by
+    /// any number of CONTINUATION frames. This limits the total size of the
+    /// payloads of these frames, in octets, before HPACK decoding. A peer
+    /// exceeding it is sent a GOAWAY frame with `ENHANCE_YOUR_CALM`.
+    ///
+    /// The default value is the maximum header list size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_header_block_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_header_block_size(&mut self, max: usize) -> &mut Self {
+        self.max_header_block_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum size of a header block sent to the peer.
+    ///
+    /// A header block larger than a frame is split in CONTINUATION frames,
+    /// which cannot be interleaved with the frames of other streams: a huge
+    /// header block holds up every stream of the connection until it is
+    /// written. Headers, trailers and push promises over this limit fail fast
+    /// with a user error, and are not sent.
+    ///
+    /// The size is measured as the header list size of
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`, the length of each name and value plus
+    /// 32 octets per field, which is never smaller than the HPACK encoded
+    /// block.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_send_header_block_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_send_header_block_size(&mut self, max: usize) -> &mut Self {
+        self.max_send_header_block_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of CONTINUATION frames in a header block
+    /// received from the peer.
+    ///
+    /// Without this limit, a peer could send an endless stream of small
+    /// CONTINUATION frames. A peer exceeding it is sent a GOAWAY frame with
+    /// `ENHANCE_YOUR_CALM`.
+    ///
+    /// By default, the limit is the number of frames needed to send a header
+    /// block of the [maximum size] with frames of the maximum frame size, plus
+    /// 25%, and at least 5.
+    ///
+    /// [maximum size]: #method.max_header_block_size
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_continuation_frames(16)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_continuation_frames(&mut self, max: usize) -> &mut Self {
+        self.max_continuation_frames = Some(max);
+        self
+    }
+
+    /// Enables or disables the validation of the header fields received from
+    /// the server.
+    ///
+    /// Section 8.1.2 of RFC 7540 forbids uppercase header names,
+    /// connection-specific header fields (such as `connection` or
+    /// `transfer-encoding`) and a `te` header field with a value other than
+    /// "trailers". By default, a response or a push promise containing such a
+    /// header field is malformed, and the stream is reset with
+    /// `PROTOCOL_ERROR`.
+    ///
+    /// Disabling the validation passes these header fields through, which is
+    /// useful to proxies wanting the raw view of the peer's headers. The names
+    /// are still lowercased. The pseudo-header fields are always validated,
+    /// and so are the octets of the names and values: a name that is not a
+    /// token, or a value containing NUL, CR or LF, always makes the message
+    /// malformed.
+    ///
+    /// The default value is `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .strict_header_validation(false)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn strict_header_validation(&mut self, enabled: bool) -> &mut Self {
+        self.strict_header_validation = enabled;
+        self
+    }
+
+    /// Sets the maximum number of concurrent streams.
+    ///
+    /// The maximum concurrent streams setting only controls the maximum number
+    /// of streams that can be initiated by the remote peer. In other words,
+    /// when this setting is set to 100, this does not limit the number of
+    /// concurrent streams that can be created by the caller.
+    ///
+    /// It is recommended that this value be no smaller than 100, so as to not
+    /// unnecessarily limit parallelism. However, any value is legal, including
+    /// 0. If `max` is set to 0, then the remote will not be permitted to
+    /// initiate streams.
+    ///
+    /// Note that streams in the reserved state, i.e., push promises that have
+    /// been reserved but the stream has not started, do not count against this
+    /// setting.
+    ///
+    /// Also note that if the remote *does* exceed the value set here, it is not
+    /// a protocol level error. Instead, the `h2` library will immediately reset
+    /// the stream.
+    ///
+    /// See [Section 5.1.2] in the HTTP/2.0 spec for more details.
+    ///
+    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_concurrent_streams(1000)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.settings.max_concurrent_streams(max);
+        self
+    }
+
+    /// Sets the initial maximum of locally initiated (send) streams.
+    ///
+    /// The initial settings will be overwritten by the remote peer when
+    /// the Settings frame is received. The new value will be set to the
+    /// `max_concurrent_streams()` from the frame.
+    ///
+    /// This setting prevents the caller from exceeding this number of
+    /// streams that are counted towards the concurrency limit.
+    ///
+    /// Sending streams past the limit returned by the peer will be treated
+    /// as a stream error of type PROTOCOL_ERROR or REFUSED_STREAM.
+    ///
+    /// See [Section 5.1.2] in the HTTP/2.0 spec for more details.
+    ///
+    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .initial_max_send_streams(1000)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn initial_max_send_streams(&mut self, initial: usize) -> &mut Self {
+        self.initial_max_send_streams = initial;
+        self
+    }
+
+    /// Sets the maximum number of requests waiting for a stream to be
+    /// opened.
+    ///
+    /// Requests sent while the server's `MAX_CONCURRENT_STREAMS` limit is
+    /// reached are queued, and sent in order as streams close. Once `max`
+    /// requests are queued, `send_request` returns an error for which
+    /// [`Error::is_at_capacity`] returns `true`. Waiting for
+    /// [`SendRequest::poll_ready`] before sending a request avoids queuing
+    /// it.
+    ///
+    /// The default value is 10.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_pending_open_streams(100)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Error::is_at_capacity`]: ../struct.Error.html#method.is_at_capacity
+    /// [`SendRequest::poll_ready`]: struct.SendRequest.html#method.poll_ready
+    pub fn max_pending_open_streams(&mut self, max: usize) -> &mut Self {
+        self.max_pending_open_streams = max;
+        self
+    }
+
+    /// Sets the maximum number of parameters accepted in a single SETTINGS
+    /// frame.
+    ///
+    /// Every parameter in a received SETTINGS frame counts towards this limit,
+    /// including repeated and unknown parameters. A SETTINGS frame exceeding
+    /// the limit is treated as a connection error and a GOAWAY frame with
+    /// `ENHANCE_YOUR_CALM` is sent to the peer.
+    ///
+    /// The default value is 32.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_settings_entries(64)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_settings_entries(&mut self, max: usize) -> &mut Self {
+        self.max_settings_entries = max;
+        self
+    }
+
+    /// Sets the maximum number of bytes of debug data kept from a GOAWAY
+    /// frame received from the peer.
+    ///
+    /// The debug data of a GOAWAY frame is made available through
+    /// [`Error::debug_data`] when the peer closes the connection with an
+    /// error. Any debug data past this limit is discarded, so that a peer
+    /// cannot make the connection hold on to a large amount of memory.
+    ///
+    /// The default value is 1,024.
+    ///
+    /// [`Error::debug_data`]: ../struct.Error.html#method.debug_data
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_go_away_debug_data(4_096)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_go_away_debug_data(&mut self, max: usize) -> &mut Self {
+        self.max_go_away_debug_data = max;
+        self
+    }
+
+    /// Sets the maximum number of concurrent locally reset streams.
+    ///
+    /// When a stream is explicitly reset, the HTTP/2.0 specification requires
+    /// that any further frames received for that stream must be ignored for
+    /// "some time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `max_concurrent_reset_streams` setting configures sets an upper
+    /// bound on the amount of state that is maintained. When this max value is
+    /// reached, the oldest reset stream is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional DATA
+    /// or HEADERS frames received for that stream will result in a connection
+    /// level protocol error, forcing the connection to terminate. PRIORITY,
+    /// WINDOW_UPDATE and RST_STREAM frames, which the peer may still send on a
+    /// closed stream, are ignored.
+    ///
+    /// The default value is 10.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_concurrent_reset_streams(1000)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.reset_stream_max = max;
+        self
+    }
+
+    /// Sets the maximum number of streams the server may reserve at a time.
+    ///
+    /// A server reserves a stream with each PUSH_PROMISE frame, and the
+    /// reserved stream, along with the promised request, is kept until the
+    /// server starts the pushed response. Streams in the reserved state do
+    /// not count towards the max concurrent streams limit.
+    ///
+    /// Promises received while the limit is reached are reset with
+    /// `REFUSED_STREAM` and their request is discarded.
+    ///
+    /// The default value is 100.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_reserved_remote_streams(10)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_reserved_remote_streams(&mut self, max: usize) -> &mut Self {
+        self.max_reserved_remote_streams = max;
+        self
+    }
+
+    /// Sets the duration to remember locally reset streams.
+    ///
+    /// When a stream is explicitly reset, the HTTP/2.0 specification requires
+    /// that any further frames received for that stream must be ignored for
+    /// "some time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
     /// streams that are locally reset.
     ///
-    /// The `reset_stream_duration` setting configures the max amount of time
-    /// this state will be maintained in memory. Once the duration elapses, the
-    /// stream state is purged from memory.
+    /// The `reset_stream_duration` setting configures the max amount of time
+    /// this state will be maintained in memory. Once the duration elapses, the
+    /// stream state is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional DATA
+    /// or HEADERS frames received for that stream will result in a connection
+    /// level protocol error, forcing the connection to terminate. PRIORITY,
+    /// WINDOW_UPDATE and RST_STREAM frames, which the peer may still send on a
+    /// closed stream, are ignored.
+    ///
+    /// The default value is 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
+        self.reset_stream_duration = dur;
+        self
+    }
+
+    /// Sets the maximum number of bytes of received data buffered per stream
+    /// before WINDOW_UPDATE frames are held back.
+    ///
+    /// Received DATA frames are buffered until they are read from the
+    /// `RecvStream`. Flow control bounds the buffered data by the stream
+    /// window, but releasing capacity does not require reading the data.
+    /// When a stream buffers more than `max` bytes, no WINDOW_UPDATE frames
+    /// are sent for it until the data is read, even if its capacity was
+    /// released. The peer may still send data up to the remaining window, so
+    /// this is a soft limit.
+    ///
+    /// The connection window is not affected, so other streams keep making
+    /// progress.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_buffered_recv_bytes_per_stream(16_384)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_buffered_recv_bytes_per_stream(&mut self, max: usize) -> &mut Self {
+        self.max_buffered_recv_bytes_per_stream = Some(max);
+        self
+    }
+
+    /// Sets how long a stream may buffer more than
+    /// [`max_buffered_recv_bytes_per_stream`] bytes of received data before
+    /// it is reset.
+    ///
+    /// When the data is not read within `dur`, the stream is reset with the
+    /// `CANCEL` error code. Pending data can no longer be read from the
+    /// `RecvStream` once the stream is reset. The connection is woken up when
+    /// the timeout expires, see [timeouts].
+    ///
+    /// By default, streams are never reset for buffering too much data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_buffered_recv_bytes_per_stream(16_384)
+    ///     .buffered_recv_timeout(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_buffered_recv_bytes_per_stream`]: #method.max_buffered_recv_bytes_per_stream
+    /// [timeouts]: ../index.html#timeouts
+    pub fn buffered_recv_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.buffered_recv_timeout = Some(dur);
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for the peer to acknowledge
+    /// the SETTINGS frame sent during the handshake.
+    ///
+    /// Values in our SETTINGS frame, such as the initial window size, are only
+    /// put into effect once the peer acknowledges them. If no acknowledgement
+    /// is received within `dur`, the connection is terminated with a
+    /// `SETTINGS_TIMEOUT` error. The connection is woken up when the timeout
+    /// expires, see [timeouts].
+    ///
+    /// By default, there is no timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .settings_ack_timeout(Duration::from_secs(5))
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [timeouts]: ../index.html#timeouts
+    pub fn settings_ack_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.settings_ack_timeout = Some(dur);
+        self
+    }
+
+    /// Sets whether SETTINGS updates are coalesced.
+    ///
+    /// Updates queued with `update_settings` before the connection had the
+    /// opportunity to write the previous one are merged into it, and sent as
+    /// a single SETTINGS frame. Disabling this sends every update as its own
+    /// frame, in order, each acknowledged separately by the server.
+    ///
+    /// The default value is `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .coalesce_settings_updates(false)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn coalesce_settings_updates(&mut self, enabled: bool) -> &mut Self {
+        self.coalesce_settings_updates = enabled;
+        self
+    }
+
+    /// Sends PING frames to check that the connection is still alive once no
+    /// frame has been received for `dur`.
+    ///
+    /// If a PING is not acknowledged within the [`keep_alive_timeout`], the
+    /// connection is closed with an I/O error of kind `TimedOut`. The
+    /// acknowledgements are also used to measure the connection's round-trip
+    /// time, see [`Connection::rtt`]. The connection is woken up when either
+    /// duration expires, so that an idle connection is kept alive too, see
+    /// [timeouts].
+    ///
+    /// By default, keep-alive PINGs are not sent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .keep_alive_interval(Duration::from_secs(30))
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`keep_alive_timeout`]: #method.keep_alive_timeout
+    /// [`Connection::rtt`]: struct.Connection.html#method.rtt
+    /// [timeouts]: ../index.html#timeouts
+    pub fn keep_alive_interval(&mut self, dur: Duration) -> &mut Self {
+        self.keep_alive_interval = Some(dur);
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for a keep-alive PING to be
+    /// acknowledged before closing the connection.
+    ///
+    /// This has no effect unless the [`keep_alive_interval`] is set.
+    ///
+    /// The default value is 20 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .keep_alive_interval(Duration::from_secs(30))
+    ///     .keep_alive_timeout(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`keep_alive_interval`]: #method.keep_alive_interval
+    pub fn keep_alive_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.keep_alive_timeout = dur;
+        self
+    }
+
+    /// Sets the maximum number of PING frames the server may send within
+    /// each [`ping_interval`].
+    ///
+    /// Every PING received has to be acknowledged, so a peer flooding the
+    /// connection with them can starve other traffic. Once the limit is
+    /// exceeded, the connection is closed with a GOAWAY frame carrying
+    /// `ENHANCE_YOUR_CALM`. Acknowledgements of our own PINGs are not
+    /// counted.
+    ///
+    /// By default, the number of PINGs is not limited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_pings_per_interval(10)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`ping_interval`]: #method.ping_interval
+    pub fn max_pings_per_interval(&mut self, max: usize) -> &mut Self {
+        self.max_pings_per_interval = Some(max);
+        self
+    }
+
+    /// Sets the interval over which PING frames received from the server are
+    /// counted.
+    ///
+    /// This has no effect unless [`max_pings_per_interval`] is set.
+    ///
+    /// The default value is 1 second.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_pings_per_interval(100)
+    ///     .ping_interval(Duration::from_secs(60))
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_pings_per_interval`]: #method.max_pings_per_interval
+    pub fn ping_interval(&mut self, dur: Duration) -> &mut Self {
+        self.ping_interval = dur;
+        self
+    }
+
+    /// Includes an extension setting in the initial SETTINGS handshake.
+    ///
+    /// Extension settings are parameters not defined by the HTTP/2.0
+    /// specification, such as vendor-specific identifiers. They are sent
+    /// as-is; peers that do not understand them ignore them ([Section
+    /// 6.5.2]).
+    ///
+    /// [Section 6.5.2]: http://httpwg.org/specs/rfc7540.html#SettingValues
+    ///
+    /// # Errors
+    ///
+    /// The handshake fails if `id` identifies one of the settings defined by
+    /// the specification, which are configured through their dedicated
+    /// methods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .initial_setting(0xf000, 1)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn initial_setting(&mut self, id: u16, val: u32) -> &mut Self {
+        self.settings.opaque(id, val);
+        self
+    }
+
+    /// Sets a callback invoked with every extension setting received from
+    /// the peer.
+    ///
+    /// Extension settings are parameters not defined by the HTTP/2.0
+    /// specification. The callback is given the identifier and value of each
+    /// of them, as SETTINGS frames are received and applied. Settings defined
+    /// by the specification are never passed to the callback; use
+    /// `peer_settings` or `settings_changes` on the connection to observe
+    /// them.
+    ///
+    /// Setting a new callback replaces the previous one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .on_unknown_setting(|id, val| {
+    ///         println!("received setting {:#06x}={}", id, val);
+    ///     })
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn on_unknown_setting<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(u16, u32) + Send + Sync + 'static,
+    {
+        self.on_unknown_setting = Some(proto::OnUnknownSetting::new(f));
+        self
+    }
+
+    /// Sets a callback invoked with the alternative services advertised by
+    /// the server in ALTSVC frames ([RFC 7838]).
+    ///
+    /// The server can advertise alternative services for any origin on the
+    /// connection, or for the origin of a request on the request's stream.
+    /// Frames that are invalid according to the RFC, such as frames on the
+    /// connection without an origin, or without any alternative service, are
+    /// ignored and never passed to the callback.
+    ///
+    /// When no callback is set, ALTSVC frames are ignored. Setting a new
+    /// callback replaces the previous one.
+    ///
+    /// [RFC 7838]: https://tools.ietf.org/html/rfc7838
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .on_alt_svc(|alt_svc| {
+    ///         println!("alternative services: {:?}", alt_svc.field_value());
+    ///     })
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn on_alt_svc<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(AltSvc) + Send + Sync + 'static,
+    {
+        self.on_alt_svc = Some(proto::OnAltSvc::new(f));
+        self
+    }
+
+    /// Sets the callback called with the frames of the given extension
+    /// types received from the peer.
+    ///
+    /// Section 5.5 of [RFC 7540] lets extensions define new frame types.
+    /// Frames of unknown types are ignored by default, unless their type is
+    /// part of `types`, in which case they are passed to the callback as an
+    /// [`ExtensionFrame`], whether they are sent on the connection or on a
+    /// stream. The flags and the payload of these frames are not interpreted.
+    ///
+    /// Frame types defined by this library are never passed to the callback.
+    /// Extension frames larger than [`max_extension_frame_size`] are ignored.
+    /// Setting a new callback replaces the previous one and its types.
+    ///
+    /// [RFC 7540]: https://tools.ietf.org/html/rfc7540#section-5.5
+    /// [`ExtensionFrame`]: ../struct.ExtensionFrame.html
+    /// [`max_extension_frame_size`]: #method.max_extension_frame_size
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .on_extension_frame(&[0xf0, 0xf1], |frame| {
+    ///         println!("received frame {:#04x}: {:?}", frame.kind(), frame.payload());
+    ///     })
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn on_extension_frame<F>(&mut self, types: &[u8], f: F) -> &mut Self
+    where
+        F: Fn(ExtensionFrame) + Send + Sync + 'static,
+    {
+        self.extension_types = types.to_vec();
+        self.on_extension_frame = Some(proto::OnExtensionFrame::new(f));
+        self
+    }
+
+    /// Sets the maximum payload size of a received extension frame.
+    ///
+    /// Frames of the types registered with [`on_extension_frame`] with a
+    /// larger payload are ignored. The payload of a frame is also limited by
+    /// the maximum frame size advertised to the peer.
+    ///
+    /// The default value is 16,384.
+    ///
+    /// [`on_extension_frame`]: #method.on_extension_frame
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_extension_frame_size(1_024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_extension_frame_size(&mut self, max: usize) -> &mut Self {
+        self.max_extension_frame_size = max;
+        self
+    }
+
+    /// Sets the strategy used to pad the sent DATA and HEADERS frames.
+    ///
+    /// The function is called with the length of the data, or of the header
+    /// block fragment, carried by a frame and returns the number of octets of
+    /// padding to add. Zero leaves the frame unpadded. Padding can hide the
+    /// exact size of messages (section 10.7 of RFC 7540).
+    ///
+    /// The padding of DATA frames counts against flow control, and the padding
+    /// of any frame against the maximum frame size. The padding is shortened
+    /// when a frame could not fit otherwise.
+    ///
+    /// By default, frames are not padded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     // Round frames up to a multiple of 64 octets.
+    ///     .padding(|len| ((64 - len % 64) % 64) as u8)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn padding<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(usize) -> u8 + Send + Sync + 'static,
+    {
+        self.padding = Some(Padding::new(f));
+        self
+    }
+
+    /// Sets a hook called with every frame sent or received on the
+    /// connection, for instance to record them when debugging.
+    ///
+    /// The hook is called with a read-only [`FrameView`] of each frame, in
+    /// the order the frames are sent and received. Sent frames are seen once
+    /// they are encoded, before they are written to the socket, and received
+    /// frames before they are processed. The payload of DATA frames is only
+    /// included in the view when `include_data` is true.
+    ///
+    /// Received frames are borrowed from the read buffer. Sent frames may be
+    /// encoded while the state of the streams is locked, so they are copied
+    /// to a buffer, reused for all of them, until the hook can be called.
+    ///
+    /// The hook is called by the task polling the connection, and never
+    /// while the state of the streams is locked, so it may block or use the
+    /// streams of the connection. Connections built from the same builder
+    /// share the hook, it is called by one of them at a time.
+    ///
+    /// By default, there is no hook.
+    ///
+    /// [`FrameView`]: ../struct.FrameView.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// let mut frames = 0;
+    ///
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .frame_tap(false, move |direction, frame| {
+    ///         frames += 1;
+    ///         println!(
+    ///             "#{} {:?} frame {:#04x} on stream {:?}; len={}",
+    ///             frames,
+    ///             direction,
+    ///             frame.kind(),
+    ///             frame.stream_id(),
+    ///             frame.len(),
+    ///         );
+    ///     })
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn frame_tap<F>(&mut self, include_data: bool, f: F) -> &mut Self
+    where
+        F: FnMut(Direction, &FrameView) + Send + 'static,
+    {
+        self.frame_tap = Some(codec::FrameTap::new(include_data, f));
+        self
+    }
+
+    /// Sets the size of the buffer the frames to send are written to.
+    ///
+    /// Frames are written to the socket in writes of up to this size, or of a
+    /// single frame when its payload is too big to be copied to the buffer.
+    /// Header blocks and GOAWAY frames larger than the buffer grow it.
+    ///
+    /// The default value is 4KB. Values smaller than a few hundred octets are
+    /// rounded up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .write_buffer_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn write_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.write_buffer_size = size;
+        self
+    }
+
+    /// Sets when the buffered frames are written to the socket.
+    ///
+    /// Frames ending a stream, PING acknowledgements and the other control
+    /// frames are always flushed once the connection has nothing more to
+    /// write, whatever the strategy. See [`FlushStrategy`] for details.
+    ///
+    /// The default value is `FlushStrategy::Adaptive`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::FlushStrategy;
+    /// # use h2::client::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .write_buffer_size(64 * 1024)
+    ///     .flush_strategy(FlushStrategy::FlushWhenFull)
+    ///     .handshake(my_io);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`FlushStrategy`]: ../enum.FlushStrategy.html
+    pub fn flush_strategy(&mut self, strategy: FlushStrategy) -> &mut Self {
+        self.flush_strategy = strategy;
+        self
+    }
+
+    /// Sets the header fields whose values are sensitive.
     ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// Sensitive values are sent as never indexed literals (section 7.1.3 of
+    /// RFC 7541): they are not added to the HPACK dynamic table, and
+    /// intermediaries are asked not to index them either. This protects
+    /// secrets with little entropy against attacks guessing them from the
+    /// size of the compressed headers.
     ///
-    /// The default value is 30 seconds.
+    /// Other values can be marked individually with
+    /// [`HeaderValue::set_sensitive`]. The values received as never indexed
+    /// literals are marked sensitive, so that they are forwarded the same way.
+    ///
+    /// The default value is `authorization`, `cookie` and
+    /// `proxy-authorization`.
     ///
     /// # Examples
     ///
     /// ```
     /// # extern crate h2;
+    /// # extern crate http;
     /// # extern crate tokio_io;
     /// # use tokio_io::*;
     /// # use h2::client::*;
-    /// # use std::time::Duration;
+    /// # use http::header::{self, HeaderName};
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
     /// # -> Handshake<T>
@@ -1003,15 +2783,25 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .sensitive_headers(vec![
+    ///         header::AUTHORIZATION,
+    ///         header::COOKIE,
+    ///         header::SET_COOKIE,
+    ///         HeaderName::from_static("x-api-key"),
+    ///     ])
     ///     .handshake(my_io);
     /// # client_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
-        self.reset_stream_duration = dur;
+    ///
+    /// [`HeaderValue::set_sensitive`]: https://docs.rs/http/0.1/http/header/struct.HeaderValue.html#method.set_sensitive
+    pub fn sensitive_headers<I>(&mut self, names: I) -> &mut Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.sensitive_headers = names.into_iter().collect();
         self
     }
 
@@ -1050,7 +2840,7 @@ impl Builder {
     /// # pub fn main() {}
     /// ```
     pub fn enable_push(&mut self, enabled: bool) -> &mut Self {
-        self.settings.set_enable_push(enabled);
+        self.settings.enable_push(enabled as u32);
         self
     }
 
@@ -1080,7 +2870,7 @@ impl Builder {
     /// [Handshake]: ../index.html#handshake
     /// [`Connection`]: struct.Connection.html
     /// [`SendRequest`]: struct.SendRequest.html
-    /// [Outbound data type]: ../index.html#outbound-data-type.
+    /// [Outbound data type]: ../index.html#outbound-data-type
     ///
     /// # Examples
     ///
@@ -1134,6 +2924,97 @@ impl Builder {
     {
         Connection::handshake2(io, self.clone())
     }
+
+    /// Creates a new configured HTTP/2.0 client backed by `io`, after
+    /// upgrading it from HTTP/1.1.
+    ///
+    /// This starts HTTP/2.0 on a cleartext connection without prior knowledge
+    /// that the server supports it, as described in section 3.2 of RFC 7540.
+    /// `request` is sent over HTTP/1.1 with an `Upgrade: h2c` header field and
+    /// an `HTTP2-Settings` header field carrying the settings of this
+    /// builder. `request` has no body, and its `Host` header field is taken
+    /// from the authority of the URI.
+    ///
+    /// Once the server responds with 101 (Switching Protocols), the HTTP/2.0
+    /// handshake is performed. The response to `request` is sent by the server
+    /// over HTTP/2.0 on the stream 1, and is returned by the
+    /// [`ResponseFuture`] yielded with the [`SendRequest`] and
+    /// [`Connection`]. New requests use the stream IDs following 1, whatever
+    /// the [`initial_stream_id`] is.
+    ///
+    /// The returned future fails if the server responds with any other
+    /// status, in which case the connection is still speaking HTTP/1.1.
+    ///
+    /// [`ResponseFuture`]: struct.ResponseFuture.html
+    /// [`SendRequest`]: struct.SendRequest.html
+    /// [`Connection`]: struct.Connection.html
+    /// [`initial_stream_id`]: #method.initial_stream_id
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate http;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::client::*;
+    /// # use http::Request;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> UpgradeHandshake<T>
+    /// # {
+    /// let request = Request::builder()
+    ///     .uri("http://example.com/")
+    ///     .body(())
+    ///     .unwrap();
+    ///
+    /// // `client_fut` is a future representing the completion of the upgrade
+    /// // and of the HTTP/2.0 handshake.
+    /// let client_fut = Builder::new()
+    ///     .handshake_h2c_upgrade(my_io, request);
+    /// # client_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn handshake_h2c_upgrade<T, B>(&self, io: T, request: Request<()>) -> UpgradeHandshake<T, B>
+    where
+        T: AsyncRead + AsyncWrite,
+        B: IntoBuf,
+        B::Buf: 'static,
+    {
+        use tokio_io::io;
+
+        debug!("upgrading client connection to h2c");
+
+        let mut builder = self.clone();
+        builder.stream_id = 1.into();
+
+        let encoded = builder.initial_settings()
+            .and_then(|settings| h2c::encode_request(&request, &settings));
+
+        let state = match encoded {
+            Ok(encoded) => Upgrade::SendRequest(io::write_all(io, encoded)),
+            Err(e) => Upgrade::Failed(Some(e)),
+        };
+
+        UpgradeHandshake {
+            builder,
+            method: request.method().clone(),
+            state,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Builder {
+    /// Fails if a setting was given a value out of its bounds.
+    fn initial_settings(&self) -> Result<Settings, UserError> {
+        self.settings.build().map_err(|e| {
+            debug!("invalid setting; err={:?}", e);
+            UserError::InvalidSettings
+        })
+    }
 }
 
 impl Default for Builder {
@@ -1202,8 +3083,7 @@ where
 
         debug!("binding client connection");
 
-        let msg: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
-        let handshake = io::write_all(io, msg);
+        let handshake = io::write_all(io, PREFACE);
 
         Handshake {
             builder,
@@ -1233,6 +3113,165 @@ where
         assert!(size <= proto::MAX_WINDOW_SIZE);
         self.inner.set_target_window_size(size);
     }
+
+    /// Sets the max size of the header table used to compress the header
+    /// blocks sent to the peer.
+    ///
+    /// The next header block sent starts with a dynamic table size update
+    /// when the size of the table changes. See
+    /// [`Builder::max_send_header_table_size`] for more details.
+    ///
+    /// [`Builder::max_send_header_table_size`]: struct.Builder.html#method.max_send_header_table_size
+    pub fn set_max_send_header_table_size(&mut self, size: u32) {
+        self.inner.set_max_send_header_table_size(size as usize);
+    }
+
+    /// Takes the [`PingPong`] handle used to send PING frames to the server.
+    ///
+    /// Only the first call returns a handle, later calls return `None`.
+    ///
+    /// [`PingPong`]: ../struct.PingPong.html
+    pub fn ping_pong(&mut self) -> Option<PingPong> {
+        self.inner.take_user_pings().map(PingPong::new)
+    }
+
+    /// Sends a frame of a type unknown to this library, as defined by an
+    /// extension (section 5.5 of [RFC 7540]).
+    ///
+    /// The frame is sent on `stream_id`, or on the connection (stream 0) when
+    /// `stream_id` is `None`. The flags and the payload are sent as given.
+    /// The server ignores the frame unless it supports the extension.
+    ///
+    /// The connection must be polled for the frame to be sent.
+    ///
+    /// [RFC 7540]: https://tools.ietf.org/html/rfc7540#section-5.5
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `kind` is a frame type defined by this library, or
+    /// if the payload is larger than the maximum frame size of the server.
+    pub fn send_extension_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: Option<::StreamId>,
+        payload: Bytes,
+    ) -> Result<(), ::Error> {
+        if frame::is_known_kind(kind) {
+            return Err(UserError::InvalidExtensionFrame.into());
+        }
+
+        let stream_id = stream_id
+            .map(::StreamId::into_internal)
+            .unwrap_or(StreamId::zero());
+        let frame = frame::Extension::new(kind, flags, stream_id, payload);
+        self.inner.send_extension_frame(frame).map_err(Into::into)
+    }
+
+    /// Returns the round-trip time to the server, smoothed over the PING
+    /// frames sent on the connection.
+    ///
+    /// Returns `None` until a PING sent by the connection, such as a
+    /// keep-alive PING, has been acknowledged.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.inner.rtt()
+    }
+
+    /// Returns a snapshot of the settings received from the server.
+    ///
+    /// The snapshot reflects every SETTINGS frame received so far. Before the
+    /// server's first SETTINGS frame has been processed, all values are
+    /// reported with their protocol defaults.
+    ///
+    /// See [`PeerSettings`] for more details.
+    ///
+    /// [`PeerSettings`]: ../struct.PeerSettings.html
+    pub fn peer_settings(&self) -> PeerSettings {
+        PeerSettings::new(self.inner.remote_settings())
+    }
+
+    /// Returns a stream yielding the SETTINGS frames received from the server
+    /// from now on.
+    ///
+    /// The connection must still be polled for the updates to be received.
+    /// See [`SettingsChanges`] for more details.
+    ///
+    /// [`SettingsChanges`]: ../struct.SettingsChanges.html
+    pub fn settings_changes(&self) -> SettingsChanges {
+        SettingsChanges::new(self.inner.settings_changes())
+    }
+
+    /// Sends a SETTINGS frame with the values included in `settings`.
+    ///
+    /// The returned future completes once the server acknowledges the frame, at
+    /// which point the new values are put into effect. Each call results in
+    /// its own acknowledgement; updates queued before the connection had the
+    /// opportunity to write the previous one are coalesced into a single
+    /// frame, unless disabled with `Builder::coalesce_settings_updates`.
+    ///
+    /// The connection must be polled for the frame to be sent and the
+    /// acknowledgement to be received.
+    pub fn update_settings(&mut self, settings: SettingsUpdate) -> SettingsAcked {
+        let (settings, reset) = settings.into_parts();
+        SettingsAcked::new(self.inner.update_settings(settings, &reset))
+    }
+
+    /// Sets the connection to a GOAWAY state.
+    ///
+    /// Does not terminate the connection. Must continue being polled to close
+    /// connection.
+    ///
+    /// After flushing the GOAWAY frame, the connection is closed. Any
+    /// outstanding streams do not prevent the connection from closing.
+    /// Active streams fail with an error carrying `reason`.
+    pub fn abrupt_shutdown(&mut self, reason: Reason) {
+        self.inner.abort(reason, Bytes::new());
+    }
+
+    /// Sets the connection to a GOAWAY state, including `debug_data` in the
+    /// GOAWAY frame.
+    ///
+    /// This behaves like [`abrupt_shutdown`](Connection::abrupt_shutdown).
+    /// The debug data is opaque to the server and is meant to help diagnose
+    /// why the connection was closed. Debug data that does not fit in a
+    /// single frame of the minimum size (16,376 bytes) is truncated.
+    pub fn abrupt_shutdown_with_debug_data(&mut self, reason: Reason, debug_data: Bytes) {
+        self.inner.abort(reason, debug_data);
+    }
+
+    /// Aborts the connection with a GOAWAY frame carrying `reason` and
+    /// `debug_data`.
+    ///
+    /// This is the same as [`abrupt_shutdown_with_debug_data`]. All active
+    /// streams fail with an error carrying `reason`, for which `is_go_away`
+    /// and `is_local` return true. The connection must continue being
+    /// polled, and completes once the GOAWAY frame is flushed.
+    ///
+    /// To abort the connection from another task, use an [`AbortHandle`]
+    /// instead.
+    ///
+    /// [`abrupt_shutdown_with_debug_data`]: #method.abrupt_shutdown_with_debug_data
+    /// [`AbortHandle`]: ../struct.AbortHandle.html
+    pub fn abort(&mut self, reason: Reason, debug_data: &[u8]) {
+        self.inner.abort(reason, Bytes::from(debug_data));
+    }
+
+    /// Returns a handle aborting the connection from any task.
+    ///
+    /// See [`AbortHandle`] for more details.
+    ///
+    /// [`AbortHandle`]: ../struct.AbortHandle.html
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle::new(self.inner.abort_handle())
+    }
+
+    /// Returns a snapshot of the statistics of the connection.
+    ///
+    /// This reads counters kept up to date as the connection is polled, and
+    /// does not poll the connection itself.
+    pub fn stats(&self) -> ConnectionStats {
+        self.inner.stats()
+    }
 }
 
 impl<T, B> Future for Connection<T, B>
@@ -1273,48 +3312,125 @@ where
     type Error = ::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Nothing is written to `io` if the settings are invalid.
+        let settings = self.builder.initial_settings()?;
+
         let res = self.inner.poll()
             .map_err(::Error::from);
 
         let (io, _) = try_ready!(res);
 
-        debug!("client connection bound");
+        Ok(Async::Ready(bind(io, &self.builder, settings)))
+    }
+}
+
+/// Creates the connection once the preface was written to `io`, and queues
+/// the initial SETTINGS frame.
+fn bind<T, B>(io: T, builder: &Builder, settings: Settings) -> (SendRequest<B>, Connection<T, B>)
+where
+    T: AsyncRead + AsyncWrite,
+    B: IntoBuf,
+    B::Buf: 'static,
+{
+    debug!("client connection bound");
+
+    // Create the codec
+    let mut codec = Codec::new(io);
 
-        // Create the codec
-        let mut codec = Codec::new(io);
+    if let Some(max) = settings.max_frame_size() {
+        codec.set_max_recv_frame_size(max as usize);
+    }
 
-        if let Some(max) = self.builder.settings.max_frame_size() {
-            codec.set_max_recv_frame_size(max as usize);
-        }
+    if let Some(max) = settings.max_header_list_size() {
+        codec.set_max_recv_header_list_size(max as usize);
+    }
 
-        if let Some(max) = self.builder.settings.max_header_list_size() {
-            codec.set_max_recv_header_list_size(max as usize);
-        }
+    if let Some(max) = builder.max_send_header_table_size {
+        codec.set_max_send_header_table_size(max);
+    }
 
-        // Send initial settings frame
-        codec
-            .buffer(self.builder.settings.clone().into())
-            .expect("invalid SETTINGS frame");
-
-        let inner = proto::Connection::new(codec, proto::Config {
-            next_stream_id: self.builder.stream_id,
-            initial_max_send_streams: self.builder.initial_max_send_streams,
-            reset_stream_duration: self.builder.reset_stream_duration,
-            reset_stream_max: self.builder.reset_stream_max,
-            settings: self.builder.settings.clone(),
-        });
-        let send_request = SendRequest {
-            inner: inner.streams().clone(),
-            pending: None,
-        };
+    if let Some(max) = builder.max_header_block_size {
+        codec.set_max_recv_header_block_size(max);
+    }
 
-        let mut connection = Connection { inner };
-        if let Some(sz) = self.builder.initial_target_connection_window_size {
-            connection.set_target_window_size(sz);
-        }
+    if let Some(max) = builder.max_continuation_frames {
+        codec.set_max_recv_continuation_frames(max);
+    }
+
+    codec.set_strict_header_validation(builder.strict_header_validation);
+    codec.set_max_recv_settings_entries(builder.max_settings_entries);
+    codec.set_max_recv_go_away_debug_data(builder.max_go_away_debug_data);
+    codec.set_recv_extension_types(builder.extension_types.clone());
+    codec.set_max_recv_extension_frame_size(builder.max_extension_frame_size);
+
+    if let Some(ref padding) = builder.padding {
+        codec.set_send_padding(padding.clone());
+    }
+
+    if let Some(ref tap) = builder.frame_tap {
+        codec.set_frame_tap(tap.clone());
+    }
 
-        Ok(Async::Ready((send_request, connection)))
+    codec.set_send_buffer_size(builder.write_buffer_size);
+    codec.set_flush_strategy(builder.flush_strategy);
+    codec.set_send_sensitive_headers(builder.sensitive_headers.clone());
+
+    // Send initial settings frame
+    codec
+        .buffer(settings.clone().into())
+        .expect("invalid SETTINGS frame");
+
+    let inner = proto::Connection::new(codec, proto::Config {
+        next_stream_id: builder.stream_id,
+        initial_max_send_streams: builder.initial_max_send_streams,
+        reset_stream_duration: builder.reset_stream_duration,
+        reset_stream_max: builder.reset_stream_max,
+        remote_reset_stream_max: None,
+        remote_reset_stream_duration: Duration::from_secs(proto::DEFAULT_REMOTE_RESET_STREAM_SECS),
+        max_pending_accept_streams: None,
+        max_pending_open_streams: Some(builder.max_pending_open_streams),
+        max_reserved_remote_streams: builder.max_reserved_remote_streams,
+        max_buffered_recv_bytes_per_stream: builder.max_buffered_recv_bytes_per_stream,
+        buffered_recv_timeout: builder.buffered_recv_timeout,
+        settings: settings,
+        settings_ack_timeout: builder.settings_ack_timeout,
+        coalesce_settings_updates: builder.coalesce_settings_updates,
+        remote_settings_deadline: None,
+        keep_alive_interval: builder.keep_alive_interval,
+        keep_alive_timeout: builder.keep_alive_timeout,
+        max_pings_per_interval: builder.max_pings_per_interval,
+        ping_interval: builder.ping_interval,
+        window_update_threshold: builder.window_update_threshold,
+        flow_control_mode: builder.flow_control_mode,
+        window_stall_warning: builder.window_stall_warning,
+        max_adaptive_window_size: if builder.adaptive_window {
+            Some(builder.max_adaptive_window_size)
+        } else {
+            None
+        },
+        graceful_shutdown_timeout: None,
+        idle_timeout: None,
+        max_connection_age: None,
+        max_connection_age_grace: None,
+        on_unknown_setting: builder.on_unknown_setting.clone(),
+        on_alt_svc: builder.on_alt_svc.clone(),
+        on_extension_frame: builder.on_extension_frame.clone(),
+        padding: builder.padding.clone(),
+        max_send_header_block_size: builder.max_send_header_block_size,
+        // Only servers respond to requests.
+        auto_100_continue: false,
+        accept_malformed_requests: false,
+    });
+    let send_request = SendRequest {
+        inner: inner.streams().clone(),
+    };
+
+    let mut connection = Connection { inner };
+    if let Some(sz) = builder.initial_target_connection_window_size {
+        connection.set_target_window_size(sz);
     }
+
+    (send_request, connection)
 }
 
 impl<T, B> fmt::Debug for Handshake<T, B>
@@ -1329,6 +3445,93 @@ where
     }
 }
 
+// ===== impl UpgradeHandshake =====
+
+impl<T, B> Future for UpgradeHandshake<T, B>
+where
+    T: AsyncRead + AsyncWrite,
+    B: IntoBuf,
+    B::Buf: 'static,
+{
+    type Item = (SendRequest<B>, ResponseFuture, Connection<T, B>);
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                Upgrade::SendRequest(ref mut write) => {
+                    let (io, _) = try_ready!(write.poll());
+                    Upgrade::RecvResponse(Some(io), Vec::new())
+                },
+                Upgrade::RecvResponse(ref mut io, ref mut head) => {
+                    while !h2c::is_response_head_complete(head) {
+                        if head.len() >= h2c::MAX_RESPONSE_HEAD_SIZE {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "HTTP/1.1 response head too large",
+                            ).into());
+                        }
+
+                        let mut byte = [0; 1];
+                        let n = try_nb!(io.as_mut().unwrap().read(&mut byte));
+                        if n == 0 {
+                            return Err(io::Error::new(
+                                io::ErrorKind::ConnectionReset,
+                                "connection closed unexpectedly",
+                            ).into());
+                        }
+
+                        head.push(byte[0]);
+                    }
+
+                    let status = h2c::parse_response_status(head)?;
+                    if status != StatusCode::SWITCHING_PROTOCOLS {
+                        debug!("h2c upgrade refused; status={}", status);
+                        return Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("h2c upgrade refused; status={}", status),
+                        ).into());
+                    }
+
+                    debug!("h2c upgrade accepted");
+                    Upgrade::SendPreface(::tokio_io::io::write_all(io.take().unwrap(), PREFACE))
+                },
+                Upgrade::SendPreface(ref mut write) => {
+                    let (io, _) = try_ready!(write.poll());
+                    // Checked before the upgrade request was encoded.
+                    let settings = self.builder.initial_settings()
+                        .expect("invalid settings");
+                    let (mut send_request, connection) = bind(io, &self.builder, settings);
+
+                    let response = ResponseFuture {
+                        inner: send_request.inner.open_upgrade_stream(&self.method),
+                        push_promise_consumed: false,
+                    };
+
+                    return Ok(Async::Ready((send_request, response, connection)));
+                },
+                Upgrade::Failed(ref mut err) => {
+                    return Err(err.take().expect("polled after error").into());
+                },
+            };
+
+            self.state = next;
+        }
+    }
+}
+
+impl<T, B> fmt::Debug for UpgradeHandshake<T, B>
+where
+    T: AsyncRead + AsyncWrite,
+    T: fmt::Debug,
+    B: fmt::Debug + IntoBuf,
+    B::Buf: fmt::Debug + IntoBuf,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "client::UpgradeHandshake")
+    }
+}
+
 // ===== impl ResponseFuture =====
 
 impl Future for ResponseFuture {
@@ -1352,6 +3555,141 @@ impl ResponseFuture {
     pub fn stream_id(&self) -> ::StreamId {
         ::StreamId::from_internal(self.inner.stream_id())
     }
+
+    /// Returns a stream of the responses the server pushes for this request.
+    ///
+    /// Promises that are not taken from the stream are rejected once the
+    /// response and the returned stream are dropped. Pushes are only received
+    /// when enabled with [`Builder::enable_push`], which is the default.
+    ///
+    /// [`Builder::enable_push`]: struct.Builder.html#method.enable_push
+    ///
+    /// # Panics
+    ///
+    /// If this function is called more than once.
+    pub fn push_promises(&mut self) -> PushPromises {
+        if self.push_promise_consumed {
+            panic!("Reference to push promises stream taken!");
+        }
+
+        self.push_promise_consumed = true;
+
+        PushPromises {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// ===== impl PushPromises =====
+
+impl Stream for PushPromises {
+    type Item = PushPromise;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let pushed = try_ready!(self.inner.poll_pushed());
+
+        Ok(pushed.map(|(request, inner)| PushPromise {
+            request,
+            response: PushedResponseFuture {
+                inner: ResponseFuture {
+                    inner,
+                    push_promise_consumed: false,
+                },
+            },
+        }).into())
+    }
+}
+
+// ===== impl PushPromise =====
+
+impl PushPromise {
+    /// Returns the request the server promised to respond to.
+    pub fn request(&self) -> &Request<()> {
+        &self.request
+    }
+
+    /// Returns the future of the pushed response.
+    pub fn response(&mut self) -> &mut PushedResponseFuture {
+        &mut self.response
+    }
+
+    /// Consumes `self`, returning the promised request and the future of the
+    /// pushed response.
+    pub fn into_parts(self) -> (Request<()>, PushedResponseFuture) {
+        (self.request, self.response)
+    }
+
+    /// Rejects the push, resetting the pushed stream with `CANCEL`.
+    ///
+    /// This is the same as dropping the promise.
+    pub fn reject(self) {
+        drop(self);
+    }
+}
+
+// ===== impl PushedResponseFuture =====
+
+impl Future for PushedResponseFuture {
+    type Item = Response<RecvStream>;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+impl PushedResponseFuture {
+    /// Returns the stream ID of the pushed stream.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn stream_id(&self) -> ::StreamId {
+        self.inner.stream_id()
+    }
+}
+
+// ===== impl AltSvc =====
+
+impl AltSvc {
+    pub(crate) fn from_internal(frame: frame::AltSvc) -> Self {
+        let stream_id = frame.stream_id();
+
+        AltSvc {
+            stream_id: if stream_id.is_zero() {
+                None
+            } else {
+                Some(::StreamId::from_internal(stream_id))
+            },
+            origin: if frame.origin().is_empty() {
+                None
+            } else {
+                Some(frame.origin().clone())
+            },
+            field_value: frame.field_value().clone(),
+        }
+    }
+
+    /// Returns the stream of the request the alternative services apply to,
+    /// or `None` if they were advertised for the whole connection.
+    pub fn stream_id(&self) -> Option<::StreamId> {
+        self.stream_id.clone()
+    }
+
+    /// Returns the origin the alternative services apply to.
+    ///
+    /// This is `None` when the alternative services were advertised on a
+    /// request stream, in which case they apply to the origin of the request.
+    pub fn origin(&self) -> Option<&Bytes> {
+        self.origin.as_ref()
+    }
+
+    /// Returns the alternative services, using the syntax of the `Alt-Svc`
+    /// header field (section 3 of RFC 7838).
+    pub fn field_value(&self) -> &Bytes {
+        &self.field_value
+    }
 }
 
 // ===== impl Peer =====
@@ -1370,6 +3708,7 @@ impl Peer {
                 uri,
                 headers,
                 version,
+                mut extensions,
                 ..
             },
             _,
@@ -1381,6 +3720,16 @@ impl Peer {
         // and `path`.
         let mut pseudo = Pseudo::request(method, uri);
 
+        if let Some(protocol) = extensions.remove::<Protocol>() {
+            // The extended CONNECT method (RFC 8441) keeps the `:scheme` and
+            // `:path` pseudo headers.
+            if !is_connect || pseudo.scheme.is_none() || pseudo.path.is_none() {
+                return Err(UserError::MalformedHeaders.into());
+            }
+
+            pseudo.protocol = Some(protocol.into_internal());
+        }
+
         if pseudo.scheme.is_none() {
             // If the scheme is not set, then there are a two options.
             //
@@ -1431,28 +3780,44 @@ impl proto::Peer for Peer {
         false
     }
 
-    fn convert_poll_message(headers: Headers) -> Result<Self::Poll, RecvError> {
+    fn convert_poll_message(headers: Headers) -> Result<Self::Poll, &'static str> {
         let mut b = Response::builder();
 
-        let stream_id = headers.stream_id();
         let (pseudo, fields) = headers.into_parts();
 
+        macro_rules! malformed {
+            ($reason:expr) => {{
+                debug!("malformed headers: {}", $reason);
+                return Err($reason);
+            }};
+            ($reason:expr; $($arg:tt)*) => {{
+                debug!("malformed headers: {}; {}", $reason, format_args!($($arg)*));
+                return Err($reason);
+            }};
+        }
+
         b.version(Version::HTTP_2);
 
+        // > Pseudo-header fields defined for requests MUST NOT appear in
+        // > responses.
+        if pseudo.method.is_some()
+            || pseudo.scheme.is_some()
+            || pseudo.authority.is_some()
+            || pseudo.path.is_some()
+            || pseudo.protocol.is_some()
+        {
+            malformed!("request pseudo-header in response");
+        }
+
         if let Some(status) = pseudo.status {
             b.status(status);
+        } else {
+            malformed!("missing status");
         }
 
         let mut response = match b.body(()) {
             Ok(response) => response,
-            Err(_) => {
-                // TODO: Should there be more specialized handling for different
-                // kinds of errors
-                return Err(RecvError::Stream {
-                    id: stream_id,
-                    reason: Reason::PROTOCOL_ERROR,
-                });
-            },
+            Err(_) => malformed!("invalid response"),
         };
 
         *response.headers_mut() = fields;
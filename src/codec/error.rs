@@ -7,6 +7,9 @@ use std::{error, fmt, io};
 pub enum RecvError {
     Connection(Reason),
     Stream { id: StreamId, reason: Reason },
+    /// A DATA frame larger than the max frame size was received. Its payload
+    /// of `len` octets is discarded without being read into memory.
+    DataTooBig { id: StreamId, len: usize },
     Io(io::Error),
 }
 
@@ -35,8 +38,9 @@ pub enum UserError {
     /// The payload size is too big
     PayloadTooBig,
 
-    /// The application attempted to initiate too many streams to remote.
-    Rejected,
+    /// The application attempted to initiate too many streams to remote,
+    /// and too many requests are queued already.
+    AtCapacity,
 
     /// The released capacity is larger than claimed capacity.
     ReleaseCapacityTooBig,
@@ -54,6 +58,50 @@ pub enum UserError {
 
     /// Calls `SendResponse::poll_reset` after having called `send_response`.
     PollResetAfterSendResponse,
+
+    /// Calls `PingPong::send_ping` before receiving the previous PONG.
+    SendPingWhilePending,
+
+    /// The priority has a weight out of range or makes a stream depend on
+    /// itself.
+    InvalidPriority,
+
+    /// An ALTSVC frame without alternative services, or without an origin on
+    /// the connection.
+    InvalidAltSvc,
+
+    /// An extension frame using a frame type defined by the library.
+    InvalidExtensionFrame,
+
+    /// Tries to push a response while the peer disabled server push.
+    PeerDisabledServerPush,
+
+    /// A pushed request with a method that is not safe.
+    MalformedPushRequest,
+
+    /// Tries to send the `:protocol` pseudo header while the peer did not
+    /// enable the extended CONNECT method.
+    PeerDisabledConnectProtocol,
+
+    /// An informational response with a status other than 1xx, or with the
+    /// 101 (Switching Protocols) status.
+    InvalidInformationalStatus,
+
+    /// An HTTP/1.1 request to upgrade to HTTP/2.0 that carries a body, or
+    /// without a valid `HTTP2-Settings` header.
+    InvalidUpgradeRequest,
+
+    /// A response to a malformed request with a status other than 4xx.
+    InvalidMalformedResponseStatus,
+
+    /// Data or trailers sent after the stream was ended with `END_STREAM`.
+    SendAfterEndStream,
+
+    /// A header block larger than the configured maximum.
+    HeaderBlockTooBig,
+
+    /// A setting given to the builder is out of its bounds.
+    InvalidSettings,
 }
 
 // ===== impl RecvError =====
@@ -73,6 +121,7 @@ impl error::Error for RecvError {
             Stream {
                 ref reason, ..
             } => reason.description(),
+            DataTooBig { .. } => Reason::FRAME_SIZE_ERROR.description(),
             Io(ref e) => e.description(),
         }
     }
@@ -128,12 +177,25 @@ impl error::Error for UserError {
             InactiveStreamId => "inactive stream",
             UnexpectedFrameType => "unexpected frame type",
             PayloadTooBig => "payload too big",
-            Rejected => "rejected",
+            AtCapacity => "connection at capacity",
             ReleaseCapacityTooBig => "release capacity too big",
             OverflowedStreamId => "stream ID overflowed",
             MalformedHeaders => "malformed headers",
             MissingUriSchemeAndAuthority => "request URI missing scheme and authority",
             PollResetAfterSendResponse => "poll_reset after send_response is illegal",
+            SendPingWhilePending => "send_ping before receiving the previous pong",
+            InvalidPriority => "invalid stream priority",
+            InvalidAltSvc => "invalid alternative services",
+            InvalidExtensionFrame => "invalid extension frame type",
+            PeerDisabledServerPush => "server push disabled by peer",
+            MalformedPushRequest => "malformed push request",
+            PeerDisabledConnectProtocol => "extended CONNECT protocol disabled by peer",
+            InvalidInformationalStatus => "invalid informational response status",
+            InvalidUpgradeRequest => "invalid HTTP/1.1 upgrade request",
+            InvalidMalformedResponseStatus => "response to a malformed request without a 4xx status",
+            SendAfterEndStream => "send after the stream ended",
+            HeaderBlockTooBig => "header block too big",
+            InvalidSettings => "invalid settings",
         }
     }
 }
@@ -1,31 +1,93 @@
-use codec::RecvError;
+use codec::{FrameCounts, RecvError};
+use codec::tap::TapRecv;
 use frame::{self, Frame, Kind, Reason};
-use frame::{DEFAULT_MAX_FRAME_SIZE, DEFAULT_SETTINGS_HEADER_TABLE_SIZE, MAX_MAX_FRAME_SIZE};
+use frame::{DEFAULT_MAX_FRAME_SIZE, DEFAULT_MAX_GO_AWAY_DEBUG_DATA, DEFAULT_MAX_SETTINGS_ENTRIES,
+            DEFAULT_SETTINGS_HEADER_TABLE_SIZE, MAX_MAX_FRAME_SIZE};
 
 use hpack;
+use instrument;
 
 use futures::*;
 
-use bytes::BytesMut;
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{Bytes, BytesMut};
 
-use std::io;
+use std::{cmp, io, mem};
 
 use tokio_io::AsyncRead;
-use tokio_io::codec::length_delimited;
 
 // 16 MB "sane default" taken from golang http2
 const DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE: usize = 16 << 20;
 
+// The default number of CONTINUATION frames accepted in a header block, when
+// the header block fits in fewer frames.
+const MIN_CONTINUATION_FRAMES: usize = 5;
+
+// The initial capacity of the read buffer. Frames are split off blocks of at
+// least this size.
+const MIN_READ_BLOCK_SIZE: usize = 8 * 1_024;
+
 #[derive(Debug)]
 pub struct FramedRead<T> {
-    inner: length_delimited::FramedRead<T>,
+    inner: T,
+
+    /// Bytes read from `inner` that were not split off into frames yet
+    buf: BytesMut,
+
+    /// What is expected next from `inner`
+    state: ReadState,
+
+    /// Set once `inner` reached EOF
+    eof: bool,
 
     // hpack decoder state
-    hpack: hpack::Decoder,
+    hpack: hpack::raw::Decoder,
+
+    /// The largest frame payload we advertised to the peer. This is checked
+    /// as soon as the frame head is read, so that the payload of a frame
+    /// that is too big is never buffered.
+    max_frame_size: usize,
 
     max_header_list_size: usize,
 
+    /// The max number of bytes of a header block, across the HEADERS or
+    /// PUSH_PROMISE frame and its CONTINUATION frames. Defaults to the max
+    /// header list size.
+    max_header_block_size: Option<usize>,
+
+    /// The max number of CONTINUATION frames in a header block. Defaults to
+    /// the number of frames needed to send the largest header block, plus
+    /// some slack.
+    max_continuation_frames: Option<usize>,
+
+    max_settings_entries: usize,
+
+    /// Number of bytes of debug data kept from a received GOAWAY frame.
+    max_go_away_debug_data: usize,
+
+    /// When set, reserved flag bits on SETTINGS, PING and GOAWAY frames are a
+    /// connection error instead of being ignored.
+    strict_flags: bool,
+
+    /// When set, header blocks containing header fields forbidden by section
+    /// 8.1.2 are malformed. Otherwise, the fields are passed through.
+    strict_header_validation: bool,
+
+    /// Types of the unknown frames passed to the connection instead of being
+    /// ignored.
+    extension_types: Vec<u8>,
+
+    /// Extension frames with a larger payload are ignored.
+    max_extension_frame_size: usize,
+
     partial: Option<Partial>,
+
+    /// Number of frames of each type received so far
+    frames: FrameCounts,
+
+    /// Heads and lengths of the frames received on a stream, whose events
+    /// are emitted once the frames are handled
+    recv_frames: Vec<(frame::Head, usize)>,
 }
 
 /// Partially loaded headers frame
@@ -36,6 +98,25 @@ struct Partial {
 
     /// Partial header payload
     buf: BytesMut,
+
+    /// Number of bytes of the header block received so far
+    block_len: usize,
+
+    /// Number of CONTINUATION frames received so far
+    continuations: usize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ReadState {
+    /// Waiting for the head of the next frame
+    Head,
+
+    /// Waiting for the rest of a frame of this many octets, head included
+    Frame(usize),
+
+    /// Discarding this many more octets of the payload of a DATA frame that
+    /// is too big
+    Discard(usize),
 }
 
 #[derive(Debug)]
@@ -45,12 +126,26 @@ enum Continuable {
 }
 
 impl<T> FramedRead<T> {
-    pub fn new(inner: length_delimited::FramedRead<T>) -> FramedRead<T> {
+    pub fn new(inner: T) -> FramedRead<T> {
         FramedRead {
             inner: inner,
-            hpack: hpack::Decoder::new(DEFAULT_SETTINGS_HEADER_TABLE_SIZE),
+            buf: BytesMut::with_capacity(MIN_READ_BLOCK_SIZE),
+            state: ReadState::Head,
+            eof: false,
+            hpack: hpack::raw::Decoder::new(DEFAULT_SETTINGS_HEADER_TABLE_SIZE),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE as usize,
             max_header_list_size: DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE,
+            max_header_block_size: None,
+            max_continuation_frames: None,
+            max_settings_entries: DEFAULT_MAX_SETTINGS_ENTRIES,
+            max_go_away_debug_data: DEFAULT_MAX_GO_AWAY_DEBUG_DATA,
+            strict_flags: false,
+            strict_header_validation: true,
+            extension_types: Vec::new(),
+            max_extension_frame_size: DEFAULT_MAX_FRAME_SIZE as usize,
             partial: None,
+            frames: FrameCounts::default(),
+            recv_frames: Vec::new(),
         }
     }
 
@@ -62,15 +157,30 @@ impl<T> FramedRead<T> {
         // Parse the head
         let head = frame::Head::parse(&bytes);
 
-        if self.partial.is_some() && head.kind() != Kind::Continuation {
-            trace!("connection error PROTOCOL_ERROR -- expected CONTINUATION, got {:?}", head.kind());
-            return Err(Connection(Reason::PROTOCOL_ERROR));
-        }
+        self.check_continuation(&head)?;
 
         let kind = head.kind();
 
         trace!("    -> kind={:?}", kind);
 
+        self.frames.inc(kind);
+
+        let len = bytes.len() - frame::HEADER_LEN;
+
+        if instrument::defers_recv_frame(&head) {
+            self.recv_frames.push((head, len));
+        } else {
+            instrument::frame("recv", &head, len);
+        }
+
+        if self.strict_flags {
+            if let Err(e) = head.check_flags() {
+                debug!("connection error PROTOCOL_ERROR -- {:?} frame; err={:?}; flags={:#x}",
+                       kind, e, head.flag());
+                return Err(Connection(Reason::PROTOCOL_ERROR));
+            }
+        }
+
         macro_rules! header_block {
             ($frame:ident, $head:ident, $bytes:ident) => ({
                 // Drop the frame header
@@ -80,16 +190,6 @@ impl<T> FramedRead<T> {
                 // Parse the header frame w/o parsing the payload
                 let (mut frame, mut payload) = match frame::$frame::load($head, $bytes) {
                     Ok(res) => res,
-                    Err(frame::Error::InvalidDependencyId) => {
-                        debug!("stream error PROTOCOL_ERROR -- invalid HEADERS dependency ID");
-                        // A stream cannot depend on itself. An endpoint MUST
-                        // treat this as a stream error (Section 5.4.2) of type
-                        // `PROTOCOL_ERROR`.
-                        return Err(Stream {
-                            id: $head.stream_id(),
-                            reason: Reason::PROTOCOL_ERROR,
-                        });
-                    },
                     Err(e) => {
                         debug!("connection error PROTOCOL_ERROR -- failed to load frame; err={:?}", e);
                         return Err(Connection(Reason::PROTOCOL_ERROR));
@@ -97,22 +197,21 @@ impl<T> FramedRead<T> {
                 };
 
                 let is_end_headers = frame.is_end_headers();
+                let block_len = payload.len();
 
                 // Load the HPACK encoded headers
-                match frame.load_hpack(&mut payload, self.max_header_list_size, &mut self.hpack) {
+                match frame.load_hpack(
+                    &mut payload,
+                    is_end_headers,
+                    self.max_header_list_size,
+                    self.strict_header_validation,
+                    &mut self.hpack,
+                ) {
                     Ok(_) => {},
-                    Err(frame::Error::Hpack(hpack::DecoderError::NeedMore(_))) if !is_end_headers => {},
-                    Err(frame::Error::MalformedMessage) => {
-
-                        debug!("stream error PROTOCOL_ERROR -- malformed header block");
-                        return Err(Stream {
-                            id: $head.stream_id(),
-                            reason: Reason::PROTOCOL_ERROR,
-                        });
-                    },
                     Err(e) => {
-                        debug!("connection error PROTOCOL_ERROR -- failed HPACK decoding; err={:?}", e);
-                        return Err(Connection(Reason::PROTOCOL_ERROR));
+                        let reason = hpack_error_reason(&e);
+                        debug!("connection error {:?} -- failed HPACK decoding; err={:?}", reason, e);
+                        return Err(Connection(reason));
                     }
                 }
 
@@ -124,6 +223,8 @@ impl<T> FramedRead<T> {
                     self.partial = Some(Partial {
                         frame: Continuable::$frame(frame),
                         buf: payload,
+                        block_len: block_len,
+                        continuations: 0,
                     });
 
                     return Ok(None);
@@ -133,11 +234,32 @@ impl<T> FramedRead<T> {
 
         let frame = match kind {
             Kind::Settings => {
-                let res = frame::Settings::load(head, &bytes[frame::HEADER_LEN..]);
-
-                res.map_err(|e| {
-                    debug!("connection error PROTOCOL_ERROR -- failed to load SETTINGS frame; err={:?}", e);
-                    Connection(Reason::PROTOCOL_ERROR)
+                let res = frame::Settings::load_with_max_entries(
+                    head,
+                    &bytes[frame::HEADER_LEN..],
+                    self.max_settings_entries,
+                );
+
+                res.map_err(|e| match e {
+                    frame::Error::TooManySettings => {
+                        debug!("connection error ENHANCE_YOUR_CALM -- too many settings");
+                        Connection(Reason::ENHANCE_YOUR_CALM)
+                    },
+                    // An ACK with a payload, or a length that is not a
+                    // multiple of 6 (6.5.).
+                    frame::Error::InvalidPayloadLength |
+                    frame::Error::InvalidPayloadAckSettings => {
+                        debug!("connection error FRAME_SIZE_ERROR -- invalid SETTINGS payload length; err={:?}", e);
+                        Connection(Reason::FRAME_SIZE_ERROR)
+                    },
+                    frame::Error::InvalidInitialWindowSize => {
+                        debug!("connection error FLOW_CONTROL_ERROR -- SETTINGS_INITIAL_WINDOW_SIZE too large");
+                        Connection(Reason::FLOW_CONTROL_ERROR)
+                    },
+                    e => {
+                        debug!("connection error PROTOCOL_ERROR -- failed to load SETTINGS frame; err={:?}", e);
+                        Connection(Reason::PROTOCOL_ERROR)
+                    },
                 })?.into()
             },
             Kind::Ping => {
@@ -149,22 +271,38 @@ impl<T> FramedRead<T> {
                 })?.into()
             },
             Kind::WindowUpdate => {
-                let res = frame::WindowUpdate::load(head, &bytes[frame::HEADER_LEN..]);
-
-                res.map_err(|e| {
-                    debug!("connection error PROTOCOL_ERROR -- failed to load WINDOW_UPDATE frame; err={:?}", e);
-                    Connection(Reason::PROTOCOL_ERROR)
-                })?.into()
+                match frame::WindowUpdate::load(head, &bytes[frame::HEADER_LEN..]) {
+                    Ok(frame) => frame.into(),
+                    Err(frame::Error::InvalidWindowUpdateValue) if !head.stream_id().is_zero() => {
+                        // A receiver MUST treat the receipt of a WINDOW_UPDATE
+                        // frame with a flow-control window increment of 0 as a
+                        // stream error (Section 5.4.2) of type PROTOCOL_ERROR;
+                        // errors on the connection flow-control window MUST be
+                        // treated as a connection error.
+                        debug!("stream error PROTOCOL_ERROR -- WINDOW_UPDATE with zero increment");
+                        return Err(Stream {
+                            id: head.stream_id(),
+                            reason: Reason::PROTOCOL_ERROR,
+                        });
+                    },
+                    Err(e) => {
+                        debug!("connection error PROTOCOL_ERROR -- failed to load WINDOW_UPDATE frame; err={:?}", e);
+                        return Err(Connection(Reason::PROTOCOL_ERROR));
+                    },
+                }
             },
             Kind::Data => {
+                let frame_len = bytes.len();
                 let _ = bytes.split_to(frame::HEADER_LEN);
                 let res = frame::Data::load(head, bytes.freeze());
 
                 // TODO: Should this always be connection level? Probably not...
-                res.map_err(|e| {
+                let frame = res.map_err(|e| {
                     debug!("connection error PROTOCOL_ERROR -- failed to load DATA frame; err={:?}", e);
                     Connection(Reason::PROTOCOL_ERROR)
-                })?.into()
+                })?;
+
+                detach_small_payload(frame, frame_len).into()
             },
             Kind::Headers => {
                 header_block!(Headers, head, bytes)
@@ -174,7 +312,11 @@ impl<T> FramedRead<T> {
                 res.map_err(|_| Connection(Reason::PROTOCOL_ERROR))?.into()
             },
             Kind::GoAway => {
-                let res = frame::GoAway::load(&bytes[frame::HEADER_LEN..]);
+                let res = frame::GoAway::load_with_max_debug_data(
+                    head,
+                    &bytes[frame::HEADER_LEN..],
+                    self.max_go_away_debug_data,
+                );
                 res.map_err(|_| Connection(Reason::PROTOCOL_ERROR))?.into()
             },
             Kind::PushPromise => {
@@ -201,6 +343,26 @@ impl<T> FramedRead<T> {
                     Err(_) => return Err(Connection(Reason::PROTOCOL_ERROR)),
                 }
             },
+            Kind::AltSvc => {
+                let res = frame::AltSvc::load(head, &bytes[frame::HEADER_LEN..]);
+                res.map_err(|_| {
+                    debug!("connection error FRAME_SIZE_ERROR -- ALTSVC origin longer than payload");
+                    Connection(Reason::FRAME_SIZE_ERROR)
+                })?.into()
+            },
+            Kind::PriorityUpdate => {
+                match frame::PriorityUpdate::load(head, &bytes[frame::HEADER_LEN..]) {
+                    Ok(frame) => frame.into(),
+                    Err(frame::Error::InvalidPayloadLength) => {
+                        debug!("connection error FRAME_SIZE_ERROR -- PRIORITY_UPDATE too short");
+                        return Err(Connection(Reason::FRAME_SIZE_ERROR));
+                    },
+                    Err(_) => {
+                        debug!("connection error PROTOCOL_ERROR -- PRIORITY_UPDATE invalid stream ID");
+                        return Err(Connection(Reason::PROTOCOL_ERROR));
+                    },
+                }
+            },
             Kind::Continuation => {
                 let is_end_headers = (head.flag() & 0x4) == 0x4;
 
@@ -212,13 +374,22 @@ impl<T> FramedRead<T> {
                     }
                 };
 
-                // The stream identifiers must match
-                if partial.frame.stream_id() != head.stream_id() {
-                    debug!("connection error PROTOCOL_ERROR -- CONTINUATION frame stream ID does not match previous frame stream ID");
-                    return Err(Connection(Reason::PROTOCOL_ERROR));
-                }
+                // Bound the work done for a single header block, as a peer
+                // could otherwise send an endless stream of CONTINUATION
+                // frames. Decoding stops here, so the HPACK decoder state
+                // does not matter anymore.
+                partial.continuations += 1;
+                partial.block_len += bytes.len() - frame::HEADER_LEN;
 
+                if partial.continuations > self.max_continuation_frames() {
+                    debug!("connection error ENHANCE_YOUR_CALM -- too many CONTINUATION frames");
+                    return Err(Connection(Reason::ENHANCE_YOUR_CALM));
+                }
 
+                if partial.block_len > self.max_header_block_size() {
+                    debug!("connection error ENHANCE_YOUR_CALM -- header block too big");
+                    return Err(Connection(Reason::ENHANCE_YOUR_CALM));
+                }
 
                 // Extend the buf
                 if partial.buf.is_empty() {
@@ -246,19 +417,18 @@ impl<T> FramedRead<T> {
                     partial.buf.extend_from_slice(&bytes[frame::HEADER_LEN..]);
                 }
 
-                match partial.frame.load_hpack(&mut partial.buf, self.max_header_list_size, &mut self.hpack) {
+                match partial.frame.load_hpack(
+                    &mut partial.buf,
+                    is_end_headers,
+                    self.max_header_list_size,
+                    self.strict_header_validation,
+                    &mut self.hpack,
+                ) {
                     Ok(_) => {},
-                    Err(frame::Error::Hpack(hpack::DecoderError::NeedMore(_))) if !is_end_headers => {},
-                    Err(frame::Error::MalformedMessage) => {
-                        debug!("stream error PROTOCOL_ERROR -- malformed CONTINUATION frame");
-                        return Err(Stream {
-                            id: head.stream_id(),
-                            reason: Reason::PROTOCOL_ERROR,
-                        });
-                    },
                     Err(e) => {
-                        debug!("connection error PROTOCOL_ERROR -- failed HPACK decoding; err={:?}", e);
-                        return Err(Connection(Reason::PROTOCOL_ERROR));
+                        let reason = hpack_error_reason(&e);
+                        debug!("connection error {:?} -- failed HPACK decoding; err={:?}", reason, e);
+                        return Err(Connection(reason));
                     },
                 }
 
@@ -270,27 +440,106 @@ impl<T> FramedRead<T> {
                 }
             },
             Kind::Unknown => {
-                // Unknown frames are ignored
-                return Ok(None);
+                let kind = bytes[3];
+                let len = bytes.len() - frame::HEADER_LEN;
+
+                if !self.extension_types.contains(&kind) {
+                    // Unknown frames are ignored
+                    return Ok(None);
+                }
+
+                if len > self.max_extension_frame_size {
+                    debug!("ignoring extension frame; kind={}; len={}", kind, len);
+                    return Ok(None);
+                }
+
+                // The flags are not interpreted, so the frame is passed on as
+                // received.
+                let payload = bytes.split_off(frame::HEADER_LEN).freeze();
+                frame::Extension::load(&bytes, payload).into()
             },
         };
 
         Ok(Some(frame))
     }
 
+    /// Returns the error for a frame whose payload of `len` octets is larger
+    /// than the max frame size. The payload is not read.
+    fn frame_too_big(&mut self, head: frame::Head, len: usize) -> RecvError {
+        if let Err(e) = self.check_continuation(&head) {
+            return e;
+        }
+
+        let kind = head.kind();
+
+        self.frames.inc(kind);
+        instrument::frame("recv", &head, len);
+
+        // A frame size error in a frame that could alter the state of the
+        // entire connection MUST be treated as a connection error; this
+        // includes any frame carrying a header block, SETTINGS, and any
+        // frame with a stream identifier of 0.
+        if kind == Kind::Data && !head.stream_id().is_zero() {
+            debug!("DATA frame too big; discarding payload; len={}", len);
+            self.state = ReadState::Discard(len);
+
+            return RecvError::DataTooBig {
+                id: head.stream_id(),
+                len,
+            };
+        }
+
+        debug!("connection error FRAME_SIZE_ERROR -- {:?} frame too big", kind);
+        RecvError::Connection(Reason::FRAME_SIZE_ERROR)
+    }
+
+    /// While a header block is open, only CONTINUATION frames on the same
+    /// stream may be received (section 4.3).
+    fn check_continuation(&self, head: &frame::Head) -> Result<(), RecvError> {
+        if let Some(id) = self.continuation_stream_id() {
+            if head.kind() != Kind::Continuation || head.stream_id() != id {
+                debug!(
+                    "connection error PROTOCOL_ERROR -- expected CONTINUATION on stream {:?}, got {:?} on stream {:?}",
+                    id,
+                    head.kind(),
+                    head.stream_id()
+                );
+                return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stream of the header block being received, if a
+    /// CONTINUATION frame is expected.
+    fn continuation_stream_id(&self) -> Option<frame::StreamId> {
+        self.partial.as_ref().map(|partial| partial.frame.stream_id())
+    }
+
     pub fn get_ref(&self) -> &T {
-        self.inner.get_ref()
+        &self.inner
+    }
+
+    /// Returns the number of frames of each type received so far
+    pub fn frames_received(&self) -> FrameCounts {
+        self.frames
+    }
+
+    /// Takes the frames received on a stream whose events were deferred.
+    pub(crate) fn take_recv_frames(&mut self) -> Vec<(frame::Head, usize)> {
+        mem::replace(&mut self.recv_frames, Vec::new())
     }
 
     pub fn get_mut(&mut self) -> &mut T {
-        self.inner.get_mut()
+        &mut self.inner
     }
 
     /// Returns the current max frame size setting
     #[cfg(feature = "unstable")]
     #[inline]
     pub fn max_frame_size(&self) -> usize {
-        self.inner.max_frame_length()
+        self.max_frame_size
     }
 
     /// Updates the max frame size setting.
@@ -299,7 +548,7 @@ impl<T> FramedRead<T> {
     #[inline]
     pub fn set_max_frame_size(&mut self, val: usize) {
         assert!(DEFAULT_MAX_FRAME_SIZE as usize <= val && val <= MAX_MAX_FRAME_SIZE as usize);
-        self.inner.set_max_frame_length(val)
+        self.max_frame_size = val;
     }
 
     /// Update the max header list size setting.
@@ -307,11 +556,149 @@ impl<T> FramedRead<T> {
     pub fn set_max_header_list_size(&mut self, val: usize) {
         self.max_header_list_size = val;
     }
+
+    /// Update the max size of the HPACK dynamic table the peer may use.
+    ///
+    /// The change applies from the next header block.
+    pub fn set_header_table_size(&mut self, val: usize) {
+        self.hpack.queue_size_update(val);
+    }
+
+    /// Update the max size of a header block.
+    #[inline]
+    pub fn set_max_header_block_size(&mut self, val: usize) {
+        self.max_header_block_size = Some(val);
+    }
+
+    /// Update the max number of CONTINUATION frames in a header block.
+    #[inline]
+    pub fn set_max_continuation_frames(&mut self, val: usize) {
+        self.max_continuation_frames = Some(val);
+    }
+
+    fn max_header_block_size(&self) -> usize {
+        self.max_header_block_size.unwrap_or(self.max_header_list_size)
+    }
+
+    fn max_continuation_frames(&self) -> usize {
+        self.max_continuation_frames.unwrap_or_else(|| {
+            let frames = self.max_header_block_size() / self.max_frame_size;
+            cmp::max(frames + frames / 4, MIN_CONTINUATION_FRAMES)
+        })
+    }
+
+    /// Update the max number of parameters accepted in a SETTINGS frame.
+    #[inline]
+    pub fn set_max_settings_entries(&mut self, val: usize) {
+        self.max_settings_entries = val;
+    }
+
+    /// Update the number of bytes of debug data kept from a GOAWAY frame.
+    #[inline]
+    pub fn set_max_go_away_debug_data(&mut self, val: usize) {
+        self.max_go_away_debug_data = val;
+    }
+
+    /// Update the types of the unknown frames that are not ignored.
+    #[inline]
+    pub fn set_extension_types(&mut self, val: Vec<u8>) {
+        self.extension_types = val;
+    }
+
+    /// Update the max payload size of the extension frames.
+    #[inline]
+    pub fn set_max_extension_frame_size(&mut self, val: usize) {
+        self.max_extension_frame_size = val;
+    }
+
+    /// Update whether the header fields forbidden in HTTP/2 make a header
+    /// block malformed.
+    #[inline]
+    pub fn set_strict_header_validation(&mut self, val: bool) {
+        self.strict_header_validation = val;
+    }
+
+    /// Reject reserved flag bits on SETTINGS, PING and GOAWAY frames.
+    #[cfg(feature = "unstable")]
+    #[inline]
+    pub fn set_strict_flags(&mut self, val: bool) {
+        self.strict_flags = val;
+    }
 }
 
-impl<T> Stream for FramedRead<T>
+impl<T> FramedRead<T>
 where
     T: AsyncRead,
+{
+    /// Reads the next frame, head included.
+    ///
+    /// The length of a frame is checked against the max frame size as soon
+    /// as its head is read.
+    fn poll_bytes(&mut self) -> Poll<Option<BytesMut>, RecvError> {
+        loop {
+            match self.state {
+                ReadState::Head => {
+                    if self.buf.len() >= frame::HEADER_LEN {
+                        let head = frame::Head::parse(&self.buf);
+                        let len = BigEndian::read_uint(&self.buf[..3], 3) as usize;
+
+                        if len > self.max_frame_size {
+                            let _ = self.buf.split_to(frame::HEADER_LEN);
+                            return Err(self.frame_too_big(head, len));
+                        }
+
+                        self.buf.reserve(frame::HEADER_LEN + len);
+                        self.state = ReadState::Frame(frame::HEADER_LEN + len);
+                        continue;
+                    }
+                },
+                ReadState::Frame(len) => {
+                    if self.buf.len() >= len {
+                        self.state = ReadState::Head;
+                        let bytes = self.buf.split_to(len);
+
+                        // Make room for the head of the next frame
+                        self.buf.reserve(frame::HEADER_LEN);
+
+                        return Ok(Async::Ready(Some(bytes)));
+                    }
+                },
+                ReadState::Discard(len) => {
+                    let n = cmp::min(len, self.buf.len());
+                    let _ = self.buf.split_to(n);
+
+                    if n == len {
+                        trace!("discarded payload of DATA frame too big");
+                        self.state = ReadState::Head;
+                        continue;
+                    }
+
+                    self.state = ReadState::Discard(len - n);
+                },
+            }
+
+            if self.eof {
+                if self.buf.is_empty() && self.state == ReadState::Head {
+                    return Ok(Async::Ready(None));
+                }
+
+                return Err(io::Error::new(io::ErrorKind::Other, "bytes remaining on stream").into());
+            }
+
+            // Make sure there is room for at least one byte, so that reading
+            // zero bytes means EOF.
+            self.buf.reserve(1);
+
+            if try_ready!(self.inner.read_buf(&mut self.buf)) == 0 {
+                self.eof = true;
+            }
+        }
+    }
+}
+
+impl<T> Stream for FramedRead<T>
+where
+    T: AsyncRead + TapRecv,
 {
     type Item = Frame;
     type Error = RecvError;
@@ -319,12 +706,14 @@ where
     fn poll(&mut self) -> Poll<Option<Frame>, Self::Error> {
         loop {
             trace!("poll");
-            let bytes = match try_ready!(self.inner.poll().map_err(map_err)) {
+            let bytes = match try_ready!(self.poll_bytes()) {
                 Some(bytes) => bytes,
                 None => return Ok(Async::Ready(None)),
             };
 
             trace!("poll; bytes={}B", bytes.len());
+            self.inner.tap_recv(&bytes);
+
             if let Some(frame) = self.decode_frame(bytes)? {
                 debug!("received; frame={:?}", frame);
                 return Ok(Async::Ready(Some(frame)));
@@ -333,17 +722,40 @@ where
     }
 }
 
-fn map_err(err: io::Error) -> RecvError {
-    use tokio_io::codec::length_delimited::FrameTooBig;
+/// Returns the reason of the connection error for a header block that failed
+/// to be decoded.
+///
+/// Invalid Huffman encoded strings (section 5.2 of RFC 7541) and invalid
+/// dynamic table size updates (section 4.2) are compression errors. Other
+/// errors are treated as protocol errors.
+fn hpack_error_reason(err: &frame::Error) -> Reason {
+    use hpack::raw::DecoderError::*;
+
+    match *err {
+        frame::Error::Hpack(HuffmanEos) |
+        frame::Error::Hpack(HuffmanPaddingTooLong) |
+        frame::Error::Hpack(InvalidHuffmanPadding) |
+        frame::Error::Hpack(InvalidMaxDynamicSize) => Reason::COMPRESSION_ERROR,
+        _ => Reason::PROTOCOL_ERROR,
+    }
+}
 
-    if let io::ErrorKind::InvalidData = err.kind() {
-        if let Some(custom) = err.get_ref() {
-            if custom.is::<FrameTooBig>() {
-                return RecvError::Connection(Reason::FRAME_SIZE_ERROR);
-            }
-        }
+/// Copies the payload of a received DATA frame out of the read buffer when it
+/// retains less than a quarter of the block it was split off.
+///
+/// The payload is otherwise a slice of the read buffer, which keeps the whole
+/// block allocated for as long as the payload is buffered by the stream or
+/// retained by the user. The size of the block is not known, so it is assumed
+/// to be the smallest a frame of `frame_len` octets can be split off.
+fn detach_small_payload(frame: frame::Data, frame_len: usize) -> frame::Data {
+    let block_size = cmp::max(frame_len, MIN_READ_BLOCK_SIZE);
+
+    if frame.payload().len() * 4 >= block_size {
+        return frame;
     }
-    err.into()
+
+    trace!("copying small DATA payload; len={}", frame.payload().len());
+    frame.map(|payload| Bytes::from(&payload[..]))
 }
 
 // ===== impl Continuable =====
@@ -366,12 +778,18 @@ impl Continuable {
     fn load_hpack(
         &mut self,
         src: &mut BytesMut,
+        is_end_headers: bool,
         max_header_list_size: usize,
-        decoder: &mut hpack::Decoder,
+        strict: bool,
+        decoder: &mut hpack::raw::Decoder,
     ) -> Result<(), frame::Error> {
         match *self {
-            Continuable::Headers(ref mut h) => h.load_hpack(src, max_header_list_size, decoder),
-            Continuable::PushPromise(ref mut p) => p.load_hpack(src, max_header_list_size, decoder),
+            Continuable::Headers(ref mut h) => {
+                h.load_hpack(src, is_end_headers, max_header_list_size, strict, decoder)
+            },
+            Continuable::PushPromise(ref mut p) => {
+                p.load_hpack(src, is_end_headers, max_header_list_size, strict, decoder)
+            },
         }
     }
 }
@@ -1,12 +1,17 @@
-use codec::UserError;
+use codec::{FlushStrategy, FrameCounts, FrameTap, Padding, UserError};
+use codec::tap::{Sent, TapRecv};
 use codec::UserError::*;
 use frame::{self, Frame, FrameSize};
 use hpack;
+use instrument;
 
 use bytes::{Buf, BufMut, BytesMut};
+use byteorder::{BigEndian, ByteOrder};
+use http::header::HeaderName;
 use futures::*;
 use tokio_io::{AsyncRead, AsyncWrite};
 
+use std::cmp;
 use std::io::{self, Cursor};
 
 #[derive(Debug)]
@@ -15,7 +20,14 @@ pub struct FramedWrite<T, B> {
     inner: T,
 
     /// HPACK encoder
-    hpack: hpack::Encoder,
+    hpack: hpack::raw::Encoder,
+
+    /// Header table size, this is specified by the peer
+    header_table_size: usize,
+
+    /// Local cap on the header table size, bounding the memory used by the
+    /// HPACK encoder whatever the peer allows
+    max_header_table_size: Option<usize>,
 
     /// Write buffer
     ///
@@ -30,16 +42,44 @@ pub struct FramedWrite<T, B> {
 
     /// Max frame size, this is specified by the peer
     max_frame_size: FrameSize,
+
+    /// Chooses the padding of HEADERS frames
+    padding: Option<Padding>,
+
+    /// When the buffer is written to the socket
+    flush_strategy: FlushStrategy,
+
+    /// True when a buffered frame must be flushed without waiting for the
+    /// buffer to fill up.
+    is_flush_urgent: bool,
+
+    /// Number of bytes written to `inner`
+    bytes_written: usize,
+
+    /// Number of bytes read from `inner`
+    bytes_read: usize,
+
+    /// Number of frames of each type buffered so far
+    frames: FrameCounts,
+
+    /// User hook called with the frames sent or received
+    tap: Option<FrameTap>,
+
+    /// Frames sent, waiting to be passed to the tap
+    tapped: Sent,
 }
 
 #[derive(Debug)]
 enum Next<B> {
-    Data(frame::Data<B>),
+    /// A DATA frame whose head, and pad length octet, were encoded to the
+    /// buffer. The payload and the padding are written after the buffer
+    /// without being copied.
+    Data(frame::Data<B>, Cursor<&'static [u8]>),
     Continuation(frame::Continuation),
 }
 
 /// Initialze the connection with this amount of write buffer.
-const DEFAULT_BUFFER_CAPACITY: usize = 4 * 1_024;
+pub const DEFAULT_BUFFER_CAPACITY: usize = 4 * 1_024;
 
 /// Min buffer required to attempt to write a frame
 const MIN_BUFFER_CAPACITY: usize = frame::HEADER_LEN + CHAIN_THRESHOLD;
@@ -58,11 +98,21 @@ where
     pub fn new(inner: T) -> FramedWrite<T, B> {
         FramedWrite {
             inner: inner,
-            hpack: hpack::Encoder::default(),
+            hpack: hpack::raw::Encoder::default(),
+            header_table_size: frame::DEFAULT_SETTINGS_HEADER_TABLE_SIZE,
+            max_header_table_size: None,
             buf: Cursor::new(BytesMut::with_capacity(DEFAULT_BUFFER_CAPACITY)),
             next: None,
             last_data_frame: None,
             max_frame_size: frame::DEFAULT_MAX_FRAME_SIZE,
+            padding: None,
+            flush_strategy: FlushStrategy::Adaptive,
+            is_flush_urgent: false,
+            bytes_written: 0,
+            bytes_read: 0,
+            frames: FrameCounts::default(),
+            tap: None,
+            tapped: Sent::default(),
         }
     }
 
@@ -71,6 +121,10 @@ where
     /// Calling this function may result in the current contents of the buffer
     /// to be flushed to `T`.
     pub fn poll_ready(&mut self) -> Poll<(), io::Error> {
+        if self.flush_strategy == FlushStrategy::FlushAfterEachFrame && !self.is_empty() {
+            try_ready!(self.flush());
+        }
+
         if !self.has_capacity() {
             // Try flushing
             self.flush()?;
@@ -93,12 +147,23 @@ where
 
         debug!("send; frame={:?}", item);
 
+        // Position of the head of the frame in the buffer
+        let pos = self.buf.get_ref().len();
+
+        self.is_flush_urgent |= match item {
+            Frame::Data(ref v) => v.is_end_stream(),
+            Frame::Headers(ref v) => v.is_end_stream(),
+            Frame::PushPromise(_) => false,
+            _ => true,
+        };
+
         match item {
             Frame::Data(mut v) => {
                 // Ensure that the payload is not greater than the max frame.
                 let len = v.payload().remaining();
+                let padding_len = v.padding_len();
 
-                if len > self.max_frame_size() {
+                if len + padding_len > self.max_frame_size() {
                     return Err(PayloadTooBig);
                 }
 
@@ -106,11 +171,21 @@ where
                     let head = v.head();
 
                     // Encode the frame head to the buffer
-                    head.encode(len, self.buf.get_mut());
+                    head.encode(len + padding_len, self.buf.get_mut());
+
+                    let padding = match v.pad_len() {
+                        Some(pad_len) => {
+                            self.buf.get_mut().put_u8(pad_len);
+                            frame::padding(pad_len)
+                        },
+                        None => &[],
+                    };
 
                     // Save the data frame
-                    self.next = Some(Next::Data(v));
+                    self.next = Some(Next::Data(v, Cursor::new(padding)));
                 } else {
+                    // The padding may not fit in the remaining capacity.
+                    self.buf.get_mut().reserve(frame::HEADER_LEN + len + padding_len);
                     v.encode_chunk(self.buf.get_mut());
 
                     // The chunk has been fully encoded, so there is no need to
@@ -122,7 +197,13 @@ where
                 }
             },
             Frame::Headers(v) => {
-                if let Some(continuation) = v.encode(&mut self.hpack, self.buf.get_mut()) {
+                // The header block fragment of the frame is limited by the
+                // capacity of the buffer, so that the padded frame still fits
+                // in the smallest max frame size.
+                let padding = &self.padding;
+                let pad_len = |len| padding.as_ref().map_or(0, |padding| padding.pad_len(len));
+
+                if let Some(continuation) = v.encode(&mut self.hpack, self.buf.get_mut(), pad_len) {
                     self.next = Some(Next::Continuation(continuation));
                 }
             },
@@ -136,6 +217,8 @@ where
                 trace!("encoded settings; rem={:?}", self.buf.remaining());
             },
             Frame::GoAway(v) => {
+                // The debug data may not fit in the remaining capacity.
+                self.buf.get_mut().reserve(v.encode_len());
                 v.encode(self.buf.get_mut());
                 trace!("encoded go_away; rem={:?}", self.buf.remaining());
             },
@@ -148,19 +231,37 @@ where
                 trace!("encoded window_update; rem={:?}", self.buf.remaining());
             },
 
-            Frame::Priority(_) => {
-                /*
+            Frame::Priority(v) => {
                 v.encode(self.buf.get_mut());
                 trace!("encoded priority; rem={:?}", self.buf.remaining());
-                */
-                unimplemented!();
             },
             Frame::Reset(v) => {
                 v.encode(self.buf.get_mut());
                 trace!("encoded reset; rem={:?}", self.buf.remaining());
             },
+            Frame::AltSvc(v) => {
+                self.buf.get_mut().reserve(v.encode_len());
+                v.encode(self.buf.get_mut());
+                trace!("encoded alt_svc; rem={:?}", self.buf.remaining());
+            },
+            Frame::Extension(v) => {
+                if v.payload().len() > self.max_frame_size() {
+                    return Err(PayloadTooBig);
+                }
+
+                self.buf.get_mut().reserve(v.encode_len());
+                v.encode(self.buf.get_mut());
+                trace!("encoded extension; rem={:?}", self.buf.remaining());
+            },
+            Frame::PriorityUpdate(v) => {
+                self.buf.get_mut().reserve(frame::HEADER_LEN + 4 + v.field_value().len());
+                v.encode(self.buf.get_mut());
+                trace!("encoded priority_update; rem={:?}", self.buf.remaining());
+            },
         }
 
+        self.encoded(pos);
+
         Ok(())
     }
 
@@ -171,14 +272,21 @@ where
         loop {
             while !self.is_empty() {
                 match self.next {
-                    Some(Next::Data(ref mut frame)) => {
+                    Some(Next::Data(ref mut frame, ref mut padding)) => {
                         trace!("  -> queued data frame");
-                        let mut buf = Buf::by_ref(&mut self.buf).chain(frame.payload_mut());
-                        try_ready!(self.inner.write_buf(&mut buf));
+                        // Each part of the chain keeps track of how much of it
+                        // was written, so partial writes resume where they
+                        // stopped.
+                        let mut buf = Buf::by_ref(&mut self.buf)
+                            .chain(frame.payload_mut())
+                            .chain(padding);
+                        let n = try_ready!(self.inner.write_buf(&mut buf));
+                        self.bytes_written = self.bytes_written.wrapping_add(n);
                     },
                     _ => {
                         trace!("  -> not a queued data frame");
-                        try_ready!(self.inner.write_buf(&mut self.buf));
+                        let n = try_ready!(self.inner.write_buf(&mut self.buf));
+                        self.bytes_written = self.bytes_written.wrapping_add(n);
                     },
                 }
             }
@@ -189,7 +297,7 @@ where
 
             // The data frame has been written, so unset it
             match self.next.take() {
-                Some(Next::Data(frame)) => {
+                Some(Next::Data(frame, _)) => {
                     self.last_data_frame = Some(frame);
                     debug_assert!(self.is_empty());
                     break;
@@ -199,6 +307,7 @@ where
                     if let Some(continuation) = frame.encode(&mut self.hpack, self.buf.get_mut()) {
                         self.next = Some(Next::Continuation(continuation));
                     }
+                    self.encoded(0);
                 },
                 None => {
                     break;
@@ -210,22 +319,60 @@ where
         // Flush the upstream
         try_nb!(self.inner.flush());
 
+        self.is_flush_urgent = false;
+
         Ok(Async::Ready(()))
     }
 
+    /// Flush buffered data to the wire once no more frames are ready to be
+    /// buffered, unless the flush strategy waits for the buffer to fill up.
+    pub fn flush_pending(&mut self) -> Poll<(), io::Error> {
+        if self.flush_strategy == FlushStrategy::FlushWhenFull && !self.is_flush_urgent {
+            trace!("flush_pending; waiting for a full buffer");
+            return Ok(Async::Ready(()));
+        }
+
+        self.flush()
+    }
+
     /// Close the codec
     pub fn shutdown(&mut self) -> Poll<(), io::Error> {
         try_ready!(self.flush());
         self.inner.shutdown().map_err(Into::into)
     }
 
+    /// Records a frame whose head was encoded at `pos` in the buffer.
+    fn encoded(&mut self, pos: usize) {
+        let (head, len) = {
+            let src = &self.buf.get_ref()[pos..];
+            (frame::Head::parse(src), BigEndian::read_uint(src, 3) as usize)
+        };
+
+        self.frames.inc(head.kind());
+        instrument::frame("send", &head, len);
+
+        if let Some(ref tap) = self.tap {
+            let data = match self.next {
+                Some(Next::Data(ref frame, ref padding)) => {
+                    Some((frame.payload(), *padding.get_ref()))
+                },
+                _ => None,
+            };
+
+            tap.queue_sent(&mut self.tapped, &self.buf.get_ref()[pos..], data);
+        }
+    }
+
     fn has_capacity(&self) -> bool {
         self.next.is_none() && self.buf.get_ref().remaining_mut() >= MIN_BUFFER_CAPACITY
     }
 
     fn is_empty(&self) -> bool {
         match self.next {
-            Some(Next::Data(ref frame)) => !frame.payload().has_remaining(),
+            // The parts of the chain are written in order.
+            Some(Next::Data(ref frame, ref padding)) => {
+                !frame.payload().has_remaining() && !padding.has_remaining()
+            },
             _ => !self.buf.has_remaining(),
         }
     }
@@ -237,12 +384,82 @@ impl<T, B> FramedWrite<T, B> {
         self.max_frame_size as usize
     }
 
+    /// Set the strategy used to pad HEADERS frames.
+    pub fn set_padding(&mut self, val: Padding) {
+        self.padding = Some(val);
+    }
+
+    /// Set the hook called with the frames sent or received.
+    pub fn set_tap(&mut self, val: FrameTap) {
+        self.tap = Some(val);
+    }
+
+    /// Passes the frames sent so far to the tap.
+    pub fn call_tap(&mut self) {
+        if let Some(ref tap) = self.tap {
+            tap.call_sent(&mut self.tapped);
+        }
+    }
+
+    /// Set when the buffer is written to the socket.
+    pub fn set_flush_strategy(&mut self, val: FlushStrategy) {
+        self.flush_strategy = val;
+    }
+
+    /// Set the size of the write buffer.
+    ///
+    /// The buffer must be empty, i.e. no frame has been buffered yet.
+    pub fn set_buffer_size(&mut self, val: usize) {
+        assert!(self.buf.get_ref().is_empty());
+        self.buf = Cursor::new(BytesMut::with_capacity(cmp::max(val, MIN_BUFFER_CAPACITY)));
+    }
+
     /// Set the peer's max frame size.
     pub fn set_max_frame_size(&mut self, val: usize) {
         assert!(val <= frame::MAX_MAX_FRAME_SIZE as usize);
         self.max_frame_size = val as FrameSize;
     }
 
+    /// Set the peer's header table size.
+    ///
+    /// The HPACK encoder emits a dynamic table size update at the start of
+    /// the next header block.
+    pub fn set_header_table_size(&mut self, val: usize) {
+        self.header_table_size = val;
+        self.update_header_table_size();
+    }
+
+    /// Set the local cap on the header table size.
+    ///
+    /// The encoder uses the smaller of this and the peer's header table size,
+    /// and emits a dynamic table size update at the start of the next header
+    /// block when it changes.
+    pub fn set_max_header_table_size(&mut self, val: usize) {
+        self.max_header_table_size = Some(val);
+        self.update_header_table_size();
+    }
+
+    fn update_header_table_size(&mut self) {
+        let size = match self.max_header_table_size {
+            Some(max) => cmp::min(max, self.header_table_size),
+            None => self.header_table_size,
+        };
+
+        self.hpack.update_max_size(size);
+    }
+
+    /// Set the names of the header fields whose values are always sensitive.
+    pub fn set_sensitive_headers(&mut self, val: Vec<HeaderName>) {
+        self.hpack.set_sensitive_headers(val);
+    }
+
+    /// Set whether strings of the header blocks are Huffman encoded when it
+    /// makes them shorter.
+    #[cfg(feature = "unstable")]
+    pub fn set_huffman(&mut self, val: bool) {
+        self.hpack.set_huffman(val);
+    }
+
     /// Retrieve the last data frame that has been sent
     pub fn take_last_data_frame(&mut self) -> Option<frame::Data<B>> {
         self.last_data_frame.take()
@@ -251,11 +468,48 @@ impl<T, B> FramedWrite<T, B> {
     pub fn get_mut(&mut self) -> &mut T {
         &mut self.inner
     }
+
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_written
+    }
+
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_read
+    }
+
+    pub fn frames_sent(&self) -> FrameCounts {
+        self.frames
+    }
+}
+
+impl<T, B: Buf> FramedWrite<T, B> {
+    /// Returns the number of bytes encoded in the buffer, or queued after it,
+    /// that were not written yet.
+    pub fn buffered_len(&self) -> usize {
+        let next = match self.next {
+            Some(Next::Data(ref frame, ref padding)) => {
+                frame.payload().remaining() + padding.remaining()
+            },
+            _ => 0,
+        };
+
+        self.buf.remaining() + next
+    }
 }
 
 impl<T: io::Read, B> io::Read for FramedWrite<T, B> {
     fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(dst)
+        let n = self.inner.read(dst)?;
+        self.bytes_read = self.bytes_read.wrapping_add(n);
+        Ok(n)
+    }
+}
+
+impl<T, B> TapRecv for FramedWrite<T, B> {
+    fn tap_recv(&mut self, src: &[u8]) {
+        if let Some(ref tap) = self.tap {
+            tap.call_recv(&mut self.tapped, src);
+        }
     }
 }
 
@@ -264,7 +518,9 @@ impl<T: AsyncRead, B> AsyncRead for FramedWrite<T, B> {
     where
         Self: Sized,
     {
-        self.inner.read_buf(buf)
+        let n = try_ready!(self.inner.read_buf(buf));
+        self.bytes_read = self.bytes_read.wrapping_add(n);
+        Ok(Async::Ready(n))
     }
 
     unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
@@ -282,3 +538,145 @@ mod unstable {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use frame::StreamId;
+    use http::{HeaderMap, StatusCode};
+
+    /// Accepts a single byte per write, every other write would block.
+    struct Trickle {
+        written: Vec<u8>,
+        blocked: bool,
+    }
+
+    impl io::Write for Trickle {
+        fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+            self.blocked = !self.blocked;
+
+            if self.blocked {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            self.written.push(src[0]);
+            Ok(1)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for Trickle {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    fn data(id: u32, len: usize, pad_len: Option<u8>) -> frame::Data<Cursor<Vec<u8>>> {
+        let payload = (0..len).map(|i| i as u8).collect();
+        let mut frame = frame::Data::new(StreamId::from(id), Cursor::new(payload));
+
+        if let Some(pad_len) = pad_len {
+            frame.set_pad_len(pad_len);
+        }
+
+        frame
+    }
+
+    #[test]
+    fn write_data_frames_one_byte_at_a_time() {
+        let frames = [
+            (1, 1_000, Some(10)),
+            (3, 1_000, None),
+            (5, 10, Some(3)),
+            (7, CHAIN_THRESHOLD, Some(0)),
+        ];
+
+        let mut codec = FramedWrite::new(Trickle {
+            written: vec![],
+            blocked: false,
+        });
+        let mut expected = vec![];
+
+        for &(id, len, pad_len) in &frames {
+            data(id, len, pad_len).encode_chunk(&mut expected);
+
+            while !codec.poll_ready().unwrap().is_ready() {}
+            codec.buffer(data(id, len, pad_len).into()).unwrap();
+        }
+
+        while !codec.flush().unwrap().is_ready() {}
+
+        assert_eq!(codec.get_mut().written, expected);
+        assert!(codec.take_last_data_frame().is_some());
+    }
+
+    #[test]
+    fn flush_strategies() {
+        let mut codec = FramedWrite::new(Cursor::new(vec![]));
+        codec.set_flush_strategy(FlushStrategy::FlushAfterEachFrame);
+
+        codec.buffer(data(1, 10, None).into()).unwrap();
+        assert!(codec.get_mut().get_ref().is_empty());
+
+        // The frame is written before accepting another one
+        assert!(codec.poll_ready().unwrap().is_ready());
+        assert_eq!(codec.get_mut().get_ref().len(), frame::HEADER_LEN + 10);
+
+        let mut codec = FramedWrite::new(Cursor::new(vec![]));
+        codec.set_flush_strategy(FlushStrategy::FlushWhenFull);
+
+        codec.buffer(data(1, 10, None).into()).unwrap();
+        assert!(codec.flush_pending().unwrap().is_ready());
+        assert!(codec.get_mut().get_ref().is_empty());
+
+        // Ending the stream flushes the buffer
+        let mut frame = data(1, 10, None);
+        frame.set_end_stream(true);
+        codec.buffer(frame.into()).unwrap();
+        assert!(codec.flush_pending().unwrap().is_ready());
+        assert_eq!(codec.get_mut().get_ref().len(), 2 * (frame::HEADER_LEN + 10));
+    }
+
+    /// Writes a HEADERS frame with `:status 200`, and returns its header block.
+    fn header_block(codec: &mut FramedWrite<Cursor<Vec<u8>>, Cursor<Vec<u8>>>) -> Vec<u8> {
+        let start = codec.get_mut().get_ref().len();
+        let frame = frame::Headers::new(
+            StreamId::from(1),
+            frame::Pseudo::response(StatusCode::OK),
+            HeaderMap::new(),
+        );
+
+        codec.buffer(frame.into()).unwrap();
+        while !codec.flush().unwrap().is_ready() {}
+
+        codec.get_mut().get_ref()[start + frame::HEADER_LEN..].to_vec()
+    }
+
+    #[test]
+    fn header_table_size_is_capped() {
+        let mut codec = FramedWrite::new(Cursor::new(vec![]));
+
+        // The cap is lower than the default size allowed by the peer
+        codec.set_max_header_table_size(100);
+        assert_eq!(header_block(&mut codec), [0x3f, 0x45, 0x88]);
+
+        // The peer allows less than the cap
+        codec.set_header_table_size(50);
+        assert_eq!(header_block(&mut codec), [0x3f, 0x13, 0x88]);
+
+        // Raising the cap over the size allowed by the peer changes nothing
+        codec.set_max_header_table_size(1_000);
+        assert_eq!(header_block(&mut codec), [0x88]);
+
+        // The cap is lowered at runtime
+        codec.set_max_header_table_size(0);
+        assert_eq!(header_block(&mut codec), [0x20, 0x88]);
+
+        // The peer allows more, but the cap still applies
+        codec.set_header_table_size(8_192);
+        assert_eq!(header_block(&mut codec), [0x88]);
+    }
+}
@@ -1,28 +1,85 @@
 mod error;
 mod framed_read;
 mod framed_write;
+mod tap;
 
 pub use self::error::{RecvError, SendError, UserError};
+pub use self::tap::{Direction, FrameView};
 
 use self::framed_read::FramedRead;
 use self::framed_write::FramedWrite;
+pub(crate) use self::framed_write::DEFAULT_BUFFER_CAPACITY;
+pub(crate) use self::tap::FrameTap;
 
 use frame::{self, Data, Frame};
 
 use futures::*;
 
 use tokio_io::{AsyncRead, AsyncWrite};
-use tokio_io::codec::length_delimited;
 
 use bytes::Buf;
+use http::header::HeaderName;
 
-use std::io;
+use std::{fmt, io};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct Codec<T, B> {
     inner: FramedRead<FramedWrite<T, B>>,
 }
 
+/// A user strategy choosing the length of the padding of the DATA and
+/// HEADERS frames to send.
+#[derive(Clone)]
+pub(crate) struct Padding(Arc<Fn(usize) -> u8 + Send + Sync>);
+
+/// When the frames buffered by a connection are written to the socket.
+///
+/// Whatever the strategy, the buffer is flushed once it is full, and frames
+/// ending a stream, PING acknowledgements, and other control frames (SETTINGS,
+/// WINDOW_UPDATE, RST_STREAM, GOAWAY...) are flushed as soon as the
+/// connection has nothing more to write.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FlushStrategy {
+    /// Flush after every frame.
+    ///
+    /// This minimizes latency at the cost of issuing one write, and one
+    /// flush, per frame.
+    FlushAfterEachFrame,
+
+    /// Only flush HEADERS and DATA frames that do not end their stream when
+    /// the buffer is full.
+    ///
+    /// This maximizes the size of the writes. However, the start of a stream,
+    /// or a chunk of its body, may then be held back until more frames are
+    /// sent, which stalls peers waiting on them before answering.
+    FlushWhenFull,
+
+    /// Flush when no more frames are ready to be written.
+    ///
+    /// This is the default.
+    Adaptive,
+}
+
+/// The number of frames of each type sent or received on a connection.
+///
+/// CONTINUATION frames are counted separately from the HEADERS or
+/// PUSH_PROMISE frame they continue.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct FrameCounts {
+    data: usize,
+    headers: usize,
+    priority: usize,
+    reset: usize,
+    settings: usize,
+    push_promise: usize,
+    ping: usize,
+    go_away: usize,
+    window_update: usize,
+    continuation: usize,
+    other: usize,
+}
+
 impl<T, B> Codec<T, B>
 where
     T: AsyncRead + AsyncWrite,
@@ -39,15 +96,7 @@ where
         // Wrap with writer
         let framed_write = FramedWrite::new(io);
 
-        // Delimit the frames
-        let delimited = length_delimited::Builder::new()
-            .big_endian()
-            .length_field_length(3)
-            .length_adjustment(9)
-            .num_skip(0) // Don't skip the header
-            .new_read(framed_write);
-
-        let mut inner = FramedRead::new(delimited);
+        let mut inner = FramedRead::new(framed_write);
 
         // Use FramedRead's method since it checks the value is within range.
         inner.set_max_frame_size(max_frame_size);
@@ -61,10 +110,8 @@ where
 impl<T, B> Codec<T, B> {
     /// Updates the max received frame size.
     ///
-    /// The change takes effect the next time a frame is decoded. In other
-    /// words, if a frame is currently in process of being decoded with a frame
-    /// size greater than `val` but less than the max frame size in effect
-    /// before calling this function, then the frame will be allowed.
+    /// The limit is checked when the head of a frame is read, so the change
+    /// does not apply to a frame whose payload is currently being read.
     #[inline]
     pub fn set_max_recv_frame_size(&mut self, val: usize) {
         self.inner.set_max_frame_size(val)
@@ -90,11 +137,136 @@ impl<T, B> Codec<T, B> {
         self.framed_write().set_max_frame_size(val)
     }
 
+    /// Set the peer's header table size.
+    pub fn set_send_header_table_size(&mut self, val: usize) {
+        self.framed_write().set_header_table_size(val)
+    }
+
+    /// Set the local cap on the header table size used to encode the header
+    /// blocks sent to the peer.
+    pub fn set_max_send_header_table_size(&mut self, val: usize) {
+        self.framed_write().set_max_header_table_size(val)
+    }
+
+    /// Set the names of the header fields whose values are always sent as
+    /// never indexed literals.
+    pub fn set_send_sensitive_headers(&mut self, val: Vec<HeaderName>) {
+        self.framed_write().set_sensitive_headers(val)
+    }
+
+    /// Set whether the strings of the header blocks that are sent are Huffman
+    /// encoded when it makes them shorter, which is the default.
+    ///
+    /// Disabling it sends all strings as raw octets, which makes header
+    /// blocks easier to read when debugging.
+    #[cfg(feature = "unstable")]
+    pub fn set_send_huffman(&mut self, val: bool) {
+        self.framed_write().set_huffman(val)
+    }
+
+    /// Set the strategy used to pad the HEADERS frames that are sent.
+    ///
+    /// DATA frames are padded before reaching the codec, as the padding
+    /// counts against flow control.
+    pub(crate) fn set_send_padding(&mut self, val: Padding) {
+        self.framed_write().set_padding(val)
+    }
+
+    /// Set the hook called with the frames sent and received.
+    ///
+    /// Received frames are passed to the hook as they are read. Sent frames
+    /// are queued until the next frame is received, or `call_frame_tap` is
+    /// called.
+    pub(crate) fn set_frame_tap(&mut self, val: FrameTap) {
+        self.framed_write().set_tap(val);
+    }
+
+    /// Passes the frames sent so far to the frame tap.
+    pub(crate) fn call_frame_tap(&mut self) {
+        self.framed_write().call_tap();
+    }
+
+    /// Set when the frames to send are written to the socket.
+    pub fn set_flush_strategy(&mut self, val: FlushStrategy) {
+        self.framed_write().set_flush_strategy(val)
+    }
+
+    /// Set the size of the buffer the frames to send are written to.
+    ///
+    /// This must be called before any frame is buffered.
+    pub fn set_send_buffer_size(&mut self, val: usize) {
+        self.framed_write().set_buffer_size(val)
+    }
+
     /// Set the max header list size that can be received.
     pub fn set_max_recv_header_list_size(&mut self, val: usize) {
         self.inner.set_max_header_list_size(val);
     }
 
+    /// Set the max size of the header table the peer may use to encode the
+    /// header blocks that are received.
+    pub fn set_recv_header_table_size(&mut self, val: usize) {
+        self.inner.set_header_table_size(val);
+    }
+
+    /// Set the max number of bytes of a header block that can be received,
+    /// across a HEADERS or PUSH_PROMISE frame and its CONTINUATION frames.
+    pub fn set_max_recv_header_block_size(&mut self, val: usize) {
+        self.inner.set_max_header_block_size(val);
+    }
+
+    /// Set the max number of CONTINUATION frames that can be received in a
+    /// single header block.
+    pub fn set_max_recv_continuation_frames(&mut self, val: usize) {
+        self.inner.set_max_continuation_frames(val);
+    }
+
+    /// Set the max number of parameters that can be received in a single
+    /// SETTINGS frame.
+    pub fn set_max_recv_settings_entries(&mut self, val: usize) {
+        self.inner.set_max_settings_entries(val);
+    }
+
+    /// Set the max number of bytes of debug data kept from a received GOAWAY
+    /// frame. Any additional debug data is discarded.
+    pub fn set_max_recv_go_away_debug_data(&mut self, val: usize) {
+        self.inner.set_max_go_away_debug_data(val);
+    }
+
+    /// Set the types of the unknown frames that are received as extension
+    /// frames instead of being ignored.
+    pub fn set_recv_extension_types(&mut self, val: Vec<u8>) {
+        self.inner.set_extension_types(val);
+    }
+
+    /// Set the max payload size of a received extension frame. Larger
+    /// extension frames are ignored.
+    pub fn set_max_recv_extension_frame_size(&mut self, val: usize) {
+        self.inner.set_max_extension_frame_size(val);
+    }
+
+    /// Set whether received header blocks containing uppercase header names,
+    /// connection-specific header fields or a `te` header field other than
+    /// "trailers" are malformed.
+    ///
+    /// When disabled, these header fields are passed through, with the names
+    /// lowercased.
+    pub fn set_strict_header_validation(&mut self, val: bool) {
+        self.inner.set_strict_header_validation(val);
+    }
+
+    /// Treat reserved flag bits on received SETTINGS, PING and GOAWAY frames
+    /// as a connection error.
+    ///
+    /// By default, unknown flags are ignored as required by section 4.1.
+    /// Strict parsing is meant for conformance tooling; the offending bits can
+    /// also be read back through the `flags` accessor of the leniently loaded
+    /// frame.
+    #[cfg(feature = "unstable")]
+    pub fn set_strict_flags(&mut self, val: bool) {
+        self.inner.set_strict_flags(val);
+    }
+
     /// Get a reference to the inner stream.
     #[cfg(feature = "unstable")]
     pub fn get_ref(&self) -> &T {
@@ -106,6 +278,32 @@ impl<T, B> Codec<T, B> {
         self.inner.get_mut().get_mut()
     }
 
+    /// Returns the number of bytes written to the socket.
+    pub fn bytes_sent(&self) -> usize {
+        self.inner.get_ref().bytes_sent()
+    }
+
+    /// Returns the number of bytes read from the socket.
+    pub fn bytes_received(&self) -> usize {
+        self.inner.get_ref().bytes_received()
+    }
+
+    /// Returns the number of frames of each type buffered to be sent.
+    pub fn frames_sent(&self) -> FrameCounts {
+        self.inner.get_ref().frames_sent()
+    }
+
+    /// Returns the number of frames of each type read from the socket.
+    pub fn frames_received(&self) -> FrameCounts {
+        self.inner.frames_received()
+    }
+
+    /// Takes the heads and lengths of the frames received on a stream, whose
+    /// events are emitted once the frames are handled.
+    pub(crate) fn take_recv_frames(&mut self) -> Vec<(frame::Head, usize)> {
+        self.inner.take_recv_frames()
+    }
+
     /// Takes the data payload value that was fully written to the socket
     pub(crate) fn take_last_data_frame(&mut self) -> Option<Data<B>> {
         self.framed_write().take_last_data_frame()
@@ -141,10 +339,22 @@ where
         self.framed_write().flush()
     }
 
+    /// Flush buffered data to the wire, as required by the flush strategy,
+    /// once no more frames are ready to be buffered.
+    pub fn flush_pending(&mut self) -> Poll<(), io::Error> {
+        self.framed_write().flush_pending()
+    }
+
     /// Shutdown the send half
     pub fn shutdown(&mut self) -> Poll<(), io::Error> {
         self.framed_write().shutdown()
     }
+
+    /// Returns the number of bytes encoded or queued in the codec that were
+    /// not written to the socket yet.
+    pub fn buffered_len(&self) -> usize {
+        self.inner.get_ref().buffered_len()
+    }
 }
 
 impl<T, B> Stream for Codec<T, B>
@@ -196,3 +406,121 @@ where
         Self::new(src)
     }
 }
+
+// ===== impl FrameCounts =====
+
+impl FrameCounts {
+    pub(crate) fn inc(&mut self, kind: frame::Kind) {
+        use frame::Kind::*;
+
+        let count = match kind {
+            Data => &mut self.data,
+            Headers => &mut self.headers,
+            Priority => &mut self.priority,
+            Reset => &mut self.reset,
+            Settings => &mut self.settings,
+            PushPromise => &mut self.push_promise,
+            Ping => &mut self.ping,
+            GoAway => &mut self.go_away,
+            WindowUpdate => &mut self.window_update,
+            Continuation => &mut self.continuation,
+            AltSvc | PriorityUpdate | Unknown => &mut self.other,
+        };
+
+        *count = count.wrapping_add(1);
+    }
+
+    /// Returns the number of DATA frames.
+    pub fn data(&self) -> usize {
+        self.data
+    }
+
+    /// Returns the number of HEADERS frames.
+    pub fn headers(&self) -> usize {
+        self.headers
+    }
+
+    /// Returns the number of PRIORITY frames.
+    pub fn priority(&self) -> usize {
+        self.priority
+    }
+
+    /// Returns the number of RST_STREAM frames.
+    pub fn reset(&self) -> usize {
+        self.reset
+    }
+
+    /// Returns the number of SETTINGS frames, acknowledgements included.
+    pub fn settings(&self) -> usize {
+        self.settings
+    }
+
+    /// Returns the number of PUSH_PROMISE frames.
+    pub fn push_promise(&self) -> usize {
+        self.push_promise
+    }
+
+    /// Returns the number of PING frames, acknowledgements included.
+    pub fn ping(&self) -> usize {
+        self.ping
+    }
+
+    /// Returns the number of GOAWAY frames.
+    pub fn go_away(&self) -> usize {
+        self.go_away
+    }
+
+    /// Returns the number of WINDOW_UPDATE frames.
+    pub fn window_update(&self) -> usize {
+        self.window_update
+    }
+
+    /// Returns the number of CONTINUATION frames.
+    pub fn continuation(&self) -> usize {
+        self.continuation
+    }
+
+    /// Returns the number of frames of any other type, such as ALTSVC,
+    /// PRIORITY_UPDATE and extension frames.
+    pub fn other(&self) -> usize {
+        self.other
+    }
+
+    /// Returns the number of frames of all types.
+    pub fn total(&self) -> usize {
+        self.data
+            .wrapping_add(self.headers)
+            .wrapping_add(self.priority)
+            .wrapping_add(self.reset)
+            .wrapping_add(self.settings)
+            .wrapping_add(self.push_promise)
+            .wrapping_add(self.ping)
+            .wrapping_add(self.go_away)
+            .wrapping_add(self.window_update)
+            .wrapping_add(self.continuation)
+            .wrapping_add(self.other)
+    }
+}
+
+// ===== impl Padding =====
+
+impl Padding {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(usize) -> u8 + Send + Sync + 'static,
+    {
+        Padding(Arc::new(f))
+    }
+
+    /// Returns the length of the padding of a frame carrying `len` octets of
+    /// data or header block fragment. Zero means no padding.
+    pub fn pad_len(&self, len: usize) -> u8 {
+        (self.0)(len)
+    }
+}
+
+impl fmt::Debug for Padding {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Padding").finish()
+    }
+}
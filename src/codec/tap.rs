@@ -0,0 +1,228 @@
+use frame::{self, Head};
+use share::StreamId;
+
+use bytes::{Buf, BufMut, BytesMut};
+use byteorder::{BigEndian, ByteOrder};
+use iovec::IoVec;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Whether a frame was sent or received.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Direction {
+    /// The frame was sent to the peer.
+    Send,
+
+    /// The frame was received from the peer.
+    Recv,
+}
+
+/// A read-only view of a frame sent or received on a connection.
+///
+/// Frames are seen as they are on the wire: a header block split across
+/// CONTINUATION frames is seen as several frames, and its fragments are
+/// HPACK encoded.
+///
+/// The view borrows the frame, it is only valid for the duration of the call
+/// to the hook.
+#[derive(Debug, Clone)]
+pub struct FrameView<'a> {
+    kind: u8,
+    flags: u8,
+    stream_id: StreamId,
+    len: usize,
+    payload: Option<&'a [u8]>,
+}
+
+/// A user hook called with every frame sent or received on a connection.
+///
+/// The hook is shared by the connections built from the same builder.
+#[derive(Clone)]
+pub(crate) struct FrameTap {
+    f: Arc<Mutex<FnMut(Direction, &FrameView) + Send>>,
+    include_data: bool,
+}
+
+/// The frames sent but not passed to the tap yet.
+///
+/// Frames may be encoded while the streams are locked, so they are passed to
+/// the tap later. Their heads, and their payloads when kept, are copied one
+/// after the other to a buffer reused for all the frames.
+#[derive(Debug, Default)]
+pub(crate) struct Sent {
+    buf: BytesMut,
+
+    /// Whether the payload of each frame was copied after its head
+    frames: VecDeque<bool>,
+}
+
+/// A codec layer passing the frames it receives to the frame tap.
+pub(crate) trait TapRecv {
+    /// Called with a frame received, head included, before it is decoded.
+    fn tap_recv(&mut self, src: &[u8]);
+}
+
+/// Max number of chunks gathered from the payload of a DATA frame that is
+/// sent.
+const MAX_DATA_CHUNKS: usize = 16;
+
+// ===== impl FrameView =====
+
+impl<'a> FrameView<'a> {
+    /// Returns the view of the frame at the start of `src`, head included.
+    fn parse(src: &'a [u8], has_payload: bool) -> FrameView<'a> {
+        let head = Head::parse(src);
+        let len = BigEndian::read_uint(src, 3) as usize;
+
+        let payload = if has_payload {
+            Some(&src[frame::HEADER_LEN..frame::HEADER_LEN + len])
+        } else {
+            None
+        };
+
+        FrameView {
+            kind: src[3],
+            flags: head.flag(),
+            stream_id: StreamId::from_internal(head.stream_id()),
+            len,
+            payload,
+        }
+    }
+
+    /// Returns the type of the frame, e.g. `0x1` for HEADERS frames.
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// Returns the flags of the frame.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the identifier of the stream the frame is sent on, zero for
+    /// frames of the connection itself.
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id.clone()
+    }
+
+    /// Returns the length of the payload of the frame, padding included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the payload of the frame, padding included.
+    ///
+    /// `None` is returned for DATA frames, unless the tap asks for their
+    /// payload. The payload of a DATA frame that is sent is gathered from the
+    /// buffer it was sent from, which may be split in up to 16 chunks, e.g.
+    /// a `Chain` of buffers. `None` is returned if it is split in more
+    /// chunks.
+    pub fn payload(&self) -> Option<&'a [u8]> {
+        self.payload
+    }
+}
+
+// ===== impl FrameTap =====
+
+impl FrameTap {
+    pub fn new<F>(include_data: bool, f: F) -> Self
+    where
+        F: FnMut(Direction, &FrameView) + Send + 'static,
+    {
+        FrameTap {
+            f: Arc::new(Mutex::new(f)),
+            include_data,
+        }
+    }
+
+    /// Queues the frame encoded at the start of `src`.
+    ///
+    /// `data` is the payload of a DATA frame, and its padding, when they are
+    /// not encoded with its head. `src` then only holds the head and the pad
+    /// length octet.
+    pub fn queue_sent<B: Buf>(&self, sent: &mut Sent, src: &[u8], data: Option<(&B, &[u8])>) {
+        let len = BigEndian::read_uint(src, 3) as usize;
+        let start = sent.buf.len();
+
+        sent.buf.reserve(frame::HEADER_LEN + len);
+        sent.buf.put_slice(&src[..frame::HEADER_LEN]);
+
+        if !self.has_payload(src) {
+            sent.frames.push_back(false);
+            return;
+        }
+
+        match data {
+            Some((payload, padding)) => {
+                sent.buf.put_slice(&src[frame::HEADER_LEN..]);
+
+                // `IoVec`s cannot be empty, the placeholders are overwritten.
+                let placeholder: &IoVec = (&[0u8][..]).into();
+                let mut chunks = [placeholder; MAX_DATA_CHUNKS];
+                let n = payload.bytes_vec(&mut chunks);
+
+                for chunk in &chunks[..n] {
+                    sent.buf.put_slice(chunk);
+                }
+
+                sent.buf.put_slice(padding);
+            },
+            None => sent.buf.put_slice(&src[frame::HEADER_LEN..frame::HEADER_LEN + len]),
+        }
+
+        if sent.buf.len() == start + frame::HEADER_LEN + len {
+            sent.frames.push_back(true);
+        } else {
+            // The payload is split in too many chunks.
+            sent.buf.truncate(start + frame::HEADER_LEN);
+            sent.frames.push_back(false);
+        }
+    }
+
+    /// Passes the frames sent so far to the hook, in order.
+    pub fn call_sent(&self, sent: &mut Sent) {
+        if sent.frames.is_empty() {
+            return;
+        }
+
+        let mut f = self.f.lock().unwrap();
+        let mut pos = 0;
+
+        while let Some(has_payload) = sent.frames.pop_front() {
+            let view = FrameView::parse(&sent.buf[pos..], has_payload);
+
+            pos += frame::HEADER_LEN;
+            if has_payload {
+                pos += view.len();
+            }
+
+            (&mut *f)(Direction::Send, &view);
+        }
+
+        sent.buf.clear();
+    }
+
+    /// Passes the frame received at the start of `src` to the hook, after the
+    /// frames sent so far.
+    pub fn call_recv(&self, sent: &mut Sent, src: &[u8]) {
+        self.call_sent(sent);
+
+        let view = FrameView::parse(src, self.has_payload(src));
+        let mut f = self.f.lock().unwrap();
+        (&mut *f)(Direction::Recv, &view);
+    }
+
+    fn has_payload(&self, src: &[u8]) -> bool {
+        self.include_data || Head::parse(src).kind() != frame::Kind::Data
+    }
+}
+
+impl fmt::Debug for FrameTap {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("FrameTap")
+            .field("include_data", &self.include_data)
+            .finish()
+    }
+}
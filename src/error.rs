@@ -1,7 +1,10 @@
 use codec::{SendError, UserError};
-use proto;
+use proto::{self, Initiator};
+
+use bytes::Bytes;
 
 use std::{error, fmt, io};
+use std::sync::Arc;
 
 pub use frame::Reason;
 
@@ -13,8 +16,16 @@ pub use frame::Reason;
 /// If the error was caused by the remote peer, then it will contain a
 /// [`Reason`] which can be obtained with the [`reason`] function.
 ///
+/// Errors of streams that were reset, or of connections closed with a GOAWAY
+/// frame, also tell which endpoint did so. See [`is_reset`], [`is_go_away`],
+/// [`is_remote`] and [`is_local`].
+///
 /// [`Reason`]: struct.Reason.html
 /// [`reason`]: #method.reason
+/// [`is_reset`]: #method.is_reset
+/// [`is_go_away`]: #method.is_go_away
+/// [`is_remote`]: #method.is_remote
+/// [`is_local`]: #method.is_local
 #[derive(Debug)]
 pub struct Error {
     kind: Kind,
@@ -22,35 +33,245 @@ pub struct Error {
 
 #[derive(Debug)]
 enum Kind {
-    /// An error caused by an action taken by the remote peer.
-    ///
-    /// This is either an error received by the peer or caused by an invalid
-    /// action taken by the peer (i.e. a protocol error).
-    Proto(Reason),
+    /// The stream was reset with `RST_STREAM` by either endpoint.
+    Reset(Reason, Initiator),
+
+    /// The connection was closed with a GOAWAY frame carrying an error code,
+    /// along with any debug data included in a frame sent by the peer.
+    GoAway(Bytes, Reason, Initiator),
+
+    /// The remote peer did not process the stream, as it was above the last
+    /// stream ID of a GOAWAY frame sent by the peer.
+    Refused(Reason),
+
+    /// The stream was reset with `PROTOCOL_ERROR` because the remote peer
+    /// sent a malformed request or response.
+    Malformed,
+
+    /// The client connection preface could not be received.
+    Handshake(HandshakeError),
 
     /// An error resulting from an invalid action taken by the user of this
     /// library.
     User(UserError),
 
     /// An `io::Error` occurred while trying to read or write.
-    Io(io::Error),
+    ///
+    /// The error is shared by all streams of the connection.
+    Io(Arc<io::Error>),
+}
+
+/// Why a server failed to receive the client connection preface (section
+/// 3.5 of RFC 7540).
+#[derive(Debug)]
+pub(crate) enum HandshakeError {
+    /// The connection was closed after this many bytes of the preface.
+    MissingPreface(usize),
+
+    /// The byte at this offset does not match the preface.
+    PrefaceMismatch(usize),
+
+    /// The handshake did not complete within the handshake timeout.
+    TimedOut,
 }
 
 // ===== impl Error =====
 
 impl Error {
-    /// If the error was caused by the remote peer, the error reason.
+    /// The error code of the stream reset or GOAWAY frame, if any.
     ///
     /// This is either an error received by the peer or caused by an invalid
-    /// action taken by the peer (i.e. a protocol error).
+    /// action taken by the peer (i.e. a protocol error), or the error code
+    /// of a stream reset locally.
     pub fn reason(&self) -> Option<Reason> {
         match self.kind {
-            Kind::Proto(reason) => Some(reason),
+            Kind::Reset(reason, _) |
+            Kind::GoAway(_, reason, _) |
+            Kind::Refused(reason) => Some(reason),
+            Kind::Malformed |
+            Kind::Handshake(HandshakeError::PrefaceMismatch(_)) => Some(Reason::PROTOCOL_ERROR),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the stream was reset with a `RST_STREAM` frame, sent
+    /// by either endpoint.
+    ///
+    /// Streams reset because the peer sent a malformed message are included.
+    pub fn is_reset(&self) -> bool {
+        match self.kind {
+            Kind::Reset(..) | Kind::Malformed => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the connection was closed with a GOAWAY frame carrying
+    /// an error code, sent by either endpoint.
+    ///
+    /// This is the case when the peer sent a GOAWAY frame, or when the
+    /// library detected a connection error and sent one to the peer.
+    pub fn is_go_away(&self) -> bool {
+        match self.kind {
+            Kind::GoAway(..) | Kind::Refused(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the stream was reset, or the connection closed, by
+    /// the remote peer.
+    pub fn is_remote(&self) -> bool {
+        match self.kind {
+            Kind::Reset(_, Initiator::Remote) |
+            Kind::GoAway(_, _, Initiator::Remote) |
+            Kind::Refused(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the stream was reset, or the connection closed, by
+    /// this endpoint.
+    ///
+    /// This is the case both for resets requested by the user, such as with
+    /// `send_reset`, and for errors detected by the library, such as a peer
+    /// violating flow control or sending a malformed message.
+    pub fn is_local(&self) -> bool {
+        match self.kind {
+            Kind::Reset(_, Initiator::Local) |
+            Kind::GoAway(_, _, Initiator::Local) |
+            Kind::Malformed => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the remote peer did not process the stream.
+    ///
+    /// This is the case for streams above the last stream ID of a GOAWAY
+    /// frame sent by the peer, for requests made after such a frame was
+    /// received, and for streams the peer reset with `REFUSED_STREAM`
+    /// (section 8.1.4 of RFC 7540). These requests can safely be retried,
+    /// even if they are not idempotent.
+    pub fn is_remote_refused(&self) -> bool {
+        match self.kind {
+            Kind::Refused(..) |
+            Kind::Reset(Reason::REFUSED_STREAM, Initiator::Remote) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if a request could not be sent because the connection is
+    /// at capacity.
+    ///
+    /// This is the case when the server's `MAX_CONCURRENT_STREAMS` limit is
+    /// reached and too many requests are queued already. The request can be
+    /// sent on another connection instead.
+    pub fn is_at_capacity(&self) -> bool {
+        match self.kind {
+            Kind::User(UserError::AtCapacity) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if a stream could not be opened because the connection ran
+    /// out of stream identifiers.
+    ///
+    /// Stream identifiers are never reused, so a connection can open at most
+    /// 2^30 streams of its own. Once they run out, a client connection closes
+    /// after its remaining streams complete, and new requests have to be sent
+    /// on another connection.
+    pub fn is_stream_id_overflow(&self) -> bool {
+        match self.kind {
+            Kind::User(UserError::OverflowedStreamId) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if data or trailers were sent on a stream that was already
+    /// ended.
+    ///
+    /// A stream ends when a frame carrying the `END_STREAM` flag is sent, e.g.
+    /// when calling `send_response` or `send_request` with `end_of_stream`
+    /// set, or when sending the last data frame or the trailers.
+    pub fn is_send_after_end_stream(&self) -> bool {
+        match self.kind {
+            Kind::User(UserError::SendAfterEndStream) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if headers, trailers or a push promise were larger than
+    /// the maximum size of the header blocks sent.
+    ///
+    /// The maximum is set with `max_send_header_block_size` on the client or
+    /// server `Builder`.
+    pub fn is_header_block_too_big(&self) -> bool {
+        match self.kind {
+            Kind::User(UserError::HeaderBlockTooBig) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the handshake failed because a setting given to the
+    /// client or server `Builder` is out of its bounds.
+    pub fn is_invalid_settings(&self) -> bool {
+        match self.kind {
+            Kind::User(UserError::InvalidSettings) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the stream was reset because the remote peer sent a
+    /// malformed message.
+    ///
+    /// Requests and responses violating section 8.1 of RFC 7540, such as
+    /// ones with invalid pseudo-header fields or a body that does not match
+    /// the `content-length` header, are malformed. The stream is reset with
+    /// `PROTOCOL_ERROR`, which is also the error's [`reason`].
+    ///
+    /// [`reason`]: #method.reason
+    pub fn is_malformed(&self) -> bool {
+        match self.kind {
+            Kind::Malformed => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the server handshake failed.
+    ///
+    /// This is the case when the client closes the connection before sending
+    /// the whole connection preface, sends something else than the preface,
+    /// or does not send it within the [`handshake_timeout`]. A preface
+    /// mismatch is a protocol error, and its [`reason`] is
+    /// `PROTOCOL_ERROR`.
+    ///
+    /// [`handshake_timeout`]: server/struct.Builder.html#method.handshake_timeout
+    /// [`reason`]: #method.reason
+    pub fn is_handshake(&self) -> bool {
+        match self.kind {
+            Kind::Handshake(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the debug data of the GOAWAY frame the error was received in.
+    ///
+    /// Peers may include debug data in a GOAWAY frame to help diagnose why
+    /// the connection was closed. It is opaque and often human readable
+    /// text. The amount of debug data kept is limited, see
+    /// `max_go_away_debug_data` on the client and server builders.
+    ///
+    /// Returns `None` if the error was not received in a GOAWAY frame, or
+    /// if the stream was refused.
+    pub fn debug_data(&self) -> Option<&Bytes> {
+        match self.kind {
+            Kind::GoAway(ref debug_data, _, Initiator::Remote) => Some(debug_data),
             _ => None,
         }
     }
 
     /// Returns the true if the error is an io::Error
+    ///
+    /// When the connection fails with an I/O error, the error of each of its
+    /// streams is that same I/O error.
     pub fn is_io(&self) -> bool {
         match self.kind {
             Kind::Io(_) => true,
@@ -67,9 +288,12 @@ impl Error {
     }
 
     /// Returns the error if the error is an io::Error
+    ///
+    /// If other streams of the connection still hold the I/O error, the
+    /// returned error is a copy with the same kind and message.
     pub fn into_io(self) -> Option<io::Error> {
         match self.kind {
-            Kind::Io(e) => Some(e),
+            Kind::Io(e) => Some(proto::unshare_io(e)),
             _ => None,
         }
     }
@@ -81,7 +305,10 @@ impl From<proto::Error> for Error {
 
         Error {
             kind: match src {
-                Proto(reason) => Kind::Proto(reason),
+                Reset(reason, initiator) => Kind::Reset(reason, initiator),
+                GoAway(debug_data, reason, initiator) => Kind::GoAway(debug_data, reason, initiator),
+                Refused(reason) => Kind::Refused(reason),
+                Malformed => Kind::Malformed,
                 Io(e) => Kind::Io(e),
             },
         }
@@ -91,15 +318,44 @@ impl From<proto::Error> for Error {
 impl From<io::Error> for Error {
     fn from(src: io::Error) -> Error {
         Error {
-            kind: Kind::Io(src),
+            kind: Kind::Io(Arc::new(src)),
         }
     }
 }
 
+impl From<Error> for io::Error {
+    /// Converts the error into an `io::Error`.
+    ///
+    /// I/O errors are returned as is. Other errors are wrapped in an
+    /// `io::Error` of a matching kind:
+    ///
+    /// * `ConnectionReset` if the stream was reset,
+    /// * `ConnectionAborted` if the connection was closed with a GOAWAY frame,
+    /// * `InvalidData` if the peer sent a malformed message or an invalid
+    ///   connection preface,
+    /// * `UnexpectedEof` if the connection closed during the handshake,
+    /// * `TimedOut` if the handshake timed out,
+    /// * `Other` for errors caused by the user of the library.
+    fn from(src: Error) -> io::Error {
+        let kind = match src.kind {
+            Kind::Io(_) => return src.into_io().unwrap(),
+            Kind::Reset(..) => io::ErrorKind::ConnectionReset,
+            Kind::GoAway(..) | Kind::Refused(_) => io::ErrorKind::ConnectionAborted,
+            Kind::Malformed |
+            Kind::Handshake(HandshakeError::PrefaceMismatch(_)) => io::ErrorKind::InvalidData,
+            Kind::Handshake(HandshakeError::MissingPreface(_)) => io::ErrorKind::UnexpectedEof,
+            Kind::Handshake(HandshakeError::TimedOut) => io::ErrorKind::TimedOut,
+            Kind::User(_) => io::ErrorKind::Other,
+        };
+
+        io::Error::new(kind, src)
+    }
+}
+
 impl From<Reason> for Error {
     fn from(src: Reason) -> Error {
         Error {
-            kind: Kind::Proto(src),
+            kind: Kind::Reset(src, Initiator::Local),
         }
     }
 }
@@ -108,12 +364,22 @@ impl From<SendError> for Error {
     fn from(src: SendError) -> Error {
         match src {
             SendError::User(e) => e.into(),
-            SendError::Connection(reason) => reason.into(),
+            SendError::Connection(reason) => Error {
+                kind: Kind::GoAway(Bytes::new(), reason, Initiator::Local),
+            },
             SendError::Io(e) => e.into(),
         }
     }
 }
 
+impl From<HandshakeError> for Error {
+    fn from(src: HandshakeError) -> Error {
+        Error {
+            kind: Kind::Handshake(src),
+        }
+    }
+}
+
 impl From<UserError> for Error {
     fn from(src: UserError) -> Error {
         Error {
@@ -127,7 +393,14 @@ impl fmt::Display for Error {
         use self::Kind::*;
 
         match self.kind {
-            Proto(ref reason) => write!(fmt, "protocol error: {}", reason),
+            Reset(ref reason, _) => write!(fmt, "protocol error: {}", reason),
+            GoAway(ref debug_data, ref reason, _) if !debug_data.is_empty() => {
+                write!(fmt, "protocol error: {}; debug data: {:?}", reason, debug_data)
+            },
+            GoAway(_, ref reason, _) => write!(fmt, "protocol error: {}", reason),
+            Refused(ref reason) => write!(fmt, "stream not processed by peer: {}", reason),
+            Malformed => write!(fmt, "protocol error: {}", Reason::PROTOCOL_ERROR),
+            Handshake(ref e) => write!(fmt, "handshake error: {}", e),
             User(ref e) => write!(fmt, "user error: {}", e),
             Io(ref e) => fmt::Display::fmt(e, fmt),
         }
@@ -139,9 +412,48 @@ impl error::Error for Error {
         use self::Kind::*;
 
         match self.kind {
-            Io(ref e) => error::Error::description(e),
-            Proto(ref reason) => reason.description(),
+            Io(ref e) => error::Error::description(&**e),
+            Reset(ref reason, _) |
+            GoAway(_, ref reason, _) |
+            Refused(ref reason) => reason.description(),
+            Malformed => Reason::PROTOCOL_ERROR.description(),
+            Handshake(ref e) => e.description(),
             User(ref user) => user.description(),
         }
     }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            Kind::Io(ref e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+// ===== impl HandshakeError =====
+
+impl HandshakeError {
+    fn description(&self) -> &str {
+        use self::HandshakeError::*;
+
+        match *self {
+            MissingPreface(_) => "missing preface",
+            PrefaceMismatch(_) => "preface mismatch",
+            TimedOut => "handshake timed out",
+        }
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::HandshakeError::*;
+
+        match *self {
+            MissingPreface(n) => {
+                write!(fmt, "missing preface; connection closed after {} bytes", n)
+            },
+            PrefaceMismatch(n) => write!(fmt, "preface mismatch at byte {}", n),
+            TimedOut => write!(fmt, "timed out"),
+        }
+    }
 }
@@ -0,0 +1,189 @@
+use frame::{self, Error, Head, Kind, StreamId};
+
+use bytes::{BufMut, Bytes};
+
+/// An ALTSVC frame, as defined in section 4 of RFC 7838.
+///
+/// The frame advertises alternative services for an origin. When sent on the
+/// stream 0, the frame carries the origin it applies to. Otherwise, it
+/// applies to the origin of the stream's request and the origin is empty.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AltSvc {
+    stream_id: StreamId,
+    origin: Bytes,
+    field_value: Bytes,
+}
+
+impl AltSvc {
+    pub fn new(stream_id: StreamId, origin: Bytes, field_value: Bytes) -> Self {
+        AltSvc {
+            stream_id,
+            origin,
+            field_value,
+        }
+    }
+
+    pub fn load(head: Head, payload: &[u8]) -> Result<Self, Error> {
+        debug_assert_eq!(head.kind(), Kind::AltSvc);
+
+        if payload.len() < 2 {
+            return Err(Error::InvalidPayloadLength);
+        }
+
+        let origin_len = ((payload[0] as usize) << 8) | payload[1] as usize;
+
+        if payload.len() < 2 + origin_len {
+            return Err(Error::InvalidPayloadLength);
+        }
+
+        Ok(AltSvc {
+            stream_id: head.stream_id(),
+            origin: Bytes::from(&payload[2..2 + origin_len]),
+            field_value: Bytes::from(&payload[2 + origin_len..]),
+        })
+    }
+
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Returns the origin the alternative services apply to, which is empty
+    /// for frames sent on streams other than 0.
+    pub fn origin(&self) -> &Bytes {
+        &self.origin
+    }
+
+    /// Returns the value of the frame, using the syntax of the `Alt-Svc`
+    /// header field.
+    pub fn field_value(&self) -> &Bytes {
+        &self.field_value
+    }
+
+    /// Returns true if the frame must be ignored by the receiver (section 4).
+    ///
+    /// > An ALTSVC frame on stream 0 with empty (length 0) "Origin"
+    /// > information is invalid and MUST be ignored. An ALTSVC frame on a
+    /// > stream other than stream 0 containing non-empty "Origin" information
+    /// > is invalid and MUST be ignored.
+    ///
+    /// Frames without any alternative service are ignored as well.
+    pub fn is_ignored(&self) -> bool {
+        self.stream_id.is_zero() == self.origin.is_empty() || self.field_value.is_empty()
+    }
+
+    pub fn encode_len(&self) -> usize {
+        frame::HEADER_LEN + 2 + self.origin.len() + self.field_value.len()
+    }
+
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        trace!("encoding ALTSVC; id={:?}", self.stream_id);
+        let head = Head::new(Kind::AltSvc, 0, self.stream_id);
+        head.encode(2 + self.origin.len() + self.field_value.len(), dst);
+        dst.put_u16_be(self.origin.len() as u16);
+        dst.put_slice(&self.origin);
+        dst.put_slice(&self.field_value);
+    }
+}
+
+impl<B> From<AltSvc> for frame::Frame<B> {
+    fn from(src: AltSvc) -> Self {
+        frame::Frame::AltSvc(src)
+    }
+}
+
+#[cfg(feature = "serde")]
+frame_serde!(AltSvc {
+    stream_id: StreamId = stream_id,
+    origin: Vec<u8> = origin_vec,
+    field_value: Vec<u8> = field_value_vec,
+} |stream_id, origin: Vec<u8>, field_value: Vec<u8>| {
+    if origin.len() > u16::max_value() as usize {
+        return Err("origin too long");
+    }
+
+    Ok(AltSvc::new(stream_id, origin.into(), field_value.into()))
+});
+
+#[cfg(feature = "serde")]
+impl AltSvc {
+    fn origin_vec(&self) -> Vec<u8> {
+        self.origin.to_vec()
+    }
+
+    fn field_value_vec(&self) -> Vec<u8> {
+        self.field_value.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode(frame: &AltSvc) -> Vec<u8> {
+        let mut buf = vec![];
+        frame.encode(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn encode_and_load_on_stream_zero() {
+        // The example of section 4 of RFC 7838, with an origin.
+        let frame = AltSvc::new(
+            StreamId::zero(),
+            "https://example.com".into(),
+            r#"h2="alt.example.com:8000", h2=":443""#.into(),
+        );
+
+        let mut expected = vec![0, 0, 57, 0x0a, 0, 0, 0, 0, 0, 0, 19];
+        expected.extend_from_slice(b"https://example.com");
+        expected.extend_from_slice(br#"h2="alt.example.com:8000", h2=":443""#);
+
+        assert_eq!(encode(&frame), expected);
+
+        let head = Head::parse(&expected);
+        let loaded = AltSvc::load(head, &expected[frame::HEADER_LEN..]).unwrap();
+        assert_eq!(loaded, frame);
+        assert!(!loaded.is_ignored());
+    }
+
+    #[test]
+    fn encode_and_load_on_stream() {
+        let frame = AltSvc::new(StreamId::from(1), Bytes::new(), r#"h2=":8000"; ma=60"#.into());
+
+        let mut expected = vec![0, 0, 19, 0x0a, 0, 0, 0, 0, 1, 0, 0];
+        expected.extend_from_slice(br#"h2=":8000"; ma=60"#);
+
+        assert_eq!(encode(&frame), expected);
+
+        let head = Head::parse(&expected);
+        let loaded = AltSvc::load(head, &expected[frame::HEADER_LEN..]).unwrap();
+        assert_eq!(loaded, frame);
+        assert!(!loaded.is_ignored());
+
+        // Clearing the alternative services
+        let frame = AltSvc::new(StreamId::from(1), Bytes::new(), "clear".into());
+        assert!(!frame.is_ignored());
+    }
+
+    #[test]
+    fn load_invalid_alt_svc() {
+        let head = Head::new(Kind::AltSvc, 0, StreamId::zero());
+        assert_eq!(AltSvc::load(head, &[0]), Err(Error::InvalidPayloadLength));
+        assert_eq!(AltSvc::load(head, &[0, 3, b'a', b'b']), Err(Error::InvalidPayloadLength));
+    }
+
+    #[test]
+    fn ignored_alt_svc() {
+        let value = Bytes::from(r#"h2=":443""#);
+
+        // No origin on stream 0
+        assert!(AltSvc::new(StreamId::zero(), Bytes::new(), value.clone()).is_ignored());
+
+        // Origin on another stream
+        assert!(AltSvc::new(StreamId::from(1), "https://example.com".into(), value).is_ignored());
+
+        // Empty field value
+        assert!(AltSvc::new(StreamId::zero(), "https://example.com".into(), Bytes::new()).is_ignored());
+        assert!(AltSvc::new(StreamId::from(1), Bytes::new(), Bytes::new()).is_ignored());
+    }
+}
@@ -63,6 +63,32 @@ impl<T> Data<T> {
         }
     }
 
+    /// Returns the length of the padding, if the `PADDED` flag is set.
+    pub fn pad_len(&self) -> Option<u8> {
+        self.pad_len
+    }
+
+    /// Pads the frame with `pad_len` octets of zeros.
+    ///
+    /// The padding, and the octet holding its length, count against the
+    /// max frame size and flow control.
+    pub fn set_pad_len(&mut self, pad_len: u8) {
+        self.flags.set_padded();
+        self.pad_len = Some(pad_len);
+    }
+
+    /// Removes the padding of the frame.
+    pub(crate) fn unset_padding(&mut self) {
+        self.flags.unset_padded();
+        self.pad_len = None;
+    }
+
+    /// Returns the number of octets of the frame payload taken by the
+    /// padding, including the pad length octet.
+    pub(crate) fn padding_len(&self) -> usize {
+        self.pad_len.map(|len| len as usize + 1).unwrap_or(0)
+    }
+
     /// Returns a reference to this frame's payload.
     ///
     /// This does **not** include any padding that might have been originally
@@ -137,11 +163,21 @@ impl<T: Buf> Data<T> {
     /// Panics if `dst` cannot contain the data frame.
     pub(crate) fn encode_chunk<U: BufMut>(&mut self, dst: &mut U) {
         let len = self.data.remaining() as usize;
+        let padding_len = self.padding_len();
 
-        assert!(dst.remaining_mut() >= len);
+        assert!(dst.remaining_mut() >= len + padding_len);
+
+        self.head().encode(len + padding_len, dst);
+
+        if let Some(pad_len) = self.pad_len {
+            dst.put_u8(pad_len);
+        }
 
-        self.head().encode(len, dst);
         dst.put(&mut self.data);
+
+        if let Some(pad_len) = self.pad_len {
+            util::put_padding(pad_len, dst);
+        }
     }
 }
 
@@ -151,6 +187,21 @@ impl<T> From<Data<T>> for Frame<T> {
     }
 }
 
+#[cfg(feature = "serde")]
+frame_serde!(Data<T> {
+    stream_id: StreamId = stream_id,
+    end_stream: bool = is_end_stream,
+    data: T = payload,
+} |stream_id, end_stream, data| {
+    if stream_id.is_zero() {
+        return Err("DATA frame with a zero stream identifier");
+    }
+
+    let mut frame = Data::new(stream_id, data);
+    frame.set_end_stream(end_stream);
+    Ok(frame)
+});
+
 impl<T> fmt::Debug for Data<T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Data")
@@ -184,6 +235,14 @@ impl DataFlags {
     fn is_padded(&self) -> bool {
         self.0 & PADDED == PADDED
     }
+
+    fn set_padded(&mut self) {
+        self.0 |= PADDED
+    }
+
+    fn unset_padded(&mut self) {
+        self.0 &= !PADDED
+    }
 }
 
 impl Default for DataFlags {
@@ -0,0 +1,145 @@
+use frame::{self, Head, Kind, StreamId};
+
+use bytes::{BufMut, Bytes};
+
+/// A frame of a type unknown to this library.
+///
+/// Extensions can define new frame types (section 5.5 of RFC 7540). The frame
+/// is kept as received: neither the flags nor the payload are interpreted, so
+/// a frame setting e.g. the PADDED flag is still delivered untouched.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Extension {
+    kind: u8,
+    flags: u8,
+    stream_id: StreamId,
+    payload: Bytes,
+}
+
+impl Extension {
+    pub fn new(kind: u8, flags: u8, stream_id: StreamId, payload: Bytes) -> Self {
+        debug_assert!(!is_known_kind(kind));
+
+        Extension {
+            kind,
+            flags,
+            stream_id,
+            payload,
+        }
+    }
+
+    /// Loads a frame from its raw header and payload.
+    pub fn load(header: &[u8], payload: Bytes) -> Self {
+        let head = Head::parse(header);
+        debug_assert_eq!(head.kind(), Kind::Unknown);
+
+        Extension {
+            kind: header[3],
+            flags: head.flag(),
+            stream_id: head.stream_id(),
+            payload,
+        }
+    }
+
+    /// Returns the frame type.
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    pub fn into_payload(self) -> Bytes {
+        self.payload
+    }
+
+    pub fn encode_len(&self) -> usize {
+        frame::HEADER_LEN + self.payload.len()
+    }
+
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        trace!("encoding extension frame; kind={}; id={:?}", self.kind, self.stream_id);
+        dst.put_uint_be(self.payload.len() as u64, 3);
+        dst.put_u8(self.kind);
+        dst.put_u8(self.flags);
+        dst.put_u32_be(self.stream_id.into());
+        dst.put_slice(&self.payload);
+    }
+}
+
+impl<B> From<Extension> for frame::Frame<B> {
+    fn from(src: Extension) -> Self {
+        frame::Frame::Extension(src)
+    }
+}
+
+/// Returns true if frames of this type are handled by the library.
+pub fn is_known_kind(kind: u8) -> bool {
+    Kind::new(kind) != Kind::Unknown
+}
+
+#[cfg(feature = "serde")]
+frame_serde!(Extension {
+    kind: u8 = kind,
+    flags: u8 = flags,
+    stream_id: StreamId = stream_id,
+    payload: Vec<u8> = payload_vec,
+} |kind, flags, stream_id, payload: Vec<u8>| {
+    if is_known_kind(kind) {
+        return Err("known frame type");
+    }
+
+    if payload.len() > frame::MAX_MAX_FRAME_SIZE as usize {
+        return Err("payload too long");
+    }
+
+    Ok(Extension::new(kind, flags, stream_id, payload.into()))
+});
+
+#[cfg(feature = "serde")]
+impl Extension {
+    fn payload_vec(&self) -> Vec<u8> {
+        self.payload.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_and_load_extension() {
+        // The PADDED flag is not interpreted.
+        let frame = Extension::new(0xf0, 0x8, StreamId::from(3), Bytes::from(&[5, 1, 2][..]));
+
+        let mut buf = vec![];
+        frame.encode(&mut buf);
+        assert_eq!(buf, [0, 0, 3, 0xf0, 0x8, 0, 0, 0, 3, 5, 1, 2]);
+        assert_eq!(buf.len(), frame.encode_len());
+
+        let payload = Bytes::from(&buf[frame::HEADER_LEN..]);
+        let loaded = Extension::load(&buf[..frame::HEADER_LEN], payload);
+        assert_eq!(loaded, frame);
+        assert_eq!(loaded.kind(), 0xf0);
+        assert_eq!(loaded.flags(), 0x8);
+    }
+
+    #[test]
+    fn known_kinds() {
+        for kind in 0..11 {
+            assert!(is_known_kind(kind));
+        }
+
+        assert!(is_known_kind(0x10));
+        assert!(!is_known_kind(0xb));
+        assert!(!is_known_kind(0xff));
+    }
+}
@@ -1,18 +1,31 @@
 use frame::{self, Error, Head, Kind, Reason, StreamId};
 
-use bytes::{BufMut};
+use bytes::{BufMut, Bytes};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct GoAway {
     last_stream_id: StreamId,
     error_code: Reason,
+    debug_data: Bytes,
+    flags: u8,
 }
 
+/// The default maximum number of bytes of debug data kept from a received
+/// GOAWAY frame.
+pub const DEFAULT_MAX_GO_AWAY_DEBUG_DATA: usize = 1_024;
+
 impl GoAway {
     pub fn new(last_stream_id: StreamId, reason: Reason) -> Self {
+        GoAway::with_debug_data(last_stream_id, reason, Bytes::new())
+    }
+
+    /// Creates a GOAWAY frame carrying additional debug data (6.8.).
+    pub fn with_debug_data(last_stream_id: StreamId, reason: Reason, debug_data: Bytes) -> Self {
         GoAway {
             last_stream_id,
             error_code: reason,
+            debug_data,
+            flags: 0,
         }
     }
 
@@ -24,7 +37,26 @@ impl GoAway {
         self.error_code
     }
 
-    pub fn load(payload: &[u8]) -> Result<GoAway, Error> {
+    /// Returns the opaque debug data sent along with the frame. This is empty
+    /// if the frame carried none.
+    pub fn debug_data(&self) -> &Bytes {
+        &self.debug_data
+    }
+
+    /// Returns the raw flag byte. GOAWAY does not define any flags, so this
+    /// is only non-zero if the peer set reserved bits.
+    #[cfg(any(feature = "unstable", feature = "serde"))]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Loads a GOAWAY frame, keeping at most `max_debug_data` bytes of its
+    /// debug data. The rest is discarded.
+    pub fn load_with_max_debug_data(
+        head: Head,
+        payload: &[u8],
+        max_debug_data: usize,
+    ) -> Result<GoAway, Error> {
         if payload.len() < 8 {
             return Err(Error::BadFrameSize);
         }
@@ -32,18 +64,38 @@ impl GoAway {
         let (last_stream_id, _) = StreamId::parse(&payload[..4]);
         let error_code = unpack_octets_4!(payload, 4, u32);
 
+        let debug_data = &payload[8..];
+
+        if debug_data.len() > max_debug_data {
+            debug!(
+                "truncating GOAWAY debug data; len={}; max={}",
+                debug_data.len(),
+                max_debug_data
+            );
+        }
+
+        let len = ::std::cmp::min(debug_data.len(), max_debug_data);
+
         Ok(GoAway {
             last_stream_id: last_stream_id,
             error_code: error_code.into(),
+            debug_data: Bytes::from(&debug_data[..len]),
+            flags: head.flag(),
         })
     }
 
+    /// Returns the number of bytes needed to encode the frame.
+    pub fn encode_len(&self) -> usize {
+        frame::HEADER_LEN + 8 + self.debug_data.len()
+    }
+
     pub fn encode<B: BufMut>(&self, dst: &mut B) {
         trace!("encoding GO_AWAY; code={:?}", self.error_code);
         let head = Head::new(Kind::GoAway, 0, StreamId::zero());
-        head.encode(8, dst);
+        head.encode(8 + self.debug_data.len(), dst);
         dst.put_u32_be(self.last_stream_id.into());
         dst.put_u32_be(self.error_code.into());
+        dst.put_slice(&self.debug_data);
     }
 }
 
@@ -52,3 +104,70 @@ impl<B> From<GoAway> for frame::Frame<B> {
         frame::Frame::GoAway(src)
     }
 }
+
+#[cfg(feature = "serde")]
+frame_serde!(GoAway {
+    last_stream_id: StreamId = last_stream_id,
+    error_code: Reason = reason,
+    debug_data: Vec<u8> = debug_data_vec,
+    flags: u8 = flags,
+} |last_stream_id, error_code, debug_data, flags| {
+    Ok(GoAway {
+        last_stream_id,
+        error_code,
+        debug_data: debug_data.into(),
+        flags,
+    })
+});
+
+#[cfg(feature = "serde")]
+impl GoAway {
+    fn debug_data_vec(&self) -> Vec<u8> {
+        self.debug_data.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bytes::BytesMut;
+
+    fn head() -> Head {
+        Head::new(Kind::GoAway, 0, StreamId::zero())
+    }
+
+    #[test]
+    fn encode_load_debug_data() {
+        let frame = GoAway::with_debug_data(
+            StreamId::from(5),
+            Reason::ENHANCE_YOUR_CALM,
+            Bytes::from_static(b"too many pings"),
+        );
+
+        let mut buf = BytesMut::with_capacity(frame.encode_len());
+        frame.encode(&mut buf);
+        assert_eq!(buf.len(), frame.encode_len());
+
+        let loaded = GoAway::load_with_max_debug_data(
+            head(),
+            &buf[frame::HEADER_LEN..],
+            DEFAULT_MAX_GO_AWAY_DEBUG_DATA,
+        ).unwrap();
+        assert_eq!(loaded, frame);
+        assert_eq!(&loaded.debug_data()[..], b"too many pings");
+    }
+
+    #[test]
+    fn load_truncates_debug_data() {
+        let payload = [0, 0, 0, 1, 0, 0, 0, 2, b'a', b'b', b'c', b'd'];
+
+        let frame = GoAway::load_with_max_debug_data(head(), &payload, 2).unwrap();
+        assert_eq!(frame.last_stream_id(), StreamId::from(1));
+        assert_eq!(frame.reason(), Reason::INTERNAL_ERROR);
+        assert_eq!(&frame.debug_data()[..], b"ab");
+
+        let frame = GoAway::load_with_max_debug_data(head(), &payload, 0).unwrap();
+        assert!(frame.debug_data().is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use super::StreamId;
+use super::{Error, StreamId};
 
 use bytes::{BufMut};
 
@@ -22,6 +22,8 @@ pub enum Kind {
     GoAway = 7,
     WindowUpdate = 8,
     Continuation = 9,
+    AltSvc = 10,
+    PriorityUpdate = 16,
     Unknown,
 }
 
@@ -59,6 +61,26 @@ impl Head {
         self.flag
     }
 
+    /// Returns an error if any flag bits that are not defined for the frame
+    /// type are set.
+    ///
+    /// Only SETTINGS, PING and GOAWAY frames are checked. The flags of other
+    /// frame types are always accepted.
+    pub fn check_flags(&self) -> Result<(), Error> {
+        let known = match self.kind {
+            // ACK (0x1)
+            Kind::Settings | Kind::Ping => 0x1,
+            Kind::GoAway => 0,
+            _ => return Ok(()),
+        };
+
+        if self.flag & !known != 0 {
+            return Err(Error::UnexpectedFlags);
+        }
+
+        Ok(())
+    }
+
     pub fn encode_len(&self) -> usize {
         super::HEADER_LEN
     }
@@ -88,6 +110,8 @@ impl Kind {
             7 => Kind::GoAway,
             8 => Kind::WindowUpdate,
             9 => Kind::Continuation,
+            10 => Kind::AltSvc,
+            16 => Kind::PriorityUpdate,
             _ => Kind::Unknown,
         }
     }
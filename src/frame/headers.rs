@@ -1,5 +1,5 @@
 use super::{StreamDependency, StreamId};
-use frame::{Error, Frame, Head, Kind};
+use frame::{util, Error, Frame, Head, Kind, HEADER_LEN};
 use hpack;
 
 use http::{uri, HeaderMap, Method, StatusCode, Uri};
@@ -28,6 +28,9 @@ pub struct Headers {
 
     /// The associated flags
     flags: HeadersFlag,
+
+    /// The length of the padding, if the frame is padded
+    pad_len: Option<u8>,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -60,7 +63,7 @@ pub struct Continuation {
 }
 
 // TODO: These fields shouldn't be `pub`
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct Pseudo {
     // Request
     pub method: Option<Method>,
@@ -68,6 +71,9 @@ pub struct Pseudo {
     pub authority: Option<String<Bytes>>,
     pub path: Option<String<Bytes>>,
 
+    // Extended CONNECT (RFC 8441)
+    pub protocol: Option<String<Bytes>>,
+
     // Response
     pub status: Option<StatusCode>,
 }
@@ -89,6 +95,10 @@ struct HeaderBlock {
     /// Set to true if decoding went over the max header list size.
     is_over_size: bool,
 
+    /// Set to the reason the header block makes the message malformed
+    /// (section 8.1.2.6 of RFC 7540), if it does.
+    malformed: Option<&'static str>,
+
     /// Pseudo headers, these are broken out as they must be sent as part of the
     /// headers frame.
     pseudo: Pseudo,
@@ -97,7 +107,7 @@ struct HeaderBlock {
 #[derive(Debug)]
 struct EncodingHeaderBlock {
     /// Argument to pass to the HPACK encoder to resume encoding
-    hpack: Option<hpack::EncodeState>,
+    hpack: Option<hpack::raw::EncodeState>,
 
     /// remaining headers to encode
     headers: Iter,
@@ -120,9 +130,11 @@ impl Headers {
             header_block: HeaderBlock {
                 fields: fields,
                 is_over_size: false,
+                malformed: None,
                 pseudo: pseudo,
             },
             flags: HeadersFlag::default(),
+            pad_len: None,
         }
     }
 
@@ -136,9 +148,11 @@ impl Headers {
             header_block: HeaderBlock {
                 fields: fields,
                 is_over_size: false,
+                malformed: None,
                 pseudo: Pseudo::default(),
             },
             flags: flags,
+            pad_len: None,
         }
     }
 
@@ -167,12 +181,10 @@ impl Headers {
             if src.len() < 5 {
                 return Err(Error::MalformedMessage);
             }
+            // A stream depending on itself is rejected once the header
+            // block is decoded, so that the HPACK state stays in sync.
             let stream_dep = StreamDependency::load(&src[..5])?;
 
-            if stream_dep.dependency_id() == head.stream_id() {
-                return Err(Error::InvalidDependencyId);
-            }
-
             // Drop the next 5 bytes
             let _ = src.split_to(5);
 
@@ -196,16 +208,29 @@ impl Headers {
             header_block: HeaderBlock {
                 fields: HeaderMap::new(),
                 is_over_size: false,
+                malformed: None,
                 pseudo: Pseudo::default(),
             },
             flags: flags,
+            pad_len: if flags.is_padded() {
+                Some(pad as u8)
+            } else {
+                None
+            },
         };
 
         Ok((headers, src))
     }
 
-    pub fn load_hpack(&mut self, src: &mut BytesMut, max_header_list_size: usize, decoder: &mut hpack::Decoder) -> Result<(), Error> {
-        self.header_block.load(src, max_header_list_size, decoder)
+    pub fn load_hpack(
+        &mut self,
+        src: &mut BytesMut,
+        is_end_headers: bool,
+        max_header_list_size: usize,
+        strict: bool,
+        decoder: &mut hpack::raw::Decoder,
+    ) -> Result<(), Error> {
+        self.header_block.load(src, is_end_headers, max_header_list_size, strict, decoder)
     }
 
     pub fn stream_id(&self) -> StreamId {
@@ -232,10 +257,49 @@ impl Headers {
         self.header_block.is_over_size
     }
 
+    pub fn is_malformed(&self) -> bool {
+        self.header_block.malformed.is_some()
+    }
+
+    /// Returns the reason the header block makes the message malformed, if
+    /// it does.
+    pub fn malformed_reason(&self) -> Option<&'static str> {
+        self.header_block.malformed
+    }
+
+    /// Returns the length of the padding, if the `PADDED` flag is set.
+    #[cfg(any(feature = "unstable", feature = "serde"))]
+    pub fn pad_len(&self) -> Option<u8> {
+        self.pad_len
+    }
+
+    /// Pads the frame with `pad_len` octets of zeros.
+    ///
+    /// Only the HEADERS frame is padded, not the CONTINUATION frames that
+    /// may follow it.
+    #[cfg(any(feature = "unstable", feature = "serde"))]
+    pub fn set_pad_len(&mut self, pad_len: u8) {
+        self.flags.set_padded();
+        self.pad_len = Some(pad_len);
+    }
+
+    pub fn stream_dependency(&self) -> Option<&StreamDependency> {
+        self.stream_dep.as_ref()
+    }
+
+    pub fn set_stream_dependency(&mut self, dependency: StreamDependency) {
+        self.stream_dep = Some(dependency);
+        self.flags.set_priority();
+    }
+
     pub fn into_parts(self) -> (Pseudo, HeaderMap) {
         (self.header_block.pseudo, self.header_block.fields)
     }
 
+    pub fn pseudo(&self) -> &Pseudo {
+        &self.header_block.pseudo
+    }
+
     #[cfg(feature = "unstable")]
     pub fn pseudo_mut(&mut self) -> &mut Pseudo {
         &mut self.header_block.pseudo
@@ -249,16 +313,55 @@ impl Headers {
         self.header_block.fields
     }
 
-    pub fn encode(self, encoder: &mut hpack::Encoder, dst: &mut BytesMut) -> Option<Continuation> {
+    /// Returns the size of the header list, which bounds the size of the
+    /// encoded header block.
+    pub fn header_list_size(&self) -> usize {
+        self.header_block.calculate_header_list_size()
+    }
+
+    /// Encodes the frame, followed by the CONTINUATION frames that do not fit
+    /// in the buffer.
+    ///
+    /// Unless the length of the padding was set with `set_pad_len`, the frame
+    /// is padded with the number of octets returned by `padding`, which is
+    /// given the length of the payload. Zero means no padding.
+    pub fn encode<F>(
+        self,
+        encoder: &mut hpack::raw::Encoder,
+        dst: &mut BytesMut,
+        padding: F,
+    ) -> Option<Continuation>
+    where
+        F: FnOnce(usize) -> u8,
+    {
         // At this point, the `is_end_headers` flag should always be set
         debug_assert!(self.flags.is_end_headers());
 
         // Get the HEADERS frame head
         let head = self.head();
+        let head_pos = dst.len();
+        let stream_dep = self.stream_dep;
+        let pad_len = self.pad_len;
 
-        self.header_block.into_encoding()
-            .encode(&head, encoder, dst, |_| {
-            })
+        let continuation = self.header_block.into_encoding()
+            .encode(&head, encoder, dst, |dst| {
+                if let Some(stream_dep) = stream_dep {
+                    stream_dep.encode(dst);
+                }
+            });
+
+        let pad_len = pad_len.or_else(|| {
+            match padding(dst.len() - head_pos - HEADER_LEN) {
+                0 => None,
+                pad_len => Some(pad_len),
+            }
+        });
+
+        if let Some(pad_len) = pad_len {
+            util::pad_frame(dst, head_pos, pad_len);
+        }
+
+        continuation
     }
 
     fn head(&self) -> Head {
@@ -266,6 +369,44 @@ impl Headers {
     }
 }
 
+#[cfg(feature = "serde")]
+frame_serde!(Headers {
+    stream_id: StreamId = stream_id,
+    stream_dependency: Option<StreamDependency> = stream_dependency,
+    end_stream: bool = is_end_stream,
+    pad_len: Option<u8> = pad_len,
+    pseudo: Pseudo = pseudo,
+    fields: Vec<(::std::string::String, Vec<u8>)> = serde_fields,
+} |stream_id, stream_dependency: Option<StreamDependency>, end_stream, pad_len, pseudo, fields| {
+    if stream_id.is_zero() {
+        return Err("HEADERS frame with a zero stream identifier");
+    }
+
+    let mut frame = Headers::new(stream_id, pseudo, serde_header_map(fields)?);
+
+    if let Some(dependency) = stream_dependency {
+        if dependency.dependency_id() == stream_id {
+            return Err("HEADERS frame depending on its own stream");
+        }
+        frame.set_stream_dependency(dependency);
+    }
+    if end_stream {
+        frame.set_end_stream();
+    }
+    if let Some(pad_len) = pad_len {
+        frame.set_pad_len(pad_len);
+    }
+
+    Ok(frame)
+});
+
+#[cfg(feature = "serde")]
+impl Headers {
+    fn serde_fields(&self) -> Vec<(&str, &[u8])> {
+        self.header_block.serde_fields()
+    }
+}
+
 impl<T> From<Headers> for Frame<T> {
     fn from(src: Headers) -> Self {
         Frame::Headers(src)
@@ -278,6 +419,7 @@ impl fmt::Debug for Headers {
             .field("stream_id", &self.stream_id)
             .field("stream_dep", &self.stream_dep)
             .field("flags", &self.flags)
+            .field("pad_len", &self.pad_len)
             // `fields` and `pseudo` purposefully not included
             .finish()
     }
@@ -286,6 +428,25 @@ impl fmt::Debug for Headers {
 // ===== impl PushPromise =====
 
 impl PushPromise {
+    pub fn new(
+        stream_id: StreamId,
+        promised_id: StreamId,
+        pseudo: Pseudo,
+        fields: HeaderMap,
+    ) -> Self {
+        PushPromise {
+            flags: PushPromiseFlag::default(),
+            header_block: HeaderBlock {
+                fields,
+                is_over_size: false,
+                malformed: None,
+                pseudo,
+            },
+            promised_id,
+            stream_id,
+        }
+    }
+
     /// Loads the push promise frame but doesn't actually do HPACK decoding.
     ///
     /// HPACK decoding is done in the `load_hpack` step.
@@ -328,6 +489,7 @@ impl PushPromise {
             header_block: HeaderBlock {
                 fields: HeaderMap::new(),
                 is_over_size: false,
+                malformed: None,
                 pseudo: Pseudo::default(),
             },
             promised_id: promised_id,
@@ -336,8 +498,15 @@ impl PushPromise {
         Ok((frame, src))
     }
 
-    pub fn load_hpack(&mut self, src: &mut BytesMut, max_header_list_size: usize, decoder: &mut hpack::Decoder) -> Result<(), Error> {
-        self.header_block.load(src, max_header_list_size, decoder)
+    pub fn load_hpack(
+        &mut self,
+        src: &mut BytesMut,
+        is_end_headers: bool,
+        max_header_list_size: usize,
+        strict: bool,
+        decoder: &mut hpack::raw::Decoder,
+    ) -> Result<(), Error> {
+        self.header_block.load(src, is_end_headers, max_header_list_size, strict, decoder)
     }
 
     pub fn stream_id(&self) -> StreamId {
@@ -360,7 +529,25 @@ impl PushPromise {
         self.header_block.is_over_size
     }
 
-    pub fn encode(self, encoder: &mut hpack::Encoder, dst: &mut BytesMut) -> Option<Continuation> {
+    pub fn is_malformed(&self) -> bool {
+        self.header_block.malformed.is_some()
+    }
+
+    pub fn fields(&self) -> &HeaderMap {
+        &self.header_block.fields
+    }
+
+    pub fn into_parts(self) -> (Pseudo, HeaderMap) {
+        (self.header_block.pseudo, self.header_block.fields)
+    }
+
+    /// Returns the size of the header list, which bounds the size of the
+    /// encoded header block.
+    pub fn header_list_size(&self) -> usize {
+        self.header_block.calculate_header_list_size()
+    }
+
+    pub fn encode(self, encoder: &mut hpack::raw::Encoder, dst: &mut BytesMut) -> Option<Continuation> {
         use bytes::BufMut;
 
         // At this point, the `is_end_headers` flag should always be set
@@ -382,34 +569,33 @@ impl PushPromise {
 
 #[cfg(feature = "unstable")]
 impl PushPromise {
-    pub fn new(
-        stream_id: StreamId,
-        promised_id: StreamId,
-        pseudo: Pseudo,
-        fields: HeaderMap,
-    ) -> Self {
-        PushPromise {
-            flags: PushPromiseFlag::default(),
-            header_block: HeaderBlock {
-                fields,
-                is_over_size: false,
-                pseudo,
-            },
-            promised_id,
-            stream_id,
-        }
+    pub fn into_fields(self) -> HeaderMap {
+        self.header_block.fields
     }
+}
 
-    pub fn into_parts(self) -> (Pseudo, HeaderMap) {
-        (self.header_block.pseudo, self.header_block.fields)
+#[cfg(feature = "serde")]
+frame_serde!(PushPromise {
+    stream_id: StreamId = stream_id,
+    promised_id: StreamId = promised_id,
+    pseudo: Pseudo = serde_pseudo,
+    fields: Vec<(::std::string::String, Vec<u8>)> = serde_fields,
+} |stream_id, promised_id, pseudo, fields| {
+    if stream_id.is_zero() || promised_id.is_zero() {
+        return Err("PUSH_PROMISE frame with a zero stream identifier");
     }
 
-    pub fn fields(&self) -> &HeaderMap {
-        &self.header_block.fields
+    Ok(PushPromise::new(stream_id, promised_id, pseudo, serde_header_map(fields)?))
+});
+
+#[cfg(feature = "serde")]
+impl PushPromise {
+    fn serde_pseudo(&self) -> &Pseudo {
+        &self.header_block.pseudo
     }
 
-    pub fn into_fields(self) -> HeaderMap {
-        self.header_block.fields
+    fn serde_fields(&self) -> Vec<(&str, &[u8])> {
+        self.header_block.serde_fields()
     }
 }
 
@@ -437,7 +623,7 @@ impl Continuation {
         Head::new(Kind::Continuation, END_HEADERS, self.stream_id)
     }
 
-    pub fn encode(self, encoder: &mut hpack::Encoder, dst: &mut BytesMut) -> Option<Continuation> {
+    pub fn encode(self, encoder: &mut hpack::raw::Encoder, dst: &mut BytesMut) -> Option<Continuation> {
         // Get the CONTINUATION frame head
         let head = self.head();
 
@@ -453,6 +639,10 @@ impl Pseudo {
     pub fn request(method: Method, uri: Uri) -> Self {
         let parts = uri::Parts::from(uri);
 
+        // A CONNECT request to an authority-form URI omits the `:path` pseudo
+        // header (section 8.3 of RFC 7540).
+        let is_connect = method == Method::CONNECT && parts.path_and_query.is_none();
+
         let mut path = parts
             .path_and_query
             .map(|v| v.into())
@@ -466,7 +656,8 @@ impl Pseudo {
             method: Some(method),
             scheme: None,
             authority: None,
-            path: Some(to_string(path)),
+            path: if is_connect { None } else { Some(to_string(path)) },
+            protocol: None,
             status: None,
         };
 
@@ -492,6 +683,7 @@ impl Pseudo {
             scheme: None,
             authority: None,
             path: None,
+            protocol: None,
             status: Some(status),
         }
     }
@@ -503,18 +695,108 @@ impl Pseudo {
     pub fn set_authority(&mut self, authority: String<Bytes>) {
         self.authority = Some(authority);
     }
+
+    /// Returns true if no pseudo-header field is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Pseudo::default()
+    }
 }
 
 fn to_string(src: Bytes) -> String<Bytes> {
     unsafe { String::from_utf8_unchecked(src) }
 }
 
+#[cfg(feature = "serde")]
+frame_serde!(Pseudo {
+    method: Option<::std::string::String> = serde_method,
+    scheme: Option<::std::string::String> = serde_scheme,
+    authority: Option<::std::string::String> = serde_authority,
+    path: Option<::std::string::String> = serde_path,
+    protocol: Option<::std::string::String> = serde_protocol,
+    status: Option<u16> = serde_status,
+} |method: Option<::std::string::String>, scheme, authority, path, protocol, status| {
+    let method = match method {
+        Some(method) => match Method::from_bytes(method.as_bytes()) {
+            Ok(method) => Some(method),
+            Err(_) => return Err("invalid :method pseudo-header field"),
+        },
+        None => None,
+    };
+    let status = match status {
+        Some(status) => match StatusCode::from_u16(status) {
+            Ok(status) => Some(status),
+            Err(_) => return Err("invalid :status pseudo-header field"),
+        },
+        None => None,
+    };
+    let to_string = |value: Option<::std::string::String>| value.map(|v| to_string(v.into()));
+
+    Ok(Pseudo {
+        method,
+        scheme: to_string(scheme),
+        authority: to_string(authority),
+        path: to_string(path),
+        protocol: to_string(protocol),
+        status,
+    })
+});
+
+#[cfg(feature = "serde")]
+impl Pseudo {
+    fn serde_method(&self) -> Option<&str> {
+        self.method.as_ref().map(|v| v.as_str())
+    }
+
+    fn serde_scheme(&self) -> Option<&str> {
+        self.scheme.as_ref().map(|v| v.as_str())
+    }
+
+    fn serde_authority(&self) -> Option<&str> {
+        self.authority.as_ref().map(|v| v.as_str())
+    }
+
+    fn serde_path(&self) -> Option<&str> {
+        self.path.as_ref().map(|v| v.as_str())
+    }
+
+    fn serde_protocol(&self) -> Option<&str> {
+        self.protocol.as_ref().map(|v| v.as_str())
+    }
+
+    fn serde_status(&self) -> Option<u16> {
+        self.status.map(|v| v.as_u16())
+    }
+}
+
+/// Builds the header fields of a deserialized frame, given as name and value
+/// pairs in order.
+#[cfg(feature = "serde")]
+fn serde_header_map(
+    fields: Vec<(::std::string::String, Vec<u8>)>,
+) -> Result<HeaderMap, &'static str> {
+    let mut map = HeaderMap::with_capacity(fields.len());
+
+    for (name, value) in fields {
+        let name = match HeaderName::from_bytes(name.as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return Err("invalid header field name"),
+        };
+        let value = match HeaderValue::from_bytes(&value) {
+            Ok(value) => value,
+            Err(_) => return Err("invalid header field value"),
+        };
+        map.append(name, value);
+    }
+
+    Ok(map)
+}
+
 // ===== impl EncodingHeaderBlock =====
 
 impl EncodingHeaderBlock {
     fn encode<F>(mut self,
                  head: &Head,
-                 encoder: &mut hpack::Encoder,
+                 encoder: &mut hpack::raw::Encoder,
                  dst: &mut BytesMut,
                  f: F)
         -> Option<Continuation>
@@ -532,9 +814,9 @@ impl EncodingHeaderBlock {
         f(dst);
 
         // Now, encode the header payload
-        let continuation = match encoder.encode(self.hpack, &mut self.headers, dst) {
-            hpack::Encode::Full => None,
-            hpack::Encode::Partial(state) => Some(Continuation {
+        let continuation = match encoder.encode_partial(self.hpack, &mut self.headers, dst) {
+            hpack::raw::Encode::Full => None,
+            hpack::raw::Encode::Partial(state) => Some(Continuation {
                 stream_id: head.stream_id(),
                 header_block: EncodingHeaderBlock {
                     hpack: Some(state),
@@ -564,10 +846,10 @@ impl EncodingHeaderBlock {
 // ===== impl Iter =====
 
 impl Iterator for Iter {
-    type Item = hpack::Header<Option<HeaderName>>;
+    type Item = hpack::raw::Header<Option<HeaderName>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        use hpack::Header::*;
+        use hpack::raw::Header::*;
 
         if let Some(ref mut pseudo) = self.pseudo {
             if let Some(method) = pseudo.method.take() {
@@ -586,6 +868,10 @@ impl Iterator for Iter {
                 return Some(Path(path));
             }
 
+            if let Some(protocol) = pseudo.protocol.take() {
+                return Some(Protocol(protocol));
+            }
+
             if let Some(status) = pseudo.status.take() {
                 return Some(Status(status));
             }
@@ -633,9 +919,17 @@ impl HeadersFlag {
         self.0 & PADDED == PADDED
     }
 
+    pub fn set_padded(&mut self) {
+        self.0 |= PADDED;
+    }
+
     pub fn is_priority(&self) -> bool {
         self.0 & PRIORITY == PRIORITY
     }
+
+    pub fn set_priority(&mut self) {
+        self.0 |= PRIORITY;
+    }
 }
 
 impl Default for HeadersFlag {
@@ -712,19 +1006,43 @@ impl fmt::Debug for PushPromiseFlag {
 
 
 impl HeaderBlock {
-    fn load(&mut self, src: &mut BytesMut, max_header_list_size: usize, decoder: &mut hpack::Decoder) -> Result<(), Error> {
+    /// Returns the header fields as name and value pairs, in order.
+    #[cfg(feature = "serde")]
+    fn serde_fields(&self) -> Vec<(&str, &[u8])> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_bytes()))
+            .collect()
+    }
+
+    /// Decodes the header fields of a part of the block, `src`. Its last
+    /// representation is left in `src` if it is not complete, unless
+    /// `is_end_headers` is set.
+    ///
+    /// When `strict` is not set, the header fields forbidden by section 8.1.2
+    /// of RFC 7540 (uppercase names, connection-specific fields and `te` with
+    /// a value other than "trailers") are passed through instead of making
+    /// the message malformed. Pseudo-header fields, and the octets of the
+    /// names and values, are always checked.
+    fn load(
+        &mut self,
+        src: &mut BytesMut,
+        is_end_headers: bool,
+        max_header_list_size: usize,
+        strict: bool,
+        decoder: &mut hpack::raw::Decoder,
+    ) -> Result<(), Error> {
         let mut reg = !self.fields.is_empty();
-        let mut malformed = false;
         let mut headers_size = self.calculate_header_list_size();
 
         macro_rules! set_pseudo {
             ($field:ident, $val:expr) => {{
                 if reg {
                     trace!("load_hpack; header malformed -- pseudo not at head of block");
-                    malformed = true;
+                    self.malformed = Some("pseudo-header field after a regular header field");
                 } else if self.pseudo.$field.is_some() {
                     trace!("load_hpack; header malformed -- repeated pseudo");
-                    malformed = true;
+                    self.malformed = Some("repeated pseudo-header field");
                 } else {
                     let __val = $val;
                     headers_size += decoded_header_size(stringify!($ident).len() + 1, __val.as_str().len());
@@ -744,8 +1062,8 @@ impl HeaderBlock {
         // the headers. A malformed header frame is a stream level error, but
         // the hpack state is connection level. In order to maintain correct
         // state for other streams, the hpack decoding process must complete.
-        let res = decoder.decode(&mut cursor, |header| {
-            use hpack::Header::*;
+        let res = decoder.decode_partial(&mut cursor, is_end_headers, |header| {
+            use hpack::raw::Header::*;
 
             match header {
                 Field {
@@ -755,17 +1073,12 @@ impl HeaderBlock {
                     // Connection level header fields are not supported and must
                     // result in a protocol error.
 
-                    if name == header::CONNECTION
-                        || name == header::TRANSFER_ENCODING
-                        || name == header::UPGRADE
-                        || name == "keep-alive"
-                        || name == "proxy-connection"
-                    {
+                    if strict && is_connection_header(&name) {
                         trace!("load_hpack; connection level header");
-                        malformed = true;
-                    } else if name == header::TE && value != "trailers" {
+                        self.malformed = Some("connection-specific header field");
+                    } else if strict && name == header::TE && value != "trailers" {
                         trace!("load_hpack; TE header not set to trailers; val={:?}", value);
-                        malformed = true;
+                        self.malformed = Some("te header field other than trailers");
                     } else {
                         reg = true;
 
@@ -782,18 +1095,27 @@ impl HeaderBlock {
                 Method(v) => set_pseudo!(method, v),
                 Scheme(v) => set_pseudo!(scheme, v),
                 Path(v) => set_pseudo!(path, v),
+                Protocol(v) => set_pseudo!(protocol, v),
                 Status(v) => set_pseudo!(status, v),
             }
         });
 
-        if let Err(e) = res {
-            trace!("hpack decoding error; err={:?}", e);
-            return Err(e.into());
+        if decoder.take_uppercase_field_name() && strict {
+            trace!("load_hpack; header malformed -- uppercase header name");
+            self.malformed = Some("uppercase header name");
         }
 
-        if malformed {
-            trace!("malformed message");
-            return Err(Error::MalformedMessage.into());
+        if decoder.take_invalid_field() {
+            trace!("load_hpack; header malformed -- invalid header field");
+            self.malformed = Some("invalid header field");
+        }
+
+        match res {
+            Ok(n) => trace!("load_hpack; decoded={}; remaining={}", n, cursor.get_ref().len()),
+            Err(e) => {
+                trace!("hpack decoding error; err={:?}", e);
+                return Err(e.into());
+            },
         }
 
         Ok(())
@@ -832,6 +1154,7 @@ impl HeaderBlock {
         pseudo_size!(status) +
         pseudo_size!(authority) +
         pseudo_size!(path) +
+        pseudo_size!(protocol) +
         self.fields.iter()
             .map(|(name, value)| decoded_header_size(name.as_str().len(), value.len()))
             .sum::<usize>()
@@ -842,6 +1165,16 @@ fn decoded_header_size(name: usize, value: usize) -> usize {
     name + value + 32
 }
 
+/// Returns true if the header field is connection-specific, which is not
+/// allowed in HTTP/2 (section 8.1.2.2).
+fn is_connection_header(name: &HeaderName) -> bool {
+    *name == header::CONNECTION
+        || *name == header::TRANSFER_ENCODING
+        || *name == header::UPGRADE
+        || name == "keep-alive"
+        || name == "proxy-connection"
+}
+
 // Stupid hack to make the set_pseudo! macro happy, since all other values
 // have a method `as_str` except for `String<Bytes>`.
 trait AsStr {
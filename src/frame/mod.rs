@@ -26,12 +26,125 @@ macro_rules! unpack_octets_4 {
     );
 }
 
+/// Implements `Serialize` and `Deserialize` for a frame struct, representing
+/// it as a struct with the given fields.
+///
+/// Each field is read through the named accessor. `$build` is called with the
+/// deserialized fields, in order, and either returns the frame or a message
+/// describing why the values are invalid.
+#[cfg(feature = "serde")]
+macro_rules! frame_serde {
+    (
+        $name:ident<$($gen:ident),*> {
+            $($field:ident: $fty:ty = $getter:ident,)+
+        }
+        $build:expr
+    ) => {
+        impl<$($gen: ::serde::Serialize),*> ::serde::Serialize for $name<$($gen),*> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                use serde::ser::SerializeStruct;
+
+                const FIELDS: &'static [&'static str] = &[$(stringify!($field)),+];
+
+                let mut state = serializer.serialize_struct(stringify!($name), FIELDS.len())?;
+                $(state.serialize_field(stringify!($field), &self.$getter())?;)+
+                state.end()
+            }
+        }
+
+        impl<'de, $($gen: ::serde::Deserialize<'de>),*> ::serde::Deserialize<'de> for $name<$($gen),*> {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                use serde::de::{self, MapAccess, SeqAccess, Visitor};
+                use std::fmt;
+                use std::marker::PhantomData;
+
+                const FIELDS: &'static [&'static str] = &[$(stringify!($field)),+];
+
+                struct FrameVisitor<$($gen),*>(PhantomData<($($gen,)*)>);
+
+                impl<'de, $($gen: ::serde::Deserialize<'de>),*> Visitor<'de> for FrameVisitor<$($gen),*> {
+                    type Value = $name<$($gen),*>;
+
+                    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                        write!(fmt, "struct {}", stringify!($name))
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: SeqAccess<'de>,
+                    {
+                        let mut len = 0;
+                        $(
+                            let $field: $fty = match seq.next_element()? {
+                                Some(val) => val,
+                                None => return Err(de::Error::invalid_length(len, &self)),
+                            };
+                            len += 1;
+                        )+
+                        let _ = len;
+
+                        let build: fn($($fty),+) -> Result<Self::Value, &'static str> = $build;
+                        build($($field),+).map_err(de::Error::custom)
+                    }
+
+                    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: MapAccess<'de>,
+                    {
+                        $(let mut $field: Option<$fty> = None;)+
+
+                        while let Some(key) = map.next_key::<::std::string::String>()? {
+                            match &key[..] {
+                                $(
+                                    stringify!($field) => {
+                                        if $field.is_some() {
+                                            return Err(de::Error::duplicate_field(stringify!($field)));
+                                        }
+                                        $field = Some(map.next_value()?);
+                                    },
+                                )+
+                                _ => {
+                                    map.next_value::<de::IgnoredAny>()?;
+                                },
+                            }
+                        }
+
+                        $(
+                            let $field = match $field {
+                                Some(val) => val,
+                                None => return Err(de::Error::missing_field(stringify!($field))),
+                            };
+                        )+
+
+                        let build: fn($($fty),+) -> Result<Self::Value, &'static str> = $build;
+                        build($($field),+).map_err(de::Error::custom)
+                    }
+                }
+
+                deserializer.deserialize_struct(stringify!($name), FIELDS, FrameVisitor(PhantomData))
+            }
+        }
+    };
+    ($name:ident { $($fields:tt)+ } $build:expr) => {
+        frame_serde!($name<> { $($fields)+ } $build);
+    };
+}
+
+mod alt_svc;
 mod data;
+mod extension;
 mod go_away;
 mod head;
 mod headers;
 mod ping;
 mod priority;
+mod priority_update;
 mod reason;
 mod reset;
 mod settings;
@@ -39,16 +152,24 @@ mod stream_id;
 mod util;
 mod window_update;
 
+pub use self::alt_svc::AltSvc;
 pub use self::data::Data;
-pub use self::go_away::GoAway;
+pub use self::extension::{is_known_kind, Extension};
+pub use self::go_away::{GoAway, DEFAULT_MAX_GO_AWAY_DEBUG_DATA};
 pub use self::head::{Head, Kind};
 pub use self::headers::{Continuation, Headers, Pseudo, PushPromise};
 pub use self::ping::Ping;
 pub use self::priority::{Priority, StreamDependency};
+pub use self::priority_update::{PriorityParams, PriorityUpdate, MAX_URGENCY};
 pub use self::reason::Reason;
 pub use self::reset::Reset;
-pub use self::settings::Settings;
+pub use self::settings::{Settings, SettingsBuilder};
+#[cfg(feature = "unstable")]
+pub use self::settings::{Iter as SettingsIter, Setting};
+#[cfg(feature = "unstable")]
+pub use self::settings::Extensions as SettingsExtensions;
 pub use self::stream_id::{StreamId, StreamIdOverflow};
+pub(crate) use self::util::padding;
 pub use self::window_update::WindowUpdate;
 
 // Re-export some constants
@@ -56,6 +177,7 @@ pub use self::window_update::WindowUpdate;
 pub use self::settings::{
     DEFAULT_INITIAL_WINDOW_SIZE,
     DEFAULT_MAX_FRAME_SIZE,
+    DEFAULT_MAX_SETTINGS_ENTRIES,
     DEFAULT_SETTINGS_HEADER_TABLE_SIZE,
     MAX_INITIAL_WINDOW_SIZE,
     MAX_MAX_FRAME_SIZE,
@@ -70,12 +192,15 @@ pub enum Frame<T = Bytes> {
     Data(Data<T>),
     Headers(Headers),
     Priority(Priority),
+    PriorityUpdate(PriorityUpdate),
     PushPromise(PushPromise),
     Settings(Settings),
     Ping(Ping),
     GoAway(GoAway),
     WindowUpdate(WindowUpdate),
     Reset(Reset),
+    AltSvc(AltSvc),
+    Extension(Extension),
 }
 
 impl<T> Frame<T> {
@@ -89,12 +214,15 @@ impl<T> Frame<T> {
             Data(frame) => frame.map(f).into(),
             Headers(frame) => frame.into(),
             Priority(frame) => frame.into(),
+            PriorityUpdate(frame) => frame.into(),
             PushPromise(frame) => frame.into(),
             Settings(frame) => frame.into(),
             Ping(frame) => frame.into(),
             GoAway(frame) => frame.into(),
             WindowUpdate(frame) => frame.into(),
             Reset(frame) => frame.into(),
+            AltSvc(frame) => frame.into(),
+            Extension(frame) => frame.into(),
         }
     }
 }
@@ -107,12 +235,15 @@ impl<T> fmt::Debug for Frame<T> {
             Data(ref frame) => write!(fmt, "Frame::Data({:?})", frame),
             Headers(ref frame) => write!(fmt, "Frame::Headers({:?})", frame),
             Priority(ref frame) => write!(fmt, "Frame::Priority({:?})", frame),
+            PriorityUpdate(ref frame) => write!(fmt, "Frame::PriorityUpdate({:?})", frame),
             PushPromise(ref frame) => write!(fmt, "Frame::PushPromise({:?})", frame),
             Settings(ref frame) => write!(fmt, "Frame::Settings({:?})", frame),
             Ping(ref frame) => write!(fmt, "Frame::Ping({:?})", frame),
             GoAway(ref frame) => write!(fmt, "Frame::GoAway({:?})", frame),
             WindowUpdate(ref frame) => write!(fmt, "Frame::WindowUpdate({:?})", frame),
             Reset(ref frame) => write!(fmt, "Frame::Reset({:?})", frame),
+            AltSvc(ref frame) => write!(fmt, "Frame::AltSvc({:?})", frame),
+            Extension(ref frame) => write!(fmt, "Frame::Extension({:?})", frame),
         }
     }
 }
@@ -130,6 +261,10 @@ pub enum Error {
     /// An invalid setting value was provided
     InvalidSettingValue,
 
+    /// A SETTINGS_INITIAL_WINDOW_SIZE value above the maximum flow control
+    /// window size was received.
+    InvalidInitialWindowSize,
+
     /// An invalid window update value
     InvalidWindowUpdateValue,
 
@@ -142,8 +277,9 @@ pub enum Error {
 
     /// An invalid stream identifier was provided.
     ///
-    /// This is returned if a SETTINGS or PING frame is received with a stream
-    /// identifier other than zero.
+    /// This is returned if a SETTINGS, PING or PRIORITY_UPDATE frame is
+    /// received with a stream identifier other than zero, or if a
+    /// PRIORITY_UPDATE frame prioritizes the stream zero.
     InvalidStreamId,
 
     /// A request or response is malformed.
@@ -151,10 +287,246 @@ pub enum Error {
 
     /// An invalid stream dependency ID was provided
     ///
-    /// This is returned if a HEADERS or PRIORITY frame is received with an
-    /// invalid stream identifier.
+    /// This is returned if a PRIORITY frame is received with an invalid
+    /// stream identifier. HEADERS frames are validated once the header block
+    /// is decoded.
     InvalidDependencyId,
 
+    /// A SETTINGS frame contained more parameters than allowed.
+    TooManySettings,
+
+    /// An opaque setting was given the identifier of a setting defined by
+    /// the specification.
+    InvalidSettingId,
+
+    /// Flag bits that are not defined for the frame type were set.
+    ///
+    /// This is only returned by `Head::check_flags`; frames are otherwise
+    /// loaded leniently and unknown flags are ignored (4.1.).
+    UnexpectedFlags,
+
     /// Failed to perform HPACK decoding
-    Hpack(hpack::DecoderError),
+    Hpack(hpack::raw::DecoderError),
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    extern crate serde_json;
+
+    use super::*;
+
+    use serde::{Deserialize, Serialize};
+
+    use std::fmt::Debug;
+
+    fn round_trip<T>(frame: T, json: &str)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + Debug + PartialEq,
+    {
+        assert_eq!(serde_json::to_string(&frame).unwrap(), json);
+        assert_eq!(serde_json::from_str::<T>(json).unwrap(), frame);
+    }
+
+    #[test]
+    fn serde_settings() {
+        let mut settings = Settings::default();
+        settings.set_initial_window_size(Some(65_535));
+        round_trip(
+            settings,
+            r#"{"flags":0,"values":{"SETTINGS_INITIAL_WINDOW_SIZE":65535}}"#,
+        );
+
+        round_trip(Settings::ack(), r#"{"flags":1,"values":{}}"#);
+
+        // Unknown settings use the numeric identifier
+        let head = Head::new(Kind::Settings, 0, StreamId::zero());
+        let settings = Settings::load(head, &[0xf0, 0, 0, 0, 0, 1]).unwrap();
+        round_trip(settings, r#"{"flags":0,"values":{"61440":1}}"#);
+    }
+
+    #[test]
+    fn serde_setting() {
+        round_trip(
+            settings::Setting::MaxFrameSize(16_384),
+            r#"{"SETTINGS_MAX_FRAME_SIZE":16384}"#,
+        );
+
+        round_trip(settings::Setting::Opaque(0xf000, 1), r#"{"61440":1}"#);
+    }
+
+    #[test]
+    fn serde_data() {
+        let mut data = Data::new(StreamId::from(1), b"hello".to_vec());
+        data.set_end_stream(true);
+        round_trip(
+            data,
+            r#"{"stream_id":1,"end_stream":true,"data":[104,101,108,108,111]}"#,
+        );
+
+        let json = r#"{"stream_id":0,"end_stream":false,"data":[]}"#;
+        assert!(serde_json::from_str::<Data<Vec<u8>>>(json).is_err());
+    }
+
+    #[test]
+    fn serde_headers() {
+        let mut fields = ::http::HeaderMap::new();
+        fields.append("cookie", "a=1".parse().unwrap());
+        fields.append("accept", "*/*".parse().unwrap());
+        fields.append("cookie", "b=2".parse().unwrap());
+
+        let uri = "https://example.com/index.html".parse().unwrap();
+        let pseudo = Pseudo::request(::http::Method::GET, uri);
+        let mut headers = Headers::new(StreamId::from(1), pseudo, fields);
+        headers.set_end_stream();
+        round_trip(
+            headers,
+            concat!(
+                r#"{"stream_id":1,"stream_dependency":null,"end_stream":true,"pad_len":null,"#,
+                r#""pseudo":{"method":"GET","scheme":"https","authority":"example.com","#,
+                r#""path":"/index.html","protocol":null,"status":null},"#,
+                r#""fields":[["cookie",[97,61,49]],["cookie",[98,61,50]],["accept",[42,47,42]]]}"#,
+            ),
+        );
+
+        let pseudo = Pseudo::response(::http::StatusCode::NO_CONTENT);
+        let mut headers = Headers::new(StreamId::from(3), pseudo, ::http::HeaderMap::new());
+        headers.set_stream_dependency(StreamDependency::new(StreamId::from(1), 15, false));
+        headers.set_pad_len(4);
+        round_trip(
+            headers,
+            concat!(
+                r#"{"stream_id":3,"#,
+                r#""stream_dependency":{"dependency_id":1,"weight":15,"is_exclusive":false},"#,
+                r#""end_stream":false,"pad_len":4,"#,
+                r#""pseudo":{"method":null,"scheme":null,"authority":null,"#,
+                r#""path":null,"protocol":null,"status":204},"fields":[]}"#,
+            ),
+        );
+
+        let json = concat!(
+            r#"{"stream_id":1,"stream_dependency":null,"end_stream":false,"pad_len":null,"#,
+            r#""pseudo":{"method":null,"scheme":null,"authority":null,"#,
+            r#""path":null,"protocol":null,"status":99},"fields":[]}"#,
+        );
+        assert!(serde_json::from_str::<Headers>(json).is_err());
+
+        let json = concat!(
+            r#"{"stream_id":1,"stream_dependency":null,"end_stream":false,"pad_len":null,"#,
+            r#""pseudo":{"method":null,"scheme":null,"authority":null,"#,
+            r#""path":null,"protocol":null,"status":200},"fields":[["Bad Name",[]]]}"#,
+        );
+        assert!(serde_json::from_str::<Headers>(json).is_err());
+    }
+
+    #[test]
+    fn serde_push_promise() {
+        let mut fields = ::http::HeaderMap::new();
+        fields.insert("accept", "text/css".parse().unwrap());
+
+        let uri = "https://example.com/style.css".parse().unwrap();
+        let pseudo = Pseudo::request(::http::Method::GET, uri);
+        round_trip(
+            PushPromise::new(StreamId::from(1), StreamId::from(2), pseudo, fields),
+            concat!(
+                r#"{"stream_id":1,"promised_id":2,"#,
+                r#""pseudo":{"method":"GET","scheme":"https","authority":"example.com","#,
+                r#""path":"/style.css","protocol":null,"status":null},"#,
+                r#""fields":[["accept",[116,101,120,116,47,99,115,115]]]}"#,
+            ),
+        );
+
+        let json = concat!(
+            r#"{"stream_id":1,"promised_id":0,"#,
+            r#""pseudo":{"method":"GET","scheme":null,"authority":null,"#,
+            r#""path":"/","protocol":null,"status":null},"fields":[]}"#,
+        );
+        assert!(serde_json::from_str::<PushPromise>(json).is_err());
+    }
+
+    #[test]
+    fn serde_ping() {
+        round_trip(
+            Ping::pong([1, 2, 3, 4, 5, 6, 7, 8]),
+            r#"{"flags":1,"payload":[1,2,3,4,5,6,7,8]}"#,
+        );
+    }
+
+    #[test]
+    fn serde_go_away() {
+        round_trip(
+            GoAway::new(StreamId::from(7), Reason::ENHANCE_YOUR_CALM),
+            r#"{"last_stream_id":7,"error_code":11,"debug_data":[],"flags":0}"#,
+        );
+        round_trip(
+            GoAway::with_debug_data(StreamId::from(7), Reason::NO_ERROR, "bye".into()),
+            r#"{"last_stream_id":7,"error_code":0,"debug_data":[98,121,101],"flags":0}"#,
+        );
+    }
+
+    #[test]
+    fn serde_reset() {
+        round_trip(
+            Reset::new(StreamId::from(3), Reason::CANCEL),
+            r#"{"stream_id":3,"error_code":8}"#,
+        );
+    }
+
+    #[test]
+    fn serde_window_update() {
+        round_trip(
+            WindowUpdate::new(StreamId::zero(), 1024),
+            r#"{"stream_id":0,"size_increment":1024}"#,
+        );
+
+        let json = r#"{"stream_id":0,"size_increment":2147483648}"#;
+        assert!(serde_json::from_str::<WindowUpdate>(json).is_err());
+    }
+
+    #[test]
+    fn serde_priority() {
+        let head = Head::new(Kind::Priority, 0, StreamId::from(3));
+        let priority = Priority::load(head, &[0x80, 0, 0, 1, 15]).unwrap();
+        round_trip(
+            priority,
+            r#"{"stream_id":3,"dependency":{"dependency_id":1,"weight":15,"is_exclusive":true}}"#,
+        );
+
+        let json = r#"{"stream_id":3,"dependency":{"dependency_id":3,"weight":15,"is_exclusive":true}}"#;
+        assert!(serde_json::from_str::<Priority>(json).is_err());
+    }
+
+    #[test]
+    fn serde_priority_update() {
+        let head = Head::new(Kind::PriorityUpdate, 0, StreamId::zero());
+        let update = PriorityUpdate::load(head, &[0, 0, 0, 1, b'u', b'=', b'0']).unwrap();
+        round_trip(update, r#"{"prioritized_stream_id":1,"field_value":[117,61,48]}"#);
+
+        let json = r#"{"prioritized_stream_id":0,"field_value":[]}"#;
+        assert!(serde_json::from_str::<PriorityUpdate>(json).is_err());
+    }
+
+    #[test]
+    fn serde_alt_svc() {
+        round_trip(
+            AltSvc::new(StreamId::from(1), "".into(), "clear".into()),
+            r#"{"stream_id":1,"origin":[],"field_value":[99,108,101,97,114]}"#,
+        );
+    }
+
+    #[test]
+    fn serde_extension() {
+        round_trip(
+            Extension::new(0xf0, 0x8, StreamId::zero(), "hi".into()),
+            r#"{"kind":240,"flags":8,"stream_id":0,"payload":[104,105]}"#,
+        );
+
+        let json = r#"{"kind":1,"flags":0,"stream_id":0,"payload":[]}"#;
+        assert!(serde_json::from_str::<Extension>(json).is_err());
+    }
+
+    #[test]
+    fn serde_stream_id() {
+        round_trip(StreamId::MAX, "2147483647");
+        assert!(serde_json::from_str::<StreamId>("2147483648").is_err());
+    }
 }
@@ -7,13 +7,14 @@ pub type Payload = [u8; 8];
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Ping {
-    ack: bool,
+    flags: u8,
     payload: Payload,
 }
 
 // This was just 8 randomly generated bytes. We use something besides just
 // zeroes to distinguish this specific PING from any other.
 const SHUTDOWN_PAYLOAD: Payload = [0x0b, 0x7b, 0xa2, 0xf0, 0x8b, 0x9b, 0xfe, 0x54];
+const USER_PAYLOAD: Payload = [0x3b, 0x7c, 0xdb, 0x7a, 0x0b, 0x87, 0x16, 0xb4];
 
 impl Ping {
 
@@ -23,22 +24,35 @@ impl Ping {
     #[cfg(not(feature = "unstable"))]
     pub(crate) const SHUTDOWN: Payload = SHUTDOWN_PAYLOAD;
 
+    #[cfg(feature = "unstable")]
+    pub const USER: Payload = USER_PAYLOAD;
+
+    #[cfg(not(feature = "unstable"))]
+    pub(crate) const USER: Payload = USER_PAYLOAD;
+
     pub fn new(payload: Payload) -> Ping {
         Ping {
-            ack: false,
+            flags: 0,
             payload,
         }
     }
 
     pub fn pong(payload: Payload) -> Ping {
         Ping {
-            ack: true,
+            flags: ACK_FLAG,
             payload,
         }
     }
 
     pub fn is_ack(&self) -> bool {
-        self.ack
+        self.flags & ACK_FLAG != 0
+    }
+
+    /// Returns the raw flag byte, including any unknown bits the frame was
+    /// received with.
+    #[cfg(any(feature = "unstable", feature = "serde"))]
+    pub fn flags(&self) -> u8 {
+        self.flags
     }
 
     pub fn payload(&self) -> &Payload {
@@ -75,19 +89,17 @@ impl Ping {
         // ACK (0x1): When set, bit 0 indicates that this PING frame is a PING
         //    response. An endpoint MUST set this flag in PING responses. An
         //    endpoint MUST NOT respond to PING frames containing this flag.
-        let ack = head.flag() & ACK_FLAG != 0;
-
         Ok(Ping {
-            ack,
+            flags: head.flag(),
             payload,
         })
     }
 
     pub fn encode<B: BufMut>(&self, dst: &mut B) {
         let sz = self.payload.len();
-        trace!("encoding PING; ack={} len={}", self.ack, sz);
+        trace!("encoding PING; ack={} len={}", self.is_ack(), sz);
 
-        let flags = if self.ack { ACK_FLAG } else { 0 };
+        let flags = self.flags & ACK_FLAG;
         let head = Head::new(Kind::Ping, flags, StreamId::zero());
 
         head.encode(sz, dst);
@@ -100,3 +112,9 @@ impl<T> From<Ping> for Frame<T> {
         Frame::Ping(src)
     }
 }
+
+#[cfg(feature = "serde")]
+frame_serde!(Ping {
+    flags: u8 = flags,
+    payload: Payload = payload,
+} |flags, payload| Ok(Ping { flags, payload }));
@@ -1,12 +1,14 @@
 use frame::*;
 
+use bytes::BufMut;
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct Priority {
     stream_id: StreamId,
     dependency: StreamDependency,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct StreamDependency {
     /// The ID of the stream dependency target
     dependency_id: StreamId,
@@ -21,6 +23,13 @@ pub struct StreamDependency {
 }
 
 impl Priority {
+    pub fn new(stream_id: StreamId, dependency: StreamDependency) -> Self {
+        Priority {
+            stream_id,
+            dependency,
+        }
+    }
+
     pub fn load(head: Head, payload: &[u8]) -> Result<Self, Error> {
         let dependency = StreamDependency::load(payload)?;
 
@@ -33,6 +42,21 @@ impl Priority {
             dependency: dependency,
         })
     }
+
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    pub fn dependency(&self) -> &StreamDependency {
+        &self.dependency
+    }
+
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        trace!("encoding PRIORITY; id={:?} dep={:?}", self.stream_id, self.dependency);
+        let head = Head::new(Kind::Priority, 0, self.stream_id);
+        head.encode(5, dst);
+        self.dependency.encode(dst);
+    }
 }
 
 impl<B> From<Priority> for Frame<B> {
@@ -69,4 +93,49 @@ impl StreamDependency {
     pub fn dependency_id(&self) -> StreamId {
         self.dependency_id
     }
+
+    pub fn weight(&self) -> u8 {
+        self.weight
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.is_exclusive
+    }
+
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        let mut dependency_id: u32 = self.dependency_id.into();
+
+        if self.is_exclusive {
+            dependency_id |= 1 << 31;
+        }
+
+        dst.put_u32_be(dependency_id);
+        dst.put_u8(self.weight);
+    }
 }
+
+// ===== impl Serialize / Deserialize =====
+
+#[cfg(feature = "serde")]
+frame_serde!(Priority {
+    stream_id: StreamId = stream_id,
+    dependency: StreamDependency = dependency,
+} |stream_id, dependency: StreamDependency| {
+    if stream_id.is_zero() || dependency.dependency_id == stream_id {
+        return Err("invalid priority stream identifier");
+    }
+
+    Ok(Priority {
+        stream_id,
+        dependency,
+    })
+});
+
+#[cfg(feature = "serde")]
+frame_serde!(StreamDependency {
+    dependency_id: StreamId = dependency_id,
+    weight: u8 = weight,
+    is_exclusive: bool = is_exclusive,
+} |dependency_id, weight, is_exclusive| {
+    Ok(StreamDependency::new(dependency_id, weight, is_exclusive))
+});
@@ -0,0 +1,256 @@
+use frame::{self, Error, Head, Kind, StreamId};
+
+use bytes::{BufMut, Bytes};
+
+/// The urgency of requests without priority parameters (RFC 9218, 4.1.).
+const DEFAULT_URGENCY: u8 = 3;
+
+/// The lowest urgency a request can have. Lower values are more urgent.
+pub const MAX_URGENCY: u8 = 7;
+
+/// A PRIORITY_UPDATE frame, as defined in section 7.1 of RFC 9218.
+///
+/// The frame is sent on stream 0 and carries the priority field value of
+/// another stream.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PriorityUpdate {
+    prioritized_stream_id: StreamId,
+    field_value: Bytes,
+}
+
+/// The priority parameters of a stream, as defined in section 4 of RFC 9218.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PriorityParams {
+    urgency: u8,
+    is_incremental: bool,
+}
+
+impl PriorityUpdate {
+    #[cfg(feature = "unstable")]
+    pub fn new(prioritized_stream_id: StreamId, field_value: Bytes) -> Self {
+        PriorityUpdate {
+            prioritized_stream_id,
+            field_value,
+        }
+    }
+
+    pub fn load(head: Head, payload: &[u8]) -> Result<Self, Error> {
+        debug_assert_eq!(head.kind(), Kind::PriorityUpdate);
+
+        if !head.stream_id().is_zero() {
+            return Err(Error::InvalidStreamId);
+        }
+
+        if payload.len() < 4 {
+            return Err(Error::InvalidPayloadLength);
+        }
+
+        let (prioritized_stream_id, _) = StreamId::parse(&payload[..4]);
+
+        if prioritized_stream_id.is_zero() {
+            return Err(Error::InvalidStreamId);
+        }
+
+        Ok(PriorityUpdate {
+            prioritized_stream_id,
+            field_value: Bytes::from(&payload[4..]),
+        })
+    }
+
+    /// Returns the stream whose priority is updated.
+    pub fn prioritized_stream_id(&self) -> StreamId {
+        self.prioritized_stream_id
+    }
+
+    /// Returns the priority field value, using the syntax of the `priority`
+    /// header field.
+    pub fn field_value(&self) -> &Bytes {
+        &self.field_value
+    }
+
+    pub fn params(&self) -> PriorityParams {
+        PriorityParams::parse(&self.field_value)
+    }
+
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        trace!("encoding PRIORITY_UPDATE; id={:?}", self.prioritized_stream_id);
+        let head = Head::new(Kind::PriorityUpdate, 0, StreamId::zero());
+        head.encode(4 + self.field_value.len(), dst);
+        dst.put_u32_be(self.prioritized_stream_id.into());
+        dst.put_slice(&self.field_value);
+    }
+}
+
+impl<B> From<PriorityUpdate> for frame::Frame<B> {
+    fn from(src: PriorityUpdate) -> Self {
+        frame::Frame::PriorityUpdate(src)
+    }
+}
+
+// ===== impl PriorityParams =====
+
+impl PriorityParams {
+    /// Parses a priority field value, as sent in the `priority` header field
+    /// or in a PRIORITY_UPDATE frame.
+    ///
+    /// The value is a structured field dictionary (RFC 8941). Parameters that
+    /// are missing, unknown or that have an invalid value are ignored, in
+    /// which case the default value is used (section 4).
+    pub fn parse(value: &[u8]) -> Self {
+        let mut params = PriorityParams::default();
+
+        for member in value.split(|&b| b == b',') {
+            // Parameters of the dictionary member itself are not used.
+            let member = trim(member.split(|&b| b == b';').next().unwrap_or(member));
+
+            let (key, val) = match member.iter().position(|&b| b == b'=') {
+                Some(i) => (&member[..i], Some(&member[i + 1..])),
+                None => (member, None),
+            };
+
+            match key {
+                b"u" => {
+                    if let Some(urgency) = val.and_then(parse_integer) {
+                        if urgency <= MAX_URGENCY as u64 {
+                            params.urgency = urgency as u8;
+                        }
+                    }
+                },
+                b"i" => match val {
+                    // A bare key is a boolean true.
+                    None | Some(b"?1") => params.is_incremental = true,
+                    Some(b"?0") => params.is_incremental = false,
+                    Some(_) => {},
+                },
+                _ => {},
+            }
+        }
+
+        params
+    }
+
+    /// Returns the urgency, between 0 and 7. Lower values are more urgent.
+    pub fn urgency(&self) -> u8 {
+        self.urgency
+    }
+
+    /// Returns true if the response can be processed incrementally, in which
+    /// case it can share bandwidth with other responses of the same urgency.
+    pub fn is_incremental(&self) -> bool {
+        self.is_incremental
+    }
+}
+
+impl Default for PriorityParams {
+    fn default() -> Self {
+        PriorityParams {
+            urgency: DEFAULT_URGENCY,
+            is_incremental: false,
+        }
+    }
+}
+
+fn trim(mut src: &[u8]) -> &[u8] {
+    while let Some((&b' ', rest)) = src.split_first() {
+        src = rest;
+    }
+
+    while let Some((&b' ', rest)) = src.split_last() {
+        src = rest;
+    }
+
+    src
+}
+
+/// Parses a non-negative structured field integer, which has at most 15
+/// digits.
+fn parse_integer(src: &[u8]) -> Option<u64> {
+    if src.is_empty() || src.len() > 15 {
+        return None;
+    }
+
+    src.iter().fold(Some(0), |acc, &b| {
+        if b.is_ascii_digit() {
+            acc.map(|n| n * 10 + (b - b'0') as u64)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(feature = "serde")]
+frame_serde!(PriorityUpdate {
+    prioritized_stream_id: StreamId = prioritized_stream_id,
+    field_value: Vec<u8> = field_value_vec,
+} |prioritized_stream_id, field_value: Vec<u8>| {
+    if prioritized_stream_id.is_zero() {
+        return Err("invalid prioritized stream identifier");
+    }
+
+    Ok(PriorityUpdate {
+        prioritized_stream_id,
+        field_value: field_value.into(),
+    })
+});
+
+#[cfg(feature = "serde")]
+impl PriorityUpdate {
+    fn field_value_vec(&self) -> Vec<u8> {
+        self.field_value.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(value: &str) -> (u8, bool) {
+        let params = PriorityParams::parse(value.as_bytes());
+        (params.urgency(), params.is_incremental())
+    }
+
+    #[test]
+    fn parse_priority_params() {
+        assert_eq!(parse(""), (3, false));
+        assert_eq!(parse("u=0"), (0, false));
+        assert_eq!(parse("u=7, i"), (7, true));
+        assert_eq!(parse("i=?1,u=5"), (5, true));
+        assert_eq!(parse("u=1, i=?0"), (1, false));
+        // The last value of a parameter wins
+        assert_eq!(parse("u=1, u=2"), (2, false));
+    }
+
+    #[test]
+    fn parse_priority_params_ignores_invalid_values() {
+        assert_eq!(parse("u=8"), (3, false));
+        assert_eq!(parse("u=-1, i=1"), (3, false));
+        assert_eq!(parse("u=x, i=?2"), (3, false));
+        assert_eq!(parse("u=2;foo=bar, i;baz"), (2, true));
+        assert_eq!(parse("foo=1, u=4"), (4, false));
+    }
+
+    #[test]
+    fn load_priority_update() {
+        let head = Head::new(Kind::PriorityUpdate, 0, StreamId::zero());
+        let frame = PriorityUpdate::load(head, &[0x80, 0, 0, 5, b'u', b'=', b'1']).unwrap();
+
+        // The reserved bit is ignored
+        assert_eq!(frame.prioritized_stream_id(), StreamId::from(5));
+        assert_eq!(frame.params().urgency(), 1);
+        assert!(!frame.params().is_incremental());
+
+        let mut buf = vec![];
+        frame.encode(&mut buf);
+        assert_eq!(buf, [0, 0, 7, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 5, b'u', b'=', b'1']);
+    }
+
+    #[test]
+    fn load_invalid_priority_update() {
+        let head = Head::new(Kind::PriorityUpdate, 0, StreamId::from(1));
+        assert_eq!(PriorityUpdate::load(head, &[0, 0, 0, 5]), Err(Error::InvalidStreamId));
+
+        let head = Head::new(Kind::PriorityUpdate, 0, StreamId::zero());
+        assert_eq!(PriorityUpdate::load(head, &[0, 0, 0, 0]), Err(Error::InvalidStreamId));
+        assert_eq!(PriorityUpdate::load(head, &[0, 0, 5]), Err(Error::InvalidPayloadLength));
+    }
+}
@@ -54,3 +54,9 @@ impl<B> From<Reset> for frame::Frame<B> {
         frame::Frame::Reset(src)
     }
 }
+
+#[cfg(feature = "serde")]
+frame_serde!(Reset {
+    stream_id: StreamId = stream_id,
+    error_code: Reason = reason,
+} |stream_id, error_code| Ok(Reset::new(stream_id, error_code)));
@@ -1,23 +1,38 @@
 use bytes::{BufMut, BytesMut};
 use frame::{Error, Frame, FrameSize, Head, Kind, StreamId};
 
-#[derive(Debug, Clone, Default, Eq, PartialEq)]
+use std::fmt;
+
+#[derive(Debug, Clone, Default)]
 pub struct Settings {
     flags: SettingsFlags,
-    // Fields
-    header_table_size: Option<u32>,
-    enable_push: Option<u32>,
-    max_concurrent_streams: Option<u32>,
-    initial_window_size: Option<u32>,
-    max_frame_size: Option<u32>,
-    max_header_list_size: Option<u32>,
+    // Setting values, kept in the order they were first set so that encoding
+    // reproduces it. Each identifier appears at most once. Unknown identifiers
+    // are kept here too and encoded as-is, which is how extension settings
+    // are sent.
+    values: Vec<(u16, u32)>,
+}
+
+/// Builds a `Settings` frame, validating each value against the bounds
+/// defined in section 6.5.2.
+///
+/// Unlike the `set_*` methods on `Settings`, invalid values are reported as
+/// an `Error` from `build` rather than causing a panic.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsBuilder {
+    inner: Settings,
+
+    /// The first known identifier given to `opaque`, reported by `build`.
+    invalid_opaque_id: Option<u16>,
 }
 
 /// An enum that lists all valid settings that can be sent in a SETTINGS
 /// frame.
 ///
 /// Each setting has a value that is a 32 bit unsigned integer (6.5.1.).
-#[derive(Debug)]
+/// Settings this implementation does not know are `Opaque`, with their
+/// identifier.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Setting {
     HeaderTableSize(u32),
     EnablePush(u32),
@@ -25,6 +40,26 @@ pub enum Setting {
     InitialWindowSize(u32),
     MaxFrameSize(u32),
     MaxHeaderListSize(u32),
+    EnableConnectProtocol(u32),
+    NoRfc7540Priorities(u32),
+    Opaque(u16, u32),
+}
+
+/// An iterator over the settings present in a `Settings` frame.
+///
+/// Created by `Settings::iter`.
+#[derive(Debug)]
+pub struct Iter<'a> {
+    inner: ::std::slice::Iter<'a, (u16, u32)>,
+}
+
+/// An iterator over the identifiers and values of the unknown (extension)
+/// settings present in a `Settings` frame.
+///
+/// Created by `Settings::extensions`.
+#[derive(Debug)]
+pub struct Extensions<'a> {
+    inner: ::std::slice::Iter<'a, (u16, u32)>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
@@ -33,6 +68,20 @@ pub struct SettingsFlags(u8);
 const ACK: u8 = 0x1;
 const ALL: u8 = ACK;
 
+// Setting identifiers, as defined in section 6.5.2.
+const HEADER_TABLE_SIZE: u16 = 1;
+const ENABLE_PUSH: u16 = 2;
+const MAX_CONCURRENT_STREAMS: u16 = 3;
+const INITIAL_WINDOW_SIZE: u16 = 4;
+const MAX_FRAME_SIZE: u16 = 5;
+const MAX_HEADER_LIST_SIZE: u16 = 6;
+
+// Defined in RFC 8441, section 3.
+const ENABLE_CONNECT_PROTOCOL: u16 = 8;
+
+// Defined in RFC 9218, section 2.1.
+const NO_RFC7540_PRIORITIES: u16 = 9;
+
 /// The default value of SETTINGS_HEADER_TABLE_SIZE
 pub const DEFAULT_SETTINGS_HEADER_TABLE_SIZE: usize = 4_096;
 
@@ -48,6 +97,10 @@ pub const MAX_INITIAL_WINDOW_SIZE: usize = (1 << 31) - 1;
 /// MAX_FRAME_SIZE upper bound
 pub const MAX_MAX_FRAME_SIZE: FrameSize = (1 << 24) - 1;
 
+/// The default maximum number of parameters accepted in a single SETTINGS
+/// frame.
+pub const DEFAULT_MAX_SETTINGS_ENTRIES: usize = 32;
+
 // ===== impl Settings =====
 
 impl Settings {
@@ -58,56 +111,195 @@ impl Settings {
         }
     }
 
+    pub fn builder() -> SettingsBuilder {
+        SettingsBuilder::new()
+    }
+
     pub fn is_ack(&self) -> bool {
         self.flags.is_ack()
     }
 
+    /// Returns the raw flag byte, including any unknown bits the frame was
+    /// received with.
+    pub fn flags(&self) -> u8 {
+        self.flags.bits()
+    }
+
+    /// Returns true if no setting values are present in the frame.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the values of `other` that differ from the ones in `self`.
+    ///
+    /// A value that is missing from `self` is compared against the initial
+    /// value of the setting (6.5.2.), if it has one. Applying the result on top
+    /// of `self` with `merge` gives the same values as applying `other`.
+    pub fn diff(&self, other: &Settings) -> Settings {
+        let mut diff = Settings::default();
+
+        for &(id, val) in &other.values {
+            let current = self.get(id).or_else(|| initial_value(id));
+
+            if current != Some(val) {
+                diff.set(id, Some(val));
+            }
+        }
+
+        diff
+    }
+
+    /// Returns the values to send for the settings in `ids` to go back to
+    /// their initial value (6.5.2.), leaving out the ones that already have
+    /// it.
+    ///
+    /// A SETTINGS frame can't unset a setting that has no initial value, so
+    /// such settings are removed from `self` and left out of the result.
+    pub fn reset(&mut self, ids: &[u16]) -> Settings {
+        let mut diff = Settings::default();
+
+        for &id in ids {
+            match initial_value(id) {
+                Some(val) => {
+                    if self.get(id).map_or(false, |current| current != val) {
+                        diff.set(id, Some(val));
+                    }
+                },
+                None => self.set(id, None),
+            }
+        }
+
+        diff
+    }
+
+    pub fn header_table_size(&self) -> Option<u32> {
+        self.get(HEADER_TABLE_SIZE)
+    }
+
+    pub fn set_header_table_size(&mut self, size: Option<u32>) {
+        self.set(HEADER_TABLE_SIZE, size);
+    }
+
     pub fn initial_window_size(&self) -> Option<u32> {
-        self.initial_window_size
+        self.get(INITIAL_WINDOW_SIZE)
     }
 
     pub fn set_initial_window_size(&mut self, size: Option<u32>) {
-        self.initial_window_size = size;
+        self.set(INITIAL_WINDOW_SIZE, size);
     }
 
     pub fn max_concurrent_streams(&self) -> Option<u32> {
-        self.max_concurrent_streams
+        self.get(MAX_CONCURRENT_STREAMS)
     }
 
     pub fn set_max_concurrent_streams(&mut self, max: Option<u32>) {
-        self.max_concurrent_streams = max;
+        self.set(MAX_CONCURRENT_STREAMS, max);
     }
 
     pub fn max_frame_size(&self) -> Option<u32> {
-        self.max_frame_size
+        self.get(MAX_FRAME_SIZE)
     }
 
     pub fn set_max_frame_size(&mut self, size: Option<u32>) {
         if let Some(val) = size {
             assert!(DEFAULT_MAX_FRAME_SIZE <= val && val <= MAX_MAX_FRAME_SIZE);
         }
-        self.max_frame_size = size;
+        self.set(MAX_FRAME_SIZE, size);
     }
 
     pub fn max_header_list_size(&self) -> Option<u32> {
-        self.max_header_list_size
+        self.get(MAX_HEADER_LIST_SIZE)
     }
 
     pub fn set_max_header_list_size(&mut self, size: Option<u32>) {
-        self.max_header_list_size = size;
+        self.set(MAX_HEADER_LIST_SIZE, size);
+    }
+
+    pub fn enable_push(&self) -> Option<u32> {
+        self.get(ENABLE_PUSH)
     }
 
     pub fn is_push_enabled(&self) -> bool {
-        self.enable_push.unwrap_or(1) != 0
+        self.get(ENABLE_PUSH).unwrap_or(1) != 0
     }
 
     pub fn set_enable_push(&mut self, enable: bool) {
-        self.enable_push = Some(enable as u32);
+        self.set(ENABLE_PUSH, Some(enable as u32));
+    }
+
+    pub fn enable_connect_protocol(&self) -> Option<u32> {
+        self.get(ENABLE_CONNECT_PROTOCOL)
+    }
+
+    pub fn set_enable_connect_protocol(&mut self, val: Option<u32>) {
+        self.set(ENABLE_CONNECT_PROTOCOL, val);
+    }
+
+    /// Returns true if the sender supports the extended CONNECT method
+    /// (RFC 8441).
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.enable_connect_protocol().unwrap_or(0) != 0
+    }
+
+    pub fn no_rfc7540_priorities(&self) -> Option<u32> {
+        self.get(NO_RFC7540_PRIORITIES)
+    }
+
+    pub fn set_no_rfc7540_priorities(&mut self, val: Option<u32>) {
+        self.set(NO_RFC7540_PRIORITIES, val);
+    }
+
+    /// Returns true if the sender does not use the priority signals of RFC
+    /// 7540 (RFC 9218).
+    pub fn is_rfc7540_priorities_disabled(&self) -> bool {
+        self.no_rfc7540_priorities().unwrap_or(0) != 0
+    }
+
+    /// Returns the value of an extension setting, which is any setting not
+    /// defined in section 6.5.2., RFC 8441 or RFC 9218.
+    ///
+    /// Returns `None` for the standard identifiers.
+    pub fn extension(&self, id: u16) -> Option<u32> {
+        if is_known(id) {
+            return None;
+        }
+
+        self.get(id)
+    }
+
+    /// Sets the value of an extension setting.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `id` is one of the standard identifiers, which
+    /// must be set through their dedicated methods.
+    pub fn set_extension(&mut self, id: u16, val: u32) {
+        assert!(!is_known(id), "setting {:#06x} is not an extension", id);
+        self.set(id, Some(val));
+    }
+
+    /// Returns an iterator over the extension settings present in the frame,
+    /// in the order they were set (or received).
+    pub fn extensions<'a>(&'a self) -> Extensions<'a> {
+        Extensions {
+            inner: self.values.iter(),
+        }
     }
 
     pub fn load(head: Head, payload: &[u8]) -> Result<Settings, Error> {
-        use self::Setting::*;
+        Settings::load_with_max_entries(head, payload, DEFAULT_MAX_SETTINGS_ENTRIES)
+    }
 
+    /// Loads a SETTINGS frame, rejecting frames that carry more than
+    /// `max_entries` parameters.
+    ///
+    /// Every 6 byte parameter counts towards the limit, including repeated
+    /// and unknown identifiers.
+    pub fn load_with_max_entries(
+        head: Head,
+        payload: &[u8],
+        max_entries: usize,
+    ) -> Result<Settings, Error> {
         debug_assert_eq!(head.kind(), ::frame::Kind::Settings);
 
         if !head.stream_id().is_zero() {
@@ -124,60 +316,116 @@ impl Settings {
             }
 
             // Return the ACK frame
-            return Ok(Settings::ack());
+            return Ok(Settings {
+                flags: flag,
+                ..Settings::default()
+            });
         }
 
+        Settings::load_parameters(flag, payload, max_entries)
+    }
+
+    /// Loads the payload of a SETTINGS frame received without its frame
+    /// header, as in the `HTTP2-Settings` header field of an HTTP/1.1 upgrade
+    /// request (section 3.2.1 of RFC 7540).
+    pub fn load_payload(payload: &[u8], max_entries: usize) -> Result<Settings, Error> {
+        Settings::load_parameters(SettingsFlags::empty(), payload, max_entries)
+    }
+
+    fn load_parameters(
+        flag: SettingsFlags,
+        payload: &[u8],
+        max_entries: usize,
+    ) -> Result<Settings, Error> {
+        use self::Setting::*;
+
         // Ensure the payload length is correct, each setting is 6 bytes long.
         if payload.len() % 6 != 0 {
             debug!("invalid settings payload length; len={:?}", payload.len());
             return Err(Error::InvalidPayloadAckSettings);
         }
 
-        let mut settings = Settings::default();
+        if payload.len() / 6 > max_entries {
+            debug!(
+                "too many settings; count={}; max={}",
+                payload.len() / 6,
+                max_entries
+            );
+            return Err(Error::TooManySettings);
+        }
+
+        let mut settings = Settings {
+            flags: flag,
+            ..Settings::default()
+        };
         debug_assert!(!settings.flags.is_ack());
 
         for raw in payload.chunks(6) {
             match Setting::load(raw) {
                 Some(HeaderTableSize(val)) => {
-                    settings.header_table_size = Some(val);
+                    settings.set(HEADER_TABLE_SIZE, Some(val));
                 },
                 Some(EnablePush(val)) => match val {
                     0 | 1 => {
-                        settings.enable_push = Some(val);
+                        settings.set(ENABLE_PUSH, Some(val));
                     },
                     _ => {
                         return Err(Error::InvalidSettingValue);
                     },
                 },
                 Some(MaxConcurrentStreams(val)) => {
-                    settings.max_concurrent_streams = Some(val);
+                    settings.set(MAX_CONCURRENT_STREAMS, Some(val));
                 },
                 Some(InitialWindowSize(val)) => if val as usize > MAX_INITIAL_WINDOW_SIZE {
-                    return Err(Error::InvalidSettingValue);
+                    return Err(Error::InvalidInitialWindowSize);
                 } else {
-                    settings.initial_window_size = Some(val);
+                    settings.set(INITIAL_WINDOW_SIZE, Some(val));
                 },
                 Some(MaxFrameSize(val)) => {
                     if val < DEFAULT_MAX_FRAME_SIZE || val > MAX_MAX_FRAME_SIZE {
                         return Err(Error::InvalidSettingValue);
                     } else {
-                        settings.max_frame_size = Some(val);
+                        settings.set(MAX_FRAME_SIZE, Some(val));
                     }
                 },
                 Some(MaxHeaderListSize(val)) => {
-                    settings.max_header_list_size = Some(val);
+                    settings.set(MAX_HEADER_LIST_SIZE, Some(val));
+                },
+                Some(EnableConnectProtocol(val)) => match val {
+                    0 | 1 => {
+                        settings.set(ENABLE_CONNECT_PROTOCOL, Some(val));
+                    },
+                    _ => {
+                        return Err(Error::InvalidSettingValue);
+                    },
+                },
+                Some(NoRfc7540Priorities(val)) => match val {
+                    0 | 1 => {
+                        settings.set(NO_RFC7540_PRIORITIES, Some(val));
+                    },
+                    _ => {
+                        return Err(Error::InvalidSettingValue);
+                    },
+                },
+                // Unsupported settings MUST be ignored (6.5.2), but are kept
+                // around so that they can be inspected.
+                Some(Opaque(id, val)) => {
+                    settings.set(id, Some(val));
+                },
+                // Each chunk is 6 bytes long, since the payload length was
+                // checked above.
+                None => {
+                    return Err(Error::InvalidPayloadAckSettings);
                 },
-                None => {},
             }
         }
 
         Ok(settings)
     }
 
-    fn payload_len(&self) -> usize {
-        let mut len = 0;
-        self.for_each(|_| len += 6);
-        len
+    /// Returns the length of the encoded payload.
+    pub fn payload_len(&self) -> usize {
+        self.values.len() * 6
     }
 
     pub fn encode(&self, dst: &mut BytesMut) {
@@ -188,65 +436,298 @@ impl Settings {
         trace!("encoding SETTINGS; len={}", payload_len);
 
         head.encode(payload_len, dst);
+        self.encode_payload(dst);
+    }
 
-        // Encode the settings
-        self.for_each(|setting| {
-            trace!("encoding setting; val={:?}", setting);
-            setting.encode(dst)
-        });
+    /// Encodes the payload of the frame, without the frame header.
+    ///
+    /// This is the value carried, base64url encoded, by the `HTTP2-Settings`
+    /// header field of an HTTP/1.1 upgrade request.
+    pub fn encode_payload(&self, dst: &mut BytesMut) {
+        // Encode the settings, including extension ones
+        for &(id, val) in &self.values {
+            trace!("encoding setting; id={:#06x}; val={}", id, val);
+            dst.put_u16_be(id);
+            dst.put_u32_be(val);
+        }
     }
 
-    fn for_each<F: FnMut(Setting)>(&self, mut f: F) {
-        use self::Setting::*;
+    /// Returns an iterator over the settings present in the frame, in the
+    /// order they were set (or received).
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter {
+            inner: self.values.iter(),
+        }
+    }
 
-        if let Some(v) = self.header_table_size {
-            f(HeaderTableSize(v));
+    /// Applies every value present in `other` on top of `self`.
+    ///
+    /// This is used to track the cumulative settings of a peer, since each
+    /// SETTINGS frame only carries the values being changed.
+    pub fn merge(&mut self, other: &Settings) {
+        for &(id, val) in &other.values {
+            self.set(id, Some(val));
         }
+    }
+
+    pub(crate) fn get(&self, id: u16) -> Option<u32> {
+        self.values
+            .iter()
+            .find(|&&(k, _)| k == id)
+            .map(|&(_, v)| v)
+    }
 
-        if let Some(v) = self.enable_push {
-            f(EnablePush(v));
+    /// Sets the value for `id`, or removes it if `val` is `None`.
+    ///
+    /// Overriding an existing value keeps its original position, so that a
+    /// later duplicate in a received frame replaces the earlier value as
+    /// required by section 6.5.3.
+    fn set(&mut self, id: u16, val: Option<u32>) {
+        let pos = self.values.iter().position(|&(k, _)| k == id);
+
+        match (pos, val) {
+            (Some(pos), Some(val)) => self.values[pos].1 = val,
+            (Some(pos), None) => {
+                self.values.remove(pos);
+            },
+            (None, Some(val)) => self.values.push((id, val)),
+            (None, None) => {},
         }
+    }
+}
+
+impl<T> From<Settings> for Frame<T> {
+    fn from(src: Settings) -> Frame<T> {
+        Frame::Settings(src)
+    }
+}
+
+impl PartialEq for Settings {
+    fn eq(&self, other: &Settings) -> bool {
+        // The order of settings is not significant (6.5.1.)
+        self.flags == other.flags &&
+            self.values.len() == other.values.len() &&
+            self.values.iter().all(|&(id, val)| other.get(id) == Some(val))
+    }
+}
 
-        if let Some(v) = self.max_concurrent_streams {
-            f(MaxConcurrentStreams(v));
+impl Eq for Settings {}
+
+impl fmt::Display for Settings {
+    /// Formats the frame using the parameter names from section 6.5.2, for
+    /// example `SETTINGS { SETTINGS_INITIAL_WINDOW_SIZE=65535 }`.
+    ///
+    /// Unknown parameters are written with their identifier in hex.
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "SETTINGS {{")?;
+
+        let mut first = true;
+        let mut sep = |fmt: &mut fmt::Formatter| {
+            let res = if first { write!(fmt, " ") } else { write!(fmt, ", ") };
+            first = false;
+            res
+        };
+
+        if self.is_ack() {
+            sep(fmt)?;
+            write!(fmt, "ACK")?;
         }
 
-        if let Some(v) = self.initial_window_size {
-            f(InitialWindowSize(v));
+        for &(id, val) in &self.values {
+            sep(fmt)?;
+
+            match name(id) {
+                Some(name) => write!(fmt, "{}={}", name, val)?,
+                None => write!(fmt, "{:#06x}={}", id, val)?,
+            }
         }
 
-        if let Some(v) = self.max_frame_size {
-            f(MaxFrameSize(v));
+        if first {
+            write!(fmt, "}}")
+        } else {
+            write!(fmt, " }}")
         }
+    }
+}
+
+impl<'a> IntoIterator for &'a Settings {
+    type Item = Setting;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+// ===== impl Iter =====
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Setting;
+
+    fn next(&mut self) -> Option<Setting> {
+        self.inner.next().map(|&(id, val)| Setting::new(id, val))
+    }
+}
+
+// ===== impl Extensions =====
 
-        if let Some(v) = self.max_header_list_size {
-            f(MaxHeaderListSize(v));
+impl<'a> Iterator for Extensions<'a> {
+    type Item = (u16, u32);
+
+    fn next(&mut self) -> Option<(u16, u32)> {
+        // Known settings are skipped.
+        while let Some(&(id, val)) = self.inner.next() {
+            if !is_known(id) {
+                return Some((id, val));
+            }
         }
+
+        None
     }
 }
 
-impl<T> From<Settings> for Frame<T> {
-    fn from(src: Settings) -> Frame<T> {
-        Frame::Settings(src)
+// ===== impl SettingsBuilder =====
+
+impl SettingsBuilder {
+    pub fn new() -> SettingsBuilder {
+        SettingsBuilder::default()
+    }
+
+    pub fn header_table_size(&mut self, val: u32) -> &mut Self {
+        self.inner.set(HEADER_TABLE_SIZE, Some(val));
+        self
+    }
+
+    /// Sets SETTINGS_ENABLE_PUSH. Only `0` and `1` are valid values.
+    pub fn enable_push(&mut self, val: u32) -> &mut Self {
+        self.inner.set(ENABLE_PUSH, Some(val));
+        self
+    }
+
+    pub fn max_concurrent_streams(&mut self, val: u32) -> &mut Self {
+        self.inner.set(MAX_CONCURRENT_STREAMS, Some(val));
+        self
+    }
+
+    /// Sets SETTINGS_INITIAL_WINDOW_SIZE. The value must not exceed
+    /// `MAX_INITIAL_WINDOW_SIZE`.
+    pub fn initial_window_size(&mut self, val: u32) -> &mut Self {
+        self.inner.set(INITIAL_WINDOW_SIZE, Some(val));
+        self
+    }
+
+    /// Sets SETTINGS_MAX_FRAME_SIZE. The value must be between
+    /// `DEFAULT_MAX_FRAME_SIZE` and `MAX_MAX_FRAME_SIZE`, inclusive.
+    pub fn max_frame_size(&mut self, val: u32) -> &mut Self {
+        self.inner.set(MAX_FRAME_SIZE, Some(val));
+        self
+    }
+
+    pub fn max_header_list_size(&mut self, val: u32) -> &mut Self {
+        self.inner.set(MAX_HEADER_LIST_SIZE, Some(val));
+        self
+    }
+
+    /// Sets SETTINGS_ENABLE_CONNECT_PROTOCOL. Only `0` and `1` are valid
+    /// values.
+    pub fn enable_connect_protocol(&mut self, val: u32) -> &mut Self {
+        self.inner.set(ENABLE_CONNECT_PROTOCOL, Some(val));
+        self
+    }
+
+    /// Sets SETTINGS_NO_RFC7540_PRIORITIES. Only `0` and `1` are valid
+    /// values.
+    pub fn no_rfc7540_priorities(&mut self, val: u32) -> &mut Self {
+        self.inner.set(NO_RFC7540_PRIORITIES, Some(val));
+        self
+    }
+
+    /// Sets an opaque (extension) setting, sent as-is.
+    ///
+    /// The identifier must not be one of the settings defined in section
+    /// 6.5.2., RFC 8441 or RFC 9218, which are set through their dedicated
+    /// methods.
+    pub fn opaque(&mut self, id: u16, val: u32) -> &mut Self {
+        if is_known(id) {
+            self.invalid_opaque_id = self.invalid_opaque_id.or(Some(id));
+        } else {
+            self.inner.set(id, Some(val));
+        }
+        self
+    }
+
+    /// Validates the configured values and returns the `Settings` frame.
+    ///
+    /// Settings are encoded in the order they were given to the builder.
+    /// Returns `Error::InvalidSettingValue` if any value is out of bounds, and
+    /// `Error::InvalidSettingId` if an opaque setting collides with a known
+    /// one.
+    pub fn build(&self) -> Result<Settings, Error> {
+        if let Some(id) = self.invalid_opaque_id {
+            debug!("opaque setting collides with a known setting; id={:#06x}", id);
+            return Err(Error::InvalidSettingId);
+        }
+
+        if let Some(val) = self.inner.get(ENABLE_PUSH) {
+            if val > 1 {
+                return Err(Error::InvalidSettingValue);
+            }
+        }
+
+        if let Some(val) = self.inner.get(ENABLE_CONNECT_PROTOCOL) {
+            if val > 1 {
+                return Err(Error::InvalidSettingValue);
+            }
+        }
+
+        if let Some(val) = self.inner.get(NO_RFC7540_PRIORITIES) {
+            if val > 1 {
+                return Err(Error::InvalidSettingValue);
+            }
+        }
+
+        if let Some(val) = self.inner.get(INITIAL_WINDOW_SIZE) {
+            if val as usize > MAX_INITIAL_WINDOW_SIZE {
+                return Err(Error::InvalidSettingValue);
+            }
+        }
+
+        if let Some(val) = self.inner.get(MAX_FRAME_SIZE) {
+            if val < DEFAULT_MAX_FRAME_SIZE || val > MAX_MAX_FRAME_SIZE {
+                return Err(Error::InvalidSettingValue);
+            }
+        }
+
+        Ok(self.inner.clone())
     }
 }
 
 // ===== impl Setting =====
 
 impl Setting {
+    /// Creates a new `Setting`, which is `Opaque` if the identifier is not
+    /// one of the known settings.
+    pub fn new(id: u16, val: u32) -> Setting {
+        Setting::from_id(id, val).unwrap_or(Setting::Opaque(id, val))
+    }
+
     /// Creates a new `Setting` with the correct variant corresponding to the
     /// given setting id, based on the settings IDs defined in section
     /// 6.5.2.
+    ///
+    /// Returns `None` for unknown identifiers.
     pub fn from_id(id: u16, val: u32) -> Option<Setting> {
         use self::Setting::*;
 
         match id {
-            1 => Some(HeaderTableSize(val)),
-            2 => Some(EnablePush(val)),
-            3 => Some(MaxConcurrentStreams(val)),
-            4 => Some(InitialWindowSize(val)),
-            5 => Some(MaxFrameSize(val)),
-            6 => Some(MaxHeaderListSize(val)),
+            HEADER_TABLE_SIZE => Some(HeaderTableSize(val)),
+            ENABLE_PUSH => Some(EnablePush(val)),
+            MAX_CONCURRENT_STREAMS => Some(MaxConcurrentStreams(val)),
+            INITIAL_WINDOW_SIZE => Some(InitialWindowSize(val)),
+            MAX_FRAME_SIZE => Some(MaxFrameSize(val)),
+            MAX_HEADER_LIST_SIZE => Some(MaxHeaderListSize(val)),
+            ENABLE_CONNECT_PROTOCOL => Some(EnableConnectProtocol(val)),
+            NO_RFC7540_PRIORITIES => Some(NoRfc7540Priorities(val)),
             _ => None,
         }
     }
@@ -255,33 +736,104 @@ impl Setting {
     /// contains the raw byte representation of the setting, according to the
     /// "SETTINGS format" defined in section 6.5.1.
     ///
-    /// The `raw` parameter should have length at least 6 bytes, since the
-    /// length of the raw setting is exactly 6 bytes.
-    ///
-    /// # Panics
-    ///
-    /// If given a buffer shorter than 6 bytes, the function will panic.
+    /// Returns `None` if `raw` is shorter than 6 bytes. Any bytes past the
+    /// first 6 are ignored.
     fn load(raw: &[u8]) -> Option<Setting> {
-        let id: u16 = ((raw[0] as u16) << 8) | (raw[1] as u16);
-        let val: u32 = unpack_octets_4!(raw, 2, u32);
-
-        Setting::from_id(id, val)
+        load_parts(raw).map(|(id, val)| Setting::new(id, val))
     }
 
-    fn encode(&self, dst: &mut BytesMut) {
+    /// Returns the identifier and value of the setting.
+    fn parts(&self) -> (u16, u32) {
         use self::Setting::*;
 
-        let (kind, val) = match *self {
-            HeaderTableSize(v) => (1, v),
-            EnablePush(v) => (2, v),
-            MaxConcurrentStreams(v) => (3, v),
-            InitialWindowSize(v) => (4, v),
-            MaxFrameSize(v) => (5, v),
-            MaxHeaderListSize(v) => (6, v),
-        };
+        match *self {
+            HeaderTableSize(v) => (HEADER_TABLE_SIZE, v),
+            EnablePush(v) => (ENABLE_PUSH, v),
+            MaxConcurrentStreams(v) => (MAX_CONCURRENT_STREAMS, v),
+            InitialWindowSize(v) => (INITIAL_WINDOW_SIZE, v),
+            MaxFrameSize(v) => (MAX_FRAME_SIZE, v),
+            MaxHeaderListSize(v) => (MAX_HEADER_LIST_SIZE, v),
+            EnableConnectProtocol(v) => (ENABLE_CONNECT_PROTOCOL, v),
+            NoRfc7540Priorities(v) => (NO_RFC7540_PRIORITIES, v),
+            Opaque(id, v) => (id, v),
+        }
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let (id, val) = self.parts();
 
-        dst.put_u16_be(kind);
-        dst.put_u32_be(val);
+        match name(id) {
+            Some(name) => write!(fmt, "{}={}", name, val),
+            None => write!(fmt, "{:#06x}={}", id, val),
+        }
+    }
+}
+
+/// Parses the identifier and value of a setting from the first 6 bytes of
+/// `raw` (6.5.1.), returning `None` if `raw` is too short.
+fn load_parts(raw: &[u8]) -> Option<(u16, u32)> {
+    if raw.len() < 6 {
+        return None;
+    }
+
+    let id: u16 = ((raw[0] as u16) << 8) | (raw[1] as u16);
+    let val: u32 = unpack_octets_4!(raw, 2, u32);
+
+    Some((id, val))
+}
+
+/// Returns true if `id` is one of the settings this implementation
+/// understands.
+fn is_known(id: u16) -> bool {
+    name(id).is_some()
+}
+
+/// Returns the value a setting has before it is first sent, if the protocol
+/// defines one.
+fn initial_value(id: u16) -> Option<u32> {
+    match id {
+        HEADER_TABLE_SIZE => Some(DEFAULT_SETTINGS_HEADER_TABLE_SIZE as u32),
+        ENABLE_PUSH => Some(1),
+        INITIAL_WINDOW_SIZE => Some(DEFAULT_INITIAL_WINDOW_SIZE),
+        MAX_FRAME_SIZE => Some(DEFAULT_MAX_FRAME_SIZE),
+        ENABLE_CONNECT_PROTOCOL => Some(0),
+        NO_RFC7540_PRIORITIES => Some(0),
+        // MAX_CONCURRENT_STREAMS and MAX_HEADER_LIST_SIZE are unlimited
+        _ => None,
+    }
+}
+
+/// Returns the parameter name for a setting identifier, as used in section
+/// 6.5.2.
+fn name(id: u16) -> Option<&'static str> {
+    match id {
+        HEADER_TABLE_SIZE => Some("SETTINGS_HEADER_TABLE_SIZE"),
+        ENABLE_PUSH => Some("SETTINGS_ENABLE_PUSH"),
+        MAX_CONCURRENT_STREAMS => Some("SETTINGS_MAX_CONCURRENT_STREAMS"),
+        INITIAL_WINDOW_SIZE => Some("SETTINGS_INITIAL_WINDOW_SIZE"),
+        MAX_FRAME_SIZE => Some("SETTINGS_MAX_FRAME_SIZE"),
+        MAX_HEADER_LIST_SIZE => Some("SETTINGS_MAX_HEADER_LIST_SIZE"),
+        ENABLE_CONNECT_PROTOCOL => Some("SETTINGS_ENABLE_CONNECT_PROTOCOL"),
+        NO_RFC7540_PRIORITIES => Some("SETTINGS_NO_RFC7540_PRIORITIES"),
+        _ => None,
+    }
+}
+
+/// The inverse of `name`.
+#[cfg(feature = "serde")]
+fn from_name(name: &str) -> Option<u16> {
+    match name {
+        "SETTINGS_HEADER_TABLE_SIZE" => Some(HEADER_TABLE_SIZE),
+        "SETTINGS_ENABLE_PUSH" => Some(ENABLE_PUSH),
+        "SETTINGS_MAX_CONCURRENT_STREAMS" => Some(MAX_CONCURRENT_STREAMS),
+        "SETTINGS_INITIAL_WINDOW_SIZE" => Some(INITIAL_WINDOW_SIZE),
+        "SETTINGS_MAX_FRAME_SIZE" => Some(MAX_FRAME_SIZE),
+        "SETTINGS_MAX_HEADER_LIST_SIZE" => Some(MAX_HEADER_LIST_SIZE),
+        "SETTINGS_ENABLE_CONNECT_PROTOCOL" => Some(ENABLE_CONNECT_PROTOCOL),
+        "SETTINGS_NO_RFC7540_PRIORITIES" => Some(NO_RFC7540_PRIORITIES),
+        _ => None,
     }
 }
 
@@ -292,8 +844,10 @@ impl SettingsFlags {
         SettingsFlags(0)
     }
 
+    /// Unknown bits are kept so that they can be inspected, but are never
+    /// encoded.
     pub fn load(bits: u8) -> SettingsFlags {
-        SettingsFlags(bits & ALL)
+        SettingsFlags(bits)
     }
 
     pub fn ack() -> SettingsFlags {
@@ -303,10 +857,758 @@ impl SettingsFlags {
     pub fn is_ack(&self) -> bool {
         self.0 & ACK == ACK
     }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
 }
 
 impl From<SettingsFlags> for u8 {
     fn from(src: SettingsFlags) -> u8 {
-        src.0
+        src.0 & ALL
+    }
+}
+
+// ===== impl Serialize / Deserialize =====
+
+#[cfg(feature = "serde")]
+frame_serde!(Settings {
+    flags: u8 = flags,
+    values: Values = values,
+} |flags, Values(values)| {
+    Ok(Settings {
+        flags: SettingsFlags::load(flags),
+        values: values,
+    })
+});
+
+#[cfg(feature = "serde")]
+impl Settings {
+    fn values(&self) -> Values {
+        Values(self.values.clone())
+    }
+}
+
+/// The values of a `Settings` frame, represented as a map from identifier to
+/// value.
+#[cfg(feature = "serde")]
+struct Values(Vec<(u16, u32)>);
+
+/// A setting identifier. Known identifiers are represented by their name, and
+/// unknown ones by the numeric identifier.
+#[cfg(feature = "serde")]
+struct SettingId(u16);
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Setting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let (id, val) = self.parts();
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&SettingId(id), &val)?;
+        map.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Setting {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+
+        struct SettingVisitor;
+
+        impl<'de> Visitor<'de> for SettingVisitor {
+            type Value = Setting;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "a map with a single setting")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Setting, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let (SettingId(id), val) = match map.next_entry()? {
+                    Some(entry) => entry,
+                    None => return Err(de::Error::invalid_length(0, &self)),
+                };
+
+                if map.next_key::<IgnoredAny>()?.is_some() {
+                    return Err(de::Error::custom("expected a single setting"));
+                }
+
+                Ok(Setting::new(id, val))
+            }
+        }
+
+        deserializer.deserialize_map(SettingVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Values {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+
+        for &(id, val) in &self.0 {
+            map.serialize_entry(&SettingId(id), &val)?;
+        }
+
+        map.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Values {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use serde::de::{MapAccess, Visitor};
+
+        struct ValuesVisitor;
+
+        impl<'de> Visitor<'de> for ValuesVisitor {
+            type Value = Values;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "a map of settings")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Values, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut settings = Settings::default();
+
+                while let Some((SettingId(id), val)) = map.next_entry()? {
+                    settings.set(id, Some(val));
+                }
+
+                Ok(Values(settings.values))
+            }
+        }
+
+        deserializer.deserialize_map(ValuesVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for SettingId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        match name(self.0) {
+            Some(name) => serializer.serialize_str(name),
+            None => serializer.serialize_u16(self.0),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for SettingId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use serde::de::{self, Unexpected, Visitor};
+
+        struct IdVisitor;
+
+        impl<'de> Visitor<'de> for IdVisitor {
+            type Value = SettingId;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "a setting name or identifier")
+            }
+
+            fn visit_u64<E: de::Error>(self, val: u64) -> Result<SettingId, E> {
+                if val > u16::max_value() as u64 {
+                    return Err(E::invalid_value(Unexpected::Unsigned(val), &self));
+                }
+
+                Ok(SettingId(val as u16))
+            }
+
+            fn visit_str<E: de::Error>(self, val: &str) -> Result<SettingId, E> {
+                // Map keys are strings in some formats, so numeric identifiers
+                // may arrive as text.
+                from_name(val)
+                    .or_else(|| val.parse().ok())
+                    .map(SettingId)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Str(val), &self))
+            }
+        }
+
+        deserializer.deserialize_any(IdVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate quickcheck;
+
+    use super::*;
+
+    #[test]
+    fn build_empty() {
+        let settings = SettingsBuilder::new().build().unwrap();
+        assert!(settings.is_empty());
+        assert!(!settings.is_ack());
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn build_all_settings() {
+        let settings = Settings::builder()
+            .header_table_size(0)
+            .enable_push(0)
+            .max_concurrent_streams(100)
+            .initial_window_size(1)
+            .max_frame_size(32_768)
+            .max_header_list_size(u32::max_value())
+            .build()
+            .unwrap();
+
+        assert!(!settings.is_empty());
+        assert!(!settings.is_push_enabled());
+        assert_eq!(settings.max_concurrent_streams(), Some(100));
+        assert_eq!(settings.initial_window_size(), Some(1));
+        assert_eq!(settings.max_frame_size(), Some(32_768));
+        assert_eq!(settings.max_header_list_size(), Some(u32::max_value()));
+    }
+
+    #[test]
+    fn build_enable_push_bounds() {
+        assert!(Settings::builder().enable_push(0).build().is_ok());
+        assert!(Settings::builder().enable_push(1).build().is_ok());
+        assert_eq!(
+            Settings::builder().enable_push(2).build(),
+            Err(Error::InvalidSettingValue)
+        );
+    }
+
+    #[test]
+    fn build_initial_window_size_bounds() {
+        let max = MAX_INITIAL_WINDOW_SIZE as u32;
+
+        assert!(Settings::builder().initial_window_size(0).build().is_ok());
+        assert!(Settings::builder().initial_window_size(max).build().is_ok());
+        assert_eq!(
+            Settings::builder().initial_window_size(max + 1).build(),
+            Err(Error::InvalidSettingValue)
+        );
+        assert_eq!(
+            Settings::builder().initial_window_size(u32::max_value()).build(),
+            Err(Error::InvalidSettingValue)
+        );
+    }
+
+    #[test]
+    fn build_max_frame_size_bounds() {
+        let ok = [DEFAULT_MAX_FRAME_SIZE, MAX_MAX_FRAME_SIZE];
+        let err = [0, DEFAULT_MAX_FRAME_SIZE - 1, MAX_MAX_FRAME_SIZE + 1, u32::max_value()];
+
+        for &val in &ok {
+            let settings = Settings::builder().max_frame_size(val).build().unwrap();
+            assert_eq!(settings.max_frame_size(), Some(val));
+        }
+
+        for &val in &err {
+            assert_eq!(
+                Settings::builder().max_frame_size(val).build(),
+                Err(Error::InvalidSettingValue)
+            );
+        }
+    }
+
+    #[test]
+    fn is_empty_ignores_ack_flag() {
+        assert!(Settings::ack().is_empty());
+
+        let mut settings = Settings::default();
+        settings.set_max_header_list_size(Some(0));
+        assert!(!settings.is_empty());
+    }
+
+    #[test]
+    fn iter_yields_settings_in_insertion_order() {
+        let settings = Settings::builder()
+            .max_header_list_size(6)
+            .initial_window_size(4)
+            .header_table_size(1)
+            .enable_push(0)
+            .build()
+            .unwrap();
+
+        let all: Vec<Setting> = settings.iter().collect();
+
+        assert_eq!(all, vec![
+            Setting::MaxHeaderListSize(6),
+            Setting::InitialWindowSize(4),
+            Setting::HeaderTableSize(1),
+            Setting::EnablePush(0),
+        ]);
+
+        let mut n = 0;
+        for setting in &settings {
+            assert_eq!(setting, all[n]);
+            n += 1;
+        }
+        assert_eq!(n, 4);
+    }
+
+    #[test]
+    fn iter_empty() {
+        assert_eq!(Settings::default().iter().next(), None);
+        assert_eq!(Settings::ack().iter().count(), 0);
+    }
+
+    #[test]
+    fn load_keeps_unknown_flags() {
+        let head = Head::new(Kind::Settings, 0x80 | ACK, StreamId::zero());
+        assert_eq!(Err(Error::UnexpectedFlags), head.check_flags());
+
+        let settings = Settings::load(head, &[]).unwrap();
+        assert!(settings.is_ack());
+        assert_eq!(0x81, settings.flags());
+
+        // Unknown bits are not written back out
+        let mut buf = BytesMut::new();
+        settings.encode(&mut buf);
+        assert_eq!(ACK, buf[4]);
+
+        let head = Head::new(Kind::Settings, ACK, StreamId::zero());
+        assert!(head.check_flags().is_ok());
+    }
+
+    fn load(payload: &[u8]) -> Result<Settings, Error> {
+        let head = Head::new(Kind::Settings, 0, StreamId::zero());
+        Settings::load(head, payload)
+    }
+
+    #[test]
+    fn encode_preserves_received_order() {
+        let payload = [
+            0, 4, 0, 0, 0xff, 0xff, // INITIAL_WINDOW_SIZE = 65_535
+            0, 1, 0, 0, 0x10, 0x00, // HEADER_TABLE_SIZE = 4_096
+            0, 6, 0, 0, 0x20, 0x00, // MAX_HEADER_LIST_SIZE = 8_192
+            0, 3, 0, 0, 0x00, 0x64, // MAX_CONCURRENT_STREAMS = 100
+            0, 2, 0, 0, 0x00, 0x00, // ENABLE_PUSH = 0
+        ];
+
+        let settings = load(&payload).unwrap();
+
+        let mut dst = BytesMut::with_capacity(64);
+        settings.encode(&mut dst);
+
+        assert_eq!(&dst[..9], &[0, 0, 30, 4, 0, 0, 0, 0, 0]);
+        assert_eq!(&dst[9..], &payload[..]);
+    }
+
+    #[test]
+    fn later_duplicate_overrides_earlier_value() {
+        let payload = [
+            0, 3, 0, 0, 0, 1, // MAX_CONCURRENT_STREAMS = 1
+            0, 4, 0, 0, 0, 2, // INITIAL_WINDOW_SIZE = 2
+            0, 3, 0, 0, 0, 3, // MAX_CONCURRENT_STREAMS = 3
+        ];
+
+        let settings = load(&payload).unwrap();
+
+        assert_eq!(settings.max_concurrent_streams(), Some(3));
+        assert_eq!(settings.iter().collect::<Vec<_>>(), vec![
+            Setting::MaxConcurrentStreams(3),
+            Setting::InitialWindowSize(2),
+        ]);
+    }
+
+    #[test]
+    fn eq_ignores_order() {
+        let a = Settings::builder()
+            .initial_window_size(1)
+            .max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+            .build()
+            .unwrap();
+        let b = Settings::builder()
+            .max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+            .initial_window_size(1)
+            .build()
+            .unwrap();
+
+        assert_eq!(a, b);
+        assert!(a != Settings::default());
+    }
+
+    #[test]
+    fn load_enable_connect_protocol() {
+        let settings = load(&[0, 8, 0, 0, 0, 1]).unwrap();
+        assert_eq!(settings.enable_connect_protocol(), Some(1));
+        assert!(settings.is_extended_connect_protocol_enabled());
+
+        let settings = load(&[0, 8, 0, 0, 0, 0]).unwrap();
+        assert!(!settings.is_extended_connect_protocol_enabled());
+
+        assert_eq!(load(&[0, 8, 0, 0, 0, 2]), Err(Error::InvalidSettingValue));
+        assert!(!Settings::default().is_extended_connect_protocol_enabled());
+    }
+
+    #[test]
+    fn load_no_rfc7540_priorities() {
+        let settings = load(&[0, 9, 0, 0, 0, 1]).unwrap();
+        assert_eq!(settings.no_rfc7540_priorities(), Some(1));
+        assert!(settings.is_rfc7540_priorities_disabled());
+
+        let settings = load(&[0, 9, 0, 0, 0, 0]).unwrap();
+        assert!(!settings.is_rfc7540_priorities_disabled());
+
+        assert_eq!(load(&[0, 9, 0, 0, 0, 2]), Err(Error::InvalidSettingValue));
+        assert!(!Settings::default().is_rfc7540_priorities_disabled());
+        assert_eq!(
+            Settings::builder().no_rfc7540_priorities(2).build(),
+            Err(Error::InvalidSettingValue)
+        );
+    }
+
+    #[test]
+    fn build_enable_connect_protocol_bounds() {
+        assert!(Settings::builder().enable_connect_protocol(0).build().is_ok());
+        assert!(Settings::builder().enable_connect_protocol(1).build().is_ok());
+        assert_eq!(
+            Settings::builder().enable_connect_protocol(2).build(),
+            Err(Error::InvalidSettingValue)
+        );
+    }
+
+    #[test]
+    fn build_opaque_settings() {
+        let settings = Settings::builder()
+            .initial_window_size(1)
+            .opaque(0xf000, 7)
+            .build()
+            .unwrap();
+
+        assert_eq!(settings.extension(0xf000), Some(7));
+        assert_eq!(settings.initial_window_size(), Some(1));
+
+        // Known identifiers are rejected, without overriding the known value
+        for &id in &[HEADER_TABLE_SIZE, MAX_FRAME_SIZE, NO_RFC7540_PRIORITIES] {
+            let mut builder = Settings::builder();
+            builder.initial_window_size(1).opaque(id, 1);
+
+            assert_eq!(builder.build(), Err(Error::InvalidSettingId));
+            assert_eq!(builder.inner.get(id), None);
+        }
+
+        assert_eq!(
+            Settings::builder().opaque(INITIAL_WINDOW_SIZE, 2).build(),
+            Err(Error::InvalidSettingId)
+        );
+    }
+
+    #[test]
+    fn load_max_entries() {
+        let head = Head::new(Kind::Settings, 0, StreamId::zero());
+
+        // Repeated parameters count towards the limit too
+        let payload: Vec<u8> = (0..4)
+            .flat_map(|_| vec![0, 3, 0, 0, 0, 100])
+            .collect();
+
+        assert!(Settings::load_with_max_entries(head, &payload, 4).is_ok());
+        assert_eq!(
+            Settings::load_with_max_entries(head, &payload, 3),
+            Err(Error::TooManySettings)
+        );
+    }
+
+    #[test]
+    fn load_default_max_entries() {
+        // Unknown identifiers are counted as well
+        let at_limit: Vec<u8> = (0..DEFAULT_MAX_SETTINGS_ENTRIES)
+            .flat_map(|_| vec![0, 0xff, 0, 0, 0, 0])
+            .collect();
+
+        let mut over_limit = at_limit.clone();
+        over_limit.extend_from_slice(&[0, 0xff, 0, 0, 0, 0]);
+
+        assert_eq!(load(&at_limit).unwrap().get(0xff), Some(0));
+        assert_eq!(load(&over_limit), Err(Error::TooManySettings));
+    }
+
+    #[test]
+    fn load_payload_without_head() {
+        let settings = Settings::builder()
+            .max_concurrent_streams(100)
+            .initial_window_size(1)
+            .build()
+            .unwrap();
+
+        let mut payload = BytesMut::with_capacity(settings.payload_len());
+        settings.encode_payload(&mut payload);
+
+        assert_eq!(Settings::load_payload(&payload, 2), Ok(settings));
+        assert_eq!(Settings::load_payload(&payload, 1), Err(Error::TooManySettings));
+        assert_eq!(Settings::load_payload(&[], 1), Ok(Settings::default()));
+        assert_eq!(
+            Settings::load_payload(&[0, 4, 0, 0, 0], 1),
+            Err(Error::InvalidPayloadAckSettings)
+        );
+    }
+
+    #[test]
+    fn merge_overrides_and_keeps_values() {
+        let mut settings = Settings::builder()
+            .max_concurrent_streams(10)
+            .initial_window_size(100)
+            .build()
+            .unwrap();
+
+        let update = Settings::builder()
+            .max_concurrent_streams(5)
+            .max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+            .build()
+            .unwrap();
+
+        settings.merge(&update);
+
+        assert_eq!(settings.max_concurrent_streams(), Some(5));
+        assert_eq!(settings.initial_window_size(), Some(100));
+        assert_eq!(settings.max_frame_size(), Some(DEFAULT_MAX_FRAME_SIZE));
+        assert!(!settings.is_ack());
+    }
+
+    #[test]
+    fn load_keeps_unknown_settings() {
+        let payload = [
+            0xf0, 0, 0, 0, 0, 1,
+            0, 4, 0, 0, 0, 10,
+        ];
+        let settings = load(&payload).unwrap();
+
+        assert_eq!(settings.iter().collect::<Vec<_>>(), vec![
+            Setting::Opaque(0xf000, 1),
+            Setting::InitialWindowSize(10),
+        ]);
+        assert_eq!(settings.get(0xf000), Some(1));
+
+        assert_eq!(settings.extension(0xf000), Some(1));
+        assert_eq!(settings.extensions().collect::<Vec<_>>(), vec![(0xf000, 1)]);
+
+        // Unknown settings are encoded as-is
+        let mut buf = BytesMut::new();
+        settings.encode(&mut buf);
+        assert_eq!(&buf[..9], &[0, 0, 12, 4, 0, 0, 0, 0, 0]);
+        assert_eq!(&buf[9..], &payload[..]);
+    }
+
+    #[test]
+    fn iter_round_trips_opaque_settings() {
+        let payload = [
+            0, 3, 0, 0, 0, 100,
+            0xf0, 0, 0, 0, 0, 1,
+            0, 0x0a, 0, 0, 0, 2,
+        ];
+        let settings = load(&payload).unwrap();
+
+        let all: Vec<Setting> = settings.iter().collect();
+        assert_eq!(all, vec![
+            Setting::MaxConcurrentStreams(100),
+            Setting::Opaque(0xf000, 1),
+            Setting::Opaque(0x0a, 2),
+        ]);
+        assert_eq!(Setting::Opaque(0xf000, 1).to_string(), "0xf000=1");
+
+        // Rebuilding the frame from the iterated settings gives the same
+        // frame, and the same encoding
+        let mut rebuilt = Settings::default();
+        for setting in all {
+            let (id, val) = setting.parts();
+            rebuilt.set(id, Some(val));
+        }
+        assert_eq!(rebuilt, settings);
+
+        let mut buf = BytesMut::new();
+        rebuilt.encode(&mut buf);
+        assert_eq!(&buf[9..], &payload[..]);
+    }
+
+    #[test]
+    fn set_extension() {
+        let mut settings = Settings::default();
+        settings.set_initial_window_size(Some(10));
+        settings.set_extension(0xf000, 1);
+
+        assert_eq!(settings.extension(0xf000), Some(1));
+        assert_eq!(settings.extension(INITIAL_WINDOW_SIZE), None);
+        assert_eq!(settings.extensions().collect::<Vec<_>>(), vec![(0xf000, 1)]);
+        assert!(!settings.is_empty() && settings.iter().count() == 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_extension_rejects_known_settings() {
+        Settings::default().set_extension(MAX_FRAME_SIZE, DEFAULT_MAX_FRAME_SIZE);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Setting::InitialWindowSize(65_535).to_string(), "SETTINGS_INITIAL_WINDOW_SIZE=65535");
+        assert_eq!(Settings::default().to_string(), "SETTINGS {}");
+        assert_eq!(Settings::ack().to_string(), "SETTINGS { ACK }");
+
+        let settings = load(&[
+            0, 1, 0, 0, 0x10, 0,
+            0, 2, 0, 0, 0, 0,
+            0xf0, 0, 0, 0, 0, 1,
+        ]).unwrap();
+
+        assert_eq!(
+            settings.to_string(),
+            "SETTINGS { SETTINGS_HEADER_TABLE_SIZE=4096, SETTINGS_ENABLE_PUSH=0, 0xf000=1 }"
+        );
+    }
+
+    #[test]
+    fn diff_only_includes_changes() {
+        let current = Settings::builder()
+            .max_concurrent_streams(10)
+            .initial_window_size(100)
+            .build()
+            .unwrap();
+
+        assert!(current.diff(&current).is_empty());
+
+        let update = Settings::builder()
+            .initial_window_size(100)
+            .max_concurrent_streams(20)
+            // Matches the initial value
+            .max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+            .max_header_list_size(1_024)
+            .build()
+            .unwrap();
+
+        let diff = current.diff(&update);
+
+        assert_eq!(diff.iter().collect::<Vec<_>>(), vec![
+            Setting::MaxConcurrentStreams(20),
+            Setting::MaxHeaderListSize(1_024),
+        ]);
+        assert!(!diff.is_ack());
+    }
+
+    #[test]
+    fn reset_sends_initial_values() {
+        let mut current = Settings::builder()
+            .initial_window_size(100)
+            .max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+            .max_concurrent_streams(10)
+            .opaque(0xf000, 1)
+            .build()
+            .unwrap();
+
+        let diff = current.reset(&[
+            INITIAL_WINDOW_SIZE,
+            // Already at its initial value
+            MAX_FRAME_SIZE,
+            // Never set
+            HEADER_TABLE_SIZE,
+            // No initial value
+            MAX_CONCURRENT_STREAMS,
+            0xf000,
+        ]);
+
+        assert_eq!(diff.iter().collect::<Vec<_>>(), vec![
+            Setting::InitialWindowSize(DEFAULT_INITIAL_WINDOW_SIZE),
+        ]);
+        assert_eq!(current.iter().collect::<Vec<_>>(), vec![
+            Setting::InitialWindowSize(100),
+            Setting::MaxFrameSize(DEFAULT_MAX_FRAME_SIZE),
+        ]);
+    }
+
+    #[test]
+    fn load_invalid_lengths_and_values() {
+        let head = Head::new(Kind::Settings, ACK, StreamId::zero());
+        assert_eq!(
+            Settings::load(head, &[0, 4, 0, 0, 0, 10]),
+            Err(Error::InvalidPayloadLength)
+        );
+
+        assert_eq!(load(&[0, 4, 0, 0, 0]), Err(Error::InvalidPayloadAckSettings));
+        assert_eq!(load(&[0, 2, 0, 0, 0, 2]), Err(Error::InvalidSettingValue));
+        assert_eq!(load(&[0, 4, 0x80, 0, 0, 0]), Err(Error::InvalidInitialWindowSize));
+        assert_eq!(load(&[0, 5, 0, 0, 0, 0]), Err(Error::InvalidSettingValue));
+    }
+
+    #[test]
+    fn load_short_setting() {
+        assert_eq!(Setting::load(&[]), None);
+        assert_eq!(Setting::load(&[0, 4, 0, 0, 0]), None);
+        assert_eq!(Setting::load(&[0, 4, 0, 0, 0, 10]), Some(Setting::InitialWindowSize(10)));
+        assert_eq!(Setting::load(&[0xf0, 0, 0, 0, 0, 1]), Some(Setting::Opaque(0xf000, 1)));
+    }
+
+    #[test]
+    fn load_encode_round_trip() {
+        use self::quickcheck::{QuickCheck, TestResult};
+
+        fn prop(flags: u8, entries: Vec<(u8, u32)>) -> TestResult {
+            // Keep identifiers small so that known settings come up often
+            let payload: Vec<u8> = entries
+                .iter()
+                .flat_map(|&(id, val)| {
+                    let mut raw = vec![0, id % 16];
+                    raw.extend_from_slice(&[
+                        (val >> 24) as u8,
+                        (val >> 16) as u8,
+                        (val >> 8) as u8,
+                        val as u8,
+                    ]);
+                    raw
+                })
+                .collect();
+
+            let head = Head::new(Kind::Settings, flags, StreamId::zero());
+
+            let settings = match Settings::load_with_max_entries(head, &payload, usize::max_value()) {
+                Ok(settings) => settings,
+                Err(_) => return TestResult::discard(),
+            };
+
+            let mut buf = BytesMut::with_capacity(9 + payload.len());
+            settings.encode(&mut buf);
+
+            let head = Head::parse(&buf);
+            let reloaded = Settings::load_with_max_entries(head, &buf[9..], usize::max_value())
+                .expect("re-parse encoded frame");
+
+            let mut buf2 = BytesMut::with_capacity(buf.len());
+            reloaded.encode(&mut buf2);
+
+            TestResult::from_bool(
+                buf == buf2 &&
+                    reloaded.is_ack() == settings.is_ack() &&
+                    reloaded.iter().eq(settings.iter()) &&
+                    reloaded.extensions().eq(settings.extensions())
+            )
+        }
+
+        QuickCheck::new()
+            .tests(1_000)
+            .quickcheck(prop as fn(u8, Vec<(u8, u32)>) -> TestResult)
     }
 }
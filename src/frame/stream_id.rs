@@ -93,3 +93,34 @@ impl PartialEq<u32> for StreamId {
         self.0 == *other
     }
 }
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for StreamId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for StreamId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use serde::de::{Error, Unexpected};
+
+        let id = <u32 as ::serde::Deserialize>::deserialize(deserializer)?;
+
+        if id & STREAM_ID_MASK != 0 {
+            return Err(D::Error::invalid_value(
+                Unexpected::Unsigned(id as u64),
+                &"a 31 bit stream identifier",
+            ));
+        }
+
+        Ok(StreamId(id))
+    }
+}
@@ -1,5 +1,12 @@
-use super::Error;
-use bytes::Bytes;
+use super::{Error, HEADER_LEN};
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// The PADDED flag of the frame types that can be padded.
+const PADDED: u8 = 0x8;
+
+const PADDING: [u8; 255] = [0; 255];
 
 /// Strip padding from the given payload.
 ///
@@ -30,7 +37,36 @@ pub fn strip_padding(payload: &mut Bytes) -> Result<u8, Error> {
     }
 
     let _ = payload.split_to(1);
-    let _ = payload.split_off(pad_len);
+    let data_len = payload.len() - pad_len;
+    let _ = payload.split_off(data_len);
 
     Ok(pad_len as u8)
 }
+
+/// Writes `pad_len` octets of padding.
+pub fn put_padding<B: BufMut>(pad_len: u8, dst: &mut B) {
+    dst.put_slice(padding(pad_len));
+}
+
+/// Returns `pad_len` octets of padding.
+pub fn padding(pad_len: u8) -> &'static [u8] {
+    &PADDING[..pad_len as usize]
+}
+
+/// Pads the frame starting at `head_pos`, which must be the last frame
+/// encoded in `dst`.
+///
+/// The pad length octet is inserted at the start of the payload, before any
+/// other field, and the length and flags of the frame are updated.
+pub fn pad_frame(dst: &mut BytesMut, head_pos: usize, pad_len: u8) {
+    let payload_pos = head_pos + HEADER_LEN;
+
+    dst.reserve(pad_len as usize + 1);
+    dst.put_u8(pad_len);
+    dst[payload_pos..].rotate_right(1);
+    put_padding(pad_len, dst);
+
+    let payload_len = (dst.len() - payload_pos) as u64;
+    BigEndian::write_uint(&mut dst[head_pos..head_pos + 3], payload_len, 3);
+    dst[head_pos + 4] |= PADDED;
+}
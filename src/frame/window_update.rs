@@ -60,3 +60,15 @@ impl<B> From<WindowUpdate> for frame::Frame<B> {
         frame::Frame::WindowUpdate(src)
     }
 }
+
+#[cfg(feature = "serde")]
+frame_serde!(WindowUpdate {
+    stream_id: StreamId = stream_id,
+    size_increment: u32 = size_increment,
+} |stream_id, size_increment| {
+    if size_increment & SIZE_INCREMENT_MASK != 0 {
+        return Err("window size increment out of range");
+    }
+
+    Ok(WindowUpdate::new(stream_id, size_increment))
+});
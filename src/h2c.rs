@@ -0,0 +1,270 @@
+//! Starting HTTP/2.0 for "http" URIs by upgrading an HTTP/1.1 connection
+//! (section 3.2 of RFC 7540).
+
+use codec::UserError;
+use frame::Settings;
+
+use bytes::BytesMut;
+use http::{header, HeaderMap, Request, StatusCode};
+
+use std::io;
+
+/// Maximum size of the head of the HTTP/1.1 response to an upgrade request.
+pub const MAX_RESPONSE_HEAD_SIZE: usize = 16 * 1024;
+
+/// The HTTP/1.1 response accepting an upgrade request.
+pub const UPGRADE_RESPONSE: &'static [u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: h2c\r\n\r\n";
+
+/// Encodes the HTTP/1.1 request asking the server to upgrade the connection.
+///
+/// The request has no body. Its `Host` header field is taken from the
+/// authority of the URI, if any.
+pub fn encode_request(request: &Request<()>, settings: &Settings) -> Result<Vec<u8>, UserError> {
+    let uri = request.uri();
+    let authority = uri.authority_part();
+
+    if authority.is_none() && !request.headers().contains_key(header::HOST) {
+        return Err(UserError::MissingUriSchemeAndAuthority);
+    }
+
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    let mut dst = Vec::new();
+    dst.extend_from_slice(request.method().as_str().as_bytes());
+    dst.push(b' ');
+    dst.extend_from_slice(path.as_bytes());
+    dst.extend_from_slice(b" HTTP/1.1\r\n");
+
+    if let Some(authority) = authority {
+        encode_field(&mut dst, b"host", authority.as_str().as_bytes());
+    }
+
+    for (name, value) in request.headers() {
+        let is_replaced = match *name {
+            header::HOST => authority.is_some(),
+            header::CONNECTION | header::UPGRADE => true,
+            _ => name == "http2-settings",
+        };
+
+        if !is_replaced {
+            encode_field(&mut dst, name.as_str().as_bytes(), value.as_bytes());
+        }
+    }
+
+    encode_field(&mut dst, b"connection", b"Upgrade, HTTP2-Settings");
+    encode_field(&mut dst, b"upgrade", b"h2c");
+    encode_field(&mut dst, b"http2-settings", encode_settings(settings).as_bytes());
+    dst.extend_from_slice(b"\r\n");
+
+    Ok(dst)
+}
+
+fn encode_field(dst: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    dst.extend_from_slice(name);
+    dst.extend_from_slice(b": ");
+    dst.extend_from_slice(value);
+    dst.extend_from_slice(b"\r\n");
+}
+
+/// Returns the value of the `HTTP2-Settings` header field: the payload of the
+/// SETTINGS frame, base64url encoded without padding (section 3.2.1).
+pub fn encode_settings(settings: &Settings) -> String {
+    let mut payload = BytesMut::with_capacity(settings.payload_len());
+    settings.encode_payload(&mut payload);
+    base64url_encode(&payload)
+}
+
+const BASE64URL_ALPHABET: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn base64url_encode(src: &[u8]) -> String {
+    let mut dst = String::with_capacity((src.len() * 4 + 2) / 3);
+
+    for chunk in src.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+
+        // Each input byte produces at least one output character.
+        for i in 0..chunk.len() + 1 {
+            dst.push(BASE64URL_ALPHABET[(n >> (18 - 6 * i)) & 0x3f] as char);
+        }
+    }
+
+    dst
+}
+
+/// Decodes the `HTTP2-Settings` header field of an upgrade request.
+///
+/// The request must carry exactly one such field (section 3.2.1).
+pub fn decode_settings(headers: &HeaderMap, max_entries: usize) -> Result<Settings, UserError> {
+    let mut values = headers.get_all("http2-settings").iter();
+
+    let value = match (values.next(), values.next()) {
+        (Some(value), None) => value,
+        _ => return Err(UserError::InvalidUpgradeRequest),
+    };
+
+    let payload = base64url_decode(value.as_bytes()).ok_or(UserError::InvalidUpgradeRequest)?;
+
+    Settings::load_payload(&payload, max_entries).map_err(|e| {
+        debug!("invalid HTTP2-Settings; err={:?}", e);
+        UserError::InvalidUpgradeRequest
+    })
+}
+
+fn base64url_decode(src: &[u8]) -> Option<Vec<u8>> {
+    // The padding is omitted, but some clients send it anyway.
+    let end = src.iter().rposition(|&c| c != b'=').map_or(0, |i| i + 1);
+    let src = &src[..end];
+
+    let mut dst = Vec::with_capacity(src.len() * 3 / 4);
+
+    for chunk in src.chunks(4) {
+        // A single character does not hold a whole byte.
+        if chunk.len() == 1 {
+            return None;
+        }
+
+        let mut n = 0;
+
+        for (i, &c) in chunk.iter().enumerate() {
+            let digit = BASE64URL_ALPHABET.iter().position(|&a| a == c)?;
+            n |= digit << (18 - 6 * i);
+        }
+
+        for i in 0..chunk.len() - 1 {
+            dst.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Some(dst)
+}
+
+/// Returns true once `buf` holds the whole head of an HTTP/1.1 response.
+pub fn is_response_head_complete(buf: &[u8]) -> bool {
+    buf.ends_with(b"\r\n\r\n")
+}
+
+/// Parses the status code of the head of an HTTP/1.1 response.
+pub fn parse_response_status(head: &[u8]) -> Result<StatusCode, io::Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP/1.1 response");
+
+    // status-line = HTTP-version SP status-code SP reason-phrase CRLF
+    //
+    // Some servers omit the SP before an empty reason-phrase.
+    let is_status_line = head.starts_with(b"HTTP/1.1 ") &&
+        head.len() >= 13 &&
+        (head[12] == b' ' || head[12] == b'\r');
+
+    if !is_status_line {
+        return Err(invalid());
+    }
+
+    StatusCode::from_bytes(&head[9..12]).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base64url_encode() {
+        assert_eq!(base64url_encode(b""), "");
+        assert_eq!(base64url_encode(b"f"), "Zg");
+        assert_eq!(base64url_encode(b"fo"), "Zm8");
+        assert_eq!(base64url_encode(b"foo"), "Zm9v");
+        assert_eq!(base64url_encode(b"foob"), "Zm9vYg");
+        assert_eq!(base64url_encode(&[0xfb, 0xff, 0xbf]), "-_-_");
+    }
+
+    #[test]
+    fn test_base64url_decode() {
+        assert_eq!(base64url_decode(b"").unwrap(), b"");
+        assert_eq!(base64url_decode(b"Zg").unwrap(), b"f");
+        assert_eq!(base64url_decode(b"Zg==").unwrap(), b"f");
+        assert_eq!(base64url_decode(b"Zm8").unwrap(), b"fo");
+        assert_eq!(base64url_decode(b"Zm9v").unwrap(), b"foo");
+        assert_eq!(base64url_decode(b"Zm9vYg").unwrap(), b"foob");
+        assert_eq!(base64url_decode(b"-_-_").unwrap(), [0xfb, 0xff, 0xbf]);
+
+        assert!(base64url_decode(b"Zm9vY").is_none());
+        assert!(base64url_decode(b"Zm9v+g").is_none());
+        assert!(base64url_decode(b"Zm=9v").is_none());
+    }
+
+    #[test]
+    fn test_decode_settings() {
+        let mut headers = HeaderMap::new();
+        headers.insert("http2-settings", "AAMAAABk".parse().unwrap());
+
+        let settings = decode_settings(&headers, 10).unwrap();
+        assert_eq!(settings.max_concurrent_streams(), Some(100));
+
+        // Too many parameters
+        assert!(decode_settings(&headers, 0).is_err());
+
+        headers.append("http2-settings", "".parse().unwrap());
+        assert!(decode_settings(&headers, 10).is_err());
+
+        headers.remove("http2-settings");
+        assert!(decode_settings(&headers, 10).is_err());
+
+        // Not a whole number of parameters
+        headers.insert("http2-settings", "AAMAAAB".parse().unwrap());
+        assert!(decode_settings(&headers, 10).is_err());
+    }
+
+    #[test]
+    fn test_encode_request() {
+        let mut settings = Settings::default();
+        settings.set_max_concurrent_streams(Some(100));
+
+        let request = Request::builder()
+            .method("OPTIONS")
+            .uri("http://example.com/a?b")
+            .header("accept", "*/*")
+            .header("connection", "keep-alive")
+            .body(())
+            .unwrap();
+
+        let encoded = encode_request(&request, &settings).unwrap();
+        assert_eq!(
+            String::from_utf8(encoded).unwrap(),
+            "OPTIONS /a?b HTTP/1.1\r\n\
+             host: example.com\r\n\
+             accept: */*\r\n\
+             connection: Upgrade, HTTP2-Settings\r\n\
+             upgrade: h2c\r\n\
+             http2-settings: AAMAAABk\r\n\
+             \r\n"
+        );
+
+        let request = Request::builder().uri("/").body(()).unwrap();
+        match encode_request(&request, &settings) {
+            Err(UserError::MissingUriSchemeAndAuthority) => {},
+            res => panic!("unexpected result; res={:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_parse_response_status() {
+        let head = b"HTTP/1.1 101 Switching Protocols\r\nupgrade: h2c\r\n\r\n";
+        assert!(is_response_head_complete(head));
+        assert_eq!(parse_response_status(head).unwrap(), StatusCode::SWITCHING_PROTOCOLS);
+
+        let head = b"HTTP/1.1 200 OK\r\n\r\n";
+        assert_eq!(parse_response_status(head).unwrap(), StatusCode::OK);
+
+        let head = b"HTTP/1.1 101\r\n\r\n";
+        assert_eq!(parse_response_status(head).unwrap(), StatusCode::SWITCHING_PROTOCOLS);
+
+        assert!(parse_response_status(b"HTTP/1.0 101 Switching Protocols\r\n\r\n").is_err());
+        assert!(parse_response_status(b"HTTP/1.1 1010\r\n\r\n").is_err());
+        assert!(!is_response_head_complete(b"HTTP/1.1 101 Switching Protocols\r\n"));
+    }
+}
@@ -0,0 +1,250 @@
+use super::{decoder, encoder};
+use super::header::{Header, Name};
+
+use bytes::{Bytes, BytesMut};
+
+use std::{error, fmt};
+
+/// Encodes header blocks.
+///
+/// The encoder keeps the dynamic table of the compression context, so one
+/// encoder must be used for all the header blocks sent on a connection, in
+/// order.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate bytes;
+/// # extern crate h2;
+/// use bytes::BytesMut;
+/// use h2::hpack::{Encoder, HeaderField};
+///
+/// # fn main() {
+/// let mut encoder = Encoder::new(4096);
+/// let mut dst = BytesMut::new();
+///
+/// encoder.encode(vec![
+///     HeaderField::new(":method", "GET").unwrap(),
+///     HeaderField::new("accept", "*/*").unwrap(),
+/// ], &mut dst);
+///
+/// assert!(!dst.is_empty());
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Encoder {
+    inner: encoder::Encoder,
+}
+
+/// Decodes header blocks.
+///
+/// The decoder keeps the dynamic table of the compression context, so one
+/// decoder must be used for all the header blocks received on a connection,
+/// in order.
+///
+/// # Examples
+///
+/// ```
+/// use h2::hpack::Decoder;
+///
+/// let mut decoder = Decoder::new(4096);
+///
+/// // An indexed `:method: GET` field.
+/// let fields = decoder.decode(&[0x82]).unwrap();
+///
+/// assert_eq!(fields[0].name(), ":method");
+/// assert_eq!(fields[0].value(), b"GET");
+/// ```
+#[derive(Debug)]
+pub struct Decoder {
+    inner: decoder::Decoder,
+}
+
+/// A header field of a header block.
+///
+/// Pseudo-header fields, with a name starting with `:`, are kept as they are
+/// found in the block, since a header block does not have to make a whole
+/// `http::Request` or `http::Response`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HeaderField {
+    inner: Header,
+}
+
+/// An error found while decoding a header block.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DecoderError {
+    inner: decoder::DecoderError,
+}
+
+// ===== impl Encoder =====
+
+impl Encoder {
+    /// Creates a new `Encoder` with a dynamic table of at most `max_size`
+    /// octets.
+    pub fn new(max_size: usize) -> Encoder {
+        Encoder {
+            inner: encoder::Encoder::new(max_size, 0),
+        }
+    }
+
+    /// Changes the max size of the dynamic table, as the decoding side
+    /// allowed with `SETTINGS_HEADER_TABLE_SIZE`.
+    ///
+    /// The next header block starts with a dynamic table size update.
+    pub fn update_max_size(&mut self, max_size: usize) {
+        self.inner.update_max_size(max_size);
+    }
+
+    /// Encodes a whole header block into `dst`, growing it as needed.
+    ///
+    /// The fields are encoded in order. Values marked sensitive are encoded
+    /// as never indexed literals.
+    pub fn encode<I>(&mut self, fields: I, dst: &mut BytesMut)
+    where
+        I: IntoIterator<Item = HeaderField>,
+    {
+        let headers = fields.into_iter().map(|field| field.inner.into());
+        self.inner.encode(headers, dst);
+    }
+}
+
+// ===== impl Decoder =====
+
+impl Decoder {
+    /// Creates a new `Decoder` allowing a dynamic table of at most
+    /// `max_size` octets.
+    pub fn new(max_size: usize) -> Decoder {
+        Decoder {
+            inner: decoder::Decoder::new(max_size),
+        }
+    }
+
+    /// Changes the max size of the dynamic table the encoding side may use,
+    /// applied from the next header block.
+    pub fn queue_size_update(&mut self, max_size: usize) {
+        self.inner.queue_size_update(max_size);
+    }
+
+    /// Decodes a whole header block.
+    ///
+    /// A block holding a header field not allowed in HTTP/2 fails once it is
+    /// decoded entirely, so that the dynamic table stays in sync with the
+    /// encoding side.
+    pub fn decode(&mut self, src: &[u8]) -> Result<Vec<HeaderField>, DecoderError> {
+        let headers = self.inner.decode(src).map_err(|inner| DecoderError {
+            inner,
+        })?;
+
+        Ok(headers
+            .into_iter()
+            .map(|inner| HeaderField {
+                inner,
+            })
+            .collect())
+    }
+}
+
+// ===== impl HeaderField =====
+
+impl HeaderField {
+    /// Creates a header field, checking the name and the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use h2::hpack::HeaderField;
+    ///
+    /// let field = HeaderField::new(":status", "204").unwrap();
+    /// assert!(field.is_pseudo());
+    ///
+    /// assert!(HeaderField::new(":unknown", "value").is_err());
+    /// ```
+    pub fn new<N, V>(name: N, value: V) -> Result<HeaderField, DecoderError>
+    where
+        N: Into<Bytes>,
+        V: Into<Bytes>,
+    {
+        Header::new(name.into(), value.into())
+            .map(|inner| HeaderField {
+                inner,
+            })
+            .map_err(|inner| DecoderError {
+                inner,
+            })
+    }
+
+    /// Returns the name of the field.
+    pub fn name(&self) -> &str {
+        match self.inner.name() {
+            Name::Field(name) => name.as_str(),
+            Name::Authority => ":authority",
+            Name::Method => ":method",
+            Name::Scheme => ":scheme",
+            Name::Path => ":path",
+            Name::Protocol => ":protocol",
+            Name::Status => ":status",
+        }
+    }
+
+    /// Returns the value of the field.
+    pub fn value(&self) -> &[u8] {
+        self.inner.value_slice()
+    }
+
+    /// Returns true if the field is a pseudo-header field.
+    pub fn is_pseudo(&self) -> bool {
+        match self.inner {
+            Header::Field { .. } => false,
+            _ => true,
+        }
+    }
+
+    /// Returns true if the value is sensitive, and never indexed.
+    pub fn is_sensitive(&self) -> bool {
+        self.inner.is_sensitive()
+    }
+
+    /// Marks the value as sensitive, so that it is encoded as a never
+    /// indexed literal.
+    ///
+    /// Only the values of regular header fields can be marked.
+    pub fn set_sensitive(&mut self) {
+        self.inner.set_sensitive();
+    }
+}
+
+// ===== impl DecoderError =====
+
+impl DecoderError {
+    fn as_str(&self) -> &'static str {
+        use self::decoder::DecoderError::*;
+
+        match self.inner {
+            InvalidRepresentation => "invalid header field representation",
+            InvalidIntegerPrefix => "invalid integer prefix",
+            InvalidTableIndex => "invalid table index",
+            HuffmanEos => "EOS symbol in Huffman encoded string",
+            HuffmanPaddingTooLong => "Huffman padding too long",
+            InvalidHuffmanPadding => "invalid Huffman padding",
+            InvalidUtf8 => "invalid UTF-8 in header field",
+            InvalidStatusCode => "invalid status code",
+            InvalidPseudoheader => "invalid pseudo-header field",
+            InvalidHeaderField => "header field not allowed in HTTP/2",
+            InvalidMaxDynamicSize => "invalid dynamic table size update",
+            IntegerOverflow => "integer overflow",
+            NeedMore(_) => "unexpected end of header block",
+        }
+    }
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+impl error::Error for DecoderError {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
@@ -1,4 +1,5 @@
-use super::{huffman, Header};
+use super::huffman;
+use super::header::Header;
 use frame;
 
 use bytes::{Buf, Bytes, BytesMut};
@@ -10,6 +11,7 @@ use string::String;
 use std::cmp;
 use std::collections::VecDeque;
 use std::io::Cursor;
+use std::mem;
 use std::str::Utf8Error;
 
 /// Decodes headers using HPACK
@@ -20,6 +22,29 @@ pub struct Decoder {
     last_max_update: usize,
     table: Table,
     buffer: BytesMut,
+
+    /// Set when a literal header name containing uppercase characters was
+    /// decoded. The name itself is lowercased.
+    uppercase_field_name: bool,
+
+    /// Set when a header field not allowed in HTTP/2 was decoded. The field
+    /// is not passed to the caller.
+    invalid_field: bool,
+
+    /// The state of the header block being decoded, when only a part of it
+    /// was decoded so far.
+    block: Option<Block>,
+}
+
+/// Tracks the dynamic table size updates allowed in the rest of a header
+/// block.
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// No header has been decoded yet.
+    can_resize: bool,
+
+    /// A size update must be decoded before any header.
+    must_resize: bool,
 }
 
 /// Represents all errors that can be encountered while performing the decoding
@@ -29,10 +54,13 @@ pub enum DecoderError {
     InvalidRepresentation,
     InvalidIntegerPrefix,
     InvalidTableIndex,
-    InvalidHuffmanCode,
+    HuffmanEos,
+    HuffmanPaddingTooLong,
+    InvalidHuffmanPadding,
     InvalidUtf8,
     InvalidStatusCode,
     InvalidPseudoheader,
+    InvalidHeaderField,
     InvalidMaxDynamicSize,
     IntegerOverflow,
     NeedMore(NeedMore),
@@ -136,9 +164,20 @@ enum Representation {
     SizeUpdate,
 }
 
+/// A header field of a header block, or of the dynamic table.
+#[derive(Debug, Clone)]
+enum Entry {
+    Header(Header),
+
+    /// A header field with a name or a value not allowed in HTTP/2 (section
+    /// 8.2.1 of RFC 9113). It makes the message malformed, but still takes
+    /// its place in the dynamic table.
+    Invalid { name: Bytes, value: Bytes },
+}
+
 #[derive(Debug)]
 struct Table {
-    entries: VecDeque<Header>,
+    entries: VecDeque<Entry>,
     size: usize,
     max_size: usize,
 }
@@ -153,11 +192,39 @@ impl Decoder {
             last_max_update: size,
             table: Table::new(size),
             buffer: BytesMut::with_capacity(4096),
+            uppercase_field_name: false,
+            invalid_field: false,
+            block: None,
         }
     }
 
-    /// Queues a potential size update
-    #[allow(dead_code)]
+    /// Returns true if a header name containing uppercase characters was
+    /// decoded since the last call, and clears the flag.
+    ///
+    /// HTTP/2 requires lowercase header names (section 8.1.2 of RFC 7540), but
+    /// such a header is a stream level error, while the decoding state is
+    /// connection level. The decoder lowercases the name and lets the caller
+    /// decide what to do with the header block.
+    pub fn take_uppercase_field_name(&mut self) -> bool {
+        mem::replace(&mut self.uppercase_field_name, false)
+    }
+
+    /// Returns true if a header field not allowed in HTTP/2 was decoded since
+    /// the last call, and clears the flag.
+    ///
+    /// Such a field, with forbidden octets in its name or value or an unknown
+    /// pseudo-header name, makes the message malformed, which is a stream
+    /// level error. The field is skipped, but the rest of the header block is
+    /// still decoded to keep the dynamic table in sync with the peer.
+    pub fn take_invalid_field(&mut self) -> bool {
+        mem::replace(&mut self.invalid_field, false)
+    }
+
+    /// Queues a change of the max size of the dynamic table the peer may use,
+    /// applied from the next header block.
+    ///
+    /// If the new max size is lower than the size of the table, the next
+    /// header block must start with a dynamic table size update.
     pub fn queue_size_update(&mut self, size: usize) {
         let size = match self.max_size_update {
             Some(v) => cmp::max(v, size),
@@ -167,64 +234,156 @@ impl Decoder {
         self.max_size_update = Some(size);
     }
 
-    /// Decodes the headers found in the given buffer.
-    pub fn decode<F>(&mut self, src: &mut Cursor<&mut BytesMut>, mut f: F) -> Result<(), DecoderError>
+    /// Decodes a whole header block.
+    ///
+    /// Pseudo-header fields are returned as their own variants, in the order
+    /// they appear in the block. Never indexed literals are decoded as
+    /// sensitive values. A header field not allowed in HTTP/2 fails with
+    /// `InvalidHeaderField`, once the whole block is decoded.
+    pub fn decode(&mut self, src: &[u8]) -> Result<Vec<Header>, DecoderError> {
+        let mut buf = BytesMut::from(src);
+        let mut res = vec![];
+
+        self.decode_partial(&mut Cursor::new(&mut buf), true, |h| res.push(h))?;
+
+        if self.take_invalid_field() {
+            return Err(DecoderError::InvalidHeaderField);
+        }
+
+        Ok(res)
+    }
+
+    /// Decodes the headers found in a part of a header block, returning the
+    /// number of bytes consumed from `src`.
+    ///
+    /// Decoding stops before a representation that is not complete. Its
+    /// bytes are left in `src`, to be completed with the next part of the
+    /// block. `is_end` is set when `src` holds the end of the block, which
+    /// must then be decoded entirely.
+    ///
+    /// The state of the block is kept between the calls, so that the parts
+    /// can be decoded as they are received.
+    pub fn decode_partial<F>(
+        &mut self,
+        src: &mut Cursor<&mut BytesMut>,
+        is_end: bool,
+        mut f: F,
+    ) -> Result<usize, DecoderError>
     where
         F: FnMut(Header),
     {
-        use self::Representation::*;
+        let len = src.get_ref().len();
+
+        let mut block = match self.block.take() {
+            Some(block) => block,
+            None => self.start_block(),
+        };
+
+        trace!("decode; is_end={:?}", is_end);
+
+        match self.decode_representations(src, &mut block, &mut f) {
+            Ok(()) => {},
+            Err(DecoderError::NeedMore(_)) if !is_end => {
+                // Rewind to the start of the incomplete representation
+                src.set_position(0);
+            },
+            Err(e) => return Err(e),
+        }
+
+        if !is_end {
+            self.block = Some(block);
+        }
+
+        Ok(len - src.get_ref().len())
+    }
 
-        let mut can_resize = true;
+    fn start_block(&mut self) -> Block {
+        let mut must_resize = false;
 
         if let Some(size) = self.max_size_update.take() {
             self.last_max_update = size;
+
+            // The table must be shrunk before any header is decoded (section
+            // 4.2 of RFC 7541).
+            must_resize = size < self.table.max_size;
+        }
+
+        Block {
+            can_resize: true,
+            must_resize: must_resize,
         }
+    }
 
-        trace!("decode");
+    fn decode_representations<F>(
+        &mut self,
+        src: &mut Cursor<&mut BytesMut>,
+        block: &mut Block,
+        f: &mut F,
+    ) -> Result<(), DecoderError>
+    where
+        F: FnMut(Header),
+    {
+        use self::Representation::*;
 
         while let Some(ty) = peek_u8(src) {
             // At this point we are always at the beginning of the next block
             // within the HPACK data. The type of the block can always be
             // determined from the first byte.
-            match Representation::load(ty)? {
+            let ty = Representation::load(ty)?;
+
+            if block.must_resize {
+                match ty {
+                    SizeUpdate => block.must_resize = false,
+                    _ => {
+                        debug!("missing dynamic table size update; max={}", self.last_max_update);
+                        return Err(DecoderError::InvalidMaxDynamicSize);
+                    },
+                }
+            }
+
+            match ty {
                 Indexed => {
                     trace!("    Indexed; rem={:?}", src.remaining());
-                    can_resize = false;
+                    block.can_resize = false;
                     let entry = self.decode_indexed(src)?;
                     consume(src);
-                    f(entry);
+                    self.emit(entry, f);
                 },
                 LiteralWithIndexing => {
                     trace!("    LiteralWithIndexing; rem={:?}", src.remaining());
-                    can_resize = false;
+                    block.can_resize = false;
                     let entry = self.decode_literal(src, true)?;
 
                     // Insert the header into the table
                     self.table.insert(entry.clone());
                     consume(src);
 
-                    f(entry);
+                    self.emit(entry, f);
                 },
                 LiteralWithoutIndexing => {
                     trace!("    LiteralWithoutIndexing; rem={:?}", src.remaining());
-                    can_resize = false;
+                    block.can_resize = false;
                     let entry = self.decode_literal(src, false)?;
                     consume(src);
-                    f(entry);
+                    self.emit(entry, f);
                 },
                 LiteralNeverIndexed => {
                     trace!("    LiteralNeverIndexed; rem={:?}", src.remaining());
-                    can_resize = false;
-                    let entry = self.decode_literal(src, false)?;
+                    block.can_resize = false;
+                    let mut entry = self.decode_literal(src, false)?;
                     consume(src);
 
-                    // TODO: Track that this should never be indexed
+                    // Keep track that the value must never be indexed, so
+                    // that it is not indexed when forwarded either.
+                    if let Entry::Header(ref mut header) = entry {
+                        header.set_sensitive();
+                    }
 
-                    f(entry);
+                    self.emit(entry, f);
                 },
                 SizeUpdate => {
                     trace!("    SizeUpdate; rem={:?}", src.remaining());
-                    if !can_resize {
+                    if !block.can_resize {
                         return Err(DecoderError::InvalidMaxDynamicSize);
                     }
 
@@ -238,10 +397,29 @@ impl Decoder {
         Ok(())
     }
 
+    /// Passes a decoded header field to `f`, unless it is not allowed.
+    fn emit<F>(&mut self, entry: Entry, f: &mut F)
+    where
+        F: FnMut(Header),
+    {
+        match entry {
+            Entry::Header(header) => f(header),
+            Entry::Invalid { name, value } => {
+                debug!("invalid header field; name={:?}; value={:?}", name, value);
+                self.invalid_field = true;
+            },
+        }
+    }
+
     fn process_size_update(&mut self, buf: &mut Cursor<&mut BytesMut>) -> Result<(), DecoderError> {
         let new_size = decode_int(buf, 5)?;
 
         if new_size > self.last_max_update {
+            debug!(
+                "dynamic table size update over limit; size={}; max={}",
+                new_size,
+                self.last_max_update
+            );
             return Err(DecoderError::InvalidMaxDynamicSize);
         }
 
@@ -256,7 +434,7 @@ impl Decoder {
         Ok(())
     }
 
-    fn decode_indexed(&self, buf: &mut Cursor<&mut BytesMut>) -> Result<Header, DecoderError> {
+    fn decode_indexed(&self, buf: &mut Cursor<&mut BytesMut>) -> Result<Entry, DecoderError> {
         let index = decode_int(buf, 7)?;
         self.table.get(index)
     }
@@ -265,7 +443,7 @@ impl Decoder {
         &mut self,
         buf: &mut Cursor<&mut BytesMut>,
         index: bool,
-    ) -> Result<Header, DecoderError> {
+    ) -> Result<Entry, DecoderError> {
         let prefix = if index { 6 } else { 4 };
 
         // Extract the table index for the name, or 0 if not indexed
@@ -277,12 +455,25 @@ impl Decoder {
             let name = self.decode_string(buf)?;
             let value = self.decode_string(buf)?;
 
-            Header::new(name, value)
+            self.check_uppercase(&name);
+
+            Ok(Entry::new(name, value))
         } else {
             let e = self.table.get(table_idx)?;
             let value = self.decode_string(buf)?;
 
-            e.name().into_entry(value)
+            // The names of the invalid entries are not lowercased
+            if let Entry::Invalid { ref name, .. } = e {
+                self.check_uppercase(name);
+            }
+
+            Ok(e.with_value(value))
+        }
+    }
+
+    fn check_uppercase(&mut self, name: &[u8]) {
+        if name.iter().any(|b| b.is_ascii_uppercase()) {
+            self.uppercase_field_name = true;
         }
     }
 
@@ -438,6 +629,45 @@ fn consume(buf: &mut Cursor<&mut BytesMut>) {
     take(buf, 0);
 }
 
+// ===== impl Entry =====
+
+impl Entry {
+    fn new(name: Bytes, value: Bytes) -> Entry {
+        match Header::new(name.clone(), value.clone()) {
+            Ok(header) => Entry::Header(header),
+            Err(_) => Entry::Invalid {
+                name: name,
+                value: value,
+            },
+        }
+    }
+
+    /// Returns the entry with the name of this one and the given value.
+    fn with_value(&self, value: Bytes) -> Entry {
+        match *self {
+            Entry::Header(ref header) => {
+                let name = header.name();
+
+                match name.clone().into_entry(value.clone()) {
+                    Ok(header) => Entry::Header(header),
+                    Err(_) => Entry::Invalid {
+                        name: Bytes::from(name.as_slice()),
+                        value: value,
+                    },
+                }
+            },
+            Entry::Invalid { ref name, .. } => Entry::new(name.clone(), value),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Entry::Header(ref header) => header.len(),
+            Entry::Invalid { ref name, ref value } => 32 + name.len() + value.len(),
+        }
+    }
+}
+
 // ===== impl Table =====
 
 impl Table {
@@ -461,13 +691,13 @@ impl Table {
     ///
     /// This is according to the [HPACK spec, section 2.3.3.]
     /// (http://http2.github.io/http2-spec/compression.html#index.address.space)
-    pub fn get(&self, index: usize) -> Result<Header, DecoderError> {
+    fn get(&self, index: usize) -> Result<Entry, DecoderError> {
         if index == 0 {
             return Err(DecoderError::InvalidTableIndex);
         }
 
         if index <= 61 {
-            return Ok(get_static(index));
+            return Ok(Entry::Header(get_static(index)));
         }
 
         // Convert the index for lookup in the entries structure.
@@ -477,7 +707,7 @@ impl Table {
         }
     }
 
-    fn insert(&mut self, entry: Header) {
+    fn insert(&mut self, entry: Entry) {
         let len = entry.len();
 
         self.reserve(len);
@@ -538,15 +768,13 @@ impl From<Utf8Error> for DecoderError {
 
 impl From<header::InvalidHeaderValue> for DecoderError {
     fn from(_: header::InvalidHeaderValue) -> DecoderError {
-        // TODO: Better error?
-        DecoderError::InvalidUtf8
+        DecoderError::InvalidHeaderField
     }
 }
 
 impl From<header::InvalidHeaderName> for DecoderError {
     fn from(_: header::InvalidHeaderName) -> DecoderError {
-        // TODO: Better error
-        DecoderError::InvalidUtf8
+        DecoderError::InvalidHeaderField
     }
 }
 
@@ -789,7 +1017,7 @@ fn from_static(s: &'static str) -> String<Bytes> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use hpack::Header;
+    use hpack::raw::Header;
 
     #[test]
     fn test_peek_u8() {
@@ -812,8 +1040,8 @@ mod test {
     fn test_decode_empty() {
         let mut de = Decoder::new(0);
         let mut buf = BytesMut::new();
-        let empty = de.decode(&mut Cursor::new(&mut buf), |_| {}).unwrap();
-        assert_eq!(empty, ());
+        let consumed = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+        assert_eq!(consumed, 0);
     }
 
     #[test]
@@ -828,7 +1056,7 @@ mod test {
         let mut buf = buf.into();
 
         let mut res = vec![];
-        let _ = de.decode(&mut Cursor::new(&mut buf), |h| {
+        let _ = de.decode_partial(&mut Cursor::new(&mut buf), true, |h| {
             res.push(h);
         }).unwrap();
 
@@ -844,6 +1072,352 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_decode_size_update_limit() {
+        let mut de = Decoder::new(4096);
+
+        // Exactly the limit
+        let mut buf = vec![0x3f, 0xe1, 0x1f].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+
+        // One over the limit
+        let mut buf = vec![0x3f, 0xe2, 0x1f].into();
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap_err();
+        assert_eq!(err, DecoderError::InvalidMaxDynamicSize);
+    }
+
+    #[test]
+    fn test_decode_size_update_between_headers() {
+        let mut de = Decoder::new(4096);
+
+        // `:path: /a`, a size update to 0, then `:path: /b`
+        let mut buf = vec![0x04, 2, b'/', b'a', 0x20, 0x04, 2, b'/', b'b'].into();
+
+        let mut res = vec![];
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |h| res.push(h)).unwrap_err();
+        assert_eq!(err, DecoderError::InvalidMaxDynamicSize);
+        assert_eq!(res.len(), 1);
+    }
+
+    #[test]
+    fn test_decode_queued_size_update() {
+        let mut de = Decoder::new(4096);
+        de.queue_size_update(1024);
+
+        // The table must be resized first
+        let mut buf = vec![0x82].into();
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap_err();
+        assert_eq!(err, DecoderError::InvalidMaxDynamicSize);
+
+        de.queue_size_update(1024);
+
+        let mut buf = vec![0x3f, 0xe2, 0x07, 0x82].into();
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap_err();
+        assert_eq!(err, DecoderError::InvalidMaxDynamicSize);
+
+        let mut buf = vec![0x3f, 0xe1, 0x07, 0x82].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+        assert_eq!(de.table.max_size, 1024);
+
+        // A larger size does not require a size update
+        de.queue_size_update(8192);
+
+        let mut buf = vec![0x82].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+
+        let mut buf = vec![0x3f, 0xe1, 0x3f].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+        assert_eq!(de.table.max_size, 8192);
+    }
+
+    /// Encodes a literal header field without indexing, with a literal name.
+    fn literal(name: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0, name.len() as u8];
+        buf.extend(name);
+        buf.push(value.len() as u8);
+        buf.extend(value);
+        buf
+    }
+
+    #[test]
+    fn test_decode_invalid_fields() {
+        let invalid = vec![
+            // Forbidden octets in values
+            literal(b"x-foo", b"a\0b"),
+            literal(b"x-foo", b"a\rb"),
+            literal(b"x-foo", b"a\nb"),
+            // obs-fold
+            literal(b"x-foo", b"a\r\n b"),
+            // Names that are not tokens
+            literal(b"x foo", b"bar"),
+            literal(b"x-foo:", b"bar"),
+            literal(b"x\0foo", b"bar"),
+            literal(b"", b"bar"),
+            // Unknown pseudo-header field
+            literal(b":foo", b"bar"),
+            // Forbidden octets in pseudo-header values
+            literal(b":path", b"/a\nb"),
+            literal(b":authority", b"example.com\r"),
+            // `:path` from the static table, with a forbidden value
+            vec![0x04, 3, b'/', 0, b'a'],
+        ];
+
+        for field in invalid {
+            let mut de = Decoder::new(4096);
+
+            // The fields around the invalid one are still decoded
+            let mut buf = vec![0x82];
+            buf.extend(&field);
+            buf.extend(literal(b"x-bar", b"baz"));
+
+            let mut res = vec![];
+            de.decode_partial(&mut Cursor::new(&mut buf.into()), true, |h| {
+                res.push(h);
+            }).unwrap();
+
+            assert!(de.take_invalid_field(), "field={:?}", field);
+            assert!(!de.take_invalid_field());
+
+            assert_eq!(res.len(), 2);
+            assert_eq!(res[0], Header::Method(Method::GET));
+            assert_eq!(res[1].value_slice(), b"baz");
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_field_in_table() {
+        let mut de = Decoder::new(4096);
+
+        let mut buf = vec![];
+        // x-foo: bar, with incremental indexing
+        buf.extend(&[0x40, 5]);
+        buf.extend(b"x-foo");
+        buf.extend(&[3]);
+        buf.extend(b"bar");
+        // x-bad: a\nb, with incremental indexing
+        buf.extend(&[0x40, 5]);
+        buf.extend(b"x-bad");
+        buf.extend(&[3]);
+        buf.extend(b"a\nb");
+
+        let mut res = vec![];
+        de.decode_partial(&mut Cursor::new(&mut buf.into()), true, |h| {
+            res.push(h);
+        }).unwrap();
+
+        assert!(de.take_invalid_field());
+        assert_eq!(res.len(), 1);
+        assert_eq!(de.table.size(), 32 + 5 + 3 + 32 + 5 + 3);
+
+        // The invalid field is at the index 62, and the valid one is at 63
+        let mut res = vec![];
+        let mut buf = vec![0x80 | 63].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |h| {
+            res.push(h);
+        }).unwrap();
+
+        assert!(!de.take_invalid_field());
+        assert_eq!(res[0].value_slice(), b"bar");
+
+        let mut buf = vec![0x80 | 62].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {
+            panic!("invalid field decoded");
+        }).unwrap();
+
+        assert!(de.take_invalid_field());
+
+        // The name of the invalid entry, with a valid value, is a valid field
+        let mut res = vec![];
+        let mut buf = vec![0x0f, 62 - 15, 2, b'o', b'k'].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |h| {
+            res.push(h);
+        }).unwrap();
+
+        assert!(!de.take_invalid_field());
+        assert_eq!(res[0].name().as_slice(), b"x-bad");
+        assert_eq!(res[0].value_slice(), b"ok");
+    }
+
+    #[test]
+    fn test_decode_whole_block_with_invalid_field() {
+        let mut de = Decoder::new(4096);
+
+        let mut buf = literal(b"x-foo", b"a\rb");
+        buf.push(0x82);
+
+        assert_eq!(de.decode(&buf), Err(DecoderError::InvalidHeaderField));
+
+        // The flag was cleared
+        assert_eq!(de.decode(&[0x82]), Ok(vec![Header::Method(Method::GET)]));
+    }
+
+    #[test]
+    fn test_decode_uppercase_field_name() {
+        let mut de = Decoder::new(4096);
+
+        let mut buf = vec![0b01000000, 3];
+        buf.extend(b"Foo");
+        buf.extend(&[3]);
+        buf.extend(b"bar");
+
+        let mut buf = buf.into();
+
+        let mut res = vec![];
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |h| {
+            res.push(h);
+        }).unwrap();
+
+        assert!(de.take_uppercase_field_name());
+        assert!(!de.take_uppercase_field_name());
+
+        match res[0] {
+            Header::Field { ref name, ref value } => {
+                assert_eq!(name, "foo");
+                assert_eq!(value, "bar");
+            }
+            _ => panic!(),
+        }
+
+        // The lowercased name is found in the table
+        let mut buf = vec![0x80 | 62].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+        assert!(!de.take_uppercase_field_name());
+    }
+
+    #[test]
+    fn test_decode_partial_at_every_boundary() {
+        let block = encode_block();
+        let expect = decode_all(&mut Decoder::new(4096), &[&block[..]]);
+        assert_eq!(expect.len(), 7);
+
+        for i in 0..block.len() + 1 {
+            let res = decode_all(&mut Decoder::new(4096), &[&block[..i], &block[i..]]);
+            assert_eq!(res, expect, "split at {}", i);
+        }
+
+        // One byte at a time
+        let parts: Vec<_> = block.chunks(1).collect();
+        assert_eq!(decode_all(&mut Decoder::new(4096), &parts), expect);
+    }
+
+    #[test]
+    fn test_decode_partial_consumes_whole_representations() {
+        let mut de = Decoder::new(4096);
+
+        // `:method: GET`, then the first octets of `:path: /a`
+        let mut buf = vec![0x82, 0x04, 2, b'/'].into();
+        let n = de.decode_partial(&mut Cursor::new(&mut buf), false, |_| {}).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(&buf[..], &[0x04, 2, b'/']);
+
+        buf.extend_from_slice(b"a");
+        let n = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+        assert_eq!(n, 4);
+        assert!(buf.is_empty());
+
+        // The end of the block must be complete
+        let mut buf = vec![0x04, 2, b'/'].into();
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap_err();
+        assert_eq!(err, DecoderError::NeedMore(NeedMore::StringUnderflow));
+    }
+
+    #[test]
+    fn test_decode_partial_size_update_rules() {
+        let mut de = Decoder::new(4096);
+
+        // A size update after a header decoded from a previous part
+        let mut buf = vec![0x82].into();
+        de.decode_partial(&mut Cursor::new(&mut buf), false, |_| {}).unwrap();
+
+        let mut buf = vec![0x20].into();
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap_err();
+        assert_eq!(err, DecoderError::InvalidMaxDynamicSize);
+
+        // A required size update split across parts
+        let mut de = Decoder::new(4096);
+        de.queue_size_update(1024);
+
+        let mut buf = vec![0x3f].into();
+        let n = de.decode_partial(&mut Cursor::new(&mut buf), false, |_| {}).unwrap();
+        assert_eq!(n, 0);
+
+        buf.extend_from_slice(&[0xe1, 0x07, 0x82]);
+        de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap();
+        assert_eq!(de.table.max_size, 1024);
+
+        // A required size update missing from the first part with a header
+        de.queue_size_update(512);
+
+        let mut buf = BytesMut::new();
+        de.decode_partial(&mut Cursor::new(&mut buf), false, |_| {}).unwrap();
+
+        let mut buf = vec![0x82].into();
+        let err = de.decode_partial(&mut Cursor::new(&mut buf), true, |_| {}).unwrap_err();
+        assert_eq!(err, DecoderError::InvalidMaxDynamicSize);
+    }
+
+    /// Returns a header block using every kind of representation.
+    fn encode_block() -> BytesMut {
+        use hpack::raw::Encoder;
+        use http::header::HeaderValue;
+
+        let mut sensitive = HeaderValue::from_static("secret");
+        sensitive.set_sensitive(true);
+
+        let fields = vec![
+            Header::Method(Method::GET),
+            Header::Field {
+                name: Some(header::HeaderName::from_static("x-large")),
+                value: HeaderValue::from_bytes(&[b'a'; 300]).unwrap(),
+            },
+            Header::Field {
+                name: Some(header::HeaderName::from_static("x-repeated")),
+                value: HeaderValue::from_static("value"),
+            },
+            Header::Field {
+                name: Some(header::HeaderName::from_static("x-repeated")),
+                value: HeaderValue::from_static("value"),
+            },
+            Header::Field {
+                name: None,
+                value: HeaderValue::from_static("other value"),
+            },
+            Header::Field {
+                name: Some(header::CONTENT_LENGTH),
+                value: HeaderValue::from_static("123"),
+            },
+            Header::Field {
+                name: Some(header::AUTHORIZATION),
+                value: sensitive,
+            },
+        ];
+
+        let mut encoder = Encoder::default();
+        encoder.update_max_size(2048);
+
+        let mut dst = BytesMut::with_capacity(1024);
+        encoder.encode(fields, &mut dst);
+        dst
+    }
+
+    /// Decodes the header block made of `parts`, passing each part to the
+    /// decoder with the octets left over from the previous one.
+    fn decode_all(de: &mut Decoder, parts: &[&[u8]]) -> Vec<Header> {
+        let mut res = vec![];
+        let mut buf = BytesMut::new();
+
+        for (i, part) in parts.iter().enumerate() {
+            buf.extend_from_slice(part);
+
+            let is_end = i == parts.len() - 1;
+            de.decode_partial(&mut Cursor::new(&mut buf), is_end, |h| res.push(h))
+                .unwrap();
+        }
+
+        assert!(buf.is_empty());
+        res
+    }
+
     fn huff_encode(src: &[u8]) -> BytesMut {
         let mut buf = BytesMut::new();
         huffman::encode(src, &mut buf).unwrap();
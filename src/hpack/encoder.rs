@@ -1,13 +1,22 @@
-use super::{huffman, Header};
+use super::huffman;
+use super::header::Header;
 use super::table::{Index, Table};
 
 use bytes::{BufMut, BytesMut};
 use http::header::{HeaderName, HeaderValue};
 
+use std::cmp;
+
 #[derive(Debug)]
 pub struct Encoder {
     table: Table,
     size_update: Option<SizeUpdate>,
+
+    // Names of the header fields whose values are always sensitive
+    sensitive_headers: Vec<HeaderName>,
+
+    // Whether strings are Huffman encoded when it makes them shorter
+    use_huffman: bool,
 }
 
 #[derive(Debug)]
@@ -38,13 +47,29 @@ impl Encoder {
         Encoder {
             table: Table::new(max_size, capacity),
             size_update: None,
+            sensitive_headers: Vec::new(),
+            use_huffman: true,
         }
     }
 
+    /// Sets the names of the header fields whose values are always encoded as
+    /// never indexed literals, as if they were marked sensitive.
+    pub fn set_sensitive_headers(&mut self, names: Vec<HeaderName>) {
+        self.sensitive_headers = names;
+    }
+
+    /// Sets whether strings are Huffman encoded when it makes them shorter.
+    ///
+    /// When disabled, all strings are sent as raw octets, which makes the
+    /// header blocks easier to read on the wire.
+    #[cfg(feature = "unstable")]
+    pub fn set_huffman(&mut self, val: bool) {
+        self.use_huffman = val;
+    }
+
     /// Queues a max size update.
     ///
     /// The next call to `encode` will include a dynamic size update frame.
-    #[allow(dead_code)]
     pub fn update_max_size(&mut self, val: usize) {
         match self.size_update {
             Some(SizeUpdate::One(old)) => if val > old {
@@ -71,8 +96,37 @@ impl Encoder {
         }
     }
 
-    /// Encode a set of headers into the provide buffer
-    pub fn encode<I>(
+    /// Encodes a whole header block into `dst`, growing it as needed.
+    ///
+    /// A header field with a `None` name repeats the name of the previous
+    /// field. Pseudo-header fields are passed as their own variants, in the
+    /// order they are meant to be sent.
+    pub fn encode<I>(&mut self, headers: I, dst: &mut BytesMut)
+    where
+        I: IntoIterator<Item = Header<Option<HeaderName>>>,
+    {
+        let mut headers = headers.into_iter();
+        let mut resume = None;
+
+        // Room for the dynamic table size updates, which are not resumable
+        dst.reserve(16);
+
+        loop {
+            match self.encode_partial(resume.take(), &mut headers, dst) {
+                Encode::Full => return,
+                Encode::Partial(state) => {
+                    let additional = cmp::max(dst.capacity(), 64);
+                    dst.reserve(additional);
+                    resume = Some(state);
+                },
+            }
+        }
+    }
+
+    /// Encode a set of headers into the provide buffer, stopping at the
+    /// first header that does not fit. The returned state resumes the
+    /// encoding once more room is available.
+    pub fn encode_partial<I>(
         &mut self,
         resume: Option<EncodeState>,
         headers: &mut I,
@@ -106,6 +160,7 @@ impl Encoder {
         }
 
         let mut last_index = None;
+        let mut is_last_sensitive = false;
 
         for header in headers {
             let len = dst.len();
@@ -113,7 +168,13 @@ impl Encoder {
             match header.reify() {
                 // The header has an associated name. In which case, try to
                 // index it in the table.
-                Ok(header) => {
+                Ok(mut header) => {
+                    is_last_sensitive = self.is_sensitive_name(&header);
+
+                    if is_last_sensitive {
+                        header.set_sensitive();
+                    }
+
                     let index = self.table.index(header);
                     let res = self.encode_header(&index, dst);
 
@@ -131,7 +192,11 @@ impl Encoder {
                 // the name is the same as the previously yielded header. In
                 // which case, we skip table lookup and just use the same index
                 // as the previous entry.
-                Err(value) => {
+                Err(mut value) => {
+                    if is_last_sensitive {
+                        value.set_sensitive(true);
+                    }
+
                     let res =
                         self.encode_header_without_name(last_index.as_ref().unwrap(), &value, dst);
 
@@ -149,6 +214,13 @@ impl Encoder {
         Encode::Full
     }
 
+    fn is_sensitive_name(&self, header: &Header) -> bool {
+        match *header {
+            Header::Field { ref name, .. } => self.sensitive_headers.contains(name),
+            _ => false,
+        }
+    }
+
     fn encode_size_updates(&mut self, dst: &mut BytesMut) -> Result<(), EncoderError> {
         match self.size_update.take() {
             Some(SizeUpdate::One(val)) => {
@@ -175,7 +247,13 @@ impl Encoder {
             Index::Name(idx, _) => {
                 let header = self.table.resolve(&index);
 
-                encode_not_indexed(idx, header.value_slice(), header.is_sensitive(), dst)?;
+                encode_not_indexed(
+                    idx,
+                    header.value_slice(),
+                    header.is_sensitive(),
+                    self.use_huffman,
+                    dst,
+                )?;
             },
             Index::Inserted(_) => {
                 let header = self.table.resolve(&index);
@@ -188,8 +266,8 @@ impl Encoder {
 
                 dst.put_u8(0b01000000);
 
-                encode_str(header.name().as_slice(), dst)?;
-                encode_str(header.value_slice(), dst)?;
+                encode_str(header.name().as_slice(), self.use_huffman, dst)?;
+                encode_str(header.value_slice(), self.use_huffman, dst)?;
             },
             Index::InsertedValue(idx, _) => {
                 let header = self.table.resolve(&index);
@@ -197,7 +275,7 @@ impl Encoder {
                 assert!(!header.is_sensitive());
 
                 encode_int(idx, 6, 0b01000000, dst)?;
-                encode_str(header.value_slice(), dst)?;
+                encode_str(header.value_slice(), self.use_huffman, dst)?;
             },
             Index::NotIndexed(_) => {
                 let header = self.table.resolve(&index);
@@ -206,6 +284,7 @@ impl Encoder {
                     header.name().as_slice(),
                     header.value_slice(),
                     header.is_sensitive(),
+                    self.use_huffman,
                     dst,
                 )?;
             },
@@ -227,7 +306,13 @@ impl Encoder {
             Index::InsertedValue(..) => {
                 let idx = self.table.resolve_idx(last);
 
-                encode_not_indexed(idx, value.as_ref(), value.is_sensitive(), dst)?;
+                encode_not_indexed(
+                    idx,
+                    value.as_ref(),
+                    value.is_sensitive(),
+                    self.use_huffman,
+                    dst,
+                )?;
             },
             Index::NotIndexed(_) => {
                 let last = self.table.resolve(last);
@@ -236,6 +321,7 @@ impl Encoder {
                     last.name().as_slice(),
                     value.as_ref(),
                     value.is_sensitive(),
+                    self.use_huffman,
                     dst,
                 )?;
             },
@@ -259,6 +345,7 @@ fn encode_not_indexed(
     name: usize,
     value: &[u8],
     sensitive: bool,
+    use_huffman: bool,
     dst: &mut BytesMut,
 ) -> Result<(), EncoderError> {
     if sensitive {
@@ -267,7 +354,7 @@ fn encode_not_indexed(
         encode_int(name, 4, 0, dst)?;
     }
 
-    encode_str(value, dst)?;
+    encode_str(value, use_huffman, dst)?;
     Ok(())
 }
 
@@ -275,6 +362,7 @@ fn encode_not_indexed2(
     name: &[u8],
     value: &[u8],
     sensitive: bool,
+    use_huffman: bool,
     dst: &mut BytesMut,
 ) -> Result<(), EncoderError> {
     if !dst.has_remaining_mut() {
@@ -287,64 +375,27 @@ fn encode_not_indexed2(
         dst.put_u8(0);
     }
 
-    encode_str(name, dst)?;
-    encode_str(value, dst)?;
+    encode_str(name, use_huffman, dst)?;
+    encode_str(value, use_huffman, dst)?;
     Ok(())
 }
 
-fn encode_str(val: &[u8], dst: &mut BytesMut) -> Result<(), EncoderError> {
-    use std::io::Cursor;
-
-    if !dst.has_remaining_mut() {
-        return Err(EncoderError::BufferOverflow);
-    }
-
-    if val.len() != 0 {
-        let idx = dst.len();
-
-        // Push a placeholder byte for the length header
-        dst.put_u8(0);
+fn encode_str(val: &[u8], use_huffman: bool, dst: &mut BytesMut) -> Result<(), EncoderError> {
+    // Strings are only Huffman encoded when it makes them shorter (section
+    // 5.2 of RFC 7541).
+    let huff_len = huffman::encoded_len(val);
 
-        // Encode with huffman
+    if use_huffman && huff_len < val.len() {
+        encode_int(huff_len, 7, 0x80, dst)?;
         huffman::encode(val, dst)?;
+    } else {
+        encode_int(val.len(), 7, 0, dst)?;
 
-        let huff_len = dst.len() - (idx + 1);
-
-        if encode_int_one_byte(huff_len, 7) {
-            // Write the string head
-            dst[idx] = 0x80 | huff_len as u8;
-        } else {
-            // Write the head to a placeholer
-            let mut buf = [0; 8];
-
-            let head_len = {
-                let mut head_dst = Cursor::new(&mut buf);
-                encode_int(huff_len, 7, 0x80, &mut head_dst)?;
-                head_dst.position() as usize
-            };
-
-            if dst.remaining_mut() < head_len {
-                return Err(EncoderError::BufferOverflow);
-            }
-
-            // This is just done to reserve space in the destination
-            dst.put_slice(&buf[1..head_len]);
-
-            // Shift the header forward
-            for i in 0..huff_len {
-                let src_i = idx + 1 + (huff_len - (i + 1));
-                let dst_i = idx + head_len + (huff_len - (i + 1));
-                dst[dst_i] = dst[src_i];
-            }
-
-            // Copy in the head
-            for i in 0..head_len {
-                dst[idx + i] = buf[i];
-            }
+        if dst.remaining_mut() < val.len() {
+            return Err(EncoderError::BufferOverflow);
         }
-    } else {
-        // Write an empty string
-        dst.put_u8(0);
+
+        dst.put_slice(val);
     }
 
     Ok(())
@@ -407,8 +458,10 @@ fn encode_int_one_byte(value: usize, prefix_bits: usize) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
-    use hpack::Header;
+    use bytes::Bytes;
+    use hpack::raw::{Decoder, Header};
     use http::*;
+    use std::io::Cursor;
 
     #[test]
     fn test_encode_method_get() {
@@ -432,9 +485,9 @@ mod test {
         let res = encode(&mut encoder, vec![method("PATCH")]);
 
         assert_eq!(res[0], 0b01000000 | 2); // Incremental indexing w/ name pulled from table
-        assert_eq!(res[1], 0x80 | 5); // header value w/ huffman coding
+        assert_eq!(res[1], 5); // header value w/o huffman coding, as it is not shorter
 
-        assert_eq!("PATCH", huff_decode(&res[2..7]));
+        assert_eq!(b"PATCH", &res[2..7]);
         assert_eq!(encoder.table.len(), 1);
 
         let res = encode(&mut encoder, vec![method("PATCH")]);
@@ -590,6 +643,112 @@ mod test {
         assert_eq!("12345", huff_decode(&res[3..]));
     }
 
+    #[test]
+    fn test_sensitive_header_names() {
+        let mut encoder = Encoder::default();
+        encoder.set_sensitive_headers(vec![
+            header::AUTHORIZATION,
+            "x-api-key".parse().unwrap(),
+        ]);
+
+        // The name is in the static table
+        let res = encode(&mut encoder, vec![self::header("authorization", "secret")]);
+        assert_eq!(0x10, res[0] & 0xf0);
+        assert_eq!(&[0b11111, 8], &res[..2]);
+        assert_eq!(0, encoder.table.len());
+
+        // The name is not, and the next value has no name
+        let res = encode(
+            &mut encoder,
+            vec![
+                self::header("x-api-key", "one"),
+                Header::Field {
+                    name: None,
+                    value: HeaderValue::from_bytes(b"two").unwrap(),
+                },
+            ],
+        );
+        assert_eq!(&[0b10000, 0x80 | 7], &res[..2]);
+        assert_eq!("x-api-key", huff_decode(&res[2..9]));
+        assert_eq!(0x80 | 2, res[9]);
+        assert_eq!("one", huff_decode(&res[10..12]));
+        assert_eq!(&[0b10000, 0x80 | 7], &res[12..14]);
+        assert_eq!(0, encoder.table.len());
+
+        // Other headers are still indexed
+        let res = encode(&mut encoder, vec![self::header("x-other", "one")]);
+        assert_eq!(0x40, res[0]);
+        assert_eq!(1, encoder.table.len());
+    }
+
+    #[test]
+    fn test_sensitive_values_in_table_are_not_indexed() {
+        let mut encoder = Encoder::default();
+
+        let _ = encode(&mut encoder, vec![self::header("x-token", "abc")]);
+        assert_eq!(1, encoder.table.len());
+
+        // The exact header is in the dynamic table, only its name is used
+        let mut value = HeaderValue::from_bytes(b"abc").unwrap();
+        value.set_sensitive(true);
+
+        let res = encode(
+            &mut encoder,
+            vec![Header::Field {
+                name: Some("x-token".parse().unwrap()),
+                value: value,
+            }],
+        );
+        assert_eq!(&[0b11111, 47], &res[..2]);
+        assert_eq!(0x80 | 2, res[2]);
+        assert_eq!("abc", huff_decode(&res[3..]));
+        assert_eq!(1, encoder.table.len());
+
+        // The exact header is in the static table
+        let mut value = HeaderValue::from_bytes(b"gzip, deflate").unwrap();
+        value.set_sensitive(true);
+
+        let res = encode(
+            &mut encoder,
+            vec![Header::Field {
+                name: Some(header::ACCEPT_ENCODING),
+                value: value,
+            }],
+        );
+        assert_eq!(&[0b11111, 1], &res[..2]);
+        assert_eq!(1, encoder.table.len());
+    }
+
+    #[test]
+    fn test_decoded_never_indexed_values_are_sensitive() {
+        let mut encoder = Encoder::default();
+        encoder.set_sensitive_headers(vec![header::COOKIE]);
+
+        let mut decoder = Decoder::new(4096);
+
+        let res = encode(
+            &mut encoder,
+            vec![self::header("cookie", "a=b"), self::header("x-plain", "c")],
+        );
+        let decoded = decode(&mut decoder, res);
+
+        match decoded[0] {
+            Header::Field { ref value, .. } => assert!(value.is_sensitive()),
+            ref h => panic!("unexpected header; {:?}", h),
+        }
+
+        match decoded[1] {
+            Header::Field { ref value, .. } => assert!(!value.is_sensitive()),
+            ref h => panic!("unexpected header; {:?}", h),
+        }
+
+        // Re-encoding the decoded values keeps them out of the table
+        let mut encoder = Encoder::default();
+        let res = encode(&mut encoder, decoded);
+        assert_eq!(&[0b11111, 17], &res[..2]);
+        assert_eq!(1, encoder.table.len());
+    }
+
     #[test]
     fn test_content_length_value_not_indexed() {
         let mut encoder = Encoder::default();
@@ -610,8 +769,8 @@ mod test {
 
         // Encode second one
         let res = encode(&mut encoder, vec![header(name, "two")]);
-        assert_eq!(&[0x40 | 62, 0x80 | 3], &res[0..2]);
-        assert_eq!("two", huff_decode(&res[2..]));
+        assert_eq!(&[0x40 | 62, 3], &res[0..2]);
+        assert_eq!(b"two", &res[2..]);
         assert_eq!(5, res.len());
 
         // Encode the first one again
@@ -637,7 +796,7 @@ mod test {
         // This will evict the first header, while still referencing the header
         // name
         let res = encode(&mut encoder, vec![header("foo", "baz")]);
-        assert_eq!(&[0x40 | 63, 0, 0x80 | 3], &res[..3]);
+        assert_eq!(&[0x40 | 63, 0, 3], &res[..3]);
         assert_eq!(2, encoder.table.len());
 
         // Try adding the same header again
@@ -657,8 +816,8 @@ mod test {
         let res = encode(&mut encoder, vec![header("foo", "bar")]);
         assert_eq!(&[0, 0x80 | 2], &res[..2]);
         assert_eq!("foo", huff_decode(&res[2..4]));
-        assert_eq!(0x80 | 3, res[4]);
-        assert_eq!("bar", huff_decode(&res[5..8]));
+        assert_eq!(3, res[4]);
+        assert_eq!(b"bar", &res[5..8]);
         assert_eq!(0, encoder.table.len());
 
         // Encode a custom value
@@ -667,6 +826,31 @@ mod test {
         assert_eq!("chunked", huff_decode(&res[3..]));
     }
 
+    #[test]
+    fn test_huffman_only_when_shorter() {
+        let mut encoder = Encoder::new(0, 0);
+
+        // The name is as long once Huffman encoded, the value is shorter
+        let res = encode(&mut encoder, vec![header("xyz", "12345")]);
+        assert_eq!(&[0, 3], &res[..2]);
+        assert_eq!(b"xyz", &res[2..5]);
+        assert_eq!(0x80 | 4, res[5]);
+        assert_eq!("12345", huff_decode(&res[6..]));
+
+        // Symbols with long codes make the encoded string longer
+        let res = encode(&mut encoder, vec![header("xyz", "{~}")]);
+        assert_eq!(&[3, b'{', b'~', b'}'], &res[5..]);
+
+        // Empty strings
+        let res = encode(&mut encoder, vec![header("xyz", "")]);
+        assert_eq!(&[0], &res[5..]);
+
+        // Disabled
+        encoder.use_huffman = false;
+        let res = encode(&mut encoder, vec![header("xyz", "12345")]);
+        assert_eq!(&[5, b'1', b'2', b'3', b'4', b'5'], &res[5..]);
+    }
+
     #[test]
     fn test_update_max_size_combos() {
         let mut encoder = Encoder::default();
@@ -783,7 +967,7 @@ mod test {
             },
         ].into_iter();
 
-        let resume = match encoder.encode(None, &mut input, &mut dst) {
+        let resume = match encoder.encode_partial(None, &mut input, &mut dst) {
             Encode::Partial(r) => r,
             _ => panic!(),
         };
@@ -795,7 +979,7 @@ mod test {
 
         dst.clear();
 
-        match encoder.encode(Some(resume), &mut input, &mut dst) {
+        match encoder.encode_partial(Some(resume), &mut input, &mut dst) {
             Encode::Full => {},
             _ => panic!(),
         }
@@ -805,6 +989,138 @@ mod test {
         assert_eq!("zomg", huff_decode(&dst[3..]));
     }
 
+    #[test]
+    fn test_shrink_then_grow_round_trip() {
+        let mut encoder = Encoder::default();
+        let mut decoder = Decoder::new(4096);
+
+        // Each of these takes up 36 bytes in the table
+        let a = || header("x-a", "1");
+        let b = || header("x-b", "2");
+        let c = || header("x-c", "3");
+
+        let res = encode(&mut encoder, vec![a(), b()]);
+        assert_eq!(vec![a(), b()], decode(&mut decoder, res));
+
+        // Shrinking to zero and growing again before the next header block
+        // must produce both updates, so the decoder evicts everything.
+        encoder.update_max_size(0);
+        encoder.update_max_size(72);
+
+        let res = encode(&mut encoder, vec![a()]);
+        assert_eq!(&[32, 32 | 31, 41], &res[..3]);
+        assert_eq!(vec![a()], decode(&mut decoder, res));
+
+        // The updates are only sent once
+        let res = encode(&mut encoder, vec![b()]);
+        assert_ne!(32, res[0] & 0xE0);
+        assert_eq!(vec![b()], decode(&mut decoder, res));
+
+        // Both entries fit exactly, so `a` is still indexed
+        let res = encode(&mut encoder, vec![a()]);
+        assert_eq!(&[0x80 | 63], &res[..]);
+        assert_eq!(vec![a()], decode(&mut decoder, res));
+
+        // Inserting `c` evicts `a` on both ends
+        let res = encode(&mut encoder, vec![c()]);
+        assert_eq!(vec![c()], decode(&mut decoder, res));
+        assert_eq!(2, encoder.table.len());
+
+        let res = encode(&mut encoder, vec![a()]);
+        assert!(res.len() > 1);
+        assert_eq!(vec![a()], decode(&mut decoder, res));
+    }
+
+    #[test]
+    fn test_repeated_response_is_mostly_indexed() {
+        let response = || {
+            vec![
+                status(200),
+                header("content-type", "text/html; charset=utf-8"),
+                header("content-length", "1270"),
+                header("content-encoding", "gzip"),
+                header("cache-control", "private, max-age=0"),
+                header("date", "Mon, 12 Oct 2026 10:00:00 GMT"),
+                header("server", "h2"),
+                header("vary", "accept-encoding"),
+                header("x-request-id", "3f1a8e9c-5a1b-4bb1-9d10-0ab9d1f4e2a7"),
+            ]
+        };
+
+        let mut encoder = Encoder::default();
+        let mut decoder = Decoder::new(4096);
+
+        let first = encode(&mut encoder, response());
+        assert_eq!(response(), decode(&mut decoder, first.clone()));
+
+        let second = encode(&mut encoder, response());
+        assert_eq!(response(), decode(&mut decoder, second.clone()));
+
+        // Each header is a single octet, except for content-length: its value
+        // is never indexed, and its name is in the static table.
+        assert_eq!(&[0x80 | 8], &second[..1]);
+        assert_eq!(second.len(), 8 + 6);
+        assert!(first.len() > 5 * second.len());
+    }
+
+    #[test]
+    fn test_round_trip_through_decoder() {
+        let mut encoder = Encoder::new(256, 0);
+        let mut decoder = Decoder::new(256);
+
+        let blocks = vec![
+            vec![method("GET"), path("/"), header("accept", "*/*")],
+            vec![method("PATCH"), path("/a/b"), header("cookie", "a=b")],
+            vec![status(200), status(404), status(299), header("x-a", "1")],
+            vec![header("x-a", "1"), header("x-a", "2"), header("x-a", "1")],
+            // Larger than the table
+            vec![header("x-big", &"v".repeat(300)), header("x-a", "1")],
+        ];
+
+        // Entries are inserted and evicted as the blocks are repeated
+        for _ in 0..3 {
+            for block in &blocks {
+                let res = encode(&mut encoder, block.clone());
+                assert_eq!(*block, decode(&mut decoder, res));
+            }
+
+            for (i, block) in blocks.iter().enumerate() {
+                let mut block = block.clone();
+                block.push(header("x-seq", &i.to_string()));
+
+                let res = encode(&mut encoder, block.clone());
+                assert_eq!(block, decode(&mut decoder, res));
+            }
+        }
+
+        assert!(encoder.table.size() <= 256);
+    }
+
+    #[test]
+    fn test_encode_grows_buffer() {
+        let mut encoder = Encoder::default();
+        let mut decoder = Decoder::default();
+
+        let value: String = (0..1000).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+        let block = vec![
+            method("GET"),
+            header("foo", &value),
+            header("bar", &value),
+        ];
+
+        let mut dst = BytesMut::new();
+        encoder.encode(block.clone(), &mut dst);
+
+        let res: Vec<_> = decoder
+            .decode(&dst)
+            .unwrap()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        assert_eq!(block, res);
+    }
+
     #[test]
     #[ignore]
     fn test_evicted_overflow() {
@@ -813,14 +1129,31 @@ mod test {
 
     fn encode(e: &mut Encoder, hdrs: Vec<Header<Option<HeaderName>>>) -> BytesMut {
         let mut dst = BytesMut::with_capacity(1024);
-        e.encode(None, &mut hdrs.into_iter(), &mut dst);
+        e.encode_partial(None, &mut hdrs.into_iter(), &mut dst);
         dst
     }
 
+    fn decode(decoder: &mut Decoder, mut src: BytesMut) -> Vec<Header<Option<HeaderName>>> {
+        let mut res = vec![];
+        decoder
+            .decode_partial(&mut Cursor::new(&mut src), true, |h| res.push(h.into()))
+            .unwrap();
+        res
+    }
+
     fn method(s: &str) -> Header<Option<HeaderName>> {
         Header::Method(Method::from_bytes(s.as_bytes()).unwrap())
     }
 
+    fn path(s: &str) -> Header<Option<HeaderName>> {
+        let value: Bytes = s.into();
+        Header::Path(unsafe { ::string::String::from_utf8_unchecked(value) })
+    }
+
+    fn status(code: u16) -> Header<Option<HeaderName>> {
+        Header::Status(StatusCode::from_u16(code).unwrap())
+    }
+
     fn header(name: &str, val: &str) -> Header<Option<HeaderName>> {
         use http::header::{HeaderName, HeaderValue};
 
@@ -1,4 +1,4 @@
-use super::{DecoderError, NeedMore};
+use super::decoder::{DecoderError, NeedMore};
 
 use bytes::Bytes;
 use http::{Method, StatusCode};
@@ -14,6 +14,7 @@ pub enum Header<T = HeaderName> {
     Method(Method),
     Scheme(String<Bytes>),
     Path(String<Bytes>),
+    Protocol(String<Bytes>),
     Status(StatusCode),
 }
 
@@ -25,9 +26,20 @@ pub enum Name<'a> {
     Method,
     Scheme,
     Path,
+    Protocol,
     Status,
 }
 
+/// Parses the value of a pseudo-header field, which must not contain NUL, CR
+/// or LF octets (section 8.2.1 of RFC 9113).
+fn pseudo_value(value: Bytes) -> Result<String<Bytes>, DecoderError> {
+    if value.iter().any(|&b| b == 0 || b == b'\r' || b == b'\n') {
+        return Err(DecoderError::InvalidHeaderField);
+    }
+
+    Ok(String::try_from(value)?)
+}
+
 pub fn len(name: &HeaderName, value: &HeaderValue) -> usize {
     let n: &str = name.as_ref();
     32 + n.len() + value.len()
@@ -53,6 +65,7 @@ impl Header<Option<HeaderName>> {
             Method(v) => Method(v),
             Scheme(v) => Scheme(v),
             Path(v) => Path(v),
+            Protocol(v) => Protocol(v),
             Status(v) => Status(v),
         })
     }
@@ -66,7 +79,7 @@ impl Header {
         if name[0] == b':' {
             match &name[1..] {
                 b"authority" => {
-                    let value = String::try_from(value)?;
+                    let value = pseudo_value(value)?;
                     Ok(Header::Authority(value))
                 },
                 b"method" => {
@@ -74,13 +87,17 @@ impl Header {
                     Ok(Header::Method(method))
                 },
                 b"scheme" => {
-                    let value = String::try_from(value)?;
+                    let value = pseudo_value(value)?;
                     Ok(Header::Scheme(value))
                 },
                 b"path" => {
-                    let value = String::try_from(value)?;
+                    let value = pseudo_value(value)?;
                     Ok(Header::Path(value))
                 },
+                b"protocol" => {
+                    let value = pseudo_value(value)?;
+                    Ok(Header::Protocol(value))
+                },
                 b"status" => {
                     let status = StatusCode::from_bytes(&value)?;
                     Ok(Header::Status(status))
@@ -88,8 +105,9 @@ impl Header {
                 _ => Err(DecoderError::InvalidPseudoheader),
             }
         } else {
-            // HTTP/2 requires lower case header names
-            let name = HeaderName::from_lowercase(&name)?;
+            // HTTP/2 requires lower case header names. Uppercase names are
+            // lowercased here, and tracked by the decoder.
+            let name = HeaderName::from_bytes(&name)?;
             let value = HeaderValue::from_bytes(&value)?;
 
             Ok(Header::Field {
@@ -109,6 +127,7 @@ impl Header {
             Header::Method(ref v) => 32 + 7 + v.as_ref().len(),
             Header::Scheme(ref v) => 32 + 7 + v.len(),
             Header::Path(ref v) => 32 + 5 + v.len(),
+            Header::Protocol(ref v) => 32 + 9 + v.len(),
             Header::Status(_) => 32 + 7 + 3,
         }
     }
@@ -123,6 +142,7 @@ impl Header {
             Header::Method(..) => Name::Method,
             Header::Scheme(..) => Name::Scheme,
             Header::Path(..) => Name::Path,
+            Header::Protocol(..) => Name::Protocol,
             Header::Status(..) => Name::Status,
         }
     }
@@ -136,6 +156,7 @@ impl Header {
             Header::Method(ref v) => v.as_ref().as_ref(),
             Header::Scheme(ref v) => v.as_ref(),
             Header::Path(ref v) => v.as_ref(),
+            Header::Protocol(ref v) => v.as_ref(),
             Header::Status(ref v) => v.as_str().as_ref(),
         }
     }
@@ -169,6 +190,10 @@ impl Header {
                 Header::Path(ref b) => a == b,
                 _ => false,
             },
+            Header::Protocol(ref a) => match *other {
+                Header::Protocol(ref b) => a == b,
+                _ => false,
+            },
             Header::Status(ref a) => match *other {
                 Header::Status(ref b) => a == b,
                 _ => false,
@@ -186,6 +211,15 @@ impl Header {
         }
     }
 
+    /// Marks the value as sensitive.
+    ///
+    /// Only the values of regular header fields can be marked.
+    pub fn set_sensitive(&mut self) {
+        if let Header::Field { ref mut value, .. } = *self {
+            value.set_sensitive(true);
+        }
+    }
+
     pub fn skip_value_index(&self) -> bool {
         use http::header;
 
@@ -225,6 +259,7 @@ impl From<Header> for Header<Option<HeaderName>> {
             Header::Method(v) => Header::Method(v),
             Header::Scheme(v) => Header::Scheme(v),
             Header::Path(v) => Header::Path(v),
+            Header::Protocol(v) => Header::Protocol(v),
             Header::Status(v) => Header::Status(v),
         }
     }
@@ -237,10 +272,11 @@ impl<'a> Name<'a> {
                 name: name.clone(),
                 value: HeaderValue::from_bytes(&*value)?,
             }),
-            Name::Authority => Ok(Header::Authority(String::try_from(value)?)),
+            Name::Authority => Ok(Header::Authority(pseudo_value(value)?)),
             Name::Method => Ok(Header::Method(Method::from_bytes(&*value)?)),
-            Name::Scheme => Ok(Header::Scheme(String::try_from(value)?)),
-            Name::Path => Ok(Header::Path(String::try_from(value)?)),
+            Name::Scheme => Ok(Header::Scheme(pseudo_value(value)?)),
+            Name::Path => Ok(Header::Path(pseudo_value(value)?)),
+            Name::Protocol => Ok(Header::Protocol(pseudo_value(value)?)),
             Name::Status => {
                 match StatusCode::from_bytes(&value) {
                     Ok(status) => Ok(Header::Status(status)),
@@ -258,6 +294,7 @@ impl<'a> Name<'a> {
             Name::Method => b":method",
             Name::Scheme => b":scheme",
             Name::Path => b":path",
+            Name::Protocol => b":protocol",
             Name::Status => b":status",
         }
     }
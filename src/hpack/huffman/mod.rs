@@ -1,7 +1,7 @@
 mod table;
 
 use self::table::{DECODE_TABLE, ENCODE_TABLE};
-use hpack::{DecoderError, EncoderError};
+use hpack::raw::{DecoderError, EncoderError};
 
 use bytes::{BufMut, BytesMut};
 
@@ -9,6 +9,8 @@ use bytes::{BufMut, BytesMut};
 struct Decoder {
     state: usize,
     maybe_eos: bool,
+    // Number of bits read up to the end of the last decoded symbol
+    decoded_bits: usize,
 }
 
 // These flags must match the ones in genhuff.rs
@@ -25,21 +27,44 @@ pub fn decode(src: &[u8], buf: &mut BytesMut) -> Result<BytesMut, DecoderError>
 
     for b in src {
         if let Some(b) = decoder.decode4(b >> 4)? {
+            decoder.decoded(b);
             buf.put_u8(b);
         }
 
         if let Some(b) = decoder.decode4(b & 0xf)? {
+            decoder.decoded(b);
             buf.put_u8(b);
         }
     }
 
     if !decoder.is_final() {
-        return Err(DecoderError::InvalidHuffmanCode);
+        return Err(padding_error(src, decoder.decoded_bits));
     }
 
     Ok(buf.take())
 }
 
+/// Returns why the bits following the last symbol of `src` are not a valid
+/// padding (section 5.2 of RFC 7541).
+fn padding_error(src: &[u8], decoded_bits: usize) -> DecoderError {
+    let is_eos_prefix = (decoded_bits..src.len() * 8).all(|i| src[i / 8] & (0x80 >> (i % 8)) != 0);
+
+    if is_eos_prefix {
+        // Valid padding is at most 7 bits long
+        DecoderError::HuffmanPaddingTooLong
+    } else {
+        DecoderError::InvalidHuffmanPadding
+    }
+}
+
+/// Returns the number of bytes `src` takes once Huffman encoded.
+pub fn encoded_len(src: &[u8]) -> usize {
+    let bits: usize = src.iter().map(|&b| ENCODE_TABLE[b as usize].0).sum();
+
+    // The last byte is padded with the EOS prefix
+    (bits + 7) / 8
+}
+
 // TODO: return error when there is not enough room to encode the value
 pub fn encode<B: BufMut>(src: &[u8], dst: &mut B) -> Result<(), EncoderError> {
     let mut bits: u64 = 0;
@@ -83,6 +108,7 @@ impl Decoder {
         Decoder {
             state: 0,
             maybe_eos: false,
+            decoded_bits: 0,
         }
     }
 
@@ -92,8 +118,8 @@ impl Decoder {
         let (next, byte, flags) = DECODE_TABLE[self.state][input as usize];
 
         if flags & ERROR == ERROR {
-            // Data followed the EOS marker
-            return Err(DecoderError::InvalidHuffmanCode);
+            // The EOS symbol must not be decoded
+            return Err(DecoderError::HuffmanEos);
         }
 
         let mut ret = None;
@@ -108,6 +134,12 @@ impl Decoder {
         Ok(ret)
     }
 
+    // Symbols are contiguous, so the last one ends after the sum of the
+    // lengths of the decoded symbols.
+    fn decoded(&mut self, byte: u8) {
+        self.decoded_bits += ENCODE_TABLE[byte as usize].0;
+    }
+
     fn is_final(&self) -> bool {
         self.state == 0 || self.maybe_eos
     }
@@ -142,6 +174,49 @@ mod test {
         assert_eq!(" !", decode(&[0b01010011, 0b11111000]).unwrap());
     }
 
+    #[test]
+    fn decode_eos_symbol() {
+        // The EOS symbol alone
+        assert_eq!(decode(&[0xff, 0xff, 0xff, 0xfc]), Err(DecoderError::HuffmanEos));
+        // Followed by padding
+        assert_eq!(decode(&[0xff, 0xff, 0xff, 0xff]), Err(DecoderError::HuffmanEos));
+        // After `o`
+        assert_eq!(
+            decode(&[0b00111111, 0xff, 0xff, 0xff, 0xe0]),
+            Err(DecoderError::HuffmanEos)
+        );
+    }
+
+    #[test]
+    fn decode_invalid_padding() {
+        // `o` padded with `110`
+        assert_eq!(decode(&[0b00111110]), Err(DecoderError::InvalidHuffmanPadding));
+        // `o` padded with `011`
+        assert_eq!(decode(&[0b00111011]), Err(DecoderError::InvalidHuffmanPadding));
+        // `a` padded with 11 bits ending with a zero
+        assert_eq!(decode(&[0b00011111, 0xfe]), Err(DecoderError::InvalidHuffmanPadding));
+    }
+
+    #[test]
+    fn decode_padding_too_long() {
+        // A whole byte of padding
+        assert_eq!(decode(&[0xff]), Err(DecoderError::HuffmanPaddingTooLong));
+        // `o` padded with 11 bits
+        assert_eq!(decode(&[0b00111111, 0xff]), Err(DecoderError::HuffmanPaddingTooLong));
+        // `!0` padded with 8 bits
+        assert_eq!(decode(&[254, 1, 0xff]), Err(DecoderError::HuffmanPaddingTooLong));
+    }
+
+    #[test]
+    fn decode_max_padding() {
+        // Five 5 bits symbols are padded with 7 bits
+        let mut dst = Vec::new();
+        encode(b"aaaaa", &mut dst).unwrap();
+        assert_eq!(dst.len(), 4);
+        assert_eq!(dst[3] & 0x7f, 0x7f);
+        assert_eq!("aaaaa", decode(&dst).unwrap());
+    }
+
     #[test]
     fn encode_single_byte() {
         let mut dst = Vec::with_capacity(1);
@@ -210,4 +285,49 @@ mod test {
             assert_eq!(&decoded[..], &s[..]);
         }
     }
+
+    #[test]
+    fn encode_decode_every_byte() {
+        let all: Vec<u8> = (0..256).map(|b| b as u8).collect();
+
+        for b in 0..256 {
+            let s = &all[b..b + 1];
+            let mut dst = Vec::new();
+
+            encode(s, &mut dst).unwrap();
+            assert_eq!(dst.len(), encoded_len(s));
+            assert_eq!(&decode(&dst).unwrap()[..], s);
+        }
+
+        let mut dst = Vec::new();
+
+        encode(&all, &mut dst).unwrap();
+        assert_eq!(dst.len(), encoded_len(&all));
+        assert_eq!(&decode(&dst).unwrap()[..], &all[..]);
+    }
+
+    #[test]
+    fn typical_values_are_smaller() {
+        const DATA: &'static [&'static str] = &[
+            "https",
+            "www.example.com",
+            "/index.html",
+            "/api/v1/users/12345/profile?fields=name,email",
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            "gzip, deflate, br",
+            "en-US,en;q=0.9",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:60.0) Gecko/20100101 Firefox/60.0",
+            "max-age=0, no-cache",
+            "application/json; charset=utf-8",
+            "Tue, 15 May 2018 08:12:31 GMT",
+            "\"33a64df551425fcc55e4d42a148795d9f25f89d4\"",
+        ];
+
+        let raw: usize = DATA.iter().map(|s| s.len()).sum();
+        let huff: usize = DATA.iter().map(|s| encoded_len(s.as_bytes())).sum();
+
+        // Saves between 20% and 30%
+        assert!(huff * 10 <= raw * 8, "raw={}; huff={}", raw, huff);
+        assert!(huff * 10 >= raw * 7, "raw={}; huff={}", raw, huff);
+    }
 }
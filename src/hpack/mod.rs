@@ -1,12 +1,50 @@
+//! HPACK header compression, as defined by RFC 7541.
+//!
+//! The encoder and decoder work on header blocks alone, without building an
+//! `http::Request` or `http::Response`, so pseudo-header fields are kept as
+//! they are found in the block.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate bytes;
+//! # extern crate h2;
+//! use bytes::BytesMut;
+//! use h2::hpack::{Decoder, Encoder, HeaderField};
+//!
+//! # fn main() {
+//! let mut encoder = Encoder::new(4096);
+//! let mut decoder = Decoder::new(4096);
+//!
+//! let fields = vec![
+//!     HeaderField::new(":status", "200").unwrap(),
+//!     HeaderField::new("content-type", "text/plain").unwrap(),
+//! ];
+//!
+//! let mut block = BytesMut::new();
+//! encoder.encode(fields.clone(), &mut block);
+//!
+//! assert_eq!(decoder.decode(&block).unwrap(), fields);
+//! # }
+//! ```
+
+mod block;
+#[cfg_attr(feature = "unstable", allow(missing_docs))]
 mod encoder;
+#[cfg_attr(feature = "unstable", allow(missing_docs))]
 mod decoder;
+#[cfg_attr(feature = "unstable", allow(missing_docs))]
 mod header;
 mod huffman;
 mod table;
 
+#[cfg(feature = "unstable")]
+pub mod raw;
+
+#[cfg(not(feature = "unstable"))]
+pub(crate) mod raw;
+
 #[cfg(test)]
 mod test;
 
-pub use self::decoder::{Decoder, DecoderError, NeedMore};
-pub use self::encoder::{Encode, EncodeState, Encoder, EncoderError};
-pub use self::header::Header;
+pub use self::block::{Decoder, DecoderError, Encoder, HeaderField};
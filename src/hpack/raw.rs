@@ -0,0 +1,5 @@
+//! The encoder and decoder used by the codec, working on `Header` values.
+
+pub use super::decoder::{Decoder, DecoderError};
+pub use super::encoder::{Encode, EncodeState, Encoder, EncoderError};
+pub use super::header::Header;
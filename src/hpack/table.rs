@@ -1,4 +1,4 @@
-use super::Header;
+use super::header::Header;
 
 use fnv::FnvHasher;
 use http::header;
@@ -133,7 +133,13 @@ impl Table {
     /// Index the header in the HPACK table.
     pub fn index(&mut self, header: Header) -> Index {
         // Check the static table
-        let statik = index_static(&header);
+        let mut statik = index_static(&header);
+
+        // Sensitive values are always sent as literals, even when a table
+        // already holds them. Only their name may be indexed.
+        if header.is_sensitive() {
+            statik = statik.map(|(n, _)| (n, false));
+        }
 
         // Don't index certain headers. This logic is borrowed from nghttp2.
         if header.skip_value_index() {
@@ -211,6 +217,10 @@ impl Table {
             // Compute the real index into the VecDeque
             let real_idx = index.wrapping_add(self.inserted);
 
+            if header.is_sensitive() {
+                return Index::Name(real_idx + DYN_OFFSET, header);
+            }
+
             if self.slots[real_idx].header.value_eq(&header) {
                 // We have a full match!
                 return Index::Indexed(real_idx + DYN_OFFSET, header);
@@ -221,10 +231,6 @@ impl Table {
                 continue;
             }
 
-            if header.is_sensitive() {
-                return Index::Name(real_idx + DYN_OFFSET, header);
-            }
-
             self.update_size(header.len(), Some(index));
 
             // Insert the new header
@@ -735,6 +741,8 @@ fn index_static(header: &Header) -> Option<(usize, bool)> {
             "/index.html" => Some((5, true)),
             _ => Some((4, false)),
         },
+        // :protocol has no entry in the static table
+        Header::Protocol(..) => None,
         Header::Status(ref v) => match u16::from(*v) {
             200 => Some((8, true)),
             204 => Some((9, true)),
@@ -2,14 +2,13 @@ extern crate bytes;
 extern crate hex;
 extern crate serde_json;
 
-use hpack::{Decoder, Encoder, Header};
+use hpack::raw::{Decoder, Encoder, Header};
 
 use self::bytes::BytesMut;
 use self::hex::FromHex;
 use self::serde_json::Value;
 
 use std::fs::File;
-use std::io::Cursor;
 use std::io::prelude::*;
 use std::path::Path;
 use std::str;
@@ -73,13 +72,11 @@ fn test_story(story: Value) {
                 decoder.queue_size_update(size);
             }
 
-            decoder
-                .decode(&mut Cursor::new(&mut case.wire.clone().into()), |e| {
-                    let (name, value) = expect.remove(0);
-                    assert_eq!(name, key_str(&e));
-                    assert_eq!(value, value_str(&e));
-                })
-                .unwrap();
+            for e in decoder.decode(&case.wire).unwrap() {
+                let (name, value) = expect.remove(0);
+                assert_eq!(name, key_str(&e));
+                assert_eq!(value, value_str(&e));
+            }
 
             assert_eq!(0, expect.len());
         }
@@ -105,13 +102,11 @@ fn test_story(story: Value) {
                 })
                 .collect();
 
-            encoder.encode(None, &mut input.clone().into_iter(), &mut buf);
+            encoder.encode(input.clone(), &mut buf);
 
-            decoder
-                .decode(&mut Cursor::new(&mut buf), |e| {
-                    assert_eq!(e, input.remove(0).reify().unwrap());
-                })
-                .unwrap();
+            for e in decoder.decode(&buf).unwrap() {
+                assert_eq!(e, input.remove(0).reify().unwrap());
+            }
 
             assert_eq!(0, input.len());
         }
@@ -134,6 +129,7 @@ fn key_str(e: &Header) -> &str {
         Header::Method(..) => ":method",
         Header::Scheme(..) => ":scheme",
         Header::Path(..) => ":path",
+        Header::Protocol(..) => ":protocol",
         Header::Status(..) => ":status",
     }
 }
@@ -147,6 +143,7 @@ fn value_str(e: &Header) -> &str {
         Header::Method(ref m) => m.as_str(),
         Header::Scheme(ref v) => &**v,
         Header::Path(ref v) => &**v,
+        Header::Protocol(ref v) => &**v,
         Header::Status(ref v) => v.as_str(),
     }
 }
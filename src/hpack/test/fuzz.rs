@@ -2,7 +2,7 @@ extern crate bytes;
 extern crate quickcheck;
 extern crate rand;
 
-use hpack::{Decoder, Encode, Encoder, Header};
+use hpack::raw::{Decoder, Encode, Encoder, Header};
 
 use http::header::{HeaderName, HeaderValue};
 
@@ -142,14 +142,14 @@ impl FuzzHpack {
             }
 
             loop {
-                match encoder.encode(index.take(), &mut input, &mut buf) {
+                match encoder.encode_partial(index.take(), &mut input, &mut buf) {
                     Encode::Full => break,
                     Encode::Partial(i) => {
                         index = Some(i);
 
                         // Decode the chunk!
                         decoder
-                            .decode(&mut Cursor::new(&mut buf), |e| {
+                            .decode_partial(&mut Cursor::new(&mut buf), false, |e| {
                                 assert_eq!(e, expect.remove(0).reify().unwrap());
                             })
                             .unwrap();
@@ -161,7 +161,7 @@ impl FuzzHpack {
 
             // Decode the chunk!
             decoder
-                .decode(&mut Cursor::new(&mut buf), |e| {
+                .decode_partial(&mut Cursor::new(&mut buf), true, |e| {
                     assert_eq!(e, expect.remove(0).reify().unwrap());
                 })
                 .unwrap();
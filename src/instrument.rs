@@ -0,0 +1,98 @@
+//! Context tying the log lines of a connection, and of its streams, together.
+//!
+//! With the `tracing` feature, each connection gets a span carrying a
+//! connection id, entered while the connection is polled, and each stream
+//! gets a span carrying its stream id, nested in the span of its connection.
+//! Without the feature, `Span` does nothing and compiles out.
+
+use frame::Head;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+#[cfg(feature = "tracing")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "tracing")]
+static NEXT_CONNECTION_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the span of a new connection.
+#[cfg(feature = "tracing")]
+pub(crate) fn connection_span(is_server: bool) -> Span {
+    let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+    let peer = if is_server { "server" } else { "client" };
+
+    debug_span!("connection", id, peer)
+}
+
+/// Returns the span of a new stream, nested in the current span.
+///
+/// Streams are created while the span of their connection is entered.
+#[cfg(feature = "tracing")]
+pub(crate) fn stream_span(id: ::frame::StreamId) -> Span {
+    debug_span!("stream", id = u32::from(id))
+}
+
+/// Emits one event per frame sent or received, without its payload.
+#[cfg(feature = "tracing")]
+pub(crate) fn frame(direction: &'static str, head: &Head, len: usize) {
+    debug!(
+        direction,
+        kind = ?head.kind(),
+        stream_id = u32::from(head.stream_id()),
+        flags = head.flag(),
+        len,
+        "frame"
+    );
+}
+
+/// Returns true if the event of a frame received on a stream is deferred
+/// until the frame is handled, so that it is emitted in the span of the
+/// stream.
+#[cfg(feature = "tracing")]
+pub(crate) fn defers_recv_frame(head: &Head) -> bool {
+    !head.stream_id().is_zero()
+}
+
+/// A span that does nothing.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Span;
+
+/// The guard returned by `Span::enter`.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub fn enter(&self) -> Entered {
+        Entered
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn connection_span(_is_server: bool) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn stream_span(_id: ::frame::StreamId) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn defers_recv_frame(_head: &Head) -> bool {
+    false
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn frame(direction: &'static str, head: &Head, len: usize) {
+    debug!(
+        "{} frame; kind={:?}; stream_id={:?}; flags={:#x}; len={}",
+        direction,
+        head.kind(),
+        head.stream_id(),
+        head.flag(),
+        len
+    );
+}
@@ -72,6 +72,34 @@
 //! Managing flow control for outbound data is done through [`SendStream`]. See
 //! the struct level documentation for those two types for more details.
 //!
+//! # Timeouts
+//!
+//! The timeouts configured with the client and server builders, e.g.
+//! [`server::Builder::idle_timeout`], wake the connection up when they
+//! expire. This uses the default timer of the executor, e.g. the one of a
+//! `tokio` runtime, and requires the `timer` feature, which is enabled by
+//! default.
+//!
+//! Without the feature, or when the executor has no timer, the timeouts are
+//! only checked when the connection happens to be polled for another reason,
+//! such as a frame being received. An idle connection is then never closed,
+//! nor is a dead peer detected by keep-alive PINGs. A warning is logged when
+//! a timeout is set but no timer is available.
+//!
+//! # Outbound data type
+//!
+//! The chunks of request and response bodies, and of pushed responses, are
+//! of a type chosen by the user: any type implementing [`IntoBuf`], which
+//! defaults to [`Bytes`]. Other types are chosen with the `handshake` function
+//! of [`client::Builder`] or [`server::Builder`], e.g. a `Cursor<Vec<u8>>`,
+//! a `Cursor` over a memory-mapped file or a [`Chain`] of buffers.
+//!
+//! Chunks are not copied into another buffer type before being sent. When
+//! flow control or the max frame size only allows part of a chunk to be sent,
+//! the rest of the chunk is sent later. Large DATA payloads are written to the
+//! connection directly from the chunk, while small ones are copied to the
+//! write buffer alongside the other frames.
+//!
 //! [HTTP/2.0]: https://http2.github.io/
 //! [futures]: https://docs.rs/futures/
 //! [`client`]: client/index.html
@@ -83,6 +111,12 @@
 //! [upgrade]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Protocol_upgrade_mechanism
 //! [`server::handshake`]: server/fn.handshake.html
 //! [`client::handshake`]: client/fn.handshake.html
+//! [`IntoBuf`]: https://docs.rs/bytes/0.4/bytes/trait.IntoBuf.html
+//! [`server::Builder::idle_timeout`]: server/struct.Builder.html#method.idle_timeout
+//! [`Bytes`]: https://docs.rs/bytes/0.4/bytes/struct.Bytes.html
+//! [`Chain`]: https://docs.rs/bytes/0.4/bytes/buf/struct.Chain.html
+//! [`client::Builder`]: client/struct.Builder.html
+//! [`server::Builder`]: server/struct.Builder.html
 
 #![doc(html_root_url = "https://docs.rs/h2/0.1.12")]
 #![deny(missing_debug_implementations, missing_docs)]
@@ -99,6 +133,7 @@ extern crate http;
 
 // Buffer utilities
 extern crate bytes;
+extern crate iovec;
 
 // Hash function used for HPACK encoding and tracking stream states.
 extern crate fnv;
@@ -106,17 +141,34 @@ extern crate fnv;
 extern crate byteorder;
 extern crate slab;
 
+#[cfg(not(feature = "tracing"))]
 #[macro_use]
 extern crate log;
+
+// With the `tracing` feature, the `trace!` and `debug!` lines are emitted as
+// `tracing` events, which carry the spans of the connection and stream.
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
 extern crate string;
 extern crate indexmap;
 
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "timer")]
+extern crate tokio_timer;
+
 mod error;
+mod h2c;
+mod instrument;
+mod timer;
 #[cfg_attr(feature = "unstable", allow(missing_docs))]
 mod codec;
-mod hpack;
 mod proto;
 
+pub mod hpack;
+
 #[cfg(not(feature = "unstable"))]
 mod frame;
 
@@ -128,8 +180,16 @@ pub mod client;
 pub mod server;
 mod share;
 
+#[cfg(feature = "test-util")]
+pub mod test;
+
 pub use error::{Error, Reason};
-pub use share::{SendStream, StreamId, RecvStream, ReleaseCapacity};
+pub use share::{SendStream, StreamId, StreamDependency, Priority, RecvStream, ReleaseCapacity, PeerSettings};
+pub use share::FlowControlMode;
+pub use share::{AbortHandle, ConnectionStats, SettingsAcked, SettingsChanges, SettingsUpdate};
+pub use share::{Ping, PingPong, Pong, SendPing};
+pub use share::{DataFrameInfo, ExtensionFrame, Protocol};
+pub use codec::{Direction, FlushStrategy, FrameCounts, FrameView};
 
 #[cfg(feature = "unstable")]
 pub use codec::{Codec, RecvError, SendError, UserError};
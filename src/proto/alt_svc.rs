@@ -0,0 +1,90 @@
+use client;
+use codec::Codec;
+use frame;
+
+use bytes::Buf;
+use futures::{Async, Poll};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tokio_io::AsyncWrite;
+
+/// Handles the ALTSVC frames of the connection (RFC 7838).
+///
+/// Servers queue frames advertising alternative services for the whole
+/// connection, while clients pass the frames they receive to a user callback.
+#[derive(Debug)]
+pub(super) struct AltSvcs {
+    /// Connection level ALTSVC frames waiting to be written.
+    pending: VecDeque<frame::AltSvc>,
+
+    /// Called with every alternative service advertised by the peer.
+    on_alt_svc: Option<OnAltSvc>,
+}
+
+/// A user callback receiving the alternative services advertised by the
+/// server.
+#[derive(Clone)]
+pub(crate) struct OnAltSvc(Arc<Fn(client::AltSvc) + Send + Sync>);
+
+impl AltSvcs {
+    pub fn new(on_alt_svc: Option<OnAltSvc>) -> Self {
+        AltSvcs {
+            pending: VecDeque::new(),
+            on_alt_svc: on_alt_svc,
+        }
+    }
+
+    /// Queues an ALTSVC frame on the stream 0.
+    pub fn send_alt_svc(&mut self, frame: frame::AltSvc) {
+        debug_assert!(frame.stream_id().is_zero());
+        self.pending.push_back(frame);
+    }
+
+    /// Handles an ALTSVC frame received by a client.
+    pub fn recv_alt_svc(&mut self, frame: frame::AltSvc) {
+        if frame.is_ignored() {
+            trace!("recv_alt_svc; ignoring invalid frame; frame={:?}", frame);
+            return;
+        }
+
+        if let Some(ref f) = self.on_alt_svc {
+            (f.0)(client::AltSvc::from_internal(frame));
+        }
+    }
+
+    /// Writes the pending ALTSVC frames.
+    pub fn send_pending_alt_svc<T, B>(&mut self, dst: &mut Codec<T, B>) -> Poll<(), io::Error>
+    where
+        T: AsyncWrite,
+        B: Buf,
+    {
+        while let Some(frame) = self.pending.pop_front() {
+            if !dst.poll_ready()?.is_ready() {
+                self.pending.push_front(frame);
+                return Ok(Async::NotReady);
+            }
+
+            dst.buffer(frame.into())
+                .expect("invalid ALTSVC frame");
+        }
+
+        Ok(Async::Ready(()))
+    }
+}
+
+impl OnAltSvc {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(client::AltSvc) + Send + Sync + 'static,
+    {
+        OnAltSvc(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for OnAltSvc {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("OnAltSvc").finish()
+    }
+}
@@ -1,17 +1,21 @@
-use {client, frame, proto, server};
-use codec::RecvError;
+use {client, frame, instrument, proto, server};
+use codec::{Padding, RecvError, UserError};
 use frame::{Reason, StreamId};
 
 use frame::DEFAULT_INITIAL_WINDOW_SIZE;
+use instrument::Span;
 use proto::*;
+use timer::Timer;
+use super::ping_pong::{BdpWindows, ReceivedPing};
 
 use bytes::{Bytes, IntoBuf};
 use futures::Stream;
+use futures::sync::oneshot;
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use std::marker::PhantomData;
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// An H2 connection
 #[derive(Debug)]
@@ -22,11 +26,12 @@ where
     /// Tracks the connection level state transitions.
     state: State,
 
-    /// An error to report back once complete.
+    /// The GOAWAY frame received from the peer, reported back once
+    /// complete.
     ///
     /// This exists separately from State in order to support
     /// graceful shutdown.
-    error: Option<Reason>,
+    error: Option<frame::GoAway>,
 
     /// Read / write frame values
     codec: Codec<T, Prioritized<B::Buf>>,
@@ -37,12 +42,27 @@ where
     /// Ping/pong handler
     ping_pong: PingPong,
 
+    /// Idle timeout and maximum age
+    lifetime: Lifetime,
+
+    /// ALTSVC frames handler
+    alt_svc: AltSvcs,
+
+    /// Extension frames handler
+    extensions: Extensions,
+
     /// Connection settings
     settings: Settings,
 
     /// Stream state handler
     streams: Streams<B::Buf, P>,
 
+    /// Wakes the connection up when the next timeout expires
+    timer: Timer,
+
+    /// Span entered while the connection is polled
+    span: Span,
+
     /// Client or server
     _phantom: PhantomData<P>,
 }
@@ -53,7 +73,36 @@ pub(crate) struct Config {
     pub initial_max_send_streams: usize,
     pub reset_stream_duration: Duration,
     pub reset_stream_max: usize,
+    pub remote_reset_stream_max: Option<usize>,
+    pub remote_reset_stream_duration: Duration,
+    pub max_pending_accept_streams: Option<usize>,
+    pub max_pending_open_streams: Option<usize>,
+    pub max_reserved_remote_streams: usize,
+    pub max_buffered_recv_bytes_per_stream: Option<usize>,
+    pub buffered_recv_timeout: Option<Duration>,
     pub settings: frame::Settings,
+    pub settings_ack_timeout: Option<Duration>,
+    pub coalesce_settings_updates: bool,
+    pub remote_settings_deadline: Option<Instant>,
+    pub graceful_shutdown_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+    pub max_connection_age: Option<Duration>,
+    pub max_connection_age_grace: Option<Duration>,
+    pub keep_alive_interval: Option<Duration>,
+    pub keep_alive_timeout: Duration,
+    pub max_pings_per_interval: Option<usize>,
+    pub ping_interval: Duration,
+    pub window_update_threshold: u8,
+    pub flow_control_mode: ::FlowControlMode,
+    pub window_stall_warning: Option<Duration>,
+    pub max_adaptive_window_size: Option<WindowSize>,
+    pub on_unknown_setting: Option<OnUnknownSetting>,
+    pub on_alt_svc: Option<OnAltSvc>,
+    pub on_extension_frame: Option<OnExtensionFrame>,
+    pub padding: Option<Padding>,
+    pub max_send_header_block_size: Option<usize>,
+    pub auto_100_continue: bool,
+    pub accept_malformed_requests: bool,
 }
 
 #[derive(Debug)]
@@ -78,34 +127,134 @@ where
         codec: Codec<T, Prioritized<B::Buf>>,
         config: Config,
     ) -> Connection<T, P, B> {
+        let span = instrument::connection_span(P::is_server());
+
         let streams = Streams::new(streams::Config {
-            local_init_window_sz: config.settings
-                .initial_window_size()
-                .unwrap_or(DEFAULT_INITIAL_WINDOW_SIZE),
+            // The advertised initial window size only takes effect once the
+            // peer acknowledges the SETTINGS frame.
+            local_init_window_sz: DEFAULT_INITIAL_WINDOW_SIZE,
             initial_max_send_streams: config.initial_max_send_streams,
             local_next_stream_id: config.next_stream_id,
             local_push_enabled: config.settings.is_push_enabled(),
+            local_connect_protocol_enabled: config.settings
+                .is_extended_connect_protocol_enabled(),
+            // Only servers schedule responses using the priority parameters
+            // sent by clients.
+            local_extensible_priorities: P::is_server() && config.settings
+                .is_rfc7540_priorities_disabled(),
+            local_auto_100_continue: config.auto_100_continue,
+            local_accept_malformed_requests: config.accept_malformed_requests,
             local_reset_duration: config.reset_stream_duration,
             local_reset_max: config.reset_stream_max,
+            local_max_pending_accept: config.max_pending_accept_streams,
+            local_max_pending_open: config.max_pending_open_streams,
+            local_max_reserved_remote: config.max_reserved_remote_streams,
+            local_max_buffered_recv_data: config.max_buffered_recv_bytes_per_stream,
+            local_buffered_recv_timeout: config.buffered_recv_timeout,
+            remote_reset_max: config.remote_reset_stream_max,
+            remote_reset_duration: config.remote_reset_stream_duration,
+            local_window_update_threshold: config.window_update_threshold,
+            local_manual_flow_control: config.flow_control_mode == ::FlowControlMode::Manual,
+            local_window_stall_warning: config.window_stall_warning,
             remote_init_window_sz: DEFAULT_INITIAL_WINDOW_SIZE,
             remote_max_initiated: config.settings
                 .max_concurrent_streams()
                 .map(|max| max as usize),
+            local_padding: config.padding.clone(),
+            local_max_send_header_block_size: config.max_send_header_block_size,
+            span: span.clone(),
         });
         Connection {
             state: State::Open,
             error: None,
             codec: codec,
-            go_away: GoAway::new(),
-            ping_pong: PingPong::new(),
-            settings: Settings::new(),
+            go_away: GoAway::new(config.graceful_shutdown_timeout),
+            ping_pong: PingPong::new(
+                config.keep_alive_interval,
+                config.keep_alive_timeout,
+                config.max_pings_per_interval,
+                config.ping_interval,
+                config.max_adaptive_window_size.map(|max| {
+                    let window = config.settings
+                        .initial_window_size()
+                        .unwrap_or(DEFAULT_INITIAL_WINDOW_SIZE);
+                    (window, max)
+                }),
+            ),
+            lifetime: Lifetime::new(
+                config.idle_timeout,
+                config.max_connection_age,
+                config.max_connection_age_grace,
+            ),
+            alt_svc: AltSvcs::new(config.on_alt_svc),
+            extensions: Extensions::new(config.on_extension_frame),
+            settings: Settings::new(
+                config.settings,
+                config.settings_ack_timeout,
+                config.coalesce_settings_updates,
+                config.on_unknown_setting,
+                config.remote_settings_deadline,
+            ),
             streams: streams,
+            timer: Timer::default(),
+            span: span,
             _phantom: PhantomData,
         }
     }
 
     pub fn set_target_window_size(&mut self, size: WindowSize) {
         self.streams.set_target_connection_window_size(size);
+        self.ping_pong.set_connection_window(size);
+    }
+
+    /// Caps the header table size used to encode the header blocks sent to
+    /// the peer, from the next header block.
+    pub fn set_max_send_header_table_size(&mut self, size: usize) {
+        self.codec.set_max_send_header_table_size(size);
+    }
+
+    /// Returns the smoothed round-trip time measured with PING frames, if
+    /// any was acknowledged yet.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.ping_pong.rtt()
+    }
+
+    /// Returns the handle used to send PINGs from user code, the first time
+    /// this is called.
+    pub fn take_user_pings(&mut self) -> Option<UserPings> {
+        self.ping_pong.take_user_pings()
+    }
+
+    /// Queues an ALTSVC frame advertising alternative services for `origin`.
+    pub fn send_alt_svc(&mut self, origin: Bytes, field_value: Bytes) {
+        self.alt_svc.send_alt_svc(frame::AltSvc::new(StreamId::zero(), origin, field_value));
+    }
+
+    /// Queues a frame of a type unknown to this library.
+    pub fn send_extension_frame(&mut self, frame: frame::Extension) -> Result<(), UserError> {
+        let max_frame_size = self.codec.max_send_frame_size();
+        self.extensions.send_extension_frame(frame, max_frame_size)
+    }
+
+    /// Returns the settings received from the remote peer so far.
+    pub fn remote_settings(&self) -> frame::Settings {
+        self.streams.remote_settings()
+    }
+
+    /// Queues a SETTINGS frame to be sent to the peer. The returned receiver
+    /// completes once the peer acknowledges it.
+    pub fn update_settings(
+        &mut self,
+        settings: frame::Settings,
+        reset: &[u16],
+    ) -> oneshot::Receiver<()> {
+        self.settings.update_local(settings, reset)
+    }
+
+    /// Returns a handle yielding each settings delta applied from the remote
+    /// peer from now on.
+    pub fn settings_changes(&self) -> SettingsChanges {
+        self.streams.settings_changes()
     }
 
     /// Returns `Ready` when the connection is ready to receive a frame.
@@ -120,6 +269,9 @@ where
             self.settings
                 .send_pending_ack(&mut self.codec, &mut self.streams)
         );
+        try_ready!(self.settings.send_pending_local(&mut self.codec));
+        try_ready!(self.alt_svc.send_pending_alt_svc(&mut self.codec));
+        try_ready!(self.extensions.send_pending_extension_frames(&mut self.codec));
         try_ready!(self.streams.send_pending_refusal(&mut self.codec));
 
         Ok(().into())
@@ -140,31 +292,64 @@ where
     }
 
     pub fn go_away_now(&mut self, e: Reason) {
+        self.go_away_now_with_debug_data(e, Bytes::new());
+    }
+
+    /// Like `go_away_now`, but the GOAWAY frame carries `debug_data`.
+    ///
+    /// The debug data is truncated to fit in a frame of the smallest size
+    /// the peer may advertise.
+    pub fn go_away_now_with_debug_data(&mut self, e: Reason, mut debug_data: Bytes) {
+        // The GOAWAY payload starts with 8 bytes of stream ID and error code.
+        let max = frame::DEFAULT_MAX_FRAME_SIZE as usize - 8;
+        debug_data.truncate(max);
+
         let last_processed_id = self.streams.last_processed_id();
-        let frame = frame::GoAway::new(last_processed_id, e);
+        let frame = frame::GoAway::with_debug_data(last_processed_id, e, debug_data);
         self.go_away.go_away_now(frame);
     }
 
+    /// Closes the connection with a GOAWAY frame carrying `reason` and
+    /// `debug_data`, failing all streams with that error code.
+    pub fn abort(&mut self, reason: Reason, debug_data: Bytes) {
+        self.streams.recv_err(&proto::Error::GoAway(Bytes::new(), reason, Initiator::Local));
+        self.go_away_now_with_debug_data(reason, debug_data);
+    }
+
+    /// Returns a snapshot of the statistics of the connection.
+    pub fn stats(&self) -> ::ConnectionStats {
+        let mut stats = self.streams.stats();
+
+        stats.bytes_sent = self.codec.bytes_sent();
+        stats.bytes_received = self.codec.bytes_received();
+        stats.frames_sent = self.codec.frames_sent();
+        stats.frames_received = self.codec.frames_received();
+        stats.buffered_send_data += self.codec.buffered_len();
+
+        stats
+    }
+
+    /// Returns a handle used to abort the connection from other tasks.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.streams.abort_handle()
+    }
+
     fn take_error(&mut self, ours: Reason) -> Poll<(), proto::Error> {
-        let reason = if let Some(theirs) = self.error.take() {
-            match (ours, theirs) {
-                // If either side reported an error, return that
-                // to the user.
-                (Reason::NO_ERROR, err) | (err, Reason::NO_ERROR) => err,
-                // If both sides reported an error, give their
-                // error back to th user. We assume our error
-                // was a consequence of their error, and less
-                // important.
-                (_, theirs) => theirs,
+        if let Some(frame) = self.error.take() {
+            // If either side reported an error, return that to the user. If
+            // both sides reported an error, give their error back to the
+            // user. We assume our error was a consequence of their error, and
+            // less important.
+            if frame.reason() != Reason::NO_ERROR {
+                let debug_data = frame.debug_data().clone();
+                return Err(proto::Error::GoAway(debug_data, frame.reason(), Initiator::Remote));
             }
-        } else {
-            ours
-        };
+        }
 
-        if reason == Reason::NO_ERROR {
+        if ours == Reason::NO_ERROR {
             Ok(().into())
         } else {
-            Err(proto::Error::Proto(reason))
+            Err(proto::Error::GoAway(Bytes::new(), ours, Initiator::Local))
         }
     }
 
@@ -172,14 +357,37 @@ where
     /// iff there are no streams or references
     pub fn maybe_close_connection_if_no_streams(&mut self) {
         // If we poll() and realize that there are no streams or references
-        // then we can close the connection by transitioning to GOAWAY
-        if self.streams.num_active_streams() == 0 && !self.streams.has_streams_or_other_references() {
+        // then we can close the connection by transitioning to GOAWAY. A
+        // connection that ran out of stream IDs is closed as well, as no
+        // more requests can be sent on it.
+        if self.streams.num_active_streams() == 0
+            && (!self.streams.has_streams_or_other_references()
+                || self.streams.is_stream_id_exhausted())
+        {
             self.go_away_now(Reason::NO_ERROR);
         }
     }
 
     /// Advances the internal state of the connection.
     pub fn poll(&mut self) -> Poll<(), proto::Error> {
+        let span = self.span.clone();
+        let _enter = span.enter();
+
+        let ret = self.poll_state();
+
+        if let Ok(Async::NotReady) = ret {
+            let deadline = self.deadline();
+            self.timer.poll_at(deadline);
+        }
+
+        // The frame tap is only called here, while the streams are not locked,
+        // so that it may use them.
+        self.codec.call_frame_tap();
+
+        ret
+    }
+
+    fn poll_state(&mut self) -> Poll<(), proto::Error> {
         use codec::RecvError::*;
 
         loop {
@@ -192,6 +400,12 @@ where
                         Ok(Async::Ready(())) => self.state = State::Closing(Reason::NO_ERROR),
                         // The connection is not ready to make progress
                         Ok(Async::NotReady) => {
+                            // The PONG of a received PING is still pending
+                            // when the codec was busy. It goes before the
+                            // frames of the streams, which may start a header
+                            // block that holds up every other frame.
+                            try_ready!(self.ping_pong.send_pending_pong(&mut self.codec));
+
                             // Ensure all window updates have been sent.
                             //
                             // This will also handle flushing `self.codec`
@@ -223,7 +437,7 @@ where
                             }
 
                             // Reset all active streams
-                            self.streams.recv_err(&e.into());
+                            self.streams.recv_err(&proto::Error::GoAway(Bytes::new(), e, Initiator::Local));
                             self.go_away_now(e);
                         },
                         // Attempting to read a frame resulted in a stream level error.
@@ -236,6 +450,15 @@ where
                             trace!("stream level error; id={:?}; reason={:?}", id, reason);
                             self.streams.send_reset(id, reason);
                         },
+                        // Normally handled by `poll2`. If it gets here anyway,
+                        // the DATA frame is treated as a stream level error.
+                        Err(DataTooBig {
+                            id,
+                            len,
+                        }) => {
+                            trace!("recv DATA too big; id={:?}; len={}", id, len);
+                            self.streams.send_reset(id, Reason::FRAME_SIZE_ERROR);
+                        },
                         // Attempting to read a frame resulted in an I/O error. All
                         // active streams must be reset.
                         //
@@ -245,6 +468,7 @@ where
 
                             // Reset all active streams
                             self.streams.recv_err(&e);
+                            self.ping_pong.recv_err(&e);
 
                             // Return the error
                             return Err(e);
@@ -259,7 +483,15 @@ where
                     // Transition the state to error
                     self.state = State::Closed(reason);
                 },
-                State::Closed(reason) => return self.take_error(reason),
+                State::Closed(reason) => {
+                    let res = self.take_error(reason);
+
+                    if let Err(ref e) = res {
+                        self.ping_pong.recv_err(e);
+                    }
+
+                    return res;
+                },
             }
         }
     }
@@ -271,6 +503,20 @@ where
         // check and then comparison of the queue possibly multiple times a
         // second (and thus, the clock wouldn't have changed enough to matter).
         self.clear_expired_reset_streams();
+        self.streams.clear_stalled_recv_streams();
+        self.streams.report_window_stalls();
+        self.settings.check_ack_timeout()?;
+        self.settings.check_remote_deadline()?;
+
+        if self.go_away.is_graceful_timed_out() {
+            debug!("shutdown PING not acknowledged in time; sending final GOAWAY");
+            self.finish_graceful_shutdown();
+        }
+
+        if let Some((reason, debug_data)) = self.streams.take_pending_abort() {
+            debug!("connection aborted by user; reason={:?}", reason);
+            self.abort(reason, debug_data);
+        }
 
         loop {
             // First, ensure that the `Connection` is able to receive a frame
@@ -287,13 +533,55 @@ where
             }
             try_ready!(self.poll_ready());
 
-            match try_ready!(self.codec.poll()) {
+            let frame = match self.codec.poll() {
+                Ok(Async::Ready(frame)) => frame,
+                Ok(Async::NotReady) => {
+                    // Nothing left to read, check on the peer if the
+                    // connection has been idle for long enough.
+                    if self.ping_pong.check_keep_alive()? {
+                        continue;
+                    }
+
+                    return Ok(Async::NotReady);
+                },
+                // The payload was discarded by the codec, but it still counts
+                // against the connection window.
+                Err(RecvError::DataTooBig { id, len }) => {
+                    trace!("recv DATA too big; id={:?}; len={}", id, len);
+                    self.ping_pong.recv_frame();
+                    self.lifetime.recv_frame();
+                    self.streams.recv_data_too_big(id, len)?;
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
+
+            if frame.is_some() {
+                self.ping_pong.recv_frame();
+                self.lifetime.recv_frame();
+            }
+
+            // Frames received on a stream are logged in its span, before
+            // they may close it. A HEADERS frame may open its stream, which
+            // only has a span once the frame is handled.
+            let mut recv_frames = self.codec.take_recv_frames();
+            let is_headers = match frame {
+                Some(Headers(_)) => true,
+                _ => false,
+            };
+
+            self.log_recv_frames(&mut recv_frames, is_headers);
+
+            match frame {
                 Some(Headers(frame)) => {
                     trace!("recv HEADERS; frame={:?}", frame);
-                    self.streams.recv_headers(frame)?;
+                    let res = self.streams.recv_headers(frame);
+                    self.log_recv_frames(&mut recv_frames, false);
+                    res?;
                 },
                 Some(Data(frame)) => {
                     trace!("recv DATA; frame={:?}", frame);
+                    self.ping_pong.recv_data(frame.payload().len());
                     self.streams.recv_data(frame)?;
                 },
                 Some(Reset(frame)) => {
@@ -306,7 +594,7 @@ where
                 },
                 Some(Settings(frame)) => {
                     trace!("recv SETTINGS; frame={:?}", frame);
-                    self.settings.recv_settings(frame);
+                    self.settings.recv_settings(frame, &mut self.codec, &mut self.streams)?;
                 },
                 Some(GoAway(frame)) => {
                     trace!("recv GOAWAY; frame={:?}", frame);
@@ -315,19 +603,20 @@ where
                     // until they are all EOS. Once they are, State should
                     // transition to GoAway.
                     self.streams.recv_go_away(&frame)?;
-                    self.error = Some(frame.reason());
+                    self.error = Some(frame);
                 },
                 Some(Ping(frame)) => {
                     trace!("recv PING; frame={:?}", frame);
-                    let status = self.ping_pong.recv_ping(frame);
-                    if status.is_shutdown() {
+                    let status = self.ping_pong.recv_ping(frame)?;
+                    if let ReceivedPing::Bdp(windows) = status {
+                        self.grow_windows(windows);
+                    } else if status.is_shutdown() {
                         assert!(
                             self.go_away.is_going_away(),
                             "received unexpected shutdown ping"
                         );
 
-                        let last_processed_id = self.streams.last_processed_id();
-                        self.go_away(last_processed_id, Reason::NO_ERROR);
+                        self.finish_graceful_shutdown();
                     }
                 },
                 Some(WindowUpdate(frame)) => {
@@ -336,21 +625,98 @@ where
                 },
                 Some(Priority(frame)) => {
                     trace!("recv PRIORITY; frame={:?}", frame);
-                    // TODO: handle
+                    self.streams.recv_priority(frame)?;
+                },
+                Some(AltSvc(frame)) => {
+                    trace!("recv ALTSVC; frame={:?}", frame);
+
+                    // > The ALTSVC frame is intended for receipt by clients. A
+                    // > device acting as a server MUST ignore it.
+                    if !P::is_server() {
+                        self.alt_svc.recv_alt_svc(frame);
+                    }
+                },
+                Some(PriorityUpdate(frame)) => {
+                    trace!("recv PRIORITY_UPDATE; frame={:?}", frame);
+                    self.streams.recv_priority_update(frame)?;
+                },
+                Some(Extension(frame)) => {
+                    trace!("recv extension frame; frame={:?}", frame);
+                    self.extensions.recv_extension_frame(frame);
                 },
                 None => {
                     trace!("codec closed");
-                    self.streams.recv_eof(false)
-                        .ok().expect("mutex poisoned");
+                    self.streams.recv_eof(false).expect("mutex poisoned");
                     return Ok(Async::Ready(()));
                 },
             }
         }
     }
 
+    /// Grows the connection window and the initial window of streams,
+    /// following a bandwidth-delay product measurement.
+    ///
+    /// The stream windows grow once the peer acknowledges the SETTINGS frame.
+    fn grow_windows(&mut self, windows: BdpWindows) {
+        if let Some(window) = windows.connection {
+            self.streams.grow_target_connection_window_size(window);
+        }
+
+        if let Some(window) = windows.stream {
+            let mut settings = frame::Settings::default();
+            settings.set_initial_window_size(Some(window));
+
+            // Nothing waits on the acknowledgement.
+            let _ = self.settings.update_local(settings, &[]);
+        }
+    }
+
+    /// Sends the final GOAWAY of a graceful shutdown, if one is in progress.
+    ///
+    /// New streams above the last processed stream ID are refused from then
+    /// on.
+    fn finish_graceful_shutdown(&mut self) {
+        if self.go_away.finish_graceful() {
+            let last_processed_id = self.streams.last_processed_id();
+            self.go_away(last_processed_id, Reason::NO_ERROR);
+        }
+    }
+
+    /// Logs the frames received on a stream, in the span of their stream if
+    /// it is known.
+    ///
+    /// Frames of unknown streams are kept in `frames` if `keep_unknown` is
+    /// set, and logged outside of any stream span otherwise.
+    fn log_recv_frames(&self, frames: &mut Vec<(frame::Head, usize)>, keep_unknown: bool) {
+        frames.retain(|&(ref head, len)| {
+            let span = self.streams.span(head.stream_id());
+
+            if span.is_none() && keep_unknown {
+                return true;
+            }
+
+            let _enter = span.as_ref().map(Span::enter);
+            instrument::frame("recv", head, len);
+            false
+        });
+    }
+
     fn clear_expired_reset_streams(&mut self) {
         self.streams.clear_expired_reset_streams();
     }
+
+    /// Returns when the next timeout checked by `poll` expires, if any.
+    fn deadline(&self) -> Option<Instant> {
+        let deadlines = [
+            self.lifetime.deadline(self.streams.num_active_streams()),
+            self.ping_pong.keep_alive_deadline(),
+            self.settings.deadline(),
+            self.go_away.graceful_deadline(),
+            self.streams.stalled_recv_deadline(),
+        ];
+
+        deadlines.iter().filter_map(|&deadline| deadline).min()
+    }
 }
 
 impl<T, B> Connection<T, client::Peer, B>
@@ -372,6 +738,56 @@ where
         self.streams.next_incoming()
     }
 
+    pub fn next_malformed(&mut self) -> Option<StreamRef<B::Buf>> {
+        self.streams.next_malformed()
+    }
+
+    /// Receives the HTTP/1.1 request the connection was upgraded from, along
+    /// with the settings carried by its `HTTP2-Settings` header field.
+    ///
+    /// `request` opens the stream 1, which is half-closed (remote) since the
+    /// request was fully received over HTTP/1.1.
+    pub fn recv_upgrade(
+        &mut self,
+        request: frame::Headers,
+        settings: frame::Settings,
+    ) -> Result<(), proto::Error> {
+        debug_assert_eq!(request.stream_id(), StreamId::from(1));
+        debug_assert!(request.is_end_stream());
+
+        self.settings.apply_remote(&settings, &mut self.codec, &mut self.streams)
+            .and_then(|_| self.streams.recv_headers(request))
+            .map_err(|e| match e {
+                RecvError::Connection(reason) => {
+                    proto::Error::GoAway(Bytes::new(), reason, Initiator::Local)
+                },
+                RecvError::Stream { reason, .. } => proto::Error::Reset(reason, Initiator::Local),
+                RecvError::DataTooBig { .. } => {
+                    proto::Error::Reset(Reason::FRAME_SIZE_ERROR, Initiator::Local)
+                },
+                RecvError::Io(e) => e.into(),
+            })
+    }
+
+    /// Closes the connection if it has been idle for too long, and shuts it
+    /// down gracefully once it reached its maximum age.
+    pub fn check_lifetime(&mut self) {
+        if self.lifetime.is_idle_timed_out(self.streams.num_active_streams()) {
+            debug!("connection idle for too long; closing");
+            self.go_away_now(Reason::NO_ERROR);
+        }
+
+        if self.lifetime.is_expired() {
+            debug!("connection reached its maximum age; shutting down gracefully");
+            self.go_away_gracefully();
+        }
+
+        if self.lifetime.is_grace_elapsed() {
+            debug!("streams still active past the maximum age; closing");
+            self.abort(Reason::NO_ERROR, Bytes::new());
+        }
+    }
+
     // Graceful shutdown only makes sense for server peers.
     pub fn go_away_gracefully(&mut self) {
         if self.go_away.is_going_away() {
@@ -390,7 +806,8 @@ where
         // > send another GOAWAY frame with an updated last stream identifier.
         // > This ensures that a connection can be cleanly shut down without
         // > losing requests.
-        self.go_away(StreamId::MAX, Reason::NO_ERROR);
+        self.streams.send_go_away(StreamId::MAX);
+        self.go_away.go_away_gracefully(frame::GoAway::new(StreamId::MAX, Reason::NO_ERROR));
 
         // We take the advice of waiting 1 RTT literally, and wait
         // for a pong before proceeding.
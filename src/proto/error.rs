@@ -1,44 +1,67 @@
 use codec::{RecvError, SendError};
 use frame::Reason;
 
+use bytes::Bytes;
+
 use std::io;
+use std::sync::Arc;
 
 /// Either an H2 reason  or an I/O error
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
-    Proto(Reason),
-    Io(io::Error),
+    /// The stream was reset with `RST_STREAM`.
+    Reset(Reason, Initiator),
+
+    /// The connection was closed with a GOAWAY frame, possibly including
+    /// debug data.
+    GoAway(Bytes, Reason, Initiator),
+
+    /// The stream was not processed by the peer, which sent a GOAWAY frame
+    /// with a lower last stream ID.
+    Refused(Reason),
+
+    /// The stream was reset with `PROTOCOL_ERROR` because the peer sent a
+    /// malformed request or response.
+    Malformed,
+
+    /// The connection failed with an I/O error, which is shared by all of
+    /// its streams.
+    Io(Arc<io::Error>),
 }
 
-impl Error {
-    /// Clone the error for internal purposes.
-    ///
-    /// `io::Error` is not `Clone`, so we only copy the `ErrorKind`.
-    pub(super) fn shallow_clone(&self) -> Error {
-        match *self {
-            Error::Proto(reason) => Error::Proto(reason),
-            Error::Io(ref io) => Error::Io(io::Error::from(io.kind())),
-        }
-    }
+/// Which endpoint reset a stream or closed the connection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Initiator {
+    /// The local endpoint, either because of an error detected by the
+    /// library or because of an action taken by the user.
+    Local,
+
+    /// The remote peer.
+    Remote,
 }
 
-impl From<Reason> for Error {
-    fn from(src: Reason) -> Self {
-        Error::Proto(src)
-    }
+/// Takes an I/O error out of the `Arc` it is shared in.
+///
+/// `io::Error` is not `Clone`, so an error still shared with other streams is
+/// copied by its kind and message.
+pub fn unshare_io(e: Arc<io::Error>) -> io::Error {
+    Arc::try_unwrap(e).unwrap_or_else(|e| io::Error::new(e.kind(), e.to_string()))
 }
 
 impl From<io::Error> for Error {
     fn from(src: io::Error) -> Self {
-        Error::Io(src)
+        Error::Io(Arc::new(src))
     }
 }
 
 impl From<Error> for RecvError {
     fn from(src: Error) -> RecvError {
         match src {
-            Error::Proto(reason) => RecvError::Connection(reason),
-            Error::Io(e) => RecvError::Io(e),
+            Error::Reset(reason, _) |
+            Error::GoAway(_, reason, _) |
+            Error::Refused(reason) => RecvError::Connection(reason),
+            Error::Malformed => RecvError::Connection(Reason::PROTOCOL_ERROR),
+            Error::Io(e) => RecvError::Io(unshare_io(e)),
         }
     }
 }
@@ -46,8 +69,11 @@ impl From<Error> for RecvError {
 impl From<Error> for SendError {
     fn from(src: Error) -> SendError {
         match src {
-            Error::Proto(reason) => SendError::Connection(reason),
-            Error::Io(e) => SendError::Io(e),
+            Error::Reset(reason, _) |
+            Error::GoAway(_, reason, _) |
+            Error::Refused(reason) => SendError::Connection(reason),
+            Error::Malformed => SendError::Connection(Reason::PROTOCOL_ERROR),
+            Error::Io(e) => SendError::Io(unshare_io(e)),
         }
     }
 }
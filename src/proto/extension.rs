@@ -0,0 +1,99 @@
+use codec::{Codec, UserError};
+use frame;
+
+use bytes::Buf;
+use futures::{Async, Poll};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tokio_io::AsyncWrite;
+
+/// Handles the frames of types unknown to this library.
+///
+/// Frames of the registered types are passed to a user callback, and frames
+/// sent by the user are queued until the connection is polled.
+#[derive(Debug)]
+pub(super) struct Extensions {
+    /// Extension frames waiting to be written.
+    pending: VecDeque<frame::Extension>,
+
+    /// Called with every extension frame received from the peer.
+    on_extension_frame: Option<OnExtensionFrame>,
+}
+
+/// A user callback receiving the extension frames sent by the peer.
+#[derive(Clone)]
+pub(crate) struct OnExtensionFrame(Arc<Fn(::ExtensionFrame) + Send + Sync>);
+
+impl Extensions {
+    pub fn new(on_extension_frame: Option<OnExtensionFrame>) -> Self {
+        Extensions {
+            pending: VecDeque::new(),
+            on_extension_frame: on_extension_frame,
+        }
+    }
+
+    /// Queues an extension frame.
+    ///
+    /// `max_frame_size` is the largest frame payload the peer currently
+    /// accepts.
+    pub fn send_extension_frame(
+        &mut self,
+        frame: frame::Extension,
+        max_frame_size: usize,
+    ) -> Result<(), UserError> {
+        if frame.payload().len() > max_frame_size {
+            return Err(UserError::PayloadTooBig);
+        }
+
+        self.pending.push_back(frame);
+        Ok(())
+    }
+
+    pub fn recv_extension_frame(&mut self, frame: frame::Extension) {
+        if let Some(ref f) = self.on_extension_frame {
+            (f.0)(::ExtensionFrame::from_internal(frame));
+        }
+    }
+
+    /// Writes the pending extension frames.
+    pub fn send_pending_extension_frames<T, B>(
+        &mut self,
+        dst: &mut Codec<T, B>,
+    ) -> Poll<(), io::Error>
+    where
+        T: AsyncWrite,
+        B: Buf,
+    {
+        while let Some(frame) = self.pending.pop_front() {
+            if !dst.poll_ready()?.is_ready() {
+                self.pending.push_front(frame);
+                return Ok(Async::NotReady);
+            }
+
+            // The peer may have lowered its max frame size since the frame
+            // was queued.
+            if let Err(e) = dst.buffer(frame.into()) {
+                debug!("dropping extension frame; err={:?}", e);
+            }
+        }
+
+        Ok(Async::Ready(()))
+    }
+}
+
+impl OnExtensionFrame {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(::ExtensionFrame) + Send + Sync + 'static,
+    {
+        OnExtensionFrame(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for OnExtensionFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("OnExtensionFrame").finish()
+    }
+}
@@ -1,9 +1,11 @@
 use codec::Codec;
 use frame::{self, Reason, StreamId};
+use timer;
 
 use bytes::Buf;
 use futures::{Async, Poll};
 use std::io;
+use std::time::{Duration, Instant};
 use tokio_io::AsyncWrite;
 
 /// Manages our sending of GOAWAY frames.
@@ -16,6 +18,14 @@ pub(super) struct GoAway {
 
     /// A GOAWAY frame that must be buffered in the Codec immediately.
     pending: Option<frame::GoAway>,
+
+    /// Set while a graceful shutdown waits to send its final GOAWAY, to when
+    /// the initial GOAWAY was queued.
+    graceful_started_at: Option<Instant>,
+
+    /// Maximum amount of time to wait before sending the final GOAWAY of a
+    /// graceful shutdown.
+    graceful_timeout: Option<Duration>,
 }
 
 /// Keeps a memory of any GOAWAY frames we've sent before.
@@ -41,11 +51,13 @@ struct GoingAway {
 }
 
 impl GoAway {
-    pub fn new() -> Self {
+    pub fn new(graceful_timeout: Option<Duration>) -> Self {
         GoAway {
             close_now: false,
             going_away: None,
             pending: None,
+            graceful_started_at: None,
+            graceful_timeout: graceful_timeout,
         }
     }
 
@@ -82,6 +94,44 @@ impl GoAway {
         self.go_away(f);
     }
 
+    /// Enqueue the initial GOAWAY frame of a graceful shutdown.
+    ///
+    /// The final GOAWAY is expected to follow once `finish_graceful` returns
+    /// `true`.
+    pub fn go_away_gracefully(&mut self, f: frame::GoAway) {
+        self.go_away(f);
+        self.graceful_started_at = Some(timer::now());
+    }
+
+    /// Returns `true` if a graceful shutdown was waiting for its final
+    /// GOAWAY frame, which the caller must now send.
+    pub fn finish_graceful(&mut self) -> bool {
+        self.graceful_started_at.take().is_some()
+    }
+
+    /// Returns if a graceful shutdown has waited longer than the configured
+    /// timeout for its final GOAWAY.
+    pub fn is_graceful_timed_out(&self) -> bool {
+        match self.graceful_deadline() {
+            Some(deadline) => timer::now() >= deadline,
+            None => false,
+        }
+    }
+
+    /// Returns when a graceful shutdown times out, once its initial GOAWAY
+    /// was written.
+    pub fn graceful_deadline(&self) -> Option<Instant> {
+        if self.pending.is_some() {
+            // The initial GOAWAY hasn't been written yet.
+            return None;
+        }
+
+        match (self.graceful_started_at, self.graceful_timeout) {
+            (Some(started_at), Some(timeout)) => Some(started_at + timeout),
+            _ => None,
+        }
+    }
+
     /// Return if a GOAWAY has ever been scheduled.
     pub fn is_going_away(&self) -> bool {
         self.going_away.is_some()
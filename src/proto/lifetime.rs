@@ -0,0 +1,120 @@
+use timer;
+
+use std::time::{Duration, Instant};
+
+/// Tracks how long a connection has been idle, and how old it is.
+///
+/// The durations are checked when the connection is polled, which the
+/// connection timer does once the next one expires.
+#[derive(Debug)]
+pub(super) struct Lifetime {
+    /// Closes the connection once it was idle for this long. Cleared once it
+    /// expired.
+    idle_timeout: Option<Duration>,
+
+    /// When a frame was last received, or streams were last seen active.
+    idle_since: Instant,
+
+    /// Shuts the connection down gracefully once it is this old. Cleared once
+    /// it expired.
+    max_age: Option<Duration>,
+
+    /// How long the streams still active at the end of the maximum age may
+    /// take to complete.
+    max_age_grace: Option<Duration>,
+
+    opened_at: Instant,
+
+    /// Set once the maximum age expired.
+    expired_at: Option<Instant>,
+}
+
+impl Lifetime {
+    pub fn new(
+        idle_timeout: Option<Duration>,
+        max_age: Option<Duration>,
+        max_age_grace: Option<Duration>,
+    ) -> Self {
+        let now = timer::now();
+
+        Lifetime {
+            idle_timeout,
+            idle_since: now,
+            max_age,
+            max_age_grace,
+            opened_at: now,
+            expired_at: None,
+        }
+    }
+
+    /// Records that a frame was received from the peer.
+    pub fn recv_frame(&mut self) {
+        if self.idle_timeout.is_some() {
+            self.idle_since = timer::now();
+        }
+    }
+
+    /// Returns `true`, once, when the connection has had no active streams
+    /// and received no frame for the idle timeout.
+    pub fn is_idle_timed_out(&mut self, num_active_streams: usize) -> bool {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+
+        if num_active_streams > 0 {
+            self.idle_since = timer::now();
+            return false;
+        }
+
+        if timer::now() < self.idle_since + timeout {
+            return false;
+        }
+
+        self.idle_timeout = None;
+        true
+    }
+
+    /// Returns `true`, once, when the connection reached its maximum age.
+    pub fn is_expired(&mut self) -> bool {
+        match self.max_age {
+            Some(max_age) if timer::now() >= self.opened_at + max_age => {},
+            _ => return false,
+        }
+
+        self.max_age = None;
+        self.expired_at = Some(timer::now());
+        true
+    }
+
+    /// Returns `true`, once, when the streams active at the end of the
+    /// maximum age took longer than the grace period to complete.
+    pub fn is_grace_elapsed(&mut self) -> bool {
+        match (self.expired_at, self.max_age_grace) {
+            (Some(expired_at), Some(grace)) if timer::now() >= expired_at + grace => {},
+            _ => return false,
+        }
+
+        self.expired_at = None;
+        true
+    }
+
+    /// Returns when the next of the durations expires, if any.
+    ///
+    /// The connection isn't idle while streams are active.
+    pub fn deadline(&self, num_active_streams: usize) -> Option<Instant> {
+        let idle = match self.idle_timeout {
+            Some(timeout) if num_active_streams == 0 => Some(self.idle_since + timeout),
+            _ => None,
+        };
+
+        let max_age = self.max_age.map(|max_age| self.opened_at + max_age);
+
+        let grace = match (self.expired_at, self.max_age_grace) {
+            (Some(expired_at), Some(grace)) => Some(expired_at + grace),
+            _ => None,
+        };
+
+        timer::earliest(timer::earliest(idle, max_age), grace)
+    }
+}
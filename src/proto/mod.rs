@@ -1,20 +1,30 @@
+mod alt_svc;
 mod connection;
 mod error;
+mod extension;
 mod go_away;
+mod lifetime;
 mod peer;
 mod ping_pong;
 mod settings;
 mod streams;
 
+pub(crate) use self::alt_svc::OnAltSvc;
 pub(crate) use self::connection::{Config, Connection};
-pub(crate) use self::error::Error;
+pub(crate) use self::error::{unshare_io, Error, Initiator};
+pub(crate) use self::extension::OnExtensionFrame;
 pub(crate) use self::peer::{Peer, Dyn as DynPeer};
-pub(crate) use self::streams::{StreamRef, OpaqueStreamRef, Streams};
+pub(crate) use self::ping_pong::UserPings;
+pub(crate) use self::settings::OnUnknownSetting;
+pub(crate) use self::streams::{AbortHandle, StreamRef, OpaqueStreamRef, SettingsChanges, Streams};
 pub(crate) use self::streams::{PollReset, Prioritized, Open};
 
 use codec::Codec;
 
+use self::alt_svc::AltSvcs;
+use self::extension::Extensions;
 use self::go_away::GoAway;
+use self::lifetime::Lifetime;
 use self::ping_pong::PingPong;
 use self::settings::Settings;
 
@@ -35,3 +45,11 @@ pub type WindowSize = u32;
 pub const MAX_WINDOW_SIZE: WindowSize = (1 << 31) - 1;
 pub const DEFAULT_RESET_STREAM_MAX: usize = 10;
 pub const DEFAULT_RESET_STREAM_SECS: u64 = 30;
+pub const DEFAULT_REMOTE_RESET_STREAM_MAX: usize = 100;
+pub const DEFAULT_REMOTE_RESET_STREAM_SECS: u64 = 30;
+pub const DEFAULT_MAX_RESERVED_REMOTE_STREAMS: usize = 100;
+pub const DEFAULT_MAX_PENDING_OPEN_STREAMS: usize = 10;
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT_SECS: u64 = 20;
+pub const DEFAULT_PING_INTERVAL_SECS: u64 = 1;
+pub const DEFAULT_WINDOW_UPDATE_THRESHOLD: u8 = 50;
+pub const DEFAULT_MAX_ADAPTIVE_WINDOW_SIZE: WindowSize = 16 << 20;
@@ -16,7 +16,9 @@ pub(crate) trait Peer {
 
     fn is_server() -> bool;
 
-    fn convert_poll_message(headers: Headers) -> Result<Self::Poll, RecvError>;
+    /// Converts a received header block to a message, or returns the reason
+    /// the message is malformed.
+    fn convert_poll_message(headers: Headers) -> Result<Self::Poll, &'static str>;
 
     fn is_local_init(id: StreamId) -> bool {
         assert!(!id.is_zero());
@@ -51,7 +53,7 @@ impl Dyn {
         self.is_server() == id.is_server_initiated()
     }
 
-    pub fn convert_poll_message(&self, headers: Headers) -> Result<PollMessage, RecvError> {
+    pub fn convert_poll_message(&self, headers: Headers) -> Result<PollMessage, &'static str> {
         if self.is_server() {
             ::server::Peer::convert_poll_message(headers)
                 .map(PollMessage::Server)
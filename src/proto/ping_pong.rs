@@ -1,23 +1,138 @@
-use codec::Codec;
-use frame::Ping;
-use proto::PingPayload;
+use codec::{Codec, RecvError, UserError};
+use frame::{Ping, Reason, DEFAULT_INITIAL_WINDOW_SIZE};
+use proto::{self, PingPayload, WindowSize};
+use timer;
 
 use bytes::Buf;
-use futures::{Async, Poll};
-use std::io;
+use byteorder::{BigEndian, ByteOrder};
+use futures::{task, Async, Poll};
+use futures::task::Task;
+use std::{cmp, io};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio_io::AsyncWrite;
 
-/// Acknowledges ping requests from the remote.
+/// Acknowledges ping requests from the remote, and sends our own.
 #[derive(Debug)]
 pub struct PingPong {
     pending_ping: Option<PendingPing>,
     pending_pong: Option<PingPayload>,
+
+    /// Keep-alive PINGs, if enabled.
+    keep_alive: Option<KeepAlive>,
+
+    /// Used to give each PING sent on the connection a unique payload.
+    next_payload: u64,
+
+    /// Smoothed round-trip time measured with our PINGs.
+    rtt: Option<Duration>,
+
+    /// Connection side of the user PING handle, once it was taken.
+    user_pings: Option<UserPingsRx>,
+
+    /// The user PING waiting to be acknowledged.
+    user_ping: Option<PendingPing>,
+
+    /// Limits the rate of PINGs accepted from the peer, if enabled.
+    limit: Option<PingLimit>,
+
+    /// Bandwidth-delay product estimation, if adaptive windows are enabled.
+    bdp: Option<Bdp>,
+}
+
+/// Lets the user send PINGs on the connection, one at a time.
+#[derive(Debug, Clone)]
+pub(crate) struct UserPings(Arc<Mutex<UserPingsInner>>);
+
+/// The connection side of `UserPings`.
+#[derive(Debug)]
+struct UserPingsRx(Arc<Mutex<UserPingsInner>>);
+
+#[derive(Debug)]
+struct UserPingsInner {
+    state: UserPingState,
+
+    /// The connection task, notified when the user requests a PING.
+    conn_task: Option<Task>,
+
+    /// The user task, notified when the PONG is received.
+    pong_task: Option<Task>,
+}
+
+#[derive(Debug)]
+enum UserPingState {
+    /// No PING is in flight.
+    Empty,
+
+    /// The user requested a PING that the connection hasn't sent yet.
+    PendingPing,
+
+    /// The PING was sent and its acknowledgement is awaited.
+    PendingPong,
+
+    /// The PING was acknowledged, the user hasn't seen it yet.
+    ReceivedPong,
+
+    /// The connection is gone, with the error it failed with if any.
+    Closed(Option<proto::Error>),
 }
 
 #[derive(Debug)]
 struct PendingPing {
     payload: PingPayload,
-    sent: bool,
+
+    /// When the PING was written, `None` if it wasn't yet.
+    sent_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct KeepAlive {
+    /// Idle duration after which a PING is sent.
+    interval: Duration,
+
+    /// Maximum duration to wait for the PING to be acknowledged.
+    timeout: Duration,
+
+    /// When a frame was last received from the peer.
+    last_read_at: Instant,
+
+    /// The keep-alive PING waiting to be acknowledged.
+    ping: Option<PendingPing>,
+}
+
+#[derive(Debug)]
+struct PingLimit {
+    /// Maximum number of PINGs accepted per interval.
+    max: usize,
+
+    /// Length of an interval.
+    interval: Duration,
+
+    /// When the current interval started.
+    started_at: Instant,
+
+    /// Number of PINGs received in the current interval.
+    count: usize,
+}
+
+/// Estimates the bandwidth-delay product of the connection by counting the
+/// bytes received during the round trip of a PING.
+#[derive(Debug)]
+struct Bdp {
+    /// The initial receive window of streams currently in use.
+    window: WindowSize,
+
+    /// The receive window of the connection currently in use.
+    conn_window: WindowSize,
+
+    /// The receive window never grows past this size.
+    max_window: WindowSize,
+
+    /// Bytes received since the BDP PING was sent.
+    bytes: usize,
+
+    /// The BDP PING waiting to be acknowledged.
+    ping: Option<PendingPing>,
 }
 
 /// Status returned from `PingPong::recv_ping`.
@@ -26,27 +141,200 @@ pub(crate) enum ReceivedPing {
     MustAck,
     Unknown,
     Shutdown,
+    KeepAlive,
+    User,
+
+    /// Carries the receive windows that the measured bandwidth-delay product
+    /// calls to grow.
+    Bdp(BdpWindows),
+}
+
+/// New receive window sizes, following a bandwidth-delay product
+/// measurement. A window is `None` when it should not grow.
+#[derive(Debug)]
+pub(crate) struct BdpWindows {
+    /// The initial receive window of streams.
+    pub stream: Option<WindowSize>,
+
+    /// The receive window of the connection.
+    pub connection: Option<WindowSize>,
 }
 
 impl PingPong {
-    pub fn new() -> Self {
+    pub fn new(
+        keep_alive_interval: Option<Duration>,
+        keep_alive_timeout: Duration,
+        max_pings_per_interval: Option<usize>,
+        ping_interval: Duration,
+        adaptive_window: Option<(WindowSize, WindowSize)>,
+    ) -> Self {
         PingPong {
             pending_ping: None,
             pending_pong: None,
+            keep_alive: keep_alive_interval.map(|interval| KeepAlive {
+                interval: interval,
+                timeout: keep_alive_timeout,
+                last_read_at: timer::now(),
+                ping: None,
+            }),
+            next_payload: 1,
+            rtt: None,
+            user_pings: None,
+            user_ping: None,
+            limit: max_pings_per_interval.map(|max| PingLimit {
+                max: max,
+                interval: ping_interval,
+                started_at: timer::now(),
+                count: 0,
+            }),
+            bdp: adaptive_window.map(|(window, max_window)| Bdp {
+                window: window,
+                conn_window: DEFAULT_INITIAL_WINDOW_SIZE,
+                max_window: max_window,
+                bytes: 0,
+                ping: None,
+            }),
+        }
+    }
+
+    /// Returns the handle used to send PINGs from user code. Only the first
+    /// call returns a handle.
+    pub fn take_user_pings(&mut self) -> Option<UserPings> {
+        if self.user_pings.is_some() {
+            return None;
+        }
+
+        let inner = Arc::new(Mutex::new(UserPingsInner {
+            state: UserPingState::Empty,
+            conn_task: None,
+            pong_task: None,
+        }));
+
+        self.user_pings = Some(UserPingsRx(inner.clone()));
+        Some(UserPings(inner))
+    }
+
+    /// Fails the pending user PING, if any, with the connection error.
+    pub fn recv_err(&mut self, err: &proto::Error) {
+        if let Some(ref user_pings) = self.user_pings {
+            user_pings.close(Some(err.clone()));
         }
     }
 
     pub fn ping_shutdown(&mut self) {
         assert!(self.pending_ping.is_none());
 
-        self.pending_ping = Some(PendingPing {
-            payload: Ping::SHUTDOWN,
-            sent: false,
-        });
+        self.pending_ping = Some(PendingPing::new(Ping::SHUTDOWN));
+    }
+
+    /// Returns the smoothed round-trip time, once a PING we sent has been
+    /// acknowledged.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Records the receive window of the connection, as configured by the
+    /// user, so that BDP measurements only ever grow it from there.
+    pub fn set_connection_window(&mut self, window: WindowSize) {
+        if let Some(ref mut bdp) = self.bdp {
+            bdp.conn_window = window;
+        }
+    }
+
+    /// Records that a frame was received from the peer.
+    pub fn recv_frame(&mut self) {
+        if let Some(ref mut keep_alive) = self.keep_alive {
+            keep_alive.last_read_at = timer::now();
+        }
+    }
+
+    /// Records that `len` bytes of DATA were received, starting a BDP
+    /// measurement if none is in progress.
+    pub fn recv_data(&mut self, len: usize) {
+        let bdp = match self.bdp {
+            Some(ref mut bdp) => bdp,
+            None => return,
+        };
+
+        if bdp.ping.is_some() {
+            bdp.bytes += len;
+            return;
+        }
+
+        if bdp.window >= bdp.max_window && bdp.conn_window >= bdp.max_window {
+            return;
+        }
+
+        let mut payload = PingPayload::default();
+        BigEndian::write_u64(&mut payload, self.next_payload);
+        self.next_payload += 1;
+
+        trace!(
+            "starting BDP measurement; window={}; conn_window={}",
+            bdp.window,
+            bdp.conn_window
+        );
+
+        bdp.bytes = len;
+        bdp.ping = Some(PendingPing::new(payload));
+    }
+
+    /// Queues a keep-alive PING if the connection has been idle for long
+    /// enough, and returns an error if the previous one wasn't acknowledged
+    /// in time.
+    ///
+    /// Returns `true` if a PING was queued.
+    pub fn check_keep_alive(&mut self) -> Result<bool, io::Error> {
+        let keep_alive = match self.keep_alive {
+            Some(ref mut keep_alive) => keep_alive,
+            None => return Ok(false),
+        };
+
+        if let Some(ref ping) = keep_alive.ping {
+            if let Some(sent_at) = ping.sent_at {
+                if timer::now() >= sent_at + keep_alive.timeout {
+                    debug!("keep-alive PING not acknowledged within {:?}", keep_alive.timeout);
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "keep-alive timed out"));
+                }
+            }
+
+            return Ok(false);
+        }
+
+        if timer::now() < keep_alive.last_read_at + keep_alive.interval {
+            return Ok(false);
+        }
+
+        trace!("connection idle; sending keep-alive PING");
+
+        let mut payload = PingPayload::default();
+        BigEndian::write_u64(&mut payload, self.next_payload);
+        self.next_payload += 1;
+
+        keep_alive.ping = Some(PendingPing::new(payload));
+        Ok(true)
+    }
+
+    /// Returns when `check_keep_alive` should be called next, if keep-alive
+    /// PINGs are enabled.
+    pub fn keep_alive_deadline(&self) -> Option<Instant> {
+        let keep_alive = match self.keep_alive {
+            Some(ref keep_alive) => keep_alive,
+            None => return None,
+        };
+
+        match keep_alive.ping {
+            // Not written yet, which happens once the codec is ready.
+            Some(PendingPing { sent_at: None, .. }) => None,
+            Some(PendingPing { sent_at: Some(sent_at), .. }) => Some(sent_at + keep_alive.timeout),
+            None => Some(keep_alive.last_read_at + keep_alive.interval),
+        }
     }
 
     /// Process a ping
-    pub(crate) fn recv_ping(&mut self, ping: Ping) -> ReceivedPing {
+    ///
+    /// Returns a connection error if the peer sent more PINGs than allowed.
+    pub(crate) fn recv_ping(&mut self, ping: Ping) -> Result<ReceivedPing, RecvError> {
         // The caller should always check that `send_pongs` returns ready before
         // calling `recv_ping`.
         assert!(self.pending_pong.is_none());
@@ -55,22 +343,96 @@ impl PingPong {
             if let Some(pending) = self.pending_ping.take() {
                 if &pending.payload == ping.payload() {
                     trace!("recv PING ack");
-                    return ReceivedPing::Shutdown;
+                    self.update_rtt(&pending);
+                    return Ok(ReceivedPing::Shutdown);
                 }
 
                 // if not the payload we expected, put it back.
                 self.pending_ping = Some(pending);
             }
 
+            let keep_alive_ping = self.keep_alive
+                .as_mut()
+                .and_then(|keep_alive| {
+                    let is_match = keep_alive.ping
+                        .as_ref()
+                        .map(|pending| &pending.payload == ping.payload())
+                        .unwrap_or(false);
+
+                    if is_match {
+                        keep_alive.ping.take()
+                    } else {
+                        None
+                    }
+                });
+
+            if let Some(pending) = keep_alive_ping {
+                trace!("recv keep-alive PING ack");
+                self.update_rtt(&pending);
+                return Ok(ReceivedPing::KeepAlive);
+            }
+
+            let bdp_ping = self.bdp
+                .as_mut()
+                .and_then(|bdp| {
+                    let is_match = bdp.ping
+                        .as_ref()
+                        .map(|pending| &pending.payload == ping.payload())
+                        .unwrap_or(false);
+
+                    if is_match {
+                        bdp.ping.take()
+                    } else {
+                        None
+                    }
+                });
+
+            if let Some(pending) = bdp_ping {
+                trace!("recv BDP PING ack");
+                self.update_rtt(&pending);
+
+                let windows = self.bdp.as_mut().unwrap().recv_pong();
+                return Ok(ReceivedPing::Bdp(windows));
+            }
+
+            let is_user_pong = self.user_ping
+                .as_ref()
+                .map(|pending| &pending.payload == ping.payload())
+                .unwrap_or(false);
+
+            if is_user_pong {
+                trace!("recv user PING ack");
+                let pending = self.user_ping.take().unwrap();
+                self.update_rtt(&pending);
+
+                if let Some(ref user_pings) = self.user_pings {
+                    user_pings.recv_pong();
+                }
+
+                return Ok(ReceivedPing::User);
+            }
+
             // else we were acked a ping we didn't send?
             // The spec doesn't require us to do anything about this,
             // so for resiliency, just ignore it for now.
             warn!("recv PING ack that we never sent: {:?}", ping);
-            ReceivedPing::Unknown
+            Ok(ReceivedPing::Unknown)
         } else {
+            // Only PINGs that must be acknowledged count against the limit.
+            if let Some(ref mut limit) = self.limit {
+                if !limit.recv_ping() {
+                    debug!(
+                        "more than {} PINGs received within {:?}",
+                        limit.max,
+                        limit.interval
+                    );
+                    return Err(RecvError::Connection(Reason::ENHANCE_YOUR_CALM));
+                }
+            }
+
             // Save the ping's payload to be sent as an acknowledgement.
             self.pending_pong = Some(ping.into_payload());
-            ReceivedPing::MustAck
+            Ok(ReceivedPing::MustAck)
         }
     }
 
@@ -100,17 +462,74 @@ impl PingPong {
         B: Buf,
     {
         if let Some(ref mut ping) = self.pending_ping {
-            if !ping.sent {
-                if !dst.poll_ready()?.is_ready() {
-                    return Ok(Async::NotReady);
-                }
+            try_ready!(ping.send(dst));
+        }
+
+        if let Some(ref mut keep_alive) = self.keep_alive {
+            if let Some(ref mut ping) = keep_alive.ping {
+                try_ready!(ping.send(dst));
+            }
+        }
 
-                dst.buffer(Ping::new(ping.payload).into())
-                    .expect("invalid ping frame");
-                ping.sent = true;
+        if let Some(ref mut bdp) = self.bdp {
+            if let Some(ref mut ping) = bdp.ping {
+                try_ready!(ping.send(dst));
             }
         }
 
+        if let Some(ref user_pings) = self.user_pings {
+            if user_pings.take_requested() {
+                self.user_ping = Some(PendingPing::new(Ping::USER));
+            }
+        }
+
+        if let Some(ref mut ping) = self.user_ping {
+            try_ready!(ping.send(dst));
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    fn update_rtt(&mut self, ping: &PendingPing) {
+        let sample = match ping.sent_at {
+            Some(sent_at) => timer::now() - sent_at,
+            None => return,
+        };
+
+        // Smoothed the same way as TCP's round-trip time (RFC 6298).
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt * 7 / 8 + sample / 8,
+            None => sample,
+        });
+
+        trace!("PING round trip; sample={:?}; rtt={:?}", sample, self.rtt);
+    }
+}
+
+impl PendingPing {
+    fn new(payload: PingPayload) -> Self {
+        PendingPing {
+            payload: payload,
+            sent_at: None,
+        }
+    }
+
+    /// Writes the PING, unless it was already.
+    fn send<T, B>(&mut self, dst: &mut Codec<T, B>) -> Poll<(), io::Error>
+    where
+        T: AsyncWrite,
+        B: Buf,
+    {
+        if self.sent_at.is_none() {
+            if !dst.poll_ready()?.is_ready() {
+                return Ok(Async::NotReady);
+            }
+
+            dst.buffer(Ping::new(self.payload).into())
+                .expect("invalid ping frame");
+            self.sent_at = Some(timer::now());
+        }
+
         Ok(Async::Ready(()))
     }
 }
@@ -123,3 +542,159 @@ impl ReceivedPing {
         }
     }
 }
+
+impl PingLimit {
+    /// Counts a received PING, returning `false` if it exceeds the limit.
+    fn recv_ping(&mut self) -> bool {
+        let now = timer::now();
+
+        if now.duration_since(self.started_at) >= self.interval {
+            self.started_at = now;
+            self.count = 0;
+        }
+
+        self.count += 1;
+        self.count <= self.max
+    }
+}
+
+impl Bdp {
+    /// Completes a measurement, returning the windows that should grow.
+    fn recv_pong(&mut self) -> BdpWindows {
+        let bytes = self.bytes;
+        self.bytes = 0;
+
+        trace!(
+            "BDP measured; bytes={}; window={}; conn_window={}",
+            bytes,
+            self.window,
+            self.conn_window
+        );
+
+        BdpWindows {
+            stream: grow_window(&mut self.window, bytes, self.max_window),
+            connection: grow_window(&mut self.conn_window, bytes, self.max_window),
+        }
+    }
+}
+
+/// Grows `window` to twice the `bytes` received within a round trip, up to
+/// `max_window`. Returns the new size, if the window grew.
+fn grow_window(window: &mut WindowSize, bytes: usize, max_window: WindowSize) -> Option<WindowSize> {
+    // The window only limits the throughput when the peer used most of it
+    // within a round trip.
+    if bytes < *window as usize * 2 / 3 {
+        return None;
+    }
+
+    let new = cmp::min(bytes.saturating_mul(2), max_window as usize) as WindowSize;
+
+    if new <= *window {
+        return None;
+    }
+
+    debug!("growing receive window; old={}; new={}", window, new);
+    *window = new;
+    Some(new)
+}
+
+// ===== impl UserPings =====
+
+impl UserPings {
+    /// Requests a PING to be sent by the connection.
+    pub fn send_ping(&self) -> Result<(), ::Error> {
+        let mut inner = self.0.lock().unwrap();
+
+        match inner.state {
+            UserPingState::Empty | UserPingState::ReceivedPong => {},
+            UserPingState::PendingPing | UserPingState::PendingPong => {
+                return Err(UserError::SendPingWhilePending.into());
+            },
+            UserPingState::Closed(ref err) => return Err(closed_err(err).into()),
+        }
+
+        inner.state = UserPingState::PendingPing;
+
+        if let Some(task) = inner.conn_task.take() {
+            task.notify();
+        }
+
+        Ok(())
+    }
+
+    /// Polls for the acknowledgement of the PING sent with `send_ping`.
+    pub fn poll_pong(&self) -> Poll<(), proto::Error> {
+        let mut inner = self.0.lock().unwrap();
+
+        match inner.state {
+            UserPingState::ReceivedPong => {
+                inner.state = UserPingState::Empty;
+                Ok(Async::Ready(()))
+            },
+            UserPingState::Closed(ref err) => Err(closed_err(err)),
+            _ => {
+                inner.pong_task = Some(task::current());
+                Ok(Async::NotReady)
+            },
+        }
+    }
+}
+
+fn closed_err(err: &Option<proto::Error>) -> proto::Error {
+    match *err {
+        Some(ref err) => err.clone(),
+        None => io::Error::from(io::ErrorKind::BrokenPipe).into(),
+    }
+}
+
+// ===== impl UserPingsRx =====
+
+impl UserPingsRx {
+    /// Returns `true` if the user requested a PING, which must now be sent.
+    fn take_requested(&self) -> bool {
+        let mut inner = self.0.lock().unwrap();
+
+        match inner.state {
+            UserPingState::PendingPing => {
+                inner.state = UserPingState::PendingPong;
+                true
+            },
+            _ => {
+                inner.conn_task = Some(task::current());
+                false
+            },
+        }
+    }
+
+    fn recv_pong(&self) {
+        let mut inner = self.0.lock().unwrap();
+
+        if let UserPingState::PendingPong = inner.state {
+            inner.state = UserPingState::ReceivedPong;
+
+            if let Some(task) = inner.pong_task.take() {
+                task.notify();
+            }
+        }
+    }
+
+    fn close(&self, err: Option<proto::Error>) {
+        let mut inner = self.0.lock().unwrap();
+
+        if let UserPingState::Closed(..) = inner.state {
+            return;
+        }
+
+        inner.state = UserPingState::Closed(err);
+
+        if let Some(task) = inner.pong_task.take() {
+            task.notify();
+        }
+    }
+}
+
+impl Drop for UserPingsRx {
+    fn drop(&mut self) {
+        self.close(None);
+    }
+}
@@ -1,30 +1,270 @@
 use codec::RecvError;
-use frame;
+use frame::{self, Reason};
 use proto::*;
+use timer;
+
+use futures::sync::oneshot;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub(crate) struct Settings {
+    /// Local settings as they will be once the peer has acknowledged every
+    /// SETTINGS frame sent or queued so far. Updates are diffed against this
+    /// so that unchanged values are not sent again.
+    local: frame::Settings,
+
+    /// Local SETTINGS updates that have not been written yet, in order.
+    to_send: VecDeque<Local>,
+
+    /// Whether updates queued before the previous one could be written are
+    /// coalesced into it, rather than sent as their own frame.
+    coalesce_updates: bool,
+
+    /// Local SETTINGS frames written to the peer and waiting to be
+    /// acknowledged, in the order they were sent. The peer acknowledges
+    /// SETTINGS frames in order, so ACKs are matched against the front.
+    ///
+    /// The values of these frames must not be relied upon until the ACK is
+    /// received.
+    sent: VecDeque<(Local, Instant)>,
+
+    /// Maximum amount of time to wait for the peer to acknowledge a SETTINGS
+    /// frame.
+    ack_timeout: Option<Duration>,
+
     /// Received SETTINGS frame pending processing. The ACK must be written to
     /// the socket first then the settings applied **before** receiving any
     /// further frames.
     pending: Option<frame::Settings>,
+
+    /// Called with every extension setting received from the peer.
+    on_unknown_setting: Option<OnUnknownSetting>,
+
+    /// Instant by which the initial SETTINGS frame of the peer must be
+    /// received. Cleared once it is.
+    remote_deadline: Option<Instant>,
+}
+
+/// A user callback receiving the identifier and value of extension settings
+/// sent by the peer.
+#[derive(Clone)]
+pub(crate) struct OnUnknownSetting(Arc<Fn(u16, u32) + Send + Sync>);
+
+#[derive(Debug)]
+struct Local {
+    settings: frame::Settings,
+
+    /// Notified once the peer acknowledges `settings`.
+    acks: Vec<oneshot::Sender<()>>,
 }
 
 impl Settings {
-    pub fn new() -> Self {
+    pub fn new(
+        local: frame::Settings,
+        ack_timeout: Option<Duration>,
+        coalesce_updates: bool,
+        on_unknown_setting: Option<OnUnknownSetting>,
+        remote_deadline: Option<Instant>,
+    ) -> Self {
+        // The initial SETTINGS frame is sent as part of the handshake.
+        let mut sent = VecDeque::new();
+        sent.push_back((Local::new(local.clone()), timer::now()));
+
         Settings {
+            local: local,
+            to_send: VecDeque::new(),
+            coalesce_updates: coalesce_updates,
+            sent: sent,
+            ack_timeout: ack_timeout,
             pending: None,
+            on_unknown_setting: on_unknown_setting,
+            remote_deadline: remote_deadline,
+        }
+    }
+
+    /// Queues a local SETTINGS update, returning a receiver that completes
+    /// once the peer has acknowledged it.
+    ///
+    /// Only values that differ from the current local settings are sent. If
+    /// nothing changed, no frame is sent and the receiver completes once the
+    /// SETTINGS frames already in flight are acknowledged.
+    ///
+    /// The settings in `reset` go back to their initial value, unless
+    /// `settings` includes a value for them.
+    pub fn update_local(
+        &mut self,
+        settings: frame::Settings,
+        reset: &[u16],
+    ) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+
+        let reset: Vec<u16> = reset
+            .iter()
+            .cloned()
+            .filter(|&id| settings.get(id).is_none())
+            .collect();
+
+        let mut settings = self.local.diff(&settings);
+        settings.merge(&self.local.reset(&reset));
+
+        if settings.is_empty() {
+            trace!("local settings unchanged; not sending update");
+
+            let sent = &mut self.sent;
+            let in_flight = self.to_send
+                .back_mut()
+                .or_else(|| sent.back_mut().map(|&mut (ref mut local, _)| local));
+
+            match in_flight {
+                Some(local) => local.acks.push(tx),
+                None => {
+                    let _ = tx.send(());
+                },
+            }
+
+            return rx;
+        }
+
+        self.local.merge(&settings);
+
+        if self.coalesce_updates {
+            if let Some(local) = self.to_send.back_mut() {
+                local.settings.merge(&settings);
+                local.acks.push(tx);
+                return rx;
+            }
         }
+
+        let mut local = Local::new(settings);
+        local.acks.push(tx);
+        self.to_send.push_back(local);
+
+        rx
     }
 
-    pub fn recv_settings(&mut self, frame: frame::Settings) {
+    pub fn recv_settings<T, B, C, P>(
+        &mut self,
+        frame: frame::Settings,
+        dst: &mut Codec<T, B>,
+        streams: &mut Streams<C, P>,
+    ) -> Result<(), RecvError>
+    where
+        T: AsyncWrite,
+        B: Buf,
+        C: Buf,
+        P: Peer,
+    {
         if frame.is_ack() {
-            debug!("received remote settings ack");
-        // TODO: handle acks
+            match self.sent.pop_front() {
+                Some((local, _)) => {
+                    debug!("received settings ACK; applying {:?}", local.settings);
+
+                    if let Some(val) = local.settings.max_frame_size() {
+                        dst.set_max_recv_frame_size(val as usize);
+                    }
+
+                    if let Some(val) = local.settings.max_header_list_size() {
+                        dst.set_max_recv_header_list_size(val as usize);
+                    }
+
+                    // The peer may use the previous header table size until
+                    // it acknowledges the new one.
+                    if let Some(val) = local.settings.header_table_size() {
+                        dst.set_recv_header_table_size(val as usize);
+                    }
+
+                    streams.apply_local_settings(&local.settings)?;
+
+                    for tx in local.acks {
+                        let _ = tx.send(());
+                    }
+                },
+                None => {
+                    // We haven't sent any SETTINGS frame that would need to
+                    // be acknowledged.
+                    debug!("received unexpected settings ack");
+                    return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
+                },
+            }
         } else {
             assert!(self.pending.is_none());
             self.pending = Some(frame);
+            self.remote_deadline = None;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a connection error if the peer failed to acknowledge one of our
+    /// SETTINGS frames within the configured timeout.
+    pub fn check_ack_timeout(&mut self) -> Result<(), RecvError> {
+        if let (Some(&(_, sent_at)), Some(timeout)) = (self.sent.front(), self.ack_timeout) {
+            if timer::now() >= sent_at + timeout {
+                debug!("settings ACK not received within {:?}", timeout);
+
+                // Only report the error once, the connection is going away.
+                self.ack_timeout = None;
+                return Err(RecvError::Connection(Reason::SETTINGS_TIMEOUT));
+            }
         }
+
+        Ok(())
+    }
+
+    /// Returns a connection error if the initial SETTINGS frame of the peer
+    /// was not received in time.
+    pub fn check_remote_deadline(&mut self) -> Result<(), RecvError> {
+        if let Some(deadline) = self.remote_deadline {
+            if timer::now() >= deadline {
+                debug!("initial settings not received from peer in time");
+
+                // Only report the error once, the connection is going away.
+                self.remote_deadline = None;
+                return Err(RecvError::Connection(Reason::SETTINGS_TIMEOUT));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns when `check_ack_timeout` or `check_remote_deadline` should be
+    /// called next, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        let ack = match (self.sent.front(), self.ack_timeout) {
+            (Some(&(_, sent_at)), Some(timeout)) => Some(sent_at + timeout),
+            _ => None,
+        };
+
+        timer::earliest(ack, self.remote_deadline)
+    }
+
+    /// Writes the queued local SETTINGS updates, if any.
+    pub fn send_pending_local<T, B>(&mut self, dst: &mut Codec<T, B>) -> Poll<(), RecvError>
+    where
+        T: AsyncWrite,
+        B: Buf,
+    {
+        while !self.to_send.is_empty() {
+            if !dst.poll_ready()?.is_ready() {
+                return Ok(Async::NotReady);
+            }
+
+            let local = self.to_send.pop_front().unwrap();
+
+            // The codec only rejects DATA and extension frames, for being
+            // larger than the max frame size.
+            dst.buffer(local.settings.clone().into())
+                .expect("invalid settings frame");
+
+            trace!("local settings sent; awaiting ACK");
+            self.sent.push_back((local, timer::now()));
+        }
+
+        Ok(().into())
     }
 
     pub fn send_pending_ack<T, B, C, P>(
@@ -49,22 +289,79 @@ impl Settings {
             // Create an ACK settings frame
             let frame = frame::Settings::ack();
 
-            // Buffer the settings frame
+            // Buffer the settings frame. It can't be rejected by the codec,
+            // which only checks the size of DATA and extension frames.
             dst.buffer(frame.into())
-                .ok()
                 .expect("invalid settings frame");
 
             trace!("ACK sent; applying settings");
 
-            if let Some(val) = settings.max_frame_size() {
-                dst.set_max_send_frame_size(val as usize);
-            }
-
-            streams.apply_remote_settings(settings)?;
+            self.apply_remote(settings, dst, streams)?;
         }
 
         self.pending = None;
 
         Ok(().into())
     }
+
+    /// Applies settings received from the peer.
+    ///
+    /// This is also used for the settings of an HTTP/1.1 upgrade request,
+    /// which are acknowledged by the 101 response rather than by a SETTINGS
+    /// frame.
+    pub fn apply_remote<T, B, C, P>(
+        &self,
+        settings: &frame::Settings,
+        dst: &mut Codec<T, B>,
+        streams: &mut Streams<C, P>,
+    ) -> Result<(), RecvError>
+    where
+        T: AsyncWrite,
+        B: Buf,
+        C: Buf,
+        P: Peer,
+    {
+        if let Some(val) = settings.max_frame_size() {
+            dst.set_max_send_frame_size(val as usize);
+        }
+
+        if let Some(val) = settings.header_table_size() {
+            dst.set_send_header_table_size(val as usize);
+        }
+
+        streams.apply_remote_settings(settings)?;
+
+        if let Some(ref f) = self.on_unknown_setting {
+            for (id, val) in settings.extensions() {
+                trace!("unknown setting; id={:#06x}; val={}", id, val);
+                (f.0)(id, val);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Local {
+    fn new(settings: frame::Settings) -> Self {
+        Local {
+            settings: settings,
+            acks: Vec::new(),
+        }
+    }
+}
+
+impl OnUnknownSetting {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(u16, u32) + Send + Sync + 'static,
+    {
+        OnUnknownSetting(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for OnUnknownSetting {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("OnUnknownSetting").finish()
+    }
 }
@@ -1,5 +1,6 @@
 use super::*;
 
+use std::time::{Duration, Instant};
 use std::usize;
 
 #[derive(Debug)]
@@ -8,23 +9,48 @@ pub(super) struct Counts {
     /// inc / dec.
     peer: peer::Dyn,
 
-    /// Maximum number of locally initiated streams
+    /// Maximum number of locally initiated streams, as advertised by the peer
     max_send_streams: usize,
 
-    /// Current number of remote initiated streams
+    /// Current number of locally initiated streams that are open or
+    /// half-closed. Reserved streams are not counted (section 5.1.2 of RFC
+    /// 7540).
     num_send_streams: usize,
 
-    /// Maximum number of remote initiated streams
+    /// Maximum number of remote initiated streams, as advertised to the peer
     max_recv_streams: usize,
 
-    /// Current number of locally initiated streams
+    /// Current number of remote initiated streams that are open or
+    /// half-closed. Reserved streams are not counted.
     num_recv_streams: usize,
 
+    /// Maximum number of streams reserved by the remote
+    max_reserved_recv_streams: usize,
+
+    /// Current number of streams reserved by the remote
+    num_reserved_recv_streams: usize,
+
     /// Maximum number of pending locally reset streams
     max_reset_streams: usize,
 
     /// Current number of pending locally reset streams
     num_reset_streams: usize,
+
+    /// Maximum number of streams the peer may reset before a response was
+    /// sent, per `remote_reset_duration`
+    max_remote_reset_streams: Option<usize>,
+
+    /// Interval over which streams reset by the peer are counted
+    remote_reset_duration: Duration,
+
+    /// When the current interval started
+    remote_reset_started_at: Instant,
+
+    /// Number of streams reset by the peer in the current interval
+    num_remote_reset_streams: usize,
+
+    /// Total number of streams opened by either peer so far
+    num_opened_streams: usize,
 }
 
 impl Counts {
@@ -36,8 +62,15 @@ impl Counts {
             num_send_streams: 0,
             max_recv_streams: config.remote_max_initiated.unwrap_or(usize::MAX),
             num_recv_streams: 0,
+            max_reserved_recv_streams: config.local_max_reserved_remote,
+            num_reserved_recv_streams: 0,
             max_reset_streams: config.local_reset_max,
             num_reset_streams: 0,
+            max_remote_reset_streams: config.remote_reset_max,
+            remote_reset_duration: config.remote_reset_duration,
+            remote_reset_started_at: Instant::now(),
+            num_remote_reset_streams: 0,
+            num_opened_streams: 0,
         }
     }
 
@@ -66,9 +99,45 @@ impl Counts {
 
         // Increment the number of remote initiated streams
         self.num_recv_streams += 1;
+        self.num_opened_streams = self.num_opened_streams.wrapping_add(1);
         stream.is_counted = true;
     }
 
+    /// Returns true if the number of streams reserved by the remote can be
+    /// incremented
+    pub fn can_inc_num_reserved_recv_streams(&self) -> bool {
+        self.max_reserved_recv_streams > self.num_reserved_recv_streams
+    }
+
+    /// Increments the number of streams reserved by the remote.
+    ///
+    /// # Panics
+    ///
+    /// Panics on failure as this should have been validated before hand.
+    pub fn inc_num_reserved_recv_streams(&mut self, stream: &mut store::Ptr) {
+        assert!(self.can_inc_num_reserved_recv_streams());
+        assert!(!stream.is_reserved_counted);
+
+        self.num_reserved_recv_streams += 1;
+        stream.is_reserved_counted = true;
+    }
+
+    /// Returns the number of locally initiated streams that are open or
+    /// half-closed
+    pub fn num_send_streams(&self) -> usize {
+        self.num_send_streams
+    }
+
+    /// Returns the total number of streams opened by either peer so far
+    pub fn num_opened_streams(&self) -> usize {
+        self.num_opened_streams
+    }
+
+    /// Returns the maximum number of locally initiated streams
+    pub fn max_send_streams(&self) -> usize {
+        self.max_send_streams
+    }
+
     /// Returns true if the send stream concurrency can be incremented
     pub fn can_inc_num_send_streams(&self) -> bool {
         self.max_send_streams > self.num_send_streams
@@ -83,8 +152,9 @@ impl Counts {
         assert!(self.can_inc_num_send_streams());
         assert!(!stream.is_counted);
 
-        // Increment the number of remote initiated streams
+        // Increment the number of locally initiated streams
         self.num_send_streams += 1;
+        self.num_opened_streams = self.num_opened_streams.wrapping_add(1);
         stream.is_counted = true;
     }
 
@@ -104,6 +174,33 @@ impl Counts {
         self.num_reset_streams += 1;
     }
 
+    /// Counts a stream reset by the peer before a response was sent.
+    ///
+    /// Returns `false` if the peer reset more streams than allowed in the
+    /// current interval.
+    pub fn recv_remote_reset(&mut self) -> bool {
+        let max = match self.max_remote_reset_streams {
+            Some(max) => max,
+            None => return true,
+        };
+
+        let now = Instant::now();
+
+        if now.duration_since(self.remote_reset_started_at) >= self.remote_reset_duration {
+            self.remote_reset_started_at = now;
+            self.num_remote_reset_streams = 0;
+        }
+
+        self.num_remote_reset_streams += 1;
+        self.num_remote_reset_streams <= max
+    }
+
+    pub fn apply_local_settings(&mut self, settings: &frame::Settings) {
+        if let Some(val) = settings.max_concurrent_streams() {
+            self.max_recv_streams = val as usize;
+        }
+    }
+
     pub fn apply_remote_settings(&mut self, settings: &frame::Settings) {
         if let Some(val) = settings.max_concurrent_streams() {
             self.max_send_streams = val as usize;
@@ -120,6 +217,9 @@ impl Counts {
     where
         F: FnOnce(&mut Self, &mut store::Ptr) -> U,
     {
+        let span = stream.span.clone();
+        let _enter = span.enter();
+
         // TODO: Does this need to be computed before performing the action?
         let is_pending_reset = stream.is_pending_reset_expiration();
 
@@ -135,14 +235,21 @@ impl Counts {
     pub fn transition_after(&mut self, mut stream: store::Ptr, is_reset_counted: bool) {
         trace!("transition_after; stream={:?}; state={:?}; is_closed={:?}; \
                pending_send_empty={:?}; buffered_send_data={}; \
-               num_recv={}; num_send={}",
+               num_recv={}; num_send={}; num_reserved_recv={}",
                stream.id,
                stream.state,
                stream.is_closed(),
                stream.pending_send.is_empty(),
                stream.buffered_send_data,
                self.num_recv_streams,
-               self.num_send_streams);
+               self.num_send_streams,
+               self.num_reserved_recv_streams);
+
+        // The stream leaves the reserved state once the pushed response
+        // starts, or when it is reset.
+        if stream.is_reserved_counted && !stream.state.is_reserved_remote() {
+            self.dec_num_reserved_recv_streams(&mut stream);
+        }
 
         if stream.is_closed() {
             if !stream.is_pending_reset_expiration() {
@@ -180,6 +287,12 @@ impl Counts {
         }
     }
 
+    fn dec_num_reserved_recv_streams(&mut self, stream: &mut store::Ptr) {
+        assert!(self.num_reserved_recv_streams > 0);
+        self.num_reserved_recv_streams -= 1;
+        stream.is_reserved_counted = false;
+    }
+
     fn dec_num_reset_streams(&mut self) {
         assert!(self.num_reset_streams > 0);
         self.num_reset_streams -= 1;
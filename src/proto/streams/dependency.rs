@@ -0,0 +1,328 @@
+use super::*;
+use frame::StreamDependency;
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+/// The weight of streams with no priority information, as defined in
+/// section 5.3.5. The value is in the range [0, 255] as on the wire.
+const DEFAULT_WEIGHT: u8 = 15;
+
+/// Stream dependency tree built from the priority information sent by the
+/// peer, as described in section 5.3 of RFC 7540.
+///
+/// Nodes are kept for streams that were opened by the peer or that were
+/// referenced by PRIORITY frames, including idle streams used to group other
+/// streams. The root of the tree is the stream 0x0.
+#[derive(Debug)]
+pub(super) struct DependencyTree {
+    nodes: HashMap<StreamId, Node>,
+}
+
+#[derive(Debug)]
+struct Node {
+    /// The stream this stream depends on
+    parent: StreamId,
+
+    /// The weight of the stream, in the range [0, 255]
+    weight: u8,
+
+    /// True if the last priority received for the stream was exclusive
+    is_exclusive: bool,
+
+    /// Streams depending on this stream
+    children: Vec<StreamId>,
+}
+
+impl DependencyTree {
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(StreamId::ZERO, Node::new(StreamId::ZERO));
+
+        DependencyTree {
+            nodes,
+        }
+    }
+
+    /// Returns the number of streams in the tree, not counting the root.
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub fn contains(&self, id: StreamId) -> bool {
+        !id.is_zero() && self.nodes.contains_key(&id)
+    }
+
+    /// Returns the priority of a stream. Streams not in the tree have the
+    /// default priority.
+    pub fn get(&self, id: StreamId) -> StreamDependency {
+        match self.nodes.get(&id) {
+            Some(node) if !id.is_zero() => {
+                StreamDependency::new(node.parent, node.weight, node.is_exclusive)
+            },
+            _ => StreamDependency::new(StreamId::ZERO, DEFAULT_WEIGHT, false),
+        }
+    }
+
+    /// Returns the streams in the tree, not counting the root.
+    pub fn ids(&self) -> Vec<StreamId> {
+        self.nodes.keys().filter(|id| !id.is_zero()).cloned().collect()
+    }
+
+    /// Adds a stream with the default priority, unless it is already in the
+    /// tree.
+    pub fn insert(&mut self, id: StreamId) {
+        debug_assert!(!id.is_zero());
+
+        if self.nodes.contains_key(&id) {
+            return;
+        }
+
+        self.nodes.insert(id, Node::new(StreamId::ZERO));
+        self.node_mut(StreamId::ZERO).children.push(id);
+    }
+
+    /// Sets the priority of a stream, adding it to the tree if needed.
+    ///
+    /// The stream must not depend on itself.
+    pub fn set(&mut self, id: StreamId, dependency: &StreamDependency) {
+        assert!(dependency.dependency_id() != id);
+
+        self.insert(id);
+
+        let mut parent = dependency.dependency_id();
+        let mut weight = dependency.weight();
+        let mut is_exclusive = dependency.is_exclusive();
+
+        if !parent.is_zero() && !self.nodes.contains_key(&parent) {
+            // > A dependency on a stream that is not currently in the tree
+            // > -- such as a stream in the "idle" state -- results in that
+            // > stream being given a default priority (Section 5.3.5).
+            trace!("priority; {:?} depends on unknown {:?}", id, parent);
+            parent = StreamId::ZERO;
+            weight = DEFAULT_WEIGHT;
+            is_exclusive = false;
+        }
+
+        // > If a stream is made dependent on one of its own dependencies, the
+        // > formerly dependent stream is first moved to be dependent on the
+        // > reprioritized stream's previous parent. The moved dependency
+        // > retains its weight.
+        if self.is_descendant(parent, id) {
+            let previous = self.nodes[&id].parent;
+            self.detach(parent);
+            self.attach(parent, previous);
+        }
+
+        self.detach(id);
+
+        if is_exclusive {
+            // > An exclusive flag allows for the insertion of a new level of
+            // > dependencies. The exclusive flag causes the stream to become
+            // > the sole dependency of its parent stream, causing other
+            // > dependencies to become dependent on the exclusive stream.
+            let children = mem::replace(&mut self.node_mut(parent).children, Vec::new());
+
+            for &child in &children {
+                self.node_mut(child).parent = id;
+            }
+
+            self.node_mut(id).children.extend(children);
+        }
+
+        self.attach(id, parent);
+
+        let node = self.node_mut(id);
+        node.weight = weight;
+        node.is_exclusive = is_exclusive;
+    }
+
+    /// Shares `capacity` between the streams in `demand`, which maps each
+    /// stream waiting for capacity to the amount it is waiting for. Streams
+    /// that are not in the tree have the default priority.
+    ///
+    /// Capacity flows from the root towards the streams and is split between
+    /// siblings in proportion to their weight. A stream waiting for capacity
+    /// takes the whole share of its subtree, so the streams depending on it
+    /// only progress once it is blocked or closed. Capacity offered to a
+    /// stream that needs less is shared again between the others.
+    ///
+    /// Returns the amount of capacity assigned to each stream.
+    pub fn allocate(
+        &self,
+        demand: &HashMap<StreamId, WindowSize>,
+        mut capacity: WindowSize,
+    ) -> HashMap<StreamId, WindowSize> {
+        let mut remaining: HashMap<_, _> = demand
+            .iter()
+            .filter(|&(_, &sz)| sz > 0)
+            .map(|(&id, &sz)| (id, sz))
+            .collect();
+
+        let mut assigned = HashMap::new();
+
+        // Each pass either hands out all of the capacity or fully satisfies
+        // at least one stream.
+        while capacity > 0 && !remaining.is_empty() {
+            let active = self.active_nodes(&remaining);
+            let mut shares = HashMap::new();
+
+            self.split(StreamId::ZERO, capacity, &remaining, &active, &mut shares);
+
+            for (id, sz) in shares {
+                capacity -= sz;
+                *assigned.entry(id).or_insert(0) += sz;
+
+                let left = remaining[&id] - sz;
+
+                if left == 0 {
+                    remaining.remove(&id);
+                } else {
+                    remaining.insert(id, left);
+                }
+            }
+        }
+
+        assigned
+    }
+
+    /// Returns the streams waiting for capacity and all of their ancestors.
+    fn active_nodes(&self, remaining: &HashMap<StreamId, WindowSize>) -> HashSet<StreamId> {
+        let mut active = HashSet::new();
+
+        for &id in remaining.keys() {
+            let mut id = id;
+
+            while active.insert(id) && !id.is_zero() {
+                id = match self.nodes.get(&id) {
+                    Some(node) => node.parent,
+                    None => StreamId::ZERO,
+                };
+            }
+        }
+
+        active
+    }
+
+    /// Splits `capacity` between the active dependencies of `id`.
+    fn split(
+        &self,
+        id: StreamId,
+        capacity: WindowSize,
+        remaining: &HashMap<StreamId, WindowSize>,
+        active: &HashSet<StreamId>,
+        shares: &mut HashMap<StreamId, WindowSize>,
+    ) {
+        if let Some(&sz) = remaining.get(&id) {
+            shares.insert(id, cmp::min(sz, capacity));
+            return;
+        }
+
+        let mut children: Vec<(StreamId, u64)> = self.nodes[&id]
+            .children
+            .iter()
+            .filter(|child| active.contains(child))
+            .map(|&child| (child, self.nodes[&child].weight as u64 + 1))
+            .collect();
+
+        if id.is_zero() {
+            // Streams without priority information depend on the root.
+            let mut unknown: Vec<_> = remaining
+                .keys()
+                .filter(|id| !self.nodes.contains_key(id))
+                .cloned()
+                .collect();
+
+            unknown.sort();
+            children.extend(unknown.into_iter().map(|id| (id, DEFAULT_WEIGHT as u64 + 1)));
+        }
+
+        let total: u64 = children.iter().map(|&(_, weight)| weight).sum();
+
+        let mut split: Vec<u64> = children
+            .iter()
+            .map(|&(_, weight)| capacity as u64 * weight / total)
+            .collect();
+
+        // Rounding leftovers go to the heaviest dependency, so that no
+        // capacity is lost between passes.
+        let heaviest = (0..children.len())
+            .max_by_key(|&i| (children[i].1, cmp::Reverse(i)))
+            .expect("no active dependency");
+
+        split[heaviest] += capacity as u64 - split.iter().sum::<u64>();
+
+        for (&(child, _), sz) in children.iter().zip(split) {
+            if sz > 0 {
+                self.split(child, sz as WindowSize, remaining, active, shares);
+            }
+        }
+    }
+
+    /// Removes a stream from the tree.
+    ///
+    /// > When a stream is removed from the dependency tree, its dependencies
+    /// > can be moved to become dependent on the parent of the closed stream.
+    /// > The weights of new dependencies are recalculated by distributing the
+    /// > weight of the dependency of the closed stream proportionally based on
+    /// > the weights of its dependencies.
+    pub fn remove(&mut self, id: StreamId) {
+        self.detach(id);
+
+        let node = self.nodes.remove(&id).expect("stream not in tree");
+        let total: u32 = node.children
+            .iter()
+            .map(|child| self.nodes[child].weight as u32 + 1)
+            .sum();
+
+        for child in node.children {
+            {
+                let child = self.node_mut(child);
+                let weight = (node.weight as u32 + 1) * (child.weight as u32 + 1) / total;
+
+                child.weight = (weight.max(1) - 1) as u8;
+            }
+
+            self.attach(child, node.parent);
+        }
+    }
+
+    /// Returns true if `id` depends on `ancestor`, directly or not.
+    fn is_descendant(&self, mut id: StreamId, ancestor: StreamId) -> bool {
+        while !id.is_zero() {
+            id = self.nodes[&id].parent;
+
+            if id == ancestor {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn detach(&mut self, id: StreamId) {
+        let parent = self.nodes[&id].parent;
+        self.node_mut(parent).children.retain(|&child| child != id);
+    }
+
+    fn attach(&mut self, id: StreamId, parent: StreamId) {
+        self.node_mut(id).parent = parent;
+        self.node_mut(parent).children.push(id);
+    }
+
+    fn node_mut(&mut self, id: StreamId) -> &mut Node {
+        self.nodes.get_mut(&id).expect("stream not in tree")
+    }
+}
+
+impl Node {
+    fn new(parent: StreamId) -> Self {
+        Node {
+            parent,
+            weight: DEFAULT_WEIGHT,
+            is_exclusive: false,
+            children: Vec::new(),
+        }
+    }
+}
@@ -5,25 +5,18 @@ use std::fmt;
 
 // We don't want to send WINDOW_UPDATE frames for tiny changes, but instead
 // aggregate them when the changes are significant. Many implementations do
-// this by keeping a "ratio" of the update version the allowed window size.
+// this by keeping a "ratio" of the update versus the target window size.
 //
-// While some may wish to represent this ratio as percentage, using a f32,
-// we skip having to deal with float math and stick to integers. To do so,
-// the "ratio" is represented by 2 i32s, split into the numerator and
-// denominator. For example, a 50% ratio is simply represented as 1/2.
+// The ratio is configured as a percentage of the target window, the window
+// we want the peer to have once everything received was released. For
+// example, with a 50% threshold and a target window of 100 bytes,
+// WINDOW_UPDATE frames are scheduled once 50 bytes were released and not yet
+// announced.
 //
-// An example applying this ratio: If a stream has an allowed window size of
-// 100 bytes, WINDOW_UPDATE frames are scheduled when the unclaimed change
-// becomes greater than 1/2, or 50 bytes.
-const UNCLAIMED_NUMERATOR: i32 = 1;
-const UNCLAIMED_DENOMINATOR: i32 = 2;
-
-#[test]
-fn sanity_unclaimed_ratio() {
-    assert!(UNCLAIMED_NUMERATOR < UNCLAIMED_DENOMINATOR);
-    assert!(UNCLAIMED_NUMERATOR >= 0);
-    assert!(UNCLAIMED_DENOMINATOR > 0);
-}
+// The threshold is measured against the target rather than the window the
+// peer currently has. Otherwise a peer sending as fast as it can, and so
+// keeping its window close to zero, would get a WINDOW_UPDATE for nearly
+// every DATA frame released.
 
 #[derive(Copy, Clone, Debug)]
 pub struct FlowControl {
@@ -85,11 +78,17 @@ impl FlowControl {
     /// If a WINDOW_UPDATE frame should be sent, returns a positive number
     /// representing the increment to be used.
     ///
+    /// `in_flight` is the amount of received data not released yet, and
+    /// `threshold` the percentage of the target window that must be
+    /// unclaimed before an update is worth sending.
+    ///
     /// If there is no available bytes to be reclaimed, or the number of
     /// available bytes does not reach the threshold, this returns `None`.
+    /// The threshold is ignored when the peer has no window left and all
+    /// received data was released, as nothing else would ever unblock it.
     ///
     /// This represents pending outbound WINDOW_UPDATE frames.
-    pub fn unclaimed_capacity(&self) -> Option<WindowSize> {
+    pub fn unclaimed_capacity(&self, in_flight: WindowSize, threshold: u8) -> Option<WindowSize> {
         let available = self.available;
 
         if self.window_size >= available {
@@ -97,9 +96,13 @@ impl FlowControl {
         }
 
         let unclaimed = available.0 - self.window_size.0;
-        let threshold = self.window_size.0 / UNCLAIMED_DENOMINATOR * UNCLAIMED_NUMERATOR;
 
-        if unclaimed < threshold {
+        let target = available.0 as i64 + in_flight as i64;
+        let threshold = target * threshold as i64 / 100;
+
+        let is_stalled = self.window_size <= 0 && in_flight == 0;
+
+        if (unclaimed as i64) < threshold && !is_stalled {
             None
         } else {
             Some(unclaimed as WindowSize)
@@ -0,0 +1,35 @@
+use std::sync::{LockResult, Mutex, MutexGuard, TryLockError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A `Mutex` counting the acquisitions that had to wait for another thread
+/// to release it.
+#[derive(Debug)]
+pub(crate) struct Lock<T> {
+    mutex: Mutex<T>,
+    contended: AtomicUsize,
+}
+
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Lock {
+            mutex: Mutex::new(value),
+            contended: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn lock<'a>(&'a self) -> LockResult<MutexGuard<'a, T>> {
+        match self.mutex.try_lock() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(err)) => Err(err),
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.mutex.lock()
+            },
+        }
+    }
+
+    /// Returns the number of acquisitions that found the lock held.
+    pub fn num_contended(&self) -> usize {
+        self.contended.load(Ordering::Relaxed)
+    }
+}
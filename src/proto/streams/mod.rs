@@ -1,6 +1,8 @@
 mod buffer;
 mod counts;
+mod dependency;
 mod flow_control;
+mod lock;
 mod prioritize;
 mod recv;
 mod send;
@@ -12,11 +14,13 @@ mod streams;
 pub(crate) use self::prioritize::Prioritized;
 pub(crate) use self::recv::Open;
 pub(crate) use self::send::PollReset;
-pub(crate) use self::streams::{StreamRef, OpaqueStreamRef, Streams};
+pub(crate) use self::streams::{AbortHandle, StreamRef, OpaqueStreamRef, SettingsChanges, Streams};
 
 use self::buffer::Buffer;
 use self::counts::Counts;
+use self::dependency::DependencyTree;
 use self::flow_control::FlowControl;
+use self::lock::Lock;
 use self::prioritize::Prioritize;
 use self::recv::Recv;
 use self::send::Send;
@@ -24,6 +28,8 @@ use self::state::State;
 use self::store::Store;
 use self::stream::Stream;
 
+use codec::Padding;
+use instrument::Span;
 use frame::{StreamId, StreamIdOverflow};
 use proto::*;
 
@@ -48,15 +54,73 @@ pub struct Config {
     /// If the local peer is willing to receive push promises
     pub local_push_enabled: bool,
 
+    /// If the local peer supports the extended CONNECT method (RFC 8441)
+    pub local_connect_protocol_enabled: bool,
+
+    /// If streams are scheduled using the extensible priorities of RFC 9218
+    pub local_extensible_priorities: bool,
+
+    /// If 100 (Continue) responses are sent automatically to requests
+    /// expecting one
+    pub local_auto_100_continue: bool,
+
+    /// If malformed requests are passed to the application, which answers
+    /// them, instead of being reset
+    pub local_accept_malformed_requests: bool,
+
     /// How long a locally reset stream should ignore frames
     pub local_reset_duration: Duration,
 
     /// Maximum number of locally reset streams to keep at a time
     pub local_reset_max: usize,
 
+    /// Maximum number of remote initiated streams waiting to be accepted
+    pub local_max_pending_accept: Option<usize>,
+
+    /// Maximum number of locally initiated streams waiting to be opened
+    pub local_max_pending_open: Option<usize>,
+
+    /// Maximum number of streams reserved by the peer with PUSH_PROMISE
+    pub local_max_reserved_remote: usize,
+
+    /// Window updates are held back for streams buffering more than this
+    /// many bytes of received data
+    pub local_max_buffered_recv_data: Option<usize>,
+
+    /// How long a stream may buffer too much received data before it is reset
+    pub local_buffered_recv_timeout: Option<Duration>,
+
+    /// Maximum number of streams the peer may reset before a response was
+    /// sent, per `remote_reset_duration`
+    pub remote_reset_max: Option<usize>,
+
+    /// Interval over which streams reset by the peer are counted
+    pub remote_reset_duration: Duration,
+
+    /// Percentage of the target window released before WINDOW_UPDATE frames
+    /// are sent for received data
+    pub local_window_update_threshold: u8,
+
+    /// If the connection window is released separately from the stream
+    /// windows
+    pub local_manual_flow_control: bool,
+
+    /// Streams whose receive window stays exhausted for this long are
+    /// reported
+    pub local_window_stall_warning: Option<Duration>,
+
     /// Initial window size of remote initiated streams
     pub remote_init_window_sz: WindowSize,
 
     /// Maximum number of remote initiated streams
     pub remote_max_initiated: Option<usize>,
+
+    /// Strategy choosing the padding of sent DATA frames
+    pub local_padding: Option<Padding>,
+
+    /// Maximum size of the header blocks sent
+    pub local_max_send_header_block_size: Option<usize>,
+
+    /// Span of the connection, which the spans of the streams are nested in
+    pub span: Span,
 }
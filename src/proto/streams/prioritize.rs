@@ -7,9 +7,10 @@ use codec::UserError;
 use codec::UserError::*;
 
 use bytes::buf::Take;
+use iovec::IoVec;
 
 use std::{cmp, fmt, mem};
-use std::io;
+use std::collections::HashMap;
 
 /// # Warning
 ///
@@ -22,8 +23,12 @@ use std::io;
 /// idle stream is opened first.
 #[derive(Debug)]
 pub(super) struct Prioritize {
-    /// Queue of streams waiting for socket capacity to send a frame.
-    pending_send: store::Queue<stream::NextSend>,
+    /// Queues of streams waiting for socket capacity to send a frame.
+    ///
+    /// With extensible priorities, there is one queue per urgency level and
+    /// the most urgent streams are served first. Otherwise, there is a single
+    /// queue.
+    pending_send: Vec<store::Queue<stream::NextSend>>,
 
     /// Queue of streams waiting for window capacity to produce data.
     pending_capacity: store::Queue<stream::NextSendCapacity>,
@@ -31,24 +36,42 @@ pub(super) struct Prioritize {
     /// Streams waiting for capacity due to max concurrency
     ///
     /// The `SendRequest` handle is `Clone`. This enables initiating requests
-    /// from many tasks. However, offering this capability while supporting
-    /// backpressure at some level is tricky. If there are many `SendRequest`
-    /// handles and a single stream becomes available, which handle gets
-    /// assigned that stream? Maybe that handle is no longer ready to send a
-    /// request.
-    ///
-    /// The strategy used is to allow each `SendRequest` handle one buffered
-    /// request. A `SendRequest` handle is ready to send a request if it has no
-    /// associated buffered requests. This is the same strategy as `mpsc` in the
-    /// futures library.
+    /// from many tasks. A `SendRequest` handle is ready to send a request when
+    /// the stream can be opened right away. Requests sent regardless are
+    /// queued here, up to `max_pending_open`, and opened in order as streams
+    /// close.
     pending_open: store::Queue<stream::NextOpen>,
 
+    /// Number of streams in `pending_open`
+    num_pending_open: usize,
+
+    /// Maximum number of requests queued in `pending_open`
+    max_pending_open: usize,
+
+    /// Tasks waiting for a stream to be opened without being queued
+    open_ready_tasks: Vec<Task>,
+
     /// Connection level flow control governing sent data
     flow: FlowControl,
 
+    /// Amount of data buffered by all streams and not yet sent
+    buffered_send_data: usize,
+
+    /// Priorities of the streams, as sent by the remote peer. Connection
+    /// capacity is shared between the streams waiting for it according to
+    /// this tree.
+    dependencies: DependencyTree,
+
+    /// True when streams are scheduled according to the priority parameters
+    /// of RFC 9218 instead of the dependency tree.
+    is_extensible: bool,
+
     /// Stream ID of the last stream opened.
     last_opened_id: StreamId,
 
+    /// Chooses how much padding is added to the sent `DATA` frames.
+    padding: Option<Padding>,
+
     /// What `DATA` frame is currently being sent in the codec.
     in_flight_data_frame: InFlightData,
 }
@@ -87,16 +110,37 @@ impl Prioritize {
 
         trace!("Prioritize::new; flow={:?}", flow);
 
+        let levels = if config.local_extensible_priorities {
+            frame::MAX_URGENCY as usize + 1
+        } else {
+            1
+        };
+
         Prioritize {
-            pending_send: store::Queue::new(),
+            pending_send: (0..levels).map(|_| store::Queue::new()).collect(),
             pending_capacity: store::Queue::new(),
             pending_open: store::Queue::new(),
+            num_pending_open: 0,
+            max_pending_open: config.local_max_pending_open.unwrap_or(usize::MAX),
+            open_ready_tasks: Vec::new(),
             flow: flow,
+            buffered_send_data: 0,
+            dependencies: DependencyTree::new(),
+            is_extensible: config.local_extensible_priorities,
             last_opened_id: StreamId::ZERO,
+            padding: config.local_padding.clone(),
             in_flight_data_frame: InFlightData::Nothing,
         }
     }
 
+    pub fn dependencies(&self) -> &DependencyTree {
+        &self.dependencies
+    }
+
+    pub fn dependencies_mut(&mut self) -> &mut DependencyTree {
+        &mut self.dependencies
+    }
+
     /// Queue a frame to be sent to the remote
     pub fn queue_frame<B>(
         &mut self,
@@ -111,11 +155,12 @@ impl Prioritize {
     }
 
     pub fn schedule_send(&mut self, stream: &mut store::Ptr, task: &mut Option<Task>) {
-        // If the stream is waiting to be opened, nothing more to do.
-        if !stream.is_pending_open {
+        // If the stream is waiting to be opened or announced, nothing more
+        // to do.
+        if !stream.is_pending_open && !stream.is_pending_push {
             trace!("schedule_send; {:?}", stream.id);
             // Queue the stream
-            self.pending_send.push(stream);
+            self.push_send(stream);
 
             // Notify the connection.
             if let Some(task) = task.take() {
@@ -126,6 +171,32 @@ impl Prioritize {
 
     pub fn queue_open(&mut self, stream: &mut store::Ptr) {
         self.pending_open.push(stream);
+        self.num_pending_open += 1;
+    }
+
+    /// Returns true if a stream can be opened without being queued.
+    pub fn is_open_ready(&self, counts: &Counts) -> bool {
+        self.pending_open.is_empty() && counts.can_inc_num_send_streams()
+    }
+
+    /// Returns true if a new request can be sent, either opening its stream
+    /// right away or queuing it.
+    pub fn can_open_or_queue(&self, counts: &Counts) -> bool {
+        self.is_open_ready(counts) || self.num_pending_open < self.max_pending_open
+    }
+
+    /// Registers the current task to be notified once a stream can be opened
+    /// without being queued.
+    pub fn wait_open_ready(&mut self) {
+        if !self.open_ready_tasks.iter().any(|task| task.will_notify_current()) {
+            self.open_ready_tasks.push(task::current());
+        }
+    }
+
+    pub fn notify_open_ready(&mut self) {
+        for task in self.open_ready_tasks.drain(..) {
+            task.notify();
+        }
     }
 
     /// Send a data frame
@@ -149,7 +220,9 @@ impl Prioritize {
         let sz = sz as WindowSize;
 
         if !stream.state.is_send_streaming() {
-            if stream.state.is_closed() {
+            if stream.state.is_send_ended() {
+                return Err(UserError::SendAfterEndStream);
+            } else if stream.state.is_closed() {
                 return Err(InactiveStreamId);
             } else {
                 return Err(UnexpectedFrameType);
@@ -158,6 +231,7 @@ impl Prioritize {
 
         // Update the buffered data counter
         stream.buffered_send_data += sz;
+        self.buffered_send_data += sz as usize;
 
         trace!(
             "send_data; sz={}; buffered={}; requested={}",
@@ -216,17 +290,19 @@ impl Prioritize {
         capacity: WindowSize,
         stream: &mut store::Ptr,
         counts: &mut Counts) {
+        // Actual capacity is `capacity` + the current amount of buffered data.
+        // If it were less, then we could never send out the buffered data.
+        let effective = capacity.saturating_add(stream.buffered_send_data);
+
         trace!(
             "reserve_capacity; stream={:?}; requested={:?}; effective={:?}; curr={:?}",
             stream.id,
             capacity,
-            capacity + stream.buffered_send_data,
+            effective,
             stream.requested_send_capacity
         );
 
-        // Actual capacity is `capacity` + the current amount of buffered data.
-        // If it were less, then we could never send out the buffered data.
-        let capacity = capacity + stream.buffered_send_data;
+        let capacity = effective;
 
         if capacity == stream.requested_send_capacity {
             // Nothing to do
@@ -291,6 +367,16 @@ impl Prioritize {
         Ok(())
     }
 
+    /// Returns the connection level window data may still be sent in
+    pub fn connection_window(&self) -> WindowSize {
+        self.flow.window_size()
+    }
+
+    /// Returns the amount of data buffered by all streams and not yet sent
+    pub fn buffered_send_data(&self) -> usize {
+        self.buffered_send_data
+    }
+
     pub fn recv_connection_window_update(
         &mut self,
         inc: WindowSize,
@@ -345,37 +431,65 @@ impl Prioritize {
 
         self.flow.assign_capacity(inc);
 
-        // Assign newly acquired capacity to streams pending capacity.
-        while self.flow.available() > 0 {
-            let stream = match self.pending_capacity.pop(store) {
-                Some(stream) => stream,
-                None => return,
-            };
+        if self.flow.available() == 0 {
+            return;
+        }
+
+        // Take all the streams pending capacity along with the amount of
+        // capacity each of them can still use.
+        let mut pending = vec![];
+        let mut demand = HashMap::new();
+        let mut params = HashMap::new();
+
+        while let Some(stream) = self.pending_capacity.pop(store) {
+            demand.insert(stream.id, additional_capacity(&stream));
+            params.insert(stream.id, stream.priority_params);
+            pending.push(stream.key());
+        }
+
+        // Share the connection capacity according to the priorities of the
+        // streams instead of serving them in the order they were queued.
+        let available = self.flow.available().as_size();
+        let shares = if self.is_extensible {
+            allocate_by_urgency(&demand, &params, available)
+        } else {
+            self.dependencies.allocate(&demand, available)
+        };
+
+        for key in pending {
+            let stream = store.resolve(key);
+            let share = shares.get(&stream.id).cloned().unwrap_or(0);
+            let wants_capacity = demand[&stream.id] > 0;
 
             counts.transition(stream, |_, mut stream| {
-                // Try to assign capacity to the stream. This will also re-queue the
-                // stream if there isn't enough connection level capacity to fulfill
-                // the capacity request.
-                self.try_assign_capacity(&mut stream);
+                if share == 0 {
+                    // Keep waiting for more capacity, if still needed.
+                    if wants_capacity {
+                        self.pending_capacity.push(&mut stream);
+                    }
+                } else {
+                    // Assign the share to the stream. This will also re-queue
+                    // the stream if the share does not fulfill its capacity
+                    // request.
+                    self.assign_capacity_up_to(share, &mut stream);
+                }
             })
         }
     }
 
     /// Request capacity to send data
     fn try_assign_capacity(&mut self, stream: &mut store::Ptr) {
-        let total_requested = stream.requested_send_capacity;
+        let conn_available = self.flow.available().as_size();
+        self.assign_capacity_up_to(conn_available, stream);
+    }
 
-        // Total requested should never go below actual assigned
-        // (Note: the window size can go lower than assigned)
-        debug_assert!(total_requested >= stream.send_flow.available());
+    /// Assign the stream up to `max` of the connection capacity, queuing it
+    /// if it needs more.
+    fn assign_capacity_up_to(&mut self, max: WindowSize, stream: &mut store::Ptr) {
+        let total_requested = stream.requested_send_capacity;
 
         // The amount of additional capacity that the stream requests.
-        // Don't assign more than the window has available!
-        let additional = cmp::min(
-            total_requested - stream.send_flow.available().as_size(),
-            // Can't assign more than what is available
-            stream.send_flow.window_size() - stream.send_flow.available().as_size(),
-        );
+        let additional = additional_capacity(stream);
 
         trace!(
             "try_assign_capacity; requested={}; additional={}; buffered={}; window={}; conn={}",
@@ -400,13 +514,12 @@ impl Prioritize {
             stream.state
         );
 
-        // The amount of currently available capacity on the connection
-        let conn_available = self.flow.available().as_size();
+        // The amount of connection capacity the stream may take
+        let conn_available = cmp::min(self.flow.available().as_size(), max);
 
         // First check if capacity is immediately available
         if conn_available > 0 {
             // The amount of capacity to assign to the stream
-            // TODO: Should prioritization factor into this?
             let assign = cmp::min(conn_available, additional);
 
             trace!("  assigning; num={}", assign);
@@ -450,7 +563,7 @@ impl Prioritize {
         // If the stream is not open, we don't want the stream to be scheduled for
         // execution (pending_send). Note that if the stream is in pending_open, it will be
         // pushed to pending_send when there is room for an open stream.
-        if stream.buffered_send_data > 0 && !stream.is_pending_open {
+        if stream.buffered_send_data > 0 && !stream.is_pending_open && !stream.is_pending_push {
             // TODO: This assertion isn't *exactly* correct. There can still be
             // buffered send data while the stream's pending send queue is
             // empty. This can happen when a large data frame is in the process
@@ -463,127 +576,96 @@ impl Prioritize {
             //
             // debug_assert!(!stream.pending_send.is_empty());
 
-            self.pending_send.push(stream);
+            self.push_send(stream);
         }
     }
 
-    pub fn poll_complete<T, B>(
+    /// Returns the next frame to write, after taking back `written`, the
+    /// data frame that was written last.
+    ///
+    /// The frame is written to the codec by the caller, once the streams are
+    /// unlocked, so that encoding it does not hold up the stream handles.
+    pub fn pop_frame_to_write<B>(
         &mut self,
+        written: Option<frame::Data<Prioritized<B>>>,
         buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
+        max_frame_len: usize,
         counts: &mut Counts,
-        dst: &mut Codec<T, Prioritized<B>>,
-    ) -> Poll<(), io::Error>
+    ) -> Option<(Frame<Prioritized<B>>, Span)>
     where
-        T: AsyncWrite,
         B: Buf,
     {
-        // Ensure codec is ready
-        try_ready!(dst.poll_ready());
-
         // Reclaim any frame that has previously been written
-        self.reclaim_frame(buffer, store, dst);
-
-        // The max frame length
-        let max_frame_len = dst.max_send_frame_size();
-
-        trace!("poll_complete");
-
-        loop {
-            self.schedule_pending_open(store, counts);
-
-            match self.pop_frame(buffer, store, max_frame_len, counts) {
-                Some(frame) => {
-                    trace!("writing frame={:?}", frame);
-
-                    debug_assert_eq!(self.in_flight_data_frame, InFlightData::Nothing);
-                    if let Frame::Data(ref frame) = frame {
-                        self.in_flight_data_frame = InFlightData::DataFrame(frame.payload().stream);
-                    }
-                    dst.buffer(frame).ok().expect("invalid frame");
+        if let Some(frame) = written {
+            self.reclaim_frame(frame, buffer, store);
+        }
 
-                    // Ensure the codec is ready to try the loop again.
-                    try_ready!(dst.poll_ready());
+        self.schedule_pending_open(store, counts);
 
-                    // Because, always try to reclaim...
-                    self.reclaim_frame(buffer, store, dst);
-                },
-                None => {
-                    // Try to flush the codec.
-                    try_ready!(dst.flush());
-
-                    // This might release a data frame...
-                    if !self.reclaim_frame(buffer, store, dst) {
-                        return Ok(().into());
-                    }
+        let (frame, span) = self.pop_frame(buffer, store, max_frame_len, counts)?;
 
-                    // No need to poll ready as poll_complete() does this for
-                    // us...
-                },
-            }
+        debug_assert_eq!(self.in_flight_data_frame, InFlightData::Nothing);
+        if let Frame::Data(ref frame) = frame {
+            self.in_flight_data_frame = InFlightData::DataFrame(frame.payload().stream);
         }
+
+        Some((frame, span))
     }
 
-    /// Tries to reclaim a pending data frame from the codec.
-    ///
-    /// Returns true if a frame was reclaimed.
+    /// Reclaims a data frame taken back from the codec.
     ///
     /// When a data frame is written to the codec, it may not be written in its
     /// entirety (large chunks are split up into potentially many data frames).
     /// In this case, the stream needs to be reprioritized.
-    fn reclaim_frame<T, B>(
+    fn reclaim_frame<B>(
         &mut self,
+        frame: frame::Data<Prioritized<B>>,
         buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
-        dst: &mut Codec<T, Prioritized<B>>,
-    ) -> bool
+    )
     where
         B: Buf,
     {
-        trace!("try reclaim frame");
-
-        // First check if there are any data chunks to take back
-        if let Some(frame) = dst.take_last_data_frame() {
-            trace!(
-                "  -> reclaimed; frame={:?}; sz={}",
-                frame,
-                frame.payload().inner.get_ref().remaining()
-            );
-
-            let mut eos = false;
-            let key = frame.payload().stream;
-
-            match mem::replace(&mut self.in_flight_data_frame, InFlightData::Nothing) {
-                InFlightData::Nothing => panic!("wasn't expecting a frame to reclaim"),
-                InFlightData::Drop => {
-                    trace!("not reclaiming frame for cancelled stream");
-                    return false;
-                }
-                InFlightData::DataFrame(k) => {
-                    debug_assert_eq!(k, key);
-                }
-            }
+        trace!(
+            "  -> reclaimed; frame={:?}; sz={}",
+            frame,
+            frame.payload().inner.get_ref().remaining()
+        );
 
-            let mut frame = frame.map(|prioritized| {
-                // TODO: Ensure fully written
-                eos = prioritized.end_of_stream;
-                prioritized.inner.into_inner()
-            });
+        let mut eos = false;
+        let key = frame.payload().stream;
 
-            if frame.payload().has_remaining() {
-                let mut stream = store.resolve(key);
+        match mem::replace(&mut self.in_flight_data_frame, InFlightData::Nothing) {
+            InFlightData::Nothing => panic!("wasn't expecting a frame to reclaim"),
+            InFlightData::Drop => {
+                trace!("not reclaiming frame for cancelled stream");
+                return;
+            }
+            InFlightData::DataFrame(k) => {
+                debug_assert_eq!(k, key);
+            }
+        }
 
-                if eos {
-                    frame.set_end_stream(true);
-                }
+        let mut frame = frame.map(|prioritized| {
+            // TODO: Ensure fully written
+            eos = prioritized.end_of_stream;
+            prioritized.inner.into_inner()
+        });
 
-                self.push_back_frame(frame.into(), buffer, &mut stream);
+        if frame.payload().has_remaining() {
+            let mut stream = store.resolve(key);
 
-                return true;
+            if eos {
+                frame.set_end_stream(true);
             }
-        }
 
-        false
+            // The padding is chosen again when the rest of the data is
+            // sent.
+            frame.unset_padding();
+
+            self.push_back_frame(frame.into(), buffer, &mut stream);
+        }
     }
 
     /// Push the frame to the front of the stream's deque, scheduling the
@@ -599,7 +681,7 @@ impl Prioritize {
         // If needed, schedule the sender
         if stream.send_flow.available() > 0 {
             debug_assert!(!stream.pending_send.is_empty());
-            self.pending_send.push(stream);
+            self.push_send(stream);
         }
     }
 
@@ -609,8 +691,20 @@ impl Prioritize {
         // TODO: make this more efficient?
         while let Some(frame) = stream.pending_send.pop_front(buffer) {
             trace!("dropping; frame={:?}", frame);
+
+            if let Frame::PushPromise(frame) = frame {
+                // The pushed stream is never announced to the peer, so it is
+                // closed without sending any of its frames.
+                if let Some(mut pushed) = stream.store_mut().find_mut(&frame.promised_id()) {
+                    pushed.is_pending_push = false;
+                    pushed.state.set_reset(Reason::CANCEL);
+                    self.clear_queue(buffer, &mut pushed);
+                    pushed.notify_send();
+                }
+            }
         }
 
+        self.buffered_send_data -= stream.buffered_send_data as usize;
         stream.buffered_send_data = 0;
         stream.requested_send_capacity = 0;
         if let InFlightData::DataFrame(key) = self.in_flight_data_frame {
@@ -622,17 +716,66 @@ impl Prioritize {
     }
 
     pub fn clear_pending_send(&mut self, store: &mut Store, counts: &mut Counts) {
-        while let Some(stream) = self.pending_send.pop(store) {
-            let is_pending_reset = stream.is_pending_reset_expiration();
-            counts.transition_after(stream, is_pending_reset);
+        for queue in &mut self.pending_send {
+            while let Some(stream) = queue.pop(store) {
+                let is_pending_reset = stream.is_pending_reset_expiration();
+                counts.transition_after(stream, is_pending_reset);
+            }
+        }
+    }
+
+    /// Returns the index of the send queue for the stream.
+    fn send_level(&self, stream: &Stream) -> usize {
+        if self.is_extensible {
+            stream.priority_params.urgency() as usize
+        } else {
+            0
+        }
+    }
+
+    /// Queue the stream to send its next frame.
+    fn push_send(&mut self, stream: &mut store::Ptr) {
+        let level = self.send_level(stream);
+        self.pending_send[level].push(stream);
+    }
+
+    /// Pops the next stream to send a frame, starting with the most urgent
+    /// ones.
+    fn pop_send<'a>(&mut self, store: &'a mut Store) -> Option<store::Ptr<'a>> {
+        loop {
+            let level = match self.pending_send.iter().position(|queue| !queue.is_empty()) {
+                Some(level) => level,
+                None => return None,
+            };
+
+            let key = {
+                let mut stream = self.pending_send[level].pop(store).unwrap();
+
+                // The urgency may have been updated while the stream was
+                // queued, in which case it moves to its new queue.
+                let current = self.send_level(&stream);
+
+                if current != level {
+                    self.pending_send[current].push(&mut stream);
+                    continue;
+                }
+
+                stream.key()
+            };
+
+            return Some(store.resolve(key));
         }
     }
 
     pub fn clear_pending_open(&mut self, store: &mut Store, counts: &mut Counts) {
         while let Some(stream) = self.pending_open.pop(store) {
+            self.num_pending_open -= 1;
+
             let is_pending_reset = stream.is_pending_reset_expiration();
             counts.transition_after(stream, is_pending_reset);
         }
+
+        self.notify_open_ready();
     }
 
     fn pop_frame<B>(
@@ -641,15 +784,18 @@ impl Prioritize {
         store: &mut Store,
         max_len: usize,
         counts: &mut Counts,
-    ) -> Option<Frame<Prioritized<B>>>
+    ) -> Option<(Frame<Prioritized<B>>, Span)>
     where
         B: Buf,
     {
         trace!("pop_frame");
 
         loop {
-            match self.pending_send.pop(store) {
+            match self.pop_send(store) {
                 Some(mut stream) => {
+                    let span = stream.span.clone();
+                    let _enter = span.enter();
+
                     trace!("pop_frame; stream={:?}; stream.state={:?}",
                         stream.id, stream.state);
 
@@ -663,6 +809,8 @@ impl Prioritize {
                     trace!(" --> stream={:?}; is_pending_reset={:?};",
                         stream.id, is_pending_reset);
 
+                    let mut promised_id = None;
+
                     let frame = match stream.pending_send.pop_front(buffer) {
                         Some(Frame::Data(mut frame)) => {
                             // Get the amount of capacity remaining for stream's
@@ -707,34 +855,70 @@ impl Prioritize {
                                 continue;
                             }
 
-                            // Only send up to the max frame length
-                            let len = cmp::min(sz, max_len);
+                            let pad_len = self.padding.as_ref()
+                                .map(|padding| padding.pad_len(cmp::min(sz, max_len)) as usize)
+                                .unwrap_or(0);
+
+                            if pad_len > 0 {
+                                // The padding is not part of the capacity
+                                // requested by the stream, so it is taken from
+                                // the connection when possible.
+                                let extra = cmp::min(
+                                    (pad_len + 1) as WindowSize,
+                                    cmp::min(
+                                        stream.send_flow.window_size()
+                                            .saturating_sub(stream.send_flow.available().as_size()),
+                                        self.flow.available().as_size(),
+                                    ),
+                                );
+
+                                if extra > 0 {
+                                    stream.send_flow.assign_capacity(extra);
+                                    self.flow.claim_capacity(extra);
+                                }
+                            }
+
+                            // Only send up to the max frame length and up to
+                            // the stream's window capacity
+                            let stream_capacity = stream.send_flow.available().as_size();
+                            let budget = cmp::min(max_len, stream_capacity as usize);
+
+                            // The padding counts against both, but must leave
+                            // room for at least one octet of data.
+                            let min_len = if sz > 0 { 1 } else { 0 };
+                            let pad_len = cmp::min(pad_len, budget.saturating_sub(1 + min_len));
+                            let overhead = if pad_len > 0 { pad_len + 1 } else { 0 };
+
+                            if pad_len > 0 {
+                                frame.set_pad_len(pad_len as u8);
+                            }
 
-                            // Only send up to the stream's window capacity
-                            let len = cmp::min(len, stream_capacity.as_size() as usize) as WindowSize;
+                            let len = cmp::min(sz, budget - overhead) as WindowSize;
+                            let flow_len = len + overhead as WindowSize;
 
                             // There *must* be be enough connection level
                             // capacity at this point.
-                            debug_assert!(len <= self.flow.window_size());
+                            debug_assert!(flow_len <= self.flow.window_size());
 
-                            trace!(" --> sending data frame; len={}", len);
+                            trace!(" --> sending data frame; len={}; pad_len={}", len, pad_len);
 
                             // Update the flow control
                             trace!(" -- updating stream flow --");
-                            stream.send_flow.send_data(len);
+                            stream.send_flow.send_data(flow_len);
 
                             // Decrement the stream's buffered data counter
                             debug_assert!(stream.buffered_send_data >= len);
                             stream.buffered_send_data -= len;
+                            self.buffered_send_data -= len as usize;
                             stream.requested_send_capacity -= len;
 
                             // Assign the capacity back to the connection that
                             // was just consumed from the stream in the previous
                             // line.
-                            self.flow.assign_capacity(len);
+                            self.flow.assign_capacity(flow_len);
 
                             trace!(" -- updating connection flow --");
-                            self.flow.send_data(len);
+                            self.flow.send_data(flow_len);
 
                             // Wrap the frame's data payload to ensure that the
                             // correct amount of data gets written.
@@ -754,6 +938,10 @@ impl Prioritize {
                                 }
                             }))
                         },
+                        Some(Frame::PushPromise(frame)) => {
+                            promised_id = Some(frame.promised_id());
+                            Frame::PushPromise(frame)
+                        },
                         Some(frame) => frame.map(|_|
                             unreachable!(
                                 "Frame::map closure will only be called \
@@ -793,35 +981,144 @@ impl Prioritize {
                         // the next frame. i.e. don't requeue it if the next
                         // frame is a data frame and the stream does not have
                         // any more capacity.
-                        self.pending_send.push(&mut stream);
+                        if self.is_extensible && !stream.priority_params.is_incremental() {
+                            // Non incremental streams are sent one after the
+                            // other, so the stream keeps its place.
+                            let level = self.send_level(&stream);
+                            self.pending_send[level].push_front(&mut stream);
+                        } else {
+                            self.push_send(&mut stream);
+                        }
                     }
 
                     counts.transition_after(stream, is_pending_reset);
 
-                    return Some(frame);
+                    if let Some(promised_id) = promised_id {
+                        self.schedule_pushed(promised_id, store, counts);
+                    }
+
+                    return Some((frame, span.clone()));
                 },
                 None => return None,
             }
         }
     }
 
+    /// Schedules the frames of a stream once the PUSH_PROMISE frame reserving
+    /// it is written.
+    fn schedule_pushed(&mut self, promised_id: StreamId, store: &mut Store, counts: &mut Counts) {
+        let stream = match store.find_mut(&promised_id) {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        trace!("schedule_pushed; stream={:?}", stream.id);
+
+        counts.transition(stream, |counts, stream| {
+            stream.is_pending_push = false;
+
+            if stream.state.is_reset() {
+                // Only the RST_STREAM frame is left to send.
+                if !stream.pending_send.is_empty() || stream.state.is_scheduled_reset() {
+                    self.push_send(stream);
+                }
+            } else if !stream.pending_send.is_empty() {
+                // The response was sent while the stream was reserved, so it
+                // is opened now.
+                if counts.can_inc_num_send_streams() {
+                    counts.inc_num_send_streams(stream);
+                    self.push_send(stream);
+                } else {
+                    self.queue_open(stream);
+                }
+            }
+        });
+    }
+
     fn schedule_pending_open(&mut self, store: &mut Store, counts: &mut Counts) {
         trace!("schedule_pending_open");
         // check for any pending open streams
         while counts.can_inc_num_send_streams() {
             if let Some(mut stream) = self.pending_open.pop(store) {
                 trace!("schedule_pending_open; stream={:?}", stream.id);
+                self.num_pending_open -= 1;
 
                 counts.inc_num_send_streams(&mut stream);
-                self.pending_send.push(&mut stream);
+                self.push_send(&mut stream);
                 stream.notify_send();
             } else {
+                // A slot is left for a new request.
+                self.notify_open_ready();
                 return;
             }
         }
     }
 }
 
+/// Returns the amount of capacity the stream requests on top of what it has
+/// been assigned, without going over the stream window.
+fn additional_capacity(stream: &Stream) -> WindowSize {
+    let total_requested = stream.requested_send_capacity;
+
+    // Total requested should never go below actual assigned
+    // (Note: the window size can go lower than assigned)
+    debug_assert!(total_requested >= stream.send_flow.available());
+
+    cmp::min(
+        total_requested - stream.send_flow.available().as_size(),
+        // Can't assign more than what is available
+        stream.send_flow.window_size() - stream.send_flow.available().as_size(),
+    )
+}
+
+/// Shares `capacity` between the streams in `demand` according to their
+/// priority parameters (RFC 9218).
+///
+/// More urgent streams are served first. Within an urgency level, streams
+/// that are not incremental are served one after the other, then incremental
+/// streams split the remaining capacity evenly.
+fn allocate_by_urgency(
+    demand: &HashMap<StreamId, WindowSize>,
+    params: &HashMap<StreamId, frame::PriorityParams>,
+    mut capacity: WindowSize,
+) -> HashMap<StreamId, WindowSize> {
+    let mut ids: Vec<_> = demand.keys().cloned().collect();
+    ids.sort_by_key(|id| (params[id].urgency(), params[id].is_incremental(), *id));
+
+    let mut shares = HashMap::new();
+
+    for urgency in 0..(frame::MAX_URGENCY + 1) {
+        let (incremental, sequential): (Vec<StreamId>, Vec<StreamId>) = ids
+            .iter()
+            .filter(|id| params[id].urgency() == urgency)
+            .partition(|id| params[id].is_incremental());
+
+        for id in sequential {
+            let sz = cmp::min(demand[&id], capacity);
+            shares.insert(id, sz);
+            capacity -= sz;
+        }
+
+        let mut waiting = incremental;
+
+        while capacity > 0 && !waiting.is_empty() {
+            let each = cmp::max(capacity / waiting.len() as WindowSize, 1);
+
+            for &id in &waiting {
+                let assigned = shares.entry(id).or_insert(0);
+                let sz = cmp::min(cmp::min(each, demand[&id] - *assigned), capacity);
+
+                *assigned += sz;
+                capacity -= sz;
+            }
+
+            waiting.retain(|id| shares[id] < demand[id]);
+        }
+    }
+
+    shares
+}
+
 // ===== impl Prioritized =====
 
 impl<B> Buf for Prioritized<B>
@@ -836,6 +1133,30 @@ where
         self.inner.bytes()
     }
 
+    fn bytes_vec<'a>(&'a self, dst: &mut [&'a IoVec]) -> usize {
+        // `Take` only passes on the first chunk, the chunks of the inner
+        // buffer are cut at the limit instead.
+        let n = self.inner.get_ref().bytes_vec(dst);
+        let mut rem = self.inner.limit();
+
+        for i in 0..n {
+            if rem == 0 {
+                return i;
+            }
+
+            let chunk: &'a IoVec = dst[i];
+
+            if chunk.len() >= rem {
+                dst[i] = chunk[..rem].into();
+                return i + 1;
+            }
+
+            rem -= chunk.len();
+        }
+
+        n
+    }
+
     fn advance(&mut self, cnt: usize) {
         self.inner.advance(cnt)
     }
@@ -1,13 +1,18 @@
 use super::*;
-use {frame, proto};
+use {frame, proto, timer};
 use codec::{RecvError, UserError};
 use frame::{Reason, DEFAULT_INITIAL_WINDOW_SIZE};
 
 use http::HeaderMap;
 
+use std::collections::HashMap;
 use std::io;
 use std::time::{Duration, Instant};
 
+/// Maximum number of idle streams for which priority parameters are kept
+/// until the stream is opened.
+const MAX_PENDING_PRIORITY_UPDATES: usize = 100;
+
 #[derive(Debug)]
 pub(super) struct Recv {
     /// Initial window size of remote initiated streams
@@ -19,7 +24,28 @@ pub(super) struct Recv {
     /// Amount of connection window capacity currently used by outstanding streams.
     in_flight_data: WindowSize,
 
-    /// The lowest stream ID that is still idle
+    /// Percentage of the target window that must be released before a
+    /// WINDOW_UPDATE frame is sent.
+    window_update_threshold: u8,
+
+    /// True when the target connection window was raised and the new capacity
+    /// must be announced without waiting for the threshold to be reached.
+    is_connection_window_raised: bool,
+
+    /// If the connection window is released separately from the stream
+    /// windows, by `release_connection_share`.
+    is_manual_flow_control: bool,
+
+    /// Receive windows exhausted for this long are reported
+    window_stall_warning: Option<Duration>,
+
+    /// The time when the peer ran out of connection window, if window stalls
+    /// are reported.
+    conn_window_stalled_at: Option<Instant>,
+
+    /// The lowest stream ID that is still idle, right above the highest
+    /// stream ID the peer opened. Lower IDs are either open, and in the
+    /// store, or closed.
     next_stream_id: Result<StreamId, StreamIdOverflow>,
 
     /// The stream ID of the last processed stream
@@ -37,15 +63,47 @@ pub(super) struct Recv {
     /// Streams that have pending window updates
     pending_window_updates: store::Queue<stream::NextWindowUpdate>,
 
+    /// Streams waiting for their 100 (Continue) response to be sent
+    pending_continue: store::Queue<stream::NextContinue>,
+
+    /// If requests expecting a 100 (Continue) response get one once their
+    /// body is polled.
+    is_auto_100_continue_enabled: bool,
+
     /// New streams to be accepted
     pending_accept: store::Queue<stream::NextAccept>,
 
+    /// Malformed requests to be accepted, when the application answers them
+    /// itself. A stream is never in both this queue and `pending_accept`.
+    pending_malformed: store::Queue<stream::NextAccept>,
+
+    /// If malformed requests are queued in `pending_malformed` instead of
+    /// being reset
+    is_accept_malformed_enabled: bool,
+
+    /// Number of streams in `pending_accept` and `pending_malformed`
+    num_pending_accept: usize,
+
+    /// Streams received while this many are waiting to be accepted are
+    /// refused
+    max_pending_accept: usize,
+
     /// Locally reset streams that should be reaped when they expire
     pending_reset_expired: store::Queue<stream::NextResetExpire>,
 
     /// How long locally reset streams should ignore received frames
     reset_duration: Duration,
 
+    /// Window updates are held back for streams buffering more than this
+    /// many bytes of unread data
+    max_buffered_recv_data: usize,
+
+    /// Streams buffering too much data for this long are reset
+    buffered_recv_timeout: Option<Duration>,
+
+    /// Streams buffering too much data, checked for `buffered_recv_timeout`
+    pending_recv_stalled: store::Queue<stream::NextRecvStall>,
+
     /// Holds frames that are waiting to be read
     buffer: Buffer<Event>,
 
@@ -54,12 +112,22 @@ pub(super) struct Recv {
 
     /// If push promises are allowed to be recevied.
     is_push_enabled: bool,
+
+    /// If requests are allowed to carry the `:protocol` pseudo header.
+    is_extended_connect_protocol_enabled: bool,
+
+    /// If streams are prioritized with the parameters of RFC 9218.
+    is_extensible_priorities_enabled: bool,
+
+    /// Priority parameters received in PRIORITY_UPDATE frames for streams
+    /// that are still idle.
+    pending_priority_updates: HashMap<StreamId, frame::PriorityParams>,
 }
 
 #[derive(Debug)]
 pub(super) enum Event {
     Headers(peer::PollMessage),
-    Data(Bytes),
+    Data(Bytes, ::DataFrameInfo),
     Trailers(HeaderMap),
 }
 
@@ -97,16 +165,33 @@ impl Recv {
             init_window_sz: config.local_init_window_sz,
             flow: flow,
             in_flight_data: 0 as WindowSize,
+            window_update_threshold: config.local_window_update_threshold,
+            is_connection_window_raised: false,
+            is_manual_flow_control: config.local_manual_flow_control,
+            window_stall_warning: config.local_window_stall_warning,
+            conn_window_stalled_at: None,
             next_stream_id: Ok(next_stream_id.into()),
             pending_window_updates: store::Queue::new(),
+            pending_continue: store::Queue::new(),
+            is_auto_100_continue_enabled: config.local_auto_100_continue,
             last_processed_id: StreamId::ZERO,
             max_stream_id: StreamId::MAX,
             pending_accept: store::Queue::new(),
+            pending_malformed: store::Queue::new(),
+            is_accept_malformed_enabled: config.local_accept_malformed_requests,
+            num_pending_accept: 0,
+            max_pending_accept: config.local_max_pending_accept.unwrap_or(usize::MAX),
             pending_reset_expired: store::Queue::new(),
             reset_duration: config.local_reset_duration,
+            max_buffered_recv_data: config.local_max_buffered_recv_data.unwrap_or(usize::MAX),
+            buffered_recv_timeout: config.local_buffered_recv_timeout,
+            pending_recv_stalled: store::Queue::new(),
             buffer: Buffer::new(),
             refused: None,
             is_push_enabled: config.local_push_enabled,
+            is_extended_connect_protocol_enabled: config.local_connect_protocol_enabled,
+            is_extensible_priorities_enabled: config.local_extensible_priorities,
+            pending_priority_updates: HashMap::new(),
         }
     }
 
@@ -115,6 +200,53 @@ impl Recv {
         self.init_window_sz
     }
 
+    /// Returns the connection level window the peer may still send data in
+    pub fn connection_window(&self) -> WindowSize {
+        self.flow.window_size()
+    }
+
+    /// Applies our own SETTINGS frame once the peer has acknowledged it.
+    pub fn apply_local_settings(
+        &mut self,
+        settings: &frame::Settings,
+        store: &mut Store,
+    ) -> Result<(), RecvError> {
+        if let Some(target) = settings.initial_window_size() {
+            let old_sz = self.init_window_sz;
+            self.init_window_sz = target;
+
+            trace!("update_initial_window_size; new={}; old={}", target, old_sz);
+
+            // The peer adjusts the send window of every stream by the
+            // difference between the old and new value, so the receive
+            // windows of the streams opened so far must follow.
+            if target < old_sz {
+                let dec = old_sz - target;
+
+                store.for_each(|mut stream| {
+                    stream.recv_flow.dec_window(dec);
+
+                    let available = stream.recv_flow.available().as_size();
+                    stream.recv_flow.claim_capacity(::std::cmp::min(dec, available));
+                    Ok::<_, RecvError>(())
+                })?;
+            } else if target > old_sz {
+                let inc = target - old_sz;
+
+                store.for_each(|mut stream| {
+                    stream.recv_flow
+                        .inc_window(inc)
+                        .map_err(RecvError::Connection)?;
+                    stream.recv_flow.assign_capacity(inc);
+                    stream.recv_window_stalled_at = None;
+                    Ok::<_, RecvError>(())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Returns the ID of the last processed stream
     pub fn last_processed_id(&self) -> StreamId {
         self.last_processed_id
@@ -131,8 +263,12 @@ impl Recv {
     ) -> Result<Option<StreamId>, RecvError> {
         assert!(self.refused.is_none());
 
+        let is_push_promise = mode.is_push_promise();
         counts.peer().ensure_can_open(id, mode)?;
 
+        // Stream IDs must increase (section 5.1.1 of RFC 7540). Lower IDs
+        // belong to streams that were closed, implicitly or not, so they
+        // cannot be opened, even if a PRIORITY frame was received for them.
         let next_id = self.next_stream_id()?;
         if id < next_id {
             trace!("id ({:?}) < next_id ({:?}), PROTOCOL_ERROR", id, next_id);
@@ -141,7 +277,27 @@ impl Recv {
 
         self.next_stream_id = id.next_id();
 
-        if !counts.can_inc_num_recv_streams() {
+        // Streams above the ID announced in our GOAWAY are not processed. The
+        // peer is told so it knows they can be safely retried.
+        if id > self.max_stream_id {
+            trace!("id ({:?}) > max_stream_id ({:?}), refusing stream", id, self.max_stream_id);
+            self.refused = Some(id);
+            return Ok(None);
+        }
+
+        // Reserved streams do not count towards the concurrency limit
+        // (section 5.1.2 of RFC 7540). The pushed response is refused instead
+        // if the limit is reached when it starts.
+        if !is_push_promise && !counts.can_inc_num_recv_streams() {
+            trace!("too many concurrent streams; refusing {:?}", id);
+            self.refused = Some(id);
+            return Ok(None);
+        }
+
+        // Promises beyond the limit are refused before their request is
+        // stored.
+        if is_push_promise && !counts.can_inc_num_reserved_recv_streams() {
+            trace!("too many reserved streams; refusing {:?}", id);
             self.refused = Some(id);
             return Ok(None);
         }
@@ -167,26 +323,91 @@ impl Recv {
                 self.last_processed_id = frame.stream_id();
             }
 
+            // Reserved streams only count towards the limit once the pushed
+            // response starts.
+            if !counts.can_inc_num_recv_streams() {
+                trace!("recv_headers; too many pushed streams; refusing {:?}", stream.id);
+                return Err(RecvError::Stream {
+                    id: stream.id,
+                    reason: Reason::REFUSED_STREAM,
+                }.into());
+            }
+
             // Increment the number of concurrent streams
             counts.inc_num_recv_streams(stream);
+
+            // The application is not keeping up with accepting streams. The
+            // header block was decoded already, so the HPACK state is in sync.
+            if counts.peer().is_server() && self.num_pending_accept >= self.max_pending_accept {
+                trace!("recv_headers; too many streams pending accept; refusing {:?}", stream.id);
+                return Err(RecvError::Stream {
+                    id: stream.id,
+                    reason: Reason::REFUSED_STREAM,
+                }.into());
+            }
         }
 
-        if !stream.content_length.is_head() {
-            use super::stream::ContentLength;
-            use http::header;
-
-            if let Some(content_length) = frame.fields().get(header::CONTENT_LENGTH) {
-                let content_length = match parse_u64(content_length.as_bytes()) {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Err(RecvError::Stream {
-                            id: stream.id,
-                            reason: Reason::PROTOCOL_ERROR,
-                        }.into())
-                    },
+        // Malformed requests are handed to the application instead of being
+        // reset, when it asked to answer them itself.
+        let accepts_malformed = is_initial &&
+            counts.peer().is_server() &&
+            self.is_accept_malformed_enabled;
+
+        macro_rules! malformed {
+            ($reason:expr, $pseudo:expr, $fields:expr) => {{
+                trace!("recv_headers; malformed request: {}; stream={:?}", $reason, stream.id);
+                return if accepts_malformed {
+                    self.queue_malformed(stream, $pseudo, $fields, $reason);
+                    Ok(())
+                } else {
+                    Err(malformed(stream).into())
                 };
+            }};
+        }
+
+        if is_initial && self.is_extensible_priorities_enabled {
+            let mut params = frame.fields()
+                .get("priority")
+                .map(|value| frame::PriorityParams::parse(value.as_bytes()))
+                .unwrap_or_default();
 
-                stream.content_length = ContentLength::Remaining(content_length);
+            // A PRIORITY_UPDATE frame received before the request overrides
+            // the header field (RFC 9218, section 7.1.).
+            if let Some(update) = self.pending_priority_updates.remove(&stream.id) {
+                params = update;
+            }
+
+            stream.priority_params = params;
+        }
+
+        {
+            use super::stream::ContentLength;
+            use http::StatusCode;
+
+            let content_length = match parse_content_length(frame.fields()) {
+                Ok(v) => v,
+                Err(_) => {
+                    let (pseudo, fields) = frame.into_parts();
+                    malformed!("invalid content-length", pseudo, fields)
+                },
+            };
+
+            match frame.pseudo().status {
+                // These responses never have a body, whatever their
+                // content-length says (section 3.3.3 of RFC 7230).
+                Some(StatusCode::NO_CONTENT) | Some(StatusCode::NOT_MODIFIED) => {
+                    stream.content_length = ContentLength::Head;
+                },
+                _ => if let Some(content_length) = content_length {
+                    if !stream.content_length.is_head() {
+                        stream.content_length = ContentLength::Remaining(content_length);
+                    }
+                },
+            }
+
+            if frame.is_end_stream() && stream.ensure_content_length_zero().is_err() {
+                let (pseudo, fields) = frame.into_parts();
+                malformed!("content-length of a message without body", pseudo, fields)
             }
         }
 
@@ -216,7 +437,43 @@ impl Recv {
             };
         }
 
-        let message = counts.peer().convert_poll_message(frame)?;
+        if let Some(reason) = frame.malformed_reason() {
+            let (pseudo, fields) = frame.into_parts();
+            malformed!(reason, pseudo, fields)
+        }
+
+        if frame.pseudo().protocol.is_some() && !self.is_extended_connect_protocol_enabled {
+            // The `:protocol` pseudo header may only be used once
+            // SETTINGS_ENABLE_CONNECT_PROTOCOL has been advertised (RFC 8441,
+            // section 4). Otherwise, the request is malformed.
+            let (pseudo, fields) = frame.into_parts();
+            malformed!(":protocol without extended CONNECT", pseudo, fields)
+        }
+
+        let is_end_stream = frame.is_end_stream();
+
+        // The conversion consumes the frame, keep the head around in case the
+        // request is malformed.
+        let head = if accepts_malformed {
+            Some((frame.pseudo().clone(), frame.fields().clone()))
+        } else {
+            None
+        };
+
+        let message = match counts.peer().convert_poll_message(frame) {
+            Ok(message) => message,
+            Err(reason) => {
+                let (pseudo, fields) = head.unwrap_or_default();
+                malformed!(reason, pseudo, fields)
+            },
+        };
+
+        if let peer::PollMessage::Server(ref request) = message {
+            stream.is_pending_tunnel = request.method() == ::http::Method::CONNECT;
+            stream.is_expecting_continue = self.is_auto_100_continue_enabled &&
+                !is_end_stream &&
+                expects_continue(request.headers());
+        }
 
         // Push the frame onto the stream's recv buffer
         stream
@@ -228,11 +485,33 @@ impl Recv {
         // This is verified in `Streams` before calling this function.
         if counts.peer().is_server() {
             self.pending_accept.push(stream);
+            self.num_pending_accept += 1;
         }
 
         Ok(())
     }
 
+    /// Queues a malformed request, for the application to answer it.
+    ///
+    /// The request body is never seen by the application, so it is discarded
+    /// as it arrives.
+    fn queue_malformed(
+        &mut self,
+        stream: &mut store::Ptr,
+        pseudo: frame::Pseudo,
+        fields: HeaderMap,
+        reason: &'static str,
+    ) {
+        use super::stream::ContentLength;
+
+        stream.is_recv_released = true;
+        stream.content_length = ContentLength::Omitted;
+        stream.malformed_request = Some(::server::MalformedRequest::new(pseudo, fields, reason));
+
+        self.pending_malformed.push(stream);
+        self.num_pending_accept += 1;
+    }
+
     /// Called by the server to get the request
     ///
     /// TODO: Should this fn return `Result`?
@@ -268,20 +547,71 @@ impl Recv {
         }
     }
 
+    /// Called by the client to get the next response pushed on the stream,
+    /// along with the promised request.
+    pub fn poll_pushed(
+        &mut self,
+        stream: &mut store::Ptr,
+    ) -> Poll<Option<(Request<()>, store::Key)>, proto::Error> {
+        use super::peer::PollMessage::*;
+
+        let mut ppp = stream.pending_push_promises.take();
+
+        let pushed = ppp.pop(stream.store_mut()).map(|mut pushed| {
+            match pushed.pending_recv.pop_front(&mut self.buffer) {
+                Some(Event::Headers(Server(request))) => (request, pushed.key()),
+                // The promised request is queued when receiving the
+                // PUSH_PROMISE frame.
+                _ => panic!("promised request not set on pushed stream"),
+            }
+        });
+
+        stream.pending_push_promises = ppp;
+
+        match pushed {
+            Some(pushed) => Ok(Some(pushed).into()),
+            None => {
+                if stream.state.ensure_recv_open()? {
+                    stream.recv_task = Some(task::current());
+                    Ok(Async::NotReady)
+                } else {
+                    // No more PUSH_PROMISE frames can be received.
+                    Ok(None.into())
+                }
+            },
+        }
+    }
+
     /// Transition the stream based on receiving trailers
     pub fn recv_trailers(
         &mut self,
         frame: frame::Headers,
         stream: &mut store::Ptr,
     ) -> Result<(), RecvError> {
+        // > Pseudo-header fields MUST NOT appear in trailers. Endpoints MUST
+        // > treat a request or response that contains undefined or invalid
+        // > pseudo-header fields as malformed (Section 8.1.2.6).
+        if !frame.pseudo().is_empty() {
+            trace!("recv_trailers; pseudo-header fields in trailers; stream={:?}", stream.id);
+            return Err(malformed(stream));
+        }
+
+        if frame.is_malformed() {
+            trace!("recv_trailers; malformed header block; stream={:?}", stream.id);
+            return Err(malformed(stream));
+        }
+
+        // Trailers end the message.
+        if !frame.is_end_stream() {
+            trace!("recv_trailers; trailers without END_STREAM; stream={:?}", stream.id);
+            return Err(malformed(stream));
+        }
+
         // Transition the state
         stream.state.recv_close()?;
 
         if stream.ensure_content_length_zero().is_err() {
-            return Err(RecvError::Stream {
-                id: stream.id,
-                reason: Reason::PROTOCOL_ERROR,
-            });
+            return Err(malformed(stream));
         }
 
         let trailers = frame.into_fields();
@@ -308,15 +638,17 @@ impl Recv {
 
         // Assign capacity to connection
         self.flow.assign_capacity(capacity);
+        self.conn_window_stalled_at = None;
 
-        if self.flow.unclaimed_capacity().is_some() {
+        if self.flow.unclaimed_capacity(self.in_flight_data, self.window_update_threshold).is_some() {
             if let Some(task) = task.take() {
                 task.notify();
             }
         }
     }
 
-    /// Releases capacity back to the connection & stream
+    /// Releases capacity back to the stream, and to the connection unless
+    /// flow control is manual.
     pub fn release_capacity(
         &mut self,
         capacity: WindowSize,
@@ -329,16 +661,65 @@ impl Recv {
             return Err(UserError::ReleaseCapacityTooBig);
         }
 
+        if !self.is_manual_flow_control {
+            self.release_connection_capacity(capacity, task);
+        }
+
+        self.release_stream_capacity(capacity, stream, task);
+        Ok(())
+    }
+
+    /// Releases the connection window capacity of data received on a stream,
+    /// with manual flow control.
+    pub fn release_connection_share(
+        &mut self,
+        capacity: WindowSize,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) -> Result<(), UserError> {
+        trace!("release_connection_share; size={}", capacity);
+
+        // Nothing is left to release when flow control is automatic.
+        if capacity > stream.in_flight_conn_recv_data {
+            return Err(UserError::ReleaseCapacityTooBig);
+        }
+
+        stream.in_flight_conn_recv_data -= capacity;
         self.release_connection_capacity(capacity, task);
+        Ok(())
+    }
 
+    /// Releases received data the user never sees, whatever the flow control
+    /// mode.
+    fn release_unseen_capacity(
+        &mut self,
+        capacity: WindowSize,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) {
+        if self.is_manual_flow_control {
+            stream.in_flight_conn_recv_data -= capacity;
+        }
+
+        self.release_connection_capacity(capacity, task);
+        self.release_stream_capacity(capacity, stream, task);
+    }
+
+    fn release_stream_capacity(
+        &mut self,
+        capacity: WindowSize,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) {
         // Decrement in-flight data
         stream.in_flight_recv_data -= capacity;
 
         // Assign capacity to stream
         stream.recv_flow.assign_capacity(capacity);
+        stream.recv_window_stalled_at = None;
 
-
-        if stream.recv_flow.unclaimed_capacity().is_some() {
+        let threshold = self.window_update_threshold;
+        if stream.recv_flow.unclaimed_capacity(stream.in_flight_recv_data, threshold).is_some() {
             // Queue the stream for sending the WINDOW_UPDATE frame.
             self.pending_window_updates.push(stream);
 
@@ -346,8 +727,53 @@ impl Recv {
                 task.notify();
             }
         }
+    }
 
-        Ok(())
+    /// Releases everything received on a stream whose `ReleaseCapacity`
+    /// handles were all dropped, including data that was never read.
+    ///
+    /// Data received on the stream from then on is released as it arrives.
+    pub fn release_dropped_capacity(&mut self, stream: &mut store::Ptr, task: &mut Option<Task>) {
+        trace!(
+            "release_dropped_capacity; stream={:?}; in_flight={}",
+            stream.id,
+            stream.in_flight_recv_data,
+        );
+
+        stream.is_recv_released = true;
+
+        // Nobody is left to read the buffered frames.
+        while let Some(_) = stream.pending_recv.pop_front(&mut self.buffer) {}
+        stream.buffered_recv_data = 0;
+        stream.recv_stalled_at = None;
+
+        let capacity = stream.in_flight_recv_data;
+
+        // With manual flow control, the connection share of the data may
+        // have been released already, or not.
+        let conn_capacity = if self.is_manual_flow_control {
+            stream.in_flight_conn_recv_data
+        } else {
+            capacity
+        };
+
+        stream.in_flight_conn_recv_data = 0;
+
+        if conn_capacity > 0 {
+            self.release_connection_capacity(conn_capacity, task);
+        }
+
+        if capacity == 0 {
+            return;
+        }
+
+        if stream.state.is_recv_streaming() {
+            self.release_stream_capacity(capacity, stream, task);
+        } else {
+            // The peer can't send more on the stream, only the connection
+            // window matters.
+            stream.in_flight_recv_data = 0;
+        }
     }
 
     /// Set the "target" connection window size.
@@ -378,20 +804,31 @@ impl Recv {
         let current = (self.flow.available() + self.in_flight_data).checked_size();
         if target > current {
             self.flow.assign_capacity(target - current);
+            self.is_connection_window_raised = true;
+            self.conn_window_stalled_at = None;
         } else {
             self.flow.claim_capacity(current - target);
         }
 
-        // If changing the target capacity means we gained a bunch of capacity,
-        // enough that we went over the update threshold, then schedule sending
-        // a connection WINDOW_UPDATE.
-        if self.flow.unclaimed_capacity().is_some() {
+        // If changing the target capacity means we gained capacity, then
+        // schedule sending a connection WINDOW_UPDATE.
+        if self.is_connection_window_raised {
             if let Some(task) = task.take() {
                 task.notify();
             }
         }
     }
 
+    /// Raises the target connection window to `target`, unless the current
+    /// target is larger already.
+    pub fn grow_target_connection_window(&mut self, target: WindowSize, task: &mut Option<Task>) {
+        let current = (self.flow.available() + self.in_flight_data).checked_size();
+
+        if target > current {
+            self.set_target_connection_window(target, task);
+        }
+    }
+
     pub fn body_is_empty(&self, stream: &store::Ptr) -> bool {
         if !stream.state.is_recv_closed() {
             return false;
@@ -419,19 +856,31 @@ impl Recv {
         frame: frame::Data,
         stream: &mut store::Ptr,
     ) -> Result<(), RecvError> {
-        let sz = frame.payload().len();
+        // The padding counts against flow control as well.
+        let padding_len = frame.padding_len();
+        let sz = frame.payload().len() + padding_len;
 
         // This should have been enforced at the codec::FramedRead layer, so
         // this is just a sanity check.
         assert!(sz <= MAX_WINDOW_SIZE as usize);
 
         let sz = sz as WindowSize;
+        let padding_len = padding_len as WindowSize;
 
         let is_ignoring_frame = stream.state.is_local_reset();
 
         if !is_ignoring_frame && !stream.state.is_recv_streaming() {
-            // TODO: There are cases where this can be a stream error of
-            // STREAM_CLOSED instead...
+            if let Err(e) = stream.state.ensure_recv_not_closed(stream.id) {
+                trace!("recv_data; remote side closed; stream={:?}", stream.id);
+
+                // The frame still counts against the connection window. The
+                // caller releases it on stream errors.
+                if let RecvError::Stream { .. } = e {
+                    self.consume_connection_window(sz)?;
+                }
+
+                return Err(e);
+            }
 
             // Receiving a DATA frame when not expecting one is a protocol
             // error.
@@ -482,27 +931,28 @@ impl Recv {
             });
         }
 
+        if stream.is_pending_tunnel {
+            // The tunnel of a CONNECT request only carries data once it is
+            // established by a 2xx response (section 8.3 of RFC 7540).
+            trace!("recv_data; DATA on {:?} before the CONNECT response", stream.id);
+            return Err(RecvError::Stream {
+                id: stream.id,
+                reason: Reason::PROTOCOL_ERROR,
+            });
+        }
+
         // Update stream level flow control
         stream.recv_flow.send_data(sz);
 
-        // Track the data as in-flight
-        stream.in_flight_recv_data += sz;
-
         if stream.dec_content_length(frame.payload().len()).is_err() {
             trace!("content-length overflow");
-            return Err(RecvError::Stream {
-                id: stream.id,
-                reason: Reason::PROTOCOL_ERROR,
-            });
+            return Err(malformed(stream));
         }
 
         if frame.is_end_stream() {
             if stream.ensure_content_length_zero().is_err() {
                 trace!("content-length underflow");
-                return Err(RecvError::Stream {
-                    id: stream.id,
-                    reason: Reason::PROTOCOL_ERROR,
-                });
+                return Err(malformed(stream));
             }
 
             if stream.state.recv_close().is_err() {
@@ -511,7 +961,62 @@ impl Recv {
             }
         }
 
-        let event = Event::Data(frame.into_payload());
+        // Track the data as in-flight. This is done once the frame is known
+        // to be valid, the connection capacity of invalid frames is released
+        // by the caller.
+        stream.in_flight_recv_data += sz;
+
+        if self.is_manual_flow_control {
+            stream.in_flight_conn_recv_data += sz;
+        }
+
+        if stream.is_recv_released {
+            trace!("recv_data; body dropped, releasing {} bytes on {:?}", sz, stream.id);
+
+            // Neither the user nor a `ReleaseCapacity` handle will ever see
+            // this data, so release it right away.
+            self.release_unseen_capacity(sz, stream, &mut None);
+            return Ok(());
+        }
+
+        if padding_len > 0 {
+            // The padding is never seen by the user, so it is released right
+            // away.
+            self.release_unseen_capacity(padding_len, stream, &mut None);
+        }
+
+        if self.window_stall_warning.is_some() {
+            let now = Instant::now();
+
+            let is_stalled = stream.recv_flow.window_size() == 0 && stream.state.is_recv_streaming();
+
+            if is_stalled && stream.recv_window_stalled_at.is_none() {
+                stream.recv_window_stalled_at = Some(now);
+            }
+
+            if self.flow.window_size() == 0 && self.conn_window_stalled_at.is_none() {
+                self.conn_window_stalled_at = Some(now);
+            }
+        }
+
+        stream.buffered_recv_data += (sz - padding_len) as usize;
+
+        if stream.buffered_recv_data > self.max_buffered_recv_data && stream.recv_stalled_at.is_none() {
+            trace!(
+                "recv_data; {:?} buffering {} bytes; holding back window updates",
+                stream.id,
+                stream.buffered_recv_data,
+            );
+
+            stream.recv_stalled_at = Some(timer::now());
+
+            if self.buffered_recv_timeout.is_some() {
+                self.pending_recv_stalled.push(stream);
+            }
+        }
+
+        let info = ::DataFrameInfo::from_internal(&frame);
+        let event = Event::Data(frame.into_payload(), info);
 
         // Push the frame onto the recv buffer
         stream.pending_recv.push_back(&mut self.buffer, event);
@@ -520,6 +1025,16 @@ impl Recv {
         Ok(())
     }
 
+    /// Accounts for a DATA frame that is discarded without being assigned to
+    /// a stream.
+    pub fn ignore_data(&mut self, sz: WindowSize) -> Result<(), RecvError> {
+        self.consume_connection_window(sz)?;
+
+        // The data is released right away.
+        self.release_connection_capacity(sz, &mut None);
+        Ok(())
+    }
+
     pub fn consume_connection_window(&mut self, sz: WindowSize) -> Result<(), RecvError> {
         if self.flow.window_size() < sz {
             return Err(RecvError::Connection(Reason::FLOW_CONTROL_ERROR));
@@ -539,10 +1054,6 @@ impl Recv {
         stream: &mut store::Ptr,
     ) -> Result<(), RecvError> {
 
-        // TODO: Streams in the reserved states do not count towards the concurrency
-        // limit. However, it seems like there should be a cap otherwise this
-        // could grow in memory indefinitely.
-
         stream.state.reserve_remote()?;
 
         if frame.is_over_size() {
@@ -564,6 +1075,39 @@ impl Recv {
             });
         }
 
+        if frame.is_malformed() {
+            trace!("recv_push_promise; malformed header block; stream={:?}", frame.promised_id());
+            return Err(malformed(stream));
+        }
+
+        let promised_id = frame.promised_id();
+        let (pseudo, fields) = frame.into_parts();
+        let request = ::server::Peer::convert_poll_message(
+            frame::Headers::new(promised_id, pseudo, fields))
+            .map_err(|_| malformed(stream))?;
+
+        // > Promised requests MUST be cacheable, MUST be safe, and MUST NOT
+        // > include a request body. Clients that receive a promised request
+        // > that is not cacheable, that is not known to be safe, or that
+        // > indicates the presence of a request body MUST reset the promised
+        // > stream with a stream error of type PROTOCOL_ERROR.
+        let has_body = request.headers()
+            .get(::http::header::CONTENT_LENGTH)
+            .map_or(false, |len| len != "0");
+
+        if !request.method().is_safe() || has_body {
+            trace!("recv_push_promise; invalid promised request; stream={:?}", promised_id);
+            return Err(RecvError::Stream {
+                id: promised_id,
+                reason: Reason::PROTOCOL_ERROR,
+            });
+        }
+
+        // The promised request is handed out before the pushed response.
+        stream
+            .pending_recv
+            .push_back(&mut self.buffer, Event::Headers(peer::PollMessage::Server(request)));
+
         Ok(())
     }
 
@@ -580,6 +1124,37 @@ impl Recv {
         Ok(())
     }
 
+    pub fn is_extensible_priorities_enabled(&self) -> bool {
+        self.is_extensible_priorities_enabled
+    }
+
+    /// Keeps the priority parameters received for a remote initiated stream
+    /// that is not in the store, until the stream is opened.
+    ///
+    /// Parameters for streams that were closed already are ignored.
+    pub fn recv_priority_update(&mut self, id: StreamId, params: frame::PriorityParams) {
+        if self.ensure_not_idle(id).is_ok() {
+            trace!("recv_priority_update; stream closed; ignoring {:?}", id);
+            return;
+        }
+
+        if self.pending_priority_updates.len() >= MAX_PENDING_PRIORITY_UPDATES {
+            // Drop the parameters of streams that were skipped over.
+            if let Ok(next) = self.next_stream_id {
+                self.pending_priority_updates.retain(|&id, _| id >= next);
+            }
+
+            if self.pending_priority_updates.len() >= MAX_PENDING_PRIORITY_UPDATES
+                && !self.pending_priority_updates.contains_key(&id)
+            {
+                trace!("recv_priority_update; too many idle streams; ignoring {:?}", id);
+                return;
+            }
+        }
+
+        self.pending_priority_updates.insert(id, params);
+    }
+
     /// Handle remote sending an explicit RST_STREAM.
     pub fn recv_reset(&mut self, frame: frame::Reset, stream: &mut Stream) {
         // Notify the stream
@@ -617,6 +1192,11 @@ impl Recv {
         self.max_stream_id
     }
 
+    /// Returns true if 100 (Continue) responses are sent automatically.
+    pub fn is_auto_100_continue_enabled(&self) -> bool {
+        self.is_auto_100_continue_enabled
+    }
+
     fn next_stream_id(&self) -> Result<StreamId, RecvError> {
         if let Ok(id) = self.next_stream_id {
             Ok(id)
@@ -701,13 +1281,86 @@ impl Recv {
         }
     }
 
+    /// Returns the next stream that buffered too much received data for
+    /// longer than `buffered_recv_timeout`.
+    ///
+    /// Streams that were read from in the meantime are returned too, the
+    /// caller must check `recv_stalled_at` before acting on them.
+    pub fn pop_stalled_recv<'a>(&mut self, store: &'a mut Store) -> Option<store::Ptr<'a>> {
+        let timeout = self.buffered_recv_timeout?;
+        let now = timer::now();
+
+        self.pending_recv_stalled.pop_if(store, |stream| {
+            match stream.recv_stalled_at {
+                Some(stalled_at) => now >= stalled_at + timeout,
+                None => true,
+            }
+        })
+    }
+
+    /// Returns when `pop_stalled_recv` returns the next stream, if any.
+    pub fn stalled_recv_deadline(&self, store: &mut Store) -> Option<Instant> {
+        let timeout = self.buffered_recv_timeout?;
+        let stream = self.pending_recv_stalled.peek(store)?;
+
+        // A stream that was read from in the meantime is returned right away.
+        Some(match stream.recv_stalled_at {
+            Some(stalled_at) => stalled_at + timeout,
+            None => timer::now(),
+        })
+    }
+
+    /// Reports the streams, and the connection, whose receive window has
+    /// been exhausted for longer than `window_stall_warning`, once per
+    /// stall.
+    ///
+    /// Only the user can release the received data, and the peer cannot send
+    /// anything until it does.
+    pub fn report_window_stalls(&mut self, store: &mut Store) {
+        let warning = match self.window_stall_warning {
+            Some(warning) => warning,
+            None => return,
+        };
+
+        let now = Instant::now();
+
+        if let Some(stalled_at) = self.conn_window_stalled_at {
+            if now - stalled_at > warning {
+                warn!(
+                    "connection receive window exhausted for {:?}; in_flight={}",
+                    now - stalled_at,
+                    self.in_flight_data,
+                );
+                self.conn_window_stalled_at = None;
+            }
+        }
+
+        store.for_each(|mut stream| {
+            if let Some(stalled_at) = stream.recv_window_stalled_at {
+                if now - stalled_at > warning {
+                    warn!(
+                        "receive window of {:?} exhausted for {:?}; in_flight={}",
+                        stream.id,
+                        now - stalled_at,
+                        stream.in_flight_recv_data,
+                    );
+                    stream.recv_window_stalled_at = None;
+                }
+            }
+
+            Ok::<_, ()>(())
+        }).unwrap();
+    }
+
     pub fn clear_queues(&mut self,
                         clear_pending_accept: bool,
                         store: &mut Store,
                         counts: &mut Counts)
     {
         self.clear_stream_window_update_queue(store, counts);
+        self.clear_continue_queue(store, counts);
         self.clear_all_reset_streams(store, counts);
+        self.clear_recv_stalled_queue(store, counts);
 
         if clear_pending_accept {
             self.clear_all_pending_accept(store, counts);
@@ -722,6 +1375,20 @@ impl Recv {
         }
     }
 
+    fn clear_continue_queue(&mut self, store: &mut Store, counts: &mut Counts) {
+        while let Some(stream) = self.pending_continue.pop(store) {
+            counts.transition(stream, |_, stream| {
+                trace!("clear_continue_queue; stream={:?}", stream.id);
+            })
+        }
+    }
+
+    fn clear_recv_stalled_queue(&mut self, store: &mut Store, counts: &mut Counts) {
+        while let Some(stream) = self.pending_recv_stalled.pop(store) {
+            counts.transition_after(stream, false);
+        }
+    }
+
     /// Called on EOF
     fn clear_all_reset_streams(&mut self, store: &mut Store, counts: &mut Counts) {
         while let Some(stream) = self.pending_reset_expired.pop(store) {
@@ -731,6 +1398,7 @@ impl Recv {
 
     fn clear_all_pending_accept(&mut self, store: &mut Store, counts: &mut Counts) {
         while let Some(stream) = self.pending_accept.pop(store) {
+            self.num_pending_accept -= 1;
             counts.transition_after(stream, false);
         }
     }
@@ -745,6 +1413,9 @@ impl Recv {
         T: AsyncWrite,
         B: Buf,
     {
+        // Send any pending 100 (Continue) responses
+        try_ready!(self.send_pending_continue(store, counts, dst));
+
         // Send any pending connection level window updates
         try_ready!(self.send_connection_window_update(dst));
 
@@ -754,6 +1425,47 @@ impl Recv {
         Ok(().into())
     }
 
+    /// Sends the 100 (Continue) responses of the requests whose body was
+    /// polled.
+    fn send_pending_continue<T, B>(
+        &mut self,
+        store: &mut Store,
+        counts: &mut Counts,
+        dst: &mut Codec<T, Prioritized<B>>,
+    ) -> Poll<(), io::Error>
+    where
+        T: AsyncWrite,
+        B: Buf,
+    {
+        loop {
+            try_ready!(dst.poll_ready());
+
+            let stream = match self.pending_continue.pop(store) {
+                Some(stream) => stream,
+                None => return Ok(().into()),
+            };
+
+            counts.transition(stream, |_, stream| {
+                // The final response was sent in the meantime, or the stream
+                // was reset.
+                if !stream.state.is_send_headers() {
+                    trace!("pending_continue; not needed anymore; stream={:?}", stream.id);
+                    return;
+                }
+
+                trace!("pending_continue; sending 100 (Continue); stream={:?}", stream.id);
+
+                let frame = frame::Headers::new(
+                    stream.id,
+                    frame::Pseudo::response(::http::StatusCode::CONTINUE),
+                    HeaderMap::new(),
+                );
+
+                dst.buffer(frame.into()).expect("invalid 100 (Continue) response");
+            })
+        }
+    }
+
     /// Send connection level window update
     fn send_connection_window_update<T, B>(
         &mut self,
@@ -763,7 +1475,14 @@ impl Recv {
         T: AsyncWrite,
         B: Buf,
     {
-        if let Some(incr) = self.flow.unclaimed_capacity() {
+        // A raised target is announced in full, regardless of the threshold.
+        let threshold = if self.is_connection_window_raised {
+            0
+        } else {
+            self.window_update_threshold
+        };
+
+        if let Some(incr) = self.flow.unclaimed_capacity(self.in_flight_data, threshold) {
             let frame = frame::WindowUpdate::new(StreamId::zero(), incr);
 
             // Ensure the codec has capacity
@@ -781,6 +1500,8 @@ impl Recv {
                 .expect("unexpected flow control state");
         }
 
+        self.is_connection_window_raised = false;
+
         Ok(().into())
     }
 
@@ -795,6 +1516,8 @@ impl Recv {
         T: AsyncWrite,
         B: Buf,
     {
+        let threshold = self.window_update_threshold;
+
         loop {
             // Ensure the codec has capacity
             try_ready!(dst.poll_ready());
@@ -819,8 +1542,20 @@ impl Recv {
                     return;
                 }
 
+                if stream.recv_stalled_at.is_some() {
+                    // The user is not reading the data, the stream is queued
+                    // again once the buffered data is read.
+                    trace!("holding back window update; {:?} buffering {} bytes",
+                           stream.id, stream.buffered_recv_data);
+                    return;
+                }
+
                 // TODO: de-dup
-                if let Some(incr) = stream.recv_flow.unclaimed_capacity() {
+                let incr = stream
+                    .recv_flow
+                    .unclaimed_capacity(stream.in_flight_recv_data, threshold);
+
+                if let Some(incr) = incr {
                     // Create the WINDOW_UPDATE frame
                     let frame = frame::WindowUpdate::new(stream.id, incr);
 
@@ -841,13 +1576,64 @@ impl Recv {
     }
 
     pub fn next_incoming(&mut self, store: &mut Store) -> Option<store::Key> {
-        self.pending_accept.pop(store).map(|ptr| ptr.key())
+        self.pending_accept.pop(store).map(|ptr| {
+            self.num_pending_accept -= 1;
+            ptr.key()
+        })
+    }
+
+    pub fn next_malformed(&mut self, store: &mut Store) -> Option<store::Key> {
+        self.pending_malformed.pop(store).map(|ptr| {
+            self.num_pending_accept -= 1;
+            ptr.key()
+        })
+    }
+
+    /// Returns the head of the malformed request received on `stream`.
+    pub fn take_malformed_request(&mut self, stream: &mut store::Ptr) -> ::server::MalformedRequest {
+        stream.malformed_request.take().expect("malformed request already taken")
     }
 
-    pub fn poll_data(&mut self, stream: &mut Stream) -> Poll<Option<Bytes>, proto::Error> {
+    pub fn poll_data(
+        &mut self,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) -> Poll<Option<(Bytes, ::DataFrameInfo)>, proto::Error> {
+        // The client waits for the 100 (Continue) response before sending
+        // the body, which is only sent once the application asks for it.
+        if stream.is_expecting_continue {
+            stream.is_expecting_continue = false;
+            self.pending_continue.push(stream);
+
+            if let Some(task) = task.take() {
+                task.notify();
+            }
+        }
+
         // TODO: Return error when the stream is reset
         match stream.pending_recv.pop_front(&mut self.buffer) {
-            Some(Event::Data(payload)) => Ok(Some(payload).into()),
+            Some(Event::Data(payload, info)) => {
+                stream.buffered_recv_data -= payload.len();
+
+                if stream.recv_stalled_at.is_some() &&
+                    stream.buffered_recv_data <= self.max_buffered_recv_data
+                {
+                    trace!("poll_data; {:?} no longer stalled", stream.id);
+                    stream.recv_stalled_at = None;
+
+                    // Send the window updates that were held back.
+                    let threshold = self.window_update_threshold;
+                    if stream.recv_flow.unclaimed_capacity(stream.in_flight_recv_data, threshold).is_some() {
+                        self.pending_window_updates.push(stream);
+
+                        if let Some(task) = task.take() {
+                            task.notify();
+                        }
+                    }
+                }
+
+                Ok(Some((payload, info)).into())
+            },
             Some(event) => {
                 // Frame is trailer
                 stream.pending_recv.push_front(&mut self.buffer, event);
@@ -931,8 +1717,68 @@ impl<T> From<RecvError> for RecvHeaderBlockError<T> {
 
 // ===== util =====
 
+/// Returns the stream error for a malformed request or response received on
+/// `stream` (section 8.1.2.6 of RFC 7540).
+fn malformed(stream: &mut Stream) -> RecvError {
+    stream.is_malformed = true;
+
+    RecvError::Stream {
+        id: stream.id,
+        reason: Reason::PROTOCOL_ERROR,
+    }
+}
+
+/// Returns true if the request carries `expect: 100-continue` (section 5.1.1
+/// of RFC 7231).
+fn expects_continue(fields: &HeaderMap) -> bool {
+    fields.get(::http::header::EXPECT)
+        .map_or(false, |value| value.as_bytes().eq_ignore_ascii_case(b"100-continue"))
+}
+
+/// Parses the `content-length` header fields of a header block.
+///
+/// The field may be repeated, or hold a comma separated list, as long as all
+/// the values are identical (section 3.3.2 of RFC 7230).
+fn parse_content_length(fields: &HeaderMap) -> Result<Option<u64>, ()> {
+    use http::header;
+
+    let mut content_length = None;
+
+    for value in fields.get_all(header::CONTENT_LENGTH) {
+        for v in value.as_bytes().split(|&b| b == b',') {
+            let v = parse_u64(trim_ows(v))?;
+
+            if content_length.map_or(false, |len| len != v) {
+                return Err(());
+            }
+
+            content_length = Some(v);
+        }
+    }
+
+    Ok(content_length)
+}
+
+fn trim_ows(mut src: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = src.split_first() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        src = rest;
+    }
+
+    while let Some((&b, rest)) = src.split_last() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        src = rest;
+    }
+
+    src
+}
+
 fn parse_u64(src: &[u8]) -> Result<u64, ()> {
-    if src.len() > 19 {
+    if src.is_empty() || src.len() > 19 {
         // At danger for overflow...
         return Err(());
     }
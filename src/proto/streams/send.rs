@@ -1,17 +1,14 @@
 use codec::{RecvError, UserError};
 use frame::{self, Reason};
 use super::{
-    store, Buffer, Codec, Config, Counts, Frame, Prioritize,
-    Prioritized, Store, Stream, StreamId, StreamIdOverflow, WindowSize,
+    store, Buffer, Config, Counts, DependencyTree, Frame, Prioritize,
+    Prioritized, Span, Store, Stream, StreamId, StreamIdOverflow, WindowSize,
 };
 
 use bytes::Buf;
 use http;
 use futures::{Async, Poll};
 use futures::task::Task;
-use tokio_io::AsyncWrite;
-
-use std::io;
 
 /// Manages state transitions related to outbound frames.
 #[derive(Debug)]
@@ -24,6 +21,15 @@ pub(super) struct Send {
 
     /// Prioritization layer
     prioritize: Prioritize,
+
+    /// If the remote peer advertised support for the extended CONNECT method
+    is_extended_connect_protocol_enabled: bool,
+
+    /// If the remote peer accepts push promises
+    is_push_enabled: bool,
+
+    /// Maximum size of the header blocks sent
+    max_header_block_size: Option<usize>,
 }
 
 /// A value to detect which public API has called `poll_reset`.
@@ -40,6 +46,9 @@ impl Send {
             init_window_sz: config.remote_init_window_sz,
             next_stream_id: Ok(config.local_next_stream_id),
             prioritize: Prioritize::new(config),
+            is_extended_connect_protocol_enabled: false,
+            is_push_enabled: true,
+            max_header_block_size: config.local_max_send_header_block_size,
         }
     }
 
@@ -48,9 +57,40 @@ impl Send {
         self.init_window_sz
     }
 
+    /// Returns the connection level window data may still be sent in
+    pub fn connection_window(&self) -> WindowSize {
+        self.prioritize.connection_window()
+    }
+
+    /// Returns the amount of data buffered by all streams and not yet sent
+    pub fn buffered_send_data(&self) -> usize {
+        self.prioritize.buffered_send_data()
+    }
+
+    /// Returns the priorities of the streams, as sent by the remote peer
+    pub fn dependencies(&self) -> &DependencyTree {
+        self.prioritize.dependencies()
+    }
+
+    pub fn dependencies_mut(&mut self) -> &mut DependencyTree {
+        self.prioritize.dependencies_mut()
+    }
+
+    /// Returns true if the remote peer supports the extended CONNECT method
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.is_extended_connect_protocol_enabled
+    }
+
     pub fn open(&mut self) -> Result<StreamId, UserError> {
         let stream_id = self.ensure_next_stream_id()?;
         self.next_stream_id = stream_id.next_id();
+
+        if self.next_stream_id.is_err() {
+            // Waiters will now fail to open a stream instead of waiting for a
+            // free slot.
+            self.prioritize.notify_open_ready();
+        }
+
         Ok(stream_id)
     }
 
@@ -68,30 +108,19 @@ impl Send {
             self.init_window_sz
         );
 
-        // 8.1.2.2. Connection-Specific Header Fields
-        if frame.fields().contains_key(http::header::CONNECTION)
-            || frame.fields().contains_key(http::header::TRANSFER_ENCODING)
-            || frame.fields().contains_key(http::header::UPGRADE)
-            || frame.fields().contains_key("keep-alive")
-            || frame.fields().contains_key("proxy-connection")
-        {
-            debug!("illegal connection-specific headers found");
-            return Err(UserError::MalformedHeaders);
-        } else if let Some(te) = frame.fields().get(http::header::TE) {
-            if te != "trailers" {
-                debug!("illegal connection-specific headers found");
-                return Err(UserError::MalformedHeaders);
-
-            }
-        }
+        check_headers(frame.fields())?;
+        self.check_header_block_size(frame.header_list_size())?;
 
         let end_stream = frame.is_end_stream();
 
         // Update the state
         stream.state.send_open(end_stream)?;
 
-        if counts.peer().is_local_init(frame.stream_id()) {
-            if counts.can_inc_num_send_streams() {
+        // A pushed stream is opened once its PUSH_PROMISE frame is written.
+        // Streams are opened in order, so that a stream does not implicitly
+        // close the queued streams with lower IDs.
+        if counts.peer().is_local_init(frame.stream_id()) && !stream.is_pending_push {
+            if self.prioritize.is_open_ready(counts) {
                 counts.inc_num_send_streams(stream);
             } else {
                 self.prioritize.queue_open(stream);
@@ -104,6 +133,67 @@ impl Send {
         Ok(())
     }
 
+    /// Queues the HEADERS frame of an informational (1xx) response.
+    ///
+    /// The stream state is left untouched, as the final response is still to
+    /// be sent.
+    pub fn send_interim_headers<B>(
+        &mut self,
+        frame: frame::Headers,
+        buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) -> Result<(), UserError> {
+        trace!("send_interim_headers; frame={:?}", frame);
+
+        match frame.pseudo().status {
+            Some(status) if status.is_informational() &&
+                status != ::http::StatusCode::SWITCHING_PROTOCOLS => {},
+            _ => return Err(UserError::InvalidInformationalStatus),
+        }
+
+        check_headers(frame.fields())?;
+        self.check_header_block_size(frame.header_list_size())?;
+
+        if !stream.state.is_send_headers() {
+            return Err(UserError::UnexpectedFrameType);
+        }
+
+        self.prioritize.queue_frame(frame.into(), buffer, stream, task);
+
+        Ok(())
+    }
+
+    /// Queues a PUSH_PROMISE frame on the associated stream.
+    ///
+    /// The promised stream must already be reserved.
+    pub fn send_push_promise<B>(
+        &mut self,
+        frame: frame::PushPromise,
+        buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) -> Result<(), UserError> {
+        trace!("send_push_promise; frame={:?}", frame);
+
+        if !self.is_push_enabled {
+            return Err(UserError::PeerDisabledServerPush);
+        }
+
+        check_headers(frame.fields())?;
+        self.check_header_block_size(frame.header_list_size())?;
+
+        // > PUSH_PROMISE frames MUST only be sent on a peer-initiated stream
+        // > that is in either the "open" or "half-closed (remote)" state.
+        if stream.state.is_send_closed() || stream.state.is_idle() {
+            return Err(UserError::InactiveStreamId);
+        }
+
+        self.prioritize.queue_frame(frame.into(), buffer, stream, task);
+
+        Ok(())
+    }
+
     /// Send an explicit RST_STREAM frame
     ///
     /// # Arguments
@@ -185,6 +275,55 @@ impl Send {
         self.prioritize.schedule_send(stream, task);
     }
 
+    pub fn send_priority<B>(
+        &mut self,
+        frame: frame::Priority,
+        buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) {
+        trace!("send_priority; frame={:?}", frame);
+
+        match stream.pending_send.pop_front(buffer) {
+            Some(Frame::Headers(mut headers)) if headers.pseudo().method.is_some() => {
+                // The request has not been sent yet, so the priority is sent
+                // along with it instead.
+                headers.set_stream_dependency(*frame.dependency());
+                stream.pending_send.push_front(buffer, headers.into());
+            },
+            front => {
+                if let Some(front) = front {
+                    stream.pending_send.push_front(buffer, front);
+                }
+
+                // The PRIORITY frame goes ahead of any queued DATA frame, so
+                // that a stream waiting for capacity can be reprioritized.
+                stream.pending_send.push_front(buffer, frame.into());
+                self.prioritize.schedule_send(stream, task);
+            },
+        }
+    }
+
+    pub fn send_alt_svc<B>(
+        &mut self,
+        frame: frame::AltSvc,
+        buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        task: &mut Option<Task>,
+    ) -> Result<(), UserError> {
+        trace!("send_alt_svc; frame={:?}", frame);
+
+        // > An endpoint MUST NOT send frames other than PRIORITY on a closed
+        // > stream.
+        if stream.state.is_closed() {
+            return Err(UserError::InactiveStreamId);
+        }
+
+        self.prioritize.queue_frame(frame.into(), buffer, stream, task);
+
+        Ok(())
+    }
+
     pub fn send_data<B>(
         &mut self,
         frame: frame::Data<B>,
@@ -208,9 +347,15 @@ impl Send {
     ) -> Result<(), UserError> {
         // TODO: Should this logic be moved into state.rs?
         if !stream.state.is_send_streaming() {
+            if stream.state.is_send_ended() {
+                return Err(UserError::SendAfterEndStream);
+            }
+
             return Err(UserError::UnexpectedFrameType);
         }
 
+        self.check_header_block_size(frame.header_list_size())?;
+
         stream.state.send_close();
 
         trace!("send_trailers -- queuing; frame={:?}", frame);
@@ -222,17 +367,17 @@ impl Send {
         Ok(())
     }
 
-    pub fn poll_complete<T, B>(
+    pub fn pop_frame_to_write<B>(
         &mut self,
+        written: Option<frame::Data<Prioritized<B>>>,
         buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
+        max_frame_len: usize,
         counts: &mut Counts,
-        dst: &mut Codec<T, Prioritized<B>>,
-    ) -> Poll<(), io::Error>
-    where T: AsyncWrite,
-          B: Buf,
+    ) -> Option<(Frame<Prioritized<B>>, Span)>
+    where B: Buf,
     {
-        self.prioritize.poll_complete(buffer, store, counts, dst)
+        self.prioritize.pop_frame_to_write(written, buffer, store, max_frame_len, counts)
     }
 
     /// Request capacity to send data
@@ -340,14 +485,20 @@ impl Send {
         self.prioritize.reclaim_all_capacity(stream, counts);
     }
 
-    pub fn apply_remote_settings<B>(
+    pub fn apply_remote_settings(
         &mut self,
         settings: &frame::Settings,
-        buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
         counts: &mut Counts,
-        task: &mut Option<Task>,
     ) -> Result<(), RecvError> {
+        if let Some(val) = settings.enable_connect_protocol() {
+            self.is_extended_connect_protocol_enabled = val != 0;
+        }
+
+        if let Some(val) = settings.enable_push() {
+            self.is_push_enabled = val != 0;
+        }
+
         // Applies an update to the remote endpoint's initial window size.
         //
         // Per RFC 7540 §6.9.2:
@@ -418,8 +569,13 @@ impl Send {
             } else if val > old_val {
                 let inc = val - old_val;
 
+                // An endpoint MUST treat a change to SETTINGS_INITIAL_WINDOW_SIZE
+                // that causes any flow-control window to exceed the maximum
+                // size as a connection error of type FLOW_CONTROL_ERROR, so the
+                // stream is not reset on its own.
                 store.for_each(|mut stream| {
-                    self.recv_stream_window_update(inc, buffer, &mut stream, counts, task)
+                    self.prioritize
+                        .recv_stream_window_update(inc, &mut stream)
                         .map_err(RecvError::Connection)
                 })?;
             }
@@ -428,6 +584,24 @@ impl Send {
         Ok(())
     }
 
+    /// Returns `Ready` once a stream can be opened without being queued.
+    pub fn poll_open_ready(&mut self, counts: &Counts) -> Async<()> {
+        if self.prioritize.is_open_ready(counts) {
+            return Async::Ready(());
+        }
+
+        self.prioritize.wait_open_ready();
+        Async::NotReady
+    }
+
+    pub fn can_open_or_queue(&self, counts: &Counts) -> bool {
+        self.prioritize.can_open_or_queue(counts)
+    }
+
+    pub fn notify_open_ready(&mut self) {
+        self.prioritize.notify_open_ready();
+    }
+
     pub fn clear_queues(&mut self, store: &mut Store, counts: &mut Counts) {
         self.prioritize.clear_pending_capacity(store, counts);
         self.prioritize.clear_pending_send(store, counts);
@@ -445,7 +619,49 @@ impl Send {
         Ok(())
     }
 
+    /// Returns true once every stream ID available to this peer was used.
+    pub fn is_stream_id_exhausted(&self) -> bool {
+        self.next_stream_id.is_err()
+    }
+
     pub fn ensure_next_stream_id(&self) -> Result<StreamId, UserError> {
         self.next_stream_id.map_err(|_| UserError::OverflowedStreamId)
     }
+
+    /// Fails fast with a header list of `size` that could not be sent without
+    /// going over the maximum header block size.
+    ///
+    /// The header list size is measured as for
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`, which is never smaller than the HPACK
+    /// encoded block.
+    fn check_header_block_size(&self, size: usize) -> Result<(), UserError> {
+        match self.max_header_block_size {
+            Some(max) if size > max => {
+                debug!("header block too big; size={}; max={}", size, max);
+                Err(UserError::HeaderBlockTooBig)
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks for header fields that are not allowed in HTTP/2.0 messages.
+fn check_headers(fields: &http::HeaderMap) -> Result<(), UserError> {
+    // 8.1.2.2. Connection-Specific Header Fields
+    if fields.contains_key(http::header::CONNECTION)
+        || fields.contains_key(http::header::TRANSFER_ENCODING)
+        || fields.contains_key(http::header::UPGRADE)
+        || fields.contains_key("keep-alive")
+        || fields.contains_key("proxy-connection")
+    {
+        debug!("illegal connection-specific headers found");
+        return Err(UserError::MalformedHeaders);
+    } else if let Some(te) = fields.get(http::header::TE) {
+        if te != "trailers" {
+            debug!("illegal connection-specific headers found");
+            return Err(UserError::MalformedHeaders);
+        }
+    }
+
+    Ok(())
 }
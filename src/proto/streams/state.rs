@@ -1,9 +1,12 @@
 use std::io;
+use std::sync::Arc;
+
+use bytes::Bytes;
 
 use codec::{RecvError, UserError};
 use codec::UserError::*;
-use frame::Reason;
-use proto::{self, PollReset};
+use frame::{Reason, StreamId};
+use proto::{self, Initiator, PollReset};
 
 use self::Inner::*;
 use self::Peer::*;
@@ -51,13 +54,15 @@ use self::Peer::*;
 #[derive(Debug, Clone)]
 pub struct State {
     inner: Inner,
+    /// Set when a RST_STREAM is received after the remote side ended the
+    /// stream, in which case the data received is still complete.
+    is_recv_ended: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum Inner {
     Idle,
-    // TODO: these states shouldn't count against concurrency limits:
-    //ReservedLocal,
+    ReservedLocal,
     ReservedRemote,
     Open { local: Peer, remote: Peer },
     HalfClosedLocal(Peer), // TODO: explicitly name this value
@@ -71,12 +76,29 @@ enum Peer {
     Streaming,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 enum Cause {
     EndStream,
+
+    /// The peer reset the stream with this error code.
     Proto(Reason),
+
     LocallyReset(Reason),
-    Io,
+
+    /// The connection failed with this I/O error, or was closed.
+    Io(Arc<io::Error>),
+
+    /// The connection was closed with a GOAWAY frame carrying this error
+    /// code, sent by either endpoint.
+    GoAway(Reason, Initiator),
+
+    /// The stream was above the last stream ID of a GOAWAY frame received
+    /// from the peer, which means the peer did not process it.
+    Refused(Reason),
+
+    /// The stream was reset with `PROTOCOL_ERROR` after receiving a
+    /// malformed message.
+    Malformed,
 
     /// This indicates to the connection that a reset frame must be sent out
     /// once the send queue has been flushed.
@@ -113,7 +135,7 @@ impl State {
                     remote,
                 }
             },
-            HalfClosedRemote(AwaitingHeaders) => if eos {
+            HalfClosedRemote(AwaitingHeaders) | ReservedLocal => if eos {
                 Closed(Cause::EndStream)
             } else {
                 HalfClosedRemote(local)
@@ -150,13 +172,11 @@ impl State {
             ReservedRemote => {
                 initial = true;
 
+                // Nothing is sent on a pushed stream.
                 if eos {
                     Closed(Cause::EndStream)
                 } else {
-                    Open {
-                        local: AwaitingHeaders,
-                        remote,
-                    }
+                    HalfClosedLocal(remote)
                 }
             },
             Open {
@@ -184,6 +204,17 @@ impl State {
         return Ok(initial);
     }
 
+    /// Transition from Idle -> ReservedLocal
+    pub fn reserve_local(&mut self) -> Result<(), UserError> {
+        match self.inner {
+            Idle => {
+                self.inner = ReservedLocal;
+                Ok(())
+            },
+            _ => Err(UnexpectedFrameType),
+        }
+    }
+
     /// Transition from Idle -> ReservedRemote
     pub fn reserve_remote(&mut self) -> Result<(), RecvError> {
         match self.inner {
@@ -215,6 +246,26 @@ impl State {
         }
     }
 
+    /// Returns the error for a DATA or HEADERS frame received once the remote
+    /// side of the stream is closed (section 5.1 of RFC 7540).
+    ///
+    /// Frames received on streams that were reset locally are ignored by the
+    /// caller instead.
+    pub fn ensure_recv_not_closed(&self, id: StreamId) -> Result<(), RecvError> {
+        match self.inner {
+            // The peer may not send anything on a promised stream.
+            ReservedLocal => Err(RecvError::Connection(Reason::PROTOCOL_ERROR)),
+            // Frames after END_STREAM are a connection error, frames after
+            // a RST_STREAM or on a half-closed stream a stream error.
+            Closed(Cause::EndStream) => Err(RecvError::Connection(Reason::STREAM_CLOSED)),
+            HalfClosedRemote(..) | Closed(..) => Err(RecvError::Stream {
+                id,
+                reason: Reason::STREAM_CLOSED,
+            }),
+            _ => Ok(()),
+        }
+    }
+
     /// The remote explicitly sent a RST_STREAM.
     ///
     /// # Arguments
@@ -239,11 +290,14 @@ impl State {
             // In either of these cases, we want to overwrite the stream's
             // previous state with the received RST_STREAM, so that the queue
             // will be cleared by `Prioritize::pop_frame`.
-            state => {
+            ref state => {
                 trace!(
                     "recv_reset; reason={:?}; state={:?}; queued={:?}",
                     reason, state, queued
                 );
+                if let HalfClosedRemote(..) = *state {
+                    self.is_recv_ended = true;
+                }
                 self.inner = Closed(Cause::Proto(reason));
             },
 
@@ -259,8 +313,12 @@ impl State {
             _ => {
                 trace!("recv_err; err={:?}", err);
                 self.inner = Closed(match *err {
-                    Proto(reason) => Cause::LocallyReset(reason),
-                    Io(..) => Cause::Io,
+                    Reset(reason, Initiator::Local) => Cause::LocallyReset(reason),
+                    Reset(reason, Initiator::Remote) => Cause::Proto(reason),
+                    GoAway(_, reason, initiator) => Cause::GoAway(reason, initiator),
+                    Refused(reason) => Cause::Refused(reason),
+                    Malformed => Cause::Malformed,
+                    Io(ref e) => Cause::Io(e.clone()),
                 });
             },
         }
@@ -269,9 +327,9 @@ impl State {
     pub fn recv_eof(&mut self) {
         match self.inner {
             Closed(..) => {},
-            s => {
+            ref s => {
                 trace!("recv_eof; state={:?}", s);
-                self.inner = Closed(Cause::Io);
+                self.inner = Closed(Cause::Io(Arc::new(io::ErrorKind::BrokenPipe.into())));
             }
         }
     }
@@ -299,6 +357,14 @@ impl State {
         self.inner = Closed(Cause::LocallyReset(reason));
     }
 
+    /// Records that the stream was just reset with `PROTOCOL_ERROR` because
+    /// the peer sent a malformed message.
+    pub fn set_malformed(&mut self) {
+        if let Closed(Cause::LocallyReset(Reason::PROTOCOL_ERROR)) = self.inner {
+            self.inner = Closed(Cause::Malformed);
+        }
+    }
+
     /// Set the stream state to a scheduled reset.
     pub fn set_scheduled_reset(&mut self, reason: Reason) {
         debug_assert!(!self.is_closed());
@@ -322,6 +388,7 @@ impl State {
     pub fn is_local_reset(&self) -> bool {
         match self.inner {
             Closed(Cause::LocallyReset(_)) => true,
+            Closed(Cause::Malformed) => true,
             Closed(Cause::Scheduled(..)) => true,
             _ => false,
         }
@@ -347,6 +414,18 @@ impl State {
         }
     }
 
+    /// Returns true when no headers were sent on the stream yet
+    pub fn is_send_headers(&self) -> bool {
+        match self.inner {
+            Open {
+                local: AwaitingHeaders,
+                ..
+            } => true,
+            HalfClosedRemote(AwaitingHeaders) => true,
+            _ => false,
+        }
+    }
+
     /// Returns true when the stream is in a state to receive headers
     pub fn is_recv_headers(&self) -> bool {
         match self.inner {
@@ -372,6 +451,13 @@ impl State {
         }
     }
 
+    pub fn is_reserved_remote(&self) -> bool {
+        match self.inner {
+            ReservedRemote => true,
+            _ => false,
+        }
+    }
+
     pub fn is_closed(&self) -> bool {
         match self.inner {
             Closed(_) => true,
@@ -381,7 +467,7 @@ impl State {
 
     pub fn is_recv_closed(&self) -> bool {
         match self.inner {
-            Closed(..) | HalfClosedRemote(..) => true,
+            Closed(..) | HalfClosedRemote(..) | ReservedLocal => true,
             _ => false,
         }
     }
@@ -393,6 +479,14 @@ impl State {
         }
     }
 
+    /// Returns true if the send half was closed with `END_STREAM`.
+    pub fn is_send_ended(&self) -> bool {
+        match self.inner {
+            Closed(Cause::EndStream) | HalfClosedLocal(..) => true,
+            _ => false,
+        }
+    }
+
     pub fn is_idle(&self) -> bool {
         match self.inner {
             Idle => true,
@@ -403,12 +497,20 @@ impl State {
     pub fn ensure_recv_open(&self) -> Result<bool, proto::Error> {
         // TODO: Is this correct?
         match self.inner {
-            Closed(Cause::Proto(reason)) |
+            // The body was received in full before the reset.
+            Closed(Cause::Proto(_)) if self.is_recv_ended => Ok(false),
+            Closed(Cause::Proto(reason)) => Err(proto::Error::Reset(reason, Initiator::Remote)),
             Closed(Cause::LocallyReset(reason)) |
-            Closed(Cause::Scheduled(reason)) => Err(proto::Error::Proto(reason)),
-            Closed(Cause::Io) => Err(proto::Error::Io(io::ErrorKind::BrokenPipe.into())),
+            Closed(Cause::Scheduled(reason)) => Err(proto::Error::Reset(reason, Initiator::Local)),
+            Closed(Cause::GoAway(reason, initiator)) => {
+                Err(proto::Error::GoAway(Bytes::new(), reason, initiator))
+            },
+            Closed(Cause::Refused(reason)) => Err(proto::Error::Refused(reason)),
+            Closed(Cause::Malformed) => Err(proto::Error::Malformed),
+            Closed(Cause::Io(ref e)) => Err(proto::Error::Io(e.clone())),
             Closed(Cause::EndStream) |
-            HalfClosedRemote(..) => Ok(false),
+            HalfClosedRemote(..) |
+            ReservedLocal => Ok(false),
             _ => Ok(true),
         }
     }
@@ -418,8 +520,11 @@ impl State {
         match self.inner {
             Closed(Cause::Proto(reason)) |
             Closed(Cause::LocallyReset(reason)) |
-            Closed(Cause::Scheduled(reason)) => Ok(Some(reason)),
-            Closed(Cause::Io) => Err(proto::Error::Io(io::ErrorKind::BrokenPipe.into()).into()),
+            Closed(Cause::Scheduled(reason)) |
+            Closed(Cause::GoAway(reason, _)) |
+            Closed(Cause::Refused(reason)) => Ok(Some(reason)),
+            Closed(Cause::Malformed) => Ok(Some(Reason::PROTOCOL_ERROR)),
+            Closed(Cause::Io(ref e)) => Err(proto::Error::Io(e.clone()).into()),
             Open { local: Streaming, .. } |
             HalfClosedRemote(Streaming) => match mode {
                 PollReset::AwaitingHeaders => {
@@ -436,6 +541,7 @@ impl Default for State {
     fn default() -> State {
         State {
             inner: Inner::Idle,
+            is_recv_ended: false,
         }
     }
 }
@@ -9,6 +9,11 @@ use std::marker::PhantomData;
 use std::ops;
 
 /// Storage for streams
+///
+/// Streams live in a slab and are referenced by `Key`, which the queues link
+/// through without any lookup. `ids` is only needed to find the stream of a
+/// frame received from the peer. A stream's slot is freed once the stream is
+/// closed and no longer referenced.
 #[derive(Debug)]
 pub(super) struct Store {
     slab: slab::Slab<(StoreId, Stream)>,
@@ -273,6 +278,48 @@ where
         true
     }
 
+    /// Queue the stream ahead of the other streams.
+    ///
+    /// If the stream is already contained by the list, return `false`.
+    pub fn push_front(&mut self, stream: &mut store::Ptr) -> bool {
+        trace!("Queue::push_front");
+
+        if N::is_queued(stream) {
+            trace!(" -> already queued");
+            return false;
+        }
+
+        N::set_queued(stream, true);
+
+        // The next pointer shouldn't be set
+        debug_assert!(N::next(stream).is_none());
+
+        match self.indices {
+            Some(ref mut idxs) => {
+                trace!(" -> existing entries");
+
+                // Point `stream` to the current head node
+                N::set_next(stream, Some(idxs.head));
+
+                // Update the head pointer
+                idxs.head = stream.key();
+            },
+            None => {
+                trace!(" -> first entry");
+                self.indices = Some(store::Indices {
+                    head: stream.key(),
+                    tail: stream.key(),
+                });
+            },
+        }
+
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_none()
+    }
+
     pub fn pop<'a, R>(&mut self, store: &'a mut R) -> Option<store::Ptr<'a>>
     where
         R: Resolve,
@@ -297,6 +344,14 @@ where
         None
     }
 
+    /// Returns the stream at the head of the queue, without popping it.
+    pub fn peek<'a, R>(&self, store: &'a mut R) -> Option<store::Ptr<'a>>
+    where
+        R: Resolve,
+    {
+        self.indices.map(move |idxs| store.resolve(idxs.head))
+    }
+
     pub fn pop_if<'a, R, F>(&mut self, store: &'a mut R, f: F) -> Option<store::Ptr<'a>>
     where
         R: Resolve,
@@ -403,3 +458,122 @@ impl<'a> VacantEntry<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::stream::{NextSend, NextWindowUpdate};
+
+    fn store(ids: &[u32]) -> (Store, Vec<Key>) {
+        let mut store = Store::new();
+
+        let keys = ids.iter()
+            .map(|&id| store.insert(id.into(), Stream::new(id.into(), 0, 0)).key())
+            .collect();
+
+        (store, keys)
+    }
+
+    fn drain<N: Next>(queue: &mut Queue<N>, store: &mut Store) -> Vec<StreamId> {
+        let mut ids = vec![];
+
+        while let Some(stream) = queue.pop(store) {
+            assert!(!N::is_queued(&stream));
+            assert!(N::next(&stream).is_none());
+            ids.push(stream.id);
+        }
+
+        ids
+    }
+
+    fn clear(mut store: Store, keys: Vec<Key>) {
+        for key in keys {
+            let mut stream = store.resolve(key);
+            stream.unlink();
+            stream.remove();
+        }
+    }
+
+    fn ids(ids: &[u32]) -> Vec<StreamId> {
+        ids.iter().map(|&id| id.into()).collect()
+    }
+
+    #[test]
+    fn push_already_queued() {
+        let (mut store, keys) = store(&[1, 3]);
+        let mut queue = Queue::<NextSend>::new();
+
+        assert!(queue.push(&mut store.resolve(keys[0])));
+        assert!(queue.push(&mut store.resolve(keys[1])));
+
+        // Becoming ready again while queued keeps the stream's position.
+        assert!(!queue.push(&mut store.resolve(keys[0])));
+        assert!(!queue.push_front(&mut store.resolve(keys[1])));
+
+        assert_eq!(drain(&mut queue, &mut store), ids(&[1, 3]));
+
+        // Once popped, the stream can be queued again.
+        assert!(queue.push(&mut store.resolve(keys[0])));
+        assert_eq!(drain(&mut queue, &mut store), ids(&[1]));
+
+        clear(store, keys);
+    }
+
+    #[test]
+    fn drain_in_fifo_order() {
+        let (mut store, keys) = store(&[1, 3, 5, 7]);
+        let mut queue = Queue::<NextSend>::new();
+
+        for &key in &keys[..3] {
+            queue.push(&mut store.resolve(key));
+        }
+
+        queue.push_front(&mut store.resolve(keys[3]));
+
+        // A stream queued again while draining goes to the back.
+        let first = queue.pop(&mut store).unwrap().key();
+        queue.push(&mut store.resolve(first));
+
+        assert_eq!(drain(&mut queue, &mut store), ids(&[1, 3, 5, 7]));
+        assert!(queue.is_empty());
+
+        clear(store, keys);
+    }
+
+    #[test]
+    fn queues_are_independent() {
+        let (mut store, keys) = store(&[1, 3, 5]);
+        let mut send = Queue::<NextSend>::new();
+        let mut window_update = Queue::<NextWindowUpdate>::new();
+
+        for &key in &keys {
+            send.push(&mut store.resolve(key));
+        }
+
+        for &key in keys.iter().rev() {
+            window_update.push(&mut store.resolve(key));
+        }
+
+        assert_eq!(send.pop(&mut store).unwrap().id, StreamId::from(1));
+        assert_eq!(drain(&mut window_update, &mut store), ids(&[5, 3, 1]));
+        assert_eq!(drain(&mut send, &mut store), ids(&[3, 5]));
+
+        clear(store, keys);
+    }
+
+    #[test]
+    fn pop_if() {
+        let (mut store, keys) = store(&[1, 3]);
+        let mut queue = Queue::<NextSend>::new();
+
+        queue.push(&mut store.resolve(keys[0]));
+        queue.push(&mut store.resolve(keys[1]));
+
+        // Only the head of the queue is considered.
+        assert!(queue.pop_if(&mut store, |stream| stream.id == 3).is_none());
+        assert_eq!(queue.pop_if(&mut store, |stream| stream.id == 1).unwrap().id, StreamId::from(1));
+        assert_eq!(drain(&mut queue, &mut store), ids(&[3]));
+
+        clear(store, keys);
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
 
+use instrument;
+
 use std::time::Instant;
 use std::usize;
 
@@ -26,9 +28,17 @@ pub(super) struct Stream {
     /// concurrent streams.
     pub is_counted: bool,
 
+    /// Set to `true` when the stream is counted against the connection's max
+    /// reserved remote streams.
+    pub is_reserved_counted: bool,
+
     /// Number of outstanding handles pointing to this stream
     pub ref_count: usize,
 
+    /// Span entered while the state of the stream changes, nested in the
+    /// span of the connection
+    pub span: Span,
+
     // ===== Fields related to sending =====
     /// Next node in the accept linked list
     pub next_pending_send: Option<store::Key>,
@@ -68,6 +78,22 @@ pub(super) struct Stream {
     /// Set to true when the stream is pending to be opened
     pub is_pending_open: bool,
 
+    /// Set to true when a CONNECT request was received and no 2xx response was
+    /// sent yet. The tunnel does not accept DATA frames until then.
+    pub is_pending_tunnel: bool,
+
+    /// Set to true when a malformed request or response was received on the
+    /// stream, which is reset with PROTOCOL_ERROR.
+    pub is_malformed: bool,
+
+    /// Set to true when the stream was reserved locally and the PUSH_PROMISE
+    /// frame announcing it is not written yet
+    pub is_pending_push: bool,
+
+    /// Urgency and incremental parameters of the stream (RFC 9218). Only
+    /// used to schedule frames when extensible priorities are enabled.
+    pub priority_params: frame::PriorityParams,
+
     // ===== Fields related to receiving =====
     /// Next node in the accept linked list
     pub next_pending_accept: Option<store::Key>,
@@ -80,12 +106,57 @@ pub(super) struct Stream {
 
     pub in_flight_recv_data: WindowSize,
 
+    /// Received data whose connection window capacity was not released yet.
+    /// Only tracked with manual flow control, where the connection window is
+    /// released separately from the stream window.
+    pub in_flight_conn_recv_data: WindowSize,
+
+    /// The time when the peer ran out of window to send data on the stream,
+    /// if window stalls are reported.
+    pub recv_window_stalled_at: Option<Instant>,
+
+    /// Number of `ReleaseCapacity` handles, including the one held by the
+    /// body, that can release received data.
+    pub release_handles: usize,
+
+    /// Set once every `ReleaseCapacity` handle was dropped. Data received
+    /// from then on is released right away.
+    pub is_recv_released: bool,
+
+    /// The head of a malformed request, waiting to be taken by the
+    /// application
+    pub malformed_request: Option<::server::MalformedRequest>,
+
+    /// Number of bytes of received DATA not yet read by the user
+    pub buffered_recv_data: usize,
+
+    /// The time when the buffered data went over the per stream limit. No
+    /// window updates are sent for the stream while this is set.
+    pub recv_stalled_at: Option<Instant>,
+
+    /// Next node in the list of streams buffering too much received data
+    pub next_recv_stall: Option<store::Key>,
+
+    /// True if the stream is queued to be checked for the stall timeout
+    pub is_pending_recv_stall: bool,
+
     /// Next node in the linked list of streams waiting to send window updates.
     pub next_window_update: Option<store::Key>,
 
     /// True if the stream is waiting to send a window update
     pub is_pending_window_update: bool,
 
+    /// The request expects a 100 (Continue) response, to be sent the first
+    /// time its body is polled.
+    pub is_expecting_continue: bool,
+
+    /// Next node in the linked list of streams waiting to send a 100
+    /// (Continue) response.
+    pub next_continue: Option<store::Key>,
+
+    /// True if the stream is waiting to send a 100 (Continue) response
+    pub is_pending_continue: bool,
+
     /// The time when this stream may have been locally reset.
     pub reset_at: Option<Instant>,
 
@@ -109,6 +180,8 @@ pub(super) struct Stream {
 #[derive(Debug)]
 pub enum ContentLength {
     Omitted,
+    /// The message has no body: it is a response to a HEAD request, or a 204
+    /// or 304 response.
     Head,
     Remaining(u64),
 }
@@ -125,12 +198,18 @@ pub(super) struct NextSendCapacity;
 #[derive(Debug)]
 pub(super) struct NextWindowUpdate;
 
+#[derive(Debug)]
+pub(super) struct NextContinue;
+
 #[derive(Debug)]
 pub(super) struct NextOpen;
 
 #[derive(Debug)]
 pub(super) struct NextResetExpire;
 
+#[derive(Debug)]
+pub(super) struct NextRecvStall;
+
 impl Stream {
     pub fn new(
         id: StreamId,
@@ -155,7 +234,9 @@ impl Stream {
             id,
             state: State::default(),
             ref_count: 0,
+            span: instrument::stream_span(id),
             is_counted: false,
+            is_reserved_counted: false,
 
             // ===== Fields related to sending =====
             next_pending_send: None,
@@ -169,6 +250,10 @@ impl Stream {
             next_pending_send_capacity: None,
             send_capacity_inc: false,
             is_pending_open: false,
+            is_pending_push: false,
+            is_pending_tunnel: false,
+            is_malformed: false,
+            priority_params: frame::PriorityParams::default(),
             next_open: None,
 
             // ===== Fields related to receiving =====
@@ -176,8 +261,20 @@ impl Stream {
             is_pending_accept: false,
             recv_flow: recv_flow,
             in_flight_recv_data: 0,
+            in_flight_conn_recv_data: 0,
+            recv_window_stalled_at: None,
+            release_handles: 0,
+            is_recv_released: false,
+            malformed_request: None,
+            buffered_recv_data: 0,
+            recv_stalled_at: None,
+            next_recv_stall: None,
+            is_pending_recv_stall: false,
             next_window_update: None,
             is_pending_window_update: false,
+            is_expecting_continue: false,
+            next_continue: None,
+            is_pending_continue: false,
             reset_at: None,
             next_reset_expire: None,
             pending_recv: buffer::Deque::new(),
@@ -229,7 +326,10 @@ impl Stream {
             // The stream is not in any queue
             !self.is_pending_send && !self.is_pending_send_capacity &&
             !self.is_pending_accept && !self.is_pending_window_update &&
-            !self.is_pending_open && !self.reset_at.is_some()
+            !self.is_pending_continue &&
+            !self.is_pending_open && !self.is_pending_push &&
+            !self.reset_at.is_some() &&
+            !self.is_pending_recv_stall
     }
 
     /// Returns true when the consumer of the stream has dropped all handles
@@ -366,6 +466,28 @@ impl store::Next for NextSendCapacity {
     }
 }
 
+impl store::Next for NextContinue {
+    fn next(stream: &Stream) -> Option<store::Key> {
+        stream.next_continue
+    }
+
+    fn set_next(stream: &mut Stream, key: Option<store::Key>) {
+        stream.next_continue = key;
+    }
+
+    fn take_next(stream: &mut Stream) -> Option<store::Key> {
+        stream.next_continue.take()
+    }
+
+    fn is_queued(stream: &Stream) -> bool {
+        stream.is_pending_continue
+    }
+
+    fn set_queued(stream: &mut Stream, val: bool) {
+        stream.is_pending_continue = val;
+    }
+}
+
 impl store::Next for NextWindowUpdate {
     fn next(stream: &Stream) -> Option<store::Key> {
         stream.next_window_update
@@ -441,6 +563,28 @@ impl store::Next for NextResetExpire {
     }
 }
 
+impl store::Next for NextRecvStall {
+    fn next(stream: &Stream) -> Option<store::Key> {
+        stream.next_recv_stall
+    }
+
+    fn set_next(stream: &mut Stream, key: Option<store::Key>) {
+        stream.next_recv_stall = key;
+    }
+
+    fn take_next(stream: &mut Stream) -> Option<store::Key> {
+        stream.next_recv_stall.take()
+    }
+
+    fn is_queued(stream: &Stream) -> bool {
+        stream.is_pending_recv_stall
+    }
+
+    fn set_queued(stream: &mut Stream, val: bool) {
+        stream.is_pending_recv_stall = val;
+    }
+}
+
 // ===== impl ContentLength =====
 
 impl ContentLength {
@@ -1,8 +1,8 @@
 use {client, proto, server};
-use codec::{Codec, RecvError, SendError, UserError};
+use codec::{Codec, RecvError, UserError};
 use frame::{self, Frame, Reason};
-use proto::{peer, Peer, Open, WindowSize};
-use super::{Buffer, Config, Counts, Prioritized, Recv, Send, Stream, StreamId};
+use proto::{peer, Initiator, Peer, Open, WindowSize};
+use super::{Buffer, Config, Counts, Lock, Prioritized, Recv, Send, Span, Stream, StreamId};
 use super::recv::RecvHeaderBlockError;
 use super::store::{self, Entry, Resolve, Store};
 
@@ -12,7 +12,9 @@ use http::{HeaderMap, Request, Response};
 use tokio_io::AsyncWrite;
 
 use std::{fmt, io};
-use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
 
 #[derive(Debug)]
 pub(crate) struct Streams<B, P>
@@ -21,7 +23,7 @@ where
 {
     /// Holds most of the connection and stream related state for processing
     /// HTTP/2.0 frames associated with streams.
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Lock<Inner>>,
 
     /// This is the queue of frames to be written to the wire. This is split out
     /// to avoid requiring a `B` generic on all public API types even if `B` is
@@ -45,10 +47,36 @@ pub(crate) struct StreamRef<B> {
 
 /// Reference to the stream state that hides the send data chunk generic
 pub(crate) struct OpaqueStreamRef {
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Lock<Inner>>,
     key: store::Key,
 }
 
+/// Handle yielding the settings deltas applied from the remote peer.
+///
+/// The state is held weakly, so that the handle does not keep a client
+/// connection open once its `SendRequest` handles are dropped.
+#[derive(Debug)]
+pub(crate) struct SettingsChanges {
+    inner: Weak<Lock<Inner>>,
+}
+
+/// Handle requesting the connection to be aborted from any task.
+///
+/// The state is held weakly, so that the handle does not keep a client
+/// connection open once its `SendRequest` handles are dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct AbortHandle {
+    inner: Weak<Lock<Inner>>,
+}
+
+/// Maximum number of pending settings deltas kept for a lagging consumer.
+/// Once reached, newer deltas are merged into the most recent pending one.
+const MAX_PENDING_SETTINGS_CHANGES: usize = 8;
+
+/// Maximum number of dependency tree nodes kept for streams that are not
+/// active, i.e. idle streams used to group other streams and closed streams.
+const MAX_INACTIVE_PRIORITY_NODES: usize = 100;
+
 /// Fields needed to manage state related to managing the set of streams. This
 /// is mostly split out to make ownership happy.
 ///
@@ -63,6 +91,29 @@ struct Inner {
 
     /// Stores stream state
     store: Store,
+
+    /// The cumulative settings received from the remote peer.
+    remote_settings: frame::Settings,
+
+    /// Settings deltas not yet observed through `SettingsChanges`. This is
+    /// `None` until a `SettingsChanges` handle is requested.
+    settings_changes: Option<SettingsWatch>,
+
+    /// Error code and debug data of an abort requested through an
+    /// `AbortHandle`, until the connection acts on it.
+    pending_abort: Option<(Reason, Bytes)>,
+
+    /// Span of the connection, entered when opening streams outside of the
+    /// connection task
+    span: Span,
+}
+
+#[derive(Debug)]
+struct SettingsWatch {
+    pending: VecDeque<frame::Settings>,
+
+    /// Task polling `SettingsChanges`.
+    task: Option<task::Task>,
 }
 
 #[derive(Debug)]
@@ -97,7 +148,7 @@ where
         let peer = P::dyn();
 
         Streams {
-            inner: Arc::new(Mutex::new(Inner {
+            inner: Arc::new(Lock::new(Inner {
                 counts: Counts::new(peer, &config),
                 actions: Actions {
                     recv: Recv::new(peer, &config),
@@ -106,6 +157,10 @@ where
                     conn_error: None,
                 },
                 store: Store::new(),
+                remote_settings: frame::Settings::default(),
+                settings_changes: None,
+                pending_abort: None,
+                span: config.span,
             })),
             send_buffer: Arc::new(SendBuffer::new()),
             _p: ::std::marker::PhantomData,
@@ -121,6 +176,23 @@ where
             .set_target_connection_window(size, &mut me.actions.task)
     }
 
+    /// Raises the target connection window to `size`. A larger target is
+    /// left as is.
+    pub fn grow_target_connection_window_size(&mut self, size: WindowSize) {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        me.actions
+            .recv
+            .grow_target_connection_window(size, &mut me.actions.task)
+    }
+
+    /// Returns the span of the stream `id`, if the stream is known.
+    pub fn span(&self, id: StreamId) -> Option<Span> {
+        let mut me = self.inner.lock().unwrap();
+        me.store.find_mut(&id).map(|stream| stream.span.clone())
+    }
+
     /// Process inbound headers
     pub fn recv_headers(&mut self, frame: frame::Headers) -> Result<(), RecvError> {
         let id = frame.stream_id();
@@ -128,14 +200,30 @@ where
         let me = &mut *me;
 
         // The GOAWAY process has begun. All streams with a greater ID than
-        // specified as part of GOAWAY should be ignored.
-        if id > me.actions.recv.max_stream_id() {
+        // specified as part of GOAWAY should be ignored. Streams the peer
+        // tries to open are refused when opened below.
+        if id > me.actions.recv.max_stream_id() && me.counts.peer().is_local_init(id) {
             trace!("id ({:?}) > max_stream_id ({:?}), ignoring HEADERS", id, me.actions.recv.max_stream_id());
             return Ok(());
         }
 
-        let key = match me.store.find_entry(id) {
-            Entry::Occupied(e) => e.key(),
+        if let Some(dependency) = frame.stream_dependency() {
+            if dependency.dependency_id() == id {
+                // A stream cannot depend on itself. An endpoint MUST treat
+                // this as a stream error (Section 5.4.2) of type
+                // `PROTOCOL_ERROR`.
+                debug!("stream error PROTOCOL_ERROR -- HEADERS invalid dependency ID");
+                return Err(RecvError::Stream {
+                    id,
+                    reason: Reason::PROTOCOL_ERROR,
+                });
+            }
+        }
+
+        let (key, is_new) = match me.store.find_entry(id) {
+            Entry::Occupied(e) => (e.key(), false),
+            // Stream IDs that regress or reuse the ID of a stream that was
+            // released are rejected when opening the stream.
             Entry::Vacant(e) => match me.actions.recv.open(id, Open::Headers, &mut me.counts)? {
                 Some(stream_id) => {
                     let stream = Stream::new(
@@ -144,12 +232,22 @@ where
                         me.actions.recv.init_window_sz(),
                     );
 
-                    e.insert(stream)
+                    (e.insert(stream), true)
                 },
                 None => return Ok(()),
             },
         };
 
+        match frame.stream_dependency() {
+            Some(dependency) => me.actions.send.dependencies_mut().set(id, dependency),
+            None if is_new => me.actions.send.dependencies_mut().insert(id),
+            None => {},
+        }
+
+        if is_new {
+            me.prune_dependencies();
+        }
+
         let stream = me.store.resolve(key);
 
         if stream.state.is_local_reset() {
@@ -197,12 +295,10 @@ where
                     },
                     Err(RecvHeaderBlockError::State(err)) => Err(err),
                 }
+            } else if let Err(e) = stream.state.ensure_recv_not_closed(stream.id) {
+                trace!("recv_headers; remote side closed; stream={:?}", stream.id);
+                Err(e)
             } else {
-                if !frame.is_end_stream() {
-                    // TODO: Is this the right error
-                    return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
-                }
-
                 actions.recv.recv_trailers(frame, stream)
             };
 
@@ -210,6 +306,59 @@ where
         })
     }
 
+    pub fn recv_priority(&mut self, frame: frame::Priority) -> Result<(), RecvError> {
+        let id = frame.stream_id();
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        if !me.actions.send.dependencies().contains(id) && me.store.find_mut(&id).is_none() {
+            // The stream is idle or closed. Only a limited amount of priority
+            // information is kept for those.
+            me.prune_dependencies();
+
+            if me.is_dependency_tree_full() {
+                trace!("recv_priority; too many inactive streams; ignoring {:?}", id);
+                return Ok(());
+            }
+        }
+
+        me.actions.send.dependencies_mut().set(id, frame.dependency());
+
+        Ok(())
+    }
+
+    pub fn recv_priority_update(&mut self, frame: frame::PriorityUpdate) -> Result<(), RecvError> {
+        let id = frame.prioritized_stream_id();
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        if !me.counts.peer().is_server() {
+            // > Servers MUST NOT send PRIORITY_UPDATE frames. If a client
+            // > receives a PRIORITY_UPDATE frame, it MUST respond with a
+            // > connection error of type PROTOCOL_ERROR.
+            debug!("connection error PROTOCOL_ERROR -- client received PRIORITY_UPDATE");
+            return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
+        }
+
+        if !me.actions.recv.is_extensible_priorities_enabled() {
+            trace!("recv_priority_update; extensible priorities disabled; ignoring");
+            return Ok(());
+        }
+
+        let params = frame.params();
+
+        match me.store.find_mut(&id) {
+            // The send queues pick up the new urgency the next time the
+            // stream is scheduled.
+            Some(mut stream) => stream.priority_params = params,
+            None => if !me.counts.peer().is_local_init(id) {
+                me.actions.recv.recv_priority_update(id, params);
+            },
+        }
+
+        Ok(())
+    }
+
     pub fn recv_data(&mut self, frame: frame::Data) -> Result<(), RecvError> {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
@@ -226,6 +375,19 @@ where
                     return Ok(());
                 }
 
+                // The stream was closed and released already (section 5.1 of
+                // RFC 7540). How it was closed is not known anymore.
+                if me.actions.ensure_not_idle(me.counts.peer(), id).is_ok() {
+                    debug!("recv_data; stream closed: {:?}", id);
+                    let sz = frame.payload().len() + frame.padding_len();
+                    me.actions.recv.ignore_data(sz as WindowSize)?;
+
+                    return Err(RecvError::Stream {
+                        id,
+                        reason: Reason::STREAM_CLOSED,
+                    });
+                }
+
                 trace!("recv_data; stream not found: {:?}", id);
                 return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
             },
@@ -236,7 +398,7 @@ where
         let send_buffer = &mut *send_buffer;
 
         me.counts.transition(stream, |counts, stream| {
-            let sz = frame.payload().len();
+            let sz = frame.payload().len() + frame.padding_len();
             let res = actions.recv.recv_data(frame, stream);
 
             // Any stream error after receiving a DATA frame means
@@ -250,6 +412,32 @@ where
         })
     }
 
+    /// Handles a DATA frame larger than the max frame size, whose payload of
+    /// `len` octets was discarded.
+    ///
+    /// The stream is reset with `FRAME_SIZE_ERROR`, and the payload is
+    /// released from the connection window right away. If the stream is not
+    /// known, this is a connection error instead.
+    pub fn recv_data_too_big(&mut self, id: StreamId, len: usize) -> Result<(), RecvError> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        if me.store.find_mut(&id).is_none() {
+            debug!("connection error FRAME_SIZE_ERROR -- DATA frame too big on unknown stream {:?}", id);
+            return Err(RecvError::Connection(Reason::FRAME_SIZE_ERROR));
+        }
+
+        // The max frame size is smaller than the largest window, so this
+        // only fails if the peer did not respect the connection window.
+        me.actions.recv.ignore_data(len as WindowSize)?;
+
+        debug!("stream error FRAME_SIZE_ERROR -- DATA frame too big; id={:?}", id);
+        Err(RecvError::Stream {
+            id,
+            reason: Reason::FRAME_SIZE_ERROR,
+        })
+    }
+
     pub fn recv_reset(&mut self, frame: frame::Reset) -> Result<(), RecvError> {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
@@ -279,6 +467,15 @@ where
             },
         };
 
+        // Streams opened by the peer and reset before a response was sent
+        // cost us work without counting against the concurrency limit.
+        if !me.counts.peer().is_local_init(id) && stream.state.is_send_headers() {
+            if !me.counts.recv_remote_reset() {
+                debug!("too many streams reset by the peer; id={:?}", id);
+                return Err(RecvError::Connection(Reason::ENHANCE_YOUR_CALM));
+            }
+        }
+
         let mut send_buffer = self.send_buffer.inner.lock().unwrap();
         let send_buffer = &mut *send_buffer;
 
@@ -314,7 +511,9 @@ where
             })
             .unwrap();
 
-        actions.conn_error = Some(err.shallow_clone());
+        actions.conn_error = Some(err.clone());
+        actions.send.notify_open_ready();
+        me.notify_settings_changes();
 
         last_processed_id
     }
@@ -329,7 +528,11 @@ where
         let send_buffer = &mut *send_buffer;
 
         let last_stream_id = frame.last_stream_id();
-        let err = frame.reason().into();
+
+        // Streams above the last stream ID were not processed by the peer and
+        // can be retried elsewhere, which is also true of any stream opened
+        // from now on.
+        let err = proto::Error::Refused(frame.reason());
 
         if last_stream_id > actions.recv.max_stream_id() {
             // The remote endpoint sent a `GOAWAY` frame indicating a stream
@@ -357,6 +560,8 @@ where
             .unwrap();
 
         actions.conn_error = Some(err);
+        actions.send.notify_open_ready();
+        me.notify_settings_changes();
 
         Ok(())
     }
@@ -394,8 +599,7 @@ where
                 );
             } else {
                 me.actions
-                    .recv
-                    .ensure_not_idle(id)
+                    .ensure_not_idle(me.counts.peer(), id)
                     .map_err(RecvError::Connection)?;
             }
         }
@@ -410,8 +614,14 @@ where
         let id = frame.stream_id();
         let promised_id = frame.promised_id();
 
+        // Push promises are only sent on streams opened by the local peer.
+        if !me.counts.peer().is_local_init(id) {
+            trace!("recv_push_promise; {:?} was not opened locally", id);
+            return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
+        }
+
         // First, ensure that the initiating stream is still in a valid state.
-        let parent_key = match me.store.find_mut(&id) {
+        let (parent_key, is_parent_reset) = match me.store.find_mut(&id) {
             Some(stream) => {
                 // The GOAWAY process has begun. All streams with a greater ID
                 // than specified as part of GOAWAY should be ignored.
@@ -420,9 +630,18 @@ where
                     return Ok(());
                 }
 
+                // The PUSH_PROMISE frame may have been sent before the peer
+                // received the RST_STREAM frame, in which case the promised
+                // stream is canceled below.
+                let is_reset = stream.state.is_local_reset();
+
                 // The stream must be receive open
-                stream.state.ensure_recv_open()?;
-                stream.key()
+                if !is_reset && !stream.state.ensure_recv_open()? {
+                    trace!("recv_push_promise; {:?} is closed", id);
+                    return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
+                }
+
+                (stream.key(), is_reset)
             }
             None => return Err(RecvError::Connection(Reason::PROTOCOL_ERROR)),
         };
@@ -451,7 +670,18 @@ where
             let actions = &mut me.actions;
 
             me.counts.transition(stream, |counts, stream| {
-                let res = actions.recv.recv_push_promise(frame, stream);
+                // The stream no longer counts once it leaves the reserved
+                // state.
+                counts.inc_num_reserved_recv_streams(stream);
+
+                let res = if is_parent_reset {
+                    stream.state.reserve_remote().and_then(|_| Err(RecvError::Stream {
+                        id: promised_id,
+                        reason: Reason::CANCEL,
+                    }))
+                } else {
+                    actions.recv.recv_push_promise(frame, stream)
+                };
 
                 let mut send_buffer = self.send_buffer.inner.lock().unwrap();
                 actions.reset_on_recv_stream_err(&mut *send_buffer, stream, counts, res)
@@ -459,6 +689,11 @@ where
             })?
         };
 
+        // A promise that was refused is not handed out.
+        if me.store[child_key].state.is_reset() {
+            return Ok(());
+        }
+
         // Push the stream... this requires a bit of indirection to make
         // the borrow checker happy.
         let mut ppp = me.store[parent_key].pending_push_promises.take();
@@ -502,6 +737,36 @@ where
         })
     }
 
+    pub fn next_malformed(&mut self) -> Option<StreamRef<B>> {
+        let key = {
+            let mut me = self.inner.lock().unwrap();
+            let me = &mut *me;
+
+            match me.actions.recv.next_malformed(&mut me.store) {
+                Some(key) => {
+                    let mut stream = me.store.resolve(key);
+                    trace!("next_malformed; id={:?}, state={:?}", stream.id, stream.state);
+                    // Increment the ref count
+                    stream.ref_inc();
+
+                    // Return the key
+                    Some(key)
+                },
+                None => None,
+            }
+        };
+
+        key.map(|key| {
+            StreamRef {
+                opaque: OpaqueStreamRef {
+                    inner: self.inner.clone(),
+                    key,
+                },
+                send_buffer: self.send_buffer.clone(),
+            }
+        })
+    }
+
     pub fn send_pending_refusal<T>(
         &mut self,
         dst: &mut Codec<T, Prioritized<B>>,
@@ -520,55 +785,155 @@ where
         me.actions.recv.clear_expired_reset_streams(&mut me.store, &mut me.counts);
     }
 
-    pub fn poll_complete<T>(&mut self, dst: &mut Codec<T, Prioritized<B>>) -> Poll<(), io::Error>
-    where
-        T: AsyncWrite,
-    {
+    /// Resets streams that buffered too much received data for too long.
+    pub fn clear_stalled_recv_streams(&mut self) {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
 
+        let actions = &mut me.actions;
         let mut send_buffer = self.send_buffer.inner.lock().unwrap();
         let send_buffer = &mut *send_buffer;
 
-        // Send WINDOW_UPDATE frames first
-        //
-        // TODO: It would probably be better to interleave updates w/ data
-        // frames.
-        try_ready!(me.actions.recv.poll_complete(&mut me.store, &mut me.counts, dst));
-
-        // Send any other pending frames
-        try_ready!(me.actions.send.poll_complete(
-            send_buffer,
-            &mut me.store,
-            &mut me.counts,
-            dst
-        ));
+        while let Some(stream) = actions.recv.pop_stalled_recv(&mut me.store) {
+            me.counts.transition(stream, |counts, stream| {
+                if stream.recv_stalled_at.is_none() {
+                    return;
+                }
 
-        // Nothing else to do, track the task
-        me.actions.task = Some(task::current());
+                trace!("clear_stalled_recv_streams; resetting {:?}", stream.id);
 
-        Ok(().into())
+                actions.send.send_reset(
+                    Reason::CANCEL, send_buffer, stream, counts, &mut actions.task);
+                actions.recv.enqueue_reset_expiration(stream, counts);
+            })
+        }
     }
 
-    pub fn apply_remote_settings(&mut self, frame: &frame::Settings) -> Result<(), RecvError> {
+    /// Returns when `clear_stalled_recv_streams` resets the next stream, if
+    /// any.
+    pub fn stalled_recv_deadline(&self) -> Option<Instant> {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
+        me.actions.recv.stalled_recv_deadline(&mut me.store)
+    }
 
-        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
-        let send_buffer = &mut *send_buffer;
+    /// Reports receive windows exhausted for too long.
+    pub fn report_window_stalls(&mut self) {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+        me.actions.recv.report_window_stalls(&mut me.store);
+    }
+
+    pub fn poll_complete<T>(&mut self, dst: &mut Codec<T, Prioritized<B>>) -> Poll<(), io::Error>
+    where
+        T: AsyncWrite,
+    {
+        {
+            let mut me = self.inner.lock().unwrap();
+            let me = &mut *me;
+
+            // Track the task first, frames may be queued as soon as the
+            // streams are unlocked.
+            me.actions.task = Some(task::current());
+
+            // Send WINDOW_UPDATE frames first, the ones queued later are sent
+            // between the other frames.
+            try_ready!(me.actions.recv.poll_complete(&mut me.store, &mut me.counts, dst));
+        }
+
+        // Send any other pending frames. The streams are only locked to pick
+        // the next frame, it is encoded and written with the lock released.
+        try_ready!(dst.poll_ready());
 
+        // The max frame length. This is read from the codec on every call so
+        // that a SETTINGS_MAX_FRAME_SIZE change from the peer applies to data
+        // that is already queued, including a reclaimed partial frame.
+        let max_frame_len = dst.max_send_frame_size();
+
+        let mut written = dst.take_last_data_frame();
+
+        loop {
+            let frame = {
+                let mut me = self.inner.lock().unwrap();
+                let me = &mut *me;
+
+                let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+
+                me.actions.send.pop_frame_to_write(
+                    written.take(),
+                    &mut *send_buffer,
+                    &mut me.store,
+                    max_frame_len,
+                    &mut me.counts,
+                )
+            };
+
+            let is_empty = match frame {
+                Some((frame, span)) => {
+                    let _enter = span.enter();
+
+                    trace!("writing frame={:?}", frame);
+                    dst.buffer(frame).ok().expect("invalid frame");
+
+                    // Ensure the codec is ready to try the loop again.
+                    try_ready!(dst.poll_ready());
+
+                    // Window updates queued meanwhile, e.g. by releasing
+                    // capacity, go out at this frame boundary. The next frame
+                    // may start a header block, which nothing can be
+                    // interleaved with until its last CONTINUATION frame.
+                    let mut me = self.inner.lock().unwrap();
+                    let me = &mut *me;
+
+                    try_ready!(me.actions.recv.poll_complete(&mut me.store, &mut me.counts, dst));
+                    false
+                },
+                None => {
+                    // Try to flush the codec, unless the flush strategy
+                    // waits for the buffer to fill up.
+                    try_ready!(dst.flush_pending());
+                    true
+                },
+            };
+
+            // Because, always try to reclaim...
+            written = dst.take_last_data_frame();
+
+            // This might release a data frame...
+            if is_empty && written.is_none() {
+                return Ok(().into());
+            }
+        }
+    }
+
+    pub fn apply_remote_settings(&mut self, frame: &frame::Settings) -> Result<(), RecvError> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        me.remote_settings.merge(frame);
         me.counts.apply_remote_settings(frame);
 
-        me.actions.send.apply_remote_settings(
-            frame, send_buffer, &mut me.store, &mut me.counts, &mut me.actions.task)
+        if let Some(ref mut watch) = me.settings_changes {
+            watch.push(frame);
+        }
+
+        me.actions.send.apply_remote_settings(frame, &mut me.store, &mut me.counts)
+    }
+
+    pub fn apply_local_settings(&mut self, frame: &frame::Settings) -> Result<(), RecvError> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        me.counts.apply_local_settings(frame);
+        me.actions.recv.apply_local_settings(frame, &mut me.store)
     }
 
     pub fn send_request(
         &mut self,
         request: Request<()>,
         end_of_stream: bool,
-        pending: Option<&OpaqueStreamRef>,
-    ) -> Result<StreamRef<B>, SendError> {
+        priority: Option<frame::StreamDependency>,
+    ) -> Result<StreamRef<B>, ::Error> {
         use http::Method;
         use super::stream::ContentLength;
 
@@ -585,18 +950,13 @@ where
             let send_buffer = &mut *send_buffer;
 
             me.actions.ensure_no_conn_error()?;
-            me.actions.send.ensure_next_stream_id()?;
-
-            // The `pending` argument is provided by the `Client`, and holds
-            // a store `Key` of a `Stream` that may have been not been opened
-            // yet.
-            //
-            // If that stream is still pending, the Client isn't allowed to
-            // queue up another pending stream. They should use `poll_ready`.
-            if let Some(stream) = pending {
-                if me.store.resolve(stream.key).is_pending_open {
-                    return Err(UserError::Rejected.into());
-                }
+            let next_id = me.actions.send.ensure_next_stream_id()?;
+
+            // Requests over the concurrency limit are queued until streams
+            // close, up to a limit. The Client should use `poll_ready` to
+            // avoid reaching it.
+            if !me.actions.send.can_open_or_queue(&me.counts) {
+                return Err(UserError::AtCapacity.into());
             }
 
             if me.counts.peer().is_server() {
@@ -604,7 +964,31 @@ where
                 return Err(UserError::UnexpectedFrameType.into());
             }
 
+            // The `:protocol` pseudo header may only be sent once the server
+            // enabled the extended CONNECT method (RFC 8441, section 4).
+            if request.extensions().get::<::Protocol>().is_some()
+                && !me.actions.send.is_extended_connect_protocol_enabled()
+            {
+                return Err(UserError::PeerDisabledConnectProtocol.into());
+            }
+
+            let is_head = *request.method() == Method::HEAD;
+
+            // Convert the message before opening the stream, so that invalid
+            // requests do not use up a stream ID.
+            let mut headers = client::Peer::convert_send_message(
+                next_id, request, end_of_stream)?;
+
             let stream_id = me.actions.send.open()?;
+            debug_assert_eq!(stream_id, next_id);
+
+            if let Some(ref priority) = priority {
+                if priority.dependency_id() == stream_id {
+                    return Err(UserError::InvalidPriority.into());
+                }
+            }
+
+            let _enter = me.span.enter();
 
             let mut stream = Stream::new(
                 stream_id,
@@ -612,13 +996,13 @@ where
                 me.actions.recv.init_window_sz(),
             );
 
-            if *request.method() == Method::HEAD {
+            if is_head {
                 stream.content_length = ContentLength::Head;
             }
 
-            // Convert the message
-            let headers = client::Peer::convert_send_message(
-                stream_id, request, end_of_stream)?;
+            if let Some(priority) = priority {
+                headers.set_stream_dependency(priority);
+            }
 
             let mut stream = me.store.insert(stream.id, stream);
 
@@ -657,12 +1041,61 @@ where
         })
     }
 
+    /// Opens the stream 1 of a connection upgraded from HTTP/1.1 (section
+    /// 3.2 of RFC 7540).
+    ///
+    /// The request was sent over HTTP/1.1, so the stream is half-closed
+    /// (local) and only waits for the response.
+    pub fn open_upgrade_stream(&mut self, method: &::http::Method) -> OpaqueStreamRef {
+        use super::stream::ContentLength;
+
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let stream_id = me.actions.send.open().expect("upgrade stream already opened");
+        debug_assert_eq!(stream_id, StreamId::from(1));
+
+        let _enter = me.span.enter();
+
+        let mut stream = Stream::new(
+            stream_id,
+            me.actions.send.init_window_sz(),
+            me.actions.recv.init_window_sz(),
+        );
+
+        if *method == ::http::Method::HEAD {
+            stream.content_length = ContentLength::Head;
+        }
+
+        stream.state.send_open(true).expect("invalid upgrade stream state");
+
+        let mut stream = me.store.insert(stream.id, stream);
+
+        if me.counts.can_inc_num_send_streams() {
+            me.counts.inc_num_send_streams(&mut stream);
+        }
+
+        // Increment the stream ref count as we will be returning a handle.
+        stream.ref_inc();
+
+        OpaqueStreamRef {
+            inner: self.inner.clone(),
+            key: stream.key(),
+        }
+    }
+
+    /// Resets a stream after a received frame caused a stream error.
     pub fn send_reset(&mut self, id: StreamId, reason: Reason) {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
 
         let key = match me.store.find_entry(id) {
             Entry::Occupied(e) => e.key(),
+            // The stream was closed and released already. It is reset
+            // without being opened again.
+            Entry::Vacant(e) if me.actions.ensure_not_idle(me.counts.peer(), id).is_ok() => {
+                e.insert(Stream::new(id, 0, 0))
+            },
             Entry::Vacant(e) => match me.actions.recv.open(id, Open::Headers, &mut me.counts) {
                 Ok(Some(stream_id)) => {
                     let stream = Stream::new(stream_id, 0, 0);
@@ -679,8 +1112,7 @@ where
         let send_buffer = &mut *send_buffer;
 
         me.counts.transition(stream, |counts, stream| {
-            actions.send.send_reset(
-                reason, send_buffer, stream, counts, &mut actions.task);
+            actions.reset_on_recv_err(reason, send_buffer, stream, counts);
             actions.recv.enqueue_reset_expiration(stream, counts)
         })
     }
@@ -697,22 +1129,28 @@ impl<B> Streams<B, client::Peer>
 where
     B: Buf,
 {
-    pub fn poll_pending_open(&mut self, pending: Option<&OpaqueStreamRef>) -> Poll<(), ::Error> {
+    /// Returns `Ready` once a request can be sent without being queued.
+    pub fn poll_open_ready(&mut self) -> Poll<(), ::Error> {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
 
         me.actions.ensure_no_conn_error()?;
         me.actions.send.ensure_next_stream_id()?;
 
-        if let Some(pending) = pending {
-            let mut stream = me.store.resolve(pending.key);
-            trace!("poll_pending_open; stream = {:?}", stream.is_pending_open);
-            if stream.is_pending_open {
-                stream.wait_send();
-                return Ok(Async::NotReady);
-            }
-        }
-        Ok(().into())
+        Ok(me.actions.send.poll_open_ready(&me.counts))
+    }
+
+    /// Returns the number of locally initiated streams that are open or
+    /// half-closed.
+    pub fn num_send_streams(&self) -> usize {
+        let me = self.inner.lock().unwrap();
+        me.counts.num_send_streams()
+    }
+
+    /// Returns the maximum number of locally initiated streams.
+    pub fn max_send_streams(&self) -> usize {
+        let me = self.inner.lock().unwrap();
+        me.counts.max_send_streams()
     }
 }
 
@@ -732,6 +1170,15 @@ where
         let mut send_buffer = self.send_buffer.inner.lock().unwrap();
         let send_buffer = &mut *send_buffer;
 
+        // If the peer sent a GOAWAY frame, the streams it went on to process
+        // are closed with its error code.
+        let go_away_err = match actions.conn_error {
+            Some(proto::Error::Refused(reason)) => {
+                Some(proto::Error::GoAway(Bytes::new(), reason, Initiator::Remote))
+            },
+            _ => None,
+        };
+
         if actions.conn_error.is_none() {
             actions.conn_error = Some(io::Error::from(io::ErrorKind::BrokenPipe).into());
         }
@@ -741,7 +1188,10 @@ where
         me.store
             .for_each(|stream| {
                 counts.transition(stream, |counts, stream| {
-                    actions.recv.recv_eof(stream);
+                    match go_away_err {
+                        Some(ref err) => actions.recv.recv_err(err, stream),
+                        None => actions.recv.recv_eof(stream),
+                    }
 
                     // This handles resetting send state associated with the
                     // stream
@@ -752,14 +1202,82 @@ where
             .expect("recv_eof");
 
         actions.clear_queues(clear_pending_accept, &mut me.store, counts);
+        me.notify_settings_changes();
         Ok(())
     }
 
+    /// Returns `true` if no new streams can be opened, because the peer
+    /// sent a GOAWAY frame or the connection failed.
+    pub fn is_closing(&self) -> bool {
+        let me = self.inner.lock().unwrap();
+        me.actions.conn_error.is_some()
+    }
+
     pub fn num_active_streams(&self) -> usize {
         let me = self.inner.lock().unwrap();
         me.store.num_active_streams()
     }
 
+    /// Returns the statistics tracked by the streams. The codec statistics
+    /// are left for the caller to fill in.
+    pub fn stats(&self) -> ::ConnectionStats {
+        let me = self.inner.lock().unwrap();
+
+        ::ConnectionStats {
+            active_streams: me.store.num_active_streams(),
+            opened_streams: me.counts.num_opened_streams(),
+            send_window: me.actions.send.connection_window(),
+            recv_window: me.actions.recv.connection_window(),
+            buffered_send_data: me.actions.send.buffered_send_data(),
+            contended_locks: self.inner.num_contended(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a copy of the settings received from the remote peer so far.
+    pub fn remote_settings(&self) -> frame::Settings {
+        let me = self.inner.lock().unwrap();
+        me.remote_settings.clone()
+    }
+
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        let me = self.inner.lock().unwrap();
+        me.actions.send.is_extended_connect_protocol_enabled()
+    }
+
+    pub fn settings_changes(&self) -> SettingsChanges {
+        let mut me = self.inner.lock().unwrap();
+
+        if me.settings_changes.is_none() {
+            me.settings_changes = Some(SettingsWatch {
+                pending: VecDeque::new(),
+                task: None,
+            });
+        }
+
+        SettingsChanges {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Returns the abort requested through an `AbortHandle`, if any.
+    pub fn take_pending_abort(&self) -> Option<(Reason, Bytes)> {
+        let mut me = self.inner.lock().unwrap();
+        me.pending_abort.take()
+    }
+
+    /// Returns true once no more locally initiated streams can be opened.
+    pub fn is_stream_id_exhausted(&self) -> bool {
+        let me = self.inner.lock().unwrap();
+        me.actions.send.is_stream_id_exhausted()
+    }
+
     pub fn has_streams_or_other_references(&self) -> bool {
         if Arc::strong_count(&self.inner) > 1 {
             return true;
@@ -843,6 +1361,42 @@ impl<B> StreamRef<B> {
         })
     }
 
+    pub fn send_priority(&mut self, dependency: frame::StreamDependency) -> Result<(), UserError> {
+        let mut me = self.opaque.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let id = me.store[self.opaque.key].id;
+
+        if dependency.dependency_id() == id {
+            return Err(UserError::InvalidPriority);
+        }
+
+        let mut stream = me.store.resolve(self.opaque.key);
+        let actions = &mut me.actions;
+        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+        let send_buffer = &mut *send_buffer;
+
+        actions.send.send_priority(
+            frame::Priority::new(id, dependency), send_buffer, &mut stream, &mut actions.task);
+
+        Ok(())
+    }
+
+    /// Sends an ALTSVC frame advertising alternative services for the origin
+    /// of the stream's request.
+    pub fn send_alt_svc(&mut self, field_value: Bytes) -> Result<(), UserError> {
+        let mut me = self.opaque.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.opaque.key);
+        let actions = &mut me.actions;
+        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+        let send_buffer = &mut *send_buffer;
+
+        let frame = frame::AltSvc::new(stream.id, Bytes::new(), field_value);
+        actions.send.send_alt_svc(frame, send_buffer, &mut stream, &mut actions.task)
+    }
+
     pub fn send_reset(&mut self, reason: Reason) {
         let mut me = self.opaque.inner.lock().unwrap();
         let me = &mut *me;
@@ -854,7 +1408,11 @@ impl<B> StreamRef<B> {
 
         me.counts.transition(stream, |counts, stream| {
             actions.send.send_reset(
-                reason, send_buffer, stream, counts, &mut actions.task)
+                reason, send_buffer, stream, counts, &mut actions.task);
+
+            // Frames the peer sent before receiving the reset are ignored
+            // until the stream expires.
+            actions.recv.enqueue_reset_expiration(stream, counts)
         })
     }
 
@@ -872,10 +1430,97 @@ impl<B> StreamRef<B> {
         let send_buffer = &mut *send_buffer;
 
         me.counts.transition(stream, |counts, stream| {
+            let is_success = response.status().is_success();
             let frame = server::Peer::convert_send_message(stream.id, response, end_of_stream);
 
             actions.send.send_headers(
-                frame, send_buffer, stream, counts, &mut actions.task)
+                frame, send_buffer, stream, counts, &mut actions.task)?;
+
+            // A 2xx response to a CONNECT request establishes the tunnel.
+            if is_success {
+                stream.is_pending_tunnel = false;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Queues an informational (1xx) response.
+    pub fn send_informational(&mut self, response: Response<()>) -> Result<(), UserError> {
+        let mut me = self.opaque.inner.lock().unwrap();
+        let me = &mut *me;
+
+        // The 100 (Continue) responses are sent by the library.
+        if response.status() == ::http::StatusCode::CONTINUE &&
+            me.actions.recv.is_auto_100_continue_enabled()
+        {
+            trace!("send_informational; ignoring 100 (Continue) response");
+            return Ok(());
+        }
+
+        let mut stream = me.store.resolve(self.opaque.key);
+        let actions = &mut me.actions;
+        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+        let send_buffer = &mut *send_buffer;
+
+        let frame = server::Peer::convert_send_message(stream.id, response, false);
+
+        actions.send.send_interim_headers(frame, send_buffer, &mut stream, &mut actions.task)
+    }
+
+    /// Reserves a stream for a response pushed by the server, and queues the
+    /// PUSH_PROMISE frame announcing it on this stream.
+    pub fn send_push_promise(&mut self, request: Request<()>) -> Result<StreamRef<B>, ::Error> {
+        let mut me = self.opaque.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+        let send_buffer = &mut *send_buffer;
+
+        me.actions.ensure_no_conn_error()?;
+
+        let parent_id = me.store[self.opaque.key].id;
+
+        // Pushed streams cannot push responses themselves.
+        if me.counts.peer().is_local_init(parent_id) {
+            return Err(UserError::UnexpectedFrameType.into());
+        }
+
+        let promised_id = me.actions.send.ensure_next_stream_id()?;
+        let frame = server::Peer::convert_push_message(parent_id, promised_id, request)?;
+
+        {
+            let mut stream = me.store.resolve(self.opaque.key);
+            let actions = &mut me.actions;
+
+            actions.send.send_push_promise(frame, send_buffer, &mut stream, &mut actions.task)?;
+        }
+
+        me.actions.send.open()?;
+
+        let _enter = me.span.enter();
+
+        let mut stream = me.store.insert(promised_id, Stream::new(
+            promised_id,
+            me.actions.send.init_window_sz(),
+            me.actions.recv.init_window_sz(),
+        ));
+
+        stream.state.reserve_local()?;
+
+        // The frames of the stream are held until the PUSH_PROMISE frame is
+        // written.
+        stream.is_pending_push = true;
+
+        // Increment the stream ref count as we will be returning a handle.
+        stream.ref_inc();
+
+        Ok(StreamRef {
+            opaque: OpaqueStreamRef {
+                inner: self.opaque.inner.clone(),
+                key: stream.key(),
+            },
+            send_buffer: self.send_buffer.clone(),
         })
     }
 
@@ -894,10 +1539,13 @@ impl<B> StreamRef<B> {
         me.actions.recv.take_request(&mut stream)
     }
 
-    /// Called by a client to see if the current stream is pending open
-    pub fn is_pending_open(&self) -> bool {
+    /// Called by the server after taking a malformed request
+    pub fn take_malformed_request(&self) -> ::server::MalformedRequest {
         let mut me = self.opaque.inner.lock().unwrap();
-        me.store.resolve(self.opaque.key).is_pending_open
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.opaque.key);
+        me.actions.recv.take_malformed_request(&mut stream)
     }
 
     /// Request capacity to send data
@@ -950,6 +1598,14 @@ impl<B> StreamRef<B> {
     pub fn stream_id(&self) -> StreamId {
         self.opaque.stream_id()
     }
+
+    pub fn stream_dependency(&self) -> frame::StreamDependency {
+        self.opaque.stream_dependency()
+    }
+
+    pub fn priority_params(&self) -> frame::PriorityParams {
+        self.opaque.priority_params()
+    }
 }
 
 impl<B> Clone for StreamRef<B> {
@@ -992,13 +1648,34 @@ impl OpaqueStreamRef {
         me.actions.recv.is_end_stream(&stream)
     }
 
-    pub fn poll_data(&mut self) -> Poll<Option<Bytes>, proto::Error> {
+    pub fn poll_data(&mut self) -> Poll<Option<(Bytes, ::DataFrameInfo)>, proto::Error> {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
 
         let mut stream = me.store.resolve(self.key);
 
-        me.actions.recv.poll_data(&mut stream)
+        me.actions.recv.poll_data(&mut stream, &mut me.actions.task)
+    }
+
+    /// Called by a client to get the responses pushed on the stream.
+    pub fn poll_pushed(&mut self) -> Poll<Option<(Request<()>, OpaqueStreamRef)>, proto::Error> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+        let pushed = try_ready!(me.actions.recv.poll_pushed(&mut stream));
+
+        Ok(pushed.map(|(request, key)| {
+            // Increment the stream ref count as we will be returning a handle.
+            me.store.resolve(key).ref_inc();
+
+            let opaque = OpaqueStreamRef {
+                inner: self.inner.clone(),
+                key,
+            };
+
+            (request, opaque)
+        }).into())
     }
 
     pub fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, proto::Error> {
@@ -1023,12 +1700,73 @@ impl OpaqueStreamRef {
             .release_capacity(capacity, &mut stream, &mut me.actions.task)
     }
 
+    /// Releases the connection window capacity of data received on the
+    /// stream, with manual flow control.
+    pub fn release_connection_capacity(&mut self, capacity: WindowSize) -> Result<(), UserError> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+
+        me.actions
+            .recv
+            .release_connection_share(capacity, &mut stream, &mut me.actions.task)
+    }
+
     pub fn stream_id(&self) -> StreamId {
         self.inner.lock()
             .unwrap()
             .store[self.key]
             .id
     }
+
+    /// Returns the priority of the stream, as last sent by the peer.
+    pub fn stream_dependency(&self) -> frame::StreamDependency {
+        let me = self.inner.lock().unwrap();
+        let id = me.store[self.key].id;
+
+        me.actions.send.dependencies().get(id)
+    }
+
+    /// Returns the priority parameters of the stream (RFC 9218).
+    pub fn priority_params(&self) -> frame::PriorityParams {
+        let me = self.inner.lock().unwrap();
+        me.store[self.key].priority_params
+    }
+
+    /// Registers a new `ReleaseCapacity` handle for the stream.
+    pub fn inc_release_handles(&self) {
+        let mut me = self.inner.lock().unwrap();
+        let mut stream = me.store.resolve(self.key);
+
+        stream.release_handles += 1;
+    }
+
+    /// Unregisters a `ReleaseCapacity` handle. Once the last one is gone,
+    /// nothing can release the data received on the stream anymore, so all of
+    /// it is released.
+    pub fn dec_release_handles(&self) {
+        let mut me = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => if ::std::thread::panicking() {
+                trace!("dec_release_handles; mutex poisoned");
+                return;
+            } else {
+                panic!("dec_release_handles; mutex poisoned");
+            },
+        };
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+
+        stream.release_handles -= 1;
+
+        if stream.release_handles == 0 {
+            me.actions
+                .recv
+                .release_dropped_capacity(&mut stream, &mut me.actions.task);
+        }
+    }
 }
 
 impl fmt::Debug for OpaqueStreamRef {
@@ -1067,7 +1805,7 @@ impl Drop for OpaqueStreamRef {
 }
 
 // TODO: Move back in fn above
-fn drop_stream_ref(inner: &Mutex<Inner>, key: store::Key) {
+fn drop_stream_ref(inner: &Lock<Inner>, key: store::Key) {
     let mut me = match inner.lock() {
         Ok(inner) => inner,
         Err(_) => if ::std::thread::panicking() {
@@ -1124,6 +1862,122 @@ fn maybe_cancel(stream: &mut store::Ptr, actions: &mut Actions, counts: &mut Cou
     }
 }
 
+// ===== impl AbortHandle =====
+
+impl AbortHandle {
+    /// Asks the connection to abort with `reason` the next time it is
+    /// polled. Only the first request is kept.
+    pub fn abort(&self, reason: Reason, debug_data: Bytes) {
+        let inner = match self.inner.upgrade() {
+            Some(inner) => inner,
+            // The connection is gone already.
+            None => return,
+        };
+
+        let mut me = inner.lock().unwrap();
+
+        if me.pending_abort.is_none() {
+            me.pending_abort = Some((reason, debug_data));
+        }
+
+        if let Some(task) = me.actions.task.take() {
+            task.notify();
+        }
+    }
+}
+
+// ===== impl SettingsChanges =====
+
+impl SettingsChanges {
+    /// Returns the next settings delta received from the peer, or `None` once
+    /// the connection has terminated and all pending deltas were returned.
+    pub fn poll(&mut self) -> Async<Option<frame::Settings>> {
+        let inner = match self.inner.upgrade() {
+            Some(inner) => inner,
+            // The connection is gone already.
+            None => return Async::Ready(None),
+        };
+
+        let mut me = inner.lock().unwrap();
+        let me = &mut *me;
+
+        let watch = me.settings_changes
+            .as_mut()
+            .expect("settings watch not initialized");
+
+        if let Some(settings) = watch.pending.pop_front() {
+            return Async::Ready(Some(settings));
+        }
+
+        if me.actions.conn_error.is_some() {
+            return Async::Ready(None);
+        }
+
+        watch.task = Some(task::current());
+        Async::NotReady
+    }
+}
+
+// ===== impl Inner =====
+
+impl Inner {
+    fn is_dependency_tree_full(&self) -> bool {
+        let len = self.actions.send.dependencies().len();
+        len >= self.store.num_active_streams() + MAX_INACTIVE_PRIORITY_NODES
+    }
+
+    /// Removes closed streams from the dependency tree once it holds too many
+    /// inactive streams.
+    fn prune_dependencies(&mut self) {
+        if !self.is_dependency_tree_full() {
+            return;
+        }
+
+        let store = &mut self.store;
+        let actions = &mut self.actions;
+        let peer = self.counts.peer();
+
+        let closed: Vec<_> = actions.send.dependencies()
+            .ids()
+            .into_iter()
+            .filter(|id| store.find_mut(id).is_none() && actions.ensure_not_idle(peer, *id).is_ok())
+            .collect();
+
+        for id in closed {
+            actions.send.dependencies_mut().remove(id);
+        }
+    }
+
+    fn notify_settings_changes(&mut self) {
+        if let Some(ref mut watch) = self.settings_changes {
+            if let Some(task) = watch.task.take() {
+                task.notify();
+            }
+        }
+    }
+}
+
+// ===== impl SettingsWatch =====
+
+impl SettingsWatch {
+    fn push(&mut self, frame: &frame::Settings) {
+        if self.pending.len() >= MAX_PENDING_SETTINGS_CHANGES {
+            // The consumer is lagging behind; fold the delta into the most
+            // recent pending one so that the latest values always win.
+            self.pending
+                .back_mut()
+                .expect("pending settings changes")
+                .merge(frame);
+        } else {
+            self.pending.push_back(frame.clone());
+        }
+
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
 // ===== impl SendBuffer =====
 
 impl<B> SendBuffer<B> {
@@ -1148,14 +2002,28 @@ impl Actions {
         }) = res
         {
             // Reset the stream.
-            self.send.send_reset(reason, buffer, stream, counts, &mut self.task);
+            self.reset_on_recv_err(reason, buffer, stream, counts);
             Ok(())
         } else {
             res
         }
     }
 
-    fn ensure_not_idle(&mut self, peer: peer::Dyn, id: StreamId) -> Result<(), Reason> {
+    fn reset_on_recv_err<B>(
+        &mut self,
+        reason: Reason,
+        buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        counts: &mut Counts,
+    ) {
+        self.send.send_reset(reason, buffer, stream, counts, &mut self.task);
+
+        if stream.is_malformed {
+            stream.state.set_malformed();
+        }
+    }
+
+    fn ensure_not_idle(&self, peer: peer::Dyn, id: StreamId) -> Result<(), Reason> {
         if peer.is_local_init(id) {
             self.send.ensure_not_idle(id)
         } else {
@@ -1165,7 +2033,7 @@ impl Actions {
 
     fn ensure_no_conn_error(&self) -> Result<(), proto::Error> {
         if let Some(ref err) = self.conn_error {
-            Err(err.shallow_clone())
+            Err(err.clone())
         } else {
             Ok(())
         }
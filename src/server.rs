@@ -33,6 +33,12 @@
 //! The send ([`SendStream`]) and receive ([`RecvStream`]) halves of the stream
 //! can be operated independently.
 //!
+//! A `CONNECT` request asks the server to open a tunnel (section 8.3 of the
+//! HTTP/2.0 specification). Once a 2xx response is sent, both halves of the
+//! stream carry the tunneled bytes, and ending either of them maps to closing
+//! one direction of the tunneled TCP connection. DATA frames received before
+//! the 2xx response reset the stream.
+//!
 //! # Managing the connection
 //!
 //! The [`Connection`] instance is used to manage connection state. The caller
@@ -52,10 +58,30 @@
 //! returns `Ready(None)`, there will no longer be any more inbound streams. At
 //! this point, only [`Connection::poll_close`] should be called.
 //!
+//! # Backpressure
+//!
+//! Requests received while the caller is not accepting are queued until the
+//! next call to [`Connection::poll`]. The size of that queue is bounded by
+//! [`Builder::max_pending_accept_streams`]; requests beyond it are refused
+//! with `REFUSED_STREAM`. Connection level work, such as answering PINGs and
+//! sending WINDOW_UPDATE frames, keeps progressing as long as either
+//! [`Connection::poll`] or [`Connection::poll_close`] is called.
+//!
+//! Combinators such as `for_each` stop polling the connection until the
+//! future returned for a request completes, so that future should not wait on
+//! the connection itself, e.g. to receive the request body. Spawn it on an
+//! executor instead.
+//!
+//! Calling [`Connection::stop_accepting`] refuses the queued requests, as well
+//! as all the ones received afterwards, while the requests already accepted
+//! complete.
+//!
 //! # Shutting down the server
 //!
-//! Graceful shutdown of the server is [not yet
-//! implemented](https://github.com/carllerche/h2/issues/69).
+//! [`Connection::graceful_shutdown`] stops the client from initiating new
+//! requests while letting the ones already in progress complete. Once they
+//! have, the connection closes. [`Connection::abrupt_shutdown`] closes the
+//! connection without waiting for open streams.
 //!
 //! # Example
 //!
@@ -120,26 +146,36 @@
 //! [`handshake`]: fn.handshake.html
 //! [HTTP/2.0 handshake]: http://httpwg.org/specs/rfc7540.html#ConnectionHeader
 //! [`Builder`]: struct.Builder.html
+//! [`Builder::max_pending_accept_streams`]: struct.Builder.html#method.max_pending_accept_streams
 //! [`Connection`]: struct.Connection.html
 //! [`Connection::poll`]: struct.Connection.html#method.poll
 //! [`Connection::poll_close`]: struct.Connection.html#method.poll_close
+//! [`Connection::stop_accepting`]: struct.Connection.html#method.stop_accepting
+//! [`Connection::graceful_shutdown`]: struct.Connection.html#method.graceful_shutdown
+//! [`Connection::abrupt_shutdown`]: struct.Connection.html#method.abrupt_shutdown
 //! [`futures::Stream`]: https://docs.rs/futures/0.1/futures/stream/trait.Stream.html
 //! [`http::Request<RecvStream>`]: ../struct.RecvStream.html
 //! [`RecvStream`]: ../struct.RecvStream.html
 //! [`SendStream`]: ../struct.SendStream.html
 //! [`TcpListener`]: https://docs.rs/tokio-core/0.1/tokio_core/net/struct.TcpListener.html
 
-use {SendStream, RecvStream, ReleaseCapacity};
-use codec::{Codec, RecvError};
-use frame::{self, Reason, Settings, StreamId};
+use {FlowControlMode, PeerSettings, SendStream, RecvStream, ReleaseCapacity};
+use {AbortHandle, ConnectionStats, Direction, ExtensionFrame, FlushStrategy, FrameView, PingPong, Protocol, SettingsAcked, SettingsChanges, SettingsUpdate};
+use codec::{self, Codec, Padding, UserError};
+use error::HandshakeError;
+use frame::{self, SettingsBuilder, Reason, Settings, StreamId};
+use h2c;
 use proto::{self, Config, Prioritized};
+use timer::{self, Timer};
 
 use bytes::{Buf, Bytes, IntoBuf};
 use futures::{self, Async, Future, Poll};
-use http::{Request, Response};
-use std::{convert, fmt, io, mem};
-use std::time::Duration;
-use tokio_io::{AsyncRead, AsyncWrite};
+use http::{header, Request, Response};
+use http::header::HeaderName;
+use std::{cmp, convert, fmt, mem, u16, u32};
+use std::time::{Duration, Instant};
+use tokio_io::{self, AsyncRead, AsyncWrite};
+use tokio_io::io::WriteAll;
 
 /// In progress HTTP/2.0 connection handshake future.
 ///
@@ -160,7 +196,14 @@ pub struct Handshake<T, B: IntoBuf = Bytes> {
     /// The config to pass to Connection::new after handshake succeeds.
     builder: Builder,
     /// The current state of the handshake.
-    state: Handshaking<T, B>
+    state: Handshaking<T, B>,
+    /// The HTTP/1.1 request the connection is upgraded from, and the settings
+    /// it carried.
+    upgrade: Option<(frame::Headers, Settings)>,
+    /// Instant by which the client preface must be received.
+    deadline: Option<Instant>,
+    /// Wakes the handshake up once the deadline passed.
+    timer: Timer,
 }
 
 /// Accepts inbound HTTP/2.0 streams on a connection.
@@ -209,6 +252,8 @@ pub struct Handshake<T, B: IntoBuf = Bytes> {
 #[must_use = "streams do nothing unless polled"]
 pub struct Connection<T, B: IntoBuf> {
     connection: proto::Connection<T, Peer, B>,
+    /// Cleared by `stop_accepting`
+    is_accepting: bool,
 }
 
 /// Builds server connections with custom configuration values.
@@ -253,14 +298,146 @@ pub struct Builder {
     /// Time to keep locally reset streams around before reaping.
     reset_stream_duration: Duration,
 
+    /// Maximum time to wait for the peer to acknowledge our SETTINGS frame.
+    settings_ack_timeout: Option<Duration>,
+
+    /// Whether SETTINGS updates queued before the previous one was written
+    /// are merged into it.
+    coalesce_settings_updates: bool,
+
+    /// Maximum time to wait for the client connection preface, including
+    /// its initial SETTINGS frame.
+    handshake_timeout: Option<Duration>,
+
+    /// Idle duration after which a keep-alive PING is sent.
+    keep_alive_interval: Option<Duration>,
+
+    /// Maximum time to wait for a keep-alive PING to be acknowledged.
+    keep_alive_timeout: Duration,
+
+    /// Maximum number of PINGs accepted from the peer per `ping_interval`.
+    max_pings_per_interval: Option<usize>,
+
+    /// Interval over which received PINGs are counted.
+    ping_interval: Duration,
+
+    /// Maximum time to wait before sending the final GOAWAY of a graceful
+    /// shutdown.
+    graceful_shutdown_timeout: Option<Duration>,
+
+    /// Time after which a connection without active streams is closed.
+    idle_timeout: Option<Duration>,
+
+    /// Time after which a connection is shut down gracefully.
+    max_connection_age: Option<Duration>,
+
+    /// Time given to the active streams to complete once the connection
+    /// reached its maximum age.
+    max_connection_age_grace: Option<Duration>,
+
     /// Maximum number of locally reset streams to keep at a time.
     reset_stream_max: usize,
 
+    /// Maximum number of streams the peer may reset before a response was
+    /// sent, per `remote_reset_stream_duration`.
+    remote_reset_stream_max: usize,
+
+    /// Interval over which streams reset by the peer are counted.
+    remote_reset_stream_duration: Duration,
+
+    /// Maximum number of received streams waiting to be accepted.
+    max_pending_accept_streams: Option<usize>,
+
+    /// Maximum number of bytes of received data buffered per stream before
+    /// window updates are held back.
+    max_buffered_recv_bytes_per_stream: Option<usize>,
+
+    /// Maximum time a stream may buffer too much received data.
+    buffered_recv_timeout: Option<Duration>,
+
     /// Initial `Settings` frame to send as part of the handshake.
-    settings: Settings,
+    settings: SettingsBuilder,
+
+    /// Local cap on the header table size used to encode the header blocks
+    /// sent to the peer.
+    max_send_header_table_size: Option<usize>,
+
+    /// Maximum size of a received header block, across CONTINUATION frames.
+    max_header_block_size: Option<usize>,
+
+    /// Maximum size of a sent header block, across CONTINUATION frames.
+    max_send_header_block_size: Option<usize>,
+
+    /// Maximum number of CONTINUATION frames in a received header block.
+    max_continuation_frames: Option<usize>,
+
+    /// Whether header fields forbidden in HTTP/2 make a received header block
+    /// malformed.
+    strict_header_validation: bool,
+
+    /// Maximum number of parameters accepted in a received SETTINGS frame.
+    max_settings_entries: usize,
+
+    /// Maximum number of bytes of debug data kept from a received GOAWAY
+    /// frame.
+    max_go_away_debug_data: usize,
+
+    /// The stream ID of the first (lowest) pushed stream. Subsequent pushed
+    /// streams will use monotonically increasing stream IDs.
+    stream_id: StreamId,
+
+    /// Called with extension settings received from the peer.
+    on_unknown_setting: Option<proto::OnUnknownSetting>,
+
+    /// Types of the extension frames passed to `on_extension_frame`.
+    extension_types: Vec<u8>,
+
+    /// Maximum payload size of a received extension frame.
+    max_extension_frame_size: usize,
+
+    /// Called with the extension frames received from the peer.
+    on_extension_frame: Option<proto::OnExtensionFrame>,
+
+    /// Chooses the padding of the sent DATA and HEADERS frames.
+    padding: Option<Padding>,
+
+    /// Called with the frames sent and received.
+    frame_tap: Option<codec::FrameTap>,
+
+    /// Size of the buffer the frames to send are written to.
+    write_buffer_size: usize,
+
+    /// When the buffered frames are written to the socket.
+    flush_strategy: FlushStrategy,
+
+    /// Header fields whose values are sent as never indexed literals.
+    sensitive_headers: Vec<HeaderName>,
+
+    /// Whether 100 (Continue) responses are sent automatically.
+    auto_100_continue: bool,
+
+    /// Whether malformed requests are returned by
+    /// `Connection::poll_malformed_request` instead of being reset.
+    accept_malformed_requests: bool,
 
     /// Initial target window size for new connections.
     initial_target_connection_window_size: Option<u32>,
+
+    /// Percentage of the target window released before a WINDOW_UPDATE
+    /// frame is sent.
+    window_update_threshold: u8,
+
+    /// How the connection level receive window is released.
+    flow_control_mode: FlowControlMode,
+
+    /// Duration after which exhausted receive windows are reported.
+    window_stall_warning: Option<Duration>,
+
+    /// Whether receive windows are sized from the bandwidth-delay product.
+    adaptive_window: bool,
+
+    /// Maximum size adaptive receive windows grow to.
+    max_adaptive_window_size: u32,
 }
 
 /// Send a response back to the client
@@ -269,9 +446,8 @@ pub struct Builder {
 /// to send the associated response back to the client. It is also used to
 /// explicitly reset the stream with a custom reason.
 ///
-/// It will also be used to initiate push promises linked with the associated
-/// stream. This is [not yet
-/// implemented](https://github.com/carllerche/h2/issues/185).
+/// It is also used to push responses linked with the associated stream, see
+/// [`push_request`].
 ///
 /// If the `SendResponse` instance is dropped without sending a response, then
 /// the HTTP/2.0 stream will be reset.
@@ -279,13 +455,66 @@ pub struct Builder {
 /// See [module] level docs for more details.
 ///
 /// [module]: index.html
+/// [`push_request`]: #method.push_request
 #[derive(Debug)]
 pub struct SendResponse<B: IntoBuf> {
     inner: proto::StreamRef<B::Buf>,
 }
 
+/// The head of a malformed request
+///
+/// A `MalformedRequest` is returned by [`Connection::poll_malformed_request`]
+/// when [`Builder::accept_malformed_requests`] is enabled. The pseudo-header
+/// fields are exposed as received, since they may not form a valid request.
+///
+/// [`Connection::poll_malformed_request`]: struct.Connection.html#method.poll_malformed_request
+/// [`Builder::accept_malformed_requests`]: struct.Builder.html#method.accept_malformed_requests
+#[derive(Debug)]
+pub struct MalformedRequest {
+    pseudo: frame::Pseudo,
+    headers: header::HeaderMap,
+    reason: &'static str,
+}
+
+/// Send a response to a malformed request
+///
+/// A `SendMalformedResponse` instance is returned along with a
+/// [`MalformedRequest`], and is used to answer it with a client error (4xx)
+/// response.
+///
+/// If the `SendMalformedResponse` instance is dropped without sending a
+/// response, then the stream is reset with `PROTOCOL_ERROR`.
+///
+/// [`MalformedRequest`]: struct.MalformedRequest.html
+#[derive(Debug)]
+pub struct SendMalformedResponse<B: IntoBuf> {
+    inner: proto::StreamRef<B::Buf>,
+    /// Set once a response or a reset was sent.
+    is_done: bool,
+}
+
+/// Send a pushed response back to the client
+///
+/// A `SendPushedResponse` instance is returned by
+/// [`SendResponse::push_request`] and is used to send the response to the
+/// pushed request, or to reset the reserved stream.
+///
+/// If the `SendPushedResponse` instance is dropped without sending a response,
+/// then the reserved stream will be reset.
+///
+/// [`SendResponse::push_request`]: struct.SendResponse.html#method.push_request
+pub struct SendPushedResponse<B: IntoBuf> {
+    inner: SendResponse<B>,
+}
+
 /// Stages of an in-progress handshake.
 enum Handshaking<T, B: IntoBuf> {
+    /// State 0. Connection is writing the 101 response to an HTTP/1.1 upgrade
+    /// request.
+    SendingUpgradeResponse(WriteAll<T, &'static [u8]>),
+    /// The settings of the builder are invalid, or the HTTP/1.1 upgrade
+    /// request was rejected.
+    Failed(Option<UserError>),
     /// State 1. Connection is flushing pending SETTINGS frame.
     Flushing(Flush<T, Prioritized<B::Buf>>),
     /// State 2. Connection is waiting for the client preface.
@@ -310,6 +539,10 @@ pub(crate) struct Peer;
 
 const PREFACE: [u8; 24] = *b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
+/// The longest :path accepted in a request, which is the longest URI the
+/// `http` crate can represent.
+const MAX_PATH_LEN: usize = u16::MAX as usize - 1;
+
 /// Creates a new configured HTTP/2.0 server with default configuration
 /// values backed by `io`.
 ///
@@ -363,26 +596,92 @@ where
     B: IntoBuf,
 {
     fn handshake2(io: T, builder: Builder) -> Handshake<T, B> {
+        // Create the handshake future. Nothing is written to `io` if the
+        // settings are invalid.
+        let state = match builder.initial_settings() {
+            Ok(_) => Handshaking::from(Connection::<T, B>::new_codec(io, &builder)),
+            Err(e) => Handshaking::Failed(Some(e)),
+        };
+
+        let deadline = builder.handshake_deadline();
+        Handshake { builder, state, upgrade: None, deadline, timer: Timer::default() }
+    }
+
+    fn handshake_upgraded2(io: T, builder: Builder, request: Request<()>) -> Handshake<T, B> {
+        let upgrade = builder.initial_settings()
+            .and_then(|_| h2c::decode_settings(request.headers(), builder.max_settings_entries))
+            .and_then(|settings| {
+                Peer::convert_upgrade_request(request).map(|request| (request, settings))
+            });
+
+        // Nothing is written to `io` if the request is rejected.
+        let (state, upgrade) = match upgrade {
+            Ok(upgrade) => {
+                let write = tokio_io::io::write_all(io, h2c::UPGRADE_RESPONSE);
+                (Handshaking::SendingUpgradeResponse(write), Some(upgrade))
+            },
+            Err(e) => {
+                debug!("rejecting h2c upgrade; err={:?}", e);
+                (Handshaking::Failed(Some(e)), None)
+            },
+        };
+
+        let deadline = builder.handshake_deadline();
+        Handshake { builder, state, upgrade, deadline, timer: Timer::default() }
+    }
+
+    /// Creates the codec, with the initial settings frame buffered.
+    fn new_codec(io: T, builder: &Builder) -> Codec<T, Prioritized<B::Buf>> {
         // Create the codec.
         let mut codec = Codec::new(io);
 
-        if let Some(max) = builder.settings.max_frame_size() {
+        // Checked when the handshake started.
+        let settings = builder.initial_settings().expect("invalid settings");
+
+        if let Some(max) = settings.max_frame_size() {
             codec.set_max_recv_frame_size(max as usize);
         }
 
-        if let Some(max) = builder.settings.max_header_list_size() {
+        if let Some(max) = settings.max_header_list_size() {
             codec.set_max_recv_header_list_size(max as usize);
         }
 
+        if let Some(max) = builder.max_send_header_table_size {
+            codec.set_max_send_header_table_size(max);
+        }
+
+        if let Some(max) = builder.max_header_block_size {
+            codec.set_max_recv_header_block_size(max);
+        }
+
+        if let Some(max) = builder.max_continuation_frames {
+            codec.set_max_recv_continuation_frames(max);
+        }
+
+        codec.set_strict_header_validation(builder.strict_header_validation);
+        codec.set_max_recv_settings_entries(builder.max_settings_entries);
+        codec.set_max_recv_go_away_debug_data(builder.max_go_away_debug_data);
+        codec.set_recv_extension_types(builder.extension_types.clone());
+        codec.set_max_recv_extension_frame_size(builder.max_extension_frame_size);
+
+        if let Some(ref padding) = builder.padding {
+            codec.set_send_padding(padding.clone());
+        }
+
+        if let Some(ref tap) = builder.frame_tap {
+            codec.set_frame_tap(tap.clone());
+        }
+
+        codec.set_send_buffer_size(builder.write_buffer_size);
+        codec.set_flush_strategy(builder.flush_strategy);
+        codec.set_send_sensitive_headers(builder.sensitive_headers.clone());
+
         // Send initial settings frame.
         codec
-            .buffer(builder.settings.clone().into())
+            .buffer(settings.into())
             .expect("invalid SETTINGS frame");
 
-        // Create the handshake future.
-        let state = Handshaking::from(codec);
-
-        Handshake { builder, state }
+        codec
     }
 
     /// Sets the target window size for the whole connection.
@@ -407,6 +706,152 @@ where
         self.connection.set_target_window_size(size);
     }
 
+    /// Sets the max size of the header table used to compress the header
+    /// blocks sent to the peer.
+    ///
+    /// The next header block sent starts with a dynamic table size update
+    /// when the size of the table changes. See
+    /// [`Builder::max_send_header_table_size`] for more details.
+    ///
+    /// [`Builder::max_send_header_table_size`]: struct.Builder.html#method.max_send_header_table_size
+    pub fn set_max_send_header_table_size(&mut self, size: u32) {
+        self.connection.set_max_send_header_table_size(size as usize);
+    }
+
+    /// Takes the [`PingPong`] handle used to send PING frames to the client.
+    ///
+    /// Only the first call returns a handle, later calls return `None`.
+    ///
+    /// [`PingPong`]: ../struct.PingPong.html
+    pub fn ping_pong(&mut self) -> Option<PingPong> {
+        self.connection.take_user_pings().map(PingPong::new)
+    }
+
+    /// Sends a frame of a type unknown to this library, as defined by an
+    /// extension (section 5.5 of [RFC 7540]).
+    ///
+    /// The frame is sent on `stream_id`, or on the connection (stream 0) when
+    /// `stream_id` is `None`. The flags and the payload are sent as given.
+    /// The client ignores the frame unless it supports the extension.
+    ///
+    /// The connection must be polled for the frame to be sent.
+    ///
+    /// [RFC 7540]: https://tools.ietf.org/html/rfc7540#section-5.5
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `kind` is a frame type defined by this library, or
+    /// if the payload is larger than the maximum frame size of the client.
+    pub fn send_extension_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: Option<::StreamId>,
+        payload: Bytes,
+    ) -> Result<(), ::Error> {
+        if frame::is_known_kind(kind) {
+            return Err(UserError::InvalidExtensionFrame.into());
+        }
+
+        let stream_id = stream_id
+            .map(::StreamId::into_internal)
+            .unwrap_or(StreamId::zero());
+        let frame = frame::Extension::new(kind, flags, stream_id, payload);
+        self.connection.send_extension_frame(frame).map_err(Into::into)
+    }
+
+    /// Returns the round-trip time to the client, smoothed over the PING
+    /// frames sent on the connection.
+    ///
+    /// Returns `None` until a PING sent by the connection, such as a
+    /// keep-alive PING, has been acknowledged.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.connection.rtt()
+    }
+
+    /// Returns a snapshot of the settings received from the client.
+    ///
+    /// The snapshot reflects every SETTINGS frame received so far. Before the
+    /// client's first SETTINGS frame has been processed, all values are
+    /// reported with their protocol defaults.
+    ///
+    /// See [`PeerSettings`] for more details.
+    ///
+    /// [`PeerSettings`]: ../struct.PeerSettings.html
+    pub fn peer_settings(&self) -> PeerSettings {
+        PeerSettings::new(self.connection.remote_settings())
+    }
+
+    /// Returns a stream yielding the SETTINGS frames received from the client
+    /// from now on.
+    ///
+    /// The connection must still be polled for the updates to be received.
+    /// See [`SettingsChanges`] for more details.
+    ///
+    /// [`SettingsChanges`]: ../struct.SettingsChanges.html
+    pub fn settings_changes(&self) -> SettingsChanges {
+        SettingsChanges::new(self.connection.settings_changes())
+    }
+
+    /// Sends a SETTINGS frame with the values included in `settings`.
+    ///
+    /// The returned future completes once the client acknowledges the frame, at
+    /// which point the new values are put into effect. Each call results in
+    /// its own acknowledgement; updates queued before the connection had the
+    /// opportunity to write the previous one are coalesced into a single
+    /// frame, unless disabled with `Builder::coalesce_settings_updates`.
+    ///
+    /// The connection must be polled for the frame to be sent and the
+    /// acknowledgement to be received.
+    pub fn update_settings(&mut self, settings: SettingsUpdate) -> SettingsAcked {
+        let (settings, reset) = settings.into_parts();
+        SettingsAcked::new(self.connection.update_settings(settings, &reset))
+    }
+
+    /// Changes the maximum number of concurrent streams the client is
+    /// permitted to open.
+    ///
+    /// A SETTINGS frame carrying the new value is sent to the client. Once
+    /// the client acknowledges it, which is when the returned future
+    /// completes, new streams exceeding the limit are refused with
+    /// `REFUSED_STREAM`. Streams that are already open are allowed to
+    /// complete, even if their number exceeds the new limit.
+    ///
+    /// Passing `None` removes the limit.
+    pub fn set_max_concurrent_streams(&mut self, max: Option<u32>) -> SettingsAcked {
+        let mut settings = SettingsUpdate::new();
+        settings.set_max_concurrent_streams(max.unwrap_or(u32::MAX));
+        self.update_settings(settings)
+    }
+
+    /// Advertises alternative services for `origin` by sending an ALTSVC
+    /// frame on the connection ([RFC 7838]).
+    ///
+    /// `origin` is the ASCII serialization of an origin, such as
+    /// `https://example.com`, and `field_value` lists the alternative
+    /// services using the syntax of the `Alt-Svc` header field, for example
+    /// `h2="alt.example.com:443"; ma=3600`. The value `clear` invalidates the
+    /// alternative services previously advertised for the origin.
+    ///
+    /// The connection must be polled for the frame to be sent.
+    ///
+    /// [RFC 7838]: https://tools.ietf.org/html/rfc7838
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `origin` or `field_value` is empty, or if the frame
+    /// would be larger than the smallest maximum frame size.
+    pub fn send_alt_svc(&mut self, origin: Bytes, field_value: Bytes) -> Result<(), ::Error> {
+        if origin.is_empty() {
+            return Err(UserError::InvalidAltSvc.into());
+        }
+
+        check_alt_svc(&origin, &field_value)?;
+        self.connection.send_alt_svc(origin, field_value);
+
+        Ok(())
+    }
+
     /// Returns `Ready` when the underlying connection has closed.
     ///
     /// If any new inbound streams are received during a call to `poll_close`,
@@ -421,7 +866,86 @@ where
     /// [`RecvStream`]: ../struct.RecvStream.html
     /// [`SendStream`]: ../struct.SendStream.html
     pub fn poll_close(&mut self) -> Poll<(), ::Error> {
-        self.connection.poll().map_err(Into::into)
+        self.connection.check_lifetime();
+
+        let res = self.connection.poll().map_err(Into::into);
+
+        if !self.is_accepting {
+            self.refuse_pending();
+        }
+
+        res
+    }
+
+    /// Stops accepting requests.
+    ///
+    /// Requests received from now on, as well as the ones received earlier
+    /// but not returned by [`poll`] yet, are refused with `REFUSED_STREAM`,
+    /// so the client can safely retry them. Requests that were already
+    /// returned are not affected.
+    ///
+    /// [`poll`] returns `None` after this call. The connection must still be
+    /// driven with [`poll_close`] until it closes, which happens once the
+    /// client closes it, or after a [`graceful_shutdown`].
+    ///
+    /// [`poll`]: #method.poll
+    /// [`poll_close`]: #method.poll_close
+    /// [`graceful_shutdown`]: #method.graceful_shutdown
+    pub fn stop_accepting(&mut self) {
+        self.is_accepting = false;
+        self.refuse_pending();
+    }
+
+    fn refuse_pending(&mut self) {
+        while let Some(mut stream) = self.connection.next_incoming() {
+            trace!("refusing request; not accepting");
+            stream.send_reset(Reason::REFUSED_STREAM);
+        }
+
+        while let Some(mut stream) = self.connection.next_malformed() {
+            trace!("refusing malformed request; not accepting");
+            stream.send_reset(Reason::REFUSED_STREAM);
+        }
+    }
+
+    /// Returns the next malformed request received on the connection.
+    ///
+    /// Malformed requests are only returned when
+    /// [`Builder::accept_malformed_requests`] is enabled. Each request comes
+    /// with a [`SendMalformedResponse`], used to answer it with a client
+    /// error (4xx) response.
+    ///
+    /// Like [`poll`], this function drives the connection, and returns
+    /// `None` once the connection is closed. Both functions must be polled by
+    /// the same task, so that it is notified when either kind of request is
+    /// received.
+    ///
+    /// [`Builder::accept_malformed_requests`]: struct.Builder.html#method.accept_malformed_requests
+    /// [`SendMalformedResponse`]: struct.SendMalformedResponse.html
+    /// [`poll`]: #method.poll
+    pub fn poll_malformed_request(
+        &mut self,
+    ) -> Poll<Option<(MalformedRequest, SendMalformedResponse<B>)>, ::Error> {
+        if let Async::Ready(_) = self.poll_close()? {
+            return Ok(None.into());
+        }
+
+        if !self.is_accepting {
+            return Ok(None.into());
+        }
+
+        if let Some(inner) = self.connection.next_malformed() {
+            trace!("received malformed request");
+            let request = inner.take_malformed_request();
+            let respond = SendMalformedResponse {
+                inner,
+                is_done: false,
+            };
+
+            return Ok(Some((request, respond)).into());
+        }
+
+        Ok(Async::NotReady)
     }
 
     #[deprecated(note="use abrupt_shutdown or graceful_shutdown instead", since="0.1.4")]
@@ -441,20 +965,73 @@ where
     /// external to `h2` has happened, and open streams cannot be properly
     /// handled.
     ///
+    /// Active streams fail with an error carrying `reason`.
+    ///
     /// For graceful shutdowns, see [`graceful_shutdown`](Connection::graceful_shutdown).
     pub fn abrupt_shutdown(&mut self, reason: Reason) {
-        self.connection.go_away_now(reason);
+        self.connection.abort(reason, Bytes::new());
+    }
+
+    /// Sets the connection to a GOAWAY state, including `debug_data` in the
+    /// GOAWAY frame.
+    ///
+    /// This behaves like [`abrupt_shutdown`](Connection::abrupt_shutdown).
+    /// The debug data is opaque to the client and is meant to help diagnose
+    /// why the connection was closed. Debug data that does not fit in a
+    /// single frame of the minimum size (16,376 bytes) is truncated.
+    pub fn abrupt_shutdown_with_debug_data(&mut self, reason: Reason, debug_data: Bytes) {
+        self.connection.abort(reason, debug_data);
+    }
+
+    /// Aborts the connection with a GOAWAY frame carrying `reason` and
+    /// `debug_data`.
+    ///
+    /// This is the same as [`abrupt_shutdown_with_debug_data`]. All active
+    /// streams fail with an error carrying `reason`, for which `is_go_away`
+    /// and `is_local` return true. The connection must continue being
+    /// polled, and completes once the GOAWAY frame is flushed.
+    ///
+    /// To abort the connection from a stream handler running on another
+    /// task, use an [`AbortHandle`] instead.
+    ///
+    /// [`abrupt_shutdown_with_debug_data`]: #method.abrupt_shutdown_with_debug_data
+    /// [`AbortHandle`]: ../struct.AbortHandle.html
+    pub fn abort(&mut self, reason: Reason, debug_data: &[u8]) {
+        self.connection.abort(reason, Bytes::from(debug_data));
+    }
+
+    /// Returns a handle aborting the connection from any task.
+    ///
+    /// See [`AbortHandle`] for more details.
+    ///
+    /// [`AbortHandle`]: ../struct.AbortHandle.html
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle::new(self.connection.abort_handle())
+    }
+
+    /// Returns a snapshot of the statistics of the connection.
+    ///
+    /// This reads counters kept up to date as the connection is polled, and
+    /// does not poll the connection itself.
+    pub fn stats(&self) -> ConnectionStats {
+        self.connection.stats()
     }
 
     /// Starts a [graceful shutdown][1] process.
     ///
     /// Must continue being polled to close connection.
     ///
-    /// It's possible to receive more requests after calling this method, since
-    /// they might have been in-flight from the client already. After about
-    /// 1 RTT, no new requests should be accepted. Once all active streams
+    /// A GOAWAY frame with the maximum stream ID is sent first, telling the
+    /// client not to initiate new requests, along with a PING. It's possible
+    /// to receive more requests after calling this method, since they might
+    /// have been in-flight from the client already. Once the PING is
+    /// acknowledged, or the [`graceful_shutdown_timeout`] elapses, a second
+    /// GOAWAY announces the last stream that will be processed. Requests on
+    /// streams above it are refused with `REFUSED_STREAM`, so the client can
+    /// safely retry them on another connection. Once all active streams
     /// have completed, the connection is closed.
     ///
+    /// [`graceful_shutdown_timeout`]: struct.Builder.html#method.graceful_shutdown_timeout
     /// [1]: http://httpwg.org/specs/rfc7540.html#GOAWAY
     pub fn graceful_shutdown(&mut self) {
         self.connection.go_away_gracefully();
@@ -482,6 +1059,10 @@ where
             _ => {},
         }
 
+        if !self.is_accepting {
+            return Ok(None.into());
+        }
+
         if let Some(inner) = self.connection.next_incoming() {
             trace!("received incoming");
             let (head, _) = inner.take_request().into_parts();
@@ -506,6 +1087,7 @@ where
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Connection")
             .field("connection", &self.connection)
+            .field("is_accepting", &self.is_accepting)
             .finish()
     }
 }
@@ -544,8 +1126,52 @@ impl Builder {
         Builder {
             reset_stream_duration: Duration::from_secs(proto::DEFAULT_RESET_STREAM_SECS),
             reset_stream_max: proto::DEFAULT_RESET_STREAM_MAX,
-            settings: Settings::default(),
+            remote_reset_stream_max: proto::DEFAULT_REMOTE_RESET_STREAM_MAX,
+            remote_reset_stream_duration: Duration::from_secs(proto::DEFAULT_REMOTE_RESET_STREAM_SECS),
+            max_pending_accept_streams: None,
+            max_buffered_recv_bytes_per_stream: None,
+            buffered_recv_timeout: None,
+            settings: Settings::builder(),
+            max_send_header_table_size: None,
+            max_header_block_size: None,
+            max_send_header_block_size: None,
+            max_continuation_frames: None,
+            strict_header_validation: true,
+            max_settings_entries: frame::DEFAULT_MAX_SETTINGS_ENTRIES,
+            max_go_away_debug_data: frame::DEFAULT_MAX_GO_AWAY_DEBUG_DATA,
+            stream_id: 2.into(),
             initial_target_connection_window_size: None,
+            window_update_threshold: proto::DEFAULT_WINDOW_UPDATE_THRESHOLD,
+            flow_control_mode: FlowControlMode::Auto,
+            window_stall_warning: None,
+            adaptive_window: false,
+            max_adaptive_window_size: proto::DEFAULT_MAX_ADAPTIVE_WINDOW_SIZE,
+            settings_ack_timeout: None,
+            coalesce_settings_updates: true,
+            handshake_timeout: None,
+            keep_alive_interval: None,
+            keep_alive_timeout: Duration::from_secs(proto::DEFAULT_KEEP_ALIVE_TIMEOUT_SECS),
+            max_pings_per_interval: None,
+            ping_interval: Duration::from_secs(proto::DEFAULT_PING_INTERVAL_SECS),
+            graceful_shutdown_timeout: None,
+            idle_timeout: None,
+            max_connection_age: None,
+            max_connection_age_grace: None,
+            on_unknown_setting: None,
+            extension_types: Vec::new(),
+            max_extension_frame_size: frame::DEFAULT_MAX_FRAME_SIZE as usize,
+            on_extension_frame: None,
+            padding: None,
+            frame_tap: None,
+            write_buffer_size: codec::DEFAULT_BUFFER_CAPACITY,
+            flush_strategy: FlushStrategy::Adaptive,
+            sensitive_headers: vec![
+                header::AUTHORIZATION,
+                header::COOKIE,
+                header::PROXY_AUTHORIZATION,
+            ],
+            auto_100_continue: false,
+            accept_malformed_requests: false,
         }
     }
 
@@ -580,8 +1206,13 @@ impl Builder {
     /// #
     /// # pub fn main() {}
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// The handshake fails if `size` is greater than 2^31-1, the largest
+    /// window size allowed by HTTP/2.0.
     pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
-        self.settings.set_initial_window_size(Some(size));
+        self.settings.initial_window_size(size);
         self
     }
 
@@ -591,8 +1222,17 @@ impl Builder {
     /// The initial window of a connection is used as part of flow control. For more details,
     /// see [`ReleaseCapacity`].
     ///
+    /// Connections always start with a window of 65,535 octets, so a larger
+    /// value is announced with a `WINDOW_UPDATE` frame as soon as the
+    /// handshake completes.
+    ///
     /// The default value is 65,535.
     ///
+    /// # Panics
+    ///
+    /// This function panics if `size` is greater than 2^31-1, the largest
+    /// window size allowed by HTTP/2.0.
+    ///
     /// [`ReleaseCapacity`]: ../struct.ReleaseCapacity.html
     ///
     /// # Examples
@@ -617,18 +1257,28 @@ impl Builder {
     /// # pub fn main() {}
     /// ```
     pub fn initial_connection_window_size(&mut self, size: u32) -> &mut Self {
+        assert!(size <= proto::MAX_WINDOW_SIZE);
         self.initial_target_connection_window_size = Some(size);
         self
     }
 
-    /// Indicates the size (in octets) of the largest HTTP/2.0 frame payload that the
-    /// configured server is able to accept.
+    /// Sets how much of the receive window must be released before it is
+    /// announced to the peer, as a percentage of the target window.
     ///
-    /// The sender may send data frames that are **smaller** than this value,
-    /// but any data larger than `max` will be broken up into multiple `DATA`
-    /// frames.
+    /// Capacity released with [`ReleaseCapacity`] is not announced right
+    /// away. WINDOW_UPDATE frames are only sent once the released and not yet
+    /// announced capacity of the connection, or of a stream, reaches this
+    /// percentage of its target window, which avoids sending one for nearly
+    /// every DATA frame received. A lower value keeps the peer's window
+    /// fuller at the cost of more frames. With 0, every release is announced.
     ///
-    /// The value **must** be between 16,384 and 16,777,215. The default value is 16,384.
+    /// The default value is 50.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `percent` is greater than 100.
+    ///
+    /// [`ReleaseCapacity`]: ../struct.ReleaseCapacity.html
     ///
     /// # Examples
     ///
@@ -644,32 +1294,29 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_frame_size(1_000_000)
+    ///     .window_update_threshold(25)
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    ///
-    /// # Panics
-    ///
-    /// This function panics if `max` is not within the legal range specified
-    /// above.
-    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_frame_size(Some(max));
+    pub fn window_update_threshold(&mut self, percent: u8) -> &mut Self {
+        assert!(percent <= 100, "window update threshold is a percentage");
+        self.window_update_threshold = percent;
         self
     }
 
-    /// Sets the max size of received header frames.
+    /// Sets how the connection level receive window is released.
     ///
-    /// This advisory setting informs a peer of the maximum size of header list
-    /// that the sender is prepared to accept, in octets. The value is based on
-    /// the uncompressed size of header fields, including the length of the name
-    /// and value in octets plus an overhead of 32 octets for each header field.
+    /// With [`FlowControlMode::Manual`], releasing capacity with
+    /// [`ReleaseCapacity::release_capacity`] only increments the window of
+    /// the stream, and the connection window is incremented with
+    /// [`ReleaseCapacity::release_connection_capacity`]. No WINDOW_UPDATE
+    /// frame is then sent for received data the application did not
+    /// release, on the stream or on the connection.
     ///
-    /// This setting is also used to limit the maximum amount of data that is
-    /// buffered to decode HEADERS frames.
+    /// The default value is [`FlowControlMode::Auto`].
     ///
     /// # Examples
     ///
@@ -678,6 +1325,7 @@ impl Builder {
     /// # extern crate tokio_io;
     /// # use tokio_io::*;
     /// # use h2::server::*;
+    /// use h2::FlowControlMode;
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
     /// # -> Handshake<T>
@@ -685,41 +1333,32 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_header_list_size(16 * 1024)
+    ///     .flow_control_mode(FlowControlMode::Manual)
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_header_list_size(Some(max));
+    ///
+    /// [`FlowControlMode::Manual`]: ../enum.FlowControlMode.html#variant.Manual
+    /// [`FlowControlMode::Auto`]: ../enum.FlowControlMode.html#variant.Auto
+    /// [`ReleaseCapacity::release_capacity`]: ../struct.ReleaseCapacity.html#method.release_capacity
+    /// [`ReleaseCapacity::release_connection_capacity`]: ../struct.ReleaseCapacity.html#method.release_connection_capacity
+    pub fn flow_control_mode(&mut self, mode: FlowControlMode) -> &mut Self {
+        self.flow_control_mode = mode;
         self
     }
 
-    /// Sets the maximum number of concurrent streams.
-    ///
-    /// The maximum concurrent streams setting only controls the maximum number
-    /// of streams that can be initiated by the remote peer. In other words,
-    /// when this setting is set to 100, this does not limit the number of
-    /// concurrent streams that can be created by the caller.
-    ///
-    /// It is recommended that this value be no smaller than 100, so as to not
-    /// unnecessarily limit parallelism. However, any value is legal, including
-    /// 0. If `max` is set to 0, then the remote will not be permitted to
-    /// initiate streams.
-    ///
-    /// Note that streams in the reserved state, i.e., push promises that have
-    /// been reserved but the stream has not started, do not count against this
-    /// setting.
-    ///
-    /// Also note that if the remote *does* exceed the value set here, it is not
-    /// a protocol level error. Instead, the `h2` library will immediately reset
-    /// the stream.
+    /// Logs a warning when the receive window of a stream, or of the
+    /// connection, stays exhausted for longer than `dur`.
     ///
-    /// See [Section 5.1.2] in the HTTP/2.0 spec for more details.
+    /// The peer cannot send any more data until the received data is
+    /// released, which an application using [manual flow control] may forget
+    /// to do, stalling the stream or the whole connection. Each stall is
+    /// reported once, when the connection is polled.
     ///
-    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    /// By default, stalls are not reported.
     ///
     /// # Examples
     ///
@@ -728,6 +1367,7 @@ impl Builder {
     /// # extern crate tokio_io;
     /// # use tokio_io::*;
     /// # use h2::server::*;
+    /// # use std::time::Duration;
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
     /// # -> Handshake<T>
@@ -735,39 +1375,1454 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_concurrent_streams(1000)
+    ///     .window_stall_warning(Duration::from_secs(30))
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_concurrent_streams(Some(max));
+    ///
+    /// [manual flow control]: #method.flow_control_mode
+    pub fn window_stall_warning(&mut self, dur: Duration) -> &mut Self {
+        self.window_stall_warning = Some(dur);
         self
     }
 
-    /// Sets the maximum number of concurrent locally reset streams.
+    /// Enables or disables sizing receive windows from the measured
+    /// bandwidth-delay product of the connection.
     ///
-    /// When a stream is explicitly reset by either calling
-    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
-    /// before completing the stream, the HTTP/2.0 specification requires that
-    /// any further frames received for that stream must be ignored for "some
-    /// time".
+    /// When enabled, a PING is sent as DATA starts arriving and the amount of
+    /// data received until it is acknowledged is measured. When the peer used
+    /// most of a window within that round trip, the window is what limits
+    /// the throughput: the connection window or the initial window of
+    /// streams then grows to twice the measured amount, the latter with a
+    /// SETTINGS frame. Windows never shrink, and never grow past
+    /// [`max_adaptive_window_size`].
     ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
+    /// Each window grows from the size configured with
+    /// [`initial_window_size`] or [`initial_connection_window_size`], or
+    /// later set with `set_target_window_size` for the connection window.
     ///
-    /// The `max_concurrent_reset_streams` setting configures sets an upper
-    /// bound on the amount of state that is maintained. When this max value is
+    /// The default value is `false`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .adaptive_window(true)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_adaptive_window_size`]: #method.max_adaptive_window_size
+    /// [`initial_window_size`]: #method.initial_window_size
+    /// [`initial_connection_window_size`]: #method.initial_connection_window_size
+    pub fn adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        self.adaptive_window = enabled;
+        self
+    }
+
+    /// Sets the maximum size (in octets) adaptive receive windows grow to.
+    ///
+    /// This has no effect unless [`adaptive_window`] is enabled.
+    ///
+    /// The default value is 16 MB. Values greater than 2^31-1, the largest
+    /// window size allowed by HTTP/2.0, are clamped to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .adaptive_window(true)
+    ///     .max_adaptive_window_size(4 << 20)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`adaptive_window`]: #method.adaptive_window
+    pub fn max_adaptive_window_size(&mut self, size: u32) -> &mut Self {
+        self.max_adaptive_window_size = cmp::min(size, proto::MAX_WINDOW_SIZE);
+        self
+    }
+
+    /// Indicates the size (in octets) of the largest HTTP/2.0 frame payload that the
+    /// configured server is able to accept.
+    ///
+    /// The sender may send data frames that are **smaller** than this value,
+    /// but any data larger than `max` will be broken up into multiple `DATA`
+    /// frames.
+    ///
+    /// The value **must** be between 16,384 and 16,777,215. The default value is 16,384.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_frame_size(1_000_000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// The handshake fails if `max` is not within the legal range specified
+    /// above.
+    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
+        self.settings.max_frame_size(max);
+        self
+    }
+
+    /// Sets the max size of received header frames.
+    ///
+    /// This advisory setting informs a peer of the maximum size of header list
+    /// that the sender is prepared to accept, in octets. The value is based on
+    /// the uncompressed size of header fields, including the length of the name
+    /// and value in octets plus an overhead of 32 octets for each header field.
+    ///
+    /// This setting is also used to limit the maximum amount of data that is
+    /// buffered to decode HEADERS frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_header_list_size(16 * 1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.settings.max_header_list_size(max);
+        self
+    }
+
+    /// Sets the max size of the header table the peer may use to compress
+    /// the header blocks it sends.
+    ///
+    /// This is `SETTINGS_HEADER_TABLE_SIZE`, the max size of the HPACK dynamic
+    /// table, in octets. A smaller table uses less memory, at the cost of a
+    /// lower compression ratio. The header blocks received before the peer
+    /// acknowledges the setting may use the default size of 4,096 octets.
+    ///
+    /// A header block resizing the table over this limit is a connection
+    /// error of type `COMPRESSION_ERROR`.
+    ///
+    /// The default value is 4,096.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .header_table_size(1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn header_table_size(&mut self, size: u32) -> &mut Self {
+        self.settings.header_table_size(size);
+        self
+    }
+
+    /// Sets the max size of the header table used to compress the header
+    /// blocks sent to the peer.
+    ///
+    /// The peer's `SETTINGS_HEADER_TABLE_SIZE` is the max size of the HPACK
+    /// dynamic table it allows. The table used is the smaller of that and this
+    /// value, which bounds the memory used by each connection, at the cost of
+    /// a lower compression ratio.
+    ///
+    /// By default, the table uses the size allowed by the peer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_send_header_table_size(1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_send_header_table_size(&mut self, size: u32) -> &mut Self {
+        self.max_send_header_table_size = Some(size as usize);
+        self
+    }
+
+    /// Sets the maximum size of a header block received from the peer.
+    ///
+    /// A header block is sent in a HEADERS or PUSH_PROMISE frame, followed by
+    /// any number of CONTINUATION frames. This limits the total size of the
+    /// payloads of these frames, in octets, before HPACK decoding. A peer
+    /// exceeding it is sent a GOAWAY frame with `ENHANCE_YOUR_CALM`.
+    ///
+    /// The default value is the maximum header list size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_header_block_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_header_block_size(&mut self, max: usize) -> &mut Self {
+        self.max_header_block_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum size of a header block sent to the peer.
+    ///
+    /// A header block larger than a frame is split in CONTINUATION frames,
+    /// which cannot be interleaved with the frames of other streams: a huge
+    /// header block holds up every stream of the connection until it is
+    /// written. Headers, trailers and push promises over this limit fail fast
+    /// with a user error, and are not sent.
+    ///
+    /// The size is measured as the header list size of
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`, the length of each name and value plus
+    /// 32 octets per field, which is never smaller than the HPACK encoded
+    /// block.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_send_header_block_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_send_header_block_size(&mut self, max: usize) -> &mut Self {
+        self.max_send_header_block_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of CONTINUATION frames in a header block
+    /// received from the peer.
+    ///
+    /// Without this limit, a peer could send an endless stream of small
+    /// CONTINUATION frames. A peer exceeding it is sent a GOAWAY frame with
+    /// `ENHANCE_YOUR_CALM`.
+    ///
+    /// By default, the limit is the number of frames needed to send a header
+    /// block of the [maximum size] with frames of the maximum frame size, plus
+    /// 25%, and at least 5.
+    ///
+    /// [maximum size]: #method.max_header_block_size
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_continuation_frames(16)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_continuation_frames(&mut self, max: usize) -> &mut Self {
+        self.max_continuation_frames = Some(max);
+        self
+    }
+
+    /// Enables or disables the validation of the header fields received from
+    /// the client.
+    ///
+    /// Section 8.1.2 of RFC 7540 forbids uppercase header names,
+    /// connection-specific header fields (such as `connection` or
+    /// `transfer-encoding`) and a `te` header field with a value other than
+    /// "trailers". By default, a request containing such a header field is
+    /// malformed: it is not passed to the application, and the stream is
+    /// reset with `PROTOCOL_ERROR`.
+    ///
+    /// Disabling the validation passes these header fields through, which is
+    /// useful to proxies wanting the raw view of the peer's headers. The names
+    /// are still lowercased. The pseudo-header fields are always validated,
+    /// and so are the octets of the names and values: a name that is not a
+    /// token, or a value containing NUL, CR or LF, always makes the message
+    /// malformed.
+    ///
+    /// The default value is `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .strict_header_validation(false)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn strict_header_validation(&mut self, enabled: bool) -> &mut Self {
+        self.strict_header_validation = enabled;
+        self
+    }
+
+    /// Sets the maximum number of concurrent streams.
+    ///
+    /// The maximum concurrent streams setting only controls the maximum number
+    /// of streams that can be initiated by the remote peer. In other words,
+    /// when this setting is set to 100, this does not limit the number of
+    /// concurrent streams that can be created by the caller.
+    ///
+    /// It is recommended that this value be no smaller than 100, so as to not
+    /// unnecessarily limit parallelism. However, any value is legal, including
+    /// 0. If `max` is set to 0, then the remote will not be permitted to
+    /// initiate streams.
+    ///
+    /// Note that streams in the reserved state, i.e., push promises that have
+    /// been reserved but the stream has not started, do not count against this
+    /// setting.
+    ///
+    /// Also note that if the remote *does* exceed the value set here, it is not
+    /// a protocol level error. Instead, the `h2` library will immediately reset
+    /// the stream.
+    ///
+    /// See [Section 5.1.2] in the HTTP/2.0 spec for more details.
+    ///
+    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_concurrent_streams(1000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.settings.max_concurrent_streams(max);
+        self
+    }
+
+    /// Sets the maximum number of received streams waiting to be accepted.
+    ///
+    /// Streams opened by the client are queued until they are returned by
+    /// the `Connection` stream. When `max` streams are queued, further
+    /// streams are refused by sending a RST_STREAM frame with the
+    /// `REFUSED_STREAM` error code. The client may safely retry refused
+    /// requests. The header block of a refused stream is still decoded so
+    /// that the header compression state stays in sync.
+    ///
+    /// Streams waiting to be accepted also count against
+    /// [`max_concurrent_streams`], so this limit only has an effect when it
+    /// is lower. Unlike `max_concurrent_streams`, it is not advertised to the
+    /// client and it does not include streams that were already accepted.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_pending_accept_streams(50)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_concurrent_streams`]: #method.max_concurrent_streams
+    pub fn max_pending_accept_streams(&mut self, max: usize) -> &mut Self {
+        self.max_pending_accept_streams = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of parameters accepted in a single SETTINGS
+    /// frame.
+    ///
+    /// Every parameter in a received SETTINGS frame counts towards this limit,
+    /// including repeated and unknown parameters. A SETTINGS frame exceeding
+    /// the limit is treated as a connection error and a GOAWAY frame with
+    /// `ENHANCE_YOUR_CALM` is sent to the peer.
+    ///
+    /// The default value is 32.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_settings_entries(64)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_settings_entries(&mut self, max: usize) -> &mut Self {
+        self.max_settings_entries = max;
+        self
+    }
+
+    /// Sets the maximum number of bytes of debug data kept from a GOAWAY
+    /// frame received from the peer.
+    ///
+    /// The debug data of a GOAWAY frame is made available through
+    /// [`Error::debug_data`] when the peer closes the connection with an
+    /// error. Any debug data past this limit is discarded, so that a peer
+    /// cannot make the connection hold on to a large amount of memory.
+    ///
+    /// The default value is 1,024.
+    ///
+    /// [`Error::debug_data`]: ../struct.Error.html#method.debug_data
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_go_away_debug_data(4_096)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_go_away_debug_data(&mut self, max: usize) -> &mut Self {
+        self.max_go_away_debug_data = max;
+        self
+    }
+
+    /// Sets the first pushed stream ID to something other than 2.
+    #[cfg(feature = "unstable")]
+    pub fn initial_stream_id(&mut self, stream_id: u32) -> &mut Self {
+        self.stream_id = stream_id.into();
+        assert!(
+            self.stream_id.is_server_initiated(),
+            "stream id must be even"
+        );
+        self
+    }
+
+    /// Sets the maximum number of concurrent locally reset streams.
+    ///
+    /// When a stream is explicitly reset by either calling
+    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
+    /// before completing the stream, the HTTP/2.0 specification requires that
+    /// any further frames received for that stream must be ignored for "some
+    /// time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `max_concurrent_reset_streams` setting configures sets an upper
+    /// bound on the amount of state that is maintained. When this max value is
     /// reached, the oldest reset stream is purged from memory.
     ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// Once the stream has been fully purged from memory, any additional DATA
+    /// or HEADERS frames received for that stream will result in a connection
+    /// level protocol error, forcing the connection to terminate. PRIORITY,
+    /// WINDOW_UPDATE and RST_STREAM frames, which the peer may still send on a
+    /// closed stream, are ignored.
+    ///
+    /// The default value is 10.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_concurrent_reset_streams(1000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.reset_stream_max = max;
+        self
+    }
+
+    /// Sets the maximum number of concurrent locally reset streams.
+    ///
+    /// When a stream is explicitly reset by either calling
+    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
+    /// before completing the stream, the HTTP/2.0 specification requires that
+    /// any further frames received for that stream must be ignored for "some
+    /// time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `reset_stream_duration` setting configures the max amount of time
+    /// this state will be maintained in memory. Once the duration elapses, the
+    /// stream state is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional DATA
+    /// or HEADERS frames received for that stream will result in a connection
+    /// level protocol error, forcing the connection to terminate. PRIORITY,
+    /// WINDOW_UPDATE and RST_STREAM frames, which the peer may still send on a
+    /// closed stream, are ignored.
+    ///
+    /// The default value is 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
+        self.reset_stream_duration = dur;
+        self
+    }
+
+    /// Sets the maximum number of bytes of received data buffered per stream
+    /// before WINDOW_UPDATE frames are held back.
+    ///
+    /// Received DATA frames are buffered until they are read from the
+    /// `RecvStream`. Flow control bounds the buffered data by the stream
+    /// window, but releasing capacity does not require reading the data.
+    /// When a stream buffers more than `max` bytes, no WINDOW_UPDATE frames
+    /// are sent for it until the data is read, even if its capacity was
+    /// released. The peer may still send data up to the remaining window, so
+    /// this is a soft limit.
+    ///
+    /// The connection window is not affected, so other streams keep making
+    /// progress.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_buffered_recv_bytes_per_stream(16_384)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_buffered_recv_bytes_per_stream(&mut self, max: usize) -> &mut Self {
+        self.max_buffered_recv_bytes_per_stream = Some(max);
+        self
+    }
+
+    /// Sets how long a stream may buffer more than
+    /// [`max_buffered_recv_bytes_per_stream`] bytes of received data before
+    /// it is reset.
+    ///
+    /// When the data is not read within `dur`, the stream is reset with the
+    /// `CANCEL` error code. Pending data can no longer be read from the
+    /// `RecvStream` once the stream is reset. The connection is woken up when
+    /// the timeout expires, see [timeouts].
+    ///
+    /// By default, streams are never reset for buffering too much data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_buffered_recv_bytes_per_stream(16_384)
+    ///     .buffered_recv_timeout(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_buffered_recv_bytes_per_stream`]: #method.max_buffered_recv_bytes_per_stream
+    /// [timeouts]: ../index.html#timeouts
+    pub fn buffered_recv_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.buffered_recv_timeout = Some(dur);
+        self
+    }
+
+    /// Sets the maximum number of streams the client may reset before a
+    /// response was sent, per [`remote_reset_stream_duration`].
+    ///
+    /// A client opening streams and resetting them right away makes the
+    /// server process requests without them ever counting against the
+    /// concurrent streams limit. Streams reset by the client while waiting to
+    /// be accepted, or before the response headers were sent, are counted.
+    /// When more than `max` of them are reset within the interval, the
+    /// connection is closed with a GOAWAY frame carrying the
+    /// `ENHANCE_YOUR_CALM` error code.
+    ///
+    /// The default value is 100.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_remote_reset_streams(20)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`remote_reset_stream_duration`]: #method.remote_reset_stream_duration
+    pub fn max_remote_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.remote_reset_stream_max = max;
+        self
+    }
+
+    /// Sets the interval over which streams reset by the client are counted.
+    ///
+    /// See [`max_remote_reset_streams`] for more details.
+    ///
+    /// The default value is 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_remote_reset_streams(20)
+    ///     .remote_reset_stream_duration(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_remote_reset_streams`]: #method.max_remote_reset_streams
+    pub fn remote_reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
+        self.remote_reset_stream_duration = dur;
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for the peer to acknowledge
+    /// the SETTINGS frame sent during the handshake.
+    ///
+    /// Values in our SETTINGS frame, such as the initial window size, are only
+    /// put into effect once the peer acknowledges them. If no acknowledgement
+    /// is received within `dur`, the connection is terminated with a
+    /// `SETTINGS_TIMEOUT` error. The connection is woken up when the timeout
+    /// expires, see [timeouts].
+    ///
+    /// By default, there is no timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .settings_ack_timeout(Duration::from_secs(5))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [timeouts]: ../index.html#timeouts
+    pub fn settings_ack_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.settings_ack_timeout = Some(dur);
+        self
+    }
+
+    /// Sets whether SETTINGS updates are coalesced.
+    ///
+    /// Updates queued with `update_settings` before the connection had the
+    /// opportunity to write the previous one are merged into it, and sent as
+    /// a single SETTINGS frame. Disabling this sends every update as its own
+    /// frame, in order, each acknowledged separately by the client.
+    ///
+    /// The default value is `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .coalesce_settings_updates(false)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn coalesce_settings_updates(&mut self, enabled: bool) -> &mut Self {
+        self.coalesce_settings_updates = enabled;
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for the client connection
+    /// preface.
+    ///
+    /// The timeout starts when the handshake is created. If the 24 byte
+    /// preface is not received within `dur`, the [`Handshake`] fails with an
+    /// error for which [`is_handshake`] returns true. If the initial SETTINGS
+    /// frame of the client, which completes the preface, is not received
+    /// within `dur` either, the connection is terminated with a
+    /// `SETTINGS_TIMEOUT` error. The handshake and the connection are woken up
+    /// when the timeout expires, so that a client stalling in the middle of
+    /// the preface does not hold on to the connection, see [timeouts].
+    ///
+    /// By default, there is no timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .handshake_timeout(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Handshake`]: struct.Handshake.html
+    /// [`is_handshake`]: ../struct.Error.html#method.is_handshake
+    /// [timeouts]: ../index.html#timeouts
+    pub fn handshake_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.handshake_timeout = Some(dur);
+        self
+    }
+
+    /// Sends PING frames to check that the connection is still alive once no
+    /// frame has been received for `dur`.
+    ///
+    /// If a PING is not acknowledged within the [`keep_alive_timeout`], the
+    /// connection is closed with an I/O error of kind `TimedOut`. The
+    /// acknowledgements are also used to measure the connection's round-trip
+    /// time, see [`Connection::rtt`]. The connection is woken up when either
+    /// duration expires, so that an idle connection is kept alive too, see
+    /// [timeouts].
+    ///
+    /// By default, keep-alive PINGs are not sent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .keep_alive_interval(Duration::from_secs(30))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`keep_alive_timeout`]: #method.keep_alive_timeout
+    /// [`Connection::rtt`]: struct.Connection.html#method.rtt
+    /// [timeouts]: ../index.html#timeouts
+    pub fn keep_alive_interval(&mut self, dur: Duration) -> &mut Self {
+        self.keep_alive_interval = Some(dur);
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for a keep-alive PING to be
+    /// acknowledged before closing the connection.
+    ///
+    /// This has no effect unless the [`keep_alive_interval`] is set.
+    ///
+    /// The default value is 20 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .keep_alive_interval(Duration::from_secs(30))
+    ///     .keep_alive_timeout(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`keep_alive_interval`]: #method.keep_alive_interval
+    pub fn keep_alive_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.keep_alive_timeout = dur;
+        self
+    }
+
+    /// Sets the maximum number of PING frames the client may send within
+    /// each [`ping_interval`].
+    ///
+    /// Every PING received has to be acknowledged, so a peer flooding the
+    /// connection with them can starve other traffic. Once the limit is
+    /// exceeded, the connection is closed with a GOAWAY frame carrying
+    /// `ENHANCE_YOUR_CALM`. Acknowledgements of our own PINGs are not
+    /// counted.
+    ///
+    /// By default, the number of PINGs is not limited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_pings_per_interval(10)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`ping_interval`]: #method.ping_interval
+    pub fn max_pings_per_interval(&mut self, max: usize) -> &mut Self {
+        self.max_pings_per_interval = Some(max);
+        self
+    }
+
+    /// Sets the interval over which PING frames received from the client are
+    /// counted.
+    ///
+    /// This has no effect unless [`max_pings_per_interval`] is set.
+    ///
+    /// The default value is 1 second.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_pings_per_interval(100)
+    ///     .ping_interval(Duration::from_secs(60))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_pings_per_interval`]: #method.max_pings_per_interval
+    pub fn ping_interval(&mut self, dur: Duration) -> &mut Self {
+        self.ping_interval = dur;
+        self
+    }
+
+    /// Sets the maximum amount of time a graceful shutdown waits before
+    /// sending its final GOAWAY frame.
+    ///
+    /// A [graceful shutdown] first tells the client that no new requests
+    /// should be initiated, then waits for requests that may already be on
+    /// the wire before announcing the last stream that will be processed.
+    /// By default, the connection waits one round trip, measured with a PING
+    /// frame, however long that takes. With a timeout, the final GOAWAY is
+    /// sent after `dur` even if the PING has not been acknowledged yet. The
+    /// connection is woken up when the timeout expires, see [timeouts].
+    ///
+    /// By default, there is no timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .graceful_shutdown_timeout(Duration::from_secs(1))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [graceful shutdown]: struct.Connection.html#method.graceful_shutdown
+    /// [timeouts]: ../index.html#timeouts
+    pub fn graceful_shutdown_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.graceful_shutdown_timeout = Some(dur);
+        self
+    }
+
+    /// Closes connections that have been idle for `dur`.
+    ///
+    /// A connection is idle while it has no active streams and receives no
+    /// frames. Once it was idle for `dur`, it is closed with a GOAWAY frame
+    /// carrying `NO_ERROR`. The connection is woken up when the timeout
+    /// expires, see [timeouts].
+    ///
+    /// By default, idle connections are not closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .idle_timeout(Duration::from_secs(300))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [timeouts]: ../index.html#timeouts
+    pub fn idle_timeout(&mut self, dur: Duration) -> &mut Self {
+        self.idle_timeout = Some(dur);
+        self
+    }
+
+    /// Shuts connections down gracefully once they are `dur` old.
+    ///
+    /// This is used to recycle connections periodically, e.g. to spread the
+    /// load once new servers are added. Once a connection reached its
+    /// maximum age, a [graceful shutdown] is started: the client is told not
+    /// to initiate new requests, and the connection closes once the active
+    /// streams have completed. Use [`max_connection_age_grace`] to bound how
+    /// long they may take. Like for the [idle timeout], the connection is woken
+    /// up when its age expires, see [timeouts].
+    ///
+    /// By default, connections have no maximum age.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_connection_age(Duration::from_secs(3600))
+    ///     .max_connection_age_grace(Duration::from_secs(30))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [graceful shutdown]: struct.Connection.html#method.graceful_shutdown
+    /// [`max_connection_age_grace`]: #method.max_connection_age_grace
+    /// [idle timeout]: #method.idle_timeout
+    /// [timeouts]: ../index.html#timeouts
+    pub fn max_connection_age(&mut self, dur: Duration) -> &mut Self {
+        self.max_connection_age = Some(dur);
+        self
+    }
+
+    /// Sets the maximum amount of time the active streams may take to
+    /// complete once the connection reached its [maximum age].
+    ///
+    /// Once `dur` elapsed, the connection is closed with a GOAWAY frame
+    /// carrying `NO_ERROR`, and the streams still active fail. This has no
+    /// effect unless the [maximum age] is set.
+    ///
+    /// By default, the streams may take as long as they need.
+    ///
+    /// [maximum age]: #method.max_connection_age
+    pub fn max_connection_age_grace(&mut self, dur: Duration) -> &mut Self {
+        self.max_connection_age_grace = Some(dur);
+        self
+    }
+
+    /// Includes an extension setting in the initial SETTINGS handshake.
+    ///
+    /// Extension settings are parameters not defined by the HTTP/2.0
+    /// specification, such as vendor-specific identifiers. They are sent
+    /// as-is; peers that do not understand them ignore them ([Section
+    /// 6.5.2]).
+    ///
+    /// [Section 6.5.2]: http://httpwg.org/specs/rfc7540.html#SettingValues
+    ///
+    /// # Errors
+    ///
+    /// The handshake fails if `id` identifies one of the settings defined by
+    /// the specification, which are configured through their dedicated
+    /// methods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .initial_setting(0xf000, 1)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn initial_setting(&mut self, id: u16, val: u32) -> &mut Self {
+        self.settings.opaque(id, val);
+        self
+    }
+
+    /// Sets a callback invoked with every extension setting received from
+    /// the peer.
+    ///
+    /// Extension settings are parameters not defined by the HTTP/2.0
+    /// specification. The callback is given the identifier and value of each
+    /// of them, as SETTINGS frames are received and applied. Settings defined
+    /// by the specification are never passed to the callback; use
+    /// `peer_settings` or `settings_changes` on the connection to observe
+    /// them.
+    ///
+    /// Setting a new callback replaces the previous one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .on_unknown_setting(|id, val| {
+    ///         println!("received setting {:#06x}={}", id, val);
+    ///     })
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn on_unknown_setting<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(u16, u32) + Send + Sync + 'static,
+    {
+        self.on_unknown_setting = Some(proto::OnUnknownSetting::new(f));
+        self
+    }
+
+    /// Sets the callback called with the frames of the given extension
+    /// types received from the peer.
+    ///
+    /// Section 5.5 of [RFC 7540] lets extensions define new frame types.
+    /// Frames of unknown types are ignored by default, unless their type is
+    /// part of `types`, in which case they are passed to the callback as an
+    /// [`ExtensionFrame`], whether they are sent on the connection or on a
+    /// stream. The flags and the payload of these frames are not interpreted.
+    ///
+    /// Frame types defined by this library are never passed to the callback.
+    /// Extension frames larger than [`max_extension_frame_size`] are ignored.
+    /// Setting a new callback replaces the previous one and its types.
+    ///
+    /// [RFC 7540]: https://tools.ietf.org/html/rfc7540#section-5.5
+    /// [`ExtensionFrame`]: ../struct.ExtensionFrame.html
+    /// [`max_extension_frame_size`]: #method.max_extension_frame_size
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .on_extension_frame(&[0xf0, 0xf1], |frame| {
+    ///         println!("received frame {:#04x}: {:?}", frame.kind(), frame.payload());
+    ///     })
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn on_extension_frame<F>(&mut self, types: &[u8], f: F) -> &mut Self
+    where
+        F: Fn(ExtensionFrame) + Send + Sync + 'static,
+    {
+        self.extension_types = types.to_vec();
+        self.on_extension_frame = Some(proto::OnExtensionFrame::new(f));
+        self
+    }
+
+    /// Sets the maximum payload size of a received extension frame.
+    ///
+    /// Frames of the types registered with [`on_extension_frame`] with a
+    /// larger payload are ignored. The payload of a frame is also limited by
+    /// the maximum frame size advertised to the peer.
+    ///
+    /// The default value is 16,384.
+    ///
+    /// [`on_extension_frame`]: #method.on_extension_frame
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_extension_frame_size(1_024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_extension_frame_size(&mut self, max: usize) -> &mut Self {
+        self.max_extension_frame_size = max;
+        self
+    }
+
+    /// Sets the strategy used to pad the sent DATA and HEADERS frames.
+    ///
+    /// The function is called with the length of the data, or of the header
+    /// block fragment, carried by a frame and returns the number of octets of
+    /// padding to add. Zero leaves the frame unpadded. Padding can hide the
+    /// exact size of messages (section 10.7 of RFC 7540).
     ///
-    /// The default value is 10.
+    /// The padding of DATA frames counts against flow control, and the padding
+    /// of any frame against the maximum frame size. The padding is shortened
+    /// when a frame could not fit otherwise.
+    ///
+    /// By default, frames are not padded.
     ///
     /// # Examples
     ///
@@ -783,39 +2838,43 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_concurrent_reset_streams(1000)
+    ///     // Round frames up to a multiple of 64 octets.
+    ///     .padding(|len| ((64 - len % 64) % 64) as u8)
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
-        self.reset_stream_max = max;
+    pub fn padding<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(usize) -> u8 + Send + Sync + 'static,
+    {
+        self.padding = Some(Padding::new(f));
         self
     }
 
-    /// Sets the maximum number of concurrent locally reset streams.
+    /// Sets a hook called with every frame sent or received on the
+    /// connection, for instance to record them when debugging.
     ///
-    /// When a stream is explicitly reset by either calling
-    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
-    /// before completing the stream, the HTTP/2.0 specification requires that
-    /// any further frames received for that stream must be ignored for "some
-    /// time".
+    /// The hook is called with a read-only [`FrameView`] of each frame, in
+    /// the order the frames are sent and received. Sent frames are seen once
+    /// they are encoded, before they are written to the socket, and received
+    /// frames before they are processed. The payload of DATA frames is only
+    /// included in the view when `include_data` is true.
     ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
+    /// Received frames are borrowed from the read buffer. Sent frames may be
+    /// encoded while the state of the streams is locked, so they are copied
+    /// to a buffer, reused for all of them, until the hook can be called.
     ///
-    /// The `reset_stream_duration` setting configures the max amount of time
-    /// this state will be maintained in memory. Once the duration elapses, the
-    /// stream state is purged from memory.
+    /// The hook is called by the task polling the connection, and never
+    /// while the state of the streams is locked, so it may block or use the
+    /// streams of the connection. Connections built from the same builder
+    /// share the hook, it is called by one of them at a time.
     ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// By default, there is no hook.
     ///
-    /// The default value is 30 seconds.
+    /// [`FrameView`]: ../struct.FrameView.html
     ///
     /// # Examples
     ///
@@ -824,23 +2883,334 @@ impl Builder {
     /// # extern crate tokio_io;
     /// # use tokio_io::*;
     /// # use h2::server::*;
-    /// # use std::time::Duration;
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
     /// # -> Handshake<T>
     /// # {
+    /// let mut frames = 0;
+    ///
     /// // `server_fut` is a future representing the completion of the HTTP/2.0
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .frame_tap(false, move |direction, frame| {
+    ///         frames += 1;
+    ///         println!(
+    ///             "#{} {:?} frame {:#04x} on stream {:?}; len={}",
+    ///             frames,
+    ///             direction,
+    ///             frame.kind(),
+    ///             frame.stream_id(),
+    ///             frame.len(),
+    ///         );
+    ///     })
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
-        self.reset_stream_duration = dur;
+    pub fn frame_tap<F>(&mut self, include_data: bool, f: F) -> &mut Self
+    where
+        F: FnMut(Direction, &FrameView) + Send + 'static,
+    {
+        self.frame_tap = Some(codec::FrameTap::new(include_data, f));
+        self
+    }
+
+    /// Sets the size of the buffer the frames to send are written to.
+    ///
+    /// Frames are written to the socket in writes of up to this size, or of a
+    /// single frame when its payload is too big to be copied to the buffer.
+    /// Header blocks and GOAWAY frames larger than the buffer grow it.
+    ///
+    /// The default value is 4KB. Values smaller than a few hundred octets are
+    /// rounded up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .write_buffer_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn write_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.write_buffer_size = size;
+        self
+    }
+
+    /// Sets when the buffered frames are written to the socket.
+    ///
+    /// Frames ending a stream, PING acknowledgements and the other control
+    /// frames are always flushed once the connection has nothing more to
+    /// write, whatever the strategy. See [`FlushStrategy`] for details.
+    ///
+    /// The default value is `FlushStrategy::Adaptive`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::FlushStrategy;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .write_buffer_size(64 * 1024)
+    ///     .flush_strategy(FlushStrategy::FlushWhenFull)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`FlushStrategy`]: ../enum.FlushStrategy.html
+    pub fn flush_strategy(&mut self, strategy: FlushStrategy) -> &mut Self {
+        self.flush_strategy = strategy;
+        self
+    }
+
+    /// Sets the header fields whose values are sensitive.
+    ///
+    /// Sensitive values are sent as never indexed literals (section 7.1.3 of
+    /// RFC 7541): they are not added to the HPACK dynamic table, and
+    /// intermediaries are asked not to index them either. This protects
+    /// secrets with little entropy against attacks guessing them from the
+    /// size of the compressed headers.
+    ///
+    /// Other values can be marked individually with
+    /// [`HeaderValue::set_sensitive`]. The values received as never indexed
+    /// literals are marked sensitive, so that they are forwarded the same way.
+    ///
+    /// The default value is `authorization`, `cookie` and
+    /// `proxy-authorization`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate http;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use http::header::{self, HeaderName};
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .sensitive_headers(vec![
+    ///         header::AUTHORIZATION,
+    ///         header::COOKIE,
+    ///         header::SET_COOKIE,
+    ///         HeaderName::from_static("x-api-key"),
+    ///     ])
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`HeaderValue::set_sensitive`]: https://docs.rs/http/0.1/http/header/struct.HeaderValue.html#method.set_sensitive
+    pub fn sensitive_headers<I>(&mut self, names: I) -> &mut Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.sensitive_headers = names.into_iter().collect();
+        self
+    }
+
+    /// Sends 100 (Continue) responses automatically.
+    ///
+    /// A client sending a request with an `expect: 100-continue` header field
+    /// waits for a 100 (Continue) interim response before sending the request
+    /// body. When this is enabled, the 100 (Continue) response is sent the
+    /// first time the body of such a request is polled. A request rejected
+    /// before its body is polled, for example with a 417 (Expectation
+    /// Failed) response, does not get one, and the client does not send the
+    /// body.
+    ///
+    /// While enabled, 100 (Continue) responses sent with
+    /// [`SendResponse::send_informational`] are ignored.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`SendResponse::send_informational`]: struct.SendResponse.html#method.send_informational
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .auto_100_continue(true)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn auto_100_continue(&mut self, enabled: bool) -> &mut Self {
+        self.auto_100_continue = enabled;
+        self
+    }
+
+    /// Passes malformed requests to the application instead of resetting
+    /// their streams.
+    ///
+    /// By default, a malformed request (section 8.1.2.6 of RFC 7540), for
+    /// example one without a `:path` pseudo-header field or with a header
+    /// field value containing a line feed, is reset with `PROTOCOL_ERROR`.
+    ///
+    /// When enabled, malformed requests are instead returned by
+    /// [`Connection::poll_malformed_request`], so the server can answer them
+    /// with a client error (4xx) response, which may carry a body explaining
+    /// the error. Malformed requests are never returned by
+    /// [`Connection::poll`], and their body is discarded.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`Connection::poll_malformed_request`]: struct.Connection.html#method.poll_malformed_request
+    /// [`Connection::poll`]: struct.Connection.html#method.poll
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .accept_malformed_requests(true)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn accept_malformed_requests(&mut self, enabled: bool) -> &mut Self {
+        self.accept_malformed_requests = enabled;
+        self
+    }
+
+    /// Enables the extended CONNECT method.
+    ///
+    /// This advertises `SETTINGS_ENABLE_CONNECT_PROTOCOL` in the initial
+    /// SETTINGS handshake, allowing clients to send requests carrying the
+    /// `:protocol` pseudo header, as described in [RFC 8441]. This is used,
+    /// for example, to bootstrap WebSockets over HTTP/2.0. The pseudo header
+    /// is found as a [`Protocol`] in the extensions of the received request.
+    ///
+    /// When not enabled, any request carrying the `:protocol` pseudo header is
+    /// treated as malformed and the stream is reset with `PROTOCOL_ERROR`.
+    ///
+    /// Default value: disabled.
+    ///
+    /// [RFC 8441]: https://tools.ietf.org/html/rfc8441
+    /// [`Protocol`]: ../struct.Protocol.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .enable_connect_protocol()
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn enable_connect_protocol(&mut self) -> &mut Self {
+        self.settings.enable_connect_protocol(1);
+        self
+    }
+
+    /// Enables the extensible prioritization scheme of [RFC 9218].
+    ///
+    /// The server advertises `SETTINGS_NO_RFC7540_PRIORITIES` and schedules
+    /// responses using the urgency and incremental parameters sent by the
+    /// client, either in the `priority` request header field or in
+    /// `PRIORITY_UPDATE` frames. Responses with a lower urgency value are sent
+    /// first. Incremental responses of the same urgency share the connection,
+    /// while the others are sent one after the other.
+    ///
+    /// The RFC 7540 priority information sent by the client is then no longer
+    /// used to schedule responses.
+    ///
+    /// Default value: disabled.
+    ///
+    /// [RFC 9218]: https://tools.ietf.org/html/rfc9218
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2.0
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .enable_extensible_priorities()
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn enable_extensible_priorities(&mut self) -> &mut Self {
+        self.settings.no_rfc7540_priorities(1);
         self
     }
 
@@ -858,7 +3228,7 @@ impl Builder {
     /// [HTTP/2.0 handshake]: http://httpwg.org/specs/rfc7540.html#ConnectionHeader
     /// [Handshake]: ../index.html#handshake
     /// [`Connection`]: struct.Connection.html
-    /// [Outbound data type]: ../index.html#outbound-data-type.
+    /// [Outbound data type]: ../index.html#outbound-data-type
     ///
     /// # Examples
     ///
@@ -904,13 +3274,82 @@ impl Builder {
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn handshake<T, B>(&self, io: T) -> Handshake<T, B>
+    pub fn handshake<T, B>(&self, io: T) -> Handshake<T, B>
+    where
+        T: AsyncRead + AsyncWrite,
+        B: IntoBuf,
+        B::Buf: 'static,
+    {
+        Connection::handshake2(io, self.clone())
+    }
+
+    /// Creates a new configured HTTP/2.0 server backed by `io`, upgrading it
+    /// from HTTP/1.1.
+    ///
+    /// This is the server side of starting HTTP/2.0 on a cleartext connection
+    /// without prior knowledge, as described in section 3.2 of RFC 7540.
+    /// `request` is the HTTP/1.1 request already read from `io`, which
+    /// carried the `Upgrade: h2c` and `HTTP2-Settings` header fields. The
+    /// settings decoded from the `HTTP2-Settings` header field are applied as
+    /// the initial settings of the client.
+    ///
+    /// The returned future writes the 101 (Switching Protocols) response,
+    /// then performs the HTTP/2.0 handshake, reading the connection preface
+    /// of the client. `request` is then yielded by the [`Connection`] on the
+    /// stream 1, so that it is responded to through the usual
+    /// [`SendResponse`].
+    ///
+    /// The future fails without writing anything to `io` if the
+    /// `HTTP2-Settings` header field is missing or invalid, or if `request`
+    /// has a body, which would have to be received over HTTP/1.1 before the
+    /// upgrade completes.
+    ///
+    /// [`Connection`]: struct.Connection.html
+    /// [`SendResponse`]: struct.SendResponse.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate h2;
+    /// # extern crate http;
+    /// # extern crate tokio_io;
+    /// # use tokio_io::*;
+    /// # use h2::server::*;
+    /// # use http::Request;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite>(my_io: T, request: Request<()>)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `request` was read by an HTTP/1.1 server, and asked to upgrade the
+    /// // connection to h2c.
+    /// let server_fut = Builder::new()
+    ///     .handshake_upgraded(my_io, request);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn handshake_upgraded<T, B>(&self, io: T, request: Request<()>) -> Handshake<T, B>
     where
         T: AsyncRead + AsyncWrite,
         B: IntoBuf,
         B::Buf: 'static,
     {
-        Connection::handshake2(io, self.clone())
+        Connection::handshake_upgraded2(io, self.clone(), request)
+    }
+}
+
+impl Builder {
+    fn handshake_deadline(&self) -> Option<Instant> {
+        self.handshake_timeout.map(|timeout| timer::now() + timeout)
+    }
+
+    /// Fails if a setting was given a value out of its bounds.
+    fn initial_settings(&self) -> Result<Settings, UserError> {
+        self.settings.build().map_err(|e| {
+            debug!("invalid setting; err={:?}", e);
+            UserError::InvalidSettings
+        })
     }
 }
 
@@ -930,7 +3369,16 @@ impl<B: IntoBuf> SendResponse<B> {
     ///
     /// If a body or trailers will be sent on the returned [`SendStream`]
     /// instance, then `end_of_stream` must be set to `false` when calling this
-    /// function.
+    /// function. The response is then complete once [`SendStream::send_data`]
+    /// is called with `end_of_stream` set, or [`SendStream::send_trailers`] is
+    /// called.
+    ///
+    /// When `end_of_stream` is `true`, the response has no body: the HEADERS
+    /// frame carries the `END_STREAM` flag, and no DATA frame is sent. This is
+    /// the case of e.g. 204 (No Content) responses, or responses to `HEAD`
+    /// requests, which may still carry a `content-length` header. Sending data
+    /// or trailers on the returned [`SendStream`] then fails with an error for
+    /// which [`Error::is_send_after_end_stream`] returns true.
     ///
     /// The [`SendResponse`] instance is already associated with a received
     /// request.  This function may only be called once per instance and only if
@@ -938,6 +3386,9 @@ impl<B: IntoBuf> SendResponse<B> {
     ///
     /// [`SendResponse`]: #
     /// [`SendStream`]: ../struct.SendStream.html
+    /// [`SendStream::send_data`]: ../struct.SendStream.html#method.send_data
+    /// [`SendStream::send_trailers`]: ../struct.SendStream.html#method.send_trailers
+    /// [`Error::is_send_after_end_stream`]: ../struct.Error.html#method.is_send_after_end_stream
     /// [`send_reset`]: #method.send_reset
     pub fn send_response(
         &mut self,
@@ -950,6 +3401,25 @@ impl<B: IntoBuf> SendResponse<B> {
             .map_err(Into::into)
     }
 
+    /// Send an informational (1xx) response to a client request.
+    ///
+    /// Any number of informational responses, such as 103 (Early Hints), may
+    /// be sent before the final response is sent with [`send_response`]. The
+    /// status must not be 101 (Switching Protocols), which HTTP/2.0 does not
+    /// support.
+    ///
+    /// When the server sends 100 (Continue) responses automatically (see
+    /// [`Builder::auto_100_continue`]), 100 (Continue) responses passed to
+    /// this function are ignored.
+    ///
+    /// [`send_response`]: #method.send_response
+    /// [`Builder::auto_100_continue`]: struct.Builder.html#method.auto_100_continue
+    pub fn send_informational(&mut self, response: Response<()>) -> Result<(), ::Error> {
+        self.inner
+            .send_informational(response)
+            .map_err(Into::into)
+    }
+
     /// Send a stream reset to the peer.
     ///
     /// This essentially cancels the stream, including any inbound or outbound
@@ -994,7 +3464,298 @@ impl<B: IntoBuf> SendResponse<B> {
         ::StreamId::from_internal(self.inner.stream_id())
     }
 
-    // TODO: Support reserving push promises.
+    /// Returns the priority of the request stream.
+    ///
+    /// The client sets the priority when opening the stream and may change it
+    /// at any time with PRIORITY frames, so the returned value reflects the
+    /// frames received so far. Streams opened without priority information
+    /// depend on the root of the dependency tree with a weight of 16.
+    ///
+    /// Other streams may also be moved in the dependency tree when the client
+    /// makes a stream the exclusive dependency of another.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn priority(&self) -> ::StreamDependency {
+        ::StreamDependency::from_internal(self.inner.stream_dependency())
+    }
+
+    /// Returns the urgency of the request stream, between 0 and 7. Lower
+    /// values are more urgent.
+    ///
+    /// This is only set when extensible priorities are enabled, from the
+    /// `priority` request header field and the `PRIORITY_UPDATE` frames
+    /// received so far. Otherwise, the default urgency of 3 is returned.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn urgency(&self) -> u8 {
+        self.inner.priority_params().urgency()
+    }
+
+    /// Returns true if the client can process the response incrementally.
+    ///
+    /// Like [`urgency`], this is only set when extensible priorities are
+    /// enabled.
+    ///
+    /// [`urgency`]: #method.urgency
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn is_incremental(&self) -> bool {
+        self.inner.priority_params().is_incremental()
+    }
+
+    /// Advertises alternative services for the origin of the request, by
+    /// sending an ALTSVC frame on the request stream ([RFC 7838]).
+    ///
+    /// `field_value` lists the alternative services using the syntax of the
+    /// `Alt-Svc` header field, for example `h2="alt.example.com:443"`.
+    ///
+    /// The frame is sent after the frames already queued on the stream, so
+    /// this should be called before the response is complete.
+    ///
+    /// [RFC 7838]: https://tools.ietf.org/html/rfc7838
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `field_value` is empty or does not fit in a frame
+    /// of the smallest maximum frame size, or if the stream is closed.
+    pub fn send_alt_svc(&mut self, field_value: Bytes) -> Result<(), ::Error> {
+        check_alt_svc(&Bytes::new(), &field_value)?;
+        self.inner.send_alt_svc(field_value).map_err(Into::into)
+    }
+
+    /// Pushes a response to the client, by promising the given request.
+    ///
+    /// A PUSH_PROMISE frame carrying the request headers is sent on the
+    /// stream of the received request, reserving the next server-initiated
+    /// stream. The returned [`SendPushedResponse`] is then used to send the
+    /// response on the reserved stream.
+    ///
+    /// The request must have an absolute URI, a safe method such as `GET` and
+    /// no body. The PUSH_PROMISE frame is sent after the frames already queued
+    /// on the stream, so responses referring to the pushed resource should be
+    /// sent after calling this function.
+    ///
+    /// The pushed response is sent once the client accepts more concurrent
+    /// streams, as set by its `SETTINGS_MAX_CONCURRENT_STREAMS` value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client disabled server push, if the request is
+    /// not valid, or if the stream of the received request is closed, which
+    /// includes having sent the end of the response. Once the connection runs
+    /// out of stream IDs for pushed streams, the error's
+    /// [`is_stream_id_overflow`] returns true.
+    ///
+    /// [`SendPushedResponse`]: struct.SendPushedResponse.html
+    /// [`is_stream_id_overflow`]: ../struct.Error.html#method.is_stream_id_overflow
+    pub fn push_request(
+        &mut self,
+        request: Request<()>,
+    ) -> Result<SendPushedResponse<B>, ::Error> {
+        self.inner
+            .send_push_promise(request)
+            .map(|inner| SendPushedResponse {
+                inner: SendResponse { inner },
+            })
+    }
+}
+
+// ===== impl MalformedRequest =====
+
+impl MalformedRequest {
+    pub(crate) fn new(pseudo: frame::Pseudo, headers: header::HeaderMap, reason: &'static str) -> Self {
+        MalformedRequest {
+            pseudo,
+            headers,
+            reason,
+        }
+    }
+
+    /// Returns the `:method` pseudo-header field, if any.
+    pub fn method(&self) -> Option<&::http::Method> {
+        self.pseudo.method.as_ref()
+    }
+
+    /// Returns the `:scheme` pseudo-header field, if any.
+    pub fn scheme(&self) -> Option<&str> {
+        self.pseudo.scheme.as_ref().map(|v| &v[..])
+    }
+
+    /// Returns the `:authority` pseudo-header field, if any.
+    pub fn authority(&self) -> Option<&str> {
+        self.pseudo.authority.as_ref().map(|v| &v[..])
+    }
+
+    /// Returns the `:path` pseudo-header field, if any.
+    pub fn path(&self) -> Option<&str> {
+        self.pseudo.path.as_ref().map(|v| &v[..])
+    }
+
+    /// Returns the regular header fields of the request.
+    ///
+    /// Fields that could not be represented, such as values containing a
+    /// line feed, are not included.
+    pub fn headers(&self) -> &header::HeaderMap {
+        &self.headers
+    }
+
+    /// Returns why the request is malformed.
+    ///
+    /// The description is meant for logging and may change between
+    /// releases.
+    pub fn reason(&self) -> &str {
+        self.reason
+    }
+}
+
+// ===== impl SendMalformedResponse =====
+
+impl<B: IntoBuf> SendMalformedResponse<B> {
+    /// Send a response to the malformed request.
+    ///
+    /// The response status must be a client error (4xx), otherwise a user
+    /// error is returned. On success, a [`SendStream`] instance is returned,
+    /// which is used to stream the response body, like with
+    /// [`SendResponse::send_response`].
+    ///
+    /// [`SendStream`]: ../struct.SendStream.html
+    /// [`SendResponse::send_response`]: struct.SendResponse.html#method.send_response
+    pub fn send_response(
+        &mut self,
+        response: Response<()>,
+        end_of_stream: bool,
+    ) -> Result<SendStream<B>, ::Error> {
+        if !response.status().is_client_error() {
+            return Err(UserError::InvalidMalformedResponseStatus.into());
+        }
+
+        let stream = self.inner
+            .send_response(response, end_of_stream)
+            .map(|_| SendStream::new(self.inner.clone()))?;
+
+        self.is_done = true;
+        Ok(stream)
+    }
+
+    /// Send a stream reset to the peer.
+    ///
+    /// See [`SendResponse::send_reset`].
+    ///
+    /// [`SendResponse::send_reset`]: struct.SendResponse.html#method.send_reset
+    pub fn send_reset(&mut self, reason: Reason) {
+        self.is_done = true;
+        self.inner.send_reset(reason)
+    }
+
+    /// Returns the stream ID of the malformed request.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the strean store has been poisoned.
+    pub fn stream_id(&self) -> ::StreamId {
+        ::StreamId::from_internal(self.inner.stream_id())
+    }
+}
+
+impl<B: IntoBuf> Drop for SendMalformedResponse<B> {
+    fn drop(&mut self) {
+        // The stream is reset as it would have been without the application
+        // answering malformed requests.
+        if !self.is_done {
+            self.inner.send_reset(Reason::PROTOCOL_ERROR);
+        }
+    }
+}
+
+// ===== impl SendPushedResponse =====
+
+impl<B: IntoBuf> SendPushedResponse<B> {
+    /// Send the response to the pushed request.
+    ///
+    /// This works like [`SendResponse::send_response`]: the returned
+    /// [`SendStream`] is used to stream the response body and send trailers.
+    ///
+    /// [`SendResponse::send_response`]: struct.SendResponse.html#method.send_response
+    /// [`SendStream`]: ../struct.SendStream.html
+    pub fn send_response(
+        &mut self,
+        response: Response<()>,
+        end_of_stream: bool,
+    ) -> Result<SendStream<B>, ::Error> {
+        self.inner.send_response(response, end_of_stream)
+    }
+
+    /// Send a stream reset to the peer.
+    ///
+    /// This cancels the push, see [`SendResponse::send_reset`].
+    ///
+    /// [`SendResponse::send_reset`]: struct.SendResponse.html#method.send_reset
+    pub fn send_reset(&mut self, reason: Reason) {
+        self.inner.send_reset(reason)
+    }
+
+    /// Polls to be notified when the client resets the pushed stream, which
+    /// it does to refuse the push.
+    ///
+    /// See [`SendResponse::poll_reset`].
+    ///
+    /// [`SendResponse::poll_reset`]: struct.SendResponse.html#method.poll_reset
+    pub fn poll_reset(&mut self) -> Poll<Reason, ::Error> {
+        self.inner.poll_reset()
+    }
+
+    /// Returns the stream ID of the reserved stream.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the strean store has been poisoned.
+    pub fn stream_id(&self) -> ::StreamId {
+        self.inner.stream_id()
+    }
+}
+
+impl<B> fmt::Debug for SendPushedResponse<B>
+where
+    B: IntoBuf + fmt::Debug,
+    B::Buf: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SendPushedResponse")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Checks that an ALTSVC frame carries alternative services and fits in a
+/// frame of any size the client may accept.
+fn check_alt_svc(origin: &Bytes, field_value: &Bytes) -> Result<(), UserError> {
+    if field_value.is_empty() {
+        return Err(UserError::InvalidAltSvc);
+    }
+
+    if 2 + origin.len() + field_value.len() > frame::DEFAULT_MAX_FRAME_SIZE as usize {
+        return Err(UserError::PayloadTooBig);
+    }
+
+    Ok(())
+}
+
+/// Returns true if `scheme` follows the syntax of section 3.1 of RFC 3986.
+fn is_valid_scheme(scheme: &[u8]) -> bool {
+    match scheme.split_first() {
+        Some((first, rest)) => {
+            first.is_ascii_alphabetic() && rest.iter().all(|&b| {
+                b.is_ascii_alphanumeric() || b == b'+' || b == b'-' || b == b'.'
+            })
+        },
+        None => false,
+    }
 }
 
 // ===== impl Flush =====
@@ -1052,15 +3813,16 @@ where
         while rem > 0 {
             let n = try_nb!(self.inner_mut().read(&mut buf[..rem]));
             if n == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionReset,
-                    "connection closed unexpectedly",
-                ).into());
+                debug!("connection closed before preface; received={}", self.pos);
+                return Err(HandshakeError::MissingPreface(self.pos).into());
             }
 
-            if PREFACE[self.pos..self.pos + n] != buf[..n] {
+            let expected = &PREFACE[self.pos..self.pos + n];
+
+            if let Some(i) = expected.iter().zip(&buf[..n]).position(|(a, b)| a != b) {
+                debug!("invalid preface; mismatch at byte {}", self.pos + i);
                 // TODO: Should this just write the GO_AWAY frame directly?
-                return Err(Reason::PROTOCOL_ERROR.into());
+                return Err(HandshakeError::PrefaceMismatch(self.pos + i).into());
             }
 
             self.pos += n;
@@ -1073,6 +3835,31 @@ where
 
 // ===== impl Handshake =====
 
+impl<T, B: IntoBuf> Handshake<T, B> {
+    /// Returns true while the initial SETTINGS frame of the server is being
+    /// written.
+    ///
+    /// This is the first step of the handshake, or the second one when
+    /// upgrading from HTTP/1.1.
+    pub fn is_sending_settings(&self) -> bool {
+        match self.state {
+            Handshaking::Flushing(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true while waiting for the client connection preface.
+    ///
+    /// A client that stalls or disconnects at this point is likely not
+    /// speaking HTTP/2.0.
+    pub fn is_waiting_for_preface(&self) -> bool {
+        match self.state {
+            Handshaking::ReadingPreface(_) => true,
+            _ => false,
+        }
+    }
+}
+
 impl<T, B: IntoBuf> Future for Handshake<T, B>
     where T: AsyncRead + AsyncWrite,
           B: IntoBuf,
@@ -1084,6 +3871,30 @@ impl<T, B: IntoBuf> Future for Handshake<T, B>
         trace!("Handshake::poll(); state={:?};", self.state);
         use server::Handshaking::*;
 
+        if let Some(deadline) = self.deadline {
+            if timer::now() >= deadline {
+                debug!("Handshake::poll(); timed out; state={:?}", self.state);
+                self.deadline = None;
+                self.state = Handshaking::Empty;
+                return Err(HandshakeError::TimedOut.into());
+            }
+        }
+
+        self.timer.poll_at(self.deadline);
+
+        match self.state {
+            Failed(ref mut e) => {
+                return Err(e.take().expect("polled after error").into());
+            },
+            SendingUpgradeResponse(ref mut write) => {
+                let (io, _) = try_ready!(write.poll());
+                trace!("Handshake::poll(); upgrade response sent");
+                let codec = Connection::<T, B>::new_codec(io, &self.builder);
+                self.state = Handshaking::from(codec);
+            },
+            _ => {},
+        }
+
         self.state = if let Flushing(ref mut flush) = self.state {
             // We're currently flushing a pending SETTINGS frame. Poll the
             // flush future, and, if it's completed, advance our state to wait
@@ -1118,24 +3929,64 @@ impl<T, B: IntoBuf> Future for Handshake<T, B>
         } else {
             unreachable!("Handshake::poll() state was not advanced completely!")
         };
-        let server = poll?.map(|codec| {
-            let connection = proto::Connection::new(codec, Config {
-                next_stream_id: 2.into(),
-                // Server does not need to locally initiate any streams
-                initial_max_send_streams: 0,
-                reset_stream_duration: self.builder.reset_stream_duration,
-                reset_stream_max: self.builder.reset_stream_max,
-                settings: self.builder.settings.clone(),
-            });
-
-            trace!("Handshake::poll(); connection established!");
-            let mut c = Connection { connection };
-            if let Some(sz) = self.builder.initial_target_connection_window_size {
-                c.set_target_window_size(sz);
-            }
-            c
+        let codec = try_ready!(poll);
+        let mut connection = proto::Connection::new(codec, Config {
+            next_stream_id: self.builder.stream_id,
+            // Pushed streams are not limited until the client's SETTINGS
+            // frame says otherwise.
+            initial_max_send_streams: usize::MAX,
+            reset_stream_duration: self.builder.reset_stream_duration,
+            reset_stream_max: self.builder.reset_stream_max,
+            remote_reset_stream_max: Some(self.builder.remote_reset_stream_max),
+            remote_reset_stream_duration: self.builder.remote_reset_stream_duration,
+            max_pending_accept_streams: self.builder.max_pending_accept_streams,
+            max_pending_open_streams: None,
+            // Clients cannot reserve streams.
+            max_reserved_remote_streams: 0,
+            max_buffered_recv_bytes_per_stream: self.builder.max_buffered_recv_bytes_per_stream,
+            buffered_recv_timeout: self.builder.buffered_recv_timeout,
+            settings: self.builder.initial_settings().expect("invalid settings"),
+            settings_ack_timeout: self.builder.settings_ack_timeout,
+            coalesce_settings_updates: self.builder.coalesce_settings_updates,
+            remote_settings_deadline: self.deadline,
+            keep_alive_interval: self.builder.keep_alive_interval,
+            keep_alive_timeout: self.builder.keep_alive_timeout,
+            max_pings_per_interval: self.builder.max_pings_per_interval,
+            ping_interval: self.builder.ping_interval,
+            window_update_threshold: self.builder.window_update_threshold,
+            flow_control_mode: self.builder.flow_control_mode,
+            window_stall_warning: self.builder.window_stall_warning,
+            max_adaptive_window_size: if self.builder.adaptive_window {
+                Some(self.builder.max_adaptive_window_size)
+            } else {
+                None
+            },
+            graceful_shutdown_timeout: self.builder.graceful_shutdown_timeout,
+            idle_timeout: self.builder.idle_timeout,
+            max_connection_age: self.builder.max_connection_age,
+            max_connection_age_grace: self.builder.max_connection_age_grace,
+            on_unknown_setting: self.builder.on_unknown_setting.clone(),
+            on_alt_svc: None,
+            on_extension_frame: self.builder.on_extension_frame.clone(),
+            padding: self.builder.padding.clone(),
+            max_send_header_block_size: self.builder.max_send_header_block_size,
+            auto_100_continue: self.builder.auto_100_continue,
+            accept_malformed_requests: self.builder.accept_malformed_requests,
         });
-        Ok(server)
+
+        if let Some((request, settings)) = self.upgrade.take() {
+            connection.recv_upgrade(request, settings)?;
+        }
+
+        trace!("Handshake::poll(); connection established!");
+        let mut c = Connection {
+            connection,
+            is_accepting: true,
+        };
+        if let Some(sz) = self.builder.initial_target_connection_window_size {
+            c.set_target_window_size(sz);
+        }
+        Ok(Async::Ready(c))
     }
 }
 
@@ -1144,11 +3995,136 @@ impl<T, B> fmt::Debug for Handshake<T, B>
           B: fmt::Debug + IntoBuf,
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "server::Handshake")
+        write!(fmt, "server::Handshake {{ state: {:?} }}", self.state)
     }
 }
 
 impl Peer {
+    pub fn convert_push_message(
+        stream_id: StreamId,
+        promised_id: StreamId,
+        request: Request<()>,
+    ) -> Result<frame::PushPromise, UserError> {
+        use http::request::Parts;
+
+        let (
+            Parts {
+                method,
+                uri,
+                headers,
+                ..
+            },
+            _,
+        ) = request.into_parts();
+
+        // > Promised requests MUST be cacheable, MUST be safe, and MUST NOT
+        // > include a request body.
+        if !method.is_safe() {
+            return Err(UserError::MalformedPushRequest);
+        }
+
+        let pseudo = frame::Pseudo::request(method, uri);
+
+        // The client matches the promised request against its own requests,
+        // which requires the scheme and the authority.
+        if pseudo.scheme.is_none() || pseudo.authority.is_none() {
+            return Err(UserError::MissingUriSchemeAndAuthority);
+        }
+
+        Ok(frame::PushPromise::new(stream_id, promised_id, pseudo, headers))
+    }
+
+    /// Converts the HTTP/1.1 request a connection is upgraded from into the
+    /// header block opening the stream 1 (section 3.2 of RFC 7540).
+    pub fn convert_upgrade_request(request: Request<()>) -> Result<frame::Headers, UserError> {
+        use http::header::{self, HeaderName};
+        use http::request::Parts;
+        use http::uri;
+
+        let (
+            Parts {
+                method,
+                uri,
+                mut headers,
+                ..
+            },
+            _,
+        ) = request.into_parts();
+
+        // The request body would have to be read over HTTP/1.1 before the
+        // connection can switch protocols.
+        let has_body = headers.contains_key(header::TRANSFER_ENCODING) ||
+            headers
+                .get_all(header::CONTENT_LENGTH)
+                .iter()
+                .any(|len| len != "0");
+
+        if has_body {
+            debug!("convert_upgrade_request; request has a body");
+            return Err(UserError::InvalidUpgradeRequest);
+        }
+
+        // The target URI of a request in origin-form is rebuilt from the
+        // `Host` header field.
+        let mut parts = uri::Parts::from(uri);
+
+        if parts.authority.is_none() {
+            if let Some(host) = headers.get(header::HOST) {
+                let authority = uri::Authority::from_shared(Bytes::from(host.as_bytes()))
+                    .map_err(|_| UserError::InvalidUpgradeRequest)?;
+
+                parts.scheme = Some(uri::Scheme::HTTP);
+                parts.authority = Some(authority);
+            }
+        }
+
+        // > The authority MUST NOT include the deprecated "userinfo"
+        // > subcomponent for "http" or "https" schemed URIs.
+        if parts.authority.as_ref().map_or(false, |a| a.as_str().contains('@')) {
+            return Err(UserError::InvalidUpgradeRequest);
+        }
+
+        let uri = uri::Uri::from_parts(parts).map_err(|_| UserError::InvalidUpgradeRequest)?;
+        let mut pseudo = frame::Pseudo::request(method, uri);
+
+        if pseudo.scheme.is_none() {
+            pseudo.set_scheme(uri::Scheme::HTTP);
+        }
+
+        // Remove the fields only meaningful to the HTTP/1.1 connection,
+        // including the ones it lists.
+        let listed: Vec<HeaderName> = headers
+            .get_all(header::CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+            .collect();
+
+        for name in listed {
+            headers.remove(name);
+        }
+
+        for name in &[
+            header::CONNECTION,
+            header::UPGRADE,
+            HeaderName::from_static("http2-settings"),
+            HeaderName::from_static("keep-alive"),
+            HeaderName::from_static("proxy-connection"),
+        ] {
+            headers.remove(name);
+        }
+
+        if headers.get(header::TE).map_or(false, |te| te != "trailers") {
+            headers.remove(header::TE);
+        }
+
+        let mut frame = frame::Headers::new(1.into(), pseudo, headers);
+        frame.set_end_stream();
+
+        Ok(frame)
+    }
+
     pub fn convert_send_message(
         id: StreamId,
         response: Response<()>,
@@ -1192,78 +4168,145 @@ impl proto::Peer for Peer {
         proto::DynPeer::Server
     }
 
-    fn convert_poll_message(headers: frame::Headers) -> Result<Self::Poll, RecvError> {
-        use http::{uri, Version};
+    fn convert_poll_message(headers: frame::Headers) -> Result<Self::Poll, &'static str> {
+        use http::{uri, Method, Version};
 
         let mut b = Request::builder();
 
-        let stream_id = headers.stream_id();
         let (pseudo, fields) = headers.into_parts();
 
         macro_rules! malformed {
-            ($($arg:tt)*) => {{
-                debug!($($arg)*);
-                return Err(RecvError::Stream {
-                    id: stream_id,
-                    reason: Reason::PROTOCOL_ERROR,
-                });
-            }}
-        };
+            ($reason:expr) => {{
+                debug!("malformed headers: {}", $reason);
+                return Err($reason);
+            }};
+            ($reason:expr; $($arg:tt)*) => {{
+                debug!("malformed headers: {}; {}", $reason, format_args!($($arg)*));
+                return Err($reason);
+            }};
+        }
 
         b.version(Version::HTTP_2);
 
+        // The extended CONNECT method (RFC 8441) uses the pseudo headers of
+        // other requests.
+        let is_connect = pseudo.method == Some(Method::CONNECT) && pseudo.protocol.is_none();
+
+        if pseudo.protocol.is_some() {
+            // > On requests that contain the :protocol pseudo-header field, the
+            // > :scheme and :path pseudo-header field of the target URI MUST
+            // > also be included.
+            if pseudo.method != Some(Method::CONNECT) {
+                malformed!(":protocol without CONNECT");
+            }
+
+            if pseudo.path.is_none() {
+                malformed!(":protocol without path");
+            }
+        }
+
         if let Some(method) = pseudo.method {
             b.method(method);
         } else {
-            malformed!("malformed headers: missing method");
+            malformed!("missing method");
         }
 
-        // Specifying :status for a request is a protocol error
+        // > Pseudo-header fields defined for responses MUST NOT appear in
+        // > requests.
         if pseudo.status.is_some() {
-            return Err(RecvError::Connection(Reason::PROTOCOL_ERROR));
+            malformed!(":status in request");
         }
 
         // Convert the URI
         let mut parts = uri::Parts::default();
 
-        if let Some(scheme) = pseudo.scheme {
+        if is_connect {
+            // > The :scheme and :path pseudo-header fields MUST be omitted.
+            // > [...] A CONNECT request that does not conform to these
+            // > restrictions is malformed.
+            if pseudo.scheme.is_some() || pseudo.path.is_some() {
+                malformed!("CONNECT with scheme or path");
+            }
+
+            if pseudo.authority.is_none() {
+                malformed!("CONNECT without authority");
+            }
+        } else if let Some(scheme) = pseudo.scheme {
+            // > scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+            if !is_valid_scheme(scheme.as_bytes()) {
+                malformed!("malformed scheme"; "scheme={:?}", &scheme[..]);
+            }
+
             parts.scheme = Some(uri::Scheme::from_shared(scheme.into_inner())
-                .or_else(|_| malformed!("malformed headers: malformed scheme"))?);
+                .or_else(|_| malformed!("malformed scheme"))?);
         } else {
-            malformed!("malformed headers: missing scheme");
+            malformed!("missing scheme");
         }
 
         if let Some(authority) = pseudo.authority {
+            // > The authority MUST NOT include the deprecated "userinfo"
+            // > subcomponent for "http" or "https" schemed URIs.
+            if authority.is_empty() || authority.contains('@') {
+                malformed!("malformed authority"; "authority={:?}", &authority[..]);
+            }
+
             parts.authority = Some(uri::Authority::from_shared(authority.into_inner())
-                .or_else(|_| malformed!("malformed headers: malformed authority"))?);
+                .or_else(|_| malformed!("malformed authority"))?);
         }
 
         if let Some(path) = pseudo.path {
             // This cannot be empty
             if path.is_empty() {
-                malformed!("malformed headers: missing path");
+                malformed!("missing path");
+            }
+
+            // The path is parsed by the `http` crate, which cannot represent
+            // longer ones, and silently drops fragments.
+            if path.len() > MAX_PATH_LEN || path.contains('#') {
+                malformed!("malformed path"; "len={}", path.len());
             }
 
-            parts.path_and_query = Some(uri::PathAndQuery::from_shared(path.into_inner())
-                .or_else(|_| malformed!("malformed headers: malformed path"))?);
+            // > This pseudo-header field MUST NOT be empty for "http" or
+            // > "https" URIs; [...] The exception to this rule is an OPTIONS
+            // > request for an "http" or "https" URI that does not include a
+            // > path component; these MUST include a ":path" pseudo-header
+            // > field with a value of '*'.
+            let is_asterisk_form = &path[..] == "*" && b.method_ref() == Some(&Method::OPTIONS);
+
+            parts.path_and_query = Some(if path.starts_with('/') || is_asterisk_form {
+                uri::PathAndQuery::from_shared(path.into_inner())
+                    .or_else(|_| malformed!("malformed path"))?
+            } else {
+                // The path of a request in absolute-form, as sent by some
+                // proxies. The scheme and authority of the request still come
+                // from the :scheme and :authority pseudo-header fields.
+                let absolute = uri::Uri::from_shared(path.into_inner())
+                    .or_else(|_| malformed!("malformed path"))?;
+
+                if absolute.scheme_part().is_none() || absolute.authority_part().is_none() {
+                    malformed!("malformed path"; "path={:?}", absolute);
+                }
+
+                uri::Parts::from(absolute).path_and_query
+                    .unwrap_or_else(|| uri::PathAndQuery::from_static("/"))
+            });
+        } else if !is_connect {
+            malformed!("missing path");
         }
 
         b.uri(parts);
 
         let mut request = match b.body(()) {
             Ok(request) => request,
-            Err(_) => {
-                // TODO: Should there be more specialized handling for different
-                // kinds of errors
-                return Err(RecvError::Stream {
-                    id: stream_id,
-                    reason: Reason::PROTOCOL_ERROR,
-                });
-            },
+            Err(_) => malformed!("invalid request"),
         };
 
         *request.headers_mut() = fields;
 
+        if let Some(protocol) = pseudo.protocol {
+            request.extensions_mut().insert(Protocol::from_internal(protocol));
+        }
+
         Ok(request)
     }
 }
@@ -1276,6 +4319,10 @@ where
 {
     #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
+            Handshaking::SendingUpgradeResponse(_) =>
+                write!(f, "Handshaking::SendingUpgradeResponse(_)"),
+            Handshaking::Failed(_) =>
+                write!(f, "Handshaking::Failed(_)"),
             Handshaking::Flushing(_) =>
                 write!(f, "Handshaking::Flushing(_)"),
             Handshaking::ReadingPreface(_) =>
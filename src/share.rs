@@ -1,12 +1,14 @@
-use codec::UserError;
-use frame::Reason;
+use codec::{FrameCounts, UserError};
+use frame::{self, Reason};
 use proto::{self, WindowSize};
 
 use bytes::{Bytes, IntoBuf};
 use futures::{self, Poll, Async};
+use futures::sync::oneshot;
 use http::{HeaderMap};
 
-use std::fmt;
+use std::{cmp, fmt, io};
+use string::String;
 
 /// Sends the body stream and trailers to the remote peer.
 ///
@@ -105,10 +107,64 @@ pub struct SendStream<B: IntoBuf> {
 /// messages; the stream identifier of zero cannot be used to establish a
 /// new stream.
 ///
+/// Stream identifiers are ordered, as streams are opened in increasing order
+/// of their identifiers.
+///
 /// [Section 5.1.1]: https://tools.ietf.org/html/rfc7540#section-5.1.1
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct StreamId(u32);
 
+/// The priority of a stream, as described in [Section 5.3] of RFC 7540.
+///
+/// A stream may depend on another stream, in which case it should only be
+/// allocated resources once the stream it depends on can't make progress.
+/// Streams depending on the same stream share resources in proportion to
+/// their weight.
+///
+/// [Section 5.3]: https://tools.ietf.org/html/rfc7540#section-5.3
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StreamDependency {
+    dependency: Option<StreamId>,
+    weight: u16,
+    is_exclusive: bool,
+}
+
+/// A priority to send for a stream, as described in [Section 5.3] of RFC
+/// 7540.
+///
+/// A `Priority` can be sent along with a request using
+/// [`SendRequest::send_request_with_priority`], or later on to change the
+/// priority of a stream using [`SendStream::send_priority`].
+///
+/// By default, a stream depends on the root of the dependency tree and has a
+/// weight of 16.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate h2;
+/// # use h2::*;
+/// # fn doc(stream: &SendStream<&'static [u8]>) {
+/// // Only make progress once `stream` is blocked or done, taking all the
+/// // resources it leaves.
+/// let priority = Priority::new()
+///     .depend_on(stream.stream_id())
+///     .weight(256)
+///     .exclusive(true);
+/// # }
+/// # pub fn main() {}
+/// ```
+///
+/// [Section 5.3]: https://tools.ietf.org/html/rfc7540#section-5.3
+/// [`SendRequest::send_request_with_priority`]: client/struct.SendRequest.html#method.send_request_with_priority
+/// [`SendStream::send_priority`]: struct.SendStream.html#method.send_priority
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Priority {
+    dependency: Option<StreamId>,
+    weight: u16,
+    is_exclusive: bool,
+}
+
 /// Receives the body stream and trailers from the remote peer.
 ///
 /// A `RecvStream` is provided by [`client::ResponseFuture`] and
@@ -127,7 +183,18 @@ pub struct StreamId(u32);
 /// the data is yielded. It is recommended to only use this API when the data
 /// will not be retained in memory for extended periods of time.
 ///
+/// If the `RecvStream` is dropped before the end of the stream once the
+/// stream's other handles are gone, the stream is reset with `CANCEL`. Data
+/// that was received but not released yet is returned to the connection
+/// window, as is data the peer sent before receiving the reset.
+///
+/// If the peer resets the stream before sending `END_STREAM`, the data
+/// received so far is still yielded, followed by an error whose
+/// [`Error::reason`] is the reason of the `RST_STREAM` frame. A reset
+/// received after `END_STREAM` does not fail the body, which is complete.
+///
 /// [`client::ResponseFuture`]: client/struct.ResponseFuture.html
+/// [`Error::reason`]: struct.Error.html#method.reason
 /// [`server::Connection`]: server/struct.Connection.html
 /// [`ReleaseCapacity`]: struct.ReleaseCapacity.html
 /// [`Stream`]: https://docs.rs/futures/0.1/futures/stream/trait.Stream.html
@@ -158,6 +225,15 @@ pub struct RecvStream {
 /// Received data counts against the connection level window as well and calls
 /// to [`release_capacity`] will also increment the connection level window.
 ///
+/// # Manual connection flow control
+///
+/// With [`FlowControlMode::Manual`], [`release_capacity`] only increments the
+/// stream level window. The connection level window is incremented
+/// separately, with [`release_connection_capacity`]. A proxy may then tie
+/// each window to the capacity the other side of the proxy accepted: the
+/// peer's streams and its connection only get more window as the data is
+/// forwarded.
+///
 /// # Sending `WINDOW_UPDATE` frames
 ///
 /// `WINDOW_UPDATE` frames will not be sent out for **every** call to
@@ -168,6 +244,16 @@ pub struct RecvStream {
 ///
 /// This essentially batches window updating.
 ///
+/// # Dropping the handle
+///
+/// Once the [`RecvStream`] and every `ReleaseCapacity` handle of a stream
+/// were dropped, nothing is left to release the stream's capacity. All data
+/// received on the stream and not released yet is then released, data that
+/// was not read is discarded, and data received afterwards is released as
+/// soon as it arrives. This keeps the connection window from being used up
+/// by streams nobody reads anymore. With manual flow control, the connection
+/// level share of the data that was not released yet is released as well.
+///
 /// # Scenarios
 ///
 /// Following is a basic scenario with an HTTP/2.0 connection containing a
@@ -188,11 +274,239 @@ pub struct RecvStream {
 ///
 /// [flow control]: ../index.html#flow-control
 /// [`release_capacity`]: struct.ReleaseCapacity.html#method.release_capacity
+/// [`release_connection_capacity`]: struct.ReleaseCapacity.html#method.release_connection_capacity
+/// [`FlowControlMode::Manual`]: enum.FlowControlMode.html#variant.Manual
+/// [`RecvStream`]: struct.RecvStream.html
 #[derive(Debug)]
 pub struct ReleaseCapacity {
     inner: proto::OpaqueStreamRef,
 }
 
+/// How the connection level receive window is released.
+///
+/// The mode is set with `flow_control_mode` on the client and server
+/// `Builder` types.
+///
+/// In both modes, data the application never receives (padding, or data
+/// discarded on a reset stream or a stream whose handles were all dropped)
+/// is released automatically.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FlowControlMode {
+    /// [`ReleaseCapacity::release_capacity`] releases capacity on both the
+    /// stream and the connection.
+    ///
+    /// This is the default.
+    ///
+    /// [`ReleaseCapacity::release_capacity`]: struct.ReleaseCapacity.html#method.release_capacity
+    Auto,
+
+    /// [`ReleaseCapacity::release_capacity`] only releases capacity on the
+    /// stream, and the connection share of the data is released with
+    /// [`ReleaseCapacity::release_connection_capacity`].
+    ///
+    /// No WINDOW_UPDATE frame is sent for received data until the
+    /// application releases it. A peer whose window is exhausted is stalled
+    /// until then, which is reported with `window_stall_warning` on the
+    /// client and server `Builder` types.
+    ///
+    /// [`ReleaseCapacity::release_capacity`]: struct.ReleaseCapacity.html#method.release_capacity
+    /// [`ReleaseCapacity::release_connection_capacity`]: struct.ReleaseCapacity.html#method.release_connection_capacity
+    Manual,
+}
+
+/// A snapshot of the settings received from the remote peer.
+///
+/// A peer may send several SETTINGS frames over the life of a connection, each
+/// one only carrying the values that changed. The snapshot reflects all
+/// SETTINGS frames received (and acknowledged) at the time it was taken.
+/// Values the peer never sent are reported using the defaults defined in
+/// [Section 6.5.2] of the HTTP/2.0 specification.
+///
+/// A snapshot is obtained with `peer_settings` on [`SendRequest`] or on the
+/// client and server `Connection` types.
+///
+/// [Section 6.5.2]: http://httpwg.org/specs/rfc7540.html#SettingValues
+/// [`SendRequest`]: client/struct.SendRequest.html
+#[derive(Debug, Clone)]
+pub struct PeerSettings {
+    inner: frame::Settings,
+}
+
+/// A stream of the SETTINGS frames received from the remote peer.
+///
+/// Each item is a [`SettingsUpdate`] holding only the values carried by one
+/// SETTINGS frame, yielded once the frame has been applied to the connection.
+/// Only frames received after the stream was created are observed. Use
+/// `peer_settings` for the cumulative view.
+///
+/// The number of updates buffered for a consumer that falls behind is
+/// bounded. When the limit is reached, further updates are folded into the
+/// most recent buffered one, so the latest value of each setting is never
+/// lost.
+///
+/// The stream completes once the connection is closed.
+///
+/// A `SettingsChanges` is obtained with `settings_changes` on the client and
+/// server `Connection` types. It does not keep the connection open.
+///
+/// [`SettingsUpdate`]: struct.SettingsUpdate.html
+#[derive(Debug)]
+pub struct SettingsChanges {
+    inner: proto::SettingsChanges,
+}
+
+/// A snapshot of the statistics of a connection.
+///
+/// This is returned by `stats` on the client and server `Connection` types.
+/// Byte counts do not include the connection preface.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    pub(crate) active_streams: usize,
+    pub(crate) opened_streams: usize,
+    pub(crate) bytes_sent: usize,
+    pub(crate) bytes_received: usize,
+    pub(crate) frames_sent: FrameCounts,
+    pub(crate) frames_received: FrameCounts,
+    pub(crate) send_window: WindowSize,
+    pub(crate) recv_window: WindowSize,
+    pub(crate) buffered_send_data: usize,
+    pub(crate) contended_locks: usize,
+}
+
+/// Aborts a connection from any task.
+///
+/// Aborting closes the connection with a GOAWAY frame carrying the given
+/// error code and debug data, and fails all of its streams with that error
+/// code. This lets a stream handler close the whole connection, for example
+/// when it detects abuse at the application layer.
+///
+/// An `AbortHandle` is obtained with `abort_handle` on the client and server
+/// `Connection` types, and can be cloned. It does not keep the connection
+/// open, and aborting a connection that is gone already does nothing.
+#[derive(Debug, Clone)]
+pub struct AbortHandle {
+    inner: proto::AbortHandle,
+}
+
+/// The values carried by a single SETTINGS frame.
+///
+/// A `SettingsUpdate` is yielded by [`SettingsChanges`] for every SETTINGS
+/// frame received from the remote peer, and is passed to `update_settings` on
+/// the client and server `Connection` types to send new local settings.
+///
+/// Unlike [`PeerSettings`], getters return `None` for any setting the frame
+/// does not include.
+///
+/// [`SettingsChanges`]: struct.SettingsChanges.html
+/// [`PeerSettings`]: struct.PeerSettings.html
+#[derive(Debug, Clone, Default)]
+pub struct SettingsUpdate {
+    inner: frame::Settings,
+    reset: Vec<u16>,
+}
+
+/// A future that completes once the remote peer has acknowledged a
+/// SETTINGS frame sent with `update_settings`.
+///
+/// The new values are only put into effect locally once the future
+/// completes. If the connection closes before the acknowledgement arrives,
+/// the future completes with an error.
+#[derive(Debug)]
+pub struct SettingsAcked {
+    inner: oneshot::Receiver<()>,
+}
+
+/// Sends PING frames to the remote peer and awaits their acknowledgement.
+///
+/// This can be used to measure the round-trip time of the connection or to
+/// check that the peer is still responsive. The payload of the PING frames is
+/// chosen by the library, so that they are not confused with PINGs sent by
+/// the connection itself, such as keep-alive PINGs.
+///
+/// Only one PING sent with a `PingPong` may be outstanding at a time.
+///
+/// A `PingPong` is obtained with `ping_pong` on the client and server
+/// `Connection` types.
+#[derive(Debug)]
+pub struct PingPong {
+    inner: proto::UserPings,
+}
+
+/// A PING frame to send with [`PingPong::send_ping`].
+///
+/// [`PingPong::send_ping`]: struct.PingPong.html#method.send_ping
+#[derive(Debug)]
+pub struct Ping {
+    _p: (),
+}
+
+/// The acknowledgement of a PING frame sent with [`PingPong::send_ping`].
+///
+/// [`PingPong::send_ping`]: struct.PingPong.html#method.send_ping
+#[derive(Debug)]
+pub struct Pong {
+    _p: (),
+}
+
+/// A future that completes with a [`Pong`] once the remote peer has
+/// acknowledged a PING frame sent with [`PingPong::send_ping`].
+///
+/// If the connection fails before the acknowledgement arrives, the future
+/// completes with the connection error.
+///
+/// [`Pong`]: struct.Pong.html
+/// [`PingPong::send_ping`]: struct.PingPong.html#method.send_ping
+#[derive(Debug)]
+pub struct SendPing {
+    inner: proto::UserPings,
+    error: Option<::Error>,
+}
+
+/// A frame of a type that is not defined by RFC 7540, received from the peer.
+///
+/// Section 5.5 of [RFC 7540] lets extensions define new frame types, which
+/// are ignored by default. The types to receive are registered with
+/// `on_extension_frame` on the client and server builders, and frames of
+/// these types are passed to the callback as received: the flags and the
+/// payload are not interpreted by this library.
+///
+/// [RFC 7540]: https://tools.ietf.org/html/rfc7540#section-5.5
+#[derive(Debug, Clone)]
+pub struct ExtensionFrame {
+    kind: u8,
+    flags: u8,
+    stream_id: Option<StreamId>,
+    payload: Bytes,
+}
+
+/// The `:protocol` pseudo header of an extended CONNECT request.
+///
+/// [RFC 8441] extends the CONNECT method so that a stream can be used to
+/// bootstrap another protocol, such as WebSocket. Clients insert a `Protocol`
+/// into the extensions of the `http::Request` passed to `send_request`, which
+/// is only allowed once the server enabled the extended CONNECT method.
+/// Servers find it in the extensions of the received request.
+///
+/// [RFC 8441]: https://tools.ietf.org/html/rfc8441#section-4
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Protocol {
+    value: String<Bytes>,
+}
+
+/// Metadata of a DATA frame received from the peer.
+///
+/// This is returned alongside the frame payload by
+/// [`RecvStream::poll_data_frame`], for example to audit how a peer uses
+/// padding. Frames whose padding is as long as the frame payload, or longer,
+/// are rejected with a `PROTOCOL_ERROR` before reaching the stream.
+///
+/// [`RecvStream::poll_data_frame`]: struct.RecvStream.html#method.poll_data_frame
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DataFrameInfo {
+    pad_len: Option<u8>,
+    is_end_stream: bool,
+}
+
 // ===== impl SendStream =====
 
 impl<B: IntoBuf> SendStream<B> {
@@ -259,10 +573,14 @@ impl<B: IntoBuf> SendStream<B> {
     /// # }
     /// ```
     ///
+    /// A stream can't be assigned more capacity than the largest window size
+    /// allowed by HTTP/2.0, 2^31-1 bytes, so larger requests are capped to
+    /// that value.
+    ///
     /// See [Flow control](struct.SendStream.html#flow-control) for an overview
     /// of how send flow control works.
     pub fn reserve_capacity(&mut self, capacity: usize) {
-        // TODO: Check for overflow
+        let capacity = cmp::min(capacity, proto::MAX_WINDOW_SIZE as usize);
         self.inner.reserve_capacity(capacity as WindowSize)
     }
 
@@ -316,6 +634,9 @@ impl<B: IntoBuf> SendStream<B> {
 
     /// Sends trailers to the remote peer.
     ///
+    /// The trailers are sent in a HEADERS frame with the `END_STREAM` flag
+    /// set, after any data already buffered.
+    ///
     /// Sending trailers implicitly closes the send stream. Once the send stream
     /// is closed, no more data can be sent.
     pub fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), ::Error> {
@@ -357,6 +678,473 @@ impl<B: IntoBuf> SendStream<B> {
     pub fn stream_id(&self) -> StreamId {
         StreamId::from_internal(self.inner.stream_id())
     }
+
+    /// Changes the priority of the stream by sending a PRIORITY frame.
+    ///
+    /// The PRIORITY frame is sent ahead of any data still buffered for the
+    /// stream. If the request has not been sent yet, the priority is sent in
+    /// its HEADERS frame instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the weight of `priority` is not between 1 and 256,
+    /// or if `priority` makes the stream depend on itself.
+    pub fn send_priority(&mut self, priority: Priority) -> Result<(), ::Error> {
+        let dependency = priority.into_internal()?;
+        self.inner.send_priority(dependency).map_err(Into::into)
+    }
+}
+
+// ===== impl PeerSettings =====
+
+impl PeerSettings {
+    pub(crate) fn new(inner: frame::Settings) -> Self {
+        PeerSettings { inner }
+    }
+
+    /// Returns the value of `SETTINGS_HEADER_TABLE_SIZE`.
+    pub fn header_table_size(&self) -> u32 {
+        self.inner
+            .header_table_size()
+            .unwrap_or(frame::DEFAULT_SETTINGS_HEADER_TABLE_SIZE as u32)
+    }
+
+    /// Returns true if the peer allows server push.
+    pub fn is_push_enabled(&self) -> bool {
+        self.inner.is_push_enabled()
+    }
+
+    /// Returns the value of `SETTINGS_MAX_CONCURRENT_STREAMS`, or `None` if
+    /// the peer did not set a limit.
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.inner.max_concurrent_streams()
+    }
+
+    /// Returns the value of `SETTINGS_INITIAL_WINDOW_SIZE`.
+    pub fn initial_window_size(&self) -> u32 {
+        self.inner
+            .initial_window_size()
+            .unwrap_or(frame::DEFAULT_INITIAL_WINDOW_SIZE)
+    }
+
+    /// Returns the value of `SETTINGS_MAX_FRAME_SIZE`.
+    pub fn max_frame_size(&self) -> u32 {
+        self.inner
+            .max_frame_size()
+            .unwrap_or(frame::DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Returns the value of `SETTINGS_MAX_HEADER_LIST_SIZE`, or `None` if the
+    /// peer did not set a limit.
+    pub fn max_header_list_size(&self) -> Option<u32> {
+        self.inner.max_header_list_size()
+    }
+
+    /// Returns true if the peer supports the extended CONNECT method (RFC
+    /// 8441).
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.inner.is_extended_connect_protocol_enabled()
+    }
+}
+
+// ===== impl ConnectionStats =====
+
+impl ConnectionStats {
+    /// Returns the number of streams that are not closed yet.
+    pub fn active_streams(&self) -> usize {
+        self.active_streams
+    }
+
+    /// Returns the number of streams opened by either peer since the
+    /// connection started.
+    pub fn opened_streams(&self) -> usize {
+        self.opened_streams
+    }
+
+    /// Returns the number of bytes written to the socket.
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_sent
+    }
+
+    /// Returns the number of bytes read from the socket.
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_received
+    }
+
+    /// Returns the number of frames of each type sent to the peer.
+    ///
+    /// Frames are counted once they are buffered to be written to the socket.
+    pub fn frames_sent(&self) -> &FrameCounts {
+        &self.frames_sent
+    }
+
+    /// Returns the number of frames of each type received from the peer.
+    pub fn frames_received(&self) -> &FrameCounts {
+        &self.frames_received
+    }
+
+    /// Returns the connection level flow control window that DATA frames can
+    /// still be sent in.
+    pub fn send_window(&self) -> WindowSize {
+        self.send_window
+    }
+
+    /// Returns the connection level flow control window that the peer can
+    /// still send DATA frames in.
+    pub fn recv_window(&self) -> WindowSize {
+        self.recv_window
+    }
+
+    /// Returns the number of bytes queued to be sent but not yet written to
+    /// the socket.
+    ///
+    /// This includes the data sent on streams that is waiting for flow
+    /// control capacity, and the frames buffered for writing.
+    pub fn buffered_send_data(&self) -> usize {
+        self.buffered_send_data
+    }
+
+    /// Returns the number of times the shared state of the streams was
+    /// found locked by another thread.
+    ///
+    /// The state is locked by the stream handles and by the connection, a
+    /// growing count means they are contending for it.
+    pub fn contended_locks(&self) -> usize {
+        self.contended_locks
+    }
+}
+
+// ===== impl AbortHandle =====
+
+impl AbortHandle {
+    pub(crate) fn new(inner: proto::AbortHandle) -> Self {
+        AbortHandle { inner }
+    }
+
+    /// Aborts the connection with `reason`, including `debug_data` in the
+    /// GOAWAY frame.
+    ///
+    /// The connection acts on the request the next time it is polled. Only
+    /// the first abort requested through any handle takes effect.
+    pub fn abort(&self, reason: Reason, debug_data: &[u8]) {
+        self.inner.abort(reason, Bytes::from(debug_data));
+    }
+}
+
+// ===== impl SettingsChanges =====
+
+impl SettingsChanges {
+    pub(crate) fn new(inner: proto::SettingsChanges) -> Self {
+        SettingsChanges { inner }
+    }
+}
+
+impl futures::Stream for SettingsChanges {
+    type Item = SettingsUpdate;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Ok(self.inner.poll().map(|settings| {
+            settings.map(|inner| SettingsUpdate {
+                inner,
+                reset: Vec::new(),
+            })
+        }))
+    }
+}
+
+// ===== impl SettingsUpdate =====
+
+impl SettingsUpdate {
+    /// Returns an update that does not include any setting.
+    pub fn new() -> SettingsUpdate {
+        SettingsUpdate::default()
+    }
+
+    pub(crate) fn into_parts(self) -> (frame::Settings, Vec<u16>) {
+        (self.inner, self.reset)
+    }
+
+    /// Includes `SETTINGS_HEADER_TABLE_SIZE` in the update.
+    ///
+    /// Until the peer acknowledges the update, header blocks it sends may
+    /// still use the previous size.
+    pub fn set_header_table_size(&mut self, size: u32) -> &mut Self {
+        self.inner.set_header_table_size(Some(size));
+        self
+    }
+
+    /// Includes `SETTINGS_ENABLE_PUSH` in the update.
+    pub fn set_enable_push(&mut self, enabled: bool) -> &mut Self {
+        self.inner.set_enable_push(enabled);
+        self
+    }
+
+    /// Includes `SETTINGS_MAX_CONCURRENT_STREAMS` in the update.
+    pub fn set_max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.inner.set_max_concurrent_streams(Some(max));
+        self
+    }
+
+    /// Includes `SETTINGS_INITIAL_WINDOW_SIZE` in the update.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `size` is larger than 2^31 - 1.
+    pub fn set_initial_window_size(&mut self, size: u32) -> &mut Self {
+        assert!(size <= proto::MAX_WINDOW_SIZE);
+        self.inner.set_initial_window_size(Some(size));
+        self
+    }
+
+    /// Includes `SETTINGS_MAX_FRAME_SIZE` in the update.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `max` is not between 16,384 and 16,777,215.
+    pub fn set_max_frame_size(&mut self, max: u32) -> &mut Self {
+        self.inner.set_max_frame_size(Some(max));
+        self
+    }
+
+    /// Includes `SETTINGS_MAX_HEADER_LIST_SIZE` in the update.
+    pub fn set_max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.inner.set_max_header_list_size(Some(max));
+        self
+    }
+
+    /// Puts the setting identified by `id` back to its initial value.
+    ///
+    /// The initial value (as defined in section 6.5.2 of RFC 7540) is sent
+    /// only if the setting was changed before. Settings without an initial
+    /// value, such as `SETTINGS_MAX_CONCURRENT_STREAMS` or extension
+    /// settings, can't be unset by a SETTINGS frame: the value last sent
+    /// stays in effect on the peer and is no longer enforced locally.
+    ///
+    /// A value included in the update for the same setting takes precedence.
+    pub fn reset(&mut self, id: u16) -> &mut Self {
+        self.reset.push(id);
+        self
+    }
+
+    /// Returns the value of `SETTINGS_HEADER_TABLE_SIZE`, if included.
+    pub fn header_table_size(&self) -> Option<u32> {
+        self.inner.header_table_size()
+    }
+
+    /// Returns the value of `SETTINGS_ENABLE_PUSH`, if included.
+    pub fn enable_push(&self) -> Option<bool> {
+        self.inner.enable_push().map(|v| v != 0)
+    }
+
+    /// Returns the value of `SETTINGS_MAX_CONCURRENT_STREAMS`, if included.
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.inner.max_concurrent_streams()
+    }
+
+    /// Returns the value of `SETTINGS_INITIAL_WINDOW_SIZE`, if included.
+    pub fn initial_window_size(&self) -> Option<u32> {
+        self.inner.initial_window_size()
+    }
+
+    /// Returns the value of `SETTINGS_MAX_FRAME_SIZE`, if included.
+    pub fn max_frame_size(&self) -> Option<u32> {
+        self.inner.max_frame_size()
+    }
+
+    /// Returns the value of `SETTINGS_MAX_HEADER_LIST_SIZE`, if included.
+    pub fn max_header_list_size(&self) -> Option<u32> {
+        self.inner.max_header_list_size()
+    }
+
+    /// Returns the value of `SETTINGS_ENABLE_CONNECT_PROTOCOL`, if included.
+    pub fn enable_connect_protocol(&self) -> Option<bool> {
+        self.inner.enable_connect_protocol().map(|v| v != 0)
+    }
+}
+
+// ===== impl SettingsAcked =====
+
+impl SettingsAcked {
+    pub(crate) fn new(inner: oneshot::Receiver<()>) -> Self {
+        SettingsAcked { inner }
+    }
+}
+
+impl futures::Future for SettingsAcked {
+    type Item = ();
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<(), ::Error> {
+        self.inner.poll().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "connection closed before the SETTINGS frame was acknowledged",
+            ).into()
+        })
+    }
+}
+
+// ===== impl PingPong =====
+
+impl PingPong {
+    pub(crate) fn new(inner: proto::UserPings) -> Self {
+        PingPong { inner }
+    }
+
+    /// Sends a PING frame to the remote peer.
+    ///
+    /// The returned future completes once the peer acknowledges the frame. The
+    /// connection must be polled for the frame to be sent and its
+    /// acknowledgement to be received.
+    ///
+    /// If the previous PING has not been acknowledged yet, the future
+    /// completes with an error and no frame is sent.
+    ///
+    /// The payload of the frame is always chosen by the library, so `_ping`
+    /// is ignored. Taking a `Ping` leaves room for letting the caller choose
+    /// the payload in the future.
+    pub fn send_ping(&mut self, _ping: Ping) -> SendPing {
+        SendPing {
+            inner: self.inner.clone(),
+            error: self.inner.send_ping().err(),
+        }
+    }
+}
+
+// ===== impl Ping =====
+
+impl Ping {
+    /// Returns a PING frame with a payload chosen by the library.
+    pub fn opaque() -> Ping {
+        Ping { _p: () }
+    }
+}
+
+// ===== impl SendPing =====
+
+impl futures::Future for SendPing {
+    type Item = Pong;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Pong, ::Error> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        try_ready!(self.inner.poll_pong());
+        Ok(Async::Ready(Pong { _p: () }))
+    }
+}
+
+// ===== impl ExtensionFrame =====
+
+impl ExtensionFrame {
+    pub(crate) fn from_internal(frame: frame::Extension) -> Self {
+        let stream_id = frame.stream_id();
+
+        ExtensionFrame {
+            kind: frame.kind(),
+            flags: frame.flags(),
+            stream_id: if stream_id.is_zero() {
+                None
+            } else {
+                Some(StreamId::from_internal(stream_id))
+            },
+            payload: frame.into_payload(),
+        }
+    }
+
+    /// Returns the type of the frame.
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// Returns the flags of the frame, as received.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the stream the frame was sent on, or `None` if the frame was
+    /// sent on the connection (stream 0).
+    pub fn stream_id(&self) -> Option<StreamId> {
+        self.stream_id.clone()
+    }
+
+    /// Returns the payload of the frame.
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Consumes the frame, returning its payload.
+    pub fn into_payload(self) -> Bytes {
+        self.payload
+    }
+}
+
+// ===== impl Protocol =====
+
+impl Protocol {
+    /// Creates a `Protocol` from a static string, such as `"websocket"`.
+    pub fn from_static(value: &'static str) -> Self {
+        Protocol {
+            value: unsafe { String::from_utf8_unchecked(Bytes::from_static(value.as_bytes())) },
+        }
+    }
+
+    /// Returns the protocol as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    pub(crate) fn from_internal(value: String<Bytes>) -> Self {
+        Protocol { value }
+    }
+
+    pub(crate) fn into_internal(self) -> String<Bytes> {
+        self.value
+    }
+}
+
+impl<'a> From<&'a str> for Protocol {
+    fn from(value: &'a str) -> Self {
+        Protocol {
+            value: unsafe { String::from_utf8_unchecked(Bytes::from(value)) },
+        }
+    }
+}
+
+impl AsRef<[u8]> for Protocol {
+    fn as_ref(&self) -> &[u8] {
+        self.value.as_bytes()
+    }
+}
+
+// ===== impl DataFrameInfo =====
+
+impl DataFrameInfo {
+    pub(crate) fn from_internal(frame: &frame::Data) -> Self {
+        DataFrameInfo {
+            pad_len: frame.pad_len(),
+            is_end_stream: frame.is_end_stream(),
+        }
+    }
+
+    /// Returns true if the `PADDED` flag was set on the frame.
+    pub fn is_padded(&self) -> bool {
+        self.pad_len.is_some()
+    }
+
+    /// Returns the length of the padding of the frame, not counting the pad
+    /// length octet, or `None` if the frame was not padded.
+    pub fn pad_len(&self) -> Option<u8> {
+        self.pad_len
+    }
+
+    /// Returns true if the `END_STREAM` flag was set on the frame.
+    pub fn is_end_stream(&self) -> bool {
+        self.is_end_stream
+    }
 }
 
 // ===== impl StreamId =====
@@ -365,7 +1153,112 @@ impl StreamId {
     pub(crate) fn from_internal(id: ::frame::StreamId) -> Self {
         StreamId(id.into())
     }
+
+    pub(crate) fn into_internal(self) -> ::frame::StreamId {
+        self.0.into()
+    }
+}
+
+// ===== impl StreamDependency =====
+
+impl StreamDependency {
+    pub(crate) fn from_internal(src: ::frame::StreamDependency) -> Self {
+        let dependency = src.dependency_id();
+
+        StreamDependency {
+            dependency: if dependency.is_zero() {
+                None
+            } else {
+                Some(StreamId::from_internal(dependency))
+            },
+            weight: src.weight() as u16 + 1,
+            is_exclusive: src.is_exclusive(),
+        }
+    }
+
+    /// Returns the stream this stream depends on, or `None` if the stream
+    /// depends on the root of the dependency tree.
+    pub fn dependency(&self) -> Option<&StreamId> {
+        self.dependency.as_ref()
+    }
+
+    /// Returns the weight of the stream, between 1 and 256.
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    /// Returns true if the stream was made the sole dependency of its parent
+    /// when its priority was last set.
+    ///
+    /// Streams added as dependencies of the same parent afterwards share the
+    /// parent with this stream.
+    pub fn is_exclusive(&self) -> bool {
+        self.is_exclusive
+    }
+}
+
+// ===== impl Priority =====
+
+impl Priority {
+    /// Returns a priority depending on the root of the dependency tree, with
+    /// a weight of 16.
+    pub fn new() -> Self {
+        Priority {
+            dependency: None,
+            weight: 16,
+            is_exclusive: false,
+        }
+    }
+
+    /// Makes the stream depend on the given stream.
+    pub fn depend_on(mut self, id: StreamId) -> Self {
+        self.dependency = Some(id);
+        self
+    }
+
+    /// Sets the weight of the stream, which must be between 1 and 256.
+    ///
+    /// Streams depending on the same stream share resources in proportion to
+    /// their weight.
+    pub fn weight(mut self, weight: u16) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Makes the stream the sole dependency of the stream it depends on.
+    ///
+    /// The other dependencies of that stream then depend on this stream.
+    pub fn exclusive(mut self, is_exclusive: bool) -> Self {
+        self.is_exclusive = is_exclusive;
+        self
+    }
+
+    /// Converts the priority to its wire representation, where the weight is
+    /// offset by one.
+    pub(crate) fn into_internal(self) -> Result<::frame::StreamDependency, UserError> {
+        if self.weight == 0 || self.weight > 256 {
+            return Err(UserError::InvalidPriority);
+        }
+
+        let dependency = match self.dependency {
+            Some(id) => id.into_internal(),
+            None => ::frame::StreamId::zero(),
+        };
+
+        Ok(::frame::StreamDependency::new(
+            dependency,
+            (self.weight - 1) as u8,
+            self.is_exclusive,
+        ))
+    }
 }
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::new()
+    }
+}
+
 // ===== impl RecvStream =====
 
 impl RecvStream {
@@ -395,7 +1288,22 @@ impl RecvStream {
         &mut self.inner
     }
 
+    /// Returns the payload of the next received DATA frame, along with the
+    /// frame's metadata.
+    ///
+    /// This is the same as polling the `Stream` implementation, except that
+    /// the `PADDED` and `END_STREAM` flags and the length of the padding are
+    /// returned as well. Empty frames are returned too.
+    pub fn poll_data_frame(&mut self) -> Poll<Option<(Bytes, DataFrameInfo)>, ::Error> {
+        self.inner.inner.poll_data().map_err(Into::into)
+    }
+
     /// Returns received trailers.
+    ///
+    /// The trailers are only returned once all of the data has been polled,
+    /// and `None` is returned if the stream ended without trailers. Trailers
+    /// carrying pseudo-header fields are malformed, and the stream is reset
+    /// with `PROTOCOL_ERROR`.
     pub fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, ::Error> {
         self.inner.inner.poll_trailers().map_err(Into::into)
     }
@@ -415,7 +1323,8 @@ impl futures::Stream for RecvStream {
     type Error = ::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        self.inner.inner.poll_data().map_err(Into::into)
+        let frame = try_ready!(self.poll_data_frame());
+        Ok(frame.map(|(data, _)| data).into())
     }
 }
 
@@ -431,6 +1340,7 @@ impl fmt::Debug for RecvStream {
 
 impl ReleaseCapacity {
     pub(crate) fn new(inner: proto::OpaqueStreamRef) -> Self {
+        inner.inc_release_handles();
         ReleaseCapacity { inner }
     }
 
@@ -447,7 +1357,8 @@ impl ReleaseCapacity {
     /// Release window capacity back to remote stream.
     ///
     /// This releases capacity back to the stream level and the connection level
-    /// windows. Both window sizes will be increased by `sz`.
+    /// windows. Both window sizes will be increased by `sz`. With
+    /// [manual flow control], only the stream level window is.
     ///
     /// See [struct level] documentation for more details.
     ///
@@ -461,6 +1372,7 @@ impl ReleaseCapacity {
     ///
     /// [struct level]: #
     /// [`set_target_window_size`]: server/struct.Server.html#method.set_target_window_size
+    /// [manual flow control]: enum.FlowControlMode.html#variant.Manual
     pub fn release_capacity(&mut self, sz: usize) -> Result<(), ::Error> {
         if sz > proto::MAX_WINDOW_SIZE as usize {
             return Err(UserError::ReleaseCapacityTooBig.into());
@@ -469,11 +1381,40 @@ impl ReleaseCapacity {
             .release_capacity(sz as proto::WindowSize)
             .map_err(Into::into)
     }
+
+    /// Release the connection level window capacity of data received on
+    /// this stream, with [manual flow control].
+    ///
+    /// The connection level window is increased by `sz`. This may be called
+    /// before or after releasing the same data with [`release_capacity`].
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if more capacity is released than data has been
+    /// received on the stream and not released on the connection yet. With
+    /// automatic flow control, [`release_capacity`] releases the connection
+    /// level share already, and any capacity released here is too much.
+    ///
+    /// [manual flow control]: enum.FlowControlMode.html#variant.Manual
+    /// [`release_capacity`]: #method.release_capacity
+    pub fn release_connection_capacity(&mut self, sz: usize) -> Result<(), ::Error> {
+        if sz > proto::MAX_WINDOW_SIZE as usize {
+            return Err(UserError::ReleaseCapacityTooBig.into());
+        }
+        self.inner
+            .release_connection_capacity(sz as proto::WindowSize)
+            .map_err(Into::into)
+    }
 }
 
 impl Clone for ReleaseCapacity {
     fn clone(&self) -> Self {
-        let inner = self.inner.clone();
-        ReleaseCapacity { inner }
+        ReleaseCapacity::new(self.inner.clone())
+    }
+}
+
+impl Drop for ReleaseCapacity {
+    fn drop(&mut self) {
+        self.inner.dec_release_handles();
     }
 }
@@ -0,0 +1,257 @@
+//! Frames sent and expected by a [`Peer`].
+//!
+//! HEADERS, DATA and SETTINGS frames are built with a builder, which
+//! converts into a [`Frame`]. The other frames are built directly.
+//!
+//! [`Peer`]: ../struct.Peer.html
+//! [`Frame`]: struct.Frame.html
+
+use frame::{self, Pseudo, StreamId};
+use Reason;
+
+use bytes::Bytes;
+use http::{HeaderMap, Method, StatusCode, Uri};
+use http::header::{HeaderName, HeaderValue};
+
+use std::fmt;
+
+/// A frame sent or expected by a `Peer`.
+#[derive(Eq, PartialEq)]
+pub struct Frame {
+    pub(super) inner: frame::Frame,
+}
+
+/// Builds a HEADERS frame.
+///
+/// Created with [`headers`].
+///
+/// [`headers`]: fn.headers.html
+#[derive(Debug, Clone)]
+pub struct Headers {
+    stream_id: StreamId,
+    pseudo: Pseudo,
+    fields: HeaderMap,
+    is_end_stream: bool,
+}
+
+/// Builds a DATA frame.
+///
+/// Created with [`data`].
+///
+/// [`data`]: fn.data.html
+#[derive(Debug, Clone)]
+pub struct Data {
+    stream_id: StreamId,
+    payload: Bytes,
+    is_end_stream: bool,
+}
+
+/// Builds a SETTINGS frame.
+///
+/// Created with [`settings`].
+///
+/// [`settings`]: fn.settings.html
+#[derive(Debug, Clone)]
+pub struct Settings {
+    inner: frame::Settings,
+}
+
+/// Returns a HEADERS frame on the given stream, without any header field.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate h2;
+/// use h2::test::frames;
+///
+/// # pub fn main() {
+/// let request = frames::headers(1)
+///     .request("GET", "https://example.com/x")
+///     .field("accept", "text/plain")
+///     .eos();
+///
+/// let response = frames::headers(1).response(200);
+/// # drop((request, response));
+/// # }
+/// ```
+pub fn headers(stream_id: u32) -> Headers {
+    Headers {
+        stream_id: stream_id.into(),
+        pseudo: Pseudo::default(),
+        fields: HeaderMap::new(),
+        is_end_stream: false,
+    }
+}
+
+/// Returns a DATA frame on the given stream, carrying `payload`.
+pub fn data<T>(stream_id: u32, payload: T) -> Data
+where
+    T: Into<Bytes>,
+{
+    Data {
+        stream_id: stream_id.into(),
+        payload: payload.into(),
+        is_end_stream: false,
+    }
+}
+
+/// Returns a SETTINGS frame without any parameter.
+pub fn settings() -> Settings {
+    Settings {
+        inner: frame::Settings::default(),
+    }
+}
+
+/// Returns the acknowledgement of a SETTINGS frame.
+pub fn settings_ack() -> Frame {
+    Frame::new(frame::Settings::ack())
+}
+
+/// Returns a WINDOW_UPDATE frame, on a stream or on the connection when
+/// `stream_id` is zero.
+pub fn window_update(stream_id: u32, increment: u32) -> Frame {
+    Frame::new(frame::WindowUpdate::new(stream_id.into(), increment))
+}
+
+/// Returns a RST_STREAM frame.
+pub fn reset(stream_id: u32, reason: Reason) -> Frame {
+    Frame::new(frame::Reset::new(stream_id.into(), reason))
+}
+
+/// Returns a GOAWAY frame, without debug data.
+pub fn go_away(last_stream_id: u32, reason: Reason) -> Frame {
+    Frame::new(frame::GoAway::new(last_stream_id.into(), reason))
+}
+
+/// Returns a PING frame.
+pub fn ping(payload: [u8; 8]) -> Frame {
+    Frame::new(frame::Ping::new(payload))
+}
+
+/// Returns the acknowledgement of a PING frame.
+pub fn pong(payload: [u8; 8]) -> Frame {
+    Frame::new(frame::Ping::pong(payload))
+}
+
+// ===== impl Frame =====
+
+impl Frame {
+    fn new<T>(src: T) -> Frame
+    where
+        T: Into<frame::Frame>,
+    {
+        Frame { inner: src.into() }
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, fmt)
+    }
+}
+
+// ===== impl Headers =====
+
+impl Headers {
+    /// Sets the pseudo header fields of a request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method or the URI is not valid.
+    pub fn request(mut self, method: &str, uri: &str) -> Headers {
+        let method: Method = method.parse().expect("invalid method");
+        let uri: Uri = uri.parse().expect("invalid uri");
+
+        self.pseudo = Pseudo::request(method, uri);
+        self
+    }
+
+    /// Sets the pseudo header field of a response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the status code is not valid.
+    pub fn response(mut self, status: u16) -> Headers {
+        let status = StatusCode::from_u16(status).expect("invalid status code");
+
+        self.pseudo = Pseudo::response(status);
+        self
+    }
+
+    /// Appends a regular header field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or the value is not valid.
+    pub fn field(mut self, name: &str, value: &str) -> Headers {
+        let name: HeaderName = name.parse().expect("invalid header name");
+        let value: HeaderValue = value.parse().expect("invalid header value");
+
+        self.fields.append(name, value);
+        self
+    }
+
+    /// Sets the END_STREAM flag.
+    pub fn eos(mut self) -> Headers {
+        self.is_end_stream = true;
+        self
+    }
+}
+
+impl From<Headers> for Frame {
+    fn from(src: Headers) -> Frame {
+        let mut frame = frame::Headers::new(src.stream_id, src.pseudo, src.fields);
+
+        if src.is_end_stream {
+            frame.set_end_stream();
+        }
+
+        Frame::new(frame)
+    }
+}
+
+// ===== impl Data =====
+
+impl Data {
+    /// Sets the END_STREAM flag.
+    pub fn eos(mut self) -> Data {
+        self.is_end_stream = true;
+        self
+    }
+}
+
+impl From<Data> for Frame {
+    fn from(src: Data) -> Frame {
+        let mut frame = frame::Data::new(src.stream_id, src.payload);
+        frame.set_end_stream(src.is_end_stream);
+        Frame::new(frame)
+    }
+}
+
+// ===== impl Settings =====
+
+impl Settings {
+    /// Sets the SETTINGS_INITIAL_WINDOW_SIZE parameter.
+    pub fn initial_window_size(mut self, val: u32) -> Settings {
+        self.inner.set_initial_window_size(Some(val));
+        self
+    }
+
+    /// Sets the SETTINGS_MAX_CONCURRENT_STREAMS parameter.
+    pub fn max_concurrent_streams(mut self, val: u32) -> Settings {
+        self.inner.set_max_concurrent_streams(Some(val));
+        self
+    }
+
+    /// Sets the SETTINGS_MAX_FRAME_SIZE parameter.
+    pub fn max_frame_size(mut self, val: u32) -> Settings {
+        self.inner.set_max_frame_size(Some(val));
+        self
+    }
+}
+
+impl From<Settings> for Frame {
+    fn from(src: Settings) -> Frame {
+        Frame::new(src.inner)
+    }
+}
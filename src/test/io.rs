@@ -0,0 +1,330 @@
+use futures::Poll;
+use futures::task::{self, Task};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::{cmp, io, usize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// One end of an in-memory duplex connection.
+///
+/// The bytes written to an `Io` are read from the other end, in order. Reads
+/// wait until the other end writes some bytes, or is closed, in which case
+/// they return the end of the stream. Writes to an `Io` whose other end was
+/// dropped are discarded.
+///
+/// Created with [`duplex`] or [`Builder`].
+///
+/// [`duplex`]: fn.duplex.html
+/// [`Builder`]: struct.Builder.html
+#[derive(Debug)]
+pub struct Io {
+    /// The bytes written by the other end
+    read: Arc<Mutex<Pipe>>,
+
+    /// The bytes written by this end
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// Configures how the bytes of a duplex connection are delivered.
+///
+/// By default, the bytes written to an end can all be read at once from the
+/// other end, and any number of bytes can be written before the other end
+/// reads them.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate h2;
+/// use h2::test;
+///
+/// # pub fn main() {
+/// // Frames are read one byte at a time, and writes wait as soon as 16kb
+/// // are waiting to be read.
+/// let (client_io, server_io) = test::Builder::new()
+///     .read_chunk_size(1)
+///     .max_buffered(16 * 1024)
+///     .build();
+/// # drop((client_io, server_io));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    read_chunk_size: usize,
+    write_chunk_size: usize,
+    max_buffered: usize,
+}
+
+/// The bytes sent in one direction.
+#[derive(Debug)]
+struct Pipe {
+    buf: VecDeque<u8>,
+
+    /// Max number of bytes returned by a read
+    read_chunk_size: usize,
+
+    /// Max number of bytes accepted by a write
+    write_chunk_size: usize,
+
+    /// Max number of bytes waiting to be read
+    max_buffered: usize,
+
+    /// Waiting for bytes to be written, or for the writer to close
+    read_task: Option<Task>,
+
+    /// Waiting for bytes to be read
+    write_task: Option<Task>,
+
+    /// The writer will not write anymore
+    is_write_closed: bool,
+
+    /// The reader will not read anymore
+    is_read_closed: bool,
+}
+
+/// Returns both ends of a new in-memory duplex connection.
+///
+/// One end is passed to the client or server under test, and the other one
+/// to a [`Peer`], or to another client or server.
+///
+/// [`Peer`]: struct.Peer.html
+///
+/// # Examples
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate h2;
+/// # extern crate http;
+/// use futures::{Future, Stream};
+/// use h2::{client, server, test};
+/// use http::{Request, Response, StatusCode};
+///
+/// # pub fn main() {
+/// let (client_io, server_io) = test::duplex();
+///
+/// let server = server::handshake(server_io)
+///     .and_then(|conn| conn.into_future().map_err(|(e, _)| e))
+///     .and_then(|(request, conn)| {
+///         let (request, mut respond) = request.unwrap();
+///         assert_eq!(request.uri().path(), "/x");
+///
+///         let response = Response::new(());
+///         respond.send_response(response, true).unwrap();
+///
+///         conn.into_future().map_err(|(e, _)| e)
+///     });
+///
+/// let client = client::handshake(client_io)
+///     .and_then(|(mut client, conn)| {
+///         let request = Request::get("https://example.com/x").body(()).unwrap();
+///         let (response, _) = client.send_request(request, true).unwrap();
+///
+///         conn.join(response.map(move |response| {
+///             drop(client);
+///             response.status()
+///         }))
+///     });
+///
+/// let (_, (_, status)) = server.join(client).wait().unwrap();
+/// assert_eq!(status, StatusCode::OK);
+/// # }
+/// ```
+pub fn duplex() -> (Io, Io) {
+    Builder::new().build()
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Returns a new builder with the default configuration.
+    pub fn new() -> Builder {
+        Builder {
+            read_chunk_size: usize::MAX,
+            write_chunk_size: usize::MAX,
+            max_buffered: usize::MAX,
+        }
+    }
+
+    /// Sets the max number of bytes returned by a single read.
+    ///
+    /// Small chunks split frames across reads, as a slow network would.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn read_chunk_size(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0, "read chunk size must not be zero");
+        self.read_chunk_size = max;
+        self
+    }
+
+    /// Sets the max number of bytes accepted by a single write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn write_chunk_size(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0, "write chunk size must not be zero");
+        self.write_chunk_size = max;
+        self
+    }
+
+    /// Sets the max number of bytes written to an end and not read yet from
+    /// the other end.
+    ///
+    /// Writes are not ready while that many bytes wait to be read, until the
+    /// other end reads some of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_buffered(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0, "max buffered must not be zero");
+        self.max_buffered = max;
+        self
+    }
+
+    /// Returns both ends of a new duplex connection using this
+    /// configuration.
+    pub fn build(&self) -> (Io, Io) {
+        let a = Arc::new(Mutex::new(self.pipe()));
+        let b = Arc::new(Mutex::new(self.pipe()));
+
+        let one = Io {
+            read: a.clone(),
+            write: b.clone(),
+        };
+
+        let other = Io {
+            read: b,
+            write: a,
+        };
+
+        (one, other)
+    }
+
+    fn pipe(&self) -> Pipe {
+        Pipe {
+            buf: VecDeque::new(),
+            read_chunk_size: self.read_chunk_size,
+            write_chunk_size: self.write_chunk_size,
+            max_buffered: self.max_buffered,
+            read_task: None,
+            write_task: None,
+            is_write_closed: false,
+            is_read_closed: false,
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+// ===== impl Io =====
+
+impl Io {
+    /// Closes the write half of this end.
+    ///
+    /// Once the bytes written so far are read, reads from the other end
+    /// return the end of the stream.
+    pub fn close_write(&mut self) {
+        let mut pipe = self.write.lock().unwrap();
+        pipe.is_write_closed = true;
+        pipe.notify_reader();
+    }
+}
+
+impl io::Read for Io {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.read.lock().unwrap();
+
+        if pipe.buf.is_empty() {
+            if pipe.is_write_closed || dst.is_empty() {
+                return Ok(0);
+            }
+
+            pipe.read_task = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let n = cmp::min(cmp::min(dst.len(), pipe.read_chunk_size), pipe.buf.len());
+
+        for (dst, src) in dst.iter_mut().zip(pipe.buf.drain(..n)) {
+            *dst = src;
+        }
+
+        if let Some(task) = pipe.write_task.take() {
+            task.notify();
+        }
+
+        Ok(n)
+    }
+}
+
+impl AsyncRead for Io {}
+
+impl io::Write for Io {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.write.lock().unwrap();
+
+        if pipe.is_write_closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        // Nobody will read the bytes.
+        if pipe.is_read_closed {
+            return Ok(src.len());
+        }
+
+        let rem = pipe.max_buffered.saturating_sub(pipe.buf.len());
+
+        if rem == 0 && !src.is_empty() {
+            pipe.write_task = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let n = cmp::min(cmp::min(src.len(), pipe.write_chunk_size), rem);
+        pipe.buf.extend(&src[..n]);
+        pipe.notify_reader();
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for Io {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.close_write();
+        Ok(().into())
+    }
+}
+
+impl Drop for Io {
+    fn drop(&mut self) {
+        self.close_write();
+
+        let mut pipe = self.read.lock().unwrap();
+        pipe.is_read_closed = true;
+        pipe.buf.clear();
+
+        if let Some(task) = pipe.write_task.take() {
+            task.notify();
+        }
+    }
+}
+
+// ===== impl Pipe =====
+
+impl Pipe {
+    fn notify_reader(&mut self) {
+        if let Some(task) = self.read_task.take() {
+            task.notify();
+        }
+    }
+}
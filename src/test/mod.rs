@@ -0,0 +1,84 @@
+//! Utilities for testing code using this crate without a network.
+//!
+//! This module is only available with the `test-util` feature.
+//!
+//! [`duplex`] returns both ends of an in-memory connection, implementing
+//! `AsyncRead` and `AsyncWrite`. A [`Builder`] controls how the bytes are
+//! delivered: split in small reads or writes, or with writes waiting for the
+//! other end to read.
+//!
+//! A [`Peer`] is a scripted HTTP/2.0 client or server, sending and
+//! expecting the [`frames`] of its script in order. It checks the frames a
+//! client or server under test sends, at the frame level, and makes it
+//! answer frames that a real peer would rarely send.
+//!
+//! A [`Replay`] is a transport replaying the bytes received on a
+//! [`Capture`]d connection, to reproduce a bug seen on a real connection.
+//! The frames written to it can then be compared with the captured ones.
+//!
+//! # Examples
+//!
+//! A client checking the status of a response.
+//!
+//! ```
+//! # extern crate futures;
+//! # extern crate h2;
+//! # extern crate http;
+//! # extern crate tokio_io;
+//! use futures::Future;
+//! use h2::{client, Reason};
+//! use h2::test::{self, frames, Peer};
+//! use http::Request;
+//! use tokio_io::{AsyncRead, AsyncWrite};
+//!
+//! /// Returns true if the resource exists.
+//! fn exists<T>(io: T) -> Box<Future<Item = bool, Error = h2::Error>>
+//! where
+//!     T: AsyncRead + AsyncWrite + 'static,
+//! {
+//!     let check = client::handshake(io).and_then(|(mut client, conn)| {
+//!         let request = Request::head("https://example.com/x").body(()).unwrap();
+//!         let (response, _) = client.send_request(request, true).unwrap();
+//!
+//!         let response = response.map(move |response| {
+//!             drop(client);
+//!             response.status().is_success()
+//!         });
+//!
+//!         conn.join(response).map(|(_, exists)| exists)
+//!     });
+//!
+//!     Box::new(check)
+//! }
+//!
+//! # pub fn main() {
+//! let (io, peer_io) = test::duplex();
+//!
+//! let peer = Peer::server(peer_io)
+//!     .handshake()
+//!     .recv_frame(frames::headers(1).request("HEAD", "https://example.com/x").eos())
+//!     .send_frame(frames::headers(1).response(404).eos())
+//!     .recv_frame(frames::go_away(0, Reason::NO_ERROR))
+//!     .recv_eof();
+//!
+//! let (_, exists) = peer.join(exists(io)).wait().unwrap();
+//! assert!(!exists);
+//! # }
+//! ```
+//!
+//! [`duplex`]: fn.duplex.html
+//! [`Builder`]: struct.Builder.html
+//! [`Peer`]: struct.Peer.html
+//! [`frames`]: frames/index.html
+//! [`Replay`]: struct.Replay.html
+//! [`Capture`]: struct.Capture.html
+
+mod io;
+mod peer;
+mod replay;
+
+pub mod frames;
+
+pub use self::io::{duplex, Builder, Io};
+pub use self::peer::Peer;
+pub use self::replay::{Capture, Outbound, ParseError, Replay};
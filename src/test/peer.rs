@@ -0,0 +1,274 @@
+use client::PREFACE;
+use codec::{Codec, RecvError};
+use frame;
+use super::Io;
+use super::frames::Frame;
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll, Stream};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::fmt;
+use std::collections::VecDeque;
+use std::io::{self, Cursor};
+
+/// A scripted HTTP/2.0 peer, checking the frames sent by a client or server
+/// under test.
+///
+/// A script is a list of frames to send, and of frames expected to be
+/// received, in order. `Peer` is a future running the script: the frames to
+/// send are sent as soon as the frames expected before them were received.
+/// It completes once the whole script ran, and drops its end of the
+/// connection.
+///
+/// Received frames are compared with the expected ones, header blocks once
+/// decoded. The frames are encoded and decoded by the codec of the
+/// connections of this crate.
+///
+/// # Panics
+///
+/// Polling a `Peer` panics when a received frame is not the expected one,
+/// when the connection is closed before the end of the script, or when an
+/// invalid frame is received.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate h2;
+/// # extern crate http;
+/// use futures::{Future, Stream};
+/// use h2::client;
+/// use h2::test::{self, frames, Peer};
+/// use http::{Request, StatusCode};
+///
+/// # pub fn main() {
+/// let (io, peer_io) = test::duplex();
+///
+/// let peer = Peer::server(peer_io)
+///     .handshake()
+///     .recv_frame(frames::headers(1).request("GET", "https://example.com/x").eos())
+///     .send_frame(frames::headers(1).response(200))
+///     .send_frame(frames::data(1, "hello").eos());
+///
+/// let client = client::handshake(io).and_then(|(mut client, conn)| {
+///     let request = Request::get("https://example.com/x").body(()).unwrap();
+///     let (response, _) = client.send_request(request, true).unwrap();
+///
+///     let response = response.and_then(move |response| {
+///         assert_eq!(response.status(), StatusCode::OK);
+///         drop(client);
+///         response.into_body().concat2()
+///     });
+///
+///     conn.join(response)
+/// });
+///
+/// let (_, (_, body)) = peer.join(client).wait().unwrap();
+/// assert_eq!(body, "hello");
+/// # }
+/// ```
+pub struct Peer<T = Io> {
+    codec: Codec<T, Cursor<Bytes>>,
+
+    /// The rest of the script
+    steps: VecDeque<Step>,
+
+    /// True while the acknowledgement of the SETTINGS frame sent by
+    /// `handshake` was not received
+    is_ack_pending: bool,
+}
+
+enum Step {
+    /// Write the client connection preface
+    SendPreface(Cursor<&'static [u8]>),
+
+    /// Read the client connection preface, holding the bytes read so far
+    RecvPreface(Vec<u8>),
+
+    /// Send a frame, taken once it is sent
+    Send(Option<frame::Frame>),
+
+    Recv(frame::Frame),
+
+    /// Expect a SETTINGS frame, whatever its parameters
+    RecvSettings,
+
+    RecvEof,
+}
+
+impl<T> Peer<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    /// Returns a peer acting as the server of a client under test.
+    ///
+    /// The script starts by expecting the client connection preface.
+    pub fn server(io: T) -> Peer<T> {
+        Peer::new(io, Step::RecvPreface(Vec::with_capacity(PREFACE.len())))
+    }
+
+    /// Returns a peer acting as the client of a server under test.
+    ///
+    /// The script starts by sending the client connection preface.
+    pub fn client(io: T) -> Peer<T> {
+        Peer::new(io, Step::SendPreface(Cursor::new(PREFACE)))
+    }
+
+    fn new(io: T, first: Step) -> Peer<T> {
+        let mut steps = VecDeque::new();
+        steps.push_back(first);
+
+        Peer {
+            codec: Codec::new(io),
+            steps,
+            is_ack_pending: false,
+        }
+    }
+
+    /// Exchanges the SETTINGS frames starting a connection.
+    ///
+    /// The peer sends a SETTINGS frame without any parameter, expects a
+    /// SETTINGS frame with any parameters, and acknowledges it. The
+    /// acknowledgement of its own SETTINGS frame is accepted whenever it is
+    /// received, as the other end may send frames before it.
+    pub fn handshake(mut self) -> Peer<T> {
+        self.is_ack_pending = true;
+
+        let mut me = self.send_frame(::test::frames::settings());
+        me.steps.push_back(Step::RecvSettings);
+        me.send_frame(::test::frames::settings_ack())
+    }
+
+    /// Sends a frame.
+    pub fn send_frame<F>(mut self, frame: F) -> Peer<T>
+    where
+        F: Into<Frame>,
+    {
+        self.steps.push_back(Step::Send(Some(frame.into().inner)));
+        self
+    }
+
+    /// Expects to receive the given frame.
+    pub fn recv_frame<F>(mut self, frame: F) -> Peer<T>
+    where
+        F: Into<Frame>,
+    {
+        self.steps.push_back(Step::Recv(frame.into().inner));
+        self
+    }
+
+    /// Expects the connection to be closed by the other end.
+    pub fn recv_eof(mut self) -> Peer<T> {
+        self.steps.push_back(Step::RecvEof);
+        self
+    }
+
+    /// Returns the next frame received, failing on invalid frames.
+    fn poll_frame(&mut self) -> Poll<Option<frame::Frame>, io::Error> {
+        loop {
+            let frame = match self.codec.poll() {
+                Ok(Async::Ready(frame)) => frame,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(RecvError::Io(e)) => return Err(e),
+                Err(e) => panic!("received an invalid frame; err={:?}", e),
+            };
+
+            if let Some(frame::Frame::Settings(ref settings)) = frame {
+                if settings.is_ack() && self.is_ack_pending {
+                    self.is_ack_pending = false;
+                    continue;
+                }
+            }
+
+            return Ok(Async::Ready(frame));
+        }
+    }
+
+    /// Runs a step of the script.
+    fn poll_step(&mut self, step: &mut Step) -> Poll<(), ::Error> {
+        // Frames are flushed before waiting for the next ones, without
+        // waiting for the other end to read them.
+        self.codec.flush()?;
+
+        match *step {
+            Step::SendPreface(ref mut preface) => {
+                while preface.position() < PREFACE.len() as u64 {
+                    try_ready!(self.codec.get_mut().write_buf(preface));
+                }
+            },
+            Step::RecvPreface(ref mut buf) => {
+                while buf.len() < PREFACE.len() {
+                    let mut dst = [0; 32];
+                    let dst = &mut dst[..PREFACE.len() - buf.len()];
+                    let n = try_ready!(self.codec.get_mut().poll_read(dst));
+
+                    if n == 0 {
+                        panic!("unexpected EOF; expected the connection preface");
+                    }
+
+                    buf.extend_from_slice(&dst[..n]);
+                }
+
+                assert_eq!(&buf[..], PREFACE, "invalid connection preface");
+            },
+            Step::Send(ref mut frame) => {
+                try_ready!(self.codec.poll_ready());
+
+                let frame = frame.take().unwrap().map(Cursor::new);
+                self.codec.buffer(frame)?;
+            },
+            Step::Recv(ref expected) => {
+                match try_ready!(self.poll_frame()) {
+                    Some(frame) => assert_eq!(&frame, expected, "unexpected frame"),
+                    None => panic!("unexpected EOF; expected {:?}", expected),
+                }
+            },
+            Step::RecvSettings => {
+                match try_ready!(self.poll_frame()) {
+                    Some(frame::Frame::Settings(ref frame)) if !frame.is_ack() => {},
+                    Some(frame) => panic!("unexpected frame; expected SETTINGS; frame={:?}", frame),
+                    None => panic!("unexpected EOF; expected SETTINGS"),
+                }
+            },
+            Step::RecvEof => {
+                if let Some(frame) = try_ready!(self.poll_frame()) {
+                    panic!("unexpected frame; expected EOF; frame={:?}", frame);
+                }
+            },
+        }
+
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<T> Future for Peer<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = ();
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<(), ::Error> {
+        while let Some(mut step) = self.steps.pop_front() {
+            match self.poll_step(&mut step) {
+                Ok(Async::Ready(())) => {},
+                ret => {
+                    self.steps.push_front(step);
+                    return ret;
+                },
+            }
+        }
+
+        try_ready!(self.codec.flush());
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<T> fmt::Debug for Peer<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Peer")
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
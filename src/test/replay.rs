@@ -0,0 +1,589 @@
+use client::PREFACE;
+use codec::Codec;
+use frame::{self, Pseudo, StreamId};
+use super::duplex;
+
+use bytes::Bytes;
+use futures::{Future, Poll, Stream};
+use futures::task::{self, Task};
+use http::HeaderMap;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::{cmp, error, fmt, io, thread};
+use std::collections::VecDeque;
+use std::io::{Cursor, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The bytes exchanged on a captured HTTP/2.0 connection, from the point of
+/// view of the end replaced by a client or server under test.
+///
+/// A capture is parsed from a text dump, one chunk of bytes per line:
+///
+/// * `< 0000040100000001`: bytes received from the peer, in hex,
+/// * `> base64:AAAEAQAAAAE=`: bytes sent to the peer, in base64,
+/// * `@ 15ms`: a pause before the next bytes received, in `ms` or `s`.
+///
+/// Whitespace in hex bytes is ignored, and `#` starts a comment.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate h2;
+/// use h2::test::Capture;
+///
+/// # pub fn main() {
+/// let capture = Capture::parse("
+///     < 505249202a20485454502f322e300d0a0d0a534d0d0a0d0a  # connection preface
+///     < 000000 04 00 00000000                              # SETTINGS
+///     @ 10ms
+///     > base64:AAAABAAAAAAA
+/// ").unwrap();
+///
+/// assert_eq!(capture.inbound().len(), 24 + 9);
+/// assert_eq!(capture.outbound().len(), 9);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Capture {
+    events: Vec<Event>,
+}
+
+/// The error returned when a capture dump is not valid.
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    msg: &'static str,
+}
+
+/// A transport replaying the bytes received on a captured connection.
+///
+/// Reads return the inbound chunks of the capture, split as they were
+/// captured, and the end of the stream once the whole capture was read. The
+/// pauses of the capture are waited for, unless [`ignore_timing`] is used.
+///
+/// Inbound bytes captured after outbound bytes are only returned once the
+/// client or server under test wrote at least as many complete frames as the
+/// capture did at that point, so that the peer's bytes follow the frames
+/// they answered, as they did on the captured connection.
+///
+/// The written bytes are kept, to be compared with the capture through an
+/// [`Outbound`] handle.
+///
+/// [`ignore_timing`]: #method.ignore_timing
+/// [`Outbound`]: struct.Outbound.html
+#[derive(Debug)]
+pub struct Replay {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// A handle to the bytes written to a [`Replay`] transport.
+///
+/// [`Replay`]: struct.Replay.html
+#[derive(Debug, Clone)]
+pub struct Outbound {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Debug, Clone)]
+enum Event {
+    /// Bytes received from the peer
+    Recv(Bytes),
+
+    /// Bytes sent to the peer
+    Send(Bytes),
+
+    Pause(Duration),
+}
+
+#[derive(Debug)]
+enum Step {
+    Read(Bytes),
+
+    /// Wait until this many frames were written
+    AwaitFrames(usize),
+
+    Pause(Duration),
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// The rest of the capture to replay
+    steps: VecDeque<Step>,
+
+    written: Vec<u8>,
+
+    is_timing_ignored: bool,
+
+    /// When the current pause ends
+    pause_until: Option<Instant>,
+
+    /// Waiting for frames to be written, or for a pause to end
+    read_task: Option<Task>,
+}
+
+/// The parts of a frame compared with a capture, leaving out the ones which
+/// may differ between two runs of the same connection.
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Data {
+        stream_id: StreamId,
+        len: usize,
+        is_end_stream: bool,
+    },
+    Headers {
+        stream_id: StreamId,
+        is_end_stream: bool,
+        pseudo: Pseudo,
+        fields: HeaderMap,
+    },
+    PushPromise {
+        stream_id: StreamId,
+        promised_id: StreamId,
+        pseudo: Pseudo,
+        fields: HeaderMap,
+    },
+    Settings {
+        is_ack: bool,
+    },
+    Ping {
+        is_ack: bool,
+    },
+    WindowUpdate {
+        stream_id: StreamId,
+    },
+    /// Compared as a whole
+    Other(frame::Frame),
+}
+
+// ===== impl Capture =====
+
+impl Capture {
+    /// Parses a capture dump.
+    pub fn parse(dump: &str) -> Result<Capture, ParseError> {
+        let mut events = vec![];
+
+        for (i, line) in dump.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(n) => &line[..n],
+                None => line,
+            }.trim();
+
+            let direction = match line.chars().next() {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let rest = line[direction.len_utf8()..].trim();
+            let error = |msg| ParseError { line: i + 1, msg };
+
+            let event = match direction {
+                '<' => Event::Recv(decode(rest).map_err(error)?),
+                '>' => Event::Send(decode(rest).map_err(error)?),
+                '@' => Event::Pause(parse_duration(rest).map_err(error)?),
+                _ => return Err(error("expected `<`, `>` or `@`")),
+            };
+
+            events.push(event);
+        }
+
+        Ok(Capture { events })
+    }
+
+    /// Returns all the bytes received from the peer.
+    pub fn inbound(&self) -> Bytes {
+        self.concat(|event| match *event {
+            Event::Recv(ref bytes) => Some(bytes),
+            _ => None,
+        })
+    }
+
+    /// Returns all the bytes sent to the peer.
+    pub fn outbound(&self) -> Bytes {
+        self.concat(|event| match *event {
+            Event::Send(ref bytes) => Some(bytes),
+            _ => None,
+        })
+    }
+
+    fn concat<F>(&self, f: F) -> Bytes
+    where
+        F: Fn(&Event) -> Option<&Bytes>,
+    {
+        let mut dst = vec![];
+
+        for bytes in self.events.iter().filter_map(f) {
+            dst.extend_from_slice(bytes);
+        }
+
+        dst.into()
+    }
+}
+
+fn decode(src: &str) -> Result<Bytes, &'static str> {
+    let bytes = if src.starts_with("base64:") {
+        decode_base64(&src["base64:".len()..])?
+    } else {
+        decode_hex(src)?
+    };
+
+    if bytes.is_empty() {
+        return Err("expected bytes");
+    }
+
+    Ok(bytes.into())
+}
+
+fn decode_hex(src: &str) -> Result<Vec<u8>, &'static str> {
+    let digits = src
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).ok_or("invalid hex digit"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if digits.len() % 2 != 0 {
+        return Err("odd number of hex digits");
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4 | pair[1]) as u8)
+        .collect())
+}
+
+fn decode_base64(src: &str) -> Result<Vec<u8>, &'static str> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    // Padding is optional.
+    let src = src.trim().bytes().filter(|&c| c != b'=');
+    let mut dst = vec![];
+
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in src {
+        let val = ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or("invalid base64 character")?;
+
+        acc = acc << 6 | val as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            dst.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    // A single character left encodes less than a byte.
+    if bits >= 6 {
+        return Err("truncated base64");
+    }
+
+    Ok(dst)
+}
+
+fn parse_duration(src: &str) -> Result<Duration, &'static str> {
+    let (val, millis) = if src.ends_with("ms") {
+        (&src[..src.len() - 2], 1)
+    } else if src.ends_with('s') {
+        (&src[..src.len() - 1], 1_000)
+    } else {
+        return Err("expected a duration in `ms` or `s`");
+    };
+
+    let val: u64 = val.trim().parse().map_err(|_| "invalid duration")?;
+    Ok(Duration::from_millis(val * millis))
+}
+
+// ===== impl ParseError =====
+
+impl ParseError {
+    /// Returns the number of the invalid line, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "invalid capture dump at line {}: {}", self.line, self.msg)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        self.msg
+    }
+}
+
+// ===== impl Replay =====
+
+impl Replay {
+    /// Returns a transport replaying the given capture.
+    pub fn new(capture: &Capture) -> Replay {
+        let mut steps = VecDeque::new();
+        let mut sent = vec![];
+
+        for event in &capture.events {
+            match *event {
+                Event::Recv(ref bytes) => steps.push_back(Step::Read(bytes.clone())),
+                Event::Send(ref bytes) => {
+                    sent.extend_from_slice(bytes);
+                    steps.push_back(Step::AwaitFrames(count_frames(&sent)));
+                },
+                Event::Pause(dur) => steps.push_back(Step::Pause(dur)),
+            }
+        }
+
+        let shared = Shared {
+            steps,
+            written: vec![],
+            is_timing_ignored: false,
+            pause_until: None,
+            read_task: None,
+        };
+
+        Replay {
+            shared: Arc::new(Mutex::new(shared)),
+        }
+    }
+
+    /// Skips the pauses of the capture.
+    pub fn ignore_timing(self) -> Replay {
+        self.shared.lock().unwrap().is_timing_ignored = true;
+        self
+    }
+
+    /// Returns a handle to the bytes written to this transport.
+    pub fn outbound(&self) -> Outbound {
+        Outbound {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl io::Read for Replay {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        let mut shared = self.shared.lock().unwrap();
+        let me = &mut *shared;
+
+        loop {
+            match me.steps.front_mut() {
+                Some(&mut Step::Read(ref mut chunk)) => {
+                    let n = cmp::min(dst.len(), chunk.len());
+                    dst[..n].copy_from_slice(&chunk[..n]);
+                    chunk.advance(n);
+
+                    if chunk.is_empty() {
+                        me.steps.pop_front();
+                    }
+
+                    return Ok(n);
+                },
+                Some(&mut Step::AwaitFrames(n)) => {
+                    if count_frames(&me.written) < n {
+                        me.read_task = Some(task::current());
+                        return Err(io::ErrorKind::WouldBlock.into());
+                    }
+                },
+                Some(&mut Step::Pause(dur)) => {
+                    if !me.is_timing_ignored {
+                        let now = Instant::now();
+
+                        let until = match me.pause_until {
+                            Some(until) => until,
+                            None => {
+                                let until = now + dur;
+                                me.pause_until = Some(until);
+                                wake_at(self.shared.clone(), until);
+                                until
+                            },
+                        };
+
+                        if now < until {
+                            me.read_task = Some(task::current());
+                            return Err(io::ErrorKind::WouldBlock.into());
+                        }
+
+                        me.pause_until = None;
+                    }
+                },
+                None => return Ok(0),
+            }
+
+            // The step is over.
+            me.steps.pop_front();
+        }
+    }
+}
+
+impl AsyncRead for Replay {}
+
+impl io::Write for Replay {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.written.extend_from_slice(src);
+
+        if let Some(task) = shared.read_task.take() {
+            task.notify();
+        }
+
+        Ok(src.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for Replay {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(().into())
+    }
+}
+
+/// Notifies the task reading `shared` once `until` is reached.
+fn wake_at(shared: Arc<Mutex<Shared>>, until: Instant) {
+    thread::spawn(move || {
+        let now = Instant::now();
+
+        if now < until {
+            thread::sleep(until - now);
+        }
+
+        if let Some(task) = shared.lock().unwrap().read_task.take() {
+            task.notify();
+        }
+    });
+}
+
+/// Returns the number of complete frames in the bytes written on a
+/// connection, after the client connection preface if any.
+fn count_frames(mut buf: &[u8]) -> usize {
+    if buf.starts_with(PREFACE) {
+        buf = &buf[PREFACE.len()..];
+    } else if PREFACE.starts_with(buf) {
+        return 0;
+    }
+
+    let mut n = 0;
+
+    while buf.len() >= frame::HEADER_LEN {
+        let len = (buf[0] as usize) << 16 | (buf[1] as usize) << 8 | buf[2] as usize;
+
+        if buf.len() < frame::HEADER_LEN + len {
+            break;
+        }
+
+        buf = &buf[frame::HEADER_LEN + len..];
+        n += 1;
+    }
+
+    n
+}
+
+// ===== impl Outbound =====
+
+impl Outbound {
+    /// Returns the bytes written so far.
+    pub fn bytes(&self) -> Bytes {
+        self.shared.lock().unwrap().written.clone().into()
+    }
+
+    /// Checks that the frames written so far match the frames sent on the
+    /// captured connection.
+    ///
+    /// Frames are compared by their type, stream, flags and header fields,
+    /// once decoded. The parameters of SETTINGS frames, the payloads of PING
+    /// frames, the increments of WINDOW_UPDATE frames and the contents of
+    /// DATA frames are not compared, as they may differ between two runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frames do not match, or are not valid.
+    pub fn assert_matches(&self, capture: &Capture) {
+        let expected = shapes(&capture.outbound());
+        let actual = shapes(&self.bytes());
+
+        for (i, (expected, actual)) in expected.iter().zip(&actual).enumerate() {
+            assert_eq!(actual, expected, "frame #{} does not match the capture", i);
+        }
+
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "number of frames does not match the capture"
+        );
+    }
+}
+
+/// Decodes the frames of the bytes written on a connection.
+fn shapes(mut buf: &[u8]) -> Vec<Shape> {
+    if buf.starts_with(PREFACE) {
+        buf = &buf[PREFACE.len()..];
+    }
+
+    let (mut tx, rx) = duplex();
+    tx.write_all(buf).unwrap();
+    drop(tx);
+
+    let mut codec: Codec<_, Cursor<Bytes>> = Codec::new(rx);
+    codec.set_max_recv_frame_size(frame::MAX_MAX_FRAME_SIZE as usize);
+
+    codec
+        .map(Shape::from)
+        .collect()
+        .wait()
+        .expect("invalid frames")
+}
+
+// ===== impl Shape =====
+
+impl From<frame::Frame> for Shape {
+    fn from(src: frame::Frame) -> Shape {
+        match src {
+            frame::Frame::Data(frame) => Shape::Data {
+                stream_id: frame.stream_id(),
+                len: frame.payload().len(),
+                is_end_stream: frame.is_end_stream(),
+            },
+            frame::Frame::Headers(frame) => {
+                let stream_id = frame.stream_id();
+                let is_end_stream = frame.is_end_stream();
+                let (pseudo, fields) = frame.into_parts();
+
+                Shape::Headers {
+                    stream_id,
+                    is_end_stream,
+                    pseudo,
+                    fields,
+                }
+            },
+            frame::Frame::PushPromise(frame) => {
+                let stream_id = frame.stream_id();
+                let promised_id = frame.promised_id();
+                let (pseudo, fields) = frame.into_parts();
+
+                Shape::PushPromise {
+                    stream_id,
+                    promised_id,
+                    pseudo,
+                    fields,
+                }
+            },
+            frame::Frame::Settings(frame) => Shape::Settings {
+                is_ack: frame.is_ack(),
+            },
+            frame::Frame::Ping(frame) => Shape::Ping {
+                is_ack: frame.is_ack(),
+            },
+            frame::Frame::WindowUpdate(frame) => Shape::WindowUpdate {
+                stream_id: frame.stream_id(),
+            },
+            frame => Shape::Other(frame),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Wakes connections up when their timeouts expire.
+//!
+//! With the `timer` feature, enabled by default, a connection owns a `Timer`,
+//! re-armed every time it is polled to the earliest deadline of its
+//! timeouts, which uses the default timer of the executor. Without the
+//! feature, or when the executor has no timer, `Timer` does nothing: the
+//! timeouts are only checked when the connection is polled for another
+//! reason.
+
+use std::time::Instant;
+
+#[cfg(feature = "timer")]
+use futures::{task, Async, Future};
+#[cfg(feature = "timer")]
+use tokio_timer::{clock, Delay};
+
+/// Returns the current instant.
+///
+/// With the `timer` feature, this is the default clock of `tokio-timer`,
+/// which may be mocked.
+#[cfg(feature = "timer")]
+pub(crate) fn now() -> Instant {
+    clock::now()
+}
+
+/// Returns the current instant.
+#[cfg(not(feature = "timer"))]
+pub(crate) fn now() -> Instant {
+    Instant::now()
+}
+
+/// Wakes the current task up at a deadline.
+#[derive(Debug, Default)]
+pub(crate) struct Timer {
+    #[cfg(feature = "timer")]
+    delay: Option<Delay>,
+
+    /// Set once polling the delay failed, e.g. because the executor has no
+    /// timer.
+    #[cfg(feature = "timer")]
+    is_unavailable: bool,
+}
+
+impl Timer {
+    /// Wakes the current task up at `deadline`, replacing the previous
+    /// deadline. `None` disarms the timer.
+    ///
+    /// A deadline that already passed wakes the task up right away.
+    #[cfg(feature = "timer")]
+    pub fn poll_at(&mut self, deadline: Option<Instant>) {
+        let deadline = match deadline {
+            Some(deadline) if !self.is_unavailable => deadline,
+            _ => {
+                self.delay = None;
+                return;
+            },
+        };
+
+        match self.delay {
+            Some(ref mut delay) => {
+                if delay.deadline() != deadline {
+                    delay.reset(deadline);
+                }
+            },
+            None => self.delay = Some(Delay::new(deadline)),
+        }
+
+        match self.delay.as_mut().unwrap().poll() {
+            Ok(Async::NotReady) => {},
+            Ok(Async::Ready(())) => {
+                self.delay = None;
+                task::current().notify();
+            },
+            Err(e) => {
+                warn!("timer unavailable; timeouts are only checked when polled; err={}", e);
+                self.delay = None;
+                self.is_unavailable = true;
+            },
+        }
+    }
+
+    /// Does nothing without the `timer` feature.
+    #[cfg(not(feature = "timer"))]
+    pub fn poll_at(&mut self, _deadline: Option<Instant>) {}
+}
+
+/// Returns the earliest of two optional deadlines.
+pub(crate) fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(::std::cmp::min(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
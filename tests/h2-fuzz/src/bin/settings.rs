@@ -0,0 +1,52 @@
+//! Feeds arbitrary bytes through SETTINGS frame parsing.
+//!
+//! Any frame that parses successfully is encoded and parsed again, and must
+//! encode to the same bytes the second time around.
+
+extern crate bytes;
+#[macro_use]
+extern crate honggfuzz;
+extern crate h2;
+
+use bytes::BytesMut;
+use h2::frame::{self, Head, Kind, Settings};
+
+fn run(data: &[u8]) {
+    if data.len() < frame::HEADER_LEN {
+        return;
+    }
+
+    let head = Head::parse(data);
+
+    if head.kind() != Kind::Settings {
+        return;
+    }
+
+    let settings = match Settings::load(head, &data[frame::HEADER_LEN..]) {
+        Ok(settings) => settings,
+        Err(_) => return,
+    };
+
+    let mut buf = BytesMut::with_capacity(data.len());
+    settings.encode(&mut buf);
+
+    let head = Head::parse(&buf);
+    let reloaded = Settings::load(head, &buf[frame::HEADER_LEN..])
+        .expect("failed to re-parse an encoded SETTINGS frame");
+
+    let mut buf2 = BytesMut::with_capacity(buf.len());
+    reloaded.encode(&mut buf2);
+
+    assert_eq!(buf, buf2);
+    assert_eq!(settings.is_ack(), reloaded.is_ack());
+    assert!(settings.iter().eq(reloaded.iter()));
+    assert!(settings.extensions().eq(reloaded.extensions()));
+}
+
+fn main() {
+    loop {
+        fuzz!(|data: &[u8]| {
+            run(data);
+        });
+    }
+}
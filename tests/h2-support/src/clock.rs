@@ -0,0 +1,103 @@
+//! A mocked clock, to test timeouts without waiting for them.
+
+use futures::{Async, Future};
+use futures::executor::{self, Notify};
+use tokio_executor;
+use tokio_executor::park::{Park, Unpark};
+use tokio_timer::{self, Delay, Timer};
+use tokio_timer::clock::{self, Clock, Now};
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::time::{Duration, Instant};
+
+/// Runs `future` to completion on the current thread, with a mocked clock
+/// and timer.
+///
+/// The clock only moves when `future` is idle: it then jumps to the deadline
+/// of the next timer. Panics if `future` is idle while no timer is set, as
+/// it would never complete.
+pub fn run<F: Future>(future: F) -> Result<F::Item, F::Error> {
+    let time = MockTime(Arc::new(Mutex::new(Instant::now())));
+    let clock = Clock::new_with_now(time.clone());
+    let mut enter = tokio_executor::enter().expect("nested executor");
+
+    clock::with_default(&clock, &mut enter, |enter| {
+        // Uses the default clock, which is the mocked one.
+        let mut timer = Timer::new(time);
+        let handle = timer.handle();
+
+        tokio_timer::with_default(&handle, enter, |_| {
+            let notify = Arc::new(Notified(AtomicBool::new(false)));
+            let mut future = executor::spawn(future);
+
+            loop {
+                notify.0.store(false, SeqCst);
+
+                if let Async::Ready(item) = future.poll_future_notify(&notify, 0)? {
+                    return Ok(item);
+                }
+
+                // Fires the timers that already elapsed, or jumps to the next
+                // deadline if nothing else can make progress.
+                let max_wait = if notify.0.load(SeqCst) {
+                    Some(Duration::from_secs(0))
+                } else {
+                    None
+                };
+
+                timer.turn(max_wait).unwrap();
+            }
+        })
+    })
+}
+
+/// Returns the current instant of the clock, mocked within `run`.
+pub fn now() -> Instant {
+    clock::now()
+}
+
+/// Returns a future completing once `dur` elapsed on the clock of `run`.
+pub fn delay(dur: Duration) -> Box<Future<Item = (), Error = ()>> {
+    Box::new(Delay::new(now() + dur).map_err(|e| panic!("timer err: {:?}", e)))
+}
+
+#[derive(Clone)]
+struct MockTime(Arc<Mutex<Instant>>);
+
+struct Notified(AtomicBool);
+
+impl Now for MockTime {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+impl Park for MockTime {
+    type Unpark = MockTime;
+    type Error = ();
+
+    fn unpark(&self) -> MockTime {
+        self.clone()
+    }
+
+    fn park(&mut self) -> Result<(), ()> {
+        panic!("the future is idle, and no timer is set");
+    }
+
+    fn park_timeout(&mut self, duration: Duration) -> Result<(), ()> {
+        *self.0.lock().unwrap() += duration;
+        Ok(())
+    }
+}
+
+impl Unpark for MockTime {
+    fn unpark(&self) {}
+}
+
+impl Notify for Notified {
+    fn notify(&self, _: usize) {
+        self.0.store(true, SeqCst);
+    }
+}
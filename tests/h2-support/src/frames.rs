@@ -57,6 +57,38 @@ where
     Mock(frame::GoAway::new(id.into(), frame::Reason::NO_ERROR))
 }
 
+pub fn priority<T1, T2>(id: T1, dependency: T2) -> Mock<frame::Priority>
+where
+    T1: Into<StreamId>,
+    T2: Into<StreamId>,
+{
+    Mock(frame::Priority::new(
+        id.into(),
+        frame::StreamDependency::new(dependency.into(), 15, false),
+    ))
+}
+
+pub fn priority_update<T>(id: T, field_value: &str) -> Mock<frame::PriorityUpdate>
+where
+    T: Into<StreamId>,
+{
+    Mock(frame::PriorityUpdate::new(id.into(), Bytes::from(field_value)))
+}
+
+pub fn alt_svc<T>(id: T, origin: &str, field_value: &str) -> Mock<frame::AltSvc>
+where
+    T: Into<StreamId>,
+{
+    Mock(frame::AltSvc::new(id.into(), Bytes::from(origin), Bytes::from(field_value)))
+}
+
+pub fn extension<T>(kind: u8, flags: u8, id: T, payload: &[u8]) -> Mock<frame::Extension>
+where
+    T: Into<StreamId>,
+{
+    Mock(frame::Extension::new(kind, flags, id.into(), Bytes::from(payload)))
+}
+
 pub fn reset<T>(id: T) -> Mock<frame::Reset>
 where
     T: Into<StreamId>,
@@ -147,11 +179,36 @@ impl Mock<frame::Headers> {
         Mock(frame::Headers::new(id, pseudo, fields))
     }
 
+    pub fn protocol(self, value: &str) -> Self
+    {
+        let (id, mut pseudo, fields) = self.into_parts();
+        pseudo.protocol = Some(::util::byte_str(value));
+
+        Mock(frame::Headers::new(id, pseudo, fields))
+    }
+
     pub fn eos(mut self) -> Self {
         self.0.set_end_stream();
         self
     }
 
+    /// Sets the stream dependency. The weight is the value sent on the wire,
+    /// one less than the actual weight.
+    pub fn dependency<T>(mut self, id: T, weight: u8, exclusive: bool) -> Self
+    where
+        T: Into<StreamId>,
+    {
+        self.0.set_stream_dependency(frame::StreamDependency::new(id.into(), weight, exclusive));
+        self
+    }
+
+    /// Sets the length of the padding. It must be set last, as building the
+    /// header block again loses it.
+    pub fn pad(mut self, pad_len: u8) -> Self {
+        self.0.set_pad_len(pad_len);
+        self
+    }
+
     pub fn into_fields(self) -> HeaderMap {
         self.0.into_parts().1
     }
@@ -177,6 +234,47 @@ impl From<Mock<frame::Headers>> for SendFrame {
     }
 }
 
+// Priority helpers
+
+impl Mock<frame::Priority> {
+    /// Sets the weight, as sent on the wire.
+    pub fn weight(self, weight: u8) -> Self {
+        let dep = self.0.dependency();
+        let dep = frame::StreamDependency::new(dep.dependency_id(), weight, dep.is_exclusive());
+        Mock(frame::Priority::new(self.0.stream_id(), dep))
+    }
+
+    pub fn exclusive(self) -> Self {
+        let dep = self.0.dependency();
+        let dep = frame::StreamDependency::new(dep.dependency_id(), dep.weight(), true);
+        Mock(frame::Priority::new(self.0.stream_id(), dep))
+    }
+}
+
+impl From<Mock<frame::Priority>> for SendFrame {
+    fn from(src: Mock<frame::Priority>) -> Self {
+        Frame::Priority(src.0)
+    }
+}
+
+impl From<Mock<frame::PriorityUpdate>> for SendFrame {
+    fn from(src: Mock<frame::PriorityUpdate>) -> Self {
+        Frame::PriorityUpdate(src.0)
+    }
+}
+
+impl From<Mock<frame::AltSvc>> for SendFrame {
+    fn from(src: Mock<frame::AltSvc>) -> Self {
+        Frame::AltSvc(src.0)
+    }
+}
+
+impl From<Mock<frame::Extension>> for SendFrame {
+    fn from(src: Mock<frame::Extension>) -> Self {
+        Frame::Extension(src.0)
+    }
+}
+
 // Data helpers
 
 impl Mock<frame::Data> {
@@ -184,15 +282,24 @@ impl Mock<frame::Data> {
         self.0.set_end_stream(true);
         self
     }
+
+    pub fn pad(mut self, pad_len: u8) -> Self {
+        self.0.set_pad_len(pad_len);
+        self
+    }
 }
 
 impl From<Mock<frame::Data>> for SendFrame {
     fn from(src: Mock<frame::Data>) -> Self {
         let id = src.0.stream_id();
         let eos = src.0.is_end_stream();
+        let pad_len = src.0.pad_len();
         let payload = src.0.into_payload();
         let mut frame = frame::Data::new(id, payload.into_buf());
         frame.set_end_stream(eos);
+        if let Some(pad_len) = pad_len {
+            frame.set_pad_len(pad_len);
+        }
         Frame::Data(frame)
     }
 }
@@ -265,6 +372,38 @@ impl Mock<frame::GoAway> {
             frame::Reason::NO_ERROR,
         ))
     }
+
+    pub fn calm(self) -> Self {
+        Mock(frame::GoAway::new(
+            self.0.last_stream_id(),
+            frame::Reason::ENHANCE_YOUR_CALM,
+        ))
+    }
+
+    pub fn settings_timeout(self) -> Self {
+        Mock(frame::GoAway::new(
+            self.0.last_stream_id(),
+            frame::Reason::SETTINGS_TIMEOUT,
+        ))
+    }
+
+    pub fn stream_closed(self) -> Self {
+        Mock(frame::GoAway::new(
+            self.0.last_stream_id(),
+            frame::Reason::STREAM_CLOSED,
+        ))
+    }
+
+    pub fn debug_data<T>(self, debug_data: T) -> Self
+    where
+        T: Into<Bytes>,
+    {
+        Mock(frame::GoAway::with_debug_data(
+            self.0.last_stream_id(),
+            self.0.reason(),
+            debug_data.into(),
+        ))
+    }
 }
 
 impl From<Mock<frame::GoAway>> for SendFrame {
@@ -286,6 +425,11 @@ impl Mock<frame::Reset> {
         Mock(frame::Reset::new(id, frame::Reason::FLOW_CONTROL_ERROR))
     }
 
+    pub fn frame_size(self) -> Self {
+        let id = self.0.stream_id();
+        Mock(frame::Reset::new(id, frame::Reason::FRAME_SIZE_ERROR))
+    }
+
     pub fn refused(self) -> Self {
         let id = self.0.stream_id();
         Mock(frame::Reset::new(id, frame::Reason::REFUSED_STREAM))
@@ -300,6 +444,11 @@ impl Mock<frame::Reset> {
         let id = self.0.stream_id();
         Mock(frame::Reset::new(id, frame::Reason::INTERNAL_ERROR))
     }
+
+    pub fn stream_closed(self) -> Self {
+        let id = self.0.stream_id();
+        Mock(frame::Reset::new(id, frame::Reason::STREAM_CLOSED))
+    }
 }
 
 impl From<Mock<frame::Reset>> for SendFrame {
@@ -321,10 +470,40 @@ impl Mock<frame::Settings> {
         self
     }
 
+    pub fn max_frame_size(mut self, val: u32) -> Self {
+        self.0.set_max_frame_size(Some(val));
+        self
+    }
+
     pub fn max_header_list_size(mut self, val: u32) -> Self {
         self.0.set_max_header_list_size(Some(val));
         self
     }
+
+    pub fn header_table_size(mut self, val: u32) -> Self {
+        self.0.set_header_table_size(Some(val));
+        self
+    }
+
+    pub fn enable_push(mut self, enable: bool) -> Self {
+        self.0.set_enable_push(enable);
+        self
+    }
+
+    pub fn enable_connect_protocol(mut self, val: u32) -> Self {
+        self.0.set_enable_connect_protocol(Some(val));
+        self
+    }
+
+    pub fn no_rfc7540_priorities(mut self, val: u32) -> Self {
+        self.0.set_no_rfc7540_priorities(Some(val));
+        self
+    }
+
+    pub fn extension(mut self, id: u16, val: u32) -> Self {
+        self.0.set_extension(id, val);
+        self
+    }
 }
 
 impl From<Mock<frame::Settings>> for frame::Settings {
@@ -7,14 +7,17 @@ pub extern crate futures;
 pub extern crate h2;
 pub extern crate http;
 pub extern crate string;
+extern crate tokio_executor;
 #[macro_use]
 pub extern crate tokio_io;
+extern crate tokio_timer_02 as tokio_timer;
 
 #[macro_use]
 mod assert;
 
 pub mod raw;
 
+pub mod clock;
 pub mod frames;
 pub mod prelude;
 pub mod mock;
@@ -52,6 +52,9 @@ struct Inner {
 
     /// True when the pipe is closed.
     closed: bool,
+
+    /// Error returned to the h2 lib once all data was read.
+    error: Option<(io::ErrorKind, &'static str)>,
 }
 
 const PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
@@ -71,6 +74,7 @@ pub fn new_with_write_capacity(cap: usize) -> (Mock, Handle) {
         tx_rem: cap,
         tx_rem_task: None,
         closed: false,
+        error: None,
     }));
 
     let mock = Mock {
@@ -306,6 +310,10 @@ impl io::Read for Mock {
         let mut me = self.pipe.inner.lock().unwrap();
 
         if me.rx.is_empty() {
+            if let Some((kind, msg)) = me.error {
+                return Err(io::Error::new(kind, msg));
+            }
+
             if me.closed {
                 return Ok(0);
             }
@@ -642,6 +650,22 @@ pub trait HandleFutureExt {
     {
         Box::new(self.map(drop))
     }
+
+    /// Closes the pipe, failing reads with an I/O error of `kind`.
+    fn close_with_error(self, kind: io::ErrorKind, msg: &'static str)
+        -> Box<Future<Item = (), Error = ()>>
+    where
+        Self: Future<Item = Handle, Error = ()> + Sized + 'static,
+    {
+        Box::new(self.map(move |handle| {
+            let mut me = handle.codec.get_ref().inner.lock().unwrap();
+            me.error = Some((kind, msg));
+
+            if let Some(task) = me.rx_task.take() {
+                task.notify();
+            }
+        }))
+    }
 }
 
 pub struct RecvFrame<T> {
@@ -10,6 +10,9 @@ pub use self::h2::server;
 // Re-export mock
 pub use super::mock::{self, HandleFutureExt};
 
+// Re-export the mocked clock
+pub use super::clock;
+
 // Re-export frames helpers
 pub use super::frames;
 
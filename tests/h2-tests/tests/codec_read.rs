@@ -5,6 +5,15 @@ use h2_support::prelude::*;
 
 use std::error::Error;
 
+macro_rules! assert_protocol_error {
+    ($codec:expr) => {{
+        match poll_err!($codec) {
+            RecvError::Connection(reason) => assert_eq!(reason, Reason::PROTOCOL_ERROR),
+            e => panic!("unexpected error; err={:?}", e),
+        }
+    }};
+}
+
 #[test]
 fn read_none() {
     let mut codec = Codec::from(mock_io::Builder::new().build());
@@ -87,6 +96,86 @@ fn read_data_padding() {
     assert_closed!(codec);
 }
 
+#[test]
+fn read_data_padding_longer_than_data() {
+    let mut codec = raw_codec! {
+        read => [
+            0, 0, 10, 0, 0x8, 0, 0, 0, 1,
+            7,    // Pad length
+            "hi", // Data
+            "padding",
+        ];
+    };
+
+    // The content of the padding is not checked
+    let data = poll_frame!(Data, codec);
+    assert_eq!(data.stream_id(), 1);
+    assert_eq!(data.payload(), &b"hi"[..]);
+    assert_eq!(data.pad_len(), Some(7));
+
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_data_padding_fills_payload() {
+    let mut codec = raw_codec! {
+        read => [
+            0, 0, 6, 0, 0x8, 0, 0, 0, 1,
+            5, // Pad length, one less than the payload length
+            "hello",
+        ];
+    };
+
+    let data = poll_frame!(Data, codec);
+    assert_eq!(data.stream_id(), 1);
+    assert_eq!(data.payload(), &b""[..]);
+    assert_eq!(data.pad_len(), Some(5));
+
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_data_padding_too_long() {
+    let mut codec = raw_codec! {
+        read => [
+            0, 0, 6, 0, 0x8, 0, 0, 0, 1,
+            6, // Pad length, equal to the payload length
+            "hello",
+        ];
+    };
+
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_data_small_payload_is_copied() {
+    let mut codec = raw_codec! {
+        read => [
+            0, 0x10, 0, 0, 0, 0, 0, 0, 1,
+            vec![1; 4_096],
+            0, 0, 100, 0, 0, 0, 0, 0, 1,
+            vec![2; 100],
+        ];
+    };
+
+    let large = poll_frame!(Data, codec).into_payload();
+    assert_eq!(large, vec![1; 4_096]);
+
+    let small = poll_frame!(Data, codec).into_payload();
+    assert_eq!(small, vec![2; 100]);
+
+    // The small payload does not keep the read buffer alive
+    assert!(small.try_mut().is_ok());
+
+    // The large payload is a slice of the read buffer
+    let large = large.try_mut().unwrap_err();
+
+    assert_closed!(codec);
+    drop(codec);
+
+    assert!(large.try_mut().is_ok());
+}
+
 #[test]
 fn read_push_promise() {
     let mut codec = raw_codec! {
@@ -133,6 +222,68 @@ fn read_headers_with_pseudo() {}
 #[ignore]
 fn read_headers_empty_payload() {}
 
+#[test]
+fn read_headers_padding() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/ END_HEADERS and PADDED, `:method: GET`
+            0, 0, 5, 1, 0xc, 0, 0, 0, 1,
+            3, // Pad length
+            0x82,
+            "pad",
+        ];
+    };
+
+    let headers = poll_frame!(Headers, codec);
+    assert_eq!(headers.stream_id(), 1);
+    assert_eq!(headers.pad_len(), Some(3));
+    assert_eq!(headers.into_parts().0.method, Some(Method::GET));
+
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_headers_invalid_huffman_string() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/ END_HEADERS, `:path` w/ the EOS symbol
+            0, 0, 6, 1, 0x4, 0, 0, 0, 1,
+            0x04, 0x84, 0xff, 0xff, 0xff, 0xff,
+        ];
+    };
+
+    match poll_err!(codec) {
+        RecvError::Connection(reason) => assert_eq!(reason, Reason::COMPRESSION_ERROR),
+        e => panic!("unexpected error; err={:?}", e),
+    }
+
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/ END_HEADERS, `:path: o` padded w/ `110`
+            0, 0, 3, 1, 0x4, 0, 0, 0, 1,
+            0x04, 0x81, 0b00111110,
+        ];
+    };
+
+    match poll_err!(codec) {
+        RecvError::Connection(reason) => assert_eq!(reason, Reason::COMPRESSION_ERROR),
+        e => panic!("unexpected error; err={:?}", e),
+    }
+
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/ END_HEADERS, `:path: o` padded w/ 11 bits
+            0, 0, 4, 1, 0x4, 0, 0, 0, 1,
+            0x04, 0x82, 0b00111111, 0xff,
+        ];
+    };
+
+    match poll_err!(codec) {
+        RecvError::Connection(reason) => assert_eq!(reason, Reason::COMPRESSION_ERROR),
+        e => panic!("unexpected error; err={:?}", e),
+    }
+}
+
 #[test]
 fn read_continuation_frames() {
     let _ = ::env_logger::try_init();
@@ -189,6 +340,225 @@ fn read_continuation_frames() {
 
 }
 
+// ===== CONTINUATION =====
+
+#[test]
+fn read_continuation_back_to_back() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS, `:method: GET`
+            0, 0, 1, 1, 0x1, 0, 0, 0, 1,
+            0x82,
+            // CONTINUATION w/o END_HEADERS, `:scheme: https`
+            0, 0, 1, 9, 0, 0, 0, 0, 1,
+            0x87,
+            // CONTINUATION w/ END_HEADERS, `:path: /`
+            0, 0, 1, 9, 0x4, 0, 0, 0, 1,
+            0x84,
+            // PING
+            0, 0, 8, 6, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+    };
+
+    let headers = poll_frame!(Headers, codec);
+    assert_eq!(headers.stream_id(), 1);
+    assert!(headers.is_end_stream());
+
+    poll_frame!(Ping, codec);
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_representation_split_across_continuation() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS, `:method: GET` and the start of
+            // `:path: /a`
+            0, 0, 3, 1, 0x1, 0, 0, 0, 1,
+            0x82, 0x04, 2,
+            // CONTINUATION w/o END_HEADERS, the rest of `:path: /a`
+            0, 0, 2, 9, 0, 0, 0, 0, 1,
+            b'/', b'a',
+            // CONTINUATION w/ END_HEADERS, `:scheme: https`
+            0, 0, 1, 9, 0x4, 0, 0, 0, 1,
+            0x87,
+        ];
+    };
+
+    let headers = poll_frame!(Headers, codec);
+    let pseudo = headers.into_parts().0;
+    assert_eq!(pseudo.method, Some(Method::GET));
+    assert_eq!(&pseudo.path.unwrap()[..], "/a");
+    assert_eq!(&pseudo.scheme.unwrap()[..], "https");
+
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_size_update_in_continuation_after_header() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS, `:method: GET`
+            0, 0, 1, 1, 0x1, 0, 0, 0, 1,
+            0x82,
+            // CONTINUATION w/ END_HEADERS, a dynamic table size update
+            0, 0, 1, 9, 0x4, 0, 0, 0, 1,
+            0x20,
+        ];
+    };
+
+    match poll_err!(codec) {
+        RecvError::Connection(reason) => assert_eq!(reason, Reason::COMPRESSION_ERROR),
+        e => panic!("unexpected error; err={:?}", e),
+    }
+}
+
+#[test]
+fn read_data_during_continuation() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS
+            0, 0, 1, 1, 0, 0, 0, 0, 1,
+            0x82,
+            // DATA on the same stream
+            0, 0, 5, 0, 0, 0, 0, 0, 1,
+            "hello",
+        ];
+    };
+
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_ping_during_continuation() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS
+            0, 0, 1, 1, 0, 0, 0, 0, 1,
+            0x82,
+            // PING
+            0, 0, 8, 6, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+    };
+
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_headers_on_other_stream_during_continuation() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS
+            0, 0, 1, 1, 0, 0, 0, 0, 1,
+            0x82,
+            // HEADERS w/ END_HEADERS on stream 3
+            0, 0, 1, 1, 0x4, 0, 0, 0, 3,
+            0x82,
+        ];
+    };
+
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_continuation_on_wrong_stream() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS
+            0, 0, 1, 1, 0, 0, 0, 0, 1,
+            0x82,
+            // CONTINUATION w/ END_HEADERS on stream 3
+            0, 0, 1, 9, 0x4, 0, 0, 0, 3,
+            0x87,
+        ];
+    };
+
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_continuation_without_header_block() {
+    let mut codec = raw_codec! {
+        read => [
+            // CONTINUATION w/ END_HEADERS
+            0, 0, 1, 9, 0x4, 0, 0, 0, 1,
+            0x82,
+        ];
+    };
+
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_continuation_after_end_headers() {
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/ END_HEADERS
+            0, 0, 1, 1, 0x4, 0, 0, 0, 1,
+            0x82,
+            // CONTINUATION
+            0, 0, 1, 9, 0x4, 0, 0, 0, 1,
+            0x87,
+        ];
+    };
+
+    poll_frame!(Headers, codec);
+    assert_protocol_error!(codec);
+}
+
+#[test]
+fn read_continuation_flood() {
+    let _ = ::env_logger::try_init();
+
+    // 10,000 empty CONTINUATION frames
+    let continuations = (0..10_000).fold(vec![], |mut buf, _| {
+        buf.extend_from_slice(&[0, 0, 0, 9, 0, 0, 0, 0, 1]);
+        buf
+    });
+
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS, `:method: GET`
+            0, 0, 1, 1, 0, 0, 0, 0, 1,
+            0x82,
+            continuations,
+        ];
+    };
+
+    match poll_err!(codec) {
+        RecvError::Connection(reason) => assert_eq!(reason, Reason::ENHANCE_YOUR_CALM),
+        e => panic!("unexpected error; err={:?}", e),
+    }
+}
+
+#[test]
+fn read_header_block_too_big() {
+    let _ = ::env_logger::try_init();
+
+    let mut codec = raw_codec! {
+        read => [
+            // HEADERS w/o END_HEADERS, `:method: GET`
+            0, 0, 1, 1, 0, 0, 0, 0, 1,
+            0x82,
+            // CONTINUATION w/o END_HEADERS, `:scheme: https`
+            0, 0, 1, 9, 0, 0, 0, 0, 1,
+            0x87,
+            // CONTINUATION w/ END_HEADERS, `:path: /`
+            0, 0, 1, 9, 0x4, 0, 0, 0, 1,
+            0x84,
+        ];
+    };
+
+    codec.set_max_recv_header_block_size(2);
+
+    match poll_err!(codec) {
+        RecvError::Connection(reason) => assert_eq!(reason, Reason::ENHANCE_YOUR_CALM),
+        e => panic!("unexpected error; err={:?}", e),
+    }
+}
+
 #[test]
 fn update_max_frame_len_at_rest() {
     let _ = ::env_logger::try_init();
@@ -212,3 +582,66 @@ fn update_max_frame_len_at_rest() {
         "frame with invalid size"
     );
 }
+
+#[test]
+fn read_reserved_flags_are_ignored_by_default() {
+    let mut codec = raw_codec! {
+        read => [
+            // SETTINGS w/ 0x80
+            0, 0, 0, 4, 0x80, 0, 0, 0, 0,
+            // PING ACK w/ 0x2
+            0, 0, 8, 6, 0x3, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            // GOAWAY w/ 0x4
+            0, 0, 8, 7, 0x4, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+    };
+
+    let settings = poll_frame!(Settings, codec);
+    assert!(!settings.is_ack());
+    assert_eq!(settings.flags(), 0x80);
+
+    let ping = poll_frame!(Ping, codec);
+    assert!(ping.is_ack());
+    assert_eq!(ping.flags(), 0x3);
+
+    let go_away = poll_frame!(GoAway, codec);
+    assert_eq!(go_away.flags(), 0x4);
+
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_reserved_flags_strict() {
+    let mut codec = raw_codec! {
+        read => [
+            // PING w/ 0x2
+            0, 0, 8, 6, 0x2, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+    };
+
+    codec.set_strict_flags(true);
+
+    poll_err!(codec);
+}
+
+#[test]
+fn read_known_flags_strict() {
+    let mut codec = raw_codec! {
+        read => [
+            // SETTINGS ACK
+            0, 0, 0, 4, 0x1, 0, 0, 0, 0,
+            // DATA w/ reserved flags, which are not checked
+            0, 0, 5, 0, 0x2, 0, 0, 0, 1,
+            "hello",
+        ];
+    };
+
+    codec.set_strict_flags(true);
+
+    assert!(poll_frame!(Settings, codec).is_ack());
+    assert_eq!(poll_frame!(Data, codec).payload(), &b"hello"[..]);
+    assert_closed!(codec);
+}
@@ -61,3 +61,37 @@ fn write_continuation_frames() {
 
     client.join(srv).wait().expect("wait");
 }
+
+#[test]
+fn encode_duplicate_names_in_order() {
+    use h2::frame::{Headers, Pseudo};
+    use h2::hpack::Decoder;
+    use h2::hpack::raw::Encoder;
+
+    let mut encoder = Encoder::default();
+    let mut decoder = Decoder::new(4096);
+    let mut dst = BytesMut::with_capacity(1024);
+
+    let mut fields = HeaderMap::new();
+    fields.append("cookie", "a=1".parse().unwrap());
+    fields.insert("accept", "*/*".parse().unwrap());
+    fields.append("cookie", "b=2".parse().unwrap());
+    fields.append("cookie", "c=3".parse().unwrap());
+
+    let pseudo = Pseudo::request(Method::GET, "https://example.com/".parse().unwrap());
+    assert!(Headers::new(StreamId::from(1), pseudo, fields)
+        .encode(&mut encoder, &mut dst, |_| 0)
+        .is_none());
+
+    // Skip the frame head
+    let block = dst.split_off(9);
+
+    let cookies: Vec<_> = decoder.decode(&block)
+        .unwrap()
+        .into_iter()
+        .filter(|field| field.name() == "cookie")
+        .map(|field| String::from_utf8(field.value().to_vec()).unwrap())
+        .collect();
+
+    assert_eq!(cookies, ["a=1", "b=2", "c=3"]);
+}
@@ -317,9 +317,131 @@ fn recv_data_overflows_stream_window() {
 
 
 #[test]
-#[ignore]
-fn recv_window_update_causes_overflow() {
-    // A received window update causes the window to overflow.
+fn recv_window_update_causes_stream_overflow() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        // The stream window would be 2^31 - 1 + 1
+        .send_frame(frames::window_update(1, (1 << 31) - 1 - 65_535 + 1))
+        .recv_frame(frames::reset(1).flow_control())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let (resp, req_body) = client.send_request(request, false).unwrap();
+
+            let resp = resp.then(|res| {
+                let err = res.expect_err("response");
+                assert_eq!(err.reason(), Some(Reason::FLOW_CONTROL_ERROR));
+                Ok::<_, ()>(())
+            });
+
+            conn.drive(resp)
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(move |_| drop((client, req_body)))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn recv_window_update_causes_connection_overflow() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        // The connection window would be 2^31 - 1 + 1
+        .send_frame(frames::window_update(0, (1 << 31) - 1 - 65_535 + 1))
+        .recv_frame(frames::go_away(0).flow_control())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(client, conn)| {
+            conn.then(move |res| {
+                let err = res.expect_err("connection");
+                assert_eq!(err.reason(), Some(Reason::FLOW_CONTROL_ERROR));
+                drop(client);
+                Ok::<_, ()>(())
+            })
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn recv_stream_window_update_with_zero_increment() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        .send_frame(frames::window_update(1, 0))
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let (resp, req_body) = client.send_request(request, false).unwrap();
+
+            let resp = resp.then(|res| {
+                let err = res.expect_err("response");
+                assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                Ok::<_, ()>(())
+            });
+
+            conn.drive(resp)
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(move |_| drop((client, req_body)))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn recv_connection_window_update_with_zero_increment() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::window_update(0, 0))
+        .recv_frame(frames::go_away(0).protocol_error())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(client, conn)| {
+            conn.then(move |res| {
+                let err = res.expect_err("connection");
+                assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                drop(client);
+                Ok::<_, ()>(())
+            })
+        });
+
+    srv.join(client).wait().unwrap();
 }
 
 #[test]
@@ -542,8 +664,60 @@ fn stream_close_by_trailers_frame_releases_capacity() {
 }
 
 #[test]
-#[ignore]
-fn stream_close_by_send_reset_frame_releases_capacity() {}
+fn stream_close_by_dropping_response_body_releases_capacity() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+                .eos()
+        )
+        .send_frame(frames::headers(1).response(200))
+        // Use up the whole connection window on the stream
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_383]))
+        // The unread data is released once the response body is dropped
+        .recv_frame(frames::window_update(0, 65_535))
+        .recv_frame(frames::reset(1).cancel())
+        // Data sent before receiving the reset is released as it arrives
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::window_update(0, 32_768))
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            // The request is complete, the response handles are the only
+            // ones left.
+            let (response, _) = client.send_request(request, true).unwrap();
+
+            let res = response
+                .and_then(|res| {
+                    let body = res.into_parts().1;
+                    body.into_future().map_err(|(e, _)| e)
+                })
+                .map(|(chunk, body)| {
+                    assert_eq!(chunk.unwrap().len(), 16_384);
+                    drop(body);
+                });
+
+            conn.drive(res)
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
 
 #[test]
 #[ignore]
@@ -732,7 +906,13 @@ fn connection_notified_on_released_capacity() {
 
 
     let th2 = thread::spawn(move || {
-        let (mut client, h2) = client::handshake(io).wait().unwrap();
+        // Only a quarter of the connection window is released below, lower
+        // the threshold so that it is announced.
+        let (mut client, h2) = client::Builder::new()
+            .window_update_threshold(25)
+            .handshake::<_, Bytes>(io)
+            .wait()
+            .unwrap();
 
         let (h2, _) = h2.drive(settings_rx).wait().unwrap();
 
@@ -963,6 +1143,83 @@ fn recv_no_init_window_then_receive_some_init_window() {
         .wait().unwrap();
 }
 
+#[test]
+fn send_cursor_body_split_by_flow_control() {
+    use std::io::Cursor;
+
+    let body = Cursor::new(b"hello world".to_vec());
+    send_body_split_by_flow_control(body, b"hello", b" world");
+}
+
+#[test]
+fn send_chained_body_split_by_flow_control() {
+    use std::io::Cursor;
+
+    // The payloads are long enough to be written without being copied.
+    let body = Cursor::new(vec![1; 300]).chain(Cursor::new(vec![2; 300]));
+
+    let mut first = vec![1; 300];
+    first.extend(vec![2; 100]);
+
+    send_body_split_by_flow_control(body, &first, &[2; 200]);
+}
+
+/// Sends `body` as a request body on a stream whose window only fits
+/// `first`, the beginning of the body.
+fn send_body_split_by_flow_control<B>(body: B, first: &[u8], rest: &[u8])
+where
+    B: IntoBuf + ::std::fmt::Debug + 'static,
+    B::Buf: ::std::fmt::Debug + 'static,
+{
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(first.len() as u32));
+
+    let srv = srv.assert_client_handshake_with_settings(settings).unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        .recv_frame(frames::data(1, first.to_vec()))
+        .send_frame(frames::window_update(1, rest.len() as u32))
+        .recv_frame(frames::data(1, rest.to_vec()).eos())
+        .send_frame(
+            frames::headers(1)
+                .response(204)
+                .eos()
+        )
+        .close();
+
+    let h2 = client::Builder::new()
+        .handshake::<_, B>(io)
+        .unwrap()
+        .and_then(|(mut client, h2)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+
+            // The body is buffered, then sent as the window allows.
+            stream.send_data(body, true).unwrap();
+
+            h2.drive(response).map(|c| (c, client))
+        })
+        .and_then(|((h2, response), client)| {
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            // Hold on to the `client` handle to avoid sending a GO_AWAY frame.
+            h2.unwrap().map(|c| (c, client))
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
 #[test]
 fn settings_lowered_capacity_returns_capacity_to_connection() {
     use std::sync::mpsc;
@@ -1106,6 +1363,105 @@ fn client_increase_target_window_size() {
     srv.join(client).wait().unwrap();
 }
 
+#[test]
+fn initial_connection_window_size_sent_on_handshake() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    // The increment is below the WINDOW_UPDATE threshold, it must be sent
+    // anyway.
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::window_update(0, 100_000 - 65_535))
+        .close();
+
+    let client = client::Builder::new()
+        .initial_connection_window_size(100_000)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|(_client, conn)| {
+            conn.unwrap().map(|c| (c, _client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+#[should_panic]
+fn initial_connection_window_size_too_big() {
+    client::Builder::new().initial_connection_window_size(1 << 31);
+}
+
+#[test]
+fn concurrent_bodies_not_limited_by_connection_window() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::window_update(0, 3 * 65_535))
+        .recv_frame(frames::headers(1).request("GET", "https://http2.akamai.com/").eos())
+        .recv_frame(frames::headers(3).request("GET", "https://http2.akamai.com/").eos())
+        .recv_frame(frames::headers(5).request("GET", "https://http2.akamai.com/").eos())
+        .recv_frame(frames::headers(7).request("GET", "https://http2.akamai.com/").eos())
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::headers(3).response(200))
+        .send_frame(frames::headers(5).response(200))
+        .send_frame(frames::headers(7).response(200));
+
+    let mut srv: Box<Future<Item = mock::Handle, Error = ()>> = Box::new(srv);
+
+    // Fill every stream window, four times the default connection window.
+    for _ in 0..3 {
+        for &id in &[1, 3, 5, 7] {
+            srv = Box::new(srv.send_frame(frames::data(id, vec![0; 16_384])));
+        }
+    }
+    for &id in &[1, 3, 5, 7] {
+        srv = Box::new(srv.send_frame(frames::data(id, vec![0; 16_383]).eos()));
+    }
+
+    let srv = srv.close();
+
+    let client = client::Builder::new()
+        .initial_connection_window_size(4 * 65_535)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|(mut client, conn)| {
+            let bodies = (0..4).map(|_| {
+                let request = Request::builder()
+                    .uri("https://http2.akamai.com/")
+                    .body(()).unwrap();
+
+                client.send_request(request, true).unwrap().0
+                    .and_then(|res| {
+                        let mut body = res.into_parts().1;
+                        // Hold on to the capacity, the peer does not expect
+                        // any WINDOW_UPDATE.
+                        let cap = body.release_capacity().clone();
+                        body.concat2().map(move |bytes| (bytes, cap))
+                    })
+            }).collect::<Vec<_>>();
+
+            conn.drive(::futures::future::join_all(bodies))
+                .and_then(|(conn, bodies)| {
+                    for &(ref bytes, _) in &bodies {
+                        assert_eq!(bytes.len(), 65_535);
+                    }
+
+                    conn.unwrap().map(move |c| {
+                        drop(bodies);
+                        c
+                    })
+                })
+                .map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
 #[test]
 fn increase_target_window_size_after_using_some() {
     let _ = ::env_logger::try_init();
@@ -1132,14 +1488,20 @@ fn increase_target_window_size_after_using_some() {
 
             let res = client.send_request(request, true).unwrap().0
                 .and_then(|res| {
-                    // "leak" the capacity for now
-                    res.into_parts().1.concat2()
+                    let mut body = res.into_parts().1;
+                    // "leak" the capacity for now, dropping every handle
+                    // would release it
+                    let cap = body.release_capacity().clone();
+                    body.concat2().map(move |bytes| (bytes, cap))
                 });
 
             conn.drive(res)
-                .and_then(|(mut conn, _bytes)| {
+                .and_then(|(mut conn, (_bytes, cap))| {
                     conn.set_target_window_size(2 << 20);
-                    conn.unwrap()
+                    conn.unwrap().map(move |c| {
+                        drop(cap);
+                        c
+                    })
                 }).map(|c| (c, client))
         });
 
@@ -1261,10 +1623,13 @@ fn recv_settings_increase_window_size_after_using_some() {
 }
 
 #[test]
-fn reserve_capacity_after_peer_closes() {
-    // See https://github.com/carllerche/h2/issues/300
+fn stream_reset_while_pending_connection_capacity() {
+    use futures::sync::oneshot;
+
     let _ = ::env_logger::try_init();
     let (io, srv) = mock::new();
+    let (sent_tx, sent_rx) = oneshot::channel();
+    let (queued_tx, queued_rx) = oneshot::channel();
 
     let srv = srv.assert_client_handshake()
         .unwrap()
@@ -1273,23 +1638,246 @@ fn reserve_capacity_after_peer_closes() {
             frames::headers(1)
                 .request("POST", "https://http2.akamai.com/")
         )
-        // close connection suddenly
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_383]).eos())
+        .map(move |srv| {
+            sent_tx.send(()).unwrap();
+            srv
+        })
+        .recv_frame(frames::headers(3).request("POST", "https://http2.akamai.com/"))
+        .recv_frame(frames::headers(5).request("POST", "https://http2.akamai.com/"))
+        .recv_frame(frames::headers(7).request("POST", "https://http2.akamai.com/"))
+        .map(move |srv| {
+            queued_tx.send(()).unwrap();
+            srv
+        })
+        .recv_frame(frames::reset(5).cancel())
+        // The streams waiting for connection capacity are served in order,
+        // skipping the reset one.
+        .send_frame(frames::window_update(0, 30))
+        .recv_frame(frames::data(3, vec![0; 10]).eos())
+        .recv_frame(frames::data(7, vec![0; 10]).eos())
+        .send_frame(frames::headers(1).response(200).eos())
+        .send_frame(frames::headers(3).response(200).eos())
+        .send_frame(frames::headers(7).response(200).eos())
         .close();
 
+    fn send_request(
+        client: &mut client::SendRequest<Bytes>,
+        body: Vec<u8>,
+    ) -> (client::ResponseFuture, SendStream<Bytes>) {
+        let request = Request::builder()
+            .method("POST")
+            .uri("https://http2.akamai.com/")
+            .body(()).unwrap();
+        let (resp, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data(body.into(), true).unwrap();
+        (resp, stream)
+    }
+
     let client = client::handshake(io).unwrap()
         .and_then(|(mut client, conn)| {
-            let request = Request::builder()
-                .method("POST")
-                .uri("https://http2.akamai.com/")
-                .body(()).unwrap();
-            let (resp, req_body) = client.send_request(request, false).unwrap();
-            conn.drive(resp.then(move |result| {
-                assert!(result.is_err());
-                Ok::<_, ()>(req_body)
-            }))
-        })
-        .and_then(|(conn, mut req_body)| {
-            // As stated in #300, this would panic because the connection
+            // The first request uses up the connection window.
+            let (resp1, _) = send_request(&mut client, vec![0; 65_535]);
+
+            conn.drive(sent_rx.expect("sent"))
+                .and_then(move |(conn, _)| {
+                    let (resp3, _) = send_request(&mut client, vec![0; 10]);
+                    let (_, stream5) = send_request(&mut client, vec![0; 10]);
+                    let (resp7, _) = send_request(&mut client, vec![0; 10]);
+
+                    let resps = resp1.join3(resp3, resp7).expect("responses");
+
+                    conn.drive(queued_rx.expect("queued"))
+                        .map(move |(conn, _)| (conn, client, stream5, resps))
+                })
+                .and_then(|(conn, client, mut stream5, resps)| {
+                    stream5.send_reset(Reason::CANCEL);
+                    conn.drive(resps).map(move |c| (c, client))
+                })
+                .and_then(|((conn, (resp1, resp3, resp7)), client)| {
+                    assert_eq!(resp1.status(), StatusCode::OK);
+                    assert_eq!(resp3.status(), StatusCode::OK);
+                    assert_eq!(resp7.status(), StatusCode::OK);
+
+                    conn.expect("client").map(move |_| drop(client))
+                })
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn send_data_trickles_through_connection_window() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_383]))
+        // The stream window fits the rest of the body, but the connection
+        // window only grows a single byte at a time.
+        .send_frame(frames::settings().initial_window_size(65_538))
+        .recv_frame(frames::settings_ack())
+        .send_frame(frames::window_update(0, 1))
+        .recv_frame(frames::data(1, vec![0; 1]))
+        .send_frame(frames::window_update(0, 1))
+        .recv_frame(frames::data(1, vec![0; 1]))
+        .send_frame(frames::window_update(0, 1))
+        .recv_frame(frames::data(1, vec![0; 1]).eos())
+        .send_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let client = client::Builder::new()
+        .handshake::<_, Vec<u8>>(io)
+        .unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let (resp, mut req_body) = client.send_request(request, false).unwrap();
+            req_body.send_data(vec![0; 65_538], true).unwrap();
+            conn.drive(resp.expect("response")).map(|c| (c, client))
+        })
+        .and_then(|((conn, _res), client)| {
+            conn.expect("client").map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn recv_settings_shrinks_window_below_bytes_sent() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        .recv_frame(frames::data(1, vec![0; 20]))
+        // The stream window is now 10 - 20 = -10
+        .send_frame(frames::settings().initial_window_size(10))
+        .recv_frame(frames::settings_ack())
+        // Back to zero, still nothing can be sent
+        .send_frame(frames::window_update(1, 10))
+        .idle_ms(20)
+        .send_frame(frames::window_update(1, 15))
+        .recv_frame(frames::data(1, vec![0; 15]).eos())
+        .send_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let (resp, mut req_body) = client.send_request(request, false).unwrap();
+            req_body.send_data(vec![0; 20].into(), false).unwrap();
+
+            // Wait for the smaller initial window to be applied
+            let changes = conn.settings_changes()
+                .skip_while(|settings| Ok(settings.initial_window_size() != Some(10)))
+                .into_future()
+                .map_err(|(e, _)| e);
+
+            conn.drive(changes)
+                .map(move |(conn, _)| (conn, client, resp, req_body))
+        })
+        .and_then(|(conn, client, resp, mut req_body)| {
+            req_body.send_data(vec![0; 15].into(), true).unwrap();
+            conn.drive(resp.expect("response")).map(|c| (c, client))
+        })
+        .and_then(|((conn, _res), client)| {
+            conn.expect("client").map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn recv_settings_window_size_increase_overflows_stream_window() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        .send_frame(frames::window_update(1, (1 << 31) - 1 - 65_535))
+        // The stream window would be 2^31 - 1 + 1
+        .send_frame(frames::settings().initial_window_size(65_536))
+        .recv_frame(frames::settings_ack())
+        .recv_frame(frames::go_away(0).flow_control())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let (_resp, req_body) = client.send_request(request, false).unwrap();
+
+            conn.then(move |res| {
+                let err = res.expect_err("connection");
+                assert_eq!(err.reason(), Some(Reason::FLOW_CONTROL_ERROR));
+                drop((client, req_body));
+                Ok::<_, ()>(())
+            })
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn reserve_capacity_after_peer_closes() {
+    // See https://github.com/carllerche/h2/issues/300
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        // close connection suddenly
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let (resp, req_body) = client.send_request(request, false).unwrap();
+            conn.drive(resp.then(move |result| {
+                assert!(result.is_err());
+                Ok::<_, ()>(req_body)
+            }))
+        })
+        .and_then(|(conn, mut req_body)| {
+            // As stated in #300, this would panic because the connection
             // had already been closed.
             req_body.reserve_capacity(1);
             conn.expect("client")
@@ -1298,3 +1886,1104 @@ fn reserve_capacity_after_peer_closes() {
     srv.join(client).wait().expect("wait");
 }
 
+
+#[test]
+fn initial_window_size_applied_after_settings_ack() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.read_preface()
+        .recv_frame(frames::settings().initial_window_size(16))
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::settings())
+        .recv_frame(frames::settings_ack())
+        .send_frame(frames::headers(1).response(200))
+        // The client's SETTINGS have not been acknowledged yet, so the
+        // stream still has the default window.
+        .send_frame(frames::data(1, vec![0; 1_000]).eos())
+        .send_frame(frames::settings_ack())
+        .close();
+
+    let client = client::Builder::new()
+        .initial_window_size(16)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client.send_request(request, true)
+                .unwrap()
+                .0
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    resp.into_parts().1.concat2()
+                })
+                .map(|body| {
+                    assert_eq!(body.len(), 1_000);
+                });
+
+            conn.drive(req)
+                .and_then(|(conn, _)| conn.expect("client"))
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// Streams a 1 MB request body to a server releasing every chunk as soon as
+/// it is received, and returns the number of WINDOW_UPDATE frames sent by
+/// the server.
+fn window_updates_for_transfer(threshold: u8) -> usize {
+    use futures::{task, Async, Poll};
+    use h2::frame::Frame;
+
+    const LEN: usize = 1024 * 1024;
+
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        .and_then(|mut client| {
+            let mut conn_window = 65_535;
+            let mut stream_window = 65_535;
+            let mut remaining = LEN;
+            let mut updates = 0;
+
+            poll_fn(move || {
+                loop {
+                    match client.poll().expect("poll") {
+                        Async::Ready(Some(Frame::WindowUpdate(frame))) => {
+                            updates += 1;
+
+                            let incr = frame.size_increment() as usize;
+                            if frame.stream_id().is_zero() {
+                                conn_window += incr;
+                            } else {
+                                stream_window += incr;
+                            }
+                        },
+                        Async::Ready(Some(Frame::Headers(_))) => {
+                            assert_eq!(remaining, 0);
+                            return Ok::<_, ()>(Async::Ready(updates));
+                        },
+                        Async::Ready(frame) => panic!("unexpected frame; {:?}", frame),
+                        Async::NotReady => break,
+                    }
+                }
+
+                // Send as much as the windows allow, keeping the server's
+                // receive windows close to empty.
+                loop {
+                    let n = *[16_384, conn_window, stream_window, remaining]
+                        .iter()
+                        .min()
+                        .unwrap();
+
+                    if n == 0 {
+                        break;
+                    }
+
+                    conn_window -= n;
+                    stream_window -= n;
+                    remaining -= n;
+
+                    let mut data = frames::data(1, vec![0; n]);
+                    if remaining == 0 {
+                        data = data.eos();
+                    }
+                    client.send(data.into()).unwrap();
+                }
+
+                Ok(Async::NotReady)
+            })
+        });
+
+    let srv = server::Builder::new()
+        .window_update_threshold(threshold)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            let mut body = req.into_parts().1;
+            let mut received = 0;
+
+            poll_fn(move || -> Poll<(), h2::Error> {
+                match body.poll()? {
+                    Async::Ready(Some(chunk)) => {
+                        received += chunk.len();
+                        body.release_capacity().release_capacity(chunk.len()).unwrap();
+
+                        // Yield so the connection runs after every chunk
+                        // released, like a consumer slower than the peer.
+                        task::current().notify();
+                        Ok(Async::NotReady)
+                    },
+                    Async::Ready(None) => {
+                        assert_eq!(received, LEN);
+                        Ok(Async::Ready(()))
+                    },
+                    Async::NotReady => Ok(Async::NotReady),
+                }
+            }).expect("body")
+                .map(move |_| {
+                    let rsp = Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+                })
+                // The connection must keep running to send WINDOW_UPDATEs
+                .join(srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") }).expect("srv"))
+        });
+
+    let (_, updates) = srv.join(client).wait().expect("wait");
+    updates
+}
+
+#[test]
+fn window_updates_are_coalesced() {
+    let _ = ::env_logger::try_init();
+
+    // Announcing every release sends updates for nearly every DATA frame
+    let eager = window_updates_for_transfer(0);
+    let coalesced = window_updates_for_transfer(50);
+
+    assert!(eager >= 120, "eager={}", eager);
+    assert!(coalesced * 2 <= eager, "eager={}; coalesced={}", eager, coalesced);
+}
+
+#[test]
+fn peer_stalls_until_capacity_released() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos()
+        )
+        .send_frame(frames::headers(1).response(200))
+        // Use up the whole window
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_383]))
+        // Nothing is sent until all of it is released at once
+        .recv_frame(frames::window_update(0, 65_535))
+        .recv_frame(frames::window_update(1, 65_535))
+        .send_frame(frames::data(1, vec![0; 16_384]).eos())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let res = client.send_request(request, true).unwrap().0
+                .and_then(|res| res.into_parts().1.into_future().map_err(|(e, _)| e))
+                .and_then(|(chunk, body)| {
+                    assert!(chunk.is_some());
+                    body.into_future().map_err(|(e, _)| e)
+                })
+                .and_then(|(chunk, body)| {
+                    assert!(chunk.is_some());
+                    body.into_future().map_err(|(e, _)| e)
+                })
+                .and_then(|(chunk, body)| {
+                    assert!(chunk.is_some());
+                    body.into_future().map_err(|(e, _)| e)
+                })
+                .and_then(|(chunk, mut body)| {
+                    assert!(chunk.is_some());
+
+                    // The peer is stalled until the data is released
+                    body.release_capacity().release_capacity(65_535).unwrap();
+                    body.concat2()
+                })
+                .map(|rest| assert_eq!(rest.len(), 16_384));
+
+            conn.drive(res)
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn dropping_body_releases_capacity() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/one")
+                .eos()
+        )
+        .recv_frame(
+            frames::headers(3)
+                .request("GET", "https://http2.akamai.com/two")
+                .eos()
+        )
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::headers(3).response(200))
+        // Use up the whole connection window on the first stream
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_383]))
+        // The body is dropped without releasing anything
+        .recv_frame(frames::window_update(0, 65_535))
+        .recv_frame(frames::reset(1).cancel())
+        .send_frame(frames::data(3, vec![0; 16_384]).eos())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/one")
+                .body(()).unwrap();
+            let one = client.send_request(request, true).unwrap().0;
+
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/two")
+                .body(()).unwrap();
+            let two = client.send_request(request, true).unwrap().0;
+
+            let res = one
+                .and_then(|res| {
+                    let body = res.into_parts().1;
+                    body.into_future().map_err(|(e, _)| e)
+                })
+                .and_then(|(chunk, body)| {
+                    assert_eq!(chunk.unwrap().len(), 16_384);
+                    drop(body);
+                    two
+                })
+                .and_then(|res| res.into_parts().1.concat2())
+                .map(|body| assert_eq!(body.len(), 16_384));
+
+            conn.drive(res)
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn retained_chunk_outlives_dropped_body() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos()
+        )
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::data(1, vec![1; 1_000]))
+        .send_frame(frames::data(1, vec![2; 1_000]).eos())
+        .close();
+
+    let client = client::handshake(io).unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let res = client.send_request(request, true).unwrap().0
+                .and_then(|res| {
+                    let body = res.into_parts().1;
+                    body.into_future().map_err(|(e, _)| e)
+                })
+                .map(|(chunk, body)| {
+                    // The rest of the body is released with it
+                    drop(body);
+
+                    // The chunk does not keep the connection's read buffer
+                    // alive
+                    let chunk = chunk.unwrap();
+                    assert_eq!(chunk, vec![1; 1_000]);
+                    assert!(chunk.try_mut().is_ok());
+                });
+
+            conn.drive(res)
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(|c| (c, client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+/// Sends an 8 MB response body to a client, and returns the number of round
+/// trips it took. Within a round trip, the server sends as much as the
+/// windows allow, then waits for the frames the client sends in return.
+fn round_trips_for_response(adaptive: bool) -> usize {
+    use futures::Async;
+    use h2::frame::{Frame, Ping, Settings};
+
+    const LEN: usize = 8 * 1024 * 1024;
+
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos()
+        )
+        .send_frame(frames::headers(1).response(200))
+        .and_then(|mut srv| {
+            let mut conn_window = 65_535;
+            let mut stream_window = 65_535;
+            let mut init_window = 65_535;
+            let mut remaining = LEN;
+            let mut rounds = 0;
+
+            poll_fn(move || {
+                loop {
+                    match srv.poll().expect("poll") {
+                        Async::Ready(Some(Frame::WindowUpdate(frame))) => {
+                            let incr = frame.size_increment() as usize;
+                            if frame.stream_id().is_zero() {
+                                conn_window += incr;
+                            } else {
+                                stream_window += incr;
+                            }
+                        },
+                        Async::Ready(Some(Frame::Ping(ping))) => {
+                            assert!(!ping.is_ack());
+                            srv.send(Ping::pong(ping.into_payload()).into()).unwrap();
+                        },
+                        Async::Ready(Some(Frame::Settings(settings))) => {
+                            assert!(!settings.is_ack());
+                            if let Some(val) = settings.initial_window_size() {
+                                stream_window += val as usize;
+                                stream_window -= init_window;
+                                init_window = val as usize;
+                            }
+                            srv.send(Settings::ack().into()).unwrap();
+                        },
+                        Async::Ready(None) if remaining == 0 => {
+                            return Ok::<_, ()>(Async::Ready(rounds));
+                        },
+                        Async::Ready(frame) => panic!("unexpected frame; {:?}", frame),
+                        Async::NotReady => break,
+                    }
+                }
+
+                if remaining == 0 {
+                    return Ok(Async::NotReady);
+                }
+
+                let mut sent = false;
+
+                loop {
+                    let n = *[16_384, conn_window, stream_window, remaining]
+                        .iter()
+                        .min()
+                        .unwrap();
+
+                    if n == 0 {
+                        break;
+                    }
+
+                    conn_window -= n;
+                    stream_window -= n;
+                    remaining -= n;
+                    sent = true;
+
+                    let mut data = frames::data(1, vec![0; n]);
+                    if remaining == 0 {
+                        data = data.eos();
+                    }
+                    srv.send(data.into()).unwrap();
+                }
+
+                if sent {
+                    rounds += 1;
+                }
+
+                Ok(Async::NotReady)
+            })
+        });
+
+    let client = client::Builder::new()
+        .adaptive_window(adaptive)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let res = client.send_request(request, true).unwrap().0
+                .and_then(|res| {
+                    let mut body = res.into_parts().1;
+                    let mut cap = body.release_capacity().clone();
+
+                    body.fold(0, move |len, chunk| {
+                        cap.release_capacity(chunk.len()).unwrap();
+                        Ok::<_, h2::Error>(len + chunk.len())
+                    })
+                })
+                .map(|len| assert_eq!(len, LEN));
+
+            // Closing the connection lets the server know the client is done
+            conn.drive(res).map(move |_| drop(client))
+        });
+
+    let (rounds, _) = srv.join(client).wait().unwrap();
+    rounds
+}
+
+#[test]
+fn adaptive_window_grows_throughput() {
+    let _ = ::env_logger::try_init();
+
+    // A fixed 64 KB window moves 64 KB per round trip
+    let fixed = round_trips_for_response(false);
+    let adaptive = round_trips_for_response(true);
+
+    assert!(fixed >= 128, "fixed={}", fixed);
+    assert!(adaptive * 4 <= fixed, "fixed={}; adaptive={}", fixed, adaptive);
+}
+
+#[test]
+fn adaptive_window_does_not_shrink_configured_connection_window() {
+    use futures::Async;
+    use h2::frame::{Frame, Ping, Settings};
+
+    const CONN_WINDOW: usize = 1024 * 1024;
+    const LEN: usize = 65_535;
+
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::window_update(0, (CONN_WINDOW - 65_535) as u32))
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos()
+        )
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_383]))
+        .and_then(|mut srv| {
+            let mut conn_window = CONN_WINDOW - LEN;
+            let mut grew_stream_window = false;
+
+            poll_fn(move || loop {
+                match srv.poll().expect("poll") {
+                    Async::Ready(Some(Frame::WindowUpdate(frame))) => {
+                        if frame.stream_id().is_zero() {
+                            conn_window += frame.size_increment() as usize;
+                        }
+                    },
+                    Async::Ready(Some(Frame::Ping(ping))) => {
+                        // Either the BDP PING, acknowledged once all the DATA
+                        // was sent, or the client's last PING
+                        assert!(!ping.is_ack());
+                        srv.send(Ping::pong(ping.into_payload()).into()).unwrap();
+                    },
+                    Async::Ready(Some(Frame::Settings(settings))) => {
+                        // The measurement grows the smaller stream windows
+                        assert_eq!(settings.initial_window_size(), Some(2 * LEN as u32));
+                        grew_stream_window = true;
+                        srv.send(Settings::ack().into()).unwrap();
+
+                        // Only then is the body released by the client
+                        srv.send(frames::data(1, "").eos().into()).unwrap();
+                    },
+                    Async::Ready(Some(Frame::GoAway(_))) => {},
+                    Async::Ready(None) => {
+                        assert!(grew_stream_window);
+                        return Ok::<_, ()>(Async::Ready(conn_window));
+                    },
+                    Async::Ready(frame) => panic!("unexpected frame; {:?}", frame),
+                    Async::NotReady => return Ok(Async::NotReady),
+                }
+            })
+        });
+
+    let client = client::Builder::new()
+        .adaptive_window(true)
+        .initial_connection_window_size(CONN_WINDOW as u32)
+        .window_update_threshold(1)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(mut client, mut conn)| {
+            let mut ping_pong = client::Connection::ping_pong(&mut conn).expect("ping_pong");
+
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let res = client.send_request(request, true).unwrap().0
+                .and_then(|res| {
+                    let mut body = res.into_parts().1;
+                    let mut cap = body.release_capacity().clone();
+
+                    body.fold(0, |len, chunk| Ok::<_, h2::Error>(len + chunk.len()))
+                        .map(move |len| {
+                            assert_eq!(len, LEN);
+                            cap.release_capacity(len).unwrap();
+                        })
+                });
+
+            conn.drive(res)
+                .and_then(move |(conn, _)| {
+                    // A PING round trip flushes the WINDOW_UPDATE frames
+                    // before the connection is closed
+                    conn.drive(ping_pong.send_ping(h2::Ping::opaque()))
+                })
+                .and_then(move |(conn, _)| {
+                    drop(client);
+                    conn.expect("client")
+                })
+        });
+
+    // The released data is given back, up to the configured window
+    let (conn_window, _) = srv.join(client).wait().unwrap();
+    assert_eq!(conn_window, CONN_WINDOW);
+}
+
+#[test]
+fn writer_produces_as_capacity_is_granted() {
+    use futures::Async;
+
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake_with_settings(frames::settings().initial_window_size(8))
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+        )
+        .recv_frame(frames::data(1, vec![b'x'; 8]))
+        // Dribble capacity with WINDOW_UPDATE frames
+        .send_frame(frames::window_update(1, 8))
+        .recv_frame(frames::data(1, vec![b'x'; 8]))
+        .send_frame(frames::window_update(1, 4))
+        .recv_frame(frames::data(1, vec![b'x'; 4]))
+        // Then with a larger initial window size
+        .send_frame(frames::settings().initial_window_size(20))
+        .recv_frame(frames::settings_ack())
+        .recv_frame(frames::data(1, vec![b'x'; 12]).eos())
+        .send_frame(frames::headers(1).response(204).eos())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            // Wait for the small initial window to be applied
+            let changes = conn.settings_changes().into_future().map_err(|(e, _)| e);
+            conn.drive(changes).map(move |(conn, _)| (client, conn))
+        })
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+
+            let mut remaining = 32;
+            stream.reserve_capacity(remaining);
+
+            // Produce exactly as much as the capacity notifications allow
+            let writer = poll_fn(move || loop {
+                let n = match stream.poll_capacity()? {
+                    Async::Ready(Some(n)) => n,
+                    Async::Ready(None) => panic!("stream closed"),
+                    Async::NotReady => return Ok(Async::NotReady),
+                };
+
+                assert_eq!(n, stream.capacity());
+
+                if n == 0 {
+                    continue;
+                }
+
+                remaining -= n;
+                stream.send_data(vec![b'x'; n].into(), remaining == 0)?;
+
+                if remaining == 0 {
+                    return Ok(Async::Ready(()));
+                }
+            });
+
+            conn.drive(writer.join(response))
+                .and_then(|(conn, (_, response))| {
+                    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+                    conn.expect("client")
+                })
+                .map(move |_| drop(client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn reclaimed_data_split_at_lowered_max_frame_size() {
+    let _ = ::env_logger::try_init();
+    let (io, mut srv) = mock::new();
+
+    srv.codec_mut().set_max_recv_frame_size(65_536);
+
+    let srv = srv.assert_client_handshake_with_settings(
+            frames::settings()
+                .max_frame_size(32_768)
+                .initial_window_size(20_000)
+        )
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+        // Limited by the stream window, the rest of the chunk is reclaimed
+        .recv_frame(frames::data(1, vec![0; 20_000]))
+        .send_frame(frames::settings().max_frame_size(16_384))
+        .recv_frame(frames::settings_ack())
+        .send_frame(frames::window_update(1, 20_000))
+        // The reclaimed data is split at the new max frame size
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 3_616]).eos())
+        .send_frame(frames::headers(1).response(204).eos())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            // Wait for the settings of the server to be applied
+            let changes = conn.settings_changes().into_future().map_err(|(e, _)| e);
+            conn.drive(changes).map(move |(conn, _)| (client, conn))
+        })
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://example.com/")
+                .body(())
+                .unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            stream.send_data(vec![0; 40_000].into(), true).unwrap();
+
+            conn.drive(response)
+                .and_then(|(conn, response)| {
+                    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+                    conn.expect("client")
+                })
+                .map(move |_| drop(client))
+        });
+
+    srv.join(client).wait().unwrap();
+}
+
+#[test]
+fn sender_stalls_at_buffered_recv_cap() {
+    use futures::sync::oneshot;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+    let (sent_tx, sent_rx) = oneshot::channel();
+    let (read_tx, read_rx) = oneshot::channel();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        // Use up the whole window
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_383]))
+        .map(move |client| {
+            sent_tx.send(()).unwrap();
+            client
+        })
+        // Everything was released, but the stream buffers more than the cap.
+        .recv_frame(frames::window_update(0, 65_535))
+        .idle_ms(20)
+        .map(move |client| {
+            read_tx.send(()).unwrap();
+            client
+        })
+        // Reading the body brings the stream back under the cap.
+        .recv_frame(frames::window_update(1, 65_535))
+        .send_frame(frames::data(1, vec![0; 16_384]).eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .max_buffered_recv_bytes_per_stream(16_384)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            let mut body = req.into_parts().1;
+
+            let body = sent_rx.expect("sent")
+                .and_then(move |_| {
+                    // Release all of the data without reading any of it.
+                    body.release_capacity().release_capacity(65_535).unwrap();
+                    read_rx.expect("read").map(move |_| body)
+                })
+                .and_then(|body| body.concat2().expect("body"))
+                .map(move |body| {
+                    assert_eq!(body.len(), 65_535 + 16_384);
+
+                    let rsp = Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+                });
+
+            // The connection is polled first so that all of the data was
+            // received before it is released.
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn stream_reset_after_buffered_recv_timeout() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        // No other frame is received before the timeout expires.
+        .recv_frame(frames::reset(1).cancel())
+        .close();
+
+    let srv = server::Builder::new()
+        .max_buffered_recv_bytes_per_stream(16_384)
+        .buffered_recv_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            // The body is never read.
+            let held = reqstream.unwrap();
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .map(move |_| drop(held))
+        });
+
+    clock::run(srv.join(client)).expect("wait");
+}
+
+#[test]
+fn send_padded_data_consumes_window() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(16));
+
+    let srv = srv.assert_client_handshake_with_settings(settings).unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://http2.akamai.com/")
+                .pad(5)
+        )
+        // The padding and the pad length octet take 6 octets of the window.
+        .recv_frame(frames::data(1, "0123456789").pad(5))
+        .send_frame(frames::window_update(1, 16))
+        .recv_frame(frames::data(1, "abcdef").pad(5).eos())
+        .send_frame(
+            frames::headers(1)
+                .response(204)
+                .eos()
+        )
+        .close();
+
+    let h2 = client::Builder::new()
+        .padding(|_| 5)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|(mut client, h2)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            stream.send_data("0123456789abcdef".into(), true).unwrap();
+
+            h2.drive(response).map(|c| (c, client))
+        })
+        .and_then(|((h2, response), client)| {
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            // Hold on to the `client` handle to avoid sending a GO_AWAY frame.
+            h2.unwrap().map(|c| (c, client))
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_padded_data_releases_padding() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().initial_window_size(64))
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        // 5 octets of data, 1 octet of pad length and 26 octets of padding
+        .send_frame(frames::data(1, "hello").pad(26))
+        // The padding is released right away, and the data once read.
+        .recv_frame(frames::window_update(1, 32))
+        .send_frame(frames::data(1, "").eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .initial_window_size(64)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+
+            let body = req.into_body()
+                .into_future()
+                .map_err(|(e, _)| e)
+                .and_then(|(chunk, mut body)| {
+                    let chunk = chunk.unwrap();
+                    assert_eq!(chunk, "hello");
+                    body.release_capacity().release_capacity(chunk.len()).unwrap();
+                    body.concat2()
+                })
+                .expect("body")
+                .map(move |body| {
+                    assert!(body.is_empty());
+
+                    let rsp = Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+                });
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn manual_flow_control_proxy_releases_at_downstream_rate() {
+    use futures::sync::mpsc;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    // The upstream peer only sends more data once the previous chunk was
+    // accepted downstream and released on both levels.
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::window_update(0, 16_384))
+        .recv_frame(frames::window_update(1, 16_384))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::window_update(0, 16_384))
+        .recv_frame(frames::window_update(1, 16_384))
+        .send_frame(frames::data(1, vec![0; 16_384]).eos())
+        .recv_frame(frames::window_update(0, 16_384))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .flow_control_mode(FlowControlMode::Manual)
+        .window_update_threshold(0)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            let mut body = req.into_parts().1;
+            let mut release = body.release_capacity().clone();
+
+            // The downstream accepts a single chunk at a time.
+            let (tx, rx) = mpsc::channel::<Bytes>(0);
+
+            let forward = body
+                .map_err(|_| ())
+                .forward(tx.sink_map_err(|_| ()))
+                .map(|_| ())
+                .expect("forward");
+
+            let downstream = rx
+                .fold(0, move |len, chunk| {
+                    release.release_capacity(chunk.len()).unwrap();
+                    release.release_connection_capacity(chunk.len()).unwrap();
+                    Ok::<_, ()>(len + chunk.len())
+                })
+                .expect("downstream")
+                .map(move |len| {
+                    assert_eq!(len, 3 * 16_384);
+
+                    let rsp = Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+                });
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join3(forward, downstream)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn manual_flow_control_holds_connection_window() {
+    use futures::sync::oneshot;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+    let (pong_tx, pong_rx) = oneshot::channel();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        .send_frame(frames::data(1, vec![0; 16_384]))
+        // Only the stream level share is released.
+        .recv_frame(frames::window_update(1, 16_384))
+        .send_frame(frames::ping([1; 8]))
+        .recv_frame(frames::ping([1; 8]).pong())
+        .map(move |client| {
+            pong_tx.send(()).unwrap();
+            client
+        })
+        .recv_frame(frames::window_update(0, 16_384))
+        .send_frame(frames::data(1, vec![]).eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .flow_control_mode(FlowControlMode::Manual)
+        .window_update_threshold(0)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+
+            let body = req.into_body()
+                .into_future()
+                .map_err(|(e, _)| panic!("body; err={:?}", e))
+                .and_then(|(chunk, mut body)| {
+                    let len = chunk.unwrap().len();
+                    body.release_capacity().release_capacity(len).unwrap();
+
+                    // More than was received on the stream.
+                    assert!(body.release_capacity()
+                        .release_connection_capacity(len + 1)
+                        .is_err());
+
+                    pong_rx.expect("pong").and_then(move |_| {
+                        body.release_capacity()
+                            .release_connection_capacity(len)
+                            .unwrap();
+                        body.concat2().expect("body")
+                    })
+                })
+                .map(move |body| {
+                    assert!(body.is_empty());
+
+                    let rsp = Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+                });
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn release_connection_capacity_with_auto_flow_control() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+        .send_frame(frames::data(1, vec![0; 16_384]).eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            let mut body = req.into_body();
+
+            // The connection level share is already released by
+            // `release_capacity`.
+            assert!(body.release_capacity()
+                .release_connection_capacity(1)
+                .is_err());
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body.concat2().expect("body"))
+        });
+
+    srv.join(client).wait().expect("wait");
+}
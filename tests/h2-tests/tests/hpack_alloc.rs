@@ -0,0 +1,82 @@
+//! Counts the allocations made while encoding request header blocks. The
+//! counting allocator applies to the whole test binary, so this is kept apart
+//! from the other tests.
+
+extern crate h2_support;
+
+use h2_support::prelude::*;
+
+use h2::frame::{Headers, Pseudo};
+use h2::hpack::raw::Encoder;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn request_headers(i: usize) -> Headers {
+    let uri: http::Uri = format!("https://example.com/items/{}?page={}", i, i % 7)
+        .parse()
+        .unwrap();
+
+    let mut fields = HeaderMap::new();
+    fields.insert("user-agent", "h2-test/0.1".parse().unwrap());
+    fields.insert("accept", "application/json".parse().unwrap());
+    fields.insert("accept-encoding", "gzip, deflate".parse().unwrap());
+    fields.append("cookie", format!("session={}", i % 13).parse().unwrap());
+    fields.append("cookie", "theme=dark".parse().unwrap());
+
+    let id = StreamId::from(2 * i as u32 + 1);
+    Headers::new(id, Pseudo::request(Method::GET, uri), fields)
+}
+
+#[test]
+fn encode_request_headers_without_allocating() {
+    const REQUESTS: usize = 10_000;
+
+    let mut encoder = Encoder::default();
+    let mut dst = BytesMut::with_capacity(16_384);
+
+    // Fill the dynamic table first, it only allocates while growing.
+    for i in 0..100 {
+        dst.clear();
+        assert!(request_headers(i).encode(&mut encoder, &mut dst, |_| 0).is_none());
+    }
+
+    let frames: Vec<_> = (0..REQUESTS).map(request_headers).collect();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+
+    for frame in frames {
+        dst.clear();
+        assert!(frame.encode(&mut encoder, &mut dst, |_| 0).is_none());
+    }
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    assert!(
+        allocations == 0,
+        "{} allocations for {} header blocks",
+        allocations,
+        REQUESTS
+    );
+}
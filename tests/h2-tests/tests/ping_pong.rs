@@ -3,6 +3,8 @@ extern crate h2_support;
 
 use h2_support::prelude::*;
 
+use std::time::{Duration, Instant};
+
 #[test]
 fn recv_single_ping() {
     let _ = ::env_logger::try_init();
@@ -108,3 +110,265 @@ fn pong_has_highest_priority() {
 
     srv.join(client).wait().expect("wait");
 }
+
+#[test]
+fn keep_alive_measures_rtt() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        // The connection is considered idle right away
+        .recv_frame(frames::ping([0, 0, 0, 0, 0, 0, 0, 1]))
+        .send_frame(frames::ping([0, 0, 0, 0, 0, 0, 0, 1]).pong())
+        // Each keep-alive PING has its own payload
+        .recv_frame(frames::ping([0, 0, 0, 0, 0, 0, 0, 2]))
+        .close();
+
+    let client = client::Builder::new()
+        .keep_alive_interval(Duration::from_millis(0))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(client, mut conn)| {
+            assert!(conn.rtt().is_none());
+
+            futures::future::poll_fn(move || {
+                let res = conn.poll();
+
+                if let Ok(futures::Async::Ready(())) = res {
+                    assert!(conn.rtt().is_some());
+                }
+
+                res
+            }).unwrap().map(move |()| drop(client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn keep_alive_timeout() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::ping([0, 0, 0, 0, 0, 0, 0, 1]))
+        // The PING is never acknowledged.
+        .recv_eof();
+
+    let client = client::Builder::new()
+        .keep_alive_interval(Duration::from_millis(0))
+        .keep_alive_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            conn.then(move |res| {
+                drop(client);
+                let err = res.expect_err("connection");
+                assert_eq!(err.get_io().map(|e| e.kind()), Some(::std::io::ErrorKind::TimedOut));
+                Ok::<(), ()>(())
+            })
+        });
+
+    clock::run(client.join(srv)).expect("wait");
+}
+
+#[test]
+fn keep_alive_pings_connection_without_frames() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let started_at = clock::now();
+
+    // No frame is received after the handshake.
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::ping([0, 0, 0, 0, 0, 0, 0, 1]))
+        .map(move |srv| {
+            assert!(clock::now() >= started_at + Duration::from_secs(30));
+            srv
+        })
+        // The PING is never acknowledged.
+        .recv_eof()
+        .map(move |_| assert!(clock::now() >= started_at + Duration::from_secs(40)));
+
+    let client = client::Builder::new()
+        .keep_alive_interval(Duration::from_secs(30))
+        .keep_alive_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            conn.then(move |res| {
+                drop(client);
+                let err = res.expect_err("connection");
+                assert_eq!(err.get_io().map(|e| e.kind()), Some(::std::io::ErrorKind::TimedOut));
+                Ok::<(), ()>(())
+            })
+        });
+
+    clock::run(client.join(srv)).expect("wait");
+}
+
+#[test]
+fn user_ping_pong() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::ping(frame::Ping::USER))
+        .send_frame(frames::ping(frame::Ping::USER).pong())
+        .recv_frame(frames::ping(frame::Ping::USER))
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, mut conn)| {
+            // `HandleFutureExt::ping_pong` shadows the inherent method here
+            let mut ping_pong = client::Connection::ping_pong(&mut conn).expect("taken once");
+            assert!(client::Connection::ping_pong(&mut conn).is_none());
+
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            // Complete a request first so the handshake is done before the
+            // PING is written
+            let (response, _) = client.send_request(request, true).unwrap();
+
+            conn.drive(response)
+                .and_then(move |(conn, _)| {
+                    let pong = ping_pong.send_ping(Ping::opaque());
+
+                    // Only one PING may be outstanding
+                    let err = ping_pong.send_ping(Ping::opaque()).wait().expect_err("send_ping");
+                    assert!(err.to_string().contains("send_ping"), "{}", err);
+
+                    conn.drive(pong).map(move |(conn, pong)| (conn, pong, ping_pong))
+                })
+                .map_err(|e| panic!("err={:?}", e))
+                .and_then(move |(conn, _pong, mut ping_pong)| {
+                    // The next PING fails when the connection closes
+                    let pong = ping_pong.send_ping(Ping::opaque());
+                    conn.expect("client").join(pong.then(|res| {
+                        assert!(res.is_err());
+                        Ok(())
+                    }))
+                })
+                .map(move |_| drop(client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn user_ping_pong_loopback() {
+    let _ = ::env_logger::try_init();
+    let (io, srv_io) = mock::new();
+
+    // A server running on the other end of the pipe
+    let srv = server::handshake(srv_io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .map(|(req, _srv)| assert!(req.is_none(), "unexpected request"));
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(client, mut conn)| {
+            let mut ping_pong = client::Connection::ping_pong(&mut conn).expect("ping_pong");
+            assert!(conn.rtt().is_none());
+
+            let start = Instant::now();
+
+            conn.drive(ping_pong.send_ping(Ping::opaque()))
+                .map(move |(conn, _pong)| {
+                    let elapsed = start.elapsed();
+                    let rtt = conn.rtt().expect("rtt");
+
+                    assert!(rtt > Duration::from_secs(0));
+                    assert!(rtt <= elapsed);
+
+                    drop(client);
+                })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_too_many_pings() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .map(|mut client| {
+            for i in 0..100u8 {
+                client.send(frames::ping([i; 8]).into()).unwrap();
+            }
+            client
+        });
+
+    // Only the first 10 PINGs are acknowledged
+    let client = (0..10u8).fold(Box::new(client) as Box<Future<Item = _, Error = _>>, |client, i| {
+        Box::new(client.recv_frame(frames::ping([i; 8]).pong()))
+    });
+
+    let client = client
+        .recv_frame(frames::go_away(0).calm())
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .max_pings_per_interval(10)
+        .ping_interval(Duration::from_secs(60))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().then(|res| {
+            let err = res.err().expect("error").0;
+            assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+            Ok::<_, ()>(())
+        }));
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn ping_acks_not_limited() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    // PING acks, even unexpected ones, do not count against the limit
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::ping([1; 8]))
+        .recv_frame(frames::ping([1; 8]).pong())
+        .send_frame(frames::ping(frame::Ping::USER).pong())
+        .send_frame(frames::ping(frame::Ping::USER).pong())
+        .send_frame(frames::ping([2; 8]))
+        .recv_frame(frames::ping([2; 8]).pong())
+        .close();
+
+    let client = client::Builder::new()
+        .max_pings_per_interval(2)
+        .ping_interval(Duration::from_secs(60))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| conn.expect("client").map(move |_| drop(client)));
+
+    client.join(srv).wait().expect("wait");
+}
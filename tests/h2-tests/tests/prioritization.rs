@@ -438,3 +438,647 @@ fn send_data_receive_window_update() {
 
     let _ = h2.join(mock).wait().unwrap();
 }
+
+type Accepted = (Request<RecvStream>, server::SendResponse<Bytes>);
+
+/// Accepts `n` requests, keeping the connection around.
+fn accept(srv: server::Connection<mock::Mock, Bytes>, n: usize)
+    -> Box<Future<Item = (Vec<Accepted>, server::Connection<mock::Mock, Bytes>), Error = ()>>
+{
+    use futures::future::{loop_fn, Loop};
+
+    let accepted = loop_fn((srv, Vec::new()), move |(srv, mut accepted)| {
+        srv.into_future().unwrap().map(move |(stream, srv)| {
+            accepted.push(stream.expect("stream"));
+
+            if accepted.len() == n {
+                Loop::Break((accepted, srv))
+            } else {
+                Loop::Continue((srv, accepted))
+            }
+        })
+    });
+
+    Box::new(accepted)
+}
+
+/// Responds to every request, then runs the connection until the client
+/// closes it.
+fn respond(accepted: Vec<Accepted>, srv: server::Connection<mock::Mock, Bytes>)
+    -> Box<Future<Item = (), Error = ()>>
+{
+    for (_, mut stream) in accepted {
+        let rsp = Response::builder().status(200).body(()).unwrap();
+        stream.send_response(rsp, true).unwrap();
+    }
+
+    Box::new(srv.into_future().unwrap().map(|(stream, _)| assert!(stream.is_none())))
+}
+
+#[test]
+fn recv_priority_on_headers() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(1, 31, false)
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 2))
+        .and_then(|(accepted, srv)| {
+            // Streams opened without priority have the default priority.
+            let one = accepted[0].1.priority();
+            assert_eq!(one.dependency(), None);
+            assert_eq!(one.weight(), 16);
+            assert!(!one.is_exclusive());
+
+            let three = accepted[1].1.priority();
+            assert_eq!(three.dependency(), Some(&accepted[0].1.stream_id()));
+            assert_eq!(three.weight(), 32);
+            assert!(!three.is_exclusive());
+
+            respond(accepted, srv)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_exclusive_dependency_reparents_siblings() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(frames::headers(3).request("GET", "https://example.com/").eos())
+        // Stream 5 becomes the sole dependency of the root.
+        .send_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(0, 63, true)
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(3).response(200).eos())
+        .recv_frame(frames::headers(5).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 3))
+        .and_then(|(accepted, srv)| {
+            let five = accepted[2].1.stream_id();
+
+            for &(_, ref stream) in &accepted[..2] {
+                let priority = stream.priority();
+                assert_eq!(priority.dependency(), Some(&five));
+                assert_eq!(priority.weight(), 16);
+            }
+
+            let priority = accepted[2].1.priority();
+            assert_eq!(priority.dependency(), None);
+            assert_eq!(priority.weight(), 64);
+            assert!(priority.is_exclusive());
+
+            respond(accepted, srv)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_priority_depending_on_own_dependency() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(1, 63, false)
+        )
+        // Stream 3 depends on stream 1, it is first moved to the root.
+        .send_frame(frames::priority(1, 3).exclusive())
+        .send_frame(frames::headers(5).request("GET", "https://example.com/").eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(3).response(200).eos())
+        .recv_frame(frames::headers(5).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 3))
+        .and_then(|(accepted, srv)| {
+            let one = accepted[0].1.priority();
+            assert_eq!(one.dependency(), Some(&accepted[1].1.stream_id()));
+            assert_eq!(one.weight(), 16);
+            assert!(one.is_exclusive());
+
+            // The moved stream keeps its weight.
+            let three = accepted[1].1.priority();
+            assert_eq!(three.dependency(), None);
+            assert_eq!(three.weight(), 64);
+
+            respond(accepted, srv)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_priority_for_idle_stream() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        // Stream 3 is never opened, it is only used to group other streams.
+        .send_frame(frames::priority(3, 0).weight(200))
+        .send_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(3, 100, false)
+        )
+        // Depending on a stream that is not in the tree results in the
+        // default priority.
+        .send_frame(
+            frames::headers(7)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(9, 100, false)
+        )
+        .recv_frame(frames::headers(5).response(200).eos())
+        .recv_frame(frames::headers(7).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 2))
+        .and_then(|(accepted, srv)| {
+            let five = accepted[0].1.priority();
+            assert!(five.dependency().is_some());
+            assert_eq!(five.weight(), 101);
+
+            let seven = accepted[1].1.priority();
+            assert_eq!(seven.dependency(), None);
+            assert_eq!(seven.weight(), 16);
+
+            respond(accepted, srv)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_headers_depending_on_itself() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .field("x-custom", "value")
+                .eos()
+                .dependency(1, 15, false)
+        )
+        .recv_frame(frames::reset(1).protocol_error())
+        // The header block of the rejected stream was decoded, so the next
+        // one can refer to the fields it added to the HPACK table.
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .field("x-custom", "value")
+                .eos()
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 1))
+        .and_then(|(accepted, srv)| {
+            assert_eq!(accepted[0].0.headers()["x-custom"], "value");
+            respond(accepted, srv)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn connection_capacity_shared_by_weight() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    // Only the connection window limits the amount of data sent.
+    let settings = frames::settings().initial_window_size(1 << 20);
+
+    let client = client
+        .assert_server_handshake_with_settings(settings)
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(0, 0, false)
+        )
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(0, 1, false)
+        )
+        .send_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(0, 3, false)
+        )
+        .recv_frame(frames::headers(1).response(200))
+        .recv_frame(frames::headers(3).response(200))
+        .recv_frame(frames::headers(5).response(200))
+        // Stream 1 is the first to send data, and takes the whole initial
+        // connection window.
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_383]))
+        // From then on, the streams have weights 1, 2 and 4.
+        .send_frame(frames::window_update(0, 7_168))
+        .recv_frame(frames::data(1, vec![0; 1_024]))
+        .recv_frame(frames::data(3, vec![0; 2_048]))
+        .recv_frame(frames::data(5, vec![0; 4_096]))
+        .send_frame(frames::window_update(0, 7_168))
+        .recv_frame(frames::data(1, vec![0; 1_024]))
+        .recv_frame(frames::data(3, vec![0; 2_048]))
+        .recv_frame(frames::data(5, vec![0; 4_096]))
+        .send_frame(frames::window_update(0, 700))
+        .recv_frame(frames::data(1, vec![0; 100]))
+        .recv_frame(frames::data(3, vec![0; 200]))
+        .recv_frame(frames::data(5, vec![0; 400]))
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 3))
+        .and_then(|(accepted, srv)| {
+            let mut streams = vec![];
+
+            for (_, mut stream) in accepted {
+                let rsp = Response::builder().status(200).body(()).unwrap();
+                streams.push(stream.send_response(rsp, false).unwrap());
+            }
+
+            for stream in &mut streams {
+                stream.send_data(vec![0; 100_000].into(), true).unwrap();
+            }
+
+            srv.into_future().unwrap().map(move |(stream, _)| {
+                assert!(stream.is_none());
+                drop(streams);
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn connection_capacity_goes_to_dependencies_once_parent_done() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let settings = frames::settings().initial_window_size(1 << 20);
+
+    let client = client
+        .assert_server_handshake_with_settings(settings)
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(1, 15, false)
+        )
+        .recv_frame(frames::headers(1).response(200))
+        .recv_frame(frames::headers(3).response(200))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_383]))
+        // Stream 3 depends on stream 1, so it only gets what stream 1 does
+        // not need.
+        .send_frame(frames::window_update(0, 2_000))
+        .recv_frame(frames::data(1, vec![0; 2_000]))
+        .send_frame(frames::window_update(0, 2_000))
+        .recv_frame(frames::data(1, vec![0; 1_000]).eos())
+        .recv_frame(frames::data(3, vec![0; 1_000]))
+        .send_frame(frames::window_update(0, 2_000))
+        .recv_frame(frames::data(3, vec![0; 2_000]))
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 2))
+        .and_then(|(accepted, srv)| {
+            let mut streams = vec![];
+
+            for (_, mut stream) in accepted {
+                let rsp = Response::builder().status(200).body(()).unwrap();
+                streams.push(stream.send_response(rsp, false).unwrap());
+            }
+
+            streams[0].send_data(vec![0; 68_535].into(), true).unwrap();
+            streams[1].send_data(vec![0; 100_000].into(), true).unwrap();
+
+            srv.into_future().unwrap().map(move |(stream, _)| {
+                assert!(stream.is_none());
+                drop(streams);
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_request_with_priority() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        // The weight is sent minus one.
+        .recv_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+                .dependency(1, 255, true)
+        )
+        .send_frame(frames::headers(1).response(200).eos())
+        .send_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::get("https://example.com/").body(()).unwrap();
+            let (one, stream) = client.send_request(request, true).unwrap();
+
+            let priority = Priority::new()
+                .depend_on(stream.stream_id())
+                .weight(256)
+                .exclusive(true);
+
+            let request = Request::get("https://example.com/").body(()).unwrap();
+            let (three, _) = client
+                .send_request_with_priority(request, true, priority)
+                .unwrap();
+
+            conn.drive(one.join(three))
+                .and_then(move |(conn, _)| conn.expect("client"))
+                .map(move |_| drop(client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn send_priority_frame() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        // The priority set before the request is sent goes in its HEADERS
+        // frame.
+        .recv_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .dependency(0, 127, false)
+        )
+        .recv_frame(frames::headers(3).request("POST", "https://example.com/"))
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::headers(3).response(200))
+        .recv_frame(frames::priority(3, 1).weight(0).exclusive())
+        .recv_frame(frames::data(1, "").eos())
+        .recv_frame(frames::data(3, "").eos())
+        .send_frame(frames::data(1, "").eos())
+        .send_frame(frames::data(3, "").eos())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::post("https://example.com/").body(()).unwrap();
+            let (one, mut stream1) = client.send_request(request, false).unwrap();
+            stream1.send_priority(Priority::new().weight(128)).unwrap();
+
+            let request = Request::post("https://example.com/").body(()).unwrap();
+            let (three, mut stream3) = client.send_request(request, false).unwrap();
+
+            conn.drive(one.join(three))
+                .and_then(move |(conn, _)| {
+                    let priority = Priority::new()
+                        .depend_on(stream1.stream_id())
+                        .weight(1)
+                        .exclusive(true);
+
+                    stream3.send_priority(priority).unwrap();
+                    stream3.send_data("".into(), true).unwrap();
+                    stream1.send_data("".into(), true).unwrap();
+
+                    conn.expect("client")
+                })
+                .map(move |_| drop(client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn send_invalid_priority() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            for &weight in &[0, 257] {
+                let request = Request::get("https://example.com/").body(()).unwrap();
+                let priority = Priority::new().weight(weight);
+                let err = client
+                    .send_request_with_priority(request, true, priority)
+                    .unwrap_err();
+
+                assert_eq!(err.to_string(), "user error: invalid stream priority");
+            }
+
+            let request = Request::get("https://example.com/").body(()).unwrap();
+            let (response, mut stream) = client.send_request(request, true).unwrap();
+
+            // A stream cannot depend on itself.
+            let priority = Priority::new().depend_on(stream.stream_id());
+            let err = stream.send_priority(priority).unwrap_err();
+            assert_eq!(err.to_string(), "user error: invalid stream priority");
+
+            conn.drive(response)
+                .and_then(move |(conn, _)| conn.expect("client"))
+                .map(move |_| drop(client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn extensible_priorities_send_most_urgent_first() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    // Only the connection window limits the amount of data sent.
+    let settings = frames::settings().initial_window_size(1 << 20);
+
+    let client = client
+        .assert_server_handshake_with_settings(settings)
+        .unwrap()
+        .recv_custom_settings(frames::settings().no_rfc7540_priorities(1))
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .field("priority", "u=5")
+                .eos()
+        )
+        .send_frame(frames::headers(3).request("GET", "https://example.com/").eos())
+        // Stream 3 has the default urgency of 3.
+        .recv_frame(frames::headers(3).response(200))
+        .recv_frame(frames::headers(1).response(200))
+        // Stream 1 is the first to send data, and takes the whole initial
+        // connection window.
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_383]))
+        .send_frame(frames::window_update(0, 10_000))
+        .recv_frame(frames::data(3, vec![0; 10_000]))
+        // Stream 1 becomes the most urgent one.
+        .send_frame(frames::priority_update(1, "u=1"))
+        .send_frame(frames::window_update(0, 10_000))
+        .recv_frame(frames::data(1, vec![0; 10_000]))
+        // Incremental streams of the same urgency share the connection.
+        .send_frame(frames::priority_update(1, "u=1, i"))
+        .send_frame(frames::priority_update(3, "u=1, i"))
+        .send_frame(frames::window_update(0, 2_000))
+        .recv_frame(frames::data(1, vec![0; 1_000]))
+        .recv_frame(frames::data(3, vec![0; 1_000]))
+        .close();
+
+    let srv = server::Builder::new()
+        .enable_extensible_priorities()
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 2))
+        .and_then(|(accepted, srv)| {
+            assert_eq!(accepted[0].1.urgency(), 5);
+            assert_eq!(accepted[1].1.urgency(), 3);
+
+            let mut streams = vec![];
+
+            for (_, mut stream) in accepted {
+                let rsp = Response::builder().status(200).body(()).unwrap();
+                streams.push(stream.send_response(rsp, false).unwrap());
+            }
+
+            for stream in &mut streams {
+                stream.send_data(vec![0; 100_000].into(), true).unwrap();
+            }
+
+            srv.into_future().unwrap().map(move |(stream, _)| {
+                assert!(stream.is_none());
+                drop(streams);
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn extensible_priorities_update_before_request() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().no_rfc7540_priorities(1))
+        // The update overrides the header field of the request.
+        .send_frame(frames::priority_update(1, "u=0, i"))
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .field("priority", "u=6")
+                .eos()
+        )
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .field("priority", "u=6")
+                .eos()
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .enable_extensible_priorities()
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| accept(srv, 2))
+        .and_then(|(accepted, srv)| {
+            assert_eq!(accepted[0].1.urgency(), 0);
+            assert!(accepted[0].1.is_incremental());
+
+            assert_eq!(accepted[1].1.urgency(), 6);
+            assert!(!accepted[1].1.is_incremental());
+
+            respond(accepted, srv)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
@@ -5,7 +5,6 @@ use h2_support::prelude::*;
 #[test]
 fn recv_push_works() {
     // tests that by default, received push promises work
-    // TODO: once API exists, read the pushed response
     let _ = ::env_logger::try_init();
 
     let (io, srv) = mock::new();
@@ -19,7 +18,8 @@ fn recv_push_works() {
         )
         .send_frame(frames::push_promise(1, 2).request("GET", "https://http2.akamai.com/style.css"))
         .send_frame(frames::headers(1).response(200).eos())
-        .send_frame(frames::headers(2).response(200).eos());
+        .send_frame(frames::headers(2).response(200))
+        .send_frame(frames::data(2, "body {}").eos());
 
     let h2 = client::handshake(io).unwrap().and_then(|(mut client, h2)| {
         let request = Request::builder()
@@ -27,16 +27,79 @@ fn recv_push_works() {
             .uri("https://http2.akamai.com/")
             .body(())
             .unwrap();
-        let req = client
-            .send_request(request, true)
-            .unwrap()
-            .0.unwrap()
+        let (mut resp, _) = client.send_request(request, true).unwrap();
+
+        let pushed = resp.push_promises()
+            .and_then(|promise| {
+                assert_eq!(promise.request().method(), &Method::GET);
+                assert_eq!(promise.request().uri(), "https://http2.akamai.com/style.css");
+
+                let (_, resp) = promise.into_parts();
+                resp.and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    resp.into_body().concat2()
+                })
+            })
+            .collect()
+            .map(|bodies| {
+                assert_eq!(bodies.len(), 1);
+                assert_eq!(bodies[0], "body {}");
+            })
+            .expect("pushed");
+
+        let req = resp.unwrap()
+            .and_then(|resp| {
+                assert_eq!(resp.status(), StatusCode::OK);
+                Ok(())
+            });
+
+        h2.drive(req.join(pushed))
+    });
+
+    h2.join(mock).wait().unwrap();
+}
+
+#[test]
+fn reject_push_promise() {
+    let _ = ::env_logger::try_init();
+
+    let (io, srv) = mock::new();
+    let mock = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::push_promise(1, 2).request("GET", "https://http2.akamai.com/style.css"))
+        .recv_frame(frames::reset(2).cancel())
+        .send_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let h2 = client::handshake(io).unwrap().and_then(|(mut client, h2)| {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("https://http2.akamai.com/")
+            .body(())
+            .unwrap();
+        let (mut resp, _) = client.send_request(request, true).unwrap();
+
+        let pushed = resp.push_promises()
+            .for_each(|promise| {
+                promise.reject();
+                Ok(())
+            })
+            .expect("pushed");
+
+        let req = resp.unwrap()
             .and_then(|resp| {
                 assert_eq!(resp.status(), StatusCode::OK);
                 Ok(())
             });
 
-        h2.drive(req)
+        h2.drive(req.join(pushed))
+            .and_then(|(conn, _)| conn.expect("client"))
     });
 
     h2.join(mock).wait().unwrap();
@@ -138,6 +201,157 @@ fn pending_push_promises_reset_when_dropped() {
     client.join(srv).wait().expect("wait");
 }
 
+#[test]
+fn recv_push_promise_over_max_reserved_remote_streams() {
+    let _ = ::env_logger::try_init();
+
+    let (io, srv) = mock::new();
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        // Only the first 10 promises are kept.
+        .and_then(|srv| {
+            futures::stream::iter_ok(1..201).fold(srv, |srv, i| {
+                let path = format!("https://http2.akamai.com/{}.css", i);
+                let srv = futures::future::ok::<_, ()>(srv)
+                    .send_frame(frames::push_promise(1, 2 * i).request("GET", path));
+
+                if i > 10 {
+                    Box::new(srv.recv_frame(frames::reset(2 * i).refused()))
+                        as Box<Future<Item = mock::Handle, Error = ()>>
+                } else {
+                    Box::new(srv)
+                }
+            })
+        });
+
+    // Starting a pushed response releases a reserved stream.
+    let srv = srv
+        .send_frame(frames::headers(2).response(200).eos())
+        .send_frame(frames::push_promise(1, 402).request("GET", "https://http2.akamai.com/201.css"))
+        .send_frame(frames::push_promise(1, 404).request("GET", "https://http2.akamai.com/202.css"))
+        .recv_frame(frames::reset(404).refused())
+        .send_frame(frames::headers(1).response(200).eos());
+
+    let mut srv: Box<Future<Item = mock::Handle, Error = ()>> = Box::new(srv);
+
+    // The promises still pending are canceled once dropped.
+    for &id in &[4, 6, 8, 10, 12, 14, 16, 18, 20, 402] {
+        srv = Box::new(srv.recv_frame(frames::reset(id).cancel()));
+    }
+
+    let srv = srv.close();
+
+    let client = client::Builder::new()
+        .max_reserved_remote_streams(10)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+            let (mut resp, _) = client.send_request(request, true).unwrap();
+            let promises = resp.push_promises();
+
+            conn.drive(resp.expect("response"))
+                .and_then(move |(conn, resp)| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    conn.drive(promises.collect().expect("promises"))
+                })
+                .and_then(|(conn, promises)| {
+                    let paths = promises.iter()
+                        .map(|promise| promise.request().uri().path().to_string())
+                        .collect::<Vec<_>>();
+
+                    let mut expected = (1..11)
+                        .map(|i| format!("/{}.css", i))
+                        .collect::<Vec<_>>();
+                    expected.push("/201.css".to_string());
+
+                    assert_eq!(paths, expected);
+
+                    drop(promises);
+                    conn.expect("client")
+                })
+                .map(|c| (c, client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn recv_push_promise_over_max_concurrent_streams() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().max_concurrent_streams(1))
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::push_promise(1, 2).request("GET", "https://http2.akamai.com/a.css"))
+        .send_frame(frames::push_promise(1, 4).request("GET", "https://http2.akamai.com/b.css"))
+        .send_frame(frames::headers(2).response(200))
+        // Reserved streams do not count towards the limit.
+        .send_frame(frames::push_promise(1, 6).request("GET", "https://http2.akamai.com/c.css"))
+        // Only one pushed response is received at a time.
+        .send_frame(frames::headers(4).response(200))
+        .recv_frame(frames::reset(4).refused())
+        .send_frame(frames::data(2, "a").eos())
+        .send_frame(frames::headers(6).response(200).eos())
+        .send_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let client = client::Builder::new()
+        .max_concurrent_streams(1)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+            let (mut resp, _) = client.send_request(request, true).unwrap();
+            let promises = resp.push_promises().collect();
+
+            conn.drive(resp.join(promises))
+                .and_then(|(conn, (resp, promises))| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+
+                    let pushed = promises.into_iter().map(|promise| {
+                        let (_, resp) = promise.into_parts();
+                        resp.then(|res| {
+                            Ok::<_, ()>(res.map(|resp| resp.status()).map_err(|e| e.reason()))
+                        })
+                    });
+
+                    conn.drive(::futures::future::join_all(pushed))
+                })
+                .and_then(|(conn, pushed)| {
+                    assert_eq!(pushed, vec![
+                        Ok(StatusCode::OK),
+                        Err(Some(Reason::REFUSED_STREAM)),
+                        Ok(StatusCode::OK),
+                    ]);
+
+                    conn.expect("client")
+                })
+                .map(|c| (c, client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
 #[test]
 fn recv_push_promise_over_max_header_list_size() {
     let _ = ::env_logger::try_init();
@@ -189,9 +403,97 @@ fn recv_push_promise_over_max_header_list_size() {
 }
 
 #[test]
-#[ignore]
 fn recv_push_promise_with_unsafe_method_is_stream_error() {
-    // for instance, when :method = POST
+    let _ = ::env_logger::try_init();
+
+    let (io, srv) = mock::new();
+    let mock = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::push_promise(1, 2).request("POST", "https://http2.akamai.com/form"))
+        .recv_frame(frames::reset(2).protocol_error())
+        .send_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let h2 = client::handshake(io).unwrap().and_then(|(mut client, h2)| {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("https://http2.akamai.com/")
+            .body(())
+            .unwrap();
+        let (mut resp, _) = client.send_request(request, true).unwrap();
+
+        // The invalid promise is not handed out.
+        let pushed = resp.push_promises()
+            .collect()
+            .map(|promises| assert!(promises.is_empty()))
+            .expect("pushed");
+
+        let req = resp.unwrap()
+            .and_then(|resp| {
+                assert_eq!(resp.status(), StatusCode::OK);
+                Ok(())
+            });
+
+        h2.drive(req.join(pushed))
+            .and_then(|(conn, _)| conn.expect("client"))
+    });
+
+    h2.join(mock).wait().unwrap();
+}
+
+#[test]
+fn recv_push_promise_on_unopened_stream_is_conn_error() {
+    let _ = ::env_logger::try_init();
+
+    let (io, srv) = mock::new();
+    let mock = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::push_promise(3, 2).request("GET", "https://http2.akamai.com/style.css"))
+        .recv_frame(frames::go_away(0).protocol_error())
+        .close();
+
+    let h2 = client::handshake(io).unwrap().and_then(|(mut client, h2)| {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("https://http2.akamai.com/")
+            .body(())
+            .unwrap();
+
+        let req = client
+            .send_request(request, true)
+            .unwrap()
+            .0
+            .then(|res| {
+                assert!(res.is_err());
+                Ok::<_, ()>(())
+            });
+
+        // client should see a protocol error
+        let conn = h2.then(|res| {
+            let err = res.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "protocol error: unspecific protocol error detected"
+            );
+            Ok::<(), ()>(())
+        });
+
+        conn.unwrap().join(req)
+    });
+
+    h2.join(mock).wait().unwrap();
 }
 
 #[test]
@@ -305,3 +607,371 @@ fn recv_push_promise_dup_stream_id() {
 
     h2.join(mock).wait().unwrap();
 }
+
+#[test]
+fn send_push_with_body() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::push_promise(1, 2).request("GET", "https://example.com/style.css"))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(2).response(200))
+        .recv_frame(frames::data(2, "body {}").eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            let req = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(())
+                .unwrap();
+            let mut pushed = stream.push_request(req).unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            let mut body = pushed.send_response(rsp, false).unwrap();
+            body.send_data("body {}".into(), true).unwrap();
+
+            srv.into_future().unwrap()
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_push_when_push_disabled() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let settings = frames::settings().enable_push(false);
+
+    let client = client
+        .assert_server_handshake_with_settings(settings)
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            let req = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(())
+                .unwrap();
+            let err = stream.push_request(req).unwrap_err();
+            assert_eq!(err.to_string(), "user error: server push disabled by peer");
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_push_stream_id_overflows() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(
+            frames::push_promise(1, (::std::u32::MAX >> 1) - 1)
+                .request("GET", "https://example.com/style.css"),
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers((::std::u32::MAX >> 1) - 1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .initial_stream_id((::std::u32::MAX >> 1) - 1)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            let req = || {
+                Request::builder()
+                    .uri("https://example.com/style.css")
+                    .body(())
+                    .unwrap()
+            };
+
+            // The first push uses the last even stream ID.
+            let mut pushed = stream.push_request(req()).unwrap();
+
+            let err = stream.push_request(req()).unwrap_err();
+            assert!(err.is_stream_id_overflow());
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            pushed.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_push_after_response_end() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            let req = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(())
+                .unwrap();
+            let err = stream.push_request(req).unwrap_err();
+            assert_eq!(err.to_string(), "user error: inactive stream");
+
+            srv.into_future().unwrap()
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_push_with_invalid_request() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::push_promise(1, 2).request("GET", "https://example.com/style.css"))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(2).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri("https://example.com/form")
+                .body(())
+                .unwrap();
+            let err = stream.push_request(req).unwrap_err();
+            assert_eq!(err.to_string(), "user error: malformed push request");
+
+            let req = Request::builder().uri("/style.css").body(()).unwrap();
+            let err = stream.push_request(req).unwrap_err();
+            assert_eq!(err.to_string(), "user error: request URI missing scheme and authority");
+
+            // The stream ID was not consumed by the invalid requests.
+            let req = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(())
+                .unwrap();
+            let mut pushed = stream.push_request(req).unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            pushed.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_push_over_max_concurrent_streams() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let settings = frames::settings().max_concurrent_streams(1);
+
+    let client = client
+        .assert_server_handshake_with_settings(settings)
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::push_promise(1, 2).request("GET", "https://example.com/a.css"))
+        .recv_frame(frames::push_promise(1, 4).request("GET", "https://example.com/b.css"))
+        .recv_frame(frames::headers(2).response(200))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::data(2, "a").eos())
+        // Stream 4 is opened once stream 2 is closed.
+        .recv_frame(frames::headers(4).response(200))
+        .recv_frame(frames::data(4, "b").eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            let mut bodies = vec![];
+
+            for &(path, body) in &[("a.css", "a"), ("b.css", "b")] {
+                let req = Request::builder()
+                    .uri(format!("https://example.com/{}", path))
+                    .body(())
+                    .unwrap();
+                let mut pushed = stream.push_request(req).unwrap();
+
+                let rsp = Response::builder().status(200).body(()).unwrap();
+                let mut stream = pushed.send_response(rsp, false).unwrap();
+                stream.send_data(body.into(), true).unwrap();
+                bodies.push(stream);
+            }
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap().map(|_| drop(bodies))
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn exchange_pushed_response() {
+    let _ = ::env_logger::try_init();
+    let (io, srv_io) = mock::new();
+
+    let srv = server::handshake(srv_io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            assert_eq!(req.uri(), "https://example.com/");
+
+            let req = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(())
+                .unwrap();
+            let mut pushed = stream.push_request(req).unwrap();
+
+            let rsp = Response::builder().status(200).body(()).unwrap();
+            let mut body = stream.send_response(rsp, false).unwrap();
+            body.send_data("<html></html>".into(), true).unwrap();
+
+            let rsp = Response::builder()
+                .status(200)
+                .header("content-type", "text/css")
+                .body(())
+                .unwrap();
+            let mut body = pushed.send_response(rsp, false).unwrap();
+            body.send_data("body {}".into(), true).unwrap();
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+        });
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://example.com/")
+                .body(())
+                .unwrap();
+            let (mut response, _) = client.send_request(request, true).unwrap();
+
+            let pushed = response.push_promises()
+                .and_then(|promise| {
+                    let (req, resp) = promise.into_parts();
+                    assert_eq!(req.uri(), "https://example.com/style.css");
+
+                    resp.and_then(|resp| {
+                        assert_eq!(resp.status(), StatusCode::OK);
+                        assert_eq!(resp.headers()["content-type"], "text/css");
+                        resp.into_body().concat2()
+                    })
+                })
+                .collect()
+                .map(|bodies| {
+                    assert_eq!(bodies.len(), 1);
+                    assert_eq!(bodies[0], "body {}");
+                });
+
+            let req = response
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    resp.into_body().concat2()
+                })
+                .map(|body| assert_eq!(body, "<html></html>"));
+
+            conn.drive(req.join(pushed).expect("response")).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
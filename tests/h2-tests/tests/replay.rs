@@ -0,0 +1,90 @@
+extern crate h2_support;
+
+use h2_support::prelude::*;
+
+use h2::test::{Capture, Outbound, Replay};
+
+use std::time::{Duration, Instant};
+
+const GO_AWAY_AFTER_RESPONSE: &str = include_str!("captures/go_away_after_response.txt");
+
+/// Answers the request of the replayed client with `status`.
+fn serve(replay: Replay, status: StatusCode) -> Outbound {
+    let outbound = replay.outbound();
+
+    let server = server::handshake(replay)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(move |(request, srv)| {
+            let (request, mut respond) = request.unwrap();
+            assert_eq!(request.method(), Method::GET);
+            assert_eq!(request.uri().path(), "/");
+
+            let response = Response::builder().status(status).body(()).unwrap();
+            respond.send_response(response, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(request, _)| assert!(request.is_none()));
+
+    server.wait().unwrap();
+    outbound
+}
+
+#[test]
+fn go_away_after_response() {
+    let _ = ::env_logger::try_init();
+
+    let capture = Capture::parse(GO_AWAY_AFTER_RESPONSE).unwrap();
+
+    // The SETTINGS frame sent by the server differs from the captured one.
+    let replay = Replay::new(&capture).ignore_timing();
+    serve(replay, StatusCode::OK).assert_matches(&capture);
+}
+
+#[test]
+fn go_away_after_response_with_timing() {
+    let _ = ::env_logger::try_init();
+
+    let capture = Capture::parse(GO_AWAY_AFTER_RESPONSE).unwrap();
+    let start = Instant::now();
+
+    serve(Replay::new(&capture), StatusCode::OK).assert_matches(&capture);
+    assert!(start.elapsed() >= Duration::from_millis(5));
+}
+
+#[test]
+#[should_panic(expected = "frame #2 does not match the capture")]
+fn outbound_differing_from_capture() {
+    let capture = Capture::parse(GO_AWAY_AFTER_RESPONSE).unwrap();
+    let replay = Replay::new(&capture).ignore_timing();
+    serve(replay, StatusCode::NOT_FOUND).assert_matches(&capture);
+}
+
+#[test]
+fn parse_hex_and_base64() {
+    let hex = Capture::parse("< 000004 01 00000001\n> 0000 0401 0000 0001").unwrap();
+    let base64 = Capture::parse("< base64:AAAEAQAAAAE=\n> base64:AAAEAQAAAAE").unwrap();
+
+    assert_eq!(hex.inbound(), &[0, 0, 4, 1, 0, 0, 0, 1][..]);
+    assert_eq!(hex.outbound(), hex.inbound());
+    assert_eq!(base64.inbound(), hex.inbound());
+    assert_eq!(base64.outbound(), hex.inbound());
+}
+
+#[test]
+fn parse_errors() {
+    let cases = [
+        ("< 0", 1),
+        ("# comment\n\n< 00\n> 0g", 4),
+        ("< base64:A", 1),
+        ("< 00\n@ 10", 2),
+        ("< 00\n= 00", 2),
+        ("<", 1),
+    ];
+
+    for &(dump, line) in &cases {
+        let err = Capture::parse(dump).unwrap_err();
+        assert_eq!(err.line(), line, "dump={:?}; err={}", dump, err);
+    }
+}
@@ -75,6 +75,78 @@ fn server_builder_set_max_concurrent_streams() {
     h2.join(client).wait().expect("wait");
 }
 
+#[test]
+fn recv_half_closed_streams_up_to_max_concurrent_streams() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().max_concurrent_streams(2))
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .send_frame(
+            frames::headers(3)
+                .request("POST", "https://example.com/"),
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        // Half-closed streams count towards the limit.
+        .send_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::reset(5).refused())
+        // Closing stream 3 makes room for stream 7.
+        .send_frame(frames::data(3, "").eos())
+        .send_frame(
+            frames::headers(7)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::headers(7).response(200).eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let mut builder = server::Builder::new();
+    builder.max_concurrent_streams(2);
+
+    let rsp = || http::Response::builder().status(200).body(()).unwrap();
+
+    let srv = builder
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (_, stream1) = reqstream.unwrap();
+            srv.into_future().unwrap().map(move |(reqstream, srv)| {
+                (stream1, reqstream.unwrap(), srv)
+            })
+        })
+        .and_then(move |(mut stream1, (req3, mut stream3), srv)| {
+            assert_eq!(req3.method(), &http::Method::POST);
+            stream3.send_response(rsp(), true).unwrap();
+
+            srv.into_future().unwrap().and_then(move |(reqstream, srv)| {
+                let (req7, mut stream7) = reqstream.unwrap();
+                assert_eq!(req7.method(), &http::Method::GET);
+
+                stream7.send_response(rsp(), true).unwrap();
+                stream1.send_response(rsp(), true).unwrap();
+                drop(req3);
+
+                srv.into_future().unwrap()
+            })
+        })
+        .map(|(reqstream, _)| assert!(reqstream.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
+
 #[test]
 fn serve_request() {
     let _ = ::env_logger::try_init();
@@ -233,6 +305,97 @@ fn abrupt_shutdown() {
     srv.join(client).wait().expect("wait");
 }
 
+#[test]
+fn abrupt_shutdown_with_debug_data() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+        )
+        .recv_frame(frames::go_away(1).calm().debug_data("slow down"))
+        .close();
+
+    let srv = server::handshake(io).expect("handshake").and_then(|srv| {
+        srv.into_future().unwrap().and_then(|(_, mut srv)| {
+            srv.abrupt_shutdown_with_debug_data(
+                Reason::ENHANCE_YOUR_CALM,
+                Bytes::from_static(b"slow down"),
+            );
+            srv.into_future().then(|res| {
+                let err = res.err().expect("connection error").0;
+                assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+                // Our own GOAWAY is not reported as coming from the peer
+                assert!(err.is_go_away());
+                assert!(err.is_local());
+                assert!(!err.is_remote());
+                assert!(err.debug_data().is_none());
+                Ok::<(), ()>(())
+            })
+        })
+    });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn abort_from_stream_handler() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+        )
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::go_away(3).calm().debug_data("slow down"))
+        .close();
+
+    let srv = server::handshake(io).expect("handshake").and_then(|srv| {
+        let handle = srv.abort_handle();
+
+        srv.into_future().unwrap().and_then(|(req, srv)| {
+            let (req, _stream) = req.unwrap();
+            let body = req.into_body().into_future().then(|res| {
+                let err = res.err().expect("body error").0;
+                assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+                assert!(err.is_go_away());
+                assert!(err.is_local());
+                Ok::<(), ()>(())
+            });
+
+            srv.into_future().unwrap().and_then(move |(req, srv)| {
+                // The handler for the second stream aborts the connection
+                let (_req, _stream) = req.unwrap();
+                handle.clone().abort(Reason::ENHANCE_YOUR_CALM, b"slow down");
+
+                srv.into_future().then(|res| {
+                    let err = res.err().expect("connection error").0;
+                    assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+                    assert!(err.is_go_away());
+                    assert!(err.is_local());
+                    Ok::<(), ()>(())
+                }).join(body)
+            })
+        })
+    });
+
+    srv.join(client).wait().expect("wait");
+}
+
 #[test]
 fn graceful_shutdown() {
     let _ = ::env_logger::try_init();
@@ -260,11 +423,12 @@ fn graceful_shutdown() {
         )
         .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
         .recv_frame(frames::go_away(3))
-        // streams sent after GOAWAY receive no response
+        // streams sent after GOAWAY are refused
         .send_frame(
             frames::headers(7)
                 .request("GET", "https://example.com/"),
         )
+        .recv_frame(frames::reset(7).refused())
         .send_frame(frames::data(7, "").eos())
         .send_frame(frames::data(3, "").eos())
         .recv_frame(frames::headers(3).response(200).eos())
@@ -319,6 +483,114 @@ fn graceful_shutdown() {
     srv.join(client).wait().expect("wait");
 }
 
+#[test]
+fn graceful_shutdown_timeout() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::go_away(2147483647))
+        .recv_frame(frames::ping(frame::Ping::SHUTDOWN))
+        .recv_frame(frames::headers(1).response(200).eos())
+        // The PING is not acknowledged before the next frame is received,
+        // so the final GOAWAY is sent as soon as the connection is polled.
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::go_away(1))
+        .recv_frame(frames::reset(3).refused())
+        // A late PONG doesn't trigger another GOAWAY
+        .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .graceful_shutdown_timeout(Duration::from_millis(0))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap()
+        })
+        .and_then(|(reqstream, mut srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+
+            assert_eq!(req.method(), &http::Method::GET);
+
+            srv.graceful_shutdown();
+
+            let rsp = http::Response::builder()
+                .status(200)
+                .body(())
+                .unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _srv)| {
+            assert!(req.is_none(), "unexpected request");
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn graceful_shutdown_timeout_without_frames() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::go_away(StreamId::MAX))
+        .recv_frame(frames::ping(frame::Ping::SHUTDOWN))
+        .recv_frame(frames::headers(1).response(200).eos())
+        // The PING is never acknowledged, and no other frame is received.
+        .recv_frame(frames::go_away(1))
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .graceful_shutdown_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap()
+        })
+        .and_then(|(reqstream, mut srv)| {
+            let (_, mut stream) = reqstream.unwrap();
+
+            srv.graceful_shutdown();
+            let started_at = clock::now();
+
+            let rsp = http::Response::builder()
+                .status(200)
+                .body(())
+                .unwrap();
+            stream.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap().map(move |(req, _srv)| {
+                assert!(req.is_none(), "unexpected request");
+                assert!(clock::now() >= started_at + Duration::from_secs(10));
+            })
+        });
+
+    clock::run(srv.join(client)).expect("wait");
+}
+
 #[test]
 fn sends_reset_cancel_when_res_body_is_dropped() {
     let _ = ::env_logger::try_init();
@@ -605,3 +877,2612 @@ fn server_error_on_unclean_shutdown() {
 
     srv.wait().expect_err("should error");
 }
+
+#[test]
+fn recv_connect_protocol_without_setting() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("CONNECT", "https://example.com/chat")
+                .protocol("websocket"),
+        )
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap());
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn server_builder_enable_connect_protocol() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().enable_connect_protocol(1))
+        .send_frame(
+            frames::headers(1)
+                .request("CONNECT", "https://example.com/chat")
+                .protocol("websocket"),
+        )
+        .recv_frame(frames::headers(1).response(200))
+        .send_frame(frames::data(1, &b""[..]).eos())
+        .recv_frame(frames::data(1, &b""[..]).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .enable_connect_protocol()
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, mut stream) = reqstream.unwrap();
+
+                assert_eq!(req.method(), &http::Method::CONNECT);
+                assert_eq!(req.extensions().get::<h2::Protocol>().unwrap().as_str(), "websocket");
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                let mut send = stream.send_response(rsp, false).unwrap();
+                send.send_data("".into(), true).unwrap();
+
+                srv.into_future().unwrap()
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_protocol_without_connect_is_malformed() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().enable_connect_protocol(1))
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/chat")
+                .protocol("websocket")
+                .eos(),
+        )
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let srv = server::Builder::new()
+        .enable_connect_protocol()
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap());
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn websocket_handshake_over_extended_connect() {
+    let _ = ::env_logger::try_init();
+    let (io, srv_io) = mock::new();
+
+    let srv = server::Builder::new()
+        .enable_connect_protocol()
+        .handshake::<_, Bytes>(srv_io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+
+            // The request of section 5.1 of RFC 8441
+            assert_eq!(req.method(), &http::Method::CONNECT);
+            assert_eq!(req.extensions().get::<h2::Protocol>().unwrap().as_str(), "websocket");
+            assert_eq!(req.uri(), "https://server.example.com/chat");
+            assert_eq!(req.headers()["sec-websocket-protocol"], "chat, superchat");
+            assert_eq!(req.headers()["sec-websocket-extensions"], "permessage-deflate");
+            assert_eq!(req.headers()["sec-websocket-version"], "13");
+            assert_eq!(req.headers()["origin"], "http://www.example.com");
+
+            let rsp = http::Response::builder()
+                .status(200)
+                .header("sec-websocket-protocol", "chat")
+                .body(())
+                .unwrap();
+            let mut send = stream.send_response(rsp, false).unwrap();
+
+            // Echo the WebSocket frames
+            let tunnel = req.into_body()
+                .concat2()
+                .map(move |body| {
+                    send.send_data(body, true).unwrap();
+                })
+                .expect("tunnel");
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(tunnel)
+        });
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            let changes = conn.settings_changes().into_future().map_err(|(e, _)| e);
+            conn.drive(changes).map(move |(conn, _)| (client, conn))
+        })
+        .and_then(|(mut client, conn)| {
+            assert!(client.is_extended_connect_protocol_enabled());
+
+            let mut request = Request::builder()
+                .method(http::Method::CONNECT)
+                .uri("https://server.example.com/chat")
+                .header("sec-websocket-protocol", "chat, superchat")
+                .header("sec-websocket-extensions", "permessage-deflate")
+                .header("sec-websocket-version", "13")
+                .header("origin", "http://www.example.com")
+                .body(())
+                .unwrap();
+            request.extensions_mut().insert(h2::Protocol::from_static("websocket"));
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+
+            let tunnel = response
+                .and_then(move |resp| {
+                    assert_eq!(resp.status(), http::StatusCode::OK);
+                    assert_eq!(resp.headers()["sec-websocket-protocol"], "chat");
+
+                    // A masked text frame containing "Hello"
+                    let frame = Bytes::from_static(
+                        &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]);
+                    stream.send_data(frame, true).unwrap();
+
+                    resp.into_body().concat2()
+                })
+                .map(|body| assert_eq!(body.len(), 11));
+
+            conn.drive(tunnel.expect("tunnel")).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn recv_connect_with_scheme_and_path_is_malformed() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        // Only the extended CONNECT method sets :scheme and :path
+        .send_frame(frames::headers(1).request("CONNECT", "https://example.com/"))
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap());
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_connect_data_before_response() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("CONNECT", "example.com:443"))
+        .send_frame(frames::data(1, "hello"))
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, stream) = reqstream.unwrap();
+
+            assert_eq!(req.method(), &http::Method::CONNECT);
+            assert_eq!(req.uri().authority_part().unwrap(), "example.com:443");
+            assert!(req.uri().scheme_part().is_none());
+
+            let body = req.into_body().concat2().then(move |res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                drop(stream);
+                Ok(())
+            });
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn connect_tunnel_half_close() {
+    let _ = ::env_logger::try_init();
+    let (io, srv_io) = mock::new();
+
+    let srv = server::handshake(srv_io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            assert_eq!(req.method(), &http::Method::CONNECT);
+            assert_eq!(req.uri().authority_part().unwrap(), "a.example.com:443");
+
+            // The server closes its direction first.
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            let mut send = stream.send_response(rsp, false).unwrap();
+            send.send_data("from server".into(), true).unwrap();
+
+            let tunnel_a = req.into_body()
+                .concat2()
+                .map(|body| assert_eq!(body, "from client"))
+                .expect("tunnel a");
+
+            srv.into_future().unwrap().map(|(reqstream, srv)| (reqstream, srv, tunnel_a))
+        })
+        .and_then(|(reqstream, srv, tunnel_a)| {
+            let (req, mut stream) = reqstream.unwrap();
+            assert_eq!(req.uri().authority_part().unwrap(), "b.example.com:443");
+
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            let mut send = stream.send_response(rsp, false).unwrap();
+
+            // The client closes its direction first.
+            let tunnel_b = req.into_body()
+                .concat2()
+                .map(move |body| {
+                    assert_eq!(body, "from client");
+                    send.send_data("from server".into(), true).unwrap();
+                })
+                .expect("tunnel b");
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join3(tunnel_a, tunnel_b)
+        });
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let connect = |authority| {
+                Request::builder()
+                    .method(http::Method::CONNECT)
+                    .uri(authority)
+                    .body(())
+                    .unwrap()
+            };
+
+            let (response, mut send_a) = client
+                .send_request(connect("a.example.com:443"), false)
+                .unwrap();
+
+            let tunnel_a = response
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), http::StatusCode::OK);
+                    resp.into_body().concat2()
+                })
+                .map(move |body| {
+                    assert_eq!(body, "from server");
+                    send_a.send_data("from client".into(), true).unwrap();
+                });
+
+            let (response, mut send_b) = client
+                .send_request(connect("b.example.com:443"), false)
+                .unwrap();
+
+            let tunnel_b = response
+                .and_then(move |resp| {
+                    assert_eq!(resp.status(), http::StatusCode::OK);
+                    send_b.send_data("from client".into(), true).unwrap();
+                    resp.into_body().concat2()
+                })
+                .map(|body| assert_eq!(body, "from server"));
+
+            conn.drive(tunnel_a.join(tunnel_b).expect("tunnels"))
+                .and_then(move |(conn, _)| {
+                    drop(client);
+                    conn.expect("client")
+                })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn send_alt_svc() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        // Servers ignore ALTSVC frames
+        .send_frame(frames::alt_svc(0, "https://example.com", r#"h2=":443""#))
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::alt_svc(0, "https://example.com", r#"h2=":443"; ma=60"#))
+        .recv_frame(frames::alt_svc(1, "", r#"h2="alt.example.com:8000""#))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, mut srv)| {
+                let (_, mut stream) = reqstream.unwrap();
+
+                let err = srv.send_alt_svc("".into(), r#"h2=":443""#.into()).unwrap_err();
+                assert_eq!(err.to_string(), "user error: invalid alternative services");
+
+                let err = stream.send_alt_svc("".into()).unwrap_err();
+                assert_eq!(err.to_string(), "user error: invalid alternative services");
+
+                srv.send_alt_svc("https://example.com".into(), r#"h2=":443"; ma=60"#.into())
+                    .unwrap();
+                stream.send_alt_svc(r#"h2="alt.example.com:8000""#.into()).unwrap();
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+
+                // The stream is closed
+                let err = stream.send_alt_svc(r#"h2=":443""#.into()).unwrap_err();
+                assert_eq!(err.to_string(), "user error: inactive stream");
+
+                srv.into_future().unwrap()
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_extension_frames() {
+    let _ = ::env_logger::try_init();
+    let (io, mut client) = mock::new();
+    client.codec_mut().set_recv_extension_types(vec![0xf0]);
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::extension(0xf0, 0x1, 0, b"conn"))
+        .recv_frame(frames::extension(0xf0, 0x8, 1, &[5, 1, 2]))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, mut srv)| {
+                let (_, mut stream) = reqstream.unwrap();
+
+                // Frame types defined by the library are rejected
+                let err = srv.send_extension_frame(0x0, 0, None, "data".into()).unwrap_err();
+                assert_eq!(err.to_string(), "user error: invalid extension frame type");
+
+                // The payload must fit in the client's max frame size
+                let payload = Bytes::from(vec![0; 16_385]);
+                let err = srv.send_extension_frame(0xf0, 0, None, payload).unwrap_err();
+                assert_eq!(err.to_string(), "user error: payload too big");
+
+                srv.send_extension_frame(0xf0, 0x1, None, "conn".into()).unwrap();
+                srv.send_extension_frame(
+                    0xf0,
+                    0x8,
+                    Some(stream.stream_id()),
+                    Bytes::from(&[5, 1, 2][..]),
+                ).unwrap();
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+
+                srv.into_future().unwrap()
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_and_recv_padded_frames() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .pad(10),
+        )
+        .send_frame(frames::data(1, "hello").pad(255).eos())
+        // `:status: 200` is encoded in 1 octet.
+        .recv_frame(frames::headers(1).response(200).pad(15))
+        .recv_frame(frames::data(1, "world").pad(11).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        // Round frames up to a multiple of 16 octets.
+        .padding(|len| ((16 - len % 16) % 16) as u8)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+
+            let body = req.into_body()
+                .concat2()
+                .expect("body")
+                .map(move |body| {
+                    assert_eq!(body, "hello");
+
+                    let rsp = http::Response::builder().status(200).body(()).unwrap();
+                    let mut stream = stream.send_response(rsp, false).unwrap();
+                    stream.send_data("world".into(), true).unwrap();
+                });
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body)
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_too_many_continuation_frames() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        // HEADERS w/o END_HEADERS, followed by empty CONTINUATION frames
+        .send_bytes(&[0, 0, 1, 1, 0, 0, 0, 0, 1, 0x82])
+        .send_bytes(&[0, 0, 0, 9, 0, 0, 0, 0, 1])
+        .send_bytes(&[0, 0, 0, 9, 0, 0, 0, 0, 1])
+        .send_bytes(&[0, 0, 0, 9, 0, 0, 0, 0, 1])
+        .recv_frame(frames::go_away(0).calm())
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .max_continuation_frames(2)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().then(|res| {
+                let err = res.err().expect("connection error").0;
+                assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+                Ok::<(), ()>(())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_too_many_settings_entries() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        // A SETTINGS frame repeating MAX_CONCURRENT_STREAMS three times
+        .send_bytes(&[
+            0, 0, 18, 4, 0, 0, 0, 0, 0,
+            0, 3, 0, 0, 0, 100,
+            0, 3, 0, 0, 0, 100,
+            0, 3, 0, 0, 0, 100,
+        ])
+        .recv_frame(frames::go_away(0).calm())
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .max_settings_entries(2)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().then(|res| {
+                let err = res.err().expect("connection error").0;
+                assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+                Ok::<(), ()>(())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// Sends `frame` to the server after the handshake and asserts that the
+/// connection is closed with a GOAWAY carrying `reason`.
+fn recv_invalid_settings(frame: &'static [u8], reason: Reason) {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(frame)
+        .recv_frame(frame::GoAway::new(StreamId::zero(), reason))
+        .recv_eof();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(move |srv| {
+            srv.into_future().then(move |res| {
+                let err = res.err().expect("connection error").0;
+                assert_eq!(err.reason(), Some(reason));
+                Ok::<(), ()>(())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_settings_ack_with_payload() {
+    recv_invalid_settings(&[
+        0, 0, 6, 4, 1, 0, 0, 0, 0,
+        0, 3, 0, 0, 0, 100,
+    ], Reason::FRAME_SIZE_ERROR);
+}
+
+#[test]
+fn recv_settings_with_partial_parameter() {
+    recv_invalid_settings(&[
+        0, 0, 5, 4, 0, 0, 0, 0, 0,
+        0, 3, 0, 0, 0,
+    ], Reason::FRAME_SIZE_ERROR);
+}
+
+#[test]
+fn recv_header_table_size_update_at_limit() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().header_table_size(256))
+        // HEADERS w/ END_HEADERS and END_STREAM, resizing the table to 256
+        // before `GET https://example.com/`
+        .send_bytes(&[
+            0, 0, 19, 1, 0x5, 0, 0, 0, 1,
+            0x3f, 0xe1, 0x01, 0x82, 0x87, 0x84,
+            0x01, 11, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+        ])
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .header_table_size(256)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, mut stream) = reqstream.unwrap();
+
+                assert_eq!(req.method(), &http::Method::GET);
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+
+                srv.into_future().unwrap()
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// Sends a header block for `GET https://example.com/` starting with `prefix`
+/// to a server limiting the header table size to 256 octets, and asserts that
+/// the connection is closed with a COMPRESSION_ERROR.
+fn recv_invalid_header_table_size_update(prefix: &[u8]) {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let len = prefix.len() as u8 + 16;
+    let mut frame = vec![0, 0, len, 1, 0x5, 0, 0, 0, 1];
+    frame.extend_from_slice(prefix);
+    frame.extend_from_slice(&[0x82, 0x87, 0x84, 0x01, 11]);
+    frame.extend_from_slice(b"example.com");
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().header_table_size(256))
+        .send_bytes(&frame)
+        .recv_frame(frame::GoAway::new(StreamId::zero(), Reason::COMPRESSION_ERROR))
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .header_table_size(256)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().then(|res| {
+                let err = res.err().expect("connection error").0;
+                assert_eq!(err.reason(), Some(Reason::COMPRESSION_ERROR));
+                Ok::<(), ()>(())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_header_table_size_update_over_limit() {
+    // 257
+    recv_invalid_header_table_size_update(&[0x3f, 0xe2, 0x01]);
+}
+
+#[test]
+fn recv_header_table_size_update_missing() {
+    // The advertised size is lower than the size of the table
+    recv_invalid_header_table_size_update(&[]);
+}
+
+#[test]
+fn recv_header_table_size_update_after_header() {
+    // `:method: GET`, then a size update to 0
+    recv_invalid_header_table_size_update(&[0x3f, 0xe1, 0x01, 0x82, 0x20]);
+}
+
+#[test]
+fn recv_settings_invalid_enable_push() {
+    recv_invalid_settings(&[
+        0, 0, 6, 4, 0, 0, 0, 0, 0,
+        0, 2, 0, 0, 0, 2,
+    ], Reason::PROTOCOL_ERROR);
+}
+
+#[test]
+fn recv_settings_initial_window_size_too_large() {
+    recv_invalid_settings(&[
+        0, 0, 6, 4, 0, 0, 0, 0, 0,
+        0, 4, 0x80, 0, 0, 0,
+    ], Reason::FLOW_CONTROL_ERROR);
+}
+
+#[test]
+fn recv_settings_max_frame_size_too_small() {
+    recv_invalid_settings(&[
+        0, 0, 6, 4, 0, 0, 0, 0, 0,
+        0, 5, 0, 0, 0x3f, 0xff,
+    ], Reason::PROTOCOL_ERROR);
+}
+
+#[test]
+fn set_max_concurrent_streams_after_handshake() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_custom_settings(frames::settings().max_concurrent_streams(10))
+        .send_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(frames::headers(3).request("GET", "https://example.com/").eos())
+        .send_frame(frames::headers(5).request("GET", "https://example.com/").eos())
+        .recv_frame(frames::settings().max_concurrent_streams(2))
+        .send_frame(frames::settings_ack())
+        // Three streams are still open, above the new limit.
+        .send_frame(frames::headers(7).request("GET", "https://example.com/").eos())
+        .recv_frame(frames::reset(7).refused())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(3).response(200).eos())
+        .recv_frame(frames::headers(5).response(200).eos())
+        .close();
+
+    let mut builder = server::Builder::new();
+    builder.max_concurrent_streams(10);
+
+    let h2 = builder
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(s1, srv)| {
+            srv.into_future().unwrap().map(move |(s3, srv)| (vec![s1, s3], srv))
+        })
+        .and_then(|(mut streams, srv)| {
+            srv.into_future().unwrap().map(move |(s5, srv)| {
+                streams.push(s5);
+                (streams, srv)
+            })
+        })
+        .and_then(|(streams, mut srv)| {
+            let mut acked = srv.set_max_concurrent_streams(Some(2));
+            let mut srv = Some(srv);
+
+            // Drive the connection until the client acknowledges the new limit.
+            poll_fn(move || {
+                let _ = srv.as_mut().unwrap().poll_close().expect("server");
+
+                match acked.poll().expect("acked") {
+                    futures::Async::Ready(()) => Ok::<_, ()>(srv.take().unwrap().into()),
+                    futures::Async::NotReady => Ok(futures::Async::NotReady),
+                }
+            }).map(move |srv| (streams, srv))
+        })
+        .and_then(|(streams, srv)| {
+            for stream in streams {
+                let (req, mut stream) = stream.unwrap();
+                assert_eq!(req.method(), &http::Method::GET);
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+            }
+
+            srv.into_future().unwrap()
+        });
+
+    h2.join(client).wait().expect("wait");
+}
+
+#[test]
+fn refuse_streams_beyond_pending_accept_backlog() {
+    use futures::sync::oneshot;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+    let (tx, rx) = oneshot::channel();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::headers(1).request("GET", "https://example.com/").eos())
+        .send_frame(frames::headers(3).request("GET", "https://example.com/").eos())
+        // The backlog is full, the application did not accept any stream yet.
+        .send_frame(frames::headers(5).request("GET", "https://example.com/").eos())
+        .recv_frame(frames::reset(5).refused())
+        .map(move |client| {
+            tx.send(()).unwrap();
+            client
+        })
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::headers(3).response(200).eos())
+        // Accepting streams made room in the backlog again.
+        .send_frame(frames::headers(7).request("GET", "https://example.com/").eos())
+        .recv_frame(frames::headers(7).response(200).eos())
+        .close();
+
+    let mut builder = server::Builder::new();
+    builder.max_pending_accept_streams(2);
+
+    let h2 = builder
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(move |srv| {
+            let mut rx = rx;
+            let mut srv = Some(srv);
+
+            // Drive the connection without accepting streams until the
+            // client saw the refusal.
+            poll_fn(move || {
+                let _ = srv.as_mut().unwrap().poll_close().expect("server");
+
+                match rx.poll().expect("rx") {
+                    futures::Async::Ready(()) => Ok::<_, ()>(srv.take().unwrap().into()),
+                    futures::Async::NotReady => Ok(futures::Async::NotReady),
+                }
+            })
+        })
+        .and_then(|srv| {
+            srv.for_each(|(req, mut stream)| {
+                assert_eq!(req.method(), &http::Method::GET);
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).map_err(|e| panic!("server err: {:?}", e))
+        });
+
+    h2.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_too_many_resets() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    // Open and immediately reset 200 streams
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .map(|mut client| {
+            for i in 0..200 {
+                let id = 2 * i + 1;
+                client.send(
+                    frames::headers(id)
+                        .request("GET", "https://example.com/")
+                        .eos()
+                        .into()
+                ).unwrap();
+                client.send(frames::reset(id).cancel().into()).unwrap();
+            }
+            client
+        })
+        // The 101st reset stream goes over the limit
+        .recv_frame(frames::go_away(201).calm())
+        .recv_eof();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.for_each(|_| Ok(())).then(|res| {
+            let err = res.expect_err("server");
+            assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+            Ok::<_, ()>(())
+        }));
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn resets_after_response_not_limited() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+        )
+        .send_frame(frames::reset(1).cancel())
+        .send_frame(
+            frames::headers(3)
+                .request("POST", "https://example.com/")
+        )
+        .recv_frame(frames::headers(3).response(200))
+        .send_frame(frames::reset(3).cancel())
+        .send_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(5).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .max_remote_reset_streams(1)
+        .remote_reset_stream_duration(Duration::from_secs(60))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            // Keep the streams around so they are not reset by the server
+            let mut bodies = vec![];
+
+            srv.for_each(move |(req, mut stream)| {
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                let eos = req.method() == &http::Method::GET;
+
+                // Stream 1 was already reset by the client
+                if let Ok(body) = stream.send_response(rsp, eos) {
+                    bodies.push(body);
+                }
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// HPACK encoded `:method: GET`, `:scheme: https` and
+/// `:authority: example.com`, all from the static table.
+const GET_PSEUDO: &'static [u8] = &[
+    0x82, 0x87, 0x01, 0x0b,
+    b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+];
+
+/// HPACK encoded `:path: /`.
+const PATH: &'static [u8] = &[0x84];
+
+/// HPACK encoded `x-foo: bar`, as a literal without indexing.
+const X_FOO: &'static [u8] = &[0x00, 0x05, b'x', b'-', b'f', b'o', b'o', 0x03, b'b', b'a', b'r'];
+
+/// Returns a HEADERS frame on `id` with the END_STREAM and END_HEADERS flags,
+/// carrying the concatenation of `fields`.
+fn raw_headers(id: u8, fields: &[&[u8]]) -> Vec<u8> {
+    let block = fields.concat();
+    let mut frame = vec![0, 0, block.len() as u8, 1, 0x5, 0, 0, 0, id];
+    frame.extend(block);
+    frame
+}
+
+/// Sends a request with the header block `fields` on the stream 1 and asserts
+/// that the stream is reset with PROTOCOL_ERROR. The request on the stream 3
+/// is then served, as malformed requests are a stream error.
+fn recv_malformed_request(fields: &[&[u8]]) {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, fields))
+        .recv_frame(frames::reset(1).protocol_error())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(req, mut stream)| {
+                assert_eq!(req.uri(), "https://example.com/");
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_pseudo_header_after_regular_header_is_malformed() {
+    recv_malformed_request(&[GET_PSEUDO, X_FOO, PATH]);
+}
+
+#[test]
+fn recv_duplicate_path_is_malformed() {
+    // :path: /index.html
+    recv_malformed_request(&[GET_PSEUDO, PATH, &[0x85]]);
+}
+
+#[test]
+fn recv_missing_path_is_malformed() {
+    recv_malformed_request(&[GET_PSEUDO]);
+}
+
+#[test]
+fn recv_invalid_path_is_malformed() {
+    // :path: index.html
+    recv_malformed_request(&[GET_PSEUDO, &[
+        0x04, 0x0a, b'i', b'n', b'd', b'e', b'x', b'.', b'h', b't', b'm', b'l',
+    ]]);
+
+    // :path: *, which is only allowed for OPTIONS requests
+    recv_malformed_request(&[GET_PSEUDO, &[0x04, 0x01, b'*']]);
+}
+
+#[test]
+fn recv_status_in_request_is_malformed() {
+    // :status: 200
+    recv_malformed_request(&[GET_PSEUDO, PATH, &[0x88]]);
+}
+
+#[test]
+fn recv_uppercase_header_name_is_malformed() {
+    // X-Foo: bar
+    recv_malformed_request(&[GET_PSEUDO, PATH, &[
+        0x00, 0x05, b'X', b'-', b'F', b'o', b'o', 0x03, b'b', b'a', b'r',
+    ]]);
+}
+
+#[test]
+fn recv_te_other_than_trailers_is_malformed() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .field("te", "gzip")
+                .eos()
+        )
+        .recv_frame(frames::reset(1).protocol_error())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .field("te", "trailers")
+                .eos()
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(req, mut stream)| {
+                assert_eq!(req.headers()["te"], "trailers");
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_trailers_with_pseudo_header_is_malformed() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+        )
+        .send_frame(frames::data(1, &b"hello"[..]))
+        // Trailers must not contain pseudo-header fields
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, _) = reqstream.unwrap();
+
+                req.into_body().concat2().then(|res| {
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                    assert!(err.is_malformed());
+                    Ok::<(), ()>(())
+                }).join(srv.into_future().unwrap())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_forbidden_headers_without_strict_validation() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, &[GET_PSEUDO, PATH, &[
+            // X-Foo: bar
+            0x00, 0x05, b'X', b'-', b'F', b'o', b'o', 0x03, b'b', b'a', b'r',
+            // connection: close
+            0x00, 0x0a, b'c', b'o', b'n', b'n', b'e', b'c', b't', b'i', b'o', b'n',
+            0x05, b'c', b'l', b'o', b's', b'e',
+            // te: gzip
+            0x00, 0x02, b't', b'e', 0x04, b'g', b'z', b'i', b'p',
+        ]]))
+        .recv_frame(frames::headers(1).response(200).eos())
+        // Pseudo-header fields are still validated
+        .send_bytes(&raw_headers(3, &[GET_PSEUDO, X_FOO, PATH]))
+        .recv_frame(frames::reset(3).protocol_error())
+        // And so are the octets of the values
+        .send_bytes(&raw_headers(5, &[GET_PSEUDO, PATH, &x_foo(b"a\r\nb")]))
+        .recv_frame(frames::reset(5).protocol_error())
+        .close();
+
+    let srv = server::Builder::new()
+        .strict_header_validation(false)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(req, mut stream)| {
+                assert_eq!(req.headers()["x-foo"], "bar");
+                assert_eq!(req.headers()["connection"], "close");
+                assert_eq!(req.headers()["te"], "gzip");
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// HPACK encodes a header field named by the entry `index` of the static
+/// table, as a literal without indexing.
+fn literal(index: u8, value: &[u8]) -> Vec<u8> {
+    let mut field = vec![index, value.len() as u8];
+    field.extend_from_slice(value);
+    field
+}
+
+#[test]
+fn recv_malformed_path_is_malformed() {
+    recv_malformed_request(&[GET_PSEUDO, &literal(4, b"")]);
+    recv_malformed_request(&[GET_PSEUDO, &literal(4, b"/a b")]);
+    recv_malformed_request(&[GET_PSEUDO, &literal(4, b"/a#b")]);
+
+    // Neither origin-form nor absolute-form
+    recv_malformed_request(&[GET_PSEUDO, &literal(4, b"example.com/")]);
+    recv_malformed_request(&[GET_PSEUDO, &literal(4, b"urn:example")]);
+}
+
+/// HPACK encodes `x-foo: value`, as a literal without indexing.
+fn x_foo(value: &[u8]) -> Vec<u8> {
+    let mut field = vec![0x00, 0x05, b'x', b'-', b'f', b'o', b'o', value.len() as u8];
+    field.extend_from_slice(value);
+    field
+}
+
+#[test]
+fn recv_forbidden_octets_in_value_is_malformed() {
+    recv_malformed_request(&[GET_PSEUDO, PATH, &x_foo(b"a\0b")]);
+    recv_malformed_request(&[GET_PSEUDO, PATH, &x_foo(b"a\rb")]);
+    recv_malformed_request(&[GET_PSEUDO, PATH, &x_foo(b"a\nb")]);
+
+    // obs-fold
+    recv_malformed_request(&[GET_PSEUDO, PATH, &x_foo(b"a\r\n b")]);
+
+    // :path: /a<LF>b
+    recv_malformed_request(&[GET_PSEUDO, &literal(4, b"/a\nb")]);
+}
+
+#[test]
+fn recv_invalid_header_name_is_malformed() {
+    // x foo: bar
+    recv_malformed_request(&[GET_PSEUDO, PATH, &[
+        0x00, 0x05, b'x', b' ', b'f', b'o', b'o', 0x03, b'b', b'a', b'r',
+    ]]);
+
+    // x<NUL>foo: bar
+    recv_malformed_request(&[GET_PSEUDO, PATH, &[
+        0x00, 0x05, b'x', 0, b'f', b'o', b'o', 0x03, b'b', b'a', b'r',
+    ]]);
+
+    // :foo: bar
+    recv_malformed_request(&[GET_PSEUDO, PATH, &[
+        0x00, 0x04, b':', b'f', b'o', b'o', 0x03, b'b', b'a', b'r',
+    ]]);
+}
+
+#[test]
+fn recv_invalid_header_field_keeps_table_in_sync() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, &[GET_PSEUDO, PATH, &[
+            // x-foo: bar, with incremental indexing
+            0x40, 0x05, b'x', b'-', b'f', b'o', b'o', 0x03, b'b', b'a', b'r',
+            // x-bad: a<LF>b, with incremental indexing
+            0x40, 0x05, b'x', b'-', b'b', b'a', b'd', 0x03, b'a', b'\n', b'b',
+        ]]))
+        .recv_frame(frames::reset(1).protocol_error())
+        // x-foo: bar, from the dynamic table
+        .send_bytes(&raw_headers(3, &[GET_PSEUDO, PATH, &[0x80 | 63]]))
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(req, mut stream)| {
+                assert_eq!(req.headers()["x-foo"], "bar");
+                assert!(!req.headers().contains_key("x-bad"));
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// Serves the connection with malformed requests enabled: requests get a 200
+/// response and malformed requests are passed to `on_malformed`.
+fn serve_accepting_malformed<F>(io: mock::Mock, mut on_malformed: F)
+    -> Box<Future<Item = (), Error = ()>>
+where
+    F: FnMut(server::MalformedRequest, server::SendMalformedResponse<Bytes>) + 'static,
+{
+    let srv = server::Builder::new()
+        .accept_malformed_requests(true)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|mut srv| {
+            poll_fn(move || loop {
+                if let futures::Async::Ready(Some((req, respond))) = srv.poll_malformed_request()? {
+                    on_malformed(req, respond);
+                    continue;
+                }
+
+                match srv.poll()? {
+                    futures::Async::Ready(Some((req, mut stream))) => {
+                        assert_eq!(req.uri(), "https://example.com/");
+
+                        let rsp = http::Response::builder().status(200).body(()).unwrap();
+                        stream.send_response(rsp, true).unwrap();
+                    },
+                    futures::Async::Ready(None) => return Ok::<_, h2::Error>(().into()),
+                    futures::Async::NotReady => return Ok(futures::Async::NotReady),
+                }
+            }).expect("server")
+        });
+
+    Box::new(srv)
+}
+
+#[test]
+fn respond_to_malformed_request_with_body() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, &[GET_PSEUDO]))
+        .recv_frame(frames::headers(1).response(400))
+        .recv_frame(frames::data(1, "missing :path").eos())
+        // The connection is still usable.
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = serve_accepting_malformed(io, |req, mut respond| {
+        assert_eq!(req.method(), Some(&http::Method::GET));
+        assert_eq!(req.authority(), Some("example.com"));
+        assert_eq!(req.path(), None);
+        assert_eq!(req.reason(), "missing path");
+
+        let rsp = http::Response::builder().status(400).body(()).unwrap();
+        let mut body = respond.send_response(rsp, false).unwrap();
+        body.send_data("missing :path".into(), true).unwrap();
+    });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn respond_to_malformed_request_discards_body() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    // HEADERS frame on the stream 1 without the END_STREAM flag.
+    let mut headers = raw_headers(1, &[GET_PSEUDO, PATH, &x_foo(b"a\nb")]);
+    headers[4] = 0x4;
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&headers)
+        .recv_frame(frames::headers(1).response(400).eos())
+        // The request is not complete yet, so the stream is canceled.
+        .recv_frame(frames::reset(1).cancel())
+        .send_frame(frames::data(1, vec![0; 1024]).eos())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = serve_accepting_malformed(io, |req, mut respond| {
+        assert_eq!(req.path(), Some("/"));
+        assert!(req.headers().get("x-foo").is_none());
+
+        let rsp = http::Response::builder().status(400).body(()).unwrap();
+        respond.send_response(rsp, true).unwrap();
+    });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn respond_to_malformed_request_requires_client_error() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, &[GET_PSEUDO]))
+        // The stream is reset once the handle is dropped without a response.
+        .recv_frame(frames::reset(1).protocol_error())
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    let srv = serve_accepting_malformed(io, |_, mut respond| {
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        let err = respond.send_response(rsp, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: response to a malformed request without a 4xx status"
+        );
+    });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_absolute_form_path() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, &[GET_PSEUDO, &literal(4, b"https://example.com/a?b")]))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(req, mut stream)| {
+                assert_eq!(req.uri(), "https://example.com/a?b");
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_malformed_scheme_is_malformed() {
+    let authority = literal(1, b"example.com");
+
+    for scheme in &[&b""[..], b"1http", b"ht tp", b"http:"] {
+        recv_malformed_request(&[&[0x82], &literal(6, scheme), &authority, PATH]);
+    }
+}
+
+#[test]
+fn recv_malformed_authority_is_malformed() {
+    for authority in &[&b""[..], b"user@example.com", b"exa mple.com"] {
+        recv_malformed_request(&[&[0x82, 0x87], &literal(1, authority), PATH]);
+    }
+}
+
+/// HPACK encoded `content-length: <value>`, as a literal without indexing.
+fn content_length(value: &[u8]) -> Vec<u8> {
+    let mut field = vec![0x0f, 0x0d, value.len() as u8];
+    field.extend_from_slice(value);
+    field
+}
+
+#[test]
+fn recv_content_length_without_body_is_malformed() {
+    recv_malformed_request(&[GET_PSEUDO, PATH, &content_length(b"5")]);
+}
+
+#[test]
+fn recv_conflicting_content_lengths_is_malformed() {
+    recv_malformed_request(&[GET_PSEUDO, PATH, &content_length(b"0"), &content_length(b"1")]);
+    recv_malformed_request(&[GET_PSEUDO, PATH, &content_length(b"0, 1")]);
+    recv_malformed_request(&[GET_PSEUDO, PATH, &content_length(b"0,")]);
+}
+
+#[test]
+fn recv_repeated_content_length() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_bytes(&raw_headers(1, &[
+            GET_PSEUDO,
+            PATH,
+            &content_length(b"0"),
+            &content_length(b"0 ,0"),
+        ]))
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(_, mut stream)| {
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// Sends a request declaring a `content-length` of `len` with `body`, and
+/// asserts that the stream is reset once the body does not match.
+fn recv_body_not_matching_content_length(len: usize, body: &'static [u8]) {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .field("content-length", &*len.to_string())
+        )
+        .send_frame(frames::data(1, body).eos())
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, _) = reqstream.unwrap();
+
+                req.into_body().concat2().then(|res| {
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                    assert!(err.is_malformed());
+                    Ok::<(), ()>(())
+                }).join(srv.into_future().unwrap())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_body_longer_than_content_length_is_malformed() {
+    recv_body_not_matching_content_length(5, b"hello world");
+}
+
+#[test]
+fn recv_body_shorter_than_content_length_is_malformed() {
+    recv_body_not_matching_content_length(10, b"hello");
+}
+
+#[test]
+fn auto_100_continue_when_body_polled() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .field("expect", "100-continue")
+        )
+        // The body is only sent once the server asks for it
+        .recv_frame(frames::headers(1).response(100))
+        .send_frame(frames::data(1, &b"hello"[..]).eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .auto_100_continue(true)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, mut stream) = reqstream.unwrap();
+
+                req.into_body().concat2().map(move |body| {
+                    assert_eq!(body, "hello");
+
+                    let rsp = http::Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+                }).map_err(|e| panic!("body; err={:?}", e))
+                    .join(srv.into_future().unwrap())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn auto_100_continue_not_sent_when_body_not_polled() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .field("expect", "100-continue")
+        )
+        .recv_frame(frames::headers(1).response(417).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .auto_100_continue(true)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.for_each(|(_, mut stream)| {
+                // A 100 (Continue) response sent by the application is ignored
+                let rsp = http::Response::builder().status(100).body(()).unwrap();
+                stream.send_informational(rsp).unwrap();
+
+                let rsp = http::Response::builder().status(417).body(()).unwrap();
+                stream.send_response(rsp, true).unwrap();
+                Ok(())
+            }).expect("server")
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_informational_responses() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .field("expect", "100-continue")
+        )
+        .recv_frame(frames::headers(1).response(100))
+        .recv_frame(frames::headers(1).response(103).field("link", "</style.css>"))
+        .send_frame(frames::data(1, &b"hello"[..]).eos())
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, mut stream) = reqstream.unwrap();
+
+                let rsp = http::Response::builder().status(100).body(()).unwrap();
+                stream.send_informational(rsp).unwrap();
+
+                let rsp = http::Response::builder()
+                    .status(103)
+                    .header("link", "</style.css>")
+                    .body(())
+                    .unwrap();
+                stream.send_informational(rsp).unwrap();
+
+                // Final and switching protocols responses are rejected
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                assert!(stream.send_informational(rsp).is_err());
+                let rsp = http::Response::builder().status(101).body(()).unwrap();
+                assert!(stream.send_informational(rsp).is_err());
+
+                req.into_body().concat2().map(move |body| {
+                    assert_eq!(body, "hello");
+
+                    let rsp = http::Response::builder().status(200).body(()).unwrap();
+                    stream.send_response(rsp, true).unwrap();
+
+                    // No more informational responses after the final one
+                    let rsp = http::Response::builder().status(103).body(()).unwrap();
+                    assert!(stream.send_informational(rsp).is_err());
+                }).map_err(|e| panic!("body; err={:?}", e))
+                    .join(srv.into_future().unwrap())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// The head of the response accepting an h2c upgrade.
+const H2C_UPGRADE_RESPONSE: &'static [u8] = b"HTTP/1.1 101 Switching Protocols\r\n\
+    connection: Upgrade\r\n\
+    upgrade: h2c\r\n\
+    \r\n";
+
+/// Returns the HTTP/1.1 request asking to upgrade to h2c, as parsed by an
+/// HTTP/1.1 server.
+fn h2c_upgrade_request() -> http::request::Builder {
+    let mut request = http::Request::builder();
+    request
+        .method("GET")
+        .uri("/index.html")
+        .version(http::Version::HTTP_11)
+        .header("host", "example.com")
+        .header("connection", "Upgrade, HTTP2-Settings")
+        .header("upgrade", "h2c")
+        // SETTINGS_INITIAL_WINDOW_SIZE = 5
+        .header("http2-settings", "AAQAAAAF");
+    request
+}
+
+#[test]
+fn h2c_upgrade() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = tokio_io::io::read_exact(client, vec![0; H2C_UPGRADE_RESPONSE.len()])
+        .map(|(client, response)| {
+            assert_eq!(&response[..], H2C_UPGRADE_RESPONSE);
+            client
+        })
+        .map_err(|e| panic!("read upgrade response; err={:?}", e))
+        .and_then(|client| client.assert_server_handshake().map_err(|e| panic!("{:?}", e)))
+        .recv_settings()
+        // The response to the upgraded request, which is limited by the
+        // window size of the HTTP2-Settings header field.
+        .recv_frame(frames::headers(1).response(200))
+        .recv_frame(frames::data(1, &b"hello"[..]))
+        .send_frame(frames::window_update(1, 6))
+        .recv_frame(frames::data(1, &b" world"[..]).eos())
+        .close();
+
+    let request = h2c_upgrade_request()
+        .header("accept", "*/*")
+        .body(())
+        .unwrap();
+
+    let srv = server::Builder::new()
+        .handshake_upgraded::<_, Bytes>(io, request)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (req, mut stream) = reqstream.unwrap();
+
+                assert_eq!(req.version(), http::Version::HTTP_2);
+                assert_eq!(req.uri(), "http://example.com/index.html");
+                assert_eq!(req.headers()["host"], "example.com");
+                assert_eq!(req.headers()["accept"], "*/*");
+
+                // The fields of the HTTP/1.1 connection are removed
+                assert!(!req.headers().contains_key("connection"));
+                assert!(!req.headers().contains_key("upgrade"));
+                assert!(!req.headers().contains_key("http2-settings"));
+
+                // The request was fully received before the upgrade
+                assert!(req.body().is_end_stream());
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                let mut tx = stream.send_response(rsp, false).unwrap();
+                tx.send_data("hello world".into(), true).unwrap();
+
+                srv.into_future().unwrap()
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn h2c_upgrade_with_body_is_rejected() {
+    let _ = ::env_logger::try_init();
+
+    let requests = vec![
+        h2c_upgrade_request()
+            .method("POST")
+            .header("content-length", "5")
+            .body(())
+            .unwrap(),
+        h2c_upgrade_request()
+            .method("POST")
+            .header("transfer-encoding", "chunked")
+            .body(())
+            .unwrap(),
+    ];
+
+    for request in requests {
+        let (io, client) = mock::new();
+
+        let err = server::Builder::new()
+            .handshake_upgraded::<_, Bytes>(io, request)
+            .wait()
+            .unwrap_err();
+        assert!(!err.is_io());
+        assert_eq!(err.reason(), None);
+
+        // Nothing was written, the connection still speaks HTTP/1.1
+        let (_, written) = tokio_io::io::read_to_end(client, vec![]).wait().unwrap();
+        assert!(written.is_empty());
+    }
+}
+
+#[test]
+fn h2c_upgrade_with_invalid_settings_is_rejected() {
+    let _ = ::env_logger::try_init();
+
+    let mut missing = h2c_upgrade_request().body(()).unwrap();
+    missing.headers_mut().remove("http2-settings");
+
+    let mut invalid = h2c_upgrade_request().body(()).unwrap();
+    invalid.headers_mut().insert("http2-settings", "AAQAAAA".parse().unwrap());
+
+    for request in vec![missing, invalid] {
+        let (io, _client) = mock::new();
+
+        let res = server::Builder::new()
+            .handshake_upgraded::<_, Bytes>(io, request)
+            .wait();
+        assert!(res.is_err());
+    }
+}
+
+#[test]
+fn invalid_max_frame_size_is_handshake_error() {
+    let _ = ::env_logger::try_init();
+    let (io, _client) = mock::new();
+
+    let res = server::Builder::new()
+        .max_frame_size(1_000)
+        .handshake::<_, Bytes>(io)
+        .wait();
+
+    assert!(res.err().unwrap().is_invalid_settings());
+}
+
+#[test]
+fn garbage_preface_is_handshake_error() {
+    use std::io::Write;
+
+    let _ = ::env_logger::try_init();
+    let (io, mut client) = mock::new();
+
+    client.codec_mut().get_mut()
+        .write_all(b"PRI * HTTP/1.1\r\n\r\nSM\r\n\r\n")
+        .unwrap();
+
+    let err = server::handshake(io).wait().unwrap_err();
+
+    assert!(err.is_handshake());
+    assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+    assert_eq!(err.to_string(), "handshake error: preface mismatch at byte 11");
+}
+
+#[test]
+fn truncated_preface_is_handshake_error() {
+    use std::io::Write;
+
+    let _ = ::env_logger::try_init();
+    let (io, mut client) = mock::new();
+
+    client.codec_mut().get_mut().write_all(b"PRI * HTTP/2.0\r\n").unwrap();
+    drop(client);
+
+    let err = server::handshake(io).wait().unwrap_err();
+
+    assert!(err.is_handshake());
+    assert!(!err.is_io());
+    assert_eq!(err.reason(), None);
+    assert_eq!(
+        err.to_string(),
+        "handshake error: missing preface; connection closed after 16 bytes"
+    );
+}
+
+#[test]
+fn handshake_timeout_without_preface() {
+    let _ = ::env_logger::try_init();
+    let (io, _client) = mock::new();
+
+    let started_at = clock::now();
+
+    let mut handshake = server::Builder::new()
+        .handshake_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io);
+
+    assert!(handshake.is_sending_settings());
+
+    // The client never sends the preface.
+    let handshake = poll_fn(move || {
+        let res = handshake.poll();
+
+        if let Ok(futures::Async::NotReady) = res {
+            // The SETTINGS frame was flushed, and the client stalls
+            assert!(!handshake.is_sending_settings());
+            assert!(handshake.is_waiting_for_preface());
+        }
+
+        res
+    }).then(move |res| {
+        let err = res.err().expect("handshake error");
+        assert!(err.is_handshake());
+        assert_eq!(err.reason(), None);
+        assert_eq!(err.to_string(), "handshake error: timed out");
+        assert!(clock::now() >= started_at + Duration::from_secs(10));
+        Ok::<(), ()>(())
+    });
+
+    clock::run(handshake).expect("wait");
+}
+
+#[test]
+fn handshake_timeout_in_the_middle_of_preface() {
+    use std::io::Write;
+
+    let _ = ::env_logger::try_init();
+    let (io, mut client) = mock::new();
+
+    // The client stalls after the first half of the preface
+    client.write_all(b"PRI * HTTP/2.0\r\n").unwrap();
+
+    let started_at = clock::now();
+
+    let handshake = server::Builder::new()
+        .handshake_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io)
+        .then(move |res| {
+            let err = res.err().expect("handshake error");
+            assert!(err.is_handshake());
+            assert_eq!(err.to_string(), "handshake error: timed out");
+            assert!(clock::now() >= started_at + Duration::from_secs(10));
+            drop(client);
+            Ok::<(), ()>(())
+        });
+
+    clock::run(handshake).expect("wait");
+}
+
+#[test]
+fn handshake_timeout_without_initial_settings() {
+    let _ = ::env_logger::try_init();
+    let (io, mut client) = mock::new();
+
+    // The preface is not followed by a SETTINGS frame
+    client.write_preface();
+
+    let client = futures::future::ok::<_, ()>(client)
+        .recv_frame(frames::settings())
+        .recv_frame(frames::go_away(0).settings_timeout())
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .handshake_timeout(Duration::from_secs(10))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().then(|res| {
+                let err = res.map_err(|(e, _)| e).unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::SETTINGS_TIMEOUT));
+                Ok::<(), ()>(())
+            })
+        });
+
+    clock::run(srv.join(client)).expect("wait");
+}
+
+#[test]
+fn flush_when_full_does_not_delay_end_of_stream() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        );
+
+    let srv = server::Builder::new()
+        .write_buffer_size(1024 * 1024)
+        .flush_strategy(h2::FlushStrategy::FlushWhenFull)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap());
+
+    let ((reqstream, mut srv), mut client) = srv.join(client).wait().expect("wait");
+    let (_, mut stream) = reqstream.unwrap();
+
+    let notify = MockNotify::new();
+
+    let rsp = http::Response::builder().status(200).body(()).unwrap();
+    let mut stream = stream.send_response(rsp, false).unwrap();
+
+    // The response head waits for the buffer to fill up
+    assert!(notify.with(|| srv.poll()).unwrap().is_not_ready());
+    assert!(notify.with(|| client.poll()).unwrap().is_not_ready());
+
+    // The PING acknowledgement is flushed right away, with the response head
+    client.send(frames::ping([1; 8]).into()).unwrap();
+    assert!(notify.with(|| srv.poll()).unwrap().is_not_ready());
+
+    assert_eq!(
+        recv_all(&notify, &mut client),
+        vec![
+            frames::headers(1).response(200).into(),
+            frames::ping([1; 8]).pong().into(),
+        ]
+    );
+
+    // The end of the response is flushed right away, although the buffer is
+    // far from full
+    stream.send_data("hello world".into(), true).unwrap();
+    assert!(notify.with(|| srv.poll()).unwrap().is_not_ready());
+
+    assert_eq!(
+        recv_all(&notify, &mut client),
+        vec![frames::data(1, "hello world").eos().into()]
+    );
+}
+
+fn recv_all(notify: &MockNotify, client: &mut mock::Handle) -> Vec<frame::Frame> {
+    let mut received = vec![];
+
+    while let futures::Async::Ready(Some(frame)) = notify.with(|| client.poll()).unwrap() {
+        received.push(frame);
+    }
+
+    received
+}
+
+#[test]
+fn flush_after_each_frame() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(frames::headers(1).response(200))
+        .recv_frame(frames::data(1, "hello world").eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .flush_strategy(h2::FlushStrategy::FlushAfterEachFrame)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            srv.into_future().unwrap().and_then(|(reqstream, srv)| {
+                let (_, mut stream) = reqstream.unwrap();
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                let mut stream = stream.send_response(rsp, false).unwrap();
+                stream.send_data("hello world".into(), true).unwrap();
+
+                srv.into_future().unwrap()
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn echo_server_for_each() {
+    use futures::sync::mpsc;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+        )
+        .send_frame(frames::data(1, "hello "))
+        .send_frame(frames::data(1, "world").eos())
+        .recv_frame(frames::headers(1).response(200))
+        .recv_frame(frames::data(1, "hello world").eos())
+        .send_frame(
+            frames::headers(3)
+                .request("POST", "https://example.com/")
+        )
+        // The handler of stream 3 waits for its body, while the connection
+        // keeps being polled.
+        .ping_pong([1; 8])
+        .send_frame(frames::data(3, "again").eos())
+        .recv_frame(frames::headers(3).response(200))
+        .recv_frame(frames::data(3, "again").eos())
+        .close();
+
+    // Requests are handled by another future than the one accepting them.
+    let (tx, rx) = mpsc::unbounded();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(move |srv| {
+            srv.for_each(move |(req, mut stream)| {
+                assert_eq!(req.method(), &http::Method::POST);
+
+                let rsp = http::Response::builder().status(200).body(()).unwrap();
+                let mut send = stream.send_response(rsp, false).unwrap();
+
+                let echo = req.into_body()
+                    .concat2()
+                    .map(move |body| send.send_data(body, true).unwrap())
+                    .map_err(|e| panic!("echo err: {:?}", e));
+
+                tx.unbounded_send(echo).unwrap();
+                Ok(())
+            }).map_err(|e| panic!("server err: {:?}", e))
+        });
+
+    let handlers = rx.buffer_unordered(8).for_each(|_| Ok(()));
+
+    srv.join3(handlers, client).wait().expect("wait");
+}
+
+#[test]
+fn stop_accepting_refuses_new_requests() {
+    use futures::sync::oneshot;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+    let (tx, rx) = oneshot::channel();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        // Stream 3 was received before the server stopped accepting, but was
+        // not accepted yet.
+        .recv_frame(frames::reset(3).refused())
+        .send_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::reset(5).refused())
+        .map(move |client| {
+            tx.send(()).unwrap();
+            client
+        })
+        // The accepted stream still completes.
+        .recv_frame(frames::headers(1).response(200).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(move |(req, mut srv)| {
+            let (_, respond) = req.expect("request");
+            srv.stop_accepting();
+
+            let mut rx = rx;
+            let mut respond = Some(respond);
+
+            poll_fn(move || {
+                assert!(srv.poll().unwrap().is_ready());
+
+                if respond.is_some() {
+                    if let futures::Async::Ready(()) = rx.poll().expect("rx") {
+                        let rsp = http::Response::builder().status(200).body(()).unwrap();
+                        respond.take().unwrap().send_response(rsp, true).unwrap();
+                    }
+                }
+
+                Ok(srv.poll_close().expect("server"))
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn respond_204_without_body() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("DELETE", "https://example.com/items/1")
+                .eos()
+        )
+        .recv_frame(frames::headers(1).response(204).eos())
+        // No DATA frame is sent before the PONG.
+        .ping_pong([1; 8])
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut respond) = req.expect("request");
+
+            let rsp = http::Response::builder().status(204).body(()).unwrap();
+            let mut stream = respond.send_response(rsp, true).unwrap();
+
+            let err = stream.send_data(Bytes::new(), true).unwrap_err();
+            assert!(err.is_send_after_end_stream());
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn respond_to_head_with_content_length() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("HEAD", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(
+            frames::headers(1)
+                .response(200)
+                .field("content-length", "1024")
+                .eos()
+        )
+        .ping_pong([1; 8])
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (req, mut respond) = req.expect("request");
+            assert_eq!(req.method(), &http::Method::HEAD);
+
+            let rsp = http::Response::builder()
+                .status(200)
+                .header("content-length", "1024")
+                .body(())
+                .unwrap();
+            respond.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_after_end_of_stream_errors() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(1).response(200))
+        .recv_frame(frames::data(1, "hello").eos())
+        .ping_pong([1; 8])
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut respond) = req.expect("request");
+
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            let mut stream = respond.send_response(rsp, false).unwrap();
+            stream.send_data("hello".into(), true).unwrap();
+
+            // The stream already ended, neither more data nor trailers are
+            // sent.
+            let err = stream.send_data("world".into(), true).unwrap_err();
+            assert!(err.is_send_after_end_stream());
+
+            let err = stream.send_trailers(HeaderMap::new()).unwrap_err();
+            assert!(err.is_send_after_end_stream());
+
+            // Neither is a second response.
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            assert!(respond.send_response(rsp, true).is_err());
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn send_reset_without_response() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::reset(1).refused())
+        .ping_pong([1; 8])
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut respond) = req.expect("request");
+            respond.send_reset(Reason::REFUSED_STREAM);
+
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            assert!(respond.send_response(rsp, true).is_err());
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
+
+/// Drives `srv` for `dur` without accepting requests.
+fn poll_close_for<T>(
+    srv: server::Connection<T, Bytes>,
+    dur: Duration,
+) -> Box<Future<Item = server::Connection<T, Bytes>, Error = ()>>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    let mut srv = Some(srv);
+    let mut delay = clock::delay(dur);
+
+    Box::new(poll_fn(move || {
+        let closed = srv.as_mut().unwrap().poll_close().expect("server").is_ready();
+        assert!(!closed, "connection closed");
+
+        match delay.poll()? {
+            futures::Async::Ready(()) => Ok(srv.take().unwrap().into()),
+            futures::Async::NotReady => Ok(futures::Async::NotReady),
+        }
+    }))
+}
+
+#[test]
+fn idle_timeout_closes_connection() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(1).response(200).eos())
+        .recv_frame(frames::go_away(1))
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .idle_timeout(Duration::from_secs(60))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, respond) = req.expect("request");
+
+            // Nothing is received for longer than the timeout, but the
+            // stream is active.
+            poll_close_for(srv, Duration::from_secs(120)).map(move |srv| (respond, srv))
+        })
+        .and_then(|(mut respond, mut srv)| {
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            respond.send_response(rsp, true).unwrap();
+
+            // The connection is idle from now on, and is closed once the
+            // timeout expires.
+            let idle_at = clock::now();
+
+            poll_fn(move || srv.poll_close())
+                .map_err(|e| panic!("server err: {:?}", e))
+                .map(move |()| assert!(clock::now() >= idle_at + Duration::from_secs(60)))
+        });
+
+    clock::run(srv.join(client)).expect("wait");
+}
+
+#[test]
+fn max_connection_age_shuts_down_gracefully() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::go_away(StreamId::MAX))
+        .recv_frame(frames::ping(frame::Ping::SHUTDOWN))
+        // The request in flight completes.
+        .recv_frame(frames::headers(1).response(200).eos())
+        .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+        .recv_frame(frames::go_away(1))
+        .recv_eof();
+
+    let srv = server::Builder::new()
+        .max_connection_age(Duration::from_secs(60))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, respond) = req.expect("request");
+            poll_close_for(srv, Duration::from_secs(120)).map(move |srv| (respond, srv))
+        })
+        .and_then(|(mut respond, srv)| {
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            respond.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    clock::run(srv.join(client)).expect("wait");
+}
+
+#[test]
+fn max_connection_age_grace_closes_busy_connection() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::go_away(StreamId::MAX))
+        .recv_frame(frames::ping(frame::Ping::SHUTDOWN))
+        .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+        .recv_frame(frames::go_away(1))
+        // The stream never completes, the connection is closed at the end of
+        // the grace period.
+        .recv_eof();
+
+    let opened_at = clock::now();
+
+    let srv = server::Builder::new()
+        .max_connection_age(Duration::from_secs(60))
+        .max_connection_age_grace(Duration::from_secs(60))
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(move |(req, mut srv)| {
+            let (_, mut respond) = req.expect("request");
+
+            poll_fn(move || srv.poll_close())
+                .map_err(|e| panic!("server err: {:?}", e))
+                .map(move |()| {
+                    assert!(clock::now() >= opened_at + Duration::from_secs(120));
+
+                    let rsp = http::Response::builder().status(200).body(()).unwrap();
+                    assert!(respond.send_response(rsp, true).is_err());
+                })
+        });
+
+    clock::run(srv.join(client)).expect("wait");
+}
+
+/// Returns a header list of a bit more than 2 MB.
+fn huge_headers() -> HeaderMap {
+    let value = http::header::HeaderValue::from_str(&"a".repeat(1024)).unwrap();
+    let mut headers = HeaderMap::new();
+
+    for i in 0..2048 {
+        let name: http::header::HeaderName = format!("x-huge-{}", i).parse().unwrap();
+        headers.insert(name, value.clone());
+    }
+
+    headers
+}
+
+#[test]
+fn send_header_block_over_limit_errors() {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        // Only the response within the limit is sent.
+        .recv_frame(frames::headers(1).response(500).eos())
+        .close();
+
+    let srv = server::Builder::new()
+        .max_send_header_block_size(1 << 20)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut respond) = req.expect("request");
+
+            let mut rsp = http::Response::builder().status(200).body(()).unwrap();
+            *rsp.headers_mut() = huge_headers();
+
+            let err = respond.send_response(rsp, true).unwrap_err();
+            assert!(err.is_header_block_too_big());
+
+            // The stream is left untouched.
+            let rsp = http::Response::builder().status(500).body(()).unwrap();
+            respond.send_response(rsp, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn pong_not_held_up_by_pending_header_block() {
+    let _ = ::env_logger::try_init();
+
+    // Lets the handshake, the response of the first request and the start
+    // of its DATA frame through.
+    let (io, client) = mock::new_with_write_capacity(1024);
+
+    let data = Bytes::from(vec![0; 16_384]);
+    let (tx, rx) = ::futures::sync::oneshot::channel();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings()
+        .send_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .send_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos()
+        )
+        .recv_frame(frames::headers(1).response(200))
+        // The DATA frame is stuck in the codec, the huge header block is
+        // queued behind it.
+        .wait_for(rx)
+        .send_frame(frames::ping([1; 8]))
+        .unbounded_bytes()
+        .recv_frame(frames::data(1, data.clone()).eos())
+        // The PONG goes right after the DATA frame, ahead of the header
+        // block that was not started yet.
+        .recv_frame(frames::ping([1; 8]).pong())
+        .recv_frame(frames::headers(3).response(200).fields(huge_headers()).eos())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(move |(req, srv)| {
+            let (_, mut respond) = req.expect("request");
+
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            let mut stream = respond.send_response(rsp, false).unwrap();
+            stream.send_data(data, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .and_then(move |(req, srv)| {
+            let (_, mut respond) = req.expect("request");
+
+            let mut rsp = http::Response::builder().status(200).body(()).unwrap();
+            *rsp.headers_mut() = huge_headers();
+            respond.send_response(rsp, true).unwrap();
+
+            tx.send(()).unwrap();
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    srv.join(client).wait().expect("wait");
+}
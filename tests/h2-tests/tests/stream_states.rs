@@ -196,6 +196,54 @@ fn closed_streams_are_released() {
     let _ = h2.join(srv).wait().unwrap();
 }
 
+#[test]
+fn closed_streams_are_released_after_many_requests() {
+    use h2::test;
+    use std::thread;
+
+    const REQUESTS: usize = 10_000;
+
+    let _ = ::env_logger::try_init();
+    let (io, srv_io) = test::duplex();
+
+    let srv = thread::spawn(move || {
+        server::handshake(srv_io)
+            .and_then(|srv| {
+                srv.for_each(|(_, mut respond)| {
+                    let response = Response::builder().status(204).body(()).unwrap();
+                    respond.send_response(response, true).unwrap();
+                    Ok(())
+                })
+            })
+            .wait()
+            .expect("server");
+    });
+
+    let (mut client, conn) = client::handshake(io).wait().expect("handshake");
+    let conn = thread::spawn(move || conn.wait().expect("connection"));
+
+    assert_eq!(0, client.num_wired_streams());
+
+    for _ in 0..REQUESTS {
+        client = client.ready().wait().expect("ready");
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+
+        let response = response.wait().expect("response");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // The body handle is the last reference to the closed stream.
+        assert_eq!(1, client.num_wired_streams());
+        drop(response);
+        assert_eq!(0, client.num_wired_streams());
+    }
+
+    drop(client);
+    conn.join().unwrap();
+    srv.join().unwrap();
+}
+
 #[test]
 fn errors_if_recv_frame_exceeds_max_frame_size() {
     let _ = ::env_logger::try_init();
@@ -222,13 +270,12 @@ fn errors_if_recv_frame_exceeds_max_frame_size() {
                 })
             });
 
-        // client should see a conn error
-        let conn = h2.then(|res| {
-            let err = res.unwrap_err();
-            assert_eq!(err.to_string(), "protocol error: frame with invalid size");
-            Ok::<(), ()>(())
-        });
-        conn.unwrap().join(req)
+        // an oversized DATA frame only resets the stream
+        h2.drive(req)
+            .and_then(move |(h2, _)| {
+                drop(client);
+                h2.unwrap()
+            })
     });
 
     // a bad peer
@@ -244,12 +291,247 @@ fn errors_if_recv_frame_exceeds_max_frame_size() {
         )
         .send_frame(frames::headers(1).response(200))
         .send_frame(frames::data(1, vec![0; 16_385]).eos())
-        .recv_frame(frames::go_away(0).frame_size())
+        .recv_frame(frames::reset(1).frame_size())
         .close();
 
     let _ = h2.join(srv).wait().unwrap();
 }
 
+#[test]
+fn recv_headers_frame_larger_than_max_frame_size() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    // HEADERS frame on stream 1 with END_HEADERS and a 16,385 byte payload.
+    // The frame is rejected on its size, before the header block is decoded.
+    let mut frame = vec![0, 0x40, 0x01, 1, 0x4, 0, 0, 0, 1];
+    frame.extend(vec![0; 16_385]);
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_bytes(&frame)
+        .recv_frame(frames::go_away(0).frame_size())
+        .recv_eof();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client
+                .send_request(request, true)
+                .expect("send_request")
+                .0
+                .then(|res| {
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::FRAME_SIZE_ERROR));
+                    Ok::<(), ()>(())
+                });
+
+            let conn = conn.then(|res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::FRAME_SIZE_ERROR));
+                Ok::<(), ()>(())
+            });
+
+            conn.join(req)
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+
+#[test]
+fn recv_frame_too_big_is_rejected_before_payload() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        // Only the head of a 16 MiB HEADERS frame is sent.
+        .send_bytes(&[0xff, 0xff, 0xff, 1, 0x4, 0, 0, 0, 1])
+        .recv_frame(frames::go_away(0).frame_size())
+        .recv_eof();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client
+                .send_request(request, true)
+                .expect("send_request")
+                .0
+                .then(|res| {
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::FRAME_SIZE_ERROR));
+                    Ok::<(), ()>(())
+                });
+
+            let conn = conn.then(|res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::FRAME_SIZE_ERROR));
+                Ok::<(), ()>(())
+            });
+
+            conn.join(req)
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn recv_data_too_big_is_discarded_and_released() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::headers(1).response(200))
+        // The head of a 40,000 byte DATA frame. The payload counts against
+        // the connection window, and is released right away. The stream is
+        // reset before the payload is sent.
+        .send_bytes(&[0, 0x9c, 0x40, 0, 0, 0, 0, 0, 1])
+        .recv_frame(frames::window_update(0, 40_000))
+        .recv_frame(frames::reset(1).frame_size())
+        .send_bytes(&[0; 40_000])
+        .ping_pong([1; 8])
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client
+                .send_request(request, true)
+                .expect("send_request")
+                .0
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    resp.into_body().concat2()
+                })
+                .then(|res| {
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::FRAME_SIZE_ERROR));
+                    Ok::<(), ()>(())
+                });
+
+            conn.drive(req).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn recv_data_too_big_over_connection_window_is_connection_error() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::headers(1).response(200))
+        // The head of a 70,000 byte DATA frame, which is larger than the
+        // connection window.
+        .send_bytes(&[0x01, 0x11, 0x70, 0, 0, 0, 0, 0, 1])
+        .recv_frame(frames::go_away(0).flow_control())
+        .recv_eof();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client
+                .send_request(request, true)
+                .expect("send_request")
+                .0
+                .and_then(|resp| resp.into_body().concat2())
+                .then(|res| {
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::FLOW_CONTROL_ERROR));
+                    Ok::<(), ()>(())
+                });
+
+            let conn = conn.then(|res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::FLOW_CONTROL_ERROR));
+                Ok::<(), ()>(())
+            });
+
+            conn.join(req)
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn recv_data_too_big_on_idle_stream_is_connection_error() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        // The head of a 20,000 byte DATA frame on a stream that was never
+        // opened.
+        .send_bytes(&[0, 0x4e, 0x20, 0, 0, 0, 0, 0, 3])
+        .recv_frame(frames::go_away(0).frame_size())
+        .recv_eof();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            conn.then(move |res| {
+                drop(client);
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::FRAME_SIZE_ERROR));
+                Ok::<(), ()>(())
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
 
 #[test]
 fn configure_max_frame_size() {
@@ -302,6 +584,77 @@ fn configure_max_frame_size() {
     let _ = h2.join(srv).wait().expect("wait");
 }
 
+#[test]
+fn send_data_split_at_peer_max_frame_size() {
+    let _ = ::env_logger::try_init();
+    let (io, mut srv) = mock::new();
+
+    srv.codec_mut().set_max_recv_frame_size(65_536);
+
+    let srv = srv.assert_client_handshake_with_settings(
+            frames::settings().initial_window_size(1 << 20)
+        )
+        .unwrap()
+        .recv_settings()
+        .send_frame(frames::window_update(0, 1 << 20))
+        // Lets the client know the WINDOW_UPDATE has been processed.
+        .send_frame(frames::settings().max_concurrent_streams(100))
+        .recv_frame(frames::settings_ack())
+        .recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]))
+        .recv_frame(frames::data(1, vec![0; 16_384]).eos())
+        .send_frame(frames::headers(1).response(200).eos())
+        .send_frame(frames::settings().max_frame_size(65_536))
+        .recv_frame(frames::settings_ack())
+        .recv_frame(frames::headers(3).request("POST", "https://example.com/"))
+        .recv_frame(frames::data(3, vec![0; 65_536]).eos())
+        .send_frame(frames::headers(3).response(200).eos())
+        .close();
+
+    fn post(client: &mut client::SendRequest<Bytes>) -> client::ResponseFuture {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("https://example.com/")
+            .body(())
+            .unwrap();
+
+        let (resp, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data(vec![0; 65_536].into(), true).unwrap();
+        resp
+    }
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(client, conn)| {
+            let changes = conn.settings_changes();
+            conn.drive(changes.take(2).collect())
+                .map(move |(conn, _)| (client, conn))
+        })
+        .and_then(|(mut client, conn)| {
+            let resp = post(&mut client).expect("response");
+            conn.drive(resp).map(move |(conn, _)| (client, conn))
+        })
+        .and_then(|(client, conn)| {
+            let changes = conn.settings_changes();
+            conn.drive(changes.into_future().map_err(|(e, _)| e))
+                .map(move |(conn, (update, _))| {
+                    assert_eq!(update.unwrap().max_frame_size(), Some(65_536));
+                    (client, conn)
+                })
+        })
+        .and_then(|(mut client, conn)| {
+            let resp = post(&mut client).expect("response");
+            conn.drive(resp).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
 #[test]
 fn recv_goaway_finishes_processed_streams() {
     let _ = ::env_logger::try_init();
@@ -360,7 +713,8 @@ fn recv_goaway_finishes_processed_streams() {
                 .unwrap()
                 .0.then(|res| {
                     let err = res.unwrap_err();
-                    assert_eq!(err.to_string(), "protocol error: not a result of an error");
+                    assert!(err.is_remote_refused());
+                    assert_eq!(err.to_string(), "stream not processed by peer: not a result of an error");
                     Ok::<(), ()>(())
                 });
 
@@ -588,9 +942,11 @@ fn rst_stream_expires() {
         // wait till after the configured duration
         .idle_ms(15)
         .ping_pong([1; 8])
-        // sending frame after canceled!
+        // sending frame after canceled! The stream was released, so it is
+        // reset again.
         .send_frame(frames::data(1, vec![0; 16_384]).eos())
-        .recv_frame(frames::go_away(0).protocol_error())
+        .recv_frame(frames::window_update(0, 32_768))
+        .recv_frame(frames::reset(1).stream_closed())
         .close();
 
     let client = client::Builder::new()
@@ -617,14 +973,8 @@ fn rst_stream_expires() {
                 });
 
             conn.drive(req)
-                .and_then(|(conn, _)| conn.expect_err("client"))
-                .map(|err| {
-                    assert_eq!(
-                        err.to_string(),
-                        "protocol error: unspecific protocol error detected"
-                    );
-                    drop(client);
-                })
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(move |_| drop(client))
         });
 
 
@@ -661,9 +1011,9 @@ fn rst_stream_max() {
         .send_frame(frames::data(3, vec![0; 16]).eos())
         // ping pong to be sure of no goaway
         .ping_pong([1; 8])
-        // 1 has been evicted, will get a goaway
+        // 1 has been evicted, will be reset again
         .send_frame(frames::data(1, vec![0; 16]).eos())
-        .recv_frame(frames::go_away(0).protocol_error())
+        .recv_frame(frames::reset(1).stream_closed())
         .close();
 
     let client = client::Builder::new()
@@ -708,19 +1058,148 @@ fn rst_stream_max() {
                 });
 
             conn.drive(req1.join(req2))
-                .and_then(|(conn, _)| conn.expect_err("client"))
-                .map(|err| {
-                    assert_eq!(
-                        err.to_string(),
-                        "protocol error: unspecific protocol error detected"
-                    );
-                })
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(move |_| drop(client))
         });
 
 
     client.join(srv).wait().expect("wait");
 }
 
+#[test]
+fn rst_stream_max_ignores_late_frames_after_eviction() {
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .recv_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::headers(3).response(200))
+        .recv_frame(frames::reset(1).cancel())
+        .recv_frame(frames::reset(3).cancel())
+        // 1 has been evicted, these frames may still be sent on closed
+        // streams
+        .send_frame(frames::window_update(1, 10))
+        .send_frame(frames::reset(1).cancel())
+        // ping pong to be sure of no goaway
+        .ping_pong([1; 8])
+        .close();
+
+    let client = client::Builder::new()
+        .max_concurrent_reset_streams(1)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri("https://example.com/")
+                .body(())
+                .unwrap();
+
+            let req1 = client.send_request(request, true)
+                .unwrap()
+                .0.expect("response1")
+                .map(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    // drop resp will send a reset
+                });
+
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri("https://example.com/")
+                .body(())
+                .unwrap();
+
+            let req2 = client.send_request(request, true)
+                .unwrap()
+                .0.expect("response2")
+                .map(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    // drop resp will send a reset
+                });
+
+            conn.drive(req1.join(req2))
+                .and_then(|(conn, _)| conn.expect("client"))
+                .map(move |_| drop(client))
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn rst_stream_max_bounds_reset_streams() {
+    use futures::{task, Async, Poll};
+
+    const STREAMS: usize = 10_000;
+    const MAX_RESET: usize = 10;
+
+    let _ = ::env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    // The peer reads every frame and never responds
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .and_then(|srv| srv.for_each(|_| Ok(())).map_err(|e| panic!("peer; err={:?}", e)));
+
+    let client = client::Builder::new()
+        .max_concurrent_reset_streams(MAX_RESET)
+        .handshake::<_, Bytes>(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let mut sent = 0;
+            let mut max_wired = 0;
+
+            let reset_all = poll_fn(move || -> Poll<usize, h2::Error> {
+                max_wired = ::std::cmp::max(max_wired, client.num_wired_streams());
+
+                if sent == STREAMS {
+                    return Ok(Async::Ready(max_wired));
+                }
+
+                if !client.poll_ready()?.is_ready() {
+                    return Ok(Async::NotReady);
+                }
+
+                let request = Request::builder()
+                    .method(Method::GET)
+                    .uri("https://example.com/")
+                    .body(())
+                    .unwrap();
+
+                // Dropping the response resets the stream
+                let _ = client.send_request(request, true)?;
+                sent += 1;
+
+                // Let the connection write the frames
+                task::current().notify();
+                Ok(Async::NotReady)
+            });
+
+            // The reset streams are remembered for longer than the test runs,
+            // so the connection is dropped rather than waited on.
+            conn.drive(reset_all)
+                .map(|(_conn, max_wired)| {
+                    // The reset streams, plus the one being opened
+                    assert!(max_wired <= MAX_RESET + 1, "max_wired={}", max_wired);
+                })
+                .map_err(|e| panic!("client; err={:?}", e))
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
 #[test]
 fn reserved_state_recv_window_update() {
     let _ = ::env_logger::try_init();
@@ -1039,7 +1518,7 @@ fn send_err_with_buffered_data() {
         .recv_frame(
             frames::data(1, vec![0; 16_384]))
         .recv_frame(frames::reset(1).cancel())
-        .recv_frame(frames::go_away(0).no_error())
+        // The reset stream keeps the connection open until it expires.
         .close()
         ;
 
@@ -1085,3 +1564,268 @@ fn send_err_with_buffered_data() {
 
     client.join(srv).wait().expect("wait");
 }
+
+/// State a stream is put in before a frame is received on it, from the point
+/// of view of the server.
+#[derive(Debug, Clone, Copy)]
+enum RecvState {
+    Open,
+    HalfClosedRemote,
+    ClosedByEndStream,
+    ResetByPeer,
+    ResetLocally,
+    /// The stream 3 is idle, only the stream 1 was opened.
+    Idle,
+    /// The stream 3 was opened first, which implicitly closed the stream 1.
+    ImplicitlyClosed,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RecvFrameKind {
+    Data,
+    Trailers,
+    Reset,
+    WindowUpdate,
+    Priority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RecvOutcome {
+    /// Nothing is sent back and the connection stays usable.
+    Ignored,
+    StreamError(Reason),
+    ConnectionError(Reason),
+}
+
+/// Puts a stream in `state`, receives a frame of `kind` on it and checks the
+/// outcome.
+fn recv_frame_in_state(state: RecvState, kind: RecvFrameKind, expected: RecvOutcome) {
+    use futures::Async;
+
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let client = client
+        .assert_server_handshake()
+        .unwrap()
+        .recv_settings();
+
+    let request = |id| frames::headers(id).request("POST", "https://example.com/");
+
+    let (client, last_stream_id): (Box<Future<Item = mock::Handle, Error = ()>>, u32) = match state {
+        RecvState::Open => (Box::new(client.send_frame(request(1))), 1),
+        RecvState::HalfClosedRemote => (Box::new(client.send_frame(request(1).eos())), 1),
+        RecvState::ClosedByEndStream => {
+            let client = client
+                .send_frame(request(1).eos())
+                .recv_frame(frames::headers(1).response(200).eos());
+            (Box::new(client), 1)
+        },
+        RecvState::ResetByPeer => {
+            let client = client
+                .send_frame(request(1))
+                .send_frame(frames::reset(1).cancel());
+            (Box::new(client), 1)
+        },
+        RecvState::ResetLocally => {
+            let client = client
+                .send_frame(request(1))
+                .recv_frame(frames::reset(1).cancel());
+            (Box::new(client), 1)
+        },
+        RecvState::Idle => (Box::new(client.send_frame(request(1).eos())), 1),
+        RecvState::ImplicitlyClosed => (Box::new(client.send_frame(request(3).eos())), 3),
+    };
+
+    let id = match state {
+        RecvState::Idle => 3,
+        _ => 1,
+    };
+
+    let client = match kind {
+        RecvFrameKind::Data => client.send_frame(frames::data(id, "hello").eos()),
+        RecvFrameKind::Trailers => client.send_frame(frames::headers(id).field("foo", "bar").eos()),
+        RecvFrameKind::Reset => client.send_frame(frames::reset(id).cancel()),
+        RecvFrameKind::WindowUpdate => client.send_frame(frames::window_update(id, 10)),
+        RecvFrameKind::Priority => client.send_frame(frames::priority(id, 0)),
+    };
+
+    let client: Box<Future<Item = (), Error = ()>> = match expected {
+        RecvOutcome::Ignored => Box::new(client.ping_pong([1; 8]).close()),
+        RecvOutcome::StreamError(reason) => Box::new(
+            client
+                .recv_frame(frame::Reset::new(StreamId::from(id), reason))
+                .ping_pong([1; 8])
+                .close()
+        ),
+        RecvOutcome::ConnectionError(reason) => Box::new(
+            client
+                .recv_frame(frame::GoAway::new(StreamId::from(last_stream_id), reason))
+                .close()
+        ),
+    };
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(move |mut srv| {
+            // Handles are kept so that the stream is not released.
+            let mut streams = Vec::new();
+
+            poll_fn(move || loop {
+                let (req, mut respond) = match srv.poll() {
+                    Ok(Async::Ready(Some(stream))) => stream,
+                    Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => return Ok(Async::Ready(Some(e))),
+                };
+
+                match state {
+                    RecvState::ClosedByEndStream => {
+                        let rsp = Response::builder().status(200).body(()).unwrap();
+                        respond.send_response(rsp, true).unwrap();
+                    },
+                    RecvState::ResetLocally => respond.send_reset(Reason::CANCEL),
+                    _ => {},
+                }
+
+                streams.push((req, respond));
+            })
+        })
+        .map(move |err: Option<h2::Error>| {
+            let reason = err.map(|err| err.reason().expect("reason"));
+
+            match expected {
+                RecvOutcome::ConnectionError(expected) => assert_eq!(reason, Some(expected)),
+                _ => assert_eq!(reason, None),
+            }
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+#[test]
+fn recv_frames_by_stream_state() {
+    use self::RecvFrameKind::*;
+    use self::RecvOutcome::*;
+    use self::RecvState::*;
+
+    // Section 5.1 of RFC 7540.
+    let table = [
+        (Open, Data, Ignored),
+        (Open, Trailers, Ignored),
+        (Open, Reset, Ignored),
+        (Open, WindowUpdate, Ignored),
+        (Open, Priority, Ignored),
+
+        (HalfClosedRemote, Data, StreamError(Reason::STREAM_CLOSED)),
+        (HalfClosedRemote, Trailers, StreamError(Reason::STREAM_CLOSED)),
+        (HalfClosedRemote, Reset, Ignored),
+        (HalfClosedRemote, WindowUpdate, Ignored),
+        (HalfClosedRemote, Priority, Ignored),
+
+        // Closed streams are released once their frames are sent, after
+        // which how they were closed is not known anymore.
+        (ClosedByEndStream, Data, StreamError(Reason::STREAM_CLOSED)),
+        // Stream IDs cannot be reused (section 5.1.1 of RFC 7540).
+        (ClosedByEndStream, Trailers, ConnectionError(Reason::PROTOCOL_ERROR)),
+        (ClosedByEndStream, Reset, Ignored),
+        (ClosedByEndStream, WindowUpdate, Ignored),
+        (ClosedByEndStream, Priority, Ignored),
+
+        (ResetByPeer, Data, StreamError(Reason::STREAM_CLOSED)),
+        (ResetByPeer, Trailers, ConnectionError(Reason::PROTOCOL_ERROR)),
+        (ResetByPeer, Reset, Ignored),
+        (ResetByPeer, WindowUpdate, Ignored),
+        (ResetByPeer, Priority, Ignored),
+
+        // Frames in flight when the stream was reset are ignored.
+        (ResetLocally, Data, Ignored),
+        (ResetLocally, Trailers, Ignored),
+        (ResetLocally, Reset, Ignored),
+        (ResetLocally, WindowUpdate, Ignored),
+        (ResetLocally, Priority, Ignored),
+
+        (Idle, Data, ConnectionError(Reason::PROTOCOL_ERROR)),
+        // Opens the stream, with a request that has no pseudo-header fields.
+        (Idle, Trailers, StreamError(Reason::PROTOCOL_ERROR)),
+        (Idle, Reset, ConnectionError(Reason::PROTOCOL_ERROR)),
+        (Idle, WindowUpdate, ConnectionError(Reason::PROTOCOL_ERROR)),
+        (Idle, Priority, Ignored),
+
+        (ImplicitlyClosed, Data, StreamError(Reason::STREAM_CLOSED)),
+        (ImplicitlyClosed, Trailers, ConnectionError(Reason::PROTOCOL_ERROR)),
+        (ImplicitlyClosed, Reset, Ignored),
+        (ImplicitlyClosed, WindowUpdate, Ignored),
+        (ImplicitlyClosed, Priority, Ignored),
+    ];
+
+    for &(state, kind, expected) in table.iter() {
+        debug!("recv_frames_by_stream_state; state={:?}; frame={:?}", state, kind);
+        recv_frame_in_state(state, kind, expected);
+    }
+}
+
+/// Sends `frames` to the server and expects the last one to be a
+/// PROTOCOL_ERROR connection error. The requests are left unanswered.
+fn recv_invalid_stream_id(frames: Vec<SendFrame>, last_stream_id: u32) {
+    let _ = ::env_logger::try_init();
+    let (io, client) = mock::new();
+
+    let mut client: Box<Future<Item = mock::Handle, Error = ()>> = Box::new(
+        client
+            .assert_server_handshake()
+            .unwrap()
+            .recv_settings()
+    );
+
+    for frame in frames {
+        client = Box::new(client.send_frame(frame));
+    }
+
+    let client = client
+        .recv_frame(frames::go_away(last_stream_id).protocol_error())
+        .close();
+
+    let srv = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| {
+            // Dropping the handles would reset the streams.
+            let mut streams = Vec::new();
+
+            srv.for_each(move |stream| {
+                streams.push(stream);
+                Ok(())
+            }).then(|res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                Ok::<(), ()>(())
+            })
+        });
+
+    srv.join(client).wait().expect("wait");
+}
+
+fn request(id: u32) -> SendFrame {
+    frames::headers(id).request("GET", "https://example.com/").eos().into()
+}
+
+#[test]
+fn recv_regressing_stream_id() {
+    recv_invalid_stream_id(vec![request(1), request(5), request(3)], 5);
+}
+
+#[test]
+fn recv_even_stream_id_from_client() {
+    recv_invalid_stream_id(vec![request(1), request(2)], 1);
+}
+
+#[test]
+fn recv_headers_after_priority_for_lower_idle_stream() {
+    // The PRIORITY frame for the idle stream 3 is legal, the HEADERS frame
+    // opening it after the stream 5 is not.
+    recv_invalid_stream_id(vec![
+        request(5),
+        frames::priority(3, 0).into(),
+        request(3),
+    ], 5);
+}
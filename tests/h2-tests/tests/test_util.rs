@@ -0,0 +1,171 @@
+extern crate h2_support;
+
+use h2_support::prelude::*;
+
+use h2::test::{self, Peer};
+use h2::test::frames as script;
+
+#[test]
+fn scripted_server_and_real_client() {
+    let _ = ::env_logger::try_init();
+    let (io, peer_io) = test::duplex();
+
+    let peer = Peer::server(peer_io)
+        .handshake()
+        .recv_frame(
+            script::headers(1)
+                .request("POST", "https://example.com/x")
+                .field("content-type", "text/plain"),
+        )
+        .recv_frame(script::data(1, "hello").eos())
+        .send_frame(script::headers(1).response(200))
+        .send_frame(script::data(1, "world").eos())
+        .recv_frame(script::go_away(0, Reason::NO_ERROR))
+        .recv_eof();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://example.com/x")
+                .header("content-type", "text/plain")
+                .body(())
+                .unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            stream.send_data("hello".into(), true).unwrap();
+
+            let response = response
+                .expect("response")
+                .and_then(move |response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                    drop(client);
+                    response.into_body().concat2().expect("body")
+                });
+
+            conn.expect("connection").join(response)
+        });
+
+    let (_, (_, body)) = peer.expect("peer").join(client).wait().unwrap();
+    assert_eq!(body, "world");
+}
+
+#[test]
+fn scripted_client_and_real_server() {
+    let _ = ::env_logger::try_init();
+    let (io, peer_io) = test::duplex();
+
+    let peer = Peer::client(peer_io)
+        .handshake()
+        .send_frame(script::headers(1).request("GET", "https://example.com/x").eos())
+        .recv_frame(script::headers(1).response(204).eos())
+        .send_frame(script::go_away(1, Reason::NO_ERROR))
+        .recv_frame(script::go_away(1, Reason::NO_ERROR))
+        .recv_eof();
+
+    let server = server::handshake(io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(request, srv)| {
+            let (request, mut respond) = request.unwrap();
+            assert_eq!(request.method(), Method::GET);
+            assert_eq!(request.uri().path(), "/x");
+
+            let response = Response::builder().status(204).body(()).unwrap();
+            respond.send_response(response, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(request, _)| assert!(request.is_none()));
+
+    peer.expect("peer").join(server).wait().unwrap();
+}
+
+#[test]
+fn real_client_and_server_in_small_chunks() {
+    let _ = ::env_logger::try_init();
+
+    // Frames are split across reads and writes, and writes wait for the
+    // other end to read.
+    let (client_io, server_io) = test::Builder::new()
+        .read_chunk_size(3)
+        .write_chunk_size(5)
+        .max_buffered(64)
+        .build();
+
+    let body = vec![b'x'; 100_000];
+
+    let server = server::handshake(server_io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(request, srv)| {
+            let (request, mut respond) = request.unwrap();
+
+            let mut body = request.into_body();
+            let mut release = body.release_capacity().clone();
+
+            let body = body
+                .fold(0, move |len, chunk| {
+                    release.release_capacity(chunk.len())?;
+                    Ok::<_, h2::Error>(len + chunk.len())
+                })
+                .unwrap()
+                .map(move |len| {
+                    assert_eq!(len, 100_000);
+
+                    let response = Response::new(());
+                    respond.send_response(response, true).unwrap();
+                });
+
+            srv.into_future().unwrap().join(body)
+        })
+        .map(|((request, _), _)| assert!(request.is_none()));
+
+    let client = client::handshake(client_io)
+        .expect("handshake")
+        .and_then(move |(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://example.com/")
+                .body(())
+                .unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            stream.reserve_capacity(body.len());
+            stream.send_data(body.into(), true).unwrap();
+
+            let response = response.expect("response").map(move |response| {
+                assert_eq!(response.status(), StatusCode::OK);
+                drop(client);
+            });
+
+            conn.expect("connection").join(response)
+        });
+
+    server.join(client).wait().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "unexpected frame")]
+fn peer_panics_on_unexpected_frame() {
+    let (io, peer_io) = test::duplex();
+
+    let peer = Peer::server(peer_io)
+        .handshake()
+        .recv_frame(script::headers(1).request("GET", "https://example.com/y").eos());
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://example.com/x")
+                .body(())
+                .unwrap();
+
+            client.send_request(request, true).unwrap();
+            conn.expect("connection").map(move |_| drop(client))
+        });
+
+    let _ = peer.expect("peer").join(client).wait();
+}
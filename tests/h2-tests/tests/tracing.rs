@@ -0,0 +1,211 @@
+#![cfg(feature = "tracing")]
+
+extern crate h2_support;
+extern crate tracing;
+
+use h2_support::prelude::*;
+
+use tracing::{span, Event, Metadata, Subscriber};
+use tracing::field::{Field, Visit};
+
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// Records the spans and events, with their fields formatted as
+/// `name=value` pairs.
+#[derive(Clone, Default)]
+struct Recorder {
+    inner: Arc<Mutex<Recorded>>,
+}
+
+#[derive(Default)]
+struct Recorded {
+    spans: Vec<RecordedSpan>,
+    events: Vec<RecordedEvent>,
+    /// Ids of the entered spans
+    stack: Vec<u64>,
+}
+
+#[derive(Debug)]
+struct RecordedSpan {
+    name: &'static str,
+    fields: String,
+    parent: Option<u64>,
+}
+
+#[derive(Debug)]
+struct RecordedEvent {
+    fields: String,
+    /// Ids of the spans entered when the event was emitted
+    scope: Vec<u64>,
+}
+
+struct Fields<'a>(&'a mut String);
+
+impl<'a> Visit for Fields<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        write!(self.0, "{}={} ", field.name(), value).unwrap();
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &fmt::Debug) {
+        write!(self.0, "{}={:?} ", field.name(), value).unwrap();
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &span::Attributes) -> span::Id {
+        let mut me = self.inner.lock().unwrap();
+
+        let mut fields = String::new();
+        attrs.record(&mut Fields(&mut fields));
+
+        let parent = if attrs.is_contextual() {
+            me.stack.last().cloned()
+        } else {
+            attrs.parent().map(|id| id.into_u64())
+        };
+
+        me.spans.push(RecordedSpan {
+            name: attrs.metadata().name(),
+            fields: fields,
+            parent: parent,
+        });
+
+        // Span ids must not be zero
+        span::Id::from_u64(me.spans.len() as u64)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut me = self.inner.lock().unwrap();
+
+        let mut fields = String::new();
+        event.record(&mut Fields(&mut fields));
+
+        let scope = me.stack.clone();
+        me.events.push(RecordedEvent { fields, scope });
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.inner.lock().unwrap().stack.push(span.into_u64());
+    }
+
+    fn exit(&self, span: &span::Id) {
+        let mut me = self.inner.lock().unwrap();
+        let pos = me.stack.iter().rposition(|id| *id == span.into_u64()).unwrap();
+        me.stack.remove(pos);
+    }
+}
+
+impl Recorded {
+    /// Returns the id of the only span named `name` with the given fields.
+    fn span(&self, name: &str, fields: &str) -> u64 {
+        let ids: Vec<_> = self.spans.iter()
+            .enumerate()
+            .filter(|&(_, span)| span.name == name && span.fields.trim() == fields)
+            .map(|(i, _)| i as u64 + 1)
+            .collect();
+
+        assert_eq!(ids.len(), 1, "span {} {}; spans={:#?}", name, fields, self.spans);
+        ids[0]
+    }
+
+    fn parent(&self, id: u64) -> Option<u64> {
+        self.spans[id as usize - 1].parent
+    }
+
+    /// Returns the scope of the only event with the given fields.
+    fn event(&self, fields: &str) -> &[u64] {
+        let events: Vec<_> = self.events.iter()
+            .filter(|event| event.fields.trim() == fields)
+            .collect();
+
+        assert_eq!(events.len(), 1, "event {}; events={:#?}", fields, self.events);
+        &events[0].scope
+    }
+}
+
+#[test]
+fn connection_and_stream_spans() {
+    let recorder = Recorder::default();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let (io, srv) = mock::new();
+
+        let srv = srv.assert_client_handshake()
+            .unwrap()
+            .recv_settings()
+            .recv_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .send_frame(frames::headers(1).response(204).eos())
+            .close();
+
+        let client = client::handshake(io)
+            .expect("handshake")
+            .and_then(|(mut client, conn)| {
+                let request = Request::builder()
+                    .uri("https://example.com/")
+                    .body(())
+                    .unwrap();
+
+                let resp = client.send_request(request, true).unwrap().0;
+
+                conn.drive(resp.expect("response")).and_then(move |(conn, resp)| {
+                    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+                    drop(client);
+                    conn.expect("connection")
+                })
+            });
+
+        srv.join(client).wait().expect("wait");
+    });
+
+    let recorded = recorder.inner.lock().unwrap();
+
+    // The mock server does not run a connection, so the only connection span
+    // is the client's.
+    let conn = recorded.spans.iter()
+        .position(|span| span.name == "connection")
+        .map(|i| i as u64 + 1)
+        .expect("connection span");
+    assert!(recorded.spans[conn as usize - 1].fields.contains("peer=client"));
+
+    // The stream is opened by the user, outside of the connection task, and
+    // is still nested in the connection span.
+    let stream = recorded.span("stream", "id=1");
+    assert_eq!(recorded.parent(stream), Some(conn));
+
+    // END_STREAM and END_HEADERS are set on both HEADERS frames. Frames sent
+    // and received on the stream are logged in its span. The mock server
+    // also logs frames, outside of any span.
+    let scope = recorded.event("message=frame direction=send kind=Headers stream_id=1 flags=5 len=13");
+    assert_eq!(scope, &[conn, stream]);
+
+    let scope = recorded.event("message=frame direction=recv kind=Headers stream_id=1 flags=5 len=1");
+    assert_eq!(scope, &[conn, stream]);
+
+    // State transitions of the stream are logged in its span
+    let scopes: Vec<_> = recorded.events.iter()
+        .filter(|event| event.fields.starts_with("message=transition_after; stream=StreamId(1);"))
+        .map(|event| event.scope.clone())
+        .collect();
+    assert!(!scopes.is_empty());
+    assert!(scopes.iter().all(|scope| scope.ends_with(&[stream])), "{:?}", scopes);
+
+    // Frames of the connection itself are logged in the connection span
+    let scopes: Vec<_> = recorded.events.iter()
+        .filter(|event| event.fields.starts_with("message=frame direction=recv kind=Settings"))
+        .map(|event| event.scope.clone())
+        .collect();
+    assert!(scopes.contains(&vec![conn]), "{:?}", scopes);
+}
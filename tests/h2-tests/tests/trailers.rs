@@ -4,6 +4,8 @@ extern crate h2_support;
 
 use h2_support::prelude::*;
 
+use futures::Async;
+
 #[test]
 fn recv_trailers_only() {
     let _ = env_logger::try_init();
@@ -106,7 +108,243 @@ fn send_trailers_immediately() {
 }
 
 #[test]
-#[ignore]
 fn recv_trailers_without_eos() {
-    // This should be a protocol error?
+    let _ = env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::headers(1).field("grpc-status", "0"))
+        // The response is malformed.
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client.send_request(request, true)
+                .unwrap()
+                .0.expect("response")
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    resp.into_body().concat2().then(|res| {
+                        let err = res.unwrap_err();
+                        assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                        Ok::<(), ()>(())
+                    })
+                });
+
+            conn.drive(req).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn recv_trailers_with_pseudo_headers() {
+    let _ = env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(
+            frames::headers(1)
+                .request("GET", "https://http2.akamai.com/")
+                .eos(),
+        )
+        .send_frame(frames::headers(1).response(200))
+        .send_frame(frames::data(1, "hello"))
+        // Trailers must not contain pseudo-header fields
+        .send_frame(frames::headers(1).response(200).field("grpc-status", "0").eos())
+        .recv_frame(frames::reset(1).protocol_error())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let req = client.send_request(request, true)
+                .unwrap()
+                .0.expect("response")
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    resp.into_body().concat2().then(|res| {
+                        let err = res.unwrap_err();
+                        assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+                        Ok::<(), ()>(())
+                    })
+                });
+
+            conn.drive(req).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+#[test]
+fn send_data_after_trailers() {
+    let _ = env_logger::try_init();
+    let (io, srv) = mock::new();
+
+    let srv = srv.assert_client_handshake()
+        .unwrap()
+        .recv_settings()
+        .recv_frame(frames::headers(1).request("POST", "https://http2.akamai.com/"))
+        .recv_frame(frames::headers(1).field("zomg", "hello").eos())
+        .send_frame(frames::headers(1).response(204).eos())
+        .close();
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://http2.akamai.com/")
+                .body(())
+                .unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+
+            let mut trailers = HeaderMap::new();
+            trailers.insert("zomg", "hello".parse().unwrap());
+            stream.send_trailers(trailers).unwrap();
+
+            // The trailers end the stream
+            assert!(stream.send_data("hello".into(), true).is_err());
+            assert!(stream.send_trailers(HeaderMap::new()).is_err());
+
+            let req = response
+                .expect("response")
+                .map(|resp| assert_eq!(resp.status(), StatusCode::NO_CONTENT));
+
+            conn.drive(req).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
+}
+
+/// Polls the body and the trailers of a message.
+fn recv_body_and_trailers(mut body: RecvStream) -> Box<Future<Item = (Vec<u8>, HeaderMap), Error = h2::Error>> {
+    let mut data = vec![];
+
+    Box::new(poll_fn(move || {
+        loop {
+            match body.poll()? {
+                Async::Ready(Some(chunk)) => data.extend_from_slice(&chunk),
+                Async::Ready(None) => break,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        match body.poll_trailers()? {
+            Async::Ready(trailers) => {
+                let trailers = trailers.expect("trailers");
+                Ok(Async::Ready((::std::mem::replace(&mut data, vec![]), trailers)))
+            },
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }))
+}
+
+#[test]
+fn exchange_grpc_trailers() {
+    let _ = env_logger::try_init();
+    let (io, srv_io) = mock::new();
+
+    let srv = server::handshake(srv_io)
+        .expect("handshake")
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(reqstream, srv)| {
+            let (req, mut stream) = reqstream.unwrap();
+            assert_eq!(req.method(), &Method::POST);
+
+            let body = recv_body_and_trailers(req.into_body())
+                .expect("request body")
+                .map(move |(data, trailers)| {
+                    assert_eq!(data, b"ping");
+                    assert_eq!(trailers["grpc-timeout"], "1S");
+
+                    let rsp = Response::builder()
+                        .status(200)
+                        .header("content-type", "application/grpc")
+                        .body(())
+                        .unwrap();
+                    let mut stream = stream.send_response(rsp, false).unwrap();
+                    stream.send_data("pong".into(), false).unwrap();
+
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("grpc-status", "0".parse().unwrap());
+                    trailers.insert("grpc-message", "OK".parse().unwrap());
+                    stream.send_trailers(trailers).unwrap();
+                });
+
+            srv.for_each(|_| -> Result<(), _> { panic!("unexpected request") })
+                .expect("srv")
+                .join(body)
+        });
+
+    let client = client::handshake(io)
+        .expect("handshake")
+        .and_then(|(mut client, conn)| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://example.com/echo.Echo/Ping")
+                .header("content-type", "application/grpc")
+                .header("te", "trailers")
+                .body(())
+                .unwrap();
+
+            let (response, mut stream) = client.send_request(request, false).unwrap();
+            stream.send_data("ping".into(), false).unwrap();
+
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-timeout", "1S".parse().unwrap());
+            stream.send_trailers(trailers).unwrap();
+
+            let req = response
+                .expect("response")
+                .and_then(|resp| {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    recv_body_and_trailers(resp.into_body()).expect("response body")
+                })
+                .map(|(data, trailers)| {
+                    assert_eq!(data, b"pong");
+                    assert_eq!(trailers.len(), 2);
+                    assert_eq!(trailers["grpc-status"], "0");
+                    assert_eq!(trailers["grpc-message"], "OK");
+                });
+
+            conn.drive(req).and_then(move |(conn, _)| {
+                drop(client);
+                conn.expect("client")
+            })
+        });
+
+    client.join(srv).wait().expect("wait");
 }